@@ -0,0 +1,358 @@
+// Annotations: findings pinned to the map.
+//
+// An annotation is a pin with a severity, a line of text and a screenshot of
+// the view it was made in. It is anchored to the selected point of a lane,
+// as road, s and t, so that it follows the road if the map is regenerated,
+// or else to the view center. Annotations are kept in a sidecar file next
+// to the map, `<map>.annotations.json`, with the screenshots in the
+// `<map>.annotations` directory beside it, so they can be shared along with
+// the map. Annotations of the demo network go to the config directory.
+//
+// They are made and managed from the console: `note [info|warning|error]
+// <text>`, `notes` and `unnote <id>`. The sidecar file also keeps the
+// decals painted onto the map for review (see `decals`).
+//
+// Keys: N starts a note in the console.
+
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::window::PrimaryWindow;
+
+use crate::bookmarks::config_dir;
+use crate::console::Console;
+use crate::cross_section::cut;
+use crate::decals::{self, Decal};
+use crate::json::{self, Json};
+use crate::origin::RenderOrigin;
+use crate::selection::Selection;
+use crate::theme::Theme;
+use crate::validation::Severity;
+use crate::{camera_orbit, CameraOrbit, MainCamera, RoadNetwork};
+
+// Height and head radius of a pin, in meters.
+const PIN_HEIGHT: f32 = 6.0;
+const PIN_RADIUS: f32 = 0.8;
+
+// Where an annotation is pinned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    // A point on a road: station and lateral offset, left positive.
+    Road { road_id: u32, s: f64, t: f64 },
+    // A viewer-frame position.
+    World(DVec3),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub id: u32,
+    pub severity: Severity,
+    pub text: String,
+    pub anchor: Anchor,
+    // Where the pin is drawn, in the viewer frame.
+    pub position: DVec3,
+    // The screenshot, relative to the sidecar file's directory.
+    pub screenshot: Option<String>,
+    // Seconds since the Unix epoch.
+    pub created: u64,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Annotations {
+    // The sidecar file; none if there is nowhere to keep it.
+    pub file: Option<PathBuf>,
+    pub list: Vec<Annotation>,
+    // Decals kept with the annotations (see `decals`).
+    pub decals: Vec<Decal>,
+}
+
+impl Annotations {
+    // Loads the annotations of a map from its sidecar file.
+    pub fn load(map: Option<&Path>, network: &RoadNetwork) -> Self {
+        Self::open(sidecar_file(map), network)
+    }
+
+    // Loads annotations from a file, e.g. one named by a project. A missing
+    // file gives none; an unreadable one is reported and gives none.
+    pub fn open(file: Option<PathBuf>, network: &RoadNetwork) -> Self {
+        let (list, decals) = match file.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(text)) => match from_json(&text, network) {
+                Ok(read) => read,
+                Err(message) => {
+                    warn!("could not read the annotations: {message}");
+                    Default::default()
+                }
+            },
+            _ => Default::default(),
+        };
+        Self { file, list, decals }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Err("nowhere to keep annotations".to_string());
+        };
+        std::fs::write(file, to_json(&self.list, &self.decals).write())
+            .map_err(|e| format!("{}: {e}", file.display()))
+    }
+
+    fn next_id(&self) -> u32 {
+        self.list.iter().map(|a| a.id).max().unwrap_or(0) + 1
+    }
+}
+
+pub struct AnnotationPlugin;
+
+impl Plugin for AnnotationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Annotations>()
+            .add_systems(Update, (start_note, draw_pins.after(camera_orbit)));
+    }
+}
+
+fn sidecar_file(map: Option<&Path>) -> Option<PathBuf> {
+    match map {
+        Some(map) => {
+            let stem = map.file_stem()?.to_str()?;
+            Some(map.with_file_name(format!("{stem}.annotations.json")))
+        }
+        None => Some(config_dir()?.join("demo.annotations.json")),
+    }
+}
+
+// The position of a point given by road, s and t.
+pub fn road_point(network: &RoadNetwork, road_id: u32, s: f64, t: f64) -> Option<DVec3> {
+    let cut = cut(network, road_id, s)?;
+    Some(cut.reference + DVec3::Y.cross(cut.direction) * t)
+}
+
+// Map frame (x east, y north, z up) from the viewer frame and back.
+pub fn to_map(p: DVec3) -> DVec3 {
+    DVec3::new(p.x, -p.z, p.y)
+}
+
+fn from_map(p: DVec3) -> DVec3 {
+    DVec3::new(p.x, p.z, -p.y)
+}
+
+pub fn to_json(list: &[Annotation], decals: &[Decal]) -> Json {
+    let annotations = list
+        .iter()
+        .map(|note| {
+            let mut fields = vec![
+                ("id".to_string(), Json::from(note.id)),
+                ("severity".to_string(), Json::from(note.severity.name())),
+                ("text".to_string(), Json::from(note.text.as_str())),
+                ("created".to_string(), Json::Number(note.created as f64)),
+            ];
+            if let Anchor::Road { road_id, s, t } = note.anchor {
+                fields.push(("road".to_string(), Json::from(road_id)));
+                fields.push(("s".to_string(), Json::from(s)));
+                fields.push(("t".to_string(), Json::from(t)));
+            }
+            let p = to_map(note.position);
+            fields.push((
+                "position".to_string(),
+                Json::Array(vec![p.x.into(), p.y.into(), p.z.into()]),
+            ));
+            if let Some(screenshot) = &note.screenshot {
+                fields.push(("screenshot".to_string(), Json::from(screenshot.as_str())));
+            }
+            Json::Object(fields)
+        })
+        .collect();
+    let mut fields = vec![("annotations".to_string(), Json::Array(annotations))];
+    if !decals.is_empty() {
+        fields.push(("decals".to_string(), decals::to_json(decals)));
+    }
+    Json::Object(fields)
+}
+
+fn from_json(text: &str, network: &RoadNetwork) -> Result<(Vec<Annotation>, Vec<Decal>), String> {
+    let root = json::parse(text)?;
+    let items = root
+        .get("annotations")
+        .and_then(Json::as_array)
+        .ok_or("no `annotations` list")?;
+    let list = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let invalid = |what: &str| format!("annotation {}: {what}", index + 1);
+            let number = |key: &str| item.get(key).and_then(Json::as_f64);
+            let position = item
+                .get("position")
+                .and_then(Json::as_array)
+                .and_then(|p| match p {
+                    [x, y, z] => Some(DVec3::new(x.as_f64()?, y.as_f64()?, z.as_f64()?)),
+                    _ => None,
+                })
+                .map(from_map);
+            let anchor = match (number("road"), number("s"), number("t")) {
+                (Some(road), Some(s), Some(t)) => Anchor::Road {
+                    road_id: road as u32,
+                    s,
+                    t,
+                },
+                _ => Anchor::World(position.ok_or_else(|| invalid("no position"))?),
+            };
+            // A road point is placed on the road as it is now.
+            let position = match anchor {
+                Anchor::Road { road_id, s, t } => road_point(network, road_id, s, t).or(position),
+                Anchor::World(p) => Some(p),
+            }
+            .ok_or_else(|| invalid("the road is not in the map"))?;
+            Ok(Annotation {
+                id: number("id").ok_or_else(|| invalid("no id"))? as u32,
+                severity: item
+                    .get("severity")
+                    .and_then(Json::as_str)
+                    .and_then(Severity::from_name)
+                    .unwrap_or(Severity::Info),
+                text: item
+                    .get("text")
+                    .and_then(Json::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                anchor,
+                position,
+                screenshot: item
+                    .get("screenshot")
+                    .and_then(Json::as_str)
+                    .map(str::to_string),
+                created: number("created").unwrap_or(0.0) as u64,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok((list, decals::from_json(&root)?))
+}
+
+// Adds an annotation at the selected point or the view center, with a
+// screenshot of the view, and saves the sidecar file.
+pub fn annotate(world: &mut World, severity: Severity, text: &str) -> Result<String, String> {
+    let origin = *world.resource::<RenderOrigin>();
+    let selection = world.resource::<Selection>().0;
+    let center = world
+        .query_filtered::<&CameraOrbit, With<MainCamera>>()
+        .get_single(world)
+        .map(|orbit| origin.0 + orbit.center.as_dvec3())
+        .map_err(|_| "no camera")?;
+    let (anchor, position) = match selection {
+        Some(pick) => match pick.t {
+            Some(t) => (
+                Anchor::Road {
+                    road_id: pick.road_id,
+                    s: pick.s,
+                    t,
+                },
+                pick.position,
+            ),
+            None => (Anchor::World(pick.position), pick.position),
+        },
+        None => (Anchor::World(center), center),
+    };
+
+    let annotations = world.resource::<Annotations>();
+    let id = annotations.next_id();
+    let file = annotations.file.clone();
+    let screenshot = file.as_deref().and_then(|file| {
+        let stem = file.file_name()?.to_str()?.strip_suffix(".json")?;
+        Some(format!("{stem}/{id}.png"))
+    });
+    if let (Some(file), Some(screenshot)) = (&file, &screenshot) {
+        let path = file.with_file_name(screenshot);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        let window = world
+            .query_filtered::<Entity, With<PrimaryWindow>>()
+            .get_single(world)
+            .map_err(|_| "no window")?;
+        if world
+            .resource_mut::<ScreenshotManager>()
+            .save_screenshot_to_disk(window, path)
+            .is_err()
+        {
+            warn!("annotation {id}: a screenshot is already being taken; none saved");
+        }
+    }
+
+    let created = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut annotations = world.resource_mut::<Annotations>();
+    annotations.list.push(Annotation {
+        id,
+        severity,
+        text: text.to_string(),
+        anchor,
+        position,
+        screenshot,
+        created,
+    });
+    annotations.save()?;
+    Ok(format!("annotation {id} added"))
+}
+
+// Deletes an annotation (its screenshot is kept).
+pub fn remove(world: &mut World, id: u32) -> Result<String, String> {
+    let mut annotations = world.resource_mut::<Annotations>();
+    let before = annotations.list.len();
+    annotations.list.retain(|note| note.id != id);
+    if annotations.list.len() == before {
+        return Err(format!("no annotation {id}"));
+    }
+    annotations.save()?;
+    Ok(format!("annotation {id} removed"))
+}
+
+// Lists the annotations, one per line.
+pub fn describe(world: &World) -> String {
+    let annotations = world.resource::<Annotations>();
+    if annotations.list.is_empty() {
+        return "no annotations".to_string();
+    }
+    annotations
+        .list
+        .iter()
+        .map(|note| {
+            let place = match note.anchor {
+                Anchor::Road { road_id, s, t } => format!("road {road_id} s={s:.1} t={t:.1}"),
+                Anchor::World(p) => {
+                    let p = to_map(p);
+                    format!("x={:.1} y={:.1}", p.x, p.y)
+                }
+            };
+            format!(
+                "{} {} ({place}): {}",
+                note.id,
+                note.severity.name(),
+                note.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn start_note(keys: Res<ButtonInput<KeyCode>>, mut console: ResMut<Console>) {
+    if keys.just_pressed(KeyCode::KeyN) {
+        console.prompt("note warning ");
+    }
+}
+
+fn draw_pins(
+    annotations: Res<Annotations>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    for note in &annotations.list {
+        let p = origin.to_render(note.position);
+        let color = note.severity.color(&theme);
+        let head = p + Vec3::Y * PIN_HEIGHT;
+        gizmos.line(p, head, color);
+        gizmos.sphere(head, Quat::IDENTITY, PIN_RADIUS, color);
+    }
+}
@@ -0,0 +1,758 @@
+// Export of the road network to Apollo's HD map format.
+//
+// Apollo loads maps from `base_map.bin` (binary protobuf) or, equivalently,
+// `base_map.txt` (protobuf text format). Only the text form is written, so
+// that no protobuf tooling is needed; `protoc --encode` converts it to the
+// binary form if a pipeline insists on it. Asking for a `.bin` file is an
+// error rather than a text file under a binary name.
+//
+// Apollo uses a right-handed, Z-up frame with x pointing east and y north.
+// The viewer is Y-up with roads lying in the XZ plane, so positions are
+// converted with `to_apollo` on the way out.
+//
+// Apollo lanes run in their direction of travel, so the curves of lanes
+// driven against the OpenDRIVE reference line are reversed, with their left
+// and right boundaries swapped. Predecessors and successors follow the lane
+// graph of the router, across linked roads and through junctions. Junctions
+// are outlined by the convex hull of their connecting lanes, and traffic
+// lights, stop signs and yield signs get a stop line across each lane they
+// apply to, tied to it by an overlap as Apollo's planner expects.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::plan_export::convex_hull;
+use crate::routing::{self, DEFAULT_SPEED, DRIVING_TYPES};
+use crate::signals::{Signal, SignalKind};
+use crate::tessellation::point_at;
+use crate::units::KMH;
+use crate::{RoadNetwork, RoadSegment};
+
+// Writes the network as an Apollo text-format map to `path`.
+pub fn write_map(network: &RoadNetwork, path: &Path) -> io::Result<()> {
+    if path.extension().is_some_and(|ext| ext == "bin") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only the text format is written; convert it with `protoc --encode`",
+        ));
+    }
+    std::fs::write(path, to_text(network))
+}
+
+// Size of signal heads the map gives none for, in meters.
+const SIGNAL_WIDTH: f64 = 0.3;
+const SIGNAL_HEIGHT: f64 = 0.9;
+
+// Renders the network as an Apollo `Map` message in protobuf text format.
+pub fn to_text(network: &RoadNetwork) -> String {
+    let successors = routing::lane_graph(network);
+    let mut predecessors = vec![Vec::new(); successors.len()];
+    for (from, next) in successors.iter().enumerate() {
+        for &to in next {
+            predecessors[to].push(from);
+        }
+    }
+    let controls = controls(network);
+    let junctions = junctions(network);
+    let overlaps = overlaps(network, &controls, &junctions);
+
+    let mut out = ProtoWriter::default();
+
+    out.open("header");
+    out.field_str("version", "1.000000");
+    out.field_str("vendor", "rsodr");
+    out.close();
+
+    for (index, segment) in network.segments.iter().enumerate() {
+        let lanes = |indices: &[usize]| indices.iter().map(|&i| &network.segments[i]).collect();
+        let overlap_ids: Vec<&str> = overlaps
+            .iter()
+            .filter(|overlap| overlap.lane == index)
+            .map(|overlap| overlap.id.as_str())
+            .collect();
+        write_lane(
+            &mut out,
+            network,
+            segment,
+            lanes(&predecessors[index]),
+            lanes(&successors[index]),
+            &overlap_ids,
+        );
+    }
+
+    // Apollo groups lanes into roads and road sections. Lane sections map onto
+    // Apollo road sections one to one.
+    let mut roads: BTreeMap<u32, BTreeMap<u32, Vec<String>>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads
+            .entry(segment.road_id)
+            .or_default()
+            .entry(segment.lane_section_id)
+            .or_default()
+            .push(lane_id(segment));
+    }
+    for (road_id, sections) in roads {
+        out.open("road");
+        out.id("id", &road_id.to_string());
+        for (section_id, lanes) in sections {
+            out.open("section");
+            out.id("id", &section_id.to_string());
+            for lane in lanes {
+                out.id("lane_id", &lane);
+            }
+            out.close();
+        }
+        if let Some(junction) = junction_of(network, road_id) {
+            out.id("junction_id", &junction.to_string());
+        }
+        out.close();
+    }
+
+    for (junction, lanes) in &junctions {
+        out.open("junction");
+        out.id("id", &junction.to_string());
+        let points: Vec<DVec3> = lanes
+            .iter()
+            .flat_map(|&i| {
+                let lane = &network.segments[i];
+                lane.left_side.iter().chain(&lane.right_side)
+            })
+            .map(|p| to_apollo(*p))
+            .collect();
+        write_polygon(&mut out, &outline(&points));
+        for overlap in overlaps
+            .iter()
+            .filter(|o| o.info == "junction_overlap_info" && o.object == junction.to_string())
+        {
+            out.id("overlap_id", &overlap.id);
+        }
+        out.close();
+    }
+
+    for control in &controls {
+        write_control(&mut out, control, &overlaps);
+    }
+
+    for overlap in &overlaps {
+        let lane = &network.segments[overlap.lane];
+        out.open("overlap");
+        out.id("id", &overlap.id);
+        out.open("object");
+        out.id("id", &lane_id(lane));
+        out.open("lane_overlap_info");
+        out.field("start_s", overlap.s.0);
+        out.field("end_s", overlap.s.1);
+        out.field_raw("is_merge", "false");
+        out.close();
+        out.close();
+        out.open("object");
+        out.id("id", &overlap.object);
+        out.open(overlap.info);
+        out.close();
+        out.close();
+        out.close();
+    }
+
+    out.finish()
+}
+
+// Apollo identifies lanes by string; we encode road, lane section and lane.
+fn lane_id(segment: &RoadSegment) -> String {
+    format!(
+        "{}_{}_{}",
+        segment.road_id, segment.lane_section_id, segment.lane_id
+    )
+}
+
+// Converts a viewer position (Y-up) into Apollo's Z-up frame.
+// Adding zero turns the `-0` of negated zeros into a plain `0` in the output.
+fn to_apollo(p: DVec3) -> DVec3 {
+    DVec3::new(p.x, -p.z + 0.0, p.y)
+}
+
+fn junction_of(network: &RoadNetwork, road: u32) -> Option<u32> {
+    network.roads.get(&road).and_then(|info| info.junction)
+}
+
+// The boundaries of a lane in its direction of travel, left then right.
+fn driving_boundaries(segment: &RoadSegment) -> (Vec<DVec3>, Vec<DVec3>) {
+    let convert = |points: &[DVec3]| points.iter().copied().map(to_apollo).collect::<Vec<_>>();
+    if segment.follows_reference() {
+        (convert(&segment.left_side), convert(&segment.right_side))
+    } else {
+        let mut left = convert(&segment.right_side);
+        let mut right = convert(&segment.left_side);
+        left.reverse();
+        right.reverse();
+        (left, right)
+    }
+}
+
+// Apollo's lane type for an OpenDRIVE one.
+fn lane_type(segment: &RoadSegment) -> &'static str {
+    match segment.lane_type.as_str() {
+        kind if DRIVING_TYPES.contains(&kind) => "CITY_DRIVING",
+        "bus" | "taxi" | "HOV" => "CITY_DRIVING",
+        "biking" => "BIKING",
+        "sidewalk" | "walking" => "SIDEWALK",
+        "parking" => "PARKING",
+        "shoulder" => "SHOULDER",
+        _ => "NONE",
+    }
+}
+
+// Writes a single `lane` message for a segment.
+fn write_lane(
+    out: &mut ProtoWriter,
+    network: &RoadNetwork,
+    segment: &RoadSegment,
+    predecessors: Vec<&RoadSegment>,
+    successors: Vec<&RoadSegment>,
+    overlap_ids: &[&str],
+) {
+    let (left, right) = driving_boundaries(segment);
+    // The central curve runs halfway between the two boundaries.
+    let center: Vec<DVec3> = left
+        .iter()
+        .zip(&right)
+        .map(|(l, r)| (*l + *r) / 2.0)
+        .collect();
+
+    out.open("lane");
+    out.id("id", &lane_id(segment));
+
+    out.open("central_curve");
+    write_curve_segment(out, &center, 0.0);
+    out.close();
+
+    // The marks along the reference line's left and right of the lane.
+    let (reference_left, reference_right) = (
+        boundary_type(network, segment, 1),
+        boundary_type(network, segment, -1),
+    );
+    let (left_type, right_type) = if segment.follows_reference() {
+        (reference_left, reference_right)
+    } else {
+        (reference_right, reference_left)
+    };
+    write_boundary(out, "left_boundary", &left, left_type);
+    write_boundary(out, "right_boundary", &right, right_type);
+
+    // Apollo measures lanes along their central curve, which is longer than
+    // the reference line on the outside of bends and shorter on the inside.
+    out.field("length", polyline_length(&center));
+    out.field("speed_limit", segment.speed.unwrap_or(DEFAULT_SPEED * KMH));
+    for id in overlap_ids {
+        out.id("overlap_id", id);
+    }
+    for prev in predecessors {
+        out.id("predecessor_id", &lane_id(prev));
+    }
+    for next in successors {
+        out.id("successor_id", &lane_id(next));
+    }
+    out.field_enum("type", lane_type(segment));
+    out.field_enum("turn", "NO_TURN");
+    if let Some(junction) = junction_of(network, segment.road_id) {
+        out.id("junction_id", &junction.to_string());
+    }
+
+    // Width samples are measured from the central curve to each boundary.
+    let mut s = 0.0;
+    for (i, (l, r)) in left.iter().zip(&right).enumerate() {
+        if i > 0 {
+            s += center[i].distance(center[i - 1]);
+        }
+        let half_width = l.distance(*r) / 2.0;
+        for name in ["left_sample", "right_sample"] {
+            out.open(name);
+            out.field("s", s);
+            out.field("width", half_width);
+            out.close();
+        }
+    }
+
+    out.field_enum(
+        "direction",
+        if segment.lane_type == "bidirectional" {
+            "BIDIRECTION"
+        } else {
+            "FORWARD"
+        },
+    );
+    out.close();
+}
+
+// The Apollo boundary type on one side of a lane, `side` 1 for the left of
+// the reference line and -1 for its right, and whether nothing is painted
+// there. A lane's road mark is on its outer boundary; the inner one carries
+// the mark of the lane next to it towards the center, or the center lane's.
+// Apollo gives a boundary one type, that of the mark where the section
+// starts. Where the map gives no mark, boundaries shared with a
+// neighbouring lane are dashed and outer edges solid.
+fn boundary_type(network: &RoadNetwork, segment: &RoadSegment, side: i32) -> (&'static str, bool) {
+    let mark = if segment.lane_id.signum() == side {
+        segment.road_marks.first()
+    } else if segment.lane_id + side == 0 {
+        segment.center_marks.first()
+    } else {
+        network
+            .segments
+            .iter()
+            .find(|other| {
+                other.road_id == segment.road_id
+                    && other.lane_section_id == segment.lane_section_id
+                    && other.lane_id == segment.lane_id + side
+            })
+            .and_then(|other| other.road_marks.first())
+    };
+    let Some(mark) = mark else {
+        return match has_neighbour(network, segment, side) {
+            true => ("DOTTED_WHITE", false),
+            false => ("SOLID_WHITE", false),
+        };
+    };
+    let yellow = mark.color == "yellow" || mark.color == "orange";
+    let kind = mark.kind.as_str();
+    let solid = if kind == "custom" {
+        mark.lines.iter().any(|line| line.space <= 0.0)
+    } else {
+        kind.split_whitespace().any(|line| line == "solid")
+    };
+    match kind {
+        "none" | "grass" | "edge" => ("UNKNOWN", true),
+        "curb" => ("CURB", false),
+        "solid solid" if yellow => ("DOUBLE_YELLOW", false),
+        _ => (
+            match (yellow, solid) {
+                (true, true) => "SOLID_YELLOW",
+                (true, false) => "DOTTED_YELLOW",
+                (false, true) => "SOLID_WHITE",
+                (false, false) => "DOTTED_WHITE",
+            },
+            false,
+        ),
+    }
+}
+
+// Writes a lane boundary of the given type.
+fn write_boundary(
+    out: &mut ProtoWriter,
+    name: &str,
+    points: &[DVec3],
+    (kind, unpainted): (&str, bool),
+) {
+    out.open(name);
+    out.open("curve");
+    write_curve_segment(out, points, 0.0);
+    out.close();
+    out.field("length", polyline_length(points));
+    out.field_raw("virtual", if unpainted { "true" } else { "false" });
+    out.open("boundary_type");
+    out.field("s", 0.0);
+    out.field_enum("types", kind);
+    out.close();
+    out.close();
+}
+
+// Writes a `segment` holding a single line segment through `points`.
+fn write_curve_segment(out: &mut ProtoWriter, points: &[DVec3], start_s: f64) {
+    out.open("segment");
+    out.open("line_segment");
+    for p in points {
+        out.point("point", *p);
+    }
+    out.close();
+    out.field("s", start_s);
+    if let Some(first) = points.first() {
+        out.point("start_position", *first);
+    }
+    if let [a, b, ..] = points {
+        out.field("heading", (b.y - a.y).atan2(b.x - a.x));
+    }
+    out.field("length", polyline_length(points));
+    out.close();
+}
+
+fn write_polygon(out: &mut ProtoWriter, points: &[DVec3]) {
+    out.open("polygon");
+    for p in points {
+        out.point("point", *p);
+    }
+    out.close();
+}
+
+// The convex hull of points seen from above, at their mean height.
+fn outline(points: &[DVec3]) -> Vec<DVec3> {
+    let z = points.iter().map(|p| p.z).sum::<f64>() / points.len().max(1) as f64;
+    convex_hull(points.iter().map(|p| p.truncate()).collect())
+        .into_iter()
+        .map(|p| p.extend(z))
+        .collect()
+}
+
+// Checks whether the lane `offset` lanes away exists in the same lane section.
+// Lane IDs skip zero, which is the reference line.
+fn has_neighbour(network: &RoadNetwork, segment: &RoadSegment, offset: i32) -> bool {
+    let mut wanted = segment.lane_id + offset;
+    if wanted == 0 {
+        wanted += offset;
+    }
+    network.segments.iter().any(|other| {
+        other.road_id == segment.road_id
+            && other.lane_section_id == segment.lane_section_id
+            && other.lane_id == wanted
+    })
+}
+
+// Sums the lengths of the straight pieces of a polyline.
+fn polyline_length(points: &[DVec3]) -> f64 {
+    points.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+// The lanes of each junction's connecting roads, by junction.
+fn junctions(network: &RoadNetwork) -> BTreeMap<u32, Vec<usize>> {
+    let mut junctions: BTreeMap<u32, Vec<usize>> =
+        network.junctions.keys().map(|&j| (j, Vec::new())).collect();
+    for (index, segment) in network.segments.iter().enumerate() {
+        if let Some(junction) = junction_of(network, segment.road_id) {
+            junctions.entry(junction).or_default().push(index);
+        }
+    }
+    junctions.retain(|_, lanes| !lanes.is_empty());
+    junctions
+}
+
+// A traffic light, stop sign or yield sign, and where it stops the lanes it
+// applies to.
+struct Control<'a> {
+    signal: &'a Signal,
+    // The Apollo message and the overlap info that goes with it.
+    message: &'static str,
+    info: &'static str,
+    // The lanes, with the station of the stop line along each in driving
+    // direction and the stop line itself.
+    stops: Vec<(usize, f64, [DVec3; 2])>,
+}
+
+// The controls among the signals. Each applies to the driving lanes of its
+// road at its station facing it, like in the right-of-way inference.
+fn controls(network: &RoadNetwork) -> Vec<Control<'_>> {
+    network
+        .signals
+        .iter()
+        .filter_map(|signal| {
+            let (message, info) = match signal.kind {
+                SignalKind::TrafficLight => ("signal", "signal_overlap_info"),
+                SignalKind::Stop => ("stop_sign", "stop_sign_overlap_info"),
+                SignalKind::Yield => ("yield", "yield_sign_overlap_info"),
+                _ => return None,
+            };
+            let stops = network
+                .segments
+                .iter()
+                .enumerate()
+                .filter(|(_, lane)| {
+                    lane.road_id == signal.road_id
+                        && DRIVING_TYPES.contains(&lane.lane_type.as_str())
+                        && (lane.start_s..=lane.end_s).contains(&signal.s)
+                        && match signal.orientation.trim() {
+                            "+" => lane.follows_reference(),
+                            "-" => !lane.follows_reference(),
+                            _ => true,
+                        }
+                })
+                .filter_map(|(index, lane)| {
+                    let fraction =
+                        (signal.s - lane.start_s) / (lane.end_s - lane.start_s).max(f64::EPSILON);
+                    let across = |side: &[DVec3]| {
+                        point_at(side, fraction * polyline_length(side)).map(|(p, _)| to_apollo(p))
+                    };
+                    let line = [across(&lane.left_side)?, across(&lane.right_side)?];
+                    let length = polyline_length(&lane.centerline());
+                    let s = match lane.follows_reference() {
+                        true => fraction * length,
+                        false => (1.0 - fraction) * length,
+                    };
+                    Some((index, s, line))
+                })
+                .collect();
+            Some(Control {
+                signal,
+                message,
+                info,
+                stops,
+            })
+        })
+        .collect()
+}
+
+// The overlaps of lanes with controls at their stop lines and with the
+// junctions they run through.
+fn overlaps(
+    network: &RoadNetwork,
+    controls: &[Control],
+    junctions: &BTreeMap<u32, Vec<usize>>,
+) -> Vec<Overlap> {
+    let mut overlaps = Vec::new();
+    for control in controls {
+        for &(lane, s, _) in &control.stops {
+            overlaps.push(Overlap {
+                id: format!(
+                    "{}_{}_{}",
+                    control.message,
+                    control.signal.id,
+                    lane_id(&network.segments[lane])
+                ),
+                lane,
+                s: (s, s),
+                object: control.signal.id.clone(),
+                info: control.info,
+            });
+        }
+    }
+    for (junction, lanes) in junctions {
+        for &lane in lanes {
+            let segment = &network.segments[lane];
+            overlaps.push(Overlap {
+                id: format!("junction_{junction}_{}", lane_id(segment)),
+                lane,
+                s: (0.0, polyline_length(&segment.centerline())),
+                object: junction.to_string(),
+                info: "junction_overlap_info",
+            });
+        }
+    }
+    overlaps
+}
+
+// Where a lane meets a control or a junction, in Apollo's terms.
+struct Overlap {
+    id: String,
+    lane: usize,
+    // The stretch of the lane, along its central curve.
+    s: (f64, f64),
+    object: String,
+    info: &'static str,
+}
+
+// Writes a traffic light, stop sign or yield sign with its stop lines.
+fn write_control(out: &mut ProtoWriter, control: &Control, overlaps: &[Overlap]) {
+    let signal = control.signal;
+    out.open(control.message);
+    out.id("id", &signal.id);
+    for (_, _, line) in &control.stops {
+        out.open("stop_line");
+        write_curve_segment(out, line, 0.0);
+        out.close();
+    }
+    for overlap in overlaps
+        .iter()
+        .filter(|o| o.info == control.info && o.object == signal.id)
+    {
+        out.id("overlap_id", &overlap.id);
+    }
+    if control.message == "signal" {
+        // The head as an upright rectangle across the road, with red,
+        // yellow and green lights from the top.
+        let across = control
+            .stops
+            .first()
+            .map(|(_, _, [a, b])| (*b - *a).truncate().normalize_or_zero())
+            .filter(|d| *d != DVec2::ZERO)
+            .unwrap_or(DVec2::X);
+        let foot = to_apollo(signal.position);
+        let half = across.extend(0.0) * positive_or(signal.width, SIGNAL_WIDTH) / 2.0;
+        let height = positive_or(signal.height, SIGNAL_HEIGHT);
+        let bottom = foot + DVec3::Z * signal.z_offset;
+        let top = bottom + DVec3::Z * height;
+        out.open("boundary");
+        for p in [bottom - half, bottom + half, top + half, top - half] {
+            out.point("point", p);
+        }
+        out.close();
+        for light in 0..3 {
+            out.open("subsignal");
+            out.id("id", &light.to_string());
+            out.field_enum("type", "CIRCLE");
+            let at = top - DVec3::Z * height * (2.0 * light as f64 + 1.0) / 6.0;
+            out.point("location", at);
+            out.close();
+        }
+        out.field_enum("type", "MIX_3_VERTICAL");
+    }
+    out.close();
+}
+
+fn positive_or(value: f64, default: f64) -> f64 {
+    if value > 0.0 {
+        value
+    } else {
+        default
+    }
+}
+
+// A minimal protobuf text-format writer that keeps track of indentation.
+#[derive(Default)]
+struct ProtoWriter {
+    text: String,
+    depth: usize,
+}
+
+impl ProtoWriter {
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.text.push_str("  ");
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        self.indent();
+        let _ = writeln!(self.text, "{name} {{");
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth -= 1;
+        self.indent();
+        self.text.push_str("}\n");
+    }
+
+    fn field_raw(&mut self, name: &str, value: &str) {
+        self.indent();
+        let _ = writeln!(self.text, "{name}: {value}");
+    }
+
+    fn field(&mut self, name: &str, value: f64) {
+        self.field_raw(name, &value.to_string());
+    }
+
+    fn field_enum(&mut self, name: &str, value: &str) {
+        self.field_raw(name, value);
+    }
+
+    fn field_str(&mut self, name: &str, value: &str) {
+        self.field_raw(name, &format!("{value:?}"));
+    }
+
+    // Apollo wraps every identifier in an `Id { id: "..." }` message.
+    fn id(&mut self, name: &str, value: &str) {
+        self.open(name);
+        self.field_str("id", value);
+        self.close();
+    }
+
+    fn point(&mut self, name: &str, p: DVec3) {
+        self.open(name);
+        self.field("x", p.x);
+        self.field("y", p.y);
+        self.field("z", p.z);
+        self.close();
+    }
+
+    fn finish(self) -> String {
+        self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{load, temp_dir};
+
+    // The text of the message of a lane.
+    fn lane<'a>(text: &'a str, id: &str) -> &'a str {
+        let start = text
+            .find(&format!("lane {{\n  id {{\n    id: \"{id}\""))
+            .unwrap();
+        let end = text[start..].find("\n}\n").unwrap();
+        &text[start..start + end]
+    }
+
+    #[test]
+    fn lanes_carry_type_speed_and_marks() {
+        let text = to_text(&load("straight.xodr"));
+        let right = lane(&text, "1_1_-1");
+        assert!(right.contains("speed_limit: 13.88888888888889"));
+        assert!(right.contains("type: CITY_DRIVING"));
+        assert!(right.contains("direction: FORWARD"));
+        // The yellow double center line on its left, the broken mark of its
+        // own on its right.
+        let left_boundary = &right[right.find("left_boundary").unwrap()..];
+        let right_boundary = &right[right.find("right_boundary").unwrap()..];
+        assert!(left_boundary.contains("types: DOUBLE_YELLOW"));
+        assert!(right_boundary.contains("types: DOTTED_WHITE"));
+        assert!(lane(&text, "1_1_-2").contains("type: SIDEWALK"));
+
+        // Lane 1 runs west, so its curve starts at the far end and the center
+        // line is on its left, as is its own yellow mark on the right.
+        let left = lane(&text, "1_1_1");
+        assert!(left.contains("x: 100"));
+        assert!(left.find("x: 100").unwrap() < left.find("x: 0\n").unwrap());
+        let left_boundary = &left[left.find("left_boundary").unwrap()..];
+        assert!(left_boundary.contains("types: DOUBLE_YELLOW"));
+        assert!(left[left.find("right_boundary").unwrap()..].contains("types: DOTTED_YELLOW"));
+    }
+
+    #[test]
+    fn junctions_link_the_roads_through_them() {
+        let text = to_text(&load("junction.xodr"));
+        let west = lane(&text, "1_1_-1");
+        assert!(west.contains("successor_id {\n    id: \"3_1_-1\""));
+        let through = lane(&text, "3_1_-1");
+        assert!(through.contains("predecessor_id {\n    id: \"1_1_-1\""));
+        assert!(through.contains("successor_id {\n    id: \"2_1_-1\""));
+        assert!(through.contains("junction_id {\n    id: \"100\""));
+        assert!(through.contains("overlap_id {\n    id: \"junction_100_3_1_-1\""));
+        assert!(text.contains("junction {\n  id {\n    id: \"100\"\n  }\n  polygon {"));
+        assert!(text.contains("junction_overlap_info {"));
+    }
+
+    #[test]
+    fn controls_stop_the_lanes_they_face() {
+        let text = to_text(&load("signals.xodr"));
+        // The speed limit is no control.
+        assert!(!text.contains("id: \"1\"\n  }\n  stop_line"));
+        assert!(text.contains("stop_sign {\n  id {\n    id: \"2\"\n  }\n  stop_line {"));
+        assert!(text.contains("signal {\n  id {\n    id: \"3\"\n  }\n  stop_line {"));
+        assert!(text.contains("type: MIX_3_VERTICAL"));
+        assert_eq!(text.matches("subsignal {").count(), 3);
+        let driving = lane(&text, "1_1_-1");
+        assert!(driving.contains("id: \"stop_sign_2_1_1_-1\""));
+        assert!(driving.contains("id: \"signal_3_1_1_-1\""));
+        // The overlap sits at the signal's station along the lane.
+        let overlap = &text[text.find("id: \"signal_3_1_1_-1\"\n  }\n  object").unwrap()..];
+        assert!(overlap.contains("start_s: 95\n"));
+        assert!(overlap.contains("signal_overlap_info {"));
+    }
+
+    #[test]
+    fn lengths_follow_the_central_curve() {
+        let text = to_text(&load("curve.xodr"));
+        let length = |id| {
+            let lane = lane(&text, id);
+            let start = lane.rfind("\n  length: ").unwrap() + 11;
+            let end = start + lane[start..].find('\n').unwrap();
+            lane[start..end].parse::<f64>().unwrap()
+        };
+        // A quarter circle of radius 50 turning left: the centers of the lanes
+        // lie 1.75 m inside and outside of it.
+        let quarter = std::f64::consts::FRAC_PI_2;
+        assert!((length("1_1_1") - 48.25 * quarter).abs() < 0.1);
+        assert!((length("1_1_-1") - 51.75 * quarter).abs() < 0.1);
+    }
+
+    #[test]
+    fn binary_maps_are_refused() {
+        let dir = temp_dir("apollo_bin");
+        let path = dir.join("base_map.bin");
+        let error = write_map(&load("straight.xodr"), &path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+        write_map(&load("straight.xodr"), &dir.join("base_map.txt")).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+// Asset packs: glTF models for the objects of a map.
+//
+// An asset pack is a folder of glTF files with a mapping file, `assets.json`,
+// that says which objects each model stands for, by OpenDRIVE object type
+// and, optionally, name:
+//
+//   { "assets": [
+//     { "type": "tree", "model": "trees/oak.glb", "height": 8.0 },
+//     { "type": "building", "name": "church", "model": "church.gltf" },
+//     { "type": "streetLamp", "model": "lamp.glb", "height": 6.0 } ] }
+//
+// Types and names are matched regardless of case, an entry with a name
+// before one without. `height` is the model's own height: objects that give
+// theirs are scaled to it, and the others are drawn at the model's size.
+// Packs are given to `view --assets <dir>`, which may be repeated, or loaded
+// with `assets load <dir>`; the packs loaded later win.
+//
+// Objects with a model are drawn with it instead of the built-in shapes (see
+// `placement`), one copy per `<repeat>` copy or one for the object itself,
+// standing on the road and facing along it. Copies of a model share its
+// meshes and materials, so they are still drawn instanced. Continuous
+// repeats, such as guardrails, are left to `barriers`.
+
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::cut;
+use crate::edit::NetworkChanged;
+use crate::json::{self, Json};
+use crate::origin::WorldPosition;
+use crate::placement::copies;
+use crate::signals::{Signal, SignalKind};
+use crate::RoadNetwork;
+
+// The mapping file in a pack's folder.
+const MAPPING_FILE: &str = "assets.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub object_type: String,
+    // Matches every object of the type if not given.
+    pub name: Option<String>,
+    // The glTF file, whose first scene is drawn.
+    pub model: PathBuf,
+    // The model's own height, in meters.
+    pub height: Option<f64>,
+}
+
+impl Asset {
+    fn matches(&self, object: &Signal) -> bool {
+        self.object_type
+            .eq_ignore_ascii_case(object.type_code.trim())
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| name.eq_ignore_ascii_case(object.name.trim()))
+    }
+}
+
+// Reads a mapping file, with models taken relative to `dir`.
+pub fn parse(text: &str, dir: &Path) -> Result<Vec<Asset>, String> {
+    let json = json::parse(text)?;
+    json.get("assets")
+        .and_then(Json::as_array)
+        .ok_or("expected an object with an `assets` array")?
+        .iter()
+        .enumerate()
+        .map(|(i, asset)| {
+            let invalid = |what: &str| format!("asset {}: {what}", i + 1);
+            let text = |key: &str| asset.get(key).and_then(Json::as_str);
+            Ok(Asset {
+                object_type: text("type")
+                    .ok_or_else(|| invalid("no `type`"))?
+                    .to_string(),
+                name: text("name").map(str::to_string),
+                model: dir.join(text("model").ok_or_else(|| invalid("no `model`"))?),
+                height: match asset.get("height") {
+                    None => None,
+                    Some(height) => Some(
+                        height
+                            .as_f64()
+                            .filter(|height| *height > 0.0)
+                            .ok_or_else(|| invalid("`height` must be a positive number"))?,
+                    ),
+                },
+            })
+        })
+        .collect()
+}
+
+// The loaded packs' assets, the latest first.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct AssetPacks {
+    pub assets: Vec<Asset>,
+    pub folders: Vec<PathBuf>,
+}
+
+impl AssetPacks {
+    // Adds the pack in a folder, ahead of those loaded before.
+    pub fn read(&mut self, dir: &Path) -> Result<usize, String> {
+        let mapping = dir.join(MAPPING_FILE);
+        let text =
+            std::fs::read_to_string(&mapping).map_err(|e| format!("{}: {e}", mapping.display()))?;
+        // The asset server reads paths relative to its own folder.
+        let dir = std::env::current_dir()
+            .map(|current| current.join(dir))
+            .unwrap_or_else(|_| dir.to_path_buf());
+        let assets = parse(&text, &dir).map_err(|e| format!("{}: {e}", mapping.display()))?;
+        let count = assets.len();
+        self.assets.splice(0..0, assets);
+        self.folders.push(dir);
+        Ok(count)
+    }
+
+    // The model an object is drawn with, if a pack has one.
+    pub fn asset(&self, object: &Signal) -> Option<&Asset> {
+        if object.kind != SignalKind::Object {
+            return None;
+        }
+        let matching = || self.assets.iter().filter(|asset| asset.matches(object));
+        matching()
+            .find(|asset| asset.name.is_some())
+            .or_else(|| matching().next())
+    }
+}
+
+// Where the copies of an object stand, their heights, and the direction of
+// the road there.
+pub fn stands(network: &RoadNetwork, object: &Signal) -> Vec<(DVec3, f64, DVec3)> {
+    if object.repeats.is_empty() {
+        let Some(cut) = cut(network, object.road_id, object.s) else {
+            return Vec::new();
+        };
+        return vec![(
+            object.position + DVec3::Y * object.z_offset,
+            object.height,
+            cut.direction,
+        )];
+    }
+    let copies = copies(network, object);
+    let along = cut(network, object.road_id, object.s).map_or(DVec3::X, |cut| cut.direction);
+    (0..copies.len())
+        .map(|i| {
+            // Copies follow the road, so their neighbors give its direction.
+            let before = copies[i.saturating_sub(1)].0;
+            let after = copies[(i + 1).min(copies.len() - 1)].0;
+            let direction = (after - before).normalize_or_zero();
+            let direction = if direction == DVec3::ZERO {
+                along
+            } else {
+                direction
+            };
+            (copies[i].0, copies[i].1, direction)
+        })
+        .collect()
+}
+
+// `assets` in the console: lists the packs, loads one or drops them all.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut packs = world.resource_mut::<AssetPacks>();
+    match args {
+        [] if packs.folders.is_empty() => Ok("no asset packs".to_string()),
+        [] => Ok(packs
+            .folders
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")),
+        ["load", dir] => {
+            let count = packs.read(Path::new(dir))?;
+            Ok(format!("read {count} assets"))
+        }
+        ["clear"] => {
+            *packs = AssetPacks::default();
+            Ok(String::new())
+        }
+        _ => Err("expected load <dir> or clear".to_string()),
+    }
+}
+
+// Marks a model drawn for an object.
+#[derive(Component)]
+struct ObjectModel;
+
+pub struct AssetPackPlugin;
+
+impl Plugin for AssetPackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AssetPacks>()
+            .add_systems(Startup, spawn_models)
+            .add_systems(
+                Update,
+                (despawn_models, spawn_models).chain().run_if(
+                    on_event::<NetworkChanged>()
+                        .or_else(|packs: Res<AssetPacks>| packs.is_changed() && !packs.is_added()),
+                ),
+            );
+    }
+}
+
+fn despawn_models(mut commands: Commands, models: Query<Entity, With<ObjectModel>>) {
+    for model in &models {
+        commands.entity(model).despawn_recursive();
+    }
+}
+
+fn spawn_models(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    packs: Res<AssetPacks>,
+    network: Res<RoadNetwork>,
+) {
+    for object in &network.signals {
+        let Some(asset) = packs.asset(object) else {
+            continue;
+        };
+        // The server hands out the same scene for the same file, so every
+        // copy shares the model's meshes and materials.
+        let scene: Handle<Scene> = server.load(format!("{}#Scene0", asset.model.display()));
+        for (at, height, along) in stands(&network, object) {
+            let scale = match asset.height {
+                Some(own) if height > 0.0 => (height / own) as f32,
+                _ => 1.0,
+            };
+            let heading = Vec3::new(along.x as f32, 0.0, along.z as f32).normalize_or_zero();
+            let rotation = if heading == Vec3::ZERO {
+                Quat::IDENTITY
+            } else {
+                Quat::from_rotation_arc(Vec3::Z, heading)
+            };
+            commands.spawn((
+                SceneBundle {
+                    scene: scene.clone(),
+                    transform: Transform::from_rotation(rotation).with_scale(Vec3::splat(scale)),
+                    ..default()
+                },
+                WorldPosition(at),
+                ObjectModel,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placement::{self, PlacedKind};
+    use crate::sample_maps::{assert_points_near, load, temp_dir};
+
+    #[test]
+    fn packs_map_objects_to_models() {
+        let network = load("straight.xodr");
+        let mut trees =
+            placement::generate(&network, 1, PlacedKind::Tree, 10.0, 1.0, false).unwrap();
+        trees.name = "Oak".to_string();
+
+        let dir = temp_dir("asset_packs");
+        std::fs::write(
+            dir.join("assets.json"),
+            r#"{ "assets": [ { "type": "Tree", "model": "tree.glb", "height": 8 },
+                            { "type": "tree", "name": "oak", "model": "oak.glb" } ] }"#,
+        )
+        .unwrap();
+        let mut packs = AssetPacks::default();
+        assert_eq!(packs.read(&dir), Ok(2));
+        // The entry naming the object wins, and models are found in the pack.
+        let asset = packs.asset(&trees).unwrap();
+        assert!(asset.model.is_absolute() && asset.model.ends_with("oak.glb"));
+        trees.name = "Birch".to_string();
+        assert_eq!(packs.asset(&trees).unwrap().height, Some(8.0));
+        assert!(packs.read(&dir.join("missing")).is_err());
+        assert!(parse(r#"{ "assets": [ { "type": "tree" } ] }"#, &dir).is_err());
+
+        // Copies face along the road.
+        let stands = stands(&network, &trees);
+        assert_eq!(stands.len(), 10);
+        assert_points_near(stands[1].0, DVec3::new(10.0, 0.0, 7.5));
+        assert_points_near(stands[1].2, DVec3::X);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,492 @@
+// Guardrails and barriers along road edges.
+//
+// `barrier guardrail|jersey [left|right]` in the console lines the outer
+// edges of the selected road (both unless a side is given) with a
+// continuous barrier. It goes into the model as an `<object
+// type="barrier">` with `<repeat>` records of distance zero, so it is
+// exported with the map like any object read from one. The edge is
+// followed at `CLEARANCE` outside the outermost lane; where its offset
+// changes along the road, it is split into stretches over which a straight
+// change from start to end stays within `TOLERANCE`, one repeat record
+// each. Running the command again replaces the barrier on that side;
+// `barrier clear` removes the selected road's barriers.
+//
+// Every continuous repeated object of the map, generated or read, is drawn
+// as a solid along its repeats: guardrails as a steel band on posts, jersey
+// barriers with their sloped concrete profile, and anything else as a box
+// of the object's width and height.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::cut;
+use crate::edit::NetworkChanged;
+use crate::odr::touch;
+use crate::origin::WorldPosition;
+use crate::selection::Selection;
+use crate::signals::{ObjectRepeat, Signal, SignalKind};
+use crate::tessellation::TriangleMesh;
+use crate::xodr::ElementXml;
+use crate::RoadNetwork;
+
+// Gap between the road edge and a generated barrier, in meters.
+const CLEARANCE: f64 = 0.5;
+
+// How far a barrier may stray from the edge offset it follows, in meters.
+const TOLERANCE: f64 = 0.05;
+
+// Stretches are not split below this length, in meters.
+const MIN_STRETCH: f64 = 2.0;
+
+// Distance between the cross sections of a barrier mesh, in meters.
+const STEP: f64 = 1.0;
+
+// Guardrail posts: their spacing and their edge length, in meters.
+const POST_SPACING: f64 = 2.0;
+const POST_SIZE: f64 = 0.1;
+
+// Width of objects that give none, in meters.
+const DEFAULT_WIDTH: f64 = 0.3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarrierKind {
+    Guardrail,
+    Jersey,
+}
+
+impl BarrierKind {
+    fn named(name: &str) -> Option<Self> {
+        match name {
+            "guardrail" => Some(BarrierKind::Guardrail),
+            "jersey" => Some(BarrierKind::Jersey),
+            _ => None,
+        }
+    }
+
+    // The object name written, as OpenDRIVE examples use it.
+    fn object_name(self) -> &'static str {
+        match self {
+            BarrierKind::Guardrail => "guardRail",
+            BarrierKind::Jersey => "jerseyBarrier",
+        }
+    }
+
+    // Width and height, in meters.
+    fn size(self) -> (f64, f64) {
+        match self {
+            BarrierKind::Guardrail => (0.1, 0.75),
+            BarrierKind::Jersey => (0.6, 0.81),
+        }
+    }
+}
+
+// Cross-section outlines, counter-clockwise, across (left positive) and up,
+// for a barrier of unit width and height.
+const BOX: &[(f64, f64)] = &[(-0.5, 0.0), (0.5, 0.0), (0.5, 1.0), (-0.5, 1.0)];
+const GUARDRAIL_BAND: &[(f64, f64)] = &[(-0.5, 0.6), (0.5, 0.6), (0.5, 1.0), (-0.5, 1.0)];
+const JERSEY: &[(f64, f64)] = &[
+    (-0.5, 0.0),
+    (0.5, 0.0),
+    (0.25, 0.15),
+    (0.125, 1.0),
+    (-0.125, 1.0),
+    (-0.25, 0.15),
+];
+
+// The ID of the barrier generated on one side of a road.
+fn barrier_id(road_id: u32, left: bool) -> String {
+    let side = if left { "left" } else { "right" };
+    format!("barrier-{road_id}-{side}")
+}
+
+// The offset of a line `clearance` meters outside one edge of a road at
+// station `s`, if the road has lanes on that side there.
+fn edge_offset(
+    network: &RoadNetwork,
+    road_id: u32,
+    s: f64,
+    left: bool,
+    clearance: f64,
+) -> Option<f64> {
+    let cut = cut(network, road_id, s)?;
+    if left {
+        let lane = cut.lanes.first().filter(|lane| lane.lane_id > 0)?;
+        Some(lane.outer_t + clearance)
+    } else {
+        let lane = cut.lanes.last().filter(|lane| lane.lane_id < 0)?;
+        Some(lane.outer_t - clearance)
+    }
+}
+
+// Splits `start..end` into stretches over which the offset changes about
+// linearly, as (start, end, offset at start, offset at end).
+fn stretches(
+    offset: &impl Fn(f64) -> Option<f64>,
+    start: f64,
+    end: f64,
+    out: &mut Vec<(f64, f64, f64, f64)>,
+) {
+    let (Some(a), Some(b)) = (offset(start), offset(end)) else {
+        return;
+    };
+    let middle = (start + end) / 2.0;
+    let straight = offset(middle).is_none_or(|m| (m - (a + b) / 2.0).abs() <= TOLERANCE);
+    if straight || end - start < 2.0 * MIN_STRETCH {
+        out.push((start, end, a, b));
+    } else {
+        stretches(offset, start, middle, out);
+        stretches(offset, middle, end, out);
+    }
+}
+
+// The stretches of a line `clearance` meters outside one edge of a road,
+// as (start, end, offset at start, offset at end), for repeat records
+// following the edge. Empty if the road has no lanes on that side.
+pub fn edge_stretches(
+    network: &RoadNetwork,
+    road_id: u32,
+    left: bool,
+    clearance: f64,
+) -> Vec<(f64, f64, f64, f64)> {
+    let mut sections: Vec<(u32, f64, f64)> = network
+        .segments
+        .iter()
+        .filter(|lane| lane.road_id == road_id)
+        .map(|lane| (lane.lane_section_id, lane.start_s, lane.end_s))
+        .collect();
+    sections.sort_by_key(|section| section.0);
+    sections.dedup_by_key(|section| section.0);
+
+    // Sections are cut just inside their ends, where the next one starts.
+    let offset = |s: f64| edge_offset(network, road_id, s, left, clearance);
+    let mut pieces = Vec::new();
+    for (_, start, end) in sections {
+        let inset = ((end - start) * 1e-6).min(1e-3);
+        stretches(&offset, start + inset, end - inset, &mut pieces);
+    }
+    pieces
+}
+
+// A continuous barrier along one edge of a road, or `None` if the road has
+// no lanes on that side.
+pub fn generate(
+    network: &RoadNetwork,
+    road_id: u32,
+    kind: BarrierKind,
+    left: bool,
+) -> Option<Signal> {
+    let pieces = edge_stretches(network, road_id, left, CLEARANCE);
+    let &(s, _, t, _) = pieces.first()?;
+    let (width, height) = kind.size();
+    let repeats = pieces
+        .into_iter()
+        .map(|(start, end, t_start, t_end)| ObjectRepeat {
+            s: start,
+            length: end - start,
+            t_start,
+            t_end,
+            height_start: height,
+            height_end: height,
+            width_start: width,
+            width_end: width,
+            ..ObjectRepeat::default()
+        })
+        .collect();
+    let foot = cut(network, road_id, s)?;
+    Some(Signal {
+        id: barrier_id(road_id, left),
+        name: kind.object_name().to_string(),
+        road_id,
+        s,
+        t,
+        z_offset: 0.0,
+        height,
+        width,
+        length: 0.0,
+        radius: 0.0,
+        orientation: "none".to_string(),
+        kind: SignalKind::Object,
+        type_code: "barrier".to_string(),
+        subtype: String::new(),
+        country: String::new(),
+        dynamic: false,
+        value: None,
+        unit: String::new(),
+        position: foot.reference + DVec3::Y.cross(foot.direction) * t,
+        repeats,
+        xml: ElementXml::None,
+    })
+}
+
+// `barrier` in the console: adds barriers to the selected road or removes
+// them.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let road_id = world
+        .resource::<Selection>()
+        .0
+        .map(|pick| pick.road_id)
+        .ok_or("select a road first")?;
+    let (kind, sides) = match args {
+        ["clear"] => (None, vec![true, false]),
+        [kind, side @ ..] => {
+            let kind = BarrierKind::named(kind)
+                .ok_or_else(|| format!("unknown barrier `{kind}` (try guardrail or jersey)"))?;
+            let sides = match side {
+                [] => vec![true, false],
+                ["left"] => vec![true],
+                ["right"] => vec![false],
+                _ => return Err("expected left or right".to_string()),
+            };
+            (Some(kind), sides)
+        }
+        [] => return Err("expected guardrail, jersey or clear".to_string()),
+    };
+
+    let mut network = world.resource_mut::<RoadNetwork>();
+    let ids: Vec<String> = sides
+        .iter()
+        .map(|&left| barrier_id(road_id, left))
+        .collect();
+    network.signals.retain(|signal| !ids.contains(&signal.id));
+    let mut added = 0;
+    if let Some(kind) = kind {
+        let barriers: Vec<Signal> = sides
+            .iter()
+            .filter_map(|&left| generate(&network, road_id, kind, left))
+            .collect();
+        added = barriers.len();
+        network.signals.extend(barriers);
+    }
+    // The element as read no longer describes the road; exports write it
+    // from the model.
+    if let Some(info) = network.roads.get_mut(&road_id) {
+        info.xml.clear();
+    }
+
+    touch(world, road_id);
+    world.send_event(NetworkChanged {
+        changed: vec![road_id],
+    });
+    Ok(match kind {
+        Some(_) if added == 0 => format!("road {road_id} has no lanes on that side"),
+        Some(_) => format!("added {added} barriers to road {road_id}"),
+        None => format!("removed the barriers of road {road_id}"),
+    })
+}
+
+// Marks the mesh of a repeated object.
+#[derive(Component)]
+struct BarrierMesh;
+
+// The material shared by the barrier meshes.
+#[derive(Resource)]
+struct BarrierMaterial(Handle<StandardMaterial>);
+
+pub struct BarrierPlugin;
+
+impl Plugin for BarrierPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (setup, spawn_barriers).chain())
+            .add_systems(
+                Update,
+                (despawn_barriers, spawn_barriers)
+                    .chain()
+                    .run_if(on_event::<NetworkChanged>()),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.72, 0.72, 0.7),
+        perceptual_roughness: 0.6,
+        // The ends of a barrier are left open.
+        double_sided: true,
+        cull_mode: None,
+        ..default()
+    });
+    commands.insert_resource(BarrierMaterial(material));
+}
+
+// Adds an outline swept along a path, with a flat normal per face and caps
+// at both ends. The path holds positions with the directions of increasing
+// s, across to the left and up, and the scale of the outline across and up.
+fn sweep(
+    mesh: &mut TriangleMesh,
+    path: &[(DVec3, DVec3, f64, f64)],
+    outline: &[(f64, f64)],
+    origin: DVec3,
+) {
+    let point = |(at, left, width, height): (DVec3, DVec3, f64, f64), (x, y): (f64, f64)| {
+        (at + left * (x * width) + DVec3::Y * (y * height) - origin).as_vec3()
+    };
+    for (i, &a) in outline.iter().enumerate() {
+        let b = outline[(i + 1) % outline.len()];
+        for pair in path.windows(2) {
+            // Outwards from a counter-clockwise outline.
+            let normal = (pair[0].1 * (b.1 - a.1) - DVec3::Y * (b.0 - a.0))
+                .normalize_or_zero()
+                .as_vec3();
+            let base = mesh.positions.len() as u32;
+            mesh.positions.extend([
+                point(pair[0], a),
+                point(pair[0], b),
+                point(pair[1], a),
+                point(pair[1], b),
+            ]);
+            mesh.normals.extend([normal; 4]);
+            mesh.indices
+                .extend([base, base + 1, base + 2, base + 1, base + 3, base + 2]);
+        }
+    }
+    let (Some(&first), Some(&last)) = (path.first(), path.last()) else {
+        return;
+    };
+    for (end, forward) in [(first, false), (last, true)] {
+        let direction = end.1.cross(DVec3::Y).as_vec3();
+        let normal = if forward { direction } else { -direction };
+        let base = mesh.positions.len() as u32;
+        mesh.positions
+            .extend(outline.iter().map(|&corner| point(end, corner)));
+        mesh.normals.extend(vec![normal; outline.len()]);
+        for i in 1..outline.len() as u32 - 1 {
+            if forward {
+                mesh.indices.extend([base, base + i, base + i + 1]);
+            } else {
+                mesh.indices.extend([base, base + i + 1, base + i]);
+            }
+        }
+    }
+}
+
+// The mesh of a continuously repeated object, relative to `origin`.
+fn barrier_mesh(network: &RoadNetwork, object: &Signal, origin: DVec3) -> TriangleMesh {
+    let name = object.name.to_ascii_lowercase();
+    let outline = if name.contains("jersey") {
+        JERSEY
+    } else if name.contains("guard") {
+        GUARDRAIL_BAND
+    } else {
+        BOX
+    };
+    let size = |start: f64, end: f64, object: f64, f: f64| {
+        if start > 0.0 || end > 0.0 {
+            start + (end - start) * f
+        } else {
+            object
+        }
+    };
+    let default_width = if object.width > 0.0 {
+        object.width
+    } else {
+        DEFAULT_WIDTH
+    };
+    let mut mesh = TriangleMesh::default();
+    for repeat in object.repeats.iter().filter(|r| r.is_continuous()) {
+        let steps = (repeat.length / STEP).ceil().max(1.0) as usize;
+        let path: Vec<(DVec3, DVec3, f64, f64)> = (0..=steps)
+            .filter_map(|i| {
+                let f = i as f64 / steps as f64;
+                let cut = cut(network, object.road_id, repeat.s + repeat.length * f)?;
+                let left = DVec3::Y.cross(cut.direction);
+                let t = repeat.t_start + (repeat.t_end - repeat.t_start) * f;
+                let z = repeat.z_offset_start + (repeat.z_offset_end - repeat.z_offset_start) * f;
+                Some((
+                    cut.reference + left * t + DVec3::Y * z,
+                    left,
+                    size(repeat.width_start, repeat.width_end, default_width, f),
+                    size(repeat.height_start, repeat.height_end, object.height, f),
+                ))
+            })
+            .collect();
+        sweep(&mut mesh, &path, outline, origin);
+        if outline != GUARDRAIL_BAND {
+            continue;
+        }
+        // Posts under the band, as short boxes across the path.
+        let mut along = 0.0;
+        for pair in path.windows(2) {
+            let length = pair[0].0.distance(pair[1].0);
+            if along % POST_SPACING < STEP {
+                let (at, left, _, height) = pair[0];
+                let forward = left.cross(DVec3::Y) * (POST_SIZE / 2.0);
+                let post = [
+                    (at - forward, left, POST_SIZE, height),
+                    (at + forward, left, POST_SIZE, height),
+                ];
+                sweep(&mut mesh, &post, BOX, origin);
+            }
+            along += length;
+        }
+    }
+    mesh
+}
+
+fn spawn_barriers(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<BarrierMaterial>,
+    network: Res<RoadNetwork>,
+) {
+    for object in &network.signals {
+        if !object.repeats.iter().any(ObjectRepeat::is_continuous) {
+            continue;
+        }
+        let anchor = object.position;
+        let mesh = barrier_mesh(&network, object, anchor);
+        if mesh.is_empty() {
+            continue;
+        }
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh.to_mesh()),
+                material: material.0.clone(),
+                ..default()
+            },
+            WorldPosition(anchor),
+            BarrierMesh,
+        ));
+    }
+}
+
+fn despawn_barriers(mut commands: Commands, barriers: Query<Entity, With<BarrierMesh>>) {
+    for entity in &barriers {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_near, load, TOLERANCE};
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    #[test]
+    fn generated_barriers() {
+        let mut network = load("straight.xodr");
+        // The straight road's edges are 3.5 m left and 6.5 m right of it.
+        let left = generate(&network, 1, BarrierKind::Jersey, true).unwrap();
+        let right = generate(&network, 1, BarrierKind::Guardrail, false).unwrap();
+        assert_eq!(left.repeats.len(), 1);
+        assert_near(left.repeats[0].t_start, 4.0, TOLERANCE);
+        let repeat = &right.repeats[0];
+        assert_near(repeat.t_start, -7.0, TOLERANCE);
+        assert_near(repeat.t_end, -7.0, TOLERANCE);
+        assert_near(repeat.s + repeat.length, 100.0, TOLERANCE);
+        assert!(repeat.is_continuous());
+
+        // The barriers are written out as repeated objects and read back.
+        network.signals.extend([left, right.clone()]);
+        let read = xodr::read_str(&xodr::to_xml(&network), &LoadTransform::default()).unwrap();
+        let barrier = read
+            .signals
+            .iter()
+            .find(|signal| signal.id == right.id)
+            .unwrap();
+        assert_eq!(barrier.type_code, "barrier");
+        assert_eq!(barrier.repeats.len(), 1);
+        assert_near(barrier.repeats[0].length, repeat.length, TOLERANCE);
+        assert_near(barrier.repeats[0].height_end, 0.75, TOLERANCE);
+    }
+}
@@ -0,0 +1,165 @@
+// Batch validation of a directory of maps.
+//
+// `validate --dir <maps>` checks every .xodr file in a directory, several
+// at a time, and prints one summary line per map. With `--report <dir>` each
+// map's issues and annotations are also written there as `<map>.sarif` (or
+// `<map>.xml` with `--format junit`). The run fails if any map has an error
+// or could not be loaded, so it can gate a map pipeline.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::annotations::Annotations;
+use crate::compressed::is_xodr;
+use crate::issue_export::{write_report, Format};
+use crate::loader::load_network;
+use crate::validation::{validate, Issue, Severity, ValidationSettings};
+
+// What became of one map.
+struct Outcome {
+    map: PathBuf,
+    result: Result<Vec<Issue>, String>,
+}
+
+// The .xodr files of a directory, compressed or not, by name.
+fn maps_in(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    let mut maps: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_xodr(path))
+        .collect();
+    maps.sort();
+    Ok(maps)
+}
+
+// Loads, checks and reports one map.
+fn check_map(
+    map: &Path,
+    settings: &ValidationSettings,
+    report: Option<(&Path, Format)>,
+) -> Result<Vec<Issue>, String> {
+    let network = load_network(Some(map))?;
+    let issues = validate(&network, settings);
+    if let Some((dir, format)) = report {
+        let extension = match format {
+            Format::Sarif => "sarif",
+            Format::Junit => "xml",
+        };
+        let stem = map.file_stem().unwrap_or_default().to_string_lossy();
+        let out = dir.join(format!("{stem}.{extension}"));
+        let annotations = Annotations::load(Some(map), &network);
+        write_report(&out, Some(map), &issues, &annotations.list)?;
+    }
+    Ok(issues)
+}
+
+// Validates every map in `dir`, failing if any has errors.
+pub fn validate_dir(
+    dir: &Path,
+    settings: &ValidationSettings,
+    report: Option<(&Path, Format)>,
+) -> Result<(), String> {
+    let maps = maps_in(dir)?;
+    if maps.is_empty() {
+        return Err(format!("{}: no .xodr files", dir.display()));
+    }
+    if let Some((report_dir, _)) = report {
+        std::fs::create_dir_all(report_dir)
+            .map_err(|e| format!("{}: {e}", report_dir.display()))?;
+    }
+
+    // Workers take the next map until none are left.
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::with_capacity(maps.len()));
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(maps.len());
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                while let Some(map) = maps.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = check_map(map, settings, report);
+                    let outcome = Outcome {
+                        map: map.clone(),
+                        result,
+                    };
+                    outcomes.lock().unwrap().push(outcome);
+                }
+            });
+        }
+    });
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by(|a, b| a.map.cmp(&b.map));
+
+    let mut failed = 0;
+    for outcome in &outcomes {
+        let name = outcome.map.display();
+        match &outcome.result {
+            Ok(issues) => {
+                let errors = issues
+                    .iter()
+                    .filter(|issue| issue.severity == Severity::Error)
+                    .count();
+                println!(
+                    "{name}: {} issue(s), {errors} error(s), {} warning(s)",
+                    issues.len(),
+                    issues.len() - errors
+                );
+                if errors > 0 {
+                    failed += 1;
+                }
+            }
+            Err(message) => {
+                println!("{name}: not checked: {message}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "{failed} of {} map(s) failed validation",
+            outcomes.len()
+        ));
+    }
+    println!("{} map(s) passed", outcomes.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{path, temp_dir, text, SAMPLES};
+
+    #[test]
+    fn every_map_of_a_directory_is_checked_and_reported() {
+        let dir = temp_dir("batch");
+        let (maps, reports) = (dir.join("maps"), dir.join("reports"));
+        std::fs::create_dir(&maps).unwrap();
+        let settings = ValidationSettings::default();
+        assert_eq!(
+            validate_dir(&maps, &settings, None),
+            Err(format!("{}: no .xodr files", maps.display()))
+        );
+
+        for name in SAMPLES {
+            std::fs::copy(path(name), maps.join(name)).unwrap();
+        }
+        validate_dir(&maps, &settings, Some((&reports, Format::Sarif))).unwrap();
+        for name in SAMPLES {
+            let stem = name.trim_end_matches(".xodr");
+            assert!(reports.join(format!("{stem}.sarif")).is_file(), "{name}");
+        }
+
+        // A map with a road that cannot be read fails the run.
+        let broken = text("straight.xodr").replace("</laneSection>", "");
+        std::fs::write(maps.join("broken.xodr"), broken).unwrap();
+        assert_eq!(
+            validate_dir(&maps, &settings, None),
+            Err(format!(
+                "1 of {} map(s) failed validation",
+                SAMPLES.len() + 1
+            ))
+        );
+    }
+}
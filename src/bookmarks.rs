@@ -0,0 +1,338 @@
+// Bookmarks: named viewpoints, saved per map.
+//
+// A bookmark remembers where the camera looks and what is selected. Nine
+// slots are kept for every map, in a text file under the user's config
+// directory (`$XDG_CONFIG_HOME/road-visualizer/bookmarks/`, or the platform's
+// equivalent), so that problem areas found in one review session can be
+// revisited in the next. A new bookmark is named after the selection; the
+// names can be edited in the file.
+//
+// Keys: Ctrl+1 to Ctrl+9 save the view in a slot, 1 to 9 jump to it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::camera_tween::{fly_to, OrbitPose};
+use crate::origin::RenderOrigin;
+use crate::selection::{lateral_offset, Detail, Pick, Selection};
+use crate::{camera_orbit, CameraOrbit, MainCamera, RoadNetwork};
+
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+// A selected lane as stored in a bookmark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavedSelection {
+    pub detail: Detail,
+    pub road_id: u32,
+    pub lane_section_id: u32,
+    pub lane_id: i32,
+    pub s: f64,
+    pub position: DVec3,
+}
+
+// A saved viewpoint. The orbit center is a viewer-frame position, so it does
+// not depend on where the floating origin happens to be.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub pose: OrbitPose,
+    pub selection: Option<SavedSelection>,
+}
+
+// The bookmarks of the loaded map, by slot (1 to 9).
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Bookmarks {
+    // Where they are saved; none if there is no config directory.
+    pub file: Option<PathBuf>,
+    pub slots: BTreeMap<u8, Bookmark>,
+}
+
+impl Bookmarks {
+    // Loads the bookmarks of a map (the demo network if none). A missing or
+    // unreadable file gives no bookmarks.
+    pub fn load(map: Option<&Path>) -> Self {
+        let file = bookmark_file(map);
+        let slots = file
+            .as_deref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|text| parse(&text))
+            .unwrap_or_default();
+        Self { file, slots }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Err("no config directory".to_string());
+        };
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        std::fs::write(file, format(&self.slots)).map_err(|e| format!("{}: {e}", file.display()))
+    }
+}
+
+pub struct BookmarkPlugin;
+
+impl Plugin for BookmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bookmarks>()
+            .add_systems(Update, use_bookmarks.before(camera_orbit));
+    }
+}
+
+// The directory for the viewer's settings.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("road-visualizer"))
+}
+
+// The bookmark file of a map: named after the map file, with a hash of its
+// full path to tell apart maps of the same name.
+fn bookmark_file(map: Option<&Path>) -> Option<PathBuf> {
+    let name = match map {
+        Some(map) => {
+            let full = map.canonicalize().unwrap_or_else(|_| map.to_path_buf());
+            let mut hasher = DefaultHasher::new();
+            full.hash(&mut hasher);
+            let stem = map.file_stem().and_then(|s| s.to_str()).unwrap_or("map");
+            format!("{stem}-{:016x}.txt", hasher.finish())
+        }
+        None => "demo.txt".to_string(),
+    };
+    Some(config_dir()?.join("bookmarks").join(name))
+}
+
+// One bookmark per line, tab-separated: slot, name, center, distance,
+// azimuth, elevation, pan, and the selection (detail, road, lane section,
+// lane, s, position) or "-".
+fn format(slots: &BTreeMap<u8, Bookmark>) -> String {
+    let mut out = String::new();
+    for (slot, mark) in slots {
+        let (c, pose) = (mark.pose.center, &mark.pose);
+        let selection = match mark.selection {
+            Some(pick) => format!(
+                "{},{},{},{},{},{},{},{}",
+                detail_name(pick.detail),
+                pick.road_id,
+                pick.lane_section_id,
+                pick.lane_id,
+                pick.s,
+                pick.position.x,
+                pick.position.y,
+                pick.position.z
+            ),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{slot}\t{}\t{},{},{}\t{}\t{}\t{}\t{},{}\t{selection}",
+            mark.name.replace(['\t', '\n'], " "),
+            c.x,
+            c.y,
+            c.z,
+            pose.distance,
+            pose.azimuth,
+            pose.elevation,
+            pose.pan.x,
+            pose.pan.y
+        );
+    }
+    out
+}
+
+// Reads the lines written by `format`, skipping any that do not parse.
+fn parse(text: &str) -> BTreeMap<u8, Bookmark> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<(u8, Bookmark)> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [slot, name, center, distance, azimuth, elevation, pan, selection] = fields[..] else {
+        return None;
+    };
+    let numbers = |text: &str| -> Option<Vec<f64>> {
+        text.split(',').map(|n| n.trim().parse().ok()).collect()
+    };
+    let center = numbers(center)?;
+    let pan = numbers(pan)?;
+    let (&[x, y, z], &[pan_x, pan_y]) = (center.as_slice(), pan.as_slice()) else {
+        return None;
+    };
+    let selection = match selection.split(',').collect::<Vec<_>>()[..] {
+        [detail, road, section, lane, s, px, py, pz] => Some(SavedSelection {
+            detail: parse_detail(detail)?,
+            road_id: road.parse().ok()?,
+            lane_section_id: section.parse().ok()?,
+            lane_id: lane.parse().ok()?,
+            s: s.parse().ok()?,
+            position: DVec3::new(px.parse().ok()?, py.parse().ok()?, pz.parse().ok()?),
+        }),
+        _ => None,
+    };
+    Some((
+        slot.parse().ok().filter(|slot| (1..=9).contains(slot))?,
+        Bookmark {
+            name: name.to_string(),
+            pose: OrbitPose {
+                center: DVec3::new(x, y, z),
+                distance: distance.parse().ok()?,
+                azimuth: azimuth.parse().ok()?,
+                elevation: elevation.parse().ok()?,
+                pan: Vec2::new(pan_x as f32, pan_y as f32),
+            },
+            selection,
+        },
+    ))
+}
+
+fn detail_name(detail: Detail) -> &'static str {
+    match detail {
+        Detail::Road => "road",
+        Detail::Lane => "lane",
+        Detail::Section => "section",
+        Detail::Point => "point",
+    }
+}
+
+fn parse_detail(name: &str) -> Option<Detail> {
+    match name {
+        "road" => Some(Detail::Road),
+        "lane" => Some(Detail::Lane),
+        "section" => Some(Detail::Section),
+        "point" => Some(Detail::Point),
+        _ => None,
+    }
+}
+
+// Saves or recalls a bookmark on the number keys.
+fn use_bookmarks(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut selection: ResMut<Selection>,
+    cameras: Query<(Entity, &CameraOrbit), With<MainCamera>>,
+) {
+    let Some(slot) = SLOT_KEYS.iter().position(|key| keys.just_pressed(*key)) else {
+        return;
+    };
+    let slot = slot as u8 + 1;
+    let Ok((camera, orbit)) = cameras.get_single() else {
+        return;
+    };
+
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        let name = match selection.0 {
+            Some(pick) => pick.describe(),
+            None => format!("view {slot}"),
+        };
+        let mark = Bookmark {
+            name,
+            pose: OrbitPose::of(orbit, &origin),
+            selection: selection.0.map(|pick| SavedSelection {
+                detail: pick.detail,
+                road_id: pick.road_id,
+                lane_section_id: pick.lane_section_id,
+                lane_id: pick.lane_id,
+                s: pick.s,
+                position: pick.position,
+            }),
+        };
+        info!("bookmark {slot}: saved \"{}\"", mark.name);
+        bookmarks.slots.insert(slot, mark);
+        if let Err(message) = bookmarks.save() {
+            warn!("could not save bookmarks: {message}");
+        }
+        return;
+    }
+
+    let Some(mark) = bookmarks.slots.get(&slot) else {
+        info!("bookmark {slot} is empty; Ctrl+{slot} saves the view there");
+        return;
+    };
+    info!("bookmark {slot}: \"{}\"", mark.name);
+    fly_to(&mut commands, camera, orbit, &origin, mark.pose);
+    // The selection comes back if its lane is still there.
+    selection.0 = mark.selection.and_then(|saved| {
+        let segment = network.find_segment(saved.road_id, saved.lane_section_id, saved.lane_id)?;
+        Some(Pick {
+            detail: saved.detail,
+            segment,
+            road_id: saved.road_id,
+            lane_section_id: saved.lane_section_id,
+            lane_id: saved.lane_id,
+            s: saved.s,
+            position: saved.position,
+            t: lateral_offset(&network, saved.road_id, saved.s, saved.position),
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::select;
+    use crate::sample_maps::{assert_near, load, temp_dir};
+
+    #[test]
+    fn bookmarks_bring_back_the_view_and_the_selected_lane() {
+        let network = load("straight.xodr");
+        let pick = select(&network, 1, Some(-1), None).unwrap();
+        let mark = Bookmark {
+            name: pick.describe(),
+            pose: OrbitPose {
+                center: DVec3::new(50.0, 0.0, 1.75),
+                distance: 40.0,
+                azimuth: 0.25,
+                elevation: 0.6,
+                pan: Vec2::new(1.5, -2.0),
+            },
+            selection: Some(SavedSelection {
+                detail: pick.detail,
+                road_id: pick.road_id,
+                lane_section_id: pick.lane_section_id,
+                lane_id: pick.lane_id,
+                s: pick.s,
+                position: pick.position,
+            }),
+        };
+        let bookmarks = Bookmarks {
+            file: Some(temp_dir("bookmarks").join("straight.txt")),
+            slots: BTreeMap::from([(3, mark.clone())]),
+        };
+        bookmarks.save().unwrap();
+        let text = std::fs::read_to_string(bookmarks.file.unwrap()).unwrap();
+        // Lines that do not parse, and slots out of range, are skipped.
+        let read = parse(&format!("{text}garbage\n0{}", &text[1..]));
+        assert_eq!(read, BTreeMap::from([(3, mark)]));
+
+        let saved = read[&3].selection.unwrap();
+        let segment = network
+            .find_segment(saved.road_id, saved.lane_section_id, saved.lane_id)
+            .unwrap();
+        assert_eq!(network.segments[segment].lane_id, -1);
+        // Lane -1 lies 0 to 3.5 m right of the reference line.
+        let t = lateral_offset(&network, saved.road_id, saved.s, saved.position).unwrap();
+        assert_near(t, -1.75, 1.75);
+    }
+}
@@ -0,0 +1,108 @@
+// Reading the viewer's own binary files: little-endian values taken off the
+// front of a byte slice. Running short is an error naming what was being
+// read ("compiled network is truncated"). The compiled network and the mesh
+// cache add readers for their own records in their modules.
+
+pub struct Reader<'a> {
+    rest: &'a [u8],
+    // What is being read, for errors.
+    what: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], what: &'static str) -> Self {
+        Self { rest: bytes, what }
+    }
+
+    // The bytes not read yet.
+    pub fn rest(&self) -> &'a [u8] {
+        self.rest
+    }
+
+    fn truncated(&self) -> String {
+        format!("{} is truncated", self.what)
+    }
+
+    pub fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (head, rest) = self
+            .rest
+            .split_first_chunk::<N>()
+            .ok_or_else(|| self.truncated())?;
+        self.rest = rest;
+        Ok(*head)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.rest.len() {
+            return Err(self.truncated());
+        }
+        let (head, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        self.take::<1>().map(|[v]| v)
+    }
+
+    pub fn flag(&mut self) -> Result<bool, String> {
+        self.u8().map(|v| v != 0)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub fn i32(&mut self) -> Result<i32, String> {
+        self.take().map(i32::from_le_bytes)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, String> {
+        self.take().map(f32::from_le_bytes)
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    // A count, checked against what is left so that a damaged file cannot
+    // ask for a huge allocation.
+    pub fn len(&mut self) -> Result<usize, String> {
+        let len = self.u32()? as usize;
+        if len > self.rest.len() {
+            return Err(self.truncated());
+        }
+        Ok(len)
+    }
+
+    pub fn text(&mut self) -> Result<String, String> {
+        let len = self.len()?;
+        let text = self.bytes(len)?;
+        String::from_utf8(text.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_read_in_order_until_the_bytes_run_out() {
+        let mut bytes = vec![7, 1];
+        bytes.extend_from_slice(&(-3i32).to_le_bytes());
+        bytes.extend_from_slice(&2.5f64.to_le_bytes());
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(b"ok");
+        bytes.extend_from_slice(&9u32.to_le_bytes());
+        let mut reader = Reader::new(&bytes, "test file");
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.flag(), Ok(true));
+        assert_eq!(reader.i32(), Ok(-3));
+        assert_eq!(reader.f64(), Ok(2.5));
+        assert_eq!(reader.text().as_deref(), Ok("ok"));
+        // A count larger than what is left is refused before allocating.
+        assert_eq!(reader.len(), Err("test file is truncated".to_string()));
+        assert!(reader.rest().is_empty());
+        assert!(reader.u8().is_err());
+    }
+}
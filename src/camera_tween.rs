@@ -0,0 +1,132 @@
+// Animated camera transitions.
+//
+// Jumps to a bookmark or an issue do not teleport the camera: they put a
+// `CameraTween` on it, and the orbit fields are eased from where they were to
+// the target over `DURATION`. The orbit center is interpolated in the viewer
+// frame, so the floating origin may recenter halfway through. Grabbing the
+// camera with the mouse ends the animation where it is.
+
+use std::f32::consts::PI;
+
+use bevy::input::mouse::MouseWheel;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::origin::RenderOrigin;
+use crate::{camera_input, camera_orbit, CameraOrbit, MainCamera};
+
+// Length of a transition, in seconds.
+const DURATION: f32 = 0.5;
+
+// Where the orbit camera looks from, with the center in the viewer frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrbitPose {
+    pub center: DVec3,
+    pub distance: f32,
+    pub azimuth: f32,
+    pub elevation: f32,
+    pub pan: Vec2,
+}
+
+impl OrbitPose {
+    pub fn of(orbit: &CameraOrbit, origin: &RenderOrigin) -> Self {
+        Self {
+            center: origin.0 + orbit.center.as_dvec3(),
+            distance: orbit.distance,
+            azimuth: orbit.azimuth,
+            elevation: orbit.elevation,
+            pan: orbit.pan,
+        }
+    }
+
+    // The pose a fraction `t` of the way to `to`. The azimuth turns the
+    // short way round.
+    fn lerp(&self, to: &OrbitPose, t: f32) -> Self {
+        let turn = (to.azimuth - self.azimuth + PI).rem_euclid(2.0 * PI) - PI;
+        Self {
+            center: self.center.lerp(to.center, f64::from(t)),
+            distance: self.distance + (to.distance - self.distance) * t,
+            azimuth: self.azimuth + turn * t,
+            elevation: self.elevation + (to.elevation - self.elevation) * t,
+            pan: self.pan.lerp(to.pan, t),
+        }
+    }
+}
+
+// An ongoing transition of the camera it is on.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CameraTween {
+    from: OrbitPose,
+    to: OrbitPose,
+    elapsed: f32,
+}
+
+impl CameraTween {
+    pub fn new(from: OrbitPose, to: OrbitPose) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0.0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn target(&self) -> OrbitPose {
+        self.to
+    }
+}
+
+pub struct CameraTweenPlugin;
+
+impl Plugin for CameraTweenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, animate.after(camera_input).before(camera_orbit));
+    }
+}
+
+// Starts a transition of the main camera to `to`.
+pub fn fly_to(
+    commands: &mut Commands,
+    camera: Entity,
+    orbit: &CameraOrbit,
+    origin: &RenderOrigin,
+    to: OrbitPose,
+) {
+    let from = OrbitPose::of(orbit, origin);
+    commands.entity(camera).insert(CameraTween::new(from, to));
+}
+
+// Ease in and out, so the camera neither starts nor stops abruptly.
+fn ease(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn animate(
+    mut commands: Commands,
+    time: Res<Time>,
+    origin: Res<RenderOrigin>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut cameras: Query<(Entity, &mut CameraOrbit, &mut CameraTween), With<MainCamera>>,
+) {
+    let grabbed = buttons.any_just_pressed([MouseButton::Left, MouseButton::Middle])
+        || wheel.read().count() > 0;
+    for (camera, mut orbit, mut tween) in &mut cameras {
+        if grabbed {
+            commands.entity(camera).remove::<CameraTween>();
+            continue;
+        }
+        tween.elapsed += time.delta_seconds();
+        let t = tween.elapsed / DURATION;
+        let pose = tween.from.lerp(&tween.to, ease(t));
+        orbit.center = origin.to_render(pose.center);
+        orbit.distance = pose.distance;
+        orbit.azimuth = pose.azimuth;
+        orbit.elevation = pose.elevation;
+        orbit.pan = pose.pan;
+        if t >= 1.0 {
+            commands.entity(camera).remove::<CameraTween>();
+        }
+    }
+}
@@ -0,0 +1,155 @@
+// RGBA pixel buffers painted in code.
+//
+// Used for textures that would otherwise need asset files: signal icons,
+// the analysis charts and road decals. Coordinates are in pixels with rows
+// running top to bottom, as in the resulting image.
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+// An RGBA pixel buffer, transparent to begin with.
+pub struct Canvas {
+    width: i32,
+    height: i32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if (0..self.width).contains(&x) && (0..self.height).contains(&y) {
+            let i = ((y * self.width + x) * 4) as usize;
+            self.pixels[i..i + 4].copy_from_slice(&color);
+        }
+    }
+
+    // Paints every pixel whose center passes `inside`.
+    pub fn fill(&mut self, inside: impl Fn(Vec2) -> bool, color: [u8; 4]) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if inside(Vec2::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.set(x, y, color);
+                }
+            }
+        }
+    }
+
+    // Writes a number centered on `center` in a blocky 3x5 digit font.
+    pub fn number(&mut self, value: u32, center: Vec2, color: [u8; 4]) {
+        let text = value.to_string();
+        // Scale the font so that up to three digits fit inside the ring.
+        let scale = if text.len() > 2 { 3 } else { 4 };
+        self.text(&text, center, scale, color);
+    }
+
+    // Writes text centered on `center` in the same font, each of its pixels
+    // `scale` pixels across. Letters are written as capitals; characters
+    // the font lacks leave a gap.
+    pub fn text(&mut self, text: &str, center: Vec2, scale: i32, color: [u8; 4]) {
+        let count = text.chars().count() as i32;
+        let width = count * 4 * scale - scale;
+        let left = center.x as i32 - width / 2;
+        let top = center.y as i32 - 5 * scale / 2;
+        for (n, c) in text.chars().enumerate() {
+            let Some(rows) = glyph(c) else {
+                continue;
+            };
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            self.set(
+                                left + (n as i32 * 4 + column) * scale + dx,
+                                top + row as i32 * scale + dy,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Draws a one pixel wide line between two points.
+    pub fn line(&mut self, a: Vec2, b: Vec2, color: [u8; 4]) {
+        let steps = (b - a).abs().max_element().ceil().max(1.0) as i32;
+        for i in 0..=steps {
+            let p = a.lerp(b, i as f32 / steps as f32);
+            self.set(p.x.floor() as i32, p.y.floor() as i32, color);
+        }
+    }
+
+    pub fn into_image(self) -> Image {
+        Image::new(
+            Extent3d {
+                width: self.width as u32,
+                height: self.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            self.pixels,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+    }
+}
+
+// The rows of a character in the 3x5 font, top first.
+fn glyph(c: char) -> Option<[u8; 5]> {
+    const DIGITS: [[u8; 5]; 10] = [
+        [0b111, 0b101, 0b101, 0b101, 0b111],
+        [0b010, 0b110, 0b010, 0b010, 0b111],
+        [0b111, 0b001, 0b111, 0b100, 0b111],
+        [0b111, 0b001, 0b111, 0b001, 0b111],
+        [0b101, 0b101, 0b111, 0b001, 0b001],
+        [0b111, 0b100, 0b111, 0b001, 0b111],
+        [0b111, 0b100, 0b111, 0b101, 0b111],
+        [0b111, 0b001, 0b001, 0b001, 0b001],
+        [0b111, 0b101, 0b111, 0b101, 0b111],
+        [0b111, 0b101, 0b111, 0b001, 0b111],
+    ];
+    const LETTERS: [[u8; 5]; 26] = [
+        [0b010, 0b101, 0b111, 0b101, 0b101],
+        [0b110, 0b101, 0b110, 0b101, 0b110],
+        [0b011, 0b100, 0b100, 0b100, 0b011],
+        [0b110, 0b101, 0b101, 0b101, 0b110],
+        [0b111, 0b100, 0b110, 0b100, 0b111],
+        [0b111, 0b100, 0b110, 0b100, 0b100],
+        [0b011, 0b100, 0b101, 0b101, 0b011],
+        [0b101, 0b101, 0b111, 0b101, 0b101],
+        [0b111, 0b010, 0b010, 0b010, 0b111],
+        [0b001, 0b001, 0b001, 0b101, 0b010],
+        [0b101, 0b101, 0b110, 0b101, 0b101],
+        [0b100, 0b100, 0b100, 0b100, 0b111],
+        [0b101, 0b111, 0b111, 0b101, 0b101],
+        [0b110, 0b101, 0b101, 0b101, 0b101],
+        [0b010, 0b101, 0b101, 0b101, 0b010],
+        [0b110, 0b101, 0b110, 0b100, 0b100],
+        [0b010, 0b101, 0b101, 0b110, 0b011],
+        [0b110, 0b101, 0b110, 0b101, 0b101],
+        [0b011, 0b100, 0b010, 0b001, 0b110],
+        [0b111, 0b010, 0b010, 0b010, 0b010],
+        [0b101, 0b101, 0b101, 0b101, 0b111],
+        [0b101, 0b101, 0b101, 0b101, 0b010],
+        [0b101, 0b101, 0b111, 0b111, 0b101],
+        [0b101, 0b101, 0b010, 0b101, 0b101],
+        [0b101, 0b101, 0b010, 0b010, 0b010],
+        [0b111, 0b001, 0b010, 0b100, 0b111],
+    ];
+    match c.to_ascii_uppercase() {
+        c @ '0'..='9' => Some(DIGITS[c as usize - '0' as usize]),
+        c @ 'A'..='Z' => Some(LETTERS[c as usize - 'A' as usize]),
+        _ => None,
+    }
+}
@@ -0,0 +1,281 @@
+// Capture of review clips.
+//
+// A capture flies the main camera along a path, one step per rendered frame,
+// and saves every frame as a PNG. Steps are taken at a fixed `FPS` however
+// long a frame takes to render, so clips play back at the right speed. Two
+// paths are offered: a turntable, one full turn around the view center, and
+// a drive-along, a chase view following a route written by `export-route`
+// (its CSV form). When the output is an `.mp4` file, the frames go to a
+// directory next to it and `ffmpeg`, if installed, encodes them afterwards.
+//
+// Captures are started from the command line (`view --capture`), after which
+// the viewer exits, or interactively.
+//
+// Keys: F8 records a turntable into a new `capture-<time>` directory.
+
+use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use bevy::app::AppExit;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_tween::OrbitPose;
+use crate::origin::RenderOrigin;
+use crate::{camera_orbit, CameraOrbit, MainCamera};
+
+// Frames per second of the clip.
+const FPS: u32 = 30;
+
+// Length of a turntable, in seconds.
+const TURNTABLE_SECONDS: u32 = 12;
+
+// Drive-along speed (50 km/h), and the chase camera's distance, elevation and
+// how far ahead on the route it looks, in meters and radians.
+const DRIVE_SPEED: f64 = 13.9;
+const CHASE_DISTANCE: f32 = 15.0;
+const CHASE_ELEVATION: f32 = -0.25;
+const LOOK_AHEAD: f64 = 10.0;
+
+// Frames rendered before the first one is saved, so that streamed tiles have
+// appeared, and after the last one, so that all have been written.
+const WARMUP_FRAMES: u32 = 10;
+const FLUSH_FRAMES: u32 = 30;
+
+// The camera path of a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CapturePath {
+    Turntable,
+    // Route centerline points, in the viewer frame.
+    Route(Vec<DVec3>),
+}
+
+// A capture asked for on the command line: the path, and the directory or
+// `.mp4` file to write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureSettings {
+    pub path: CapturePath,
+    pub out: PathBuf,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CaptureRequest(pub Option<CaptureSettings>);
+
+// A capture in progress.
+struct Job {
+    poses: Vec<OrbitPose>,
+    frames: PathBuf,
+    video: Option<PathBuf>,
+    // Frames rendered so far, counting the warm-up.
+    frame: u32,
+    exit_after: bool,
+}
+
+#[derive(Resource, Default)]
+struct Capture(Option<Job>);
+
+pub struct CapturePlugin;
+
+impl Plugin for CapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CaptureRequest>()
+            .init_resource::<Capture>()
+            .add_systems(Update, (start_capture, record).chain().before(camera_orbit));
+    }
+}
+
+// One full turn around the view center.
+fn turntable(from: OrbitPose) -> Vec<OrbitPose> {
+    let frames = FPS * TURNTABLE_SECONDS;
+    (0..frames)
+        .map(|frame| OrbitPose {
+            azimuth: from.azimuth + 2.0 * PI * frame as f32 / frames as f32,
+            ..from
+        })
+        .collect()
+}
+
+// The point `s` meters along a polyline, clamped to its ends.
+fn along(points: &[DVec3], lengths: &[f64], s: f64) -> DVec3 {
+    let i = lengths
+        .partition_point(|&l| l < s)
+        .clamp(1, points.len() - 1);
+    let piece = (lengths[i] - lengths[i - 1]).max(f64::EPSILON);
+    let t = ((s - lengths[i - 1]) / piece).clamp(0.0, 1.0);
+    points[i - 1].lerp(points[i], t)
+}
+
+// A chase view driving along a route at `DRIVE_SPEED`.
+fn drive_along(points: &[DVec3]) -> Vec<OrbitPose> {
+    if points.len() < 2 {
+        return Vec::new();
+    }
+    let mut lengths = vec![0.0];
+    for pair in points.windows(2) {
+        lengths.push(lengths[lengths.len() - 1] + pair[0].distance(pair[1]));
+    }
+    let total = lengths[lengths.len() - 1];
+    let step = DRIVE_SPEED / f64::from(FPS);
+    let frames = (total / step).ceil() as usize + 1;
+    (0..frames)
+        .map(|frame| {
+            let s = (frame as f64 * step).min(total);
+            let here = along(points, &lengths, s);
+            // At the very end, keep looking along the last stretch.
+            let direction = if total - s > 1.0 {
+                along(points, &lengths, (s + LOOK_AHEAD).min(total)) - here
+            } else {
+                here - along(points, &lengths, (s - LOOK_AHEAD).max(0.0))
+            };
+            OrbitPose {
+                center: here,
+                distance: CHASE_DISTANCE,
+                // The orbit camera looks along -z turned by the azimuth.
+                azimuth: (-direction.x).atan2(-direction.z) as f32,
+                elevation: CHASE_ELEVATION,
+                pan: Vec2::ZERO,
+            }
+        })
+        .collect()
+}
+
+// Where the frames of an output go: the directory itself, or one next to a
+// video file.
+fn frame_directory(out: &Path) -> (PathBuf, Option<PathBuf>) {
+    let is_video = out
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("mp4"));
+    if !is_video {
+        return (out.to_path_buf(), None);
+    }
+    let stem = out
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("capture");
+    (
+        out.with_file_name(format!("{stem}-frames")),
+        Some(out.to_path_buf()),
+    )
+}
+
+// Encodes the frames of a capture with ffmpeg.
+fn encode(frames: &Path, video: &Path) -> Result<(), String> {
+    let status = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-framerate"])
+        .arg(FPS.to_string())
+        .arg("-i")
+        .arg(frames.join("frame_%05d.png"))
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(video)
+        .status()
+        .map_err(|e| format!("could not run ffmpeg: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("ffmpeg failed ({status})"))
+    }
+}
+
+fn start_capture(
+    keys: Res<ButtonInput<KeyCode>>,
+    origin: Res<RenderOrigin>,
+    mut request: ResMut<CaptureRequest>,
+    mut capture: ResMut<Capture>,
+    cameras: Query<&CameraOrbit, With<MainCamera>>,
+) {
+    if capture.0.is_some() {
+        return;
+    }
+    let Ok(orbit) = cameras.get_single() else {
+        return;
+    };
+    let (settings, exit_after) = match request.0.take() {
+        Some(settings) => (settings, true),
+        None if keys.just_pressed(KeyCode::F8) => {
+            let time = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let settings = CaptureSettings {
+                path: CapturePath::Turntable,
+                out: PathBuf::from(format!("capture-{time}")),
+            };
+            (settings, false)
+        }
+        None => return,
+    };
+    let poses = match &settings.path {
+        CapturePath::Turntable => turntable(OrbitPose::of(orbit, &origin)),
+        CapturePath::Route(points) => drive_along(points),
+    };
+    let (frames, video) = frame_directory(&settings.out);
+    if let Err(e) = std::fs::create_dir_all(&frames) {
+        warn!("could not capture: {}: {e}", frames.display());
+        return;
+    }
+    info!("capturing {} frames into {}", poses.len(), frames.display());
+    capture.0 = Some(Job {
+        poses,
+        frames,
+        video,
+        frame: 0,
+        exit_after,
+    });
+}
+
+// Moves the camera to the next pose and saves the frame.
+fn record(
+    origin: Res<RenderOrigin>,
+    mut capture: ResMut<Capture>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    mut exit: EventWriter<AppExit>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    mut cameras: Query<&mut CameraOrbit, With<MainCamera>>,
+) {
+    let Some(job) = capture.0.as_mut() else {
+        return;
+    };
+    let (Ok(window), Ok(mut orbit)) = (windows.get_single(), cameras.get_single_mut()) else {
+        return;
+    };
+    let shot = job.frame.checked_sub(WARMUP_FRAMES);
+    job.frame += 1;
+    let pose = shot.map_or(0, |shot| shot as usize);
+    if let Some(pose) = job.poses.get(pose) {
+        orbit.center = origin.to_render(pose.center);
+        orbit.distance = pose.distance;
+        orbit.azimuth = pose.azimuth;
+        orbit.elevation = pose.elevation;
+        orbit.pan = pose.pan;
+    }
+    let Some(shot) = shot else {
+        return;
+    };
+    if (shot as usize) < job.poses.len() {
+        let path = job.frames.join(format!("frame_{shot:05}.png"));
+        if let Err(e) = screenshots.save_screenshot_to_disk(window, path) {
+            warn!("could not save frame {shot}: {e:?}");
+        }
+        return;
+    }
+    if (shot as usize) < job.poses.len() + FLUSH_FRAMES as usize {
+        return;
+    }
+
+    let Some(job) = capture.0.take() else {
+        return;
+    };
+    match &job.video {
+        Some(video) => match encode(&job.frames, video) {
+            Ok(()) => info!("capture written to {}", video.display()),
+            Err(message) => warn!("{message}; the frames are in {}", job.frames.display()),
+        },
+        None => info!("capture written to {}", job.frames.display()),
+    }
+    if job.exit_after {
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,103 @@
+// Export preset for CARLA's map ingestion pipeline.
+//
+// CARLA imports a `.gltf`/`.fbx` next to the `.xodr` and assigns materials and
+// collision by mesh name prefix: `Road_Road` for drivable surfaces,
+// `Road_Marking` for painted lines, `Road_Sidewalk` for sidewalks and so on.
+// We follow that convention with one mesh per road and category, e.g.
+// `Road_Road_12`, `Road_Sidewalk_12` and `Road_Marking_12`, sorting lanes
+// into categories by their OpenDRIVE type.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use bevy::math::DVec3;
+
+use crate::gltf::GltfDocument;
+use crate::simplify::simplify;
+use crate::tessellation::{self, TriangleMesh, MARKING_LIFT, MARKING_WIDTH};
+use crate::RoadNetwork;
+
+// Flat colors for the materials CARLA replaces on import anyway.
+const ROAD_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+const MARKING_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const SIDEWALK_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.0];
+const CURB_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const GRASS_COLOR: [f32; 4] = [0.3, 0.5, 0.2, 1.0];
+
+// The mesh a lane's surface goes into, by CARLA's name prefix and color.
+// Lanes of type `none` have no surface to speak of and are left out.
+fn category(lane_type: &str) -> Option<(&'static str, [f32; 4])> {
+    match lane_type {
+        "none" => None,
+        "sidewalk" | "walking" => Some(("Road_Sidewalk", SIDEWALK_COLOR)),
+        "curb" => Some(("Road_Curb", CURB_COLOR)),
+        "border" | "median" => Some(("Road_Grass", GRASS_COLOR)),
+        _ => Some(("Road_Road", ROAD_COLOR)),
+    }
+}
+
+// Writes the network as a CARLA-ready glTF file. With `max_error`, meshes
+// are simplified to within that many meters of the tessellated surface.
+pub fn write_map(network: &RoadNetwork, path: &Path, max_error: Option<f64>) -> io::Result<()> {
+    build_document(network, max_error).write(path)
+}
+
+// Builds the glTF document, merging the lanes of a road into one mesh per
+// category so that CARLA generates one collision body per road and surface.
+// Meshes are written in map coordinates; CARLA expects them to line up with
+// the .xodr.
+pub fn build_document(network: &RoadNetwork, max_error: Option<f64>) -> GltfDocument {
+    let mut meshes: BTreeMap<(u32, &str), ([f32; 4], TriangleMesh)> = BTreeMap::new();
+    for segment in &network.segments {
+        if let Some((prefix, color)) = category(&segment.lane_type) {
+            let (_, surface) = meshes
+                .entry((segment.road_id, prefix))
+                .or_insert_with(|| (color, TriangleMesh::default()));
+            surface.append(&tessellation::road_surface(segment, DVec3::ZERO));
+        }
+        let (_, markings) = meshes
+            .entry((segment.road_id, "Road_Marking"))
+            .or_insert_with(|| (MARKING_COLOR, TriangleMesh::default()));
+        markings.append(&tessellation::boundary_markings(
+            segment,
+            MARKING_WIDTH,
+            MARKING_LIFT,
+            DVec3::ZERO,
+        ));
+    }
+
+    if let Some(max_error) = max_error {
+        for (_, mesh) in meshes.values_mut() {
+            *mesh = simplify(mesh, max_error);
+        }
+    }
+
+    let mut document = GltfDocument::default();
+    for ((road_id, prefix), (color, mesh)) in &meshes {
+        document.add_mesh(&format!("{prefix}_{road_id}"), mesh, prefix, *color);
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn sidewalks_get_meshes_of_their_own() {
+        let network = load("straight.xodr");
+        let json = build_document(&network, None).to_json();
+        for name in ["Road_Road_1", "Road_Sidewalk_1", "Road_Marking_1"] {
+            assert!(json.contains(&format!("\"name\": \"{name}\"")), "no {name}");
+        }
+        assert!(!json.contains("Road_Curb"));
+
+        assert_eq!(category("driving").unwrap().0, "Road_Road");
+        assert_eq!(category("walking").unwrap().0, "Road_Sidewalk");
+        assert_eq!(category("curb").unwrap().0, "Road_Curb");
+        assert_eq!(category("border").unwrap().0, "Road_Grass");
+        assert_eq!(category("none"), None);
+    }
+}
@@ -0,0 +1,351 @@
+// Line charts of values along a road.
+//
+// Analysis views plot one or more quantities against s in stacked panels,
+// each with its own vertical scale. A chart is a UI node along the bottom of
+// the window holding a painted image and a caption with the value ranges.
+// The module owning a chart fills in its `ChartData`; hovering over the
+// chart reports the station under the cursor in `ChartHover`, which the
+// owner uses to mark that station on the road, and the caption then lists
+// the values there.
+
+use std::fmt::Write as _;
+
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use crate::canvas::Canvas;
+
+// Size of the plotted area, in pixels.
+pub const CHART_WIDTH: i32 = 640;
+pub const CHART_HEIGHT: i32 = 220;
+
+// Distance of the bottom row of charts from the window edge, and of the row
+// above it, in pixels. The row height allows for a few caption lines.
+pub const BOTTOM_ROW: f32 = 10.0;
+pub const SECOND_ROW: f32 = BOTTOM_ROW + CHART_HEIGHT as f32 + 90.0;
+
+// Vertical gap between panels, in pixels.
+const PANEL_GAP: i32 = 6;
+
+const BACKGROUND: [u8; 4] = [20, 20, 24, 200];
+const AXIS: [u8; 4] = [110, 110, 110, 255];
+
+// A quantity plotted against s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub label: String,
+    pub values: Vec<f64>,
+    pub color: [u8; 4],
+}
+
+// Series sharing a vertical scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panel {
+    pub unit: &'static str,
+    pub series: Vec<Series>,
+}
+
+impl Panel {
+    // The value range of all series, widened if it is empty.
+    fn range(&self) -> (f64, f64) {
+        let (min, max) = self
+            .series
+            .iter()
+            .flat_map(|series| series.values.iter().copied())
+            .filter(|v| v.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                (min.min(v), max.max(v))
+            });
+        if min > max {
+            (-1.0, 1.0)
+        } else if max - min < 1e-6 {
+            (min - 1.0, max + 1.0)
+        } else {
+            (min, max)
+        }
+    }
+}
+
+// What a chart shows; `None` hides it.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct ChartData(pub Option<ChartContent>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartContent {
+    pub title: String,
+    // The station of every sample, increasing.
+    pub stations: Vec<f64>,
+    pub panels: Vec<Panel>,
+}
+
+impl ChartContent {
+    // The sample nearest to station `s`.
+    pub fn sample_at(&self, s: f64) -> Option<usize> {
+        let i = self.stations.partition_point(|&station| station < s);
+        match (i.checked_sub(1), self.stations.get(i)) {
+            (Some(before), Some(after)) if s - self.stations[before] < after - s => Some(before),
+            (_, Some(_)) => Some(i),
+            (Some(before), None) => Some(before),
+            (None, None) => None,
+        }
+    }
+}
+
+// The station under the cursor while it is over the chart.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChartHover(pub Option<f64>);
+
+// The plotted image and the caption of a chart, children of its node.
+#[derive(Component)]
+struct ChartImage;
+
+#[derive(Component)]
+struct ChartCaption;
+
+// The vertical line following the cursor, a child of the image.
+#[derive(Component)]
+struct ChartCursor;
+
+pub struct ChartPlugin;
+
+impl Plugin for ChartPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (track_hover, redraw_charts, update_captions).chain(),
+        );
+    }
+}
+
+// Spawns an empty, hidden chart `left` pixels from the left edge of the
+// window and `bottom` pixels from the bottom, tagged with `tag` so that its
+// owner can find it.
+pub fn spawn_chart(commands: &mut Commands, left: f32, bottom: f32, tag: impl Bundle) -> Entity {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(left),
+                    bottom: Val::Px(bottom),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ChartData::default(),
+            ChartHover::default(),
+            tag,
+        ))
+        .with_children(|chart| {
+            chart.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 13.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                ChartCaption,
+            ));
+            chart
+                .spawn((
+                    ImageBundle {
+                        style: Style {
+                            width: Val::Px(CHART_WIDTH as f32),
+                            height: Val::Px(CHART_HEIGHT as f32),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    // Interaction also keeps clicks on the chart from
+                    // picking the road behind it.
+                    Interaction::default(),
+                    RelativeCursorPosition::default(),
+                    ChartImage,
+                ))
+                .with_children(|image| {
+                    image.spawn((
+                        NodeBundle {
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                width: Val::Px(1.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            visibility: Visibility::Hidden,
+                            ..default()
+                        },
+                        ChartCursor,
+                    ));
+                });
+        })
+        .id()
+}
+
+// Converts the cursor position over a chart image into a station.
+fn track_hover(
+    images: Query<(&RelativeCursorPosition, &Parent, &Children), With<ChartImage>>,
+    mut charts: Query<(&ChartData, &mut ChartHover)>,
+    mut cursors: Query<(&mut Style, &mut Visibility), With<ChartCursor>>,
+) {
+    for (position, parent, children) in &images {
+        let Ok((data, mut hover)) = charts.get_mut(parent.get()) else {
+            continue;
+        };
+        let fraction = position
+            .normalized
+            .filter(|_| position.mouse_over())
+            .map(|p| p.x.clamp(0.0, 1.0));
+        let station = match (fraction, &data.0) {
+            (Some(f), Some(content)) => match (content.stations.first(), content.stations.last()) {
+                (Some(&start), Some(&end)) => Some(start + (end - start) * f as f64),
+                _ => None,
+            },
+            _ => None,
+        };
+        if hover.0 != station {
+            hover.0 = station;
+        }
+        for &child in children {
+            if let Ok((mut style, mut visibility)) = cursors.get_mut(child) {
+                match fraction.filter(|_| station.is_some()) {
+                    Some(f) => {
+                        style.left = Val::Percent(f * 100.0);
+                        *visibility = Visibility::Inherited;
+                    }
+                    None => *visibility = Visibility::Hidden,
+                }
+            }
+        }
+    }
+}
+
+// Repaints charts whose data changed.
+fn redraw_charts(
+    mut charts: Query<(&ChartData, &Children, &mut Visibility), Changed<ChartData>>,
+    mut images: Query<&mut UiImage, With<ChartImage>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    for (data, children, mut visibility) in &mut charts {
+        let Some(content) = &data.0 else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Visible;
+        let texture = assets.add(paint(&content.stations, &content.panels));
+        for &child in children {
+            if let Ok(mut image) = images.get_mut(child) {
+                // The previous texture is freed with its last handle.
+                image.texture = texture.clone();
+            }
+        }
+    }
+}
+
+// Writes the value ranges, or the values under the cursor, above the chart.
+#[allow(clippy::type_complexity)]
+fn update_captions(
+    charts: Query<
+        (&ChartData, &ChartHover, &Children),
+        Or<(Changed<ChartData>, Changed<ChartHover>)>,
+    >,
+    mut captions: Query<&mut Text, With<ChartCaption>>,
+) {
+    for (data, hover, children) in &charts {
+        let Some(content) = &data.0 else {
+            continue;
+        };
+        let caption = caption(content, hover.0);
+        for &child in children {
+            if let Ok(mut text) = captions.get_mut(child) {
+                if let Some(section) = text.sections.first_mut() {
+                    section.value.clone_from(&caption);
+                }
+            }
+        }
+    }
+}
+
+fn caption(content: &ChartContent, hover: Option<f64>) -> String {
+    let mut out = content.title.clone();
+    let sample = hover.and_then(|s| content.sample_at(s));
+    if let Some(i) = sample {
+        let _ = write!(out, "  at s = {:.1} m", content.stations[i]);
+    }
+    for panel in &content.panels {
+        for series in &panel.series {
+            let _ = write!(out, "\n{}: ", series.label);
+            match sample.and_then(|i| series.values.get(i)) {
+                Some(value) => {
+                    let _ = write!(out, "{value:.2} {}", panel.unit);
+                }
+                None => {
+                    let (min, max) = Panel {
+                        unit: panel.unit,
+                        series: vec![series.clone()],
+                    }
+                    .range();
+                    let _ = write!(out, "{min:.2} .. {max:.2} {}", panel.unit);
+                }
+            }
+        }
+    }
+    out
+}
+
+// Paints the panels on top of each other, each series as a polyline.
+pub fn paint(stations: &[f64], panels: &[Panel]) -> Image {
+    let mut canvas = Canvas::new(CHART_WIDTH, CHART_HEIGHT);
+    canvas.fill(|_| true, BACKGROUND);
+    let count = panels.len().max(1) as i32;
+    let height = (CHART_HEIGHT - PANEL_GAP * (count - 1)) / count;
+    let (start, end) = match (stations.first(), stations.last()) {
+        (Some(&start), Some(&end)) if end > start => (start, end),
+        _ => return canvas.into_image(),
+    };
+    let x = |s: f64| ((s - start) / (end - start)) as f32 * (CHART_WIDTH - 1) as f32;
+
+    for (index, panel) in panels.iter().enumerate() {
+        let top = index as i32 * (height + PANEL_GAP);
+        let (min, max) = panel.range();
+        // A little headroom keeps lines off the panel border.
+        let pad = (max - min) * 0.05;
+        let (min, max) = (min - pad, max + pad);
+        let y = |v: f64| top as f32 + ((max - v) / (max - min)) as f32 * (height - 1) as f32;
+
+        let frame = [
+            Vec2::new(0.0, top as f32),
+            Vec2::new((CHART_WIDTH - 1) as f32, top as f32),
+            Vec2::new((CHART_WIDTH - 1) as f32, (top + height - 1) as f32),
+            Vec2::new(0.0, (top + height - 1) as f32),
+        ];
+        for k in 0..4 {
+            canvas.line(frame[k], frame[(k + 1) % 4], AXIS);
+        }
+        if min < 0.0 && max > 0.0 {
+            let zero = y(0.0);
+            canvas.line(
+                Vec2::new(0.0, zero),
+                Vec2::new((CHART_WIDTH - 1) as f32, zero),
+                AXIS,
+            );
+        }
+        for series in &panel.series {
+            let points: Vec<Vec2> = stations
+                .iter()
+                .zip(&series.values)
+                .filter(|(_, v)| v.is_finite())
+                .map(|(s, v)| Vec2::new(x(*s), y(*v)))
+                .collect();
+            for pair in points.windows(2) {
+                canvas.line(pair[0], pair[1], series.color);
+            }
+        }
+    }
+    canvas.into_image()
+}
@@ -0,0 +1,211 @@
+// Clearance between road objects and the driving lanes.
+//
+// The driving envelope of a road at a station spans its lanes laterally and
+// reaches up to the required vertical clearance. Objects are compared
+// against the envelope at their own station, in road coordinates: an object
+// whose footprint overlaps the lanes intrudes unless it is high enough to
+// pass over them (a bridge or a gantry), in which case its underside must
+// clear the vertical limit. Objects beside the lanes must keep the lateral
+// clearance. Flat objects such as crosswalks and markings have no height and
+// are skipped, as are signals, whose size is that of the sign face only.
+
+use std::collections::BTreeMap;
+
+use bevy::math::DVec3;
+
+use crate::cross_section::cut_road;
+use crate::signals::{Signal, SignalKind};
+use crate::validation::{Issue, Severity};
+use crate::{RoadNetwork, RoadSegment};
+
+// Required clearances, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearanceSettings {
+    // Between the side of an object and the nearest lane edge.
+    pub lateral: f64,
+    // Between the lanes and the underside of an object above them.
+    pub vertical: f64,
+}
+
+impl Default for ClearanceSettings {
+    fn default() -> Self {
+        Self {
+            lateral: 0.5,
+            vertical: 4.5,
+        }
+    }
+}
+
+// Checks every object with a height against the lanes of its road.
+pub fn check(network: &RoadNetwork, settings: &ClearanceSettings) -> Vec<Issue> {
+    let mut roads: BTreeMap<u32, Vec<&RoadSegment>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads.entry(segment.road_id).or_default().push(segment);
+    }
+
+    let mut issues = Vec::new();
+    for object in &network.signals {
+        if object.kind != SignalKind::Object || object.height <= 0.0 {
+            continue;
+        }
+        let Some(cut) = roads
+            .get(&object.road_id)
+            .and_then(|road| cut_road(road, object.s))
+        else {
+            continue;
+        };
+        // The lateral extent of the lanes and of the object.
+        let Some((low, high)) = cut
+            .lanes
+            .iter()
+            .flat_map(|lane| [lane.inner_t, lane.outer_t])
+            .fold(None, |range: Option<(f64, f64)>, t| {
+                Some(range.map_or((t, t), |(low, high)| (low.min(t), high.max(t))))
+            })
+        else {
+            continue;
+        };
+        let half = object.half_width();
+        let (left, right) = (object.t + half, object.t - half);
+        let nearest_lane = cut
+            .lanes
+            .iter()
+            .min_by(|a, b| {
+                let distance = |lane: &crate::cross_section::LaneCut| {
+                    let middle = (lane.inner_t + lane.outer_t) / 2.0;
+                    (middle - object.t).abs()
+                };
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|lane| lane.lane_id);
+        let issue = |severity: Severity, message: String| Issue {
+            check: "clearance",
+            severity,
+            road_id: object.road_id,
+            lane_id: nearest_lane,
+            s: object.s,
+            position: object.position + DVec3::Y * object.z_offset,
+            message,
+        };
+
+        let overlap = left.min(high) - right.max(low);
+        if overlap > 0.0 {
+            if object.z_offset >= settings.vertical {
+                continue;
+            }
+            if object.z_offset > 0.0 {
+                issues.push(issue(
+                    Severity::Error,
+                    format!(
+                        "{} passes {:.2} m above the lanes, below the {:.2} m vertical clearance",
+                        describe(object),
+                        object.z_offset,
+                        settings.vertical
+                    ),
+                ));
+            } else {
+                issues.push(issue(
+                    Severity::Error,
+                    format!(
+                        "{} intrudes {overlap:.2} m into the driving lanes",
+                        describe(object)
+                    ),
+                ));
+            }
+            continue;
+        }
+
+        // Beside the lanes; overhead objects are out of the way.
+        if object.z_offset >= settings.vertical {
+            continue;
+        }
+        let gap = -overlap;
+        if gap < settings.lateral {
+            issues.push(issue(
+                Severity::Warning,
+                format!(
+                    "{} is {gap:.2} m from the lanes, within the {:.2} m lateral clearance",
+                    describe(object),
+                    settings.lateral
+                ),
+            ));
+        }
+    }
+    issues
+}
+
+// Names an object for a message.
+fn describe(object: &Signal) -> String {
+    let what = if object.type_code.is_empty() {
+        "object"
+    } else {
+        object.type_code.as_str()
+    };
+    if object.name.is_empty() {
+        format!("{what} {}", object.id)
+    } else {
+        format!("{what} {} ({})", object.id, object.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn objects_are_measured_against_the_lane_envelope() {
+        let mut network = load("signals.xodr");
+        // Signs are only their faces.
+        let settings = ClearanceSettings::default();
+        assert!(check(&network, &settings).is_empty());
+
+        // A 0.6 m wide post at 50 m, against lane -1 from t = -3.5 to 0.
+        let sign = network.signals[0].clone();
+        let post = |t: f64, z_offset: f64| Signal {
+            id: "post".to_string(),
+            name: String::new(),
+            s: 50.0,
+            t,
+            z_offset,
+            kind: SignalKind::Object,
+            type_code: String::new(),
+            height: 1.0,
+            width: 0.6,
+            radius: 0.0,
+            ..sign.clone()
+        };
+        let mut found = |t: f64, z_offset: f64| {
+            network.signals = vec![post(t, z_offset)];
+            check(&network, &settings)
+                .into_iter()
+                .map(|issue| (issue.severity, issue.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            found(-2.0, 0.0),
+            [(
+                Severity::Error,
+                "object post intrudes 0.60 m into the driving lanes".to_string()
+            )]
+        );
+        assert_eq!(
+            found(-2.0, 3.0),
+            [(
+                Severity::Error,
+                "object post passes 3.00 m above the lanes, below the 4.50 m vertical clearance"
+                    .to_string()
+            )]
+        );
+        assert!(found(-2.0, 5.0).is_empty());
+        assert_eq!(
+            found(-4.2, 0.0),
+            [(
+                Severity::Warning,
+                "object post is 0.40 m from the lanes, within the 0.50 m lateral clearance"
+                    .to_string()
+            )]
+        );
+        assert!(found(-5.0, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,984 @@
+// Headless command-line entry points.
+//
+// Running the binary without arguments opens the viewer on the demo network.
+// With a command as the first argument it either opens the viewer on a map
+// file or performs that job on the road network and exits instead.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use crate::annotations::Annotations;
+use crate::asset_packs::AssetPacks;
+use crate::batch::validate_dir;
+use crate::geo::GeoReference;
+use crate::i18n::Locale;
+use crate::issue_export::{write_report, Format};
+use crate::loader::{load_network, load_partial};
+use crate::pointcloud::PointCloudSource;
+use crate::transform::{LoadTransform, UpAxis};
+use bevy::math::{DVec2, DVec3};
+
+use crate::capture::{CapturePath, CaptureSettings};
+use crate::crop::{corridor, crop, Region};
+use crate::environment::Environment;
+use crate::map_matching::{match_trace, read_trace};
+use crate::merge::{merge, Placement};
+use crate::night::Night;
+use crate::normalize::normalize;
+use crate::occlusion::{Occlusion, Quality};
+use crate::routing::{k_shortest_paths, shortest_route, RouteOptions};
+use crate::sight::SightSettings;
+use crate::sign_models::SignCatalogs;
+use crate::style::StyleSheet;
+use crate::templates::TemplateLibrary;
+use crate::theme::{Theme, THEMES};
+use crate::tiles::TileSettings;
+use crate::units::SpeedUnit;
+use crate::validation::{format_report, validate, Severity, ValidationSettings};
+use crate::weather::Weather;
+use crate::{
+    apollo, carla, compiled, lane_report, map_tiles, plan_export, project, route_export,
+    route_profile, sumo, xodr, RoadNetwork,
+};
+
+// Usage text printed for `help` and for malformed invocations.
+const USAGE: &str = "\
+usage: road-visualizer [command]
+
+commands:
+  view [map] [--points <cloud>]...  open the viewer on a map (.xodr, .osm, either
+                                    .gz or .zst compressed, a .zip holding one, or
+                                    a .rsnet written by compile),
+                                    with optional point cloud overlays (.las, .pcd),
+                                    or a project (.rsodr) holding all of these
+      --layer <map>                 merge another map into the network
+      --trajectory <route.csv>      draw a trajectory, as written by export-route
+      --annotations <file>          keep annotations in this file rather than
+                                    next to the map
+      --offset x,y[,z]              shift the preceding layer (the map if none)
+      --rotate <deg>                rotate the preceding layer about the up axis
+      --y-up                        the preceding layer's source data is Y-up
+      --proj <PROJ string>          the preceding point cloud's projection, to
+                                    place it through the map's georeference
+                                    (LAS files may name theirs)
+      --tile-budget <MB>            GPU memory for streamed road tiles (default 512)
+      --tile-size <m>               edge length of a road tile (default 500)
+      --simplify <m>                simplify road meshes to within m meters
+      --sight-threshold <m>         flag sight distances below m meters (default 100)
+      --lateral-clearance <m>       required gap beside the lanes (default 0.5)
+      --vertical-clearance <m>      required headroom over the lanes (default 4.5)
+      --min-lane-width <m>          narrowest acceptable lane (default 2.5)
+      --max-lane-width <m>          widest acceptable lane (default 5)
+      --max-width-change <m/m>      fastest acceptable lane width change (default 0.2)
+      --max-grade <%>               steepest acceptable grade (default 8)
+      --min-radius <km/h>:<m>       smallest curve radius for a speed class, one of
+                                    30, 40, 50, 60, 70, 80, 90, 100 or 120 km/h
+                                    (defaults 25 m at 30 km/h up to 750 m at 120)
+      --min-transition <m>          shortest acceptable spiral (default 30)
+      --theme <name>                colors: default, colorblind or high-contrast
+      --style <file.json>           color or hide lanes and objects by rules
+      --environment <image>         sky and lighting from a panorama (.hdr, .png)
+      --night                       start at night, lit by the street lights
+      --weather <name>              road and fog for clear, wet, rain or fog
+      --occlusion <quality>         ambient occlusion: low, medium, high or ultra
+      --templates <file.json>       cross-section templates for new roads
+      --sign-catalog <file.json>    how signs of their type codes look
+      --assets <dir>                models for objects, from a folder with an
+                                    assets.json mapping (may be repeated)
+      --lang <code|file.ftl>        language of the UI text (default from LANG)
+      --speed-unit <unit>           show speeds in km/h (default), mph or m/s
+      --script <file>               run console commands from a file at startup
+      --capture <turntable|route.csv>
+                                    record a turntable, or a drive along a route
+                                    written by export-route, then exit
+      --capture-out <dir|file.mp4>  where the frames go (default `capture`); an
+                                    .mp4 is encoded with ffmpeg
+  validate [map] [options]          check the map and list the issues found; fails
+                                    if any is an error. Takes the clearance and lane
+                                    width options of `view`
+      --report <out.sarif|out.xml>  also write the issues and the map's annotations
+                                    as SARIF or JUnit XML
+  validate --dir <maps> [--report <dir>] [--format sarif|junit] [options]
+                                    check every .xodr (also .xodr.gz, .xodr.zst) in
+                                    a directory in parallel, writing a report per
+                                    map to the report directory (SARIF by default);
+                                    fails if any map has an error or does not load
+  export-apollo <out.txt> [map]     write the network as an Apollo HD map (text proto)
+  export-carla <out.gltf> [map] [--simplify <m>]
+                                    write CARLA-ready meshes (Road_Road, Road_Marking,
+                                    Road_Sidewalk, Road_Curb, Road_Grass),
+                                    optionally simplified to within m meters
+  export-xodr <out.xodr> [map]      write the network as OpenDRIVE
+  export-sumo <out.net.xml> [map]   write the network as a SUMO network
+  lane-report <out.csv> [map]       write the length and surface area of every
+                                    lane and lane section as CSV
+  compile <out.rsnet> [map]         write the network as read to a binary file,
+                                    which the viewer and the other commands
+                                    load without parsing the map again
+  normalize <out.xodr> [map]        rewrite the map in a canonical form for
+                                    cleaner diffs: geometry records kept as
+                                    read, degenerate and redundant lane
+                                    sections merged, elements sorted
+  crop <out.xodr> <region> [map]    cut out the part of the map inside a region,
+                                    given as minx,miny,maxx,maxy or as polygon
+                                    corners x1,y1;x2,y2;... (map frame, meters)
+  merge <out.xodr> <a> <b> [--offset dx,dy] [--rotate deg] [--tolerance m]
+                                    place map b next to map a and link road
+                                    ends that meet (tolerance defaults to 0.1 m)
+  export-route <out.csv|out.xosc> <from> <to> [map] [options]
+                                    route between two lanes, given as
+                                    road:section:lane, and write it out
+      --profile <out.csv>           also write the elevation, speed limit and
+                                    curvature every meter along the route
+      --fastest                     minimize the time at the speed limits
+                                    instead of the length
+      --no-u-turns                  never turn back
+      --vehicle <name>              use only lanes open to a road user, named
+                                    as in OpenDRIVE access rules, e.g.
+                                    passengerCar, bus or bicycle
+  match-trace <out.csv|out.xosc> <trace.csv> [map]
+                                    snap a GPS trace (CSV with x and y, map
+                                    frame, or lat and lon columns, and optional
+                                    z and heading) to the lanes and write the
+                                    matched path as a route
+  corridor <out.xodr> <from> <to> [map] [options]
+                                    find the best routes between two lanes and
+                                    cut out the lanes along them
+      --routes <k>                  how many routes (default 3)
+      --width <m>                   how far from the routes, in meters
+                                    (default 10)
+                                    and the routing options of export-route
+  export-plan <out.svg|out.pdf> [map] [--scale 1:<n>] [--theme <name>]
+                                    draw the map from above as a vector plan of
+                                    the lanes, road marks, road names and
+                                    junction outlines, at a scale (default
+                                    1:1000)
+  render-tiles <out dir> [map] [--zoom <min>-<max>] [--theme <name>]
+                                    draw the map from above into XYZ tiles,
+                                    <zoom>/<x>/<y>.png, for web maps: in Web
+                                    Mercator if the map is georeferenced, else
+                                    zoom 0 spans the map; by default six levels
+                                    from the one where the map fits in a tile
+  help                              show this message
+
+Without a command the viewer is started on a built-in demo network.";
+
+// What `main` should do after the command line has been handled.
+pub enum Launch {
+    // Open the interactive viewer.
+    Viewer(Box<ViewerOptions>),
+    // A headless command ran; exit with its status.
+    Exit(ExitCode),
+}
+
+// What the viewer shows and how.
+pub struct ViewerOptions {
+    pub network: RoadNetwork,
+    // The map file, if not the demo network.
+    pub map: Option<PathBuf>,
+    // Further map files merged into the network.
+    pub layers: Vec<PathBuf>,
+    pub point_clouds: Vec<PointCloudSource>,
+    // Viewer-frame polylines drawn over the map.
+    pub trajectories: Vec<Vec<DVec3>>,
+    // The annotations file, if not the map's sidecar file.
+    pub annotations: Option<PathBuf>,
+    pub tiles: TileSettings,
+    pub sight: SightSettings,
+    pub validation: ValidationSettings,
+    pub capture: Option<CaptureSettings>,
+    pub theme: Theme,
+    pub style: StyleSheet,
+    pub environment: Environment,
+    pub night: Night,
+    pub weather: Weather,
+    pub occlusion: Occlusion,
+    pub templates: TemplateLibrary,
+    pub sign_catalogs: SignCatalogs,
+    pub asset_packs: AssetPacks,
+    pub locale: Locale,
+    pub speed_unit: SpeedUnit,
+    pub script: Option<PathBuf>,
+}
+
+// Runs the command named in `args`, if any.
+pub fn run(args: &[String]) -> Launch {
+    let Some((command, rest)) = args.split_first() else {
+        return match load_network(None) {
+            Ok(network) => Launch::Viewer(Box::new(ViewerOptions {
+                network,
+                map: None,
+                layers: Vec::new(),
+                point_clouds: Vec::new(),
+                trajectories: Vec::new(),
+                annotations: None,
+                tiles: TileSettings::default(),
+                sight: SightSettings::default(),
+                validation: ValidationSettings::default(),
+                capture: None,
+                theme: Theme::default(),
+                style: StyleSheet::default(),
+                environment: Environment::default(),
+                night: Night::default(),
+                weather: Weather::default(),
+                occlusion: Occlusion::default(),
+                templates: TemplateLibrary::default(),
+                sign_catalogs: SignCatalogs::default(),
+                asset_packs: AssetPacks::default(),
+                locale: Locale::from_environment(),
+                speed_unit: SpeedUnit::default(),
+                script: None,
+            })),
+            Err(message) => fail(message),
+        };
+    };
+
+    let result = match command.as_str() {
+        "view" => match view_arguments(rest) {
+            Ok(options) => return Launch::Viewer(Box::new(options)),
+            Err(message) => Err(message),
+        },
+        "export-apollo" => output_and_map(rest).and_then(|(out, network)| {
+            apollo::write_map(&network, out).map_err(|e| format!("{}: {e}", out.display()))
+        }),
+        "export-carla" => export_carla(rest),
+        "export-xodr" => output_and_map(rest).and_then(|(out, network)| {
+            xodr::write_map(&network, out).map_err(|e| format!("{}: {e}", out.display()))
+        }),
+        "export-sumo" => output_and_map(rest).and_then(|(out, network)| {
+            sumo::write_map(&network, out).map_err(|e| format!("{}: {e}", out.display()))
+        }),
+        "lane-report" => {
+            output_and_map(rest).and_then(|(out, network)| lane_report::write_report(&network, out))
+        }
+        "compile" => {
+            output_and_map(rest).and_then(|(out, network)| compiled::write_file(&network, out))
+        }
+        "normalize" => normalize_map(rest),
+        "crop" => crop_map(rest),
+        "corridor" => corridor_map(rest),
+        "match-trace" => match_gps_trace(rest),
+        "merge" => merge_maps(rest),
+        "export-route" => export_route(rest),
+        "validate" => validate_map(rest),
+        "render-tiles" => render_map_tiles(rest),
+        "export-plan" => export_plan(rest),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        other => Err(format!("unknown command `{other}`\n\n{USAGE}")),
+    };
+
+    match result {
+        Ok(()) => Launch::Exit(ExitCode::SUCCESS),
+        Err(message) => fail(message),
+    }
+}
+
+// Reports an error and produces a failing exit.
+fn fail(message: String) -> Launch {
+    eprintln!("error: {message}");
+    Launch::Exit(ExitCode::FAILURE)
+}
+
+// Extracts an optional single map path.
+fn optional_path(rest: &[String]) -> Result<Option<&Path>, String> {
+    match rest {
+        [] => Ok(None),
+        [path] => Ok(Some(Path::new(path))),
+        _ => Err(format!("expected at most one map path\n\n{USAGE}")),
+    }
+}
+
+// Road ends of merged maps closer than this, in meters, are linked.
+const LINK_TOLERANCE: f64 = 0.1;
+
+// A source of the viewer, for the transform options that follow it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Map,
+    Layer,
+    Cloud,
+}
+
+// Splits the `view` arguments into the map and any `--layer` maps and
+// `--points` overlays, each with the transform options that follow it, and
+// the viewer settings. A project file among them is read as the arguments
+// it stands for.
+fn view_arguments(rest: &[String]) -> Result<ViewerOptions, String> {
+    let mut map = Vec::new();
+    let mut tiles = TileSettings::default();
+    let mut sight = SightSettings::default();
+    let mut validation = ValidationSettings::default();
+    let mut theme = Theme::default();
+    let mut style = StyleSheet::default();
+    let mut environment = Environment::default();
+    let mut night = Night::default();
+    let mut weather = Weather::default();
+    let mut occlusion = Occlusion::default();
+    let mut templates = TemplateLibrary::default();
+    let mut sign_catalogs = SignCatalogs::default();
+    let mut asset_packs = AssetPacks::default();
+    let mut locale = None;
+    let mut speed_unit = SpeedUnit::default();
+    let mut script = None;
+    let mut capture_path = None;
+    let mut capture_out = None;
+    let mut map_transform = LoadTransform::default();
+    let mut layers: Vec<(PathBuf, LoadTransform)> = Vec::new();
+    let mut point_clouds: Vec<PointCloudSource> = Vec::new();
+    let mut trajectories = Vec::new();
+    let mut annotations = None;
+    // Which of the sources read so far the transform options apply to.
+    let mut last = Source::Map;
+    // Projects are read as the arguments they stand for.
+    let rest = project::expand(rest)?;
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))
+        };
+        let transform = match (last, layers.last_mut(), point_clouds.last_mut()) {
+            (Source::Layer, Some((_, transform)), _) => transform,
+            (Source::Cloud, _, Some(cloud)) => &mut cloud.transform,
+            _ => &mut map_transform,
+        };
+        match arg.as_str() {
+            "--points" => {
+                point_clouds.push(PointCloudSource {
+                    path: PathBuf::from(value()?),
+                    transform: LoadTransform::default(),
+                    proj: None,
+                });
+                last = Source::Cloud;
+            }
+            "--proj" => {
+                let proj = value()?;
+                match (last, point_clouds.last_mut()) {
+                    (Source::Cloud, Some(cloud)) => {
+                        GeoReference::parse(proj).map_err(|e| format!("--proj: {e}"))?;
+                        cloud.proj = Some(proj.clone());
+                    }
+                    _ => return Err(format!("--proj must follow --points\n\n{USAGE}")),
+                }
+            }
+            "--layer" => {
+                layers.push((PathBuf::from(value()?), LoadTransform::default()));
+                last = Source::Layer;
+            }
+            "--trajectory" => {
+                let file = value()?;
+                let text = std::fs::read_to_string(file).map_err(|e| format!("{file}: {e}"))?;
+                trajectories
+                    .push(route_export::read_csv(&text).map_err(|e| format!("{file}: {e}"))?);
+            }
+            "--annotations" => annotations = Some(PathBuf::from(value()?)),
+            "--offset" => {
+                let text = value()?;
+                let numbers = text
+                    .split(',')
+                    .map(|n| n.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("invalid offset `{text}`"))?;
+                transform.offset = match numbers[..] {
+                    [x, y] => DVec3::new(x, y, 0.0),
+                    [x, y, z] => DVec3::new(x, y, z),
+                    _ => return Err(format!("invalid offset `{text}`")),
+                };
+            }
+            "--rotate" => {
+                let text = value()?;
+                let degrees: f64 = text
+                    .parse()
+                    .map_err(|_| format!("invalid rotation `{text}`"))?;
+                transform.rotation = degrees.to_radians();
+            }
+            "--y-up" => transform.up = UpAxis::Y,
+            "--tile-budget" => {
+                let text = value()?;
+                tiles.memory_budget = text
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|megabytes| megabytes.checked_mul(1024 * 1024))
+                    .ok_or_else(|| format!("invalid tile budget `{text}`"))?;
+            }
+            "--tile-size" => {
+                let text = value()?;
+                tiles.tile_size = text
+                    .parse()
+                    .ok()
+                    .filter(|size: &f64| *size > 0.0)
+                    .ok_or_else(|| format!("invalid tile size `{text}`"))?;
+            }
+            "--simplify" => {
+                let text = value()?;
+                let error: f64 = text
+                    .parse()
+                    .ok()
+                    .filter(|error: &f64| *error >= 0.0)
+                    .ok_or_else(|| format!("invalid simplification error `{text}`"))?;
+                tiles.max_error = Some(error);
+            }
+            "--sight-threshold" => {
+                let text = value()?;
+                sight.threshold = text
+                    .parse()
+                    .ok()
+                    .filter(|threshold: &f64| *threshold > 0.0)
+                    .ok_or_else(|| format!("invalid sight distance threshold `{text}`"))?;
+            }
+            option if VALIDATION_OPTIONS.contains(&option) => {
+                validation_option(&mut validation, option, value()?)?;
+            }
+            "--capture" => {
+                capture_path = Some(match value()?.as_str() {
+                    "turntable" => CapturePath::Turntable,
+                    route => {
+                        let text =
+                            std::fs::read_to_string(route).map_err(|e| format!("{route}: {e}"))?;
+                        CapturePath::Route(
+                            route_export::read_csv(&text).map_err(|e| format!("{route}: {e}"))?,
+                        )
+                    }
+                });
+            }
+            "--theme" => theme = theme_argument(value()?)?,
+            "--style" => style = StyleSheet::load(Path::new(value()?))?,
+            "--environment" => {
+                let path = PathBuf::from(value()?);
+                if !path.is_file() {
+                    return Err(format!("no file {}", path.display()));
+                }
+                // The asset server reads paths relative to its own folder.
+                let path = std::env::current_dir()
+                    .map(|dir| dir.join(&path))
+                    .unwrap_or(path);
+                environment = Environment::from_file(path);
+            }
+            "--night" => night.on = true,
+            "--weather" => weather = Weather::parse(value()?)?,
+            "--occlusion" => {
+                occlusion = Occlusion {
+                    on: true,
+                    quality: Quality::parse(value()?)?,
+                }
+            }
+            "--templates" => templates = TemplateLibrary::load(Path::new(value()?))?,
+            "--sign-catalog" => sign_catalogs = SignCatalogs::load(Path::new(value()?))?,
+            "--assets" => {
+                asset_packs.read(Path::new(value()?))?;
+            }
+            "--lang" => locale = Some(Locale::load(value()?)?),
+            "--speed-unit" => speed_unit = SpeedUnit::parse(value()?)?,
+            "--script" => script = Some(PathBuf::from(value()?)),
+            "--capture-out" => capture_out = Some(PathBuf::from(value()?)),
+            _ => map.push(arg.clone()),
+        }
+    }
+    if capture_out.is_some() && capture_path.is_none() {
+        return Err("--capture-out needs --capture".to_string());
+    }
+    let map = optional_path(&map)?;
+    // The viewer shows what it can of broken maps (see `failures`).
+    let mut network = load_partial(map, &map_transform)?;
+    // Further layers are merged in, linked where their roads meet.
+    for (path, transform) in &layers {
+        let other = load_partial(Some(path), transform)?;
+        network = merge(&network, &other, Placement::default(), LINK_TOLERANCE).0;
+    }
+    Ok(ViewerOptions {
+        network,
+        map: map.map(Path::to_path_buf),
+        layers: layers.into_iter().map(|(path, _)| path).collect(),
+        point_clouds,
+        trajectories,
+        annotations,
+        tiles,
+        sight,
+        validation,
+        capture: capture_path.map(|path| CaptureSettings {
+            path,
+            out: capture_out.unwrap_or_else(|| PathBuf::from("capture")),
+        }),
+        theme,
+        style,
+        environment,
+        night,
+        weather,
+        occlusion,
+        templates,
+        sign_catalogs,
+        asset_packs,
+        locale: locale.unwrap_or_else(Locale::from_environment),
+        speed_unit,
+        script,
+    })
+}
+
+// Options setting validation thresholds, shared by `view` and `validate`.
+const VALIDATION_OPTIONS: [&str; 8] = [
+    "--lateral-clearance",
+    "--vertical-clearance",
+    "--min-lane-width",
+    "--max-lane-width",
+    "--max-width-change",
+    "--max-grade",
+    "--min-radius",
+    "--min-transition",
+];
+
+// Sets a validation threshold from its option.
+fn validation_option(
+    settings: &mut ValidationSettings,
+    option: &str,
+    text: &str,
+) -> Result<(), String> {
+    if option == "--min-radius" {
+        return settings.design.set_radius(text);
+    }
+    let value: f64 = text
+        .parse()
+        .ok()
+        .filter(|value: &f64| *value >= 0.0)
+        .ok_or_else(|| format!("invalid value `{text}` for {option}"))?;
+    match option {
+        "--lateral-clearance" => settings.clearance.lateral = value,
+        "--vertical-clearance" => settings.clearance.vertical = value,
+        "--min-lane-width" => settings.lane_width.min = value,
+        "--max-lane-width" => settings.lane_width.max = value,
+        "--max-grade" => settings.design.max_grade = value,
+        "--min-transition" => settings.design.min_transition = value,
+        _ => settings.lane_width.max_change = value,
+    }
+    Ok(())
+}
+
+// Prints the validation report of a map, or of every map in a directory,
+// failing if there are errors.
+fn validate_map(rest: &[String]) -> Result<(), String> {
+    let mut settings = ValidationSettings::default();
+    let mut report = None;
+    let mut dir = None;
+    let mut format = Format::Sarif;
+    let mut map = Vec::new();
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            option if VALIDATION_OPTIONS.contains(&option) => {
+                let text = args
+                    .next()
+                    .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+                validation_option(&mut settings, option, text)?;
+            }
+            option @ ("--report" | "--dir" | "--format") => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{option} needs a value\n\n{USAGE}"))?;
+                match option {
+                    "--report" => report = Some(PathBuf::from(value)),
+                    "--dir" => dir = Some(PathBuf::from(value)),
+                    _ => {
+                        format = match value.as_str() {
+                            "sarif" => Format::Sarif,
+                            "junit" => Format::Junit,
+                            other => return Err(format!("unknown report format `{other}`")),
+                        }
+                    }
+                }
+            }
+            _ => map.push(arg.clone()),
+        }
+    }
+    if let Some(dir) = dir {
+        if !map.is_empty() {
+            return Err(format!("--dir takes no map\n\n{USAGE}"));
+        }
+        return validate_dir(&dir, &settings, report.as_deref().map(|out| (out, format)));
+    }
+    // Checked before the map is loaded and validated.
+    if let Some(report) = &report {
+        Format::of(report)?;
+    }
+    let map = optional_path(&map)?;
+    let network = load_network(map)?;
+    let issues = validate(&network, &settings);
+    print!("{}", format_report(&issues));
+    if let Some(report) = report {
+        let annotations = Annotations::load(map, &network);
+        write_report(&report, map, &issues, &annotations.list)?;
+        println!("wrote {}", report.display());
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(format!("validation found {errors} error(s)"));
+    }
+    Ok(())
+}
+
+// Extracts the output path exporters take, followed by an optional map to
+// load (the demo network otherwise).
+fn output_and_map(rest: &[String]) -> Result<(&Path, RoadNetwork), String> {
+    match rest {
+        [out, map @ ..] => Ok((Path::new(out), load_network(optional_path(map)?)?)),
+        _ => Err(format!("expected an output path\n\n{USAGE}")),
+    }
+}
+
+// Writes CARLA meshes, simplified if `--simplify` is given.
+fn export_carla(rest: &[String]) -> Result<(), String> {
+    let mut max_error = None;
+    let mut positional = Vec::new();
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        if arg != "--simplify" {
+            positional.push(arg.clone());
+            continue;
+        }
+        let text = args
+            .next()
+            .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+        let error: f64 = text
+            .parse()
+            .ok()
+            .filter(|error: &f64| *error >= 0.0)
+            .ok_or_else(|| format!("invalid simplification error `{text}`"))?;
+        max_error = Some(error);
+    }
+    let (out, network) = output_and_map(&positional)?;
+    carla::write_map(&network, out, max_error).map_err(|e| format!("{}: {e}", out.display()))
+}
+
+// Parses a theme name.
+fn theme_argument(text: &str) -> Result<Theme, String> {
+    Theme::named(text).ok_or_else(|| {
+        let names: Vec<&str> = THEMES.iter().map(|theme| theme.name).collect();
+        format!("unknown theme `{text}` (try {})", names.join(", "))
+    })
+}
+
+// Draws the map into a tile pyramid.
+fn render_map_tiles(rest: &[String]) -> Result<(), String> {
+    let mut zoom = None;
+    let mut theme = Theme::default();
+    let mut positional = Vec::new();
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        if !matches!(arg.as_str(), "--zoom" | "--theme") {
+            positional.push(arg.clone());
+            continue;
+        }
+        let text = args
+            .next()
+            .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+        if arg == "--theme" {
+            theme = theme_argument(text)?;
+            continue;
+        }
+        let levels = text
+            .split_once('-')
+            .and_then(|(min, max)| Some((min.parse::<u8>().ok()?, max.parse::<u8>().ok()?)))
+            .filter(|(min, max)| min <= max)
+            .ok_or_else(|| format!("invalid zoom levels `{text}`, expected e.g. 12-18"))?;
+        zoom = Some(levels.0..=levels.1);
+    }
+    let (out, network) = output_and_map(&positional)?;
+    let zoom = zoom.unwrap_or_else(|| map_tiles::default_zoom(&network));
+    let (first, last) = (*zoom.start(), *zoom.end());
+    let written = map_tiles::render_tiles(&network, out, zoom, &theme)?;
+    println!(
+        "wrote {written} tile(s) at zoom {first} to {last}{}",
+        if network.geo.is_some() {
+            " (Web Mercator)"
+        } else {
+            " (local)"
+        }
+    );
+    Ok(())
+}
+
+// Writes a vector plan of the map.
+fn export_plan(rest: &[String]) -> Result<(), String> {
+    let mut scale = plan_export::DEFAULT_SCALE;
+    let mut theme = Theme::default();
+    let mut positional = Vec::new();
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        if !matches!(arg.as_str(), "--scale" | "--theme") {
+            positional.push(arg.clone());
+            continue;
+        }
+        let text = args
+            .next()
+            .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+        if arg == "--theme" {
+            theme = theme_argument(text)?;
+        } else {
+            scale = plan_export::parse_scale(text)?;
+        }
+    }
+    let (out, network) = output_and_map(&positional)?;
+    plan_export::write_plan(&network, out, scale, &theme)
+}
+
+// Writes a normalized copy of a map.
+fn normalize_map(rest: &[String]) -> Result<(), String> {
+    let (out, mut network) = output_and_map(rest)?;
+    let summary = normalize(&mut network);
+    println!(
+        "{} road(s): {} degenerate lane section(s) removed, {} merged",
+        summary.roads, summary.degenerate, summary.merged
+    );
+    xodr::write_map(&network, out).map_err(|e| format!("{}: {e}", out.display()))
+}
+
+// Routes between two lanes and writes the result.
+fn export_route(rest: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut profile = None;
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        if arg != "--profile" {
+            positional.push(arg.clone());
+            continue;
+        }
+        let file = args
+            .next()
+            .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+        profile = Some(Path::new(file));
+    }
+    let (options, positional) = RouteOptions::parse(&positional)?;
+    let [out, from, to, map @ ..] = positional.as_slice() else {
+        return Err(format!("expected an output path and two lanes\n\n{USAGE}"));
+    };
+    let out = Path::new(out);
+    let map: Vec<String> = map.iter().map(|arg| arg.to_string()).collect();
+    let network = load_network(optional_path(&map)?)?;
+    let from = lane_argument(&network, from)?;
+    let to = lane_argument(&network, to)?;
+    let route =
+        shortest_route(&network, from, to, &options).ok_or("no route between those lanes")?;
+    route_export::write_route(&network, &route, out)
+        .map_err(|e| format!("{}: {e}", out.display()))?;
+    if let Some(profile) = profile {
+        let csv = route_profile::to_csv(&route_profile::route_profile(&network, &route));
+        std::fs::write(profile, csv).map_err(|e| format!("{}: {e}", profile.display()))?;
+    }
+    Ok(())
+}
+
+// Parses a `road:section:lane` triple and looks the segment up.
+pub fn lane_argument(network: &RoadNetwork, text: &str) -> Result<usize, String> {
+    let invalid = || format!("`{text}` is not of the form road:section:lane");
+    let parts: Vec<&str> = text.split(':').collect();
+    let [road, section, lane] = parts[..] else {
+        return Err(invalid());
+    };
+    let road = road.parse::<u32>().map_err(|_| invalid())?;
+    let section = section.parse::<u32>().map_err(|_| invalid())?;
+    let lane = lane.parse::<i32>().map_err(|_| invalid())?;
+    network
+        .find_segment(road, section, lane)
+        .ok_or_else(|| format!("no lane {text} in the map"))
+}
+
+// Crops the map to a region and writes the result as OpenDRIVE.
+fn crop_map(rest: &[String]) -> Result<(), String> {
+    let [out, region, map @ ..] = rest else {
+        return Err(format!("expected an output path and a region\n\n{USAGE}"));
+    };
+    let out = Path::new(out);
+    let region = region_argument(region)?;
+    let network = load_network(optional_path(map)?)?;
+    let cropped = crop(&network, &region);
+    if cropped.segments.is_empty() {
+        return Err("no road lies inside the region".into());
+    }
+    xodr::write_map(&cropped, out).map_err(|e| format!("{}: {e}", out.display()))
+}
+
+// Snaps a trace to the lanes and writes the matched path.
+fn match_gps_trace(rest: &[String]) -> Result<(), String> {
+    let [out, trace, map @ ..] = rest else {
+        return Err(format!("expected an output path and a trace\n\n{USAGE}"));
+    };
+    let out = Path::new(out);
+    let text = std::fs::read_to_string(trace).map_err(|e| format!("{trace}: {e}"))?;
+    let network = load_network(optional_path(map)?)?;
+    let points = read_trace(&text, &network).map_err(|e| format!("{trace}: {e}"))?;
+    let matched = match_trace(&network, &points).ok_or("no fix of the trace is near a lane")?;
+    route_export::write_route(&network, &matched.route, out)
+        .map_err(|e| format!("{}: {e}", out.display()))?;
+    println!(
+        "matched {} of {} fix(es) to {} lane(s)",
+        matched.matched.iter().flatten().count(),
+        points.len(),
+        matched.route.segments.len()
+    );
+    Ok(())
+}
+
+// Cuts out the lanes along the best routes between two lanes and writes
+// them as OpenDRIVE.
+fn corridor_map(rest: &[String]) -> Result<(), String> {
+    let (options, rest) = RouteOptions::parse(rest)?;
+    let mut positional = Vec::new();
+    let (mut count, mut width) = (3, 10.0);
+    let mut args = rest.into_iter();
+    while let Some(arg) = args.next() {
+        if !matches!(arg, "--routes" | "--width") {
+            positional.push(arg.to_string());
+            continue;
+        }
+        let text = args
+            .next()
+            .ok_or_else(|| format!("{arg} needs a value\n\n{USAGE}"))?;
+        if arg == "--routes" {
+            count = text
+                .parse()
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(|| format!("invalid route count `{text}`"))?;
+        } else {
+            width = text
+                .parse()
+                .ok()
+                .filter(|width: &f64| *width > 0.0)
+                .ok_or_else(|| format!("invalid corridor width `{text}`"))?;
+        }
+    }
+    let [out, from, to, map @ ..] = positional.as_slice() else {
+        return Err(format!("expected an output path and two lanes\n\n{USAGE}"));
+    };
+    let out = Path::new(out);
+    let network = load_network(optional_path(map)?)?;
+    let from = lane_argument(&network, from)?;
+    let to = lane_argument(&network, to)?;
+    let routes = k_shortest_paths(&network, from, to, count, &options);
+    if routes.is_empty() {
+        return Err("no route between those lanes".into());
+    }
+    let cut = corridor(&network, &routes, width);
+    xodr::write_map(&cut, out).map_err(|e| format!("{}: {e}", out.display()))?;
+    println!(
+        "{} route(s); {} lane(s) in the corridor",
+        routes.len(),
+        cut.segments.len()
+    );
+    Ok(())
+}
+
+// Parses a rectangle `minx,miny,maxx,maxy` or a polygon `x1,y1;x2,y2;...`.
+fn region_argument(text: &str) -> Result<Region, String> {
+    let invalid = || format!("`{text}` is neither minx,miny,maxx,maxy nor x1,y1;x2,y2;...");
+    let numbers = |part: &str| -> Result<Vec<f64>, String> {
+        part.split(',')
+            .map(|n| n.trim().parse::<f64>().map_err(|_| invalid()))
+            .collect()
+    };
+
+    if !text.contains(';') {
+        let [min_x, min_y, max_x, max_y] = numbers(text)?[..] else {
+            return Err(invalid());
+        };
+        return Ok(Region::rectangle(
+            DVec2::new(min_x.min(max_x), min_y.min(max_y)),
+            DVec2::new(min_x.max(max_x), min_y.max(max_y)),
+        ));
+    }
+
+    let corners = text
+        .split(';')
+        .map(|corner| match numbers(corner)?[..] {
+            [x, y] => Ok(DVec2::new(x, y)),
+            _ => Err(invalid()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if corners.len() < 3 {
+        return Err(invalid());
+    }
+    Ok(Region::polygon(corners))
+}
+
+// Merges two maps and writes the result as OpenDRIVE.
+fn merge_maps(rest: &[String]) -> Result<(), String> {
+    let [out, a, b, options @ ..] = rest else {
+        return Err(format!("expected an output path and two maps\n\n{USAGE}"));
+    };
+    let out = Path::new(out);
+
+    let mut placement = Placement::default();
+    let mut tolerance = LINK_TOLERANCE;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| format!("{option} needs a value\n\n{USAGE}"))?;
+        let invalid = || format!("invalid value `{value}` for {option}");
+        let number = |text: &str| {
+            text.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .ok_or_else(invalid)
+        };
+        match option.as_str() {
+            "--offset" => {
+                let parts: Vec<&str> = value.split(',').collect();
+                let [x, y] = parts[..] else {
+                    return Err(invalid());
+                };
+                placement.offset = DVec2::new(number(x)?, number(y)?);
+            }
+            "--rotate" => placement.rotation = number(value)?.to_radians(),
+            "--tolerance" => {
+                tolerance = Some(number(value)?)
+                    .filter(|tolerance| *tolerance >= 0.0)
+                    .ok_or_else(invalid)?;
+            }
+            other => return Err(format!("unknown option `{other}`\n\n{USAGE}")),
+        }
+    }
+
+    let a = load_network(Some(Path::new(a)))?;
+    let b = load_network(Some(Path::new(b)))?;
+    let (merged, links) = merge(&a, &b, placement, tolerance);
+    println!("linked {links} road end(s) between the two maps");
+    xodr::write_map(&merged, out).map_err(|e| format!("{}: {e}", out.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn tile_budgets_that_overflow_are_refused() {
+        let options = view_arguments(&args(&["--tile-budget", "64"])).unwrap();
+        assert_eq!(options.tiles.memory_budget, 64 * 1024 * 1024);
+        let huge = (usize::MAX / 1024).to_string();
+        let error = view_arguments(&args(&["--tile-budget", &huge])).err();
+        assert_eq!(error, Some(format!("invalid tile budget `{huge}`")));
+    }
+
+    #[test]
+    fn merge_options_must_be_finite() {
+        for (option, value) in [
+            ("--tolerance", "-1"),
+            ("--tolerance", "NaN"),
+            ("--offset", "inf,0"),
+            ("--offset", "10,nan"),
+            ("--rotate", "infinity"),
+        ] {
+            let error = merge_maps(&args(&["out.xodr", "a.xodr", "b.xodr", option, value]));
+            assert_eq!(error, Err(format!("invalid value `{value}` for {option}")));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// Copying text to the system clipboard.
+//
+// There is no clipboard access in Bevy itself, so text is handed to the
+// platform's clipboard tool: clip on Windows, pbcopy on macOS, and wl-copy,
+// xclip or xsel on Linux, whichever is installed.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// The tools to try, with their arguments, in order.
+fn tools() -> &'static [(&'static str, &'static [&'static str])] {
+    if cfg!(target_os = "windows") {
+        &[("clip", &[])]
+    } else if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    }
+}
+
+// Puts `text` on the clipboard.
+pub fn copy(text: &str) -> Result<(), String> {
+    for (program, args) in tools() {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| format!("{program}: {e}"))?;
+        }
+        let status = child.wait().map_err(|e| format!("{program}: {e}"))?;
+        return if status.success() {
+            Ok(())
+        } else {
+            Err(format!("{program} failed with {status}"))
+        };
+    }
+    Err("no clipboard tool found".to_string())
+}
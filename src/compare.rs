@@ -0,0 +1,324 @@
+// Before/after comparison.
+//
+// Comparing lays a snapshot over the view and shows it left of a divider,
+// with the live view right of it, so that what changed in a regenerated map
+// stands out: take a snapshot, let the map reload (see `reload`), and drag
+// the divider across. The snapshot is the window as it was when taken, less
+// the UI, or a PNG saved earlier, for instance with `screenshot`. It only
+// lines up with the live view while the camera stays put, so while
+// comparing a left drag moves the divider rather than orbiting the camera.
+//
+// Keys: F11 takes a snapshot and starts comparing, or stops comparing.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::window::PrimaryWindow;
+
+// Width of the divider, in pixels.
+const DIVIDER_WIDTH: f32 = 2.0;
+
+#[derive(Resource)]
+pub struct Comparison {
+    // The snapshot shown left of the divider; None when not comparing.
+    pub snapshot: Option<Handle<Image>>,
+    // Where the divider is, as a fraction of the window width.
+    pub divider: f32,
+    // Set to take a snapshot of the view at the end of the frame.
+    pub requested: bool,
+    // A snapshot on its way back from the renderer.
+    taken: Arc<Mutex<Option<Image>>>,
+    waiting: bool,
+    // The UI hidden from the snapshot, with how it was shown.
+    hidden_ui: Vec<(Entity, Visibility)>,
+}
+
+impl Default for Comparison {
+    fn default() -> Self {
+        Self {
+            snapshot: None,
+            divider: 0.5,
+            requested: false,
+            taken: Arc::default(),
+            waiting: false,
+            hidden_ui: Vec::new(),
+        }
+    }
+}
+
+impl Comparison {
+    // Whether a snapshot is shown, or about to be.
+    pub fn active(&self) -> bool {
+        self.snapshot.is_some() || self.requested || self.waiting
+    }
+
+    // Puts the divider under the cursor.
+    pub fn move_divider(&mut self, cursor_x: f32, window_width: f32) {
+        if window_width > 0.0 {
+            self.divider = (cursor_x / window_width).clamp(0.0, 1.0);
+        }
+    }
+}
+
+// Reads a PNG to compare the view with.
+pub fn read_snapshot(path: &Path) -> Result<Image, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+// `compare` in the console: snapshots the view and starts comparing, or
+// compares with a PNG, or stops with `off`.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => {
+            let mut comparison = world.resource_mut::<Comparison>();
+            comparison.snapshot = None;
+            comparison.requested = true;
+            Ok("comparing with a snapshot of the view; drag to move the divider".to_string())
+        }
+        ["off"] => {
+            world.resource_mut::<Comparison>().snapshot = None;
+            Ok(String::new())
+        }
+        [file] => {
+            let image = read_snapshot(Path::new(file))?;
+            let handle = world.resource_mut::<Assets<Image>>().add(image);
+            world.resource_mut::<Comparison>().snapshot = Some(handle);
+            Ok(format!("comparing with {file}"))
+        }
+        _ => Err("expected a PNG file, off, or nothing".to_string()),
+    }
+}
+
+// The snapshot, clipped at the divider.
+#[derive(Component)]
+struct SnapshotClip;
+
+#[derive(Component)]
+struct SnapshotImage;
+
+#[derive(Component)]
+struct Divider;
+
+pub struct ComparePlugin;
+
+impl Plugin for ComparePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Comparison>()
+            .add_systems(Startup, spawn_overlay)
+            .add_systems(
+                Update,
+                (
+                    toggle_on_key,
+                    take_snapshot,
+                    receive_snapshot,
+                    drag_divider,
+                    update_overlay,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_overlay(mut commands: Commands) {
+    // Below the panels, which keep their places over both halves.
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(0.0),
+                    top: Val::Px(0.0),
+                    height: Val::Percent(100.0),
+                    overflow: Overflow::clip(),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(-1),
+                ..default()
+            },
+            SnapshotClip,
+        ))
+        .with_children(|clip| {
+            clip.spawn((ImageBundle::default(), SnapshotImage));
+        });
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(0.0),
+                width: Val::Px(DIVIDER_WIDTH),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::rgb(1.0, 0.9, 0.1).into(),
+            visibility: Visibility::Hidden,
+            z_index: ZIndex::Global(-1),
+            ..default()
+        },
+        Divider,
+    ));
+}
+
+fn toggle_on_key(keys: Res<ButtonInput<KeyCode>>, mut comparison: ResMut<Comparison>) {
+    if !keys.just_pressed(KeyCode::F11) {
+        return;
+    }
+    if comparison.active() {
+        comparison.snapshot = None;
+        comparison.requested = false;
+    } else {
+        comparison.requested = true;
+    }
+}
+
+// Asks the renderer for the window without the UI, which is hidden until
+// the snapshot arrives.
+#[allow(clippy::type_complexity)]
+fn take_snapshot(
+    mut comparison: ResMut<Comparison>,
+    mut screenshots: ResMut<ScreenshotManager>,
+    windows: Query<Entity, With<PrimaryWindow>>,
+    mut roots: Query<(Entity, &mut Visibility), (With<Node>, Without<Parent>)>,
+) {
+    if !comparison.requested || comparison.waiting {
+        return;
+    }
+    comparison.requested = false;
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let taken = comparison.taken.clone();
+    let request = screenshots.take_screenshot(window, move |image| {
+        *taken.lock().unwrap_or_else(PoisonError::into_inner) = Some(image);
+    });
+    if request.is_err() {
+        warn!("a screenshot is already being taken; no snapshot to compare with");
+        return;
+    }
+    comparison.waiting = true;
+    for (root, mut visibility) in &mut roots {
+        comparison.hidden_ui.push((root, *visibility));
+        *visibility = Visibility::Hidden;
+    }
+}
+
+fn receive_snapshot(
+    mut comparison: ResMut<Comparison>,
+    mut images: ResMut<Assets<Image>>,
+    mut roots: Query<&mut Visibility, With<Node>>,
+) {
+    if !comparison.waiting {
+        return;
+    }
+    let Some(image) = comparison
+        .taken
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+    else {
+        return;
+    };
+    comparison.waiting = false;
+    comparison.snapshot = Some(images.add(image));
+    for (root, shown) in std::mem::take(&mut comparison.hidden_ui) {
+        if let Ok(mut visibility) = roots.get_mut(root) {
+            *visibility = shown;
+        }
+    }
+}
+
+fn drag_divider(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut comparison: ResMut<Comparison>,
+) {
+    if comparison.snapshot.is_none() || !buttons.pressed(MouseButton::Left) {
+        return;
+    }
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    if let Some(cursor) = window.cursor_position() {
+        comparison.move_divider(cursor.x, window.width());
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_overlay(
+    comparison: Res<Comparison>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut clips: Query<(&mut Style, &mut Visibility), With<SnapshotClip>>,
+    mut images: Query<(&mut Style, &mut UiImage), (With<SnapshotImage>, Without<SnapshotClip>)>,
+    mut dividers: Query<
+        (&mut Style, &mut Visibility),
+        (With<Divider>, Without<SnapshotClip>, Without<SnapshotImage>),
+    >,
+) {
+    let visibility = if comparison.snapshot.is_some() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let at = Val::Percent(comparison.divider * 100.0);
+    for (mut style, mut shown) in &mut clips {
+        if style.width != at {
+            style.width = at;
+        }
+        shown.set_if_neq(visibility);
+    }
+    for (mut style, mut shown) in &mut dividers {
+        if style.left != at {
+            style.left = at;
+        }
+        shown.set_if_neq(visibility);
+    }
+    let (Some(snapshot), Ok(window)) = (&comparison.snapshot, windows.get_single()) else {
+        return;
+    };
+    // The snapshot keeps the window's size however much of it is shown.
+    let size = (Val::Px(window.width()), Val::Px(window.height()));
+    for (mut style, mut image) in &mut images {
+        if (style.width, style.height) != size {
+            (style.width, style.height) = size;
+        }
+        if image.texture != *snapshot {
+            image.texture = snapshot.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::path;
+
+    #[test]
+    fn snapshots_and_divider() {
+        let image = read_snapshot(&path("pixel.png")).unwrap();
+        assert_eq!(image.size(), bevy::math::UVec2::ONE);
+        assert!(read_snapshot(&path("straight.xodr")).is_err());
+        assert!(read_snapshot(&path("missing.png")).is_err());
+
+        let mut comparison = Comparison::default();
+        assert!(!comparison.active());
+        comparison.move_divider(300.0, 1200.0);
+        assert_eq!(comparison.divider, 0.25);
+        comparison.move_divider(-5.0, 1200.0);
+        assert_eq!(comparison.divider, 0.0);
+        comparison.move_divider(2000.0, 1200.0);
+        assert_eq!(comparison.divider, 1.0);
+    }
+}
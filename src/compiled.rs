@@ -0,0 +1,698 @@
+// Compiled networks.
+//
+// Reading a big OpenDRIVE map means parsing its XML and sampling every
+// geometry piece and lane width again, which takes a long time and comes
+// out the same every time. `compile` writes the network as read into a
+// binary `.rsnet` file instead, which loads without any parsing: the map's
+// name, lane boundaries, links, signals, per-road data with the reference
+// line records, and georeference are stored as they are in memory,
+// little-endian, behind a magic number and a format version.
+// Meshes are not stored; the mesh cache keeps those per tile, for the
+// compiled file as for any other map. The XML of roads and signals as read
+// is stored too, and left in the file when it is loaded, to be read back
+// when asked for (see `xodr::ElementXml`).
+//
+// A compiled network is loaded like any map, and load transforms are
+// applied on top of the coordinates it was compiled with. Files written by
+// another format version are refused, so that a map is compiled again
+// whenever the model changes.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use bevy::math::DVec3;
+
+use crate::byte_reader::Reader;
+use crate::geo::GeoReference;
+use crate::signals::{ObjectRepeat, Signal, SignalKind};
+use crate::xodr::{Cubic, ElementXml, PlanRecord, ReferenceLine, Shape, XmlSource};
+use crate::{
+    ContactPoint, LaneAccess, LaneMaterial, MarkLine, PlanSample, RoadInfo, RoadLink, RoadMark,
+    RoadNetwork, RoadSegment, TrafficRule,
+};
+
+// Bumped whenever the file layout or the network model changes.
+const FORMAT: u32 = 12;
+const MAGIC: &[u8; 4] = b"RSNW";
+
+// Signal kinds by their number in the file.
+const SIGNAL_KINDS: [SignalKind; 6] = [
+    SignalKind::Stop,
+    SignalKind::Yield,
+    SignalKind::SpeedLimit,
+    SignalKind::TrafficLight,
+    SignalKind::Other,
+    SignalKind::Object,
+];
+
+// Writes a network to a compiled file.
+pub fn write_file(network: &RoadNetwork, path: &Path) -> Result<(), String> {
+    std::fs::write(path, encode(network)).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+// Reads a compiled file.
+pub fn read_file(path: &Path) -> Result<RoadNetwork, String> {
+    let source = XmlSource::File {
+        path: path.to_path_buf(),
+        modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+    };
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    decode(&bytes, Some(&Arc::new(source)))
+}
+
+// Appends little-endian values to a byte buffer.
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn text(&mut self, text: &str) {
+        self.len(text.len());
+        self.0.extend_from_slice(text.as_bytes());
+    }
+
+    fn point(&mut self, p: DVec3) {
+        for v in p.to_array() {
+            self.f64(v);
+        }
+    }
+
+    fn points(&mut self, points: &[DVec3]) {
+        self.len(points.len());
+        for p in points {
+            self.point(*p);
+        }
+    }
+
+    fn optional_f64(&mut self, v: Option<f64>) {
+        self.u8(v.is_some() as u8);
+        if let Some(v) = v {
+            self.f64(v);
+        }
+    }
+
+    fn optional_i32(&mut self, v: Option<i32>) {
+        self.u8(v.is_some() as u8);
+        if let Some(v) = v {
+            self.i32(v);
+        }
+    }
+
+    fn materials(&mut self, materials: &[LaneMaterial]) {
+        self.len(materials.len());
+        for material in materials {
+            self.f64(material.s_offset);
+            self.text(&material.surface);
+            self.f64(material.friction);
+            self.f64(material.roughness);
+        }
+    }
+
+    fn road_marks(&mut self, marks: &[RoadMark]) {
+        self.len(marks.len());
+        for mark in marks {
+            self.road_mark(mark);
+        }
+    }
+
+    fn road_mark(&mut self, mark: &RoadMark) {
+        self.f64(mark.s_offset);
+        self.text(&mark.kind);
+        self.text(&mark.color);
+        self.text(&mark.weight);
+        self.text(&mark.material);
+        self.optional_f64(mark.width);
+        self.len(mark.lines.len());
+        for line in &mark.lines {
+            for v in [line.length, line.space, line.t_offset, line.s_offset] {
+                self.f64(v);
+            }
+            self.optional_f64(line.width);
+            self.u8(line.color.is_some() as u8);
+            self.text(line.color.as_deref().unwrap_or_default());
+        }
+        self.text(&mark.type_name);
+        self.optional_f64(mark.type_width);
+    }
+
+    fn cubics(&mut self, records: &[Cubic]) {
+        self.len(records.len());
+        for record in records {
+            for v in [record.s, record.a, record.b, record.c, record.d] {
+                self.f64(v);
+            }
+        }
+    }
+
+    // Plan view records are stored as a shape number and the shape's
+    // parameters.
+    fn reference(&mut self, line: Option<&ReferenceLine>) {
+        self.u8(line.is_some() as u8);
+        let Some(line) = line else {
+            return;
+        };
+        self.len(line.geometry.len());
+        for record in &line.geometry {
+            for v in [record.s, record.x, record.y, record.hdg, record.length] {
+                self.f64(v);
+            }
+            let (shape, parameters) = match record.shape {
+                Shape::Line => (0, Vec::new()),
+                Shape::Arc { curvature } => (1, vec![curvature]),
+                Shape::Spiral { start, end } => (2, vec![start, end]),
+                Shape::Poly3 { v } => (3, v.to_vec()),
+                Shape::ParamPoly3 {
+                    u,
+                    v,
+                    normalized: false,
+                } => (4, [u, v].concat()),
+                Shape::ParamPoly3 {
+                    u,
+                    v,
+                    normalized: true,
+                } => (5, [u, v].concat()),
+            };
+            self.u8(shape);
+            for v in parameters {
+                self.f64(v);
+            }
+        }
+        self.cubics(&line.elevation);
+        self.cubics(&line.lane_offset);
+    }
+}
+
+fn encode(network: &RoadNetwork) -> Vec<u8> {
+    let mut out = Writer(Vec::new());
+    out.0.extend_from_slice(MAGIC);
+    out.u32(FORMAT);
+    out.text(&network.name);
+
+    out.len(network.segments.len());
+    for lane in &network.segments {
+        out.point(lane.start_pos);
+        out.point(lane.end_pos);
+        out.f64(lane.start_s);
+        out.f64(lane.end_s);
+        out.f64(lane.width);
+        out.points(&lane.left_side);
+        out.points(&lane.right_side);
+        out.u32(lane.road_id);
+        out.i32(lane.lane_id);
+        out.u32(lane.lane_section_id);
+        out.u8((lane.rule == TrafficRule::LeftHand) as u8);
+        out.text(&lane.lane_type);
+        out.text(&lane.road_type);
+        out.optional_f64(lane.speed);
+        out.materials(&lane.materials);
+        out.len(lane.access.len());
+        for rule in &lane.access {
+            out.u8(rule.allow as u8);
+            out.text(&rule.restriction);
+        }
+        out.road_marks(&lane.road_marks);
+        out.road_marks(&lane.center_marks);
+        out.optional_i32(lane.predecessor);
+        out.optional_i32(lane.successor);
+    }
+
+    out.len(network.links.len());
+    for link in &network.links {
+        out.u32(link.road_id);
+        out.u8((link.contact == ContactPoint::End) as u8);
+        out.u32(link.other_road_id);
+        out.u8((link.other_contact == ContactPoint::End) as u8);
+    }
+
+    out.len(network.signals.len());
+    for signal in &network.signals {
+        out.text(&signal.id);
+        out.text(&signal.name);
+        out.u32(signal.road_id);
+        for v in [
+            signal.s,
+            signal.t,
+            signal.z_offset,
+            signal.height,
+            signal.width,
+            signal.length,
+            signal.radius,
+        ] {
+            out.f64(v);
+        }
+        out.text(&signal.orientation);
+        let kind = SIGNAL_KINDS.iter().position(|k| *k == signal.kind);
+        out.u8(kind.unwrap_or(0) as u8);
+        out.text(&signal.type_code);
+        out.text(&signal.subtype);
+        out.text(&signal.country);
+        out.u8(signal.dynamic as u8);
+        out.optional_f64(signal.value);
+        out.text(&signal.unit);
+        out.point(signal.position);
+        out.len(signal.repeats.len());
+        for repeat in &signal.repeats {
+            for v in [
+                repeat.s,
+                repeat.length,
+                repeat.distance,
+                repeat.t_start,
+                repeat.t_end,
+                repeat.z_offset_start,
+                repeat.z_offset_end,
+                repeat.height_start,
+                repeat.height_end,
+                repeat.width_start,
+                repeat.width_end,
+            ] {
+                out.f64(v);
+            }
+        }
+        out.text(&signal.xml.text().unwrap_or_default());
+    }
+
+    out.len(network.roads.len());
+    for (road_id, info) in &network.roads {
+        out.u32(*road_id);
+        out.text(&info.name);
+        out.len(info.plan_view.len());
+        for sample in &info.plan_view {
+            out.f64(sample.s);
+            out.f64(sample.heading);
+            out.f64(sample.curvature);
+        }
+        out.reference(info.reference.as_ref());
+        out.u8(info.junction.is_some() as u8);
+        out.u32(info.junction.unwrap_or(0));
+        out.text(&info.xml.text().unwrap_or_default());
+    }
+
+    out.len(network.junctions.len());
+    for (junction, xml) in &network.junctions {
+        out.u32(*junction);
+        out.text(&xml.text().unwrap_or_default());
+    }
+
+    // The georeference as its PROJ string, empty if there is none.
+    out.text(network.geo.as_ref().map_or("", |geo| geo.proj.as_str()));
+    out.0
+}
+
+// The records of a compiled network, read with the shared byte reader.
+impl Reader<'_> {
+    // The XML of an element, in `whole`. With a file to read it back from
+    // it is kept as its place there rather than read now.
+    fn element(
+        &mut self,
+        whole: &[u8],
+        source: Option<&Arc<XmlSource>>,
+    ) -> Result<ElementXml, String> {
+        let Some(source) = source else {
+            return self.text().map(ElementXml::from);
+        };
+        let len = self.len()?;
+        let start = whole.len() - self.rest().len();
+        std::str::from_utf8(self.bytes(len)?).map_err(|e| e.to_string())?;
+        Ok(if len == 0 {
+            ElementXml::None
+        } else {
+            ElementXml::Span(source.clone(), start..start + len)
+        })
+    }
+
+    fn point(&mut self) -> Result<DVec3, String> {
+        Ok(DVec3::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+
+    fn points(&mut self) -> Result<Vec<DVec3>, String> {
+        (0..self.len()?).map(|_| self.point()).collect()
+    }
+
+    fn optional_f64(&mut self) -> Result<Option<f64>, String> {
+        Ok(if self.flag()? {
+            Some(self.f64()?)
+        } else {
+            None
+        })
+    }
+
+    fn optional_i32(&mut self) -> Result<Option<i32>, String> {
+        Ok(if self.flag()? {
+            Some(self.i32()?)
+        } else {
+            None
+        })
+    }
+
+    fn materials(&mut self) -> Result<Vec<LaneMaterial>, String> {
+        (0..self.len()?)
+            .map(|_| {
+                Ok(LaneMaterial {
+                    s_offset: self.f64()?,
+                    surface: self.text()?,
+                    friction: self.f64()?,
+                    roughness: self.f64()?,
+                })
+            })
+            .collect()
+    }
+
+    fn road_marks(&mut self) -> Result<Vec<RoadMark>, String> {
+        (0..self.len()?).map(|_| self.road_mark()).collect()
+    }
+
+    fn road_mark(&mut self) -> Result<RoadMark, String> {
+        let s_offset = self.f64()?;
+        let (kind, color, weight, material) =
+            (self.text()?, self.text()?, self.text()?, self.text()?);
+        let width = self.optional_f64()?;
+        let lines = (0..self.len()?)
+            .map(|_| {
+                let [length, space, t_offset, s_offset] =
+                    [self.f64()?, self.f64()?, self.f64()?, self.f64()?];
+                let width = self.optional_f64()?;
+                let has_color = self.flag()?;
+                let color = self.text()?;
+                Ok(MarkLine {
+                    length,
+                    space,
+                    t_offset,
+                    s_offset,
+                    width,
+                    color: has_color.then_some(color),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(RoadMark {
+            s_offset,
+            kind,
+            color,
+            weight,
+            material,
+            width,
+            lines,
+            type_name: self.text()?,
+            type_width: self.optional_f64()?,
+        })
+    }
+
+    fn four(&mut self) -> Result<[f64; 4], String> {
+        Ok([self.f64()?, self.f64()?, self.f64()?, self.f64()?])
+    }
+
+    fn cubics(&mut self) -> Result<Vec<Cubic>, String> {
+        (0..self.len()?)
+            .map(|_| {
+                Ok(Cubic {
+                    s: self.f64()?,
+                    a: self.f64()?,
+                    b: self.f64()?,
+                    c: self.f64()?,
+                    d: self.f64()?,
+                })
+            })
+            .collect()
+    }
+
+    fn reference(&mut self) -> Result<Option<ReferenceLine>, String> {
+        if !self.flag()? {
+            return Ok(None);
+        }
+        let geometry = (0..self.len()?)
+            .map(|_| {
+                let [s, x, y, hdg, length] = [
+                    self.f64()?,
+                    self.f64()?,
+                    self.f64()?,
+                    self.f64()?,
+                    self.f64()?,
+                ];
+                let shape = match self.u8()? {
+                    0 => Shape::Line,
+                    1 => Shape::Arc {
+                        curvature: self.f64()?,
+                    },
+                    2 => Shape::Spiral {
+                        start: self.f64()?,
+                        end: self.f64()?,
+                    },
+                    3 => Shape::Poly3 { v: self.four()? },
+                    shape @ (4 | 5) => Shape::ParamPoly3 {
+                        u: self.four()?,
+                        v: self.four()?,
+                        normalized: shape == 5,
+                    },
+                    shape => return Err(format!("unknown plan view shape {shape}")),
+                };
+                Ok(PlanRecord {
+                    s,
+                    x,
+                    y,
+                    hdg,
+                    length,
+                    shape,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Some(ReferenceLine {
+            geometry,
+            elevation: self.cubics()?,
+            lane_offset: self.cubics()?,
+        }))
+    }
+}
+
+fn decode(bytes: &[u8], source: Option<&Arc<XmlSource>>) -> Result<RoadNetwork, String> {
+    let mut reader = Reader::new(bytes, "compiled network");
+    if reader.take::<4>().ok().as_ref() != Some(MAGIC) {
+        return Err("not a compiled network".to_string());
+    }
+    let format = reader.u32()?;
+    if format != FORMAT {
+        return Err(format!(
+            "compiled with format {format}, this version reads {FORMAT}; compile the map again"
+        ));
+    }
+    let contact = |end: bool| {
+        if end {
+            ContactPoint::End
+        } else {
+            ContactPoint::Start
+        }
+    };
+    let mut network = RoadNetwork {
+        name: reader.text()?,
+        ..RoadNetwork::default()
+    };
+
+    for _ in 0..reader.len()? {
+        let start_pos = reader.point()?;
+        let end_pos = reader.point()?;
+        let (start_s, end_s, width) = (reader.f64()?, reader.f64()?, reader.f64()?);
+        let left_side = reader.points()?;
+        let right_side = reader.points()?;
+        let road_id = reader.u32()?;
+        let lane_id = reader.i32()?;
+        let lane_section_id = reader.u32()?;
+        let rule = if reader.flag()? {
+            TrafficRule::LeftHand
+        } else {
+            TrafficRule::RightHand
+        };
+        let lane_type = reader.text()?;
+        let road_type = reader.text()?;
+        let speed = reader.optional_f64()?;
+        let materials = reader.materials()?;
+        let access = (0..reader.len()?)
+            .map(|_| {
+                Ok(LaneAccess {
+                    allow: reader.flag()?,
+                    restriction: reader.text()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let road_marks = reader.road_marks()?;
+        let center_marks = reader.road_marks()?;
+        let (predecessor, successor) = (reader.optional_i32()?, reader.optional_i32()?);
+        network.segments.push(RoadSegment {
+            start_pos,
+            end_pos,
+            start_s,
+            end_s,
+            width,
+            left_side,
+            right_side,
+            road_id,
+            lane_id,
+            lane_section_id,
+            rule,
+            lane_type,
+            road_type,
+            speed,
+            materials,
+            access,
+            road_marks,
+            center_marks,
+            predecessor,
+            successor,
+        });
+    }
+
+    for _ in 0..reader.len()? {
+        network.links.push(RoadLink {
+            road_id: reader.u32()?,
+            contact: contact(reader.flag()?),
+            other_road_id: reader.u32()?,
+            other_contact: contact(reader.flag()?),
+        });
+    }
+
+    for _ in 0..reader.len()? {
+        let id = reader.text()?;
+        let name = reader.text()?;
+        let road_id = reader.u32()?;
+        let [s, t, z_offset, height, width, length, radius] = [
+            reader.f64()?,
+            reader.f64()?,
+            reader.f64()?,
+            reader.f64()?,
+            reader.f64()?,
+            reader.f64()?,
+            reader.f64()?,
+        ];
+        let orientation = reader.text()?;
+        let kind = *SIGNAL_KINDS
+            .get(reader.u8()? as usize)
+            .ok_or("compiled network has an unknown signal kind")?;
+        network.signals.push(Signal {
+            id,
+            name,
+            road_id,
+            s,
+            t,
+            z_offset,
+            height,
+            width,
+            length,
+            radius,
+            orientation,
+            kind,
+            type_code: reader.text()?,
+            subtype: reader.text()?,
+            country: reader.text()?,
+            dynamic: reader.flag()?,
+            value: reader.optional_f64()?,
+            unit: reader.text()?,
+            position: reader.point()?,
+            repeats: (0..reader.len()?)
+                .map(|_| {
+                    Ok(ObjectRepeat {
+                        s: reader.f64()?,
+                        length: reader.f64()?,
+                        distance: reader.f64()?,
+                        t_start: reader.f64()?,
+                        t_end: reader.f64()?,
+                        z_offset_start: reader.f64()?,
+                        z_offset_end: reader.f64()?,
+                        height_start: reader.f64()?,
+                        height_end: reader.f64()?,
+                        width_start: reader.f64()?,
+                        width_end: reader.f64()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+            xml: reader.element(bytes, source)?,
+        });
+    }
+
+    for _ in 0..reader.len()? {
+        let road_id = reader.u32()?;
+        let name = reader.text()?;
+        let plan_view = (0..reader.len()?)
+            .map(|_| {
+                Ok(PlanSample {
+                    s: reader.f64()?,
+                    heading: reader.f64()?,
+                    curvature: reader.f64()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let reference = reader.reference()?;
+        let in_junction = reader.flag()?;
+        let junction = reader.u32()?;
+        let info = RoadInfo {
+            name,
+            plan_view,
+            reference,
+            junction: in_junction.then_some(junction),
+            xml: reader.element(bytes, source)?,
+        };
+        network.roads.insert(road_id, info);
+    }
+
+    for _ in 0..reader.len()? {
+        let junction = reader.u32()?;
+        network.junctions.insert(junction, reader.text()?.into());
+    }
+
+    let proj = reader.text()?;
+    if !proj.is_empty() {
+        network.geo = Some(GeoReference::parse(&proj)?);
+    }
+
+    if !reader.rest().is_empty() {
+        return Err("compiled network has trailing data".to_string());
+    }
+    Ok(network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_network;
+    use crate::sample_maps::{load, temp_dir, SAMPLES};
+
+    #[test]
+    fn samples_survive_compiling() {
+        for name in SAMPLES {
+            let network = load(name);
+            let path = temp_dir("samples_survive_compiling").join(format!("{name}.rsnet"));
+            write_file(&network, &path).unwrap();
+            let read = load_network(Some(&path)).unwrap_or_else(|e| panic!("{e}"));
+            assert_eq!(read.segments, network.segments, "{name}");
+            assert_eq!(read.links, network.links, "{name}");
+            assert_eq!(read.signals, network.signals, "{name}");
+            assert_eq!(read.junctions, network.junctions, "{name}");
+            assert_eq!(
+                read.roads.keys().collect::<Vec<_>>(),
+                network.roads.keys().collect::<Vec<_>>()
+            );
+            for (read, road) in read.roads.values().zip(network.roads.values()) {
+                assert_eq!(read.name, road.name, "{name}");
+                assert_eq!(read.plan_view, road.plan_view, "{name}");
+                assert_eq!(read.junction, road.junction, "{name}");
+                // Read back from the compiled file.
+                assert_eq!(read.xml, road.xml, "{name}");
+            }
+            let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// Compressed map files.
+//
+// Maps are often distributed compressed. A map named `<name>.xodr.gz` or
+// `<name>.xodr.zst` (likewise `.osm`) is decompressed in memory and read as
+// the map inside, and a `.zip` archive is read as the one map it holds.
+// Archives are read from the central directory, with stored and deflated
+// entries; multi-part and ZIP64 archives are not supported.
+
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Zip,
+}
+
+impl Compression {
+    // The compression of a file, by its extension; none for a plain file.
+    pub fn of(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" => Some(Self::Gzip),
+            "zst" => Some(Self::Zstd),
+            "zip" => Some(Self::Zip),
+            _ => None,
+        }
+    }
+}
+
+// Whether a file is an OpenDRIVE map, compressed or not. Zip archives are
+// not looked into.
+pub fn is_xodr(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    [".xodr", ".xodr.gz", ".xodr.zst"]
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+}
+
+// Decompresses a map file. Returns the name of the map inside, which tells
+// its format, and its text.
+pub fn read_map(path: &Path, compression: Compression) -> Result<(String, String), String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let (name, data) = match compression {
+        Compression::Gzip | Compression::Zstd => {
+            let name = path
+                .file_stem()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            let mut data = Vec::new();
+            let read = if compression == Compression::Gzip {
+                MultiGzDecoder::new(bytes.as_slice()).read_to_end(&mut data)
+            } else {
+                ruzstd::StreamingDecoder::new(bytes.as_slice())
+                    .map_err(|e| e.to_string())?
+                    .read_to_end(&mut data)
+            };
+            read.map_err(|e| e.to_string())?;
+            (name, data)
+        }
+        Compression::Zip => unzip_map(&bytes)?,
+    };
+    let text = String::from_utf8(data).map_err(|e| e.to_string())?;
+    Ok((name, text))
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 2)?;
+    Some(u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<usize> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+// Extracts the one map in a zip archive.
+fn unzip_map(bytes: &[u8]) -> Result<(String, Vec<u8>), String> {
+    let corrupt = || "not a zip archive, or a damaged one".to_string();
+    // The end of central directory record sits at the end, followed only by
+    // an optional comment.
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|&at| u32_at(bytes, at) == Some(0x0605_4b50))
+        .ok_or_else(corrupt)?;
+    let count = u16_at(bytes, end + 10).ok_or_else(corrupt)?;
+    let mut at = u32_at(bytes, end + 16).ok_or_else(corrupt)?;
+
+    let mut maps = Vec::new();
+    for _ in 0..count {
+        if u32_at(bytes, at) != Some(0x0201_4b50) {
+            return Err(corrupt());
+        }
+        let field = |offset| u16_at(bytes, at + offset).ok_or_else(corrupt);
+        let (name_length, extra_length, comment_length) = (field(28)?, field(30)?, field(32)?);
+        let name = bytes
+            .get(at + 46..at + 46 + name_length)
+            .ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let lower = name.to_ascii_lowercase();
+        if lower.ends_with(".xodr") || lower.ends_with(".osm") {
+            maps.push((name, at));
+        }
+        at += 46 + name_length + extra_length + comment_length;
+    }
+    let (name, entry) = match maps.as_slice() {
+        [map] => map.clone(),
+        [] => return Err("the archive holds no .xodr or .osm map".to_string()),
+        _ => {
+            return Err(format!(
+                "the archive holds {} maps; expected one",
+                maps.len()
+            ))
+        }
+    };
+
+    let method = u16_at(bytes, entry + 10).ok_or_else(corrupt)?;
+    let size = u32_at(bytes, entry + 20).ok_or_else(corrupt)?;
+    let local = u32_at(bytes, entry + 42).ok_or_else(corrupt)?;
+    if u32_at(bytes, local) != Some(0x0403_4b50) || size == u32::MAX as usize {
+        return Err(corrupt());
+    }
+    let start = local
+        + 30
+        + u16_at(bytes, local + 26).ok_or_else(corrupt)?
+        + u16_at(bytes, local + 28).ok_or_else(corrupt)?;
+    let data = bytes.get(start..start + size).ok_or_else(corrupt)?;
+    let data = match method {
+        0 => data.to_vec(),
+        8 => {
+            let mut out = Vec::new();
+            DeflateDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("{name}: {e}"))?;
+            out
+        }
+        _ => {
+            return Err(format!(
+                "{name}: unsupported zip compression method {method}"
+            ))
+        }
+    };
+    Ok((name, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::DeflateEncoder;
+
+    use super::*;
+
+    // A zip archive of the given files, each stored or deflated. Checksums
+    // are left at zero; they are not checked.
+    fn zip(files: &[(&str, &str, bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for &(name, text, deflate) in files {
+            let data = if deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(text.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                text.as_bytes().to_vec()
+            };
+            let method: u16 = if deflate { 8 } else { 0 };
+            let sizes = [
+                (data.len() as u32).to_le_bytes(),
+                (text.len() as u32).to_le_bytes(),
+            ];
+            let offset = out.len() as u32;
+            out.extend(0x0403_4b50u32.to_le_bytes());
+            out.extend([20, 0, 0, 0]);
+            out.extend(method.to_le_bytes());
+            out.extend([0; 8]);
+            out.extend(sizes.concat());
+            out.extend((name.len() as u16).to_le_bytes());
+            out.extend([0, 0]);
+            out.extend(name.as_bytes());
+            out.extend(&data);
+
+            central.extend(0x0201_4b50u32.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 8]);
+            central.extend(sizes.concat());
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let directory = out.len() as u32;
+        out.extend(&central);
+        out.extend(0x0605_4b50u32.to_le_bytes());
+        out.extend([0; 4]);
+        out.extend((files.len() as u16).to_le_bytes().repeat(2));
+        out.extend((central.len() as u32).to_le_bytes());
+        out.extend(directory.to_le_bytes());
+        out.extend([0, 0]);
+        out
+    }
+
+    const MAP: &str = "<OpenDRIVE><header/></OpenDRIVE>";
+
+    #[test]
+    fn stored_and_deflated_maps_are_extracted() {
+        for deflate in [false, true] {
+            let archive = zip(&[("readme.txt", "hello", false), ("town.xodr", MAP, deflate)]);
+            let (name, data) = unzip_map(&archive).unwrap();
+            assert_eq!(name, "town.xodr");
+            assert_eq!(data, MAP.as_bytes());
+        }
+    }
+
+    #[test]
+    fn archives_need_exactly_one_map() {
+        let two = zip(&[("a.xodr", MAP, false), ("b.XODR", MAP, true)]);
+        assert_eq!(
+            unzip_map(&two),
+            Err("the archive holds 2 maps; expected one".to_string())
+        );
+        let none = zip(&[("readme.txt", "hello", false)]);
+        assert!(unzip_map(&none)
+            .unwrap_err()
+            .contains("no .xodr or .osm map"));
+    }
+
+    #[test]
+    fn damaged_archives_are_refused() {
+        let archive = zip(&[("town.xodr", MAP, true)]);
+        let damaged = |bytes: &[u8]| {
+            unzip_map(bytes).is_err_and(|e| e.contains("damaged") || e.contains("town.xodr"))
+        };
+        // Cut anywhere, the archive loses its directory or its data.
+        for length in [0, 10, archive.len() / 2, archive.len() - 1] {
+            assert!(damaged(&archive[..length]), "cut at {length}");
+        }
+        // The directory pointing past the end, the entry's data past the end,
+        // and the entry's local header elsewhere.
+        let directory = archive.len() - 22;
+        let entry = u32_at(&archive, directory + 16).unwrap();
+        for (at, value) in [
+            (directory + 16, u32::MAX),
+            (entry + 20, 1 << 20),
+            (entry + 42, 7),
+        ] {
+            let mut bad = archive.clone();
+            bad[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            assert!(damaged(&bad), "{at} = {value}");
+        }
+    }
+}
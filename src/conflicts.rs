@@ -0,0 +1,373 @@
+// Conflict points and stop lines at junctions.
+//
+// Scenario tooling needs to know where the paths through a junction meet.
+// Every lane of a connecting road is a path, followed in its direction of
+// travel. Two paths cross where their centerlines intersect away from
+// their ends, and merge where they run into the same lane: the conflict
+// point is then where they come closer than their half widths, before the
+// end they share. Paths at different levels (more than `LEVEL_GAP` apart
+// in height) do not conflict.
+//
+// A stop line lies across every lane that leads into a junction, at the
+// end where it meets a connecting road. Maps that mark stop lines as
+// signals are not consulted; the line is always at the lane end.
+//
+// The junction overlay (see `junction_overlay`) draws both, and the
+// console's `conflicts <junction>` lists them.
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::{RoadNetwork, RoadSegment};
+
+// Path ends closer than this, in meters, are the same point.
+pub const END_TOLERANCE: f64 = 0.5;
+
+// Paths further apart in height than this, in meters, do not conflict.
+const LEVEL_GAP: f64 = 2.0;
+
+// The lane types vehicles follow through a junction.
+const PATH_TYPES: [&str; 2] = ["driving", "bidirectional"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    Crossing,
+    Merging,
+}
+
+// A point where two paths through a junction meet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConflictPoint {
+    pub junction: u32,
+    pub kind: ConflictKind,
+    // Viewer frame.
+    pub position: DVec3,
+    // The two paths, as indices into `RoadNetwork::segments`, and the
+    // distance along each to the point, in meters.
+    pub paths: [usize; 2],
+    pub along: [f64; 2],
+}
+
+// The stop line of a lane entering a junction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StopLine {
+    pub junction: u32,
+    // The entering lane, as an index into `RoadNetwork::segments`.
+    pub segment: usize,
+    // The ends of the line on the lane boundaries, viewer frame.
+    pub left: DVec3,
+    pub right: DVec3,
+}
+
+// A lane's centerline in its direction of travel.
+pub fn path(lane: &RoadSegment) -> Vec<DVec3> {
+    let mut points = lane.centerline();
+    if !lane.follows_reference() {
+        points.reverse();
+    }
+    points
+}
+
+fn flat(p: DVec3) -> DVec2 {
+    DVec2::new(p.x, -p.z)
+}
+
+fn bounds(points: &[DVec3]) -> (DVec2, DVec2) {
+    points.iter().fold(
+        (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+        |(min, max), p| (min.min(flat(*p)), max.max(flat(*p))),
+    )
+}
+
+// Cumulative lengths along a polyline.
+fn stations(points: &[DVec3]) -> Vec<f64> {
+    let mut along = vec![0.0];
+    for pair in points.windows(2) {
+        along.push(along.last().unwrap() + pair[0].distance(pair[1]));
+    }
+    along
+}
+
+// The junction of a road, if it is a connecting road.
+fn junction_of(network: &RoadNetwork, road_id: u32) -> Option<u32> {
+    network.roads.get(&road_id).and_then(|info| info.junction)
+}
+
+// The paths through a junction: its connecting roads' driving lanes.
+pub fn paths(network: &RoadNetwork, junction: u32) -> Vec<usize> {
+    (0..network.segments.len())
+        .filter(|&i| {
+            let lane = &network.segments[i];
+            junction_of(network, lane.road_id) == Some(junction)
+                && PATH_TYPES.contains(&lane.lane_type.as_str())
+        })
+        .collect()
+}
+
+// The conflict points between the paths through a junction.
+pub fn conflict_points(network: &RoadNetwork, junction: u32) -> Vec<ConflictPoint> {
+    let paths = paths(network, junction);
+    let lines: Vec<Vec<DVec3>> = paths.iter().map(|&i| path(&network.segments[i])).collect();
+    let boxes: Vec<(DVec2, DVec2)> = lines.iter().map(|line| bounds(line)).collect();
+    let mut conflicts = Vec::new();
+    for a in 0..paths.len() {
+        for b in a + 1..paths.len() {
+            let (la, lb) = (&lines[a], &lines[b]);
+            if la.len() < 2 || lb.len() < 2 {
+                continue;
+            }
+            let overlap = boxes[a].0.cmple(boxes[b].1).all() && boxes[b].0.cmple(boxes[a].1).all();
+            if !overlap {
+                continue;
+            }
+            let ends_together = la.last().unwrap().distance(*lb.last().unwrap()) < END_TOLERANCE;
+            let starts_together = la[0].distance(lb[0]) < END_TOLERANCE;
+            let conflict = if ends_together {
+                let widths =
+                    (network.segments[paths[a]].width + network.segments[paths[b]].width) / 2.0;
+                merge_point(la, lb, widths)
+                    .map(|(position, along)| (ConflictKind::Merging, position, along))
+            } else {
+                crossing(la, lb, starts_together)
+                    .map(|(position, along)| (ConflictKind::Crossing, position, along))
+            };
+            if let Some((kind, position, along)) = conflict {
+                conflicts.push(ConflictPoint {
+                    junction,
+                    kind,
+                    position,
+                    paths: [paths[a], paths[b]],
+                    along,
+                });
+            }
+        }
+    }
+    conflicts
+}
+
+// Where two paths ending in the same lane come within `gap` of each other:
+// the first point of either that is that close to the other.
+fn merge_point(a: &[DVec3], b: &[DVec3], gap: f64) -> Option<(DVec3, [f64; 2])> {
+    let (sa, sb) = (stations(a), stations(b));
+    let nearest = |p: DVec3, line: &[DVec3], along: &[f64]| {
+        let mut best = (f64::INFINITY, 0.0);
+        for (k, pair) in line.windows(2).enumerate() {
+            let (foot, t) = foot(flat(p), flat(pair[0]), flat(pair[1]));
+            let distance = flat(p).distance(foot);
+            if distance < best.0 {
+                best = (distance, along[k] + (along[k + 1] - along[k]) * t);
+            }
+        }
+        best
+    };
+    let first_close = |line: &[DVec3], along: &[f64], other: &[DVec3], other_along: &[f64]| {
+        line.iter().enumerate().find_map(|(k, p)| {
+            let (distance, at) = nearest(*p, other, other_along);
+            (distance < gap).then_some((*p, along[k], at))
+        })
+    };
+    let from_a = first_close(a, &sa, b, &sb);
+    let from_b = first_close(b, &sb, a, &sa);
+    match (from_a, from_b) {
+        // The point further from the shared end is where the paths meet.
+        (Some((pa, a_at, b_at)), Some((pb, b_own, a_other))) => {
+            if sa.last().unwrap() - a_at >= sb.last().unwrap() - b_own {
+                Some((pa, [a_at, b_at]))
+            } else {
+                Some((pb, [a_other, b_own]))
+            }
+        }
+        (Some((p, a_at, b_at)), None) => Some((p, [a_at, b_at])),
+        (None, Some((p, b_at, a_at))) => Some((p, [a_at, b_at])),
+        (None, None) => None,
+    }
+}
+
+// The foot of a point on a piece, and its fraction along the piece.
+fn foot(p: DVec2, a: DVec2, b: DVec2) -> (DVec2, f64) {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t, t)
+}
+
+// The first intersection of two paths in the plan view, skipping their
+// common start if they diverge from one.
+fn crossing(a: &[DVec3], b: &[DVec3], starts_together: bool) -> Option<(DVec3, [f64; 2])> {
+    let (sa, sb) = (stations(a), stations(b));
+    for (i, pa) in a.windows(2).enumerate() {
+        for (j, pb) in b.windows(2).enumerate() {
+            let Some((t, u)) = intersect(flat(pa[0]), flat(pa[1]), flat(pb[0]), flat(pb[1])) else {
+                continue;
+            };
+            let (at_a, at_b) = (
+                sa[i] + (sa[i + 1] - sa[i]) * t,
+                sb[j] + (sb[j + 1] - sb[j]) * u,
+            );
+            if starts_together && at_a < END_TOLERANCE && at_b < END_TOLERANCE {
+                continue;
+            }
+            let (p, q) = (pa[0].lerp(pa[1], t), pb[0].lerp(pb[1], u));
+            if (p.y - q.y).abs() > LEVEL_GAP {
+                continue;
+            }
+            return Some((p, [at_a, at_b]));
+        }
+    }
+    None
+}
+
+// The fractions along two pieces where they intersect.
+fn intersect(a: DVec2, b: DVec2, c: DVec2, d: DVec2) -> Option<(f64, f64)> {
+    let (r, s) = (b - a, d - c);
+    let denominator = r.perp_dot(s);
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = (c - a).perp_dot(s) / denominator;
+    let u = (c - a).perp_dot(r) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some((t, u))
+}
+
+// The stop lines of the lanes entering a junction.
+pub fn stop_lines(network: &RoadNetwork, junction: u32) -> Vec<StopLine> {
+    let entries: Vec<DVec3> = paths(network, junction)
+        .into_iter()
+        .filter_map(|i| path(&network.segments[i]).first().copied())
+        .collect();
+    network
+        .segments
+        .iter()
+        .enumerate()
+        .filter(|(_, lane)| {
+            junction_of(network, lane.road_id).is_none()
+                && PATH_TYPES.contains(&lane.lane_type.as_str())
+        })
+        .filter_map(|(index, lane)| {
+            let end = *path(lane).last()?;
+            if !entries
+                .iter()
+                .any(|entry| entry.distance(end) < END_TOLERANCE)
+            {
+                return None;
+            }
+            let (left, right) = if lane.follows_reference() {
+                (lane.left_side.last()?, lane.right_side.last()?)
+            } else {
+                (lane.left_side.first()?, lane.right_side.first()?)
+            };
+            Some(StopLine {
+                junction,
+                segment: index,
+                left: *left,
+                right: *right,
+            })
+        })
+        .collect()
+}
+
+// The junctions of a network, by ID.
+pub fn junctions(network: &RoadNetwork) -> Vec<u32> {
+    let mut ids: Vec<u32> = network
+        .roads
+        .values()
+        .filter_map(|info| info.junction)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_near, text, TOLERANCE};
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    // A connecting road of junction 100 with one lane, driven along the
+    // reference line from (x, y) at heading `hdg`.
+    fn connecting(id: u32, x: f64, y: f64, hdg: f64, length: f64) -> String {
+        format!(
+            r#"<road length="{length}" id="{id}" junction="100">
+              <planView>
+                <geometry s="0" x="{x}" y="{y}" hdg="{hdg}" length="{length}"><line/></geometry>
+              </planView>
+              <lanes>
+                <laneSection s="0">
+                  <center><lane id="0" type="none"/></center>
+                  <right>
+                    <lane id="-1" type="driving"><width sOffset="0" a="3.5" b="0" c="0" d="0"/></lane>
+                  </right>
+                </laneSection>
+              </lanes>
+            </road>"#
+        )
+    }
+
+    // The junction sample with two more paths: road 4 comes in from the
+    // south-west and merges into the end of the through lane, and road 5
+    // runs north across both.
+    fn busy_junction() -> RoadNetwork {
+        // Road 4 heads along (0.8, 0.6), so its lane ends 1.75 m to the
+        // right of the reference line at (58.95, -0.35): at (60, -1.75).
+        let merging = connecting(4, 50.95, -6.35, 0.6f64.atan2(0.8), 10.0);
+        let crossing = connecting(5, 55.0, -10.0, std::f64::consts::FRAC_PI_2, 20.0);
+        let xml =
+            text("junction.xodr").replace("<junction ", &format!("{merging}{crossing}<junction "));
+        xodr::read_str(&xml, &LoadTransform::default()).unwrap()
+    }
+
+    #[test]
+    fn paths_through_a_junction_cross_and_merge() {
+        let network = busy_junction();
+        let [through, merging, crossing] =
+            [3, 4, 5].map(|road| network.find_segment(road, 1, -1).unwrap());
+        let conflicts = conflict_points(&network, 100);
+
+        let merges: Vec<&ConflictPoint> = conflicts
+            .iter()
+            .filter(|c| c.kind == ConflictKind::Merging)
+            .collect();
+        assert_eq!(merges.len(), 1, "{conflicts:?}");
+        assert_eq!(merges[0].paths, [through, merging]);
+        // The lanes come within their half widths of each other before the
+        // end they share.
+        let position = flat(merges[0].position);
+        assert!(position.x < 60.0 && position.x > 50.0, "{position}");
+        assert!(merges[0].along[0] < 10.0 && merges[0].along[1] < 10.0);
+
+        let across = conflicts
+            .iter()
+            .find(|c| c.kind == ConflictKind::Crossing && c.paths == [through, crossing])
+            .expect("no crossing of the through lane");
+        let position = flat(across.position);
+        assert_near(position.x, 56.75, TOLERANCE);
+        assert_near(position.y, -1.75, TOLERANCE);
+        assert_near(across.along[0], 6.75, TOLERANCE);
+        assert_near(across.along[1], 8.25, TOLERANCE);
+        assert_eq!(conflicts.len(), 3, "{conflicts:?}");
+    }
+
+    #[test]
+    fn stop_lines_lie_across_the_end_of_entering_lanes() {
+        let network = busy_junction();
+        let lines = stop_lines(&network, 100);
+        // Only road 1 leads into the junction; roads 4 and 5 start in it.
+        assert_eq!(lines.len(), 1, "{lines:?}");
+        let line = lines[0];
+        assert_eq!(Some(line.segment), network.find_segment(1, 1, -1));
+        assert_near(
+            flat(line.left).distance(DVec2::new(50.0, 0.0)),
+            0.0,
+            TOLERANCE,
+        );
+        assert_near(
+            flat(line.right).distance(DVec2::new(50.0, -3.5)),
+            0.0,
+            TOLERANCE,
+        );
+    }
+}
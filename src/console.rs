@@ -0,0 +1,1098 @@
+// Command console.
+//
+// A console for automating review tasks: every line typed is one command on
+// the map and the scene, such as selecting a lane, highlighting roads,
+// moving the camera or exporting. The same commands can be put in a script
+// file, one per line with `#` comments, and run with `run <file>` or at
+// startup with `view --script <file>`. `help` lists the commands.
+//
+// Files ending in `.rhai` are Rhai scripts instead, for review tasks that
+// need variables, loops or conditions. A script runs a console command with
+// `command("select 1 -1")`, which returns what the command reports or throws
+// its error, lists the roads with `road_ids()`, and writes to the console
+// with `print`. The script runs to its end at once, so a `wait` in it only
+// delays the commands queued after the script.
+//
+// While the console is open it takes all keyboard input.
+//
+// Keys: ` opens or closes the console, Enter runs the line, Up recalls the
+// previous one, Escape closes.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use bevy::app::AppExit;
+use bevy::input::InputSystem;
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy::render::view::screenshot::ScreenshotManager;
+use bevy::window::{PrimaryWindow, ReceivedCharacter};
+
+use crate::annotations::{self, Annotations};
+use crate::asset_packs;
+use crate::barriers;
+use crate::bookmarks::Bookmarks;
+use crate::camera_tween::{CameraTween, OrbitPose};
+use crate::compare;
+use crate::conflicts;
+use crate::continuity;
+use crate::decals;
+use crate::display;
+use crate::edit;
+use crate::entity_index::{OdrEntityIndex, OdrId};
+use crate::environment;
+use crate::failures;
+use crate::filter;
+use crate::isolate::{self, Isolation};
+use crate::issue_export::write_report;
+use crate::lane_edit;
+use crate::lane_report;
+use crate::night;
+use crate::occlusion;
+use crate::open_dialog;
+use crate::origin::{RenderOrigin, WorldPosition};
+use crate::placement;
+use crate::plan_export;
+use crate::reload::ReloadMap;
+use crate::route_export;
+use crate::route_profile;
+use crate::selection::{lateral_offset, Detail, Pick, Selection};
+use crate::sign_edit;
+use crate::sign_models;
+use crate::snapping::{self, Snapping};
+use crate::splice;
+use crate::style;
+use crate::templates;
+use crate::theme::Theme;
+use crate::traces;
+use crate::units;
+use crate::validation::{Report, Severity};
+use crate::weather;
+use crate::xml_tree;
+use crate::{apollo, camera_orbit, sumo, xodr, CameraOrbit, MainCamera, RoadNetwork};
+
+// Lines of output kept on screen.
+const OUTPUT_LINES: usize = 12;
+
+// `nearest` looks this far for lanes, in meters, and lists this many.
+const NEAREST_DISTANCE: f64 = 20.0;
+const NEAREST_LISTED: usize = 5;
+
+// Lifts highlight outlines off the road surface, in meters.
+const HIGHLIGHT_LIFT: f32 = 0.15;
+
+const HELP: &str = "\
+commands:
+  select <road> [lane] [section]  select a road, a lane, or a lane section
+  deselect                        clear the selection
+  highlight <road>...             outline roads; `highlight clear` removes them
+  move road <id> <dx> <dy> [dz]   move a road and its signals (map frame, meters)
+  continuity on|off               whether roads linked to a moved road follow it
+  hide road|junction|signal <id>  hide an element's meshes or icon
+  hide lane <road> <lane>         hide the meshes holding a lane (its road's)
+  hide selected                   hide the selected road
+  isolate                         hide all but the selected road (or junction)
+                                  and the roads linked to it
+  show                            show everything hidden or isolated again
+  filter                          describe the filter
+  filter show|hide <category>     show or hide marks, signals, objects,
+                                  junctions or lanes <type>
+  filter <field> <op> <value>     show only elements passing a condition, e.g.
+                                  speed < 30; `filter clear` resets the filter
+  style [file.json]               describe or load the style sheet; `style
+                                  clear` goes back to the theme
+  template [name]                 list the cross-section templates, or make one
+                                  current for new roads
+  template load|save <file.json>  read more templates, or write them out
+  road new <x1> <y1> <x2> <y2>    create a straight road with the current
+                                  template (map frame, meters)
+  road split <id> <s>             split a road in two at station s
+  road join <id> <id>             append the second road to the first
+  undo                            undo the last edit of roads or lanes
+  snap                            describe the snapping of edits
+  snap grid <m>|off               snap edited points and offsets to a grid
+  snap ends|tangent|widths on|off snap to road ends, to the heading of the road
+                                  drawn from, or to standard lane widths
+  lanes split <s>                 split the selected road's lane section at s
+  lanes add left|right <s> <taper> [width]
+                                  add an outermost lane from s, widening over
+                                  the taper length
+  lanes remove <lane> <s> <taper> narrow an outermost lane away from s
+  lanes merge                     join the selected road's identical sections
+  barrier guardrail|jersey [left|right]
+                                  line the selected road's edges with a barrier;
+                                  `barrier clear` removes them
+  place light|tree <interval> [offset] [left|right]
+                                  place street lights or trees along the selected
+                                  road's edge; `place clear` removes them
+  signs [country <code>|use <name>|load <file>]
+                                  list or choose the catalog and sign to place
+  signs place <road> <s> <t>      place the current sign
+  signs move <id> <road> <s> <t>  move a sign
+  signs flip [id]|remove <id>     turn a sign around, or remove it
+  sign-catalog [load <file>]      list the sign conventions, or load more
+  assets [load <dir>|clear]       list, load or drop the object asset packs
+  environment <image>|off         surround the map with a panorama's sky and
+                                  light; `environment brightness <cd/m²>`
+  weather [clear|wet|rain|fog]    show the map in a weather preset
+  occlusion [on|off|<quality>]    ambient occlusion of curbs and medians;
+                                  low, medium, high or ultra quality
+  speed-unit [km/h|mph|m/s]       the unit speeds are shown in
+  display [coordinates|stations <decimals>|local|geographic]
+                                  how positions and (s, t) are written
+  night [on|off|budget <n>]       night mode, lit by the street lights, with
+                                  at most n lights
+  route [options] <from> <to>     find a route between two lanes, given as
+                                  road:section:lane, and chart its elevation,
+                                  speed limit and curvature; options are
+                                  --fastest, --no-u-turns, --vehicle <name>
+  route export <file.csv>         write the route's samples as CSV
+  route clear                     remove the route
+  match <trace.csv>               snap a GPS trace (x,y or lat,lon[,z][,heading]
+                                  columns) to the lanes and show it
+  match export <file>             write the matched path as a route (.csv or
+                                  .xosc); `match clear` removes the traces
+  goto <x> <y>                    fly to a point (map frame, meters)
+  goto road <id>                  fly to the middle of a road
+  goto note <id>                  fly to an annotation
+  goto signal <id>                fly to a signal
+  zoom <m>                        set the camera distance
+  turn <deg> [elevation deg]      set the camera heading (and elevation)
+  bookmark <1-9>                  fly to a bookmark
+  note [severity] <text>          pin a note at the selection or view center;
+                                  severity is info, warning (default) or error
+  notes                           list the annotations
+  unnote <id>                     delete an annotation
+  decal pothole|patch <road> <s> <t> [<length> <width>]
+                                  paint a decal onto a lane, kept with the
+                                  annotations
+  decal text <road> <s> <t> <text>
+                                  paint a text stencil onto a lane
+  decal remove|to-map <id>        drop a decal, or move it into the map as an
+                                  object
+  decals                          list the decals of the annotations and map
+  roads                           count the roads, lanes and junctions
+  road <id>                       describe a road
+  failures                        list the roads that could not be loaded
+  xml [find <text>]               show the marked element of the XML tree, or
+                                  mark the next one containing the text
+  nearest <x> <y> [heading deg]   list the lanes nearest to a point (map frame,
+                                  meters), going the heading's way
+  conflicts <junction>            list a junction's conflict points and stop
+                                  lines
+  export-xodr <file>              write the network as OpenDRIVE
+  export-apollo <file>            write the network as an Apollo HD map
+  export-sumo <file.net.xml>      write the network as a SUMO network
+  lane-report <file.csv>          write each lane's length and area as CSV
+  export-plan <file.svg|file.pdf> [1:<n>]
+                                  draw the map as a vector plan, at 1:1000
+                                  unless a scale is given
+  export-issues <file>            write issues and annotations as SARIF (.sarif)
+                                  or JUnit XML (.xml)
+  screenshot <file.png>           save the window
+  compare [<file.png>|off]        compare the view with a snapshot of it, or
+                                  with a saved screenshot, or stop comparing
+  reload                          reload the map from its file
+  open <file>                     start a new viewer on a project (.rsodr) or
+                                  map and close this one
+  run <file>                      run the commands in a file, or a Rhai
+                                  script (.rhai)
+  wait <s>                        wait before running the next command
+  echo <text>                     print text";
+
+#[derive(Resource, Debug, Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: VecDeque<String>,
+    history: Vec<String>,
+    // Lines waiting to run, in order, and when the next may run (elapsed
+    // seconds).
+    pending: VecDeque<String>,
+    resume_at: f64,
+}
+
+impl Console {
+    // Opens the console with a line started for the user to finish.
+    pub fn prompt(&mut self, input: &str) {
+        self.open = true;
+        self.input = input.to_string();
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.output.push_back(line.to_string());
+        }
+        while self.output.len() > OUTPUT_LINES {
+            self.output.pop_front();
+        }
+    }
+}
+
+// A script to run at startup.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct StartupScript(pub Option<PathBuf>);
+
+// Elements hidden by `hide`; the filter applies it (see `filter`).
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Hidden(pub Vec<OdrId>);
+
+// Roads outlined by `highlight`.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Highlights(pub Vec<u32>);
+
+#[derive(Component)]
+struct ConsolePanel;
+
+#[derive(Component)]
+struct ConsoleText;
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<StartupScript>()
+            .init_resource::<Highlights>()
+            .init_resource::<Hidden>()
+            .add_systems(Startup, (spawn_panel, queue_startup_script))
+            .add_systems(PreUpdate, read_keys.after(InputSystem))
+            .add_systems(
+                Update,
+                (
+                    run_commands.before(camera_orbit),
+                    show_console,
+                    draw_highlights.after(camera_orbit),
+                ),
+            );
+    }
+}
+
+fn queue_startup_script(script: Res<StartupScript>, mut console: ResMut<Console>) {
+    if let Some(path) = &script.0 {
+        console.pending.push_back(format!("run {}", path.display()));
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(30.0),
+                    width: Val::Percent(50.0),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ConsolePanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 14.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                ConsoleText,
+            ));
+        });
+}
+
+// Edits the input line, and keeps the keys from the rest of the viewer
+// while the console is open.
+fn read_keys(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut console: ResMut<Console>,
+) {
+    let typed: String = characters.read().map(|c| c.char.as_str()).collect();
+    if keys.just_pressed(KeyCode::Backquote) {
+        console.open = !console.open;
+        keys.reset_all();
+        return;
+    }
+    if !console.open {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        console.open = false;
+    } else if keys.just_pressed(KeyCode::Enter) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.history.push(line.clone());
+            console.pending.push_back(line);
+        }
+    } else if keys.just_pressed(KeyCode::Backspace) {
+        console.input.pop();
+    } else if keys.just_pressed(KeyCode::ArrowUp) {
+        if let Some(last) = console.history.last().cloned() {
+            console.input = last;
+        }
+    } else {
+        console
+            .input
+            .extend(typed.chars().filter(|c| !c.is_control() && *c != '`'));
+    }
+    keys.reset_all();
+}
+
+fn show_console(
+    console: Res<Console>,
+    mut panels: Query<&mut Visibility, With<ConsolePanel>>,
+    mut texts: Query<&mut Text, With<ConsoleText>>,
+) {
+    if !console.is_changed() {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = if console.open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    let mut shown = String::new();
+    for line in &console.output {
+        let _ = writeln!(shown, "{line}");
+    }
+    let _ = write!(shown, "> {}_", console.input);
+    for mut text in &mut texts {
+        text.sections[0].value.clone_from(&shown);
+    }
+}
+
+// Runs the waiting commands, printing what they report.
+fn run_commands(world: &mut World) {
+    loop {
+        let now = world.resource::<Time>().elapsed_seconds_f64();
+        let console = world.resource::<Console>();
+        if console.pending.is_empty() || now < console.resume_at {
+            return;
+        }
+        let Some(line) = world.resource_mut::<Console>().pending.pop_front() else {
+            return;
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = execute(world, line);
+        let mut console = world.resource_mut::<Console>();
+        console.print(&format!("> {line}"));
+        match result {
+            Ok(message) if message.is_empty() => {}
+            Ok(message) => {
+                info!("{message}");
+                console.print(&message);
+            }
+            Err(message) => {
+                warn!("{line}: {message}");
+                console.print(&format!("error: {message}"));
+            }
+        }
+    }
+}
+
+fn number<T: std::str::FromStr>(text: Option<&str>, what: &str) -> Result<T, String> {
+    let text = text.ok_or_else(|| format!("expected {what}"))?;
+    text.parse()
+        .map_err(|_| format!("`{text}` is not a valid {what}"))
+}
+
+// Runs one command.
+fn execute(world: &mut World, line: &str) -> Result<String, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let args: Vec<&str> = words.collect();
+    let arg = |i: usize| args.get(i).copied();
+    match command {
+        "help" => Ok(HELP.to_string()),
+        "echo" => Ok(args.join(" ")),
+        "select" => {
+            let road: u32 = number(arg(0), "road ID")?;
+            let lane: Option<i32> = arg(1).map(|a| number(Some(a), "lane ID")).transpose()?;
+            let section: Option<u32> = arg(2)
+                .map(|a| number(Some(a), "lane section ID"))
+                .transpose()?;
+            let pick = select(world.resource::<RoadNetwork>(), road, lane, section)?;
+            world.resource_mut::<Selection>().0 = Some(pick);
+            Ok(format!("selected {}", pick.describe()))
+        }
+        "deselect" => {
+            world.resource_mut::<Selection>().0 = None;
+            Ok(String::new())
+        }
+        "highlight" if arg(0) == Some("clear") => {
+            world.resource_mut::<Highlights>().0.clear();
+            Ok(String::new())
+        }
+        "highlight" => {
+            let roads = args
+                .iter()
+                .map(|a| number(Some(a), "road ID"))
+                .collect::<Result<Vec<u32>, _>>()?;
+            let network = world.resource::<RoadNetwork>();
+            if let Some(missing) = roads.iter().find(|id| !network.roads.contains_key(id)) {
+                return Err(format!("no road {missing}"));
+            }
+            world.resource_mut::<Highlights>().0.extend(roads);
+            Ok(String::new())
+        }
+        "move" if arg(0) == Some("road") => {
+            let road: u32 = number(arg(1), "road ID")?;
+            let dx: f64 = number(arg(2), "dx")?;
+            let dy: f64 = number(arg(3), "dy")?;
+            let dz: f64 = arg(4).map_or(Ok(0.0), |a| number(Some(a), "dz"))?;
+            // Map y is north, which is -z in the viewer.
+            let offset = world.resource::<Snapping>().offset(DVec3::new(dx, dz, -dy));
+            edit::move_road(world, road, offset)
+        }
+        "hide" if arg(0) == Some("selected") => isolate::hide_selected(world),
+        "isolate" => isolate::isolate(world),
+        "hide" => {
+            let id = element(world.resource::<RoadNetwork>(), &args)?;
+            world.resource_mut::<Hidden>().0.push(id);
+            Ok(String::new())
+        }
+        "show" => {
+            world.resource_mut::<Hidden>().0.clear();
+            world.resource_mut::<Isolation>().0 = None;
+            Ok(String::new())
+        }
+        "filter" => filter::command(world, &args),
+        "style" => style::command(world, &args),
+        "template" => templates::command(world, &args),
+        "barrier" => barriers::command(world, &args),
+        "snap" => snapping::command(world, &args),
+        "continuity" => continuity::command(world, &args),
+        "lanes" => lane_edit::command(world, &args),
+        "place" => placement::command(world, &args),
+        "signs" => sign_edit::command(world, &args),
+        "sign-catalog" => sign_models::command(world, &args),
+        "assets" => asset_packs::command(world, &args),
+        "environment" => environment::command(world, &args),
+        "night" => night::command(world, &args),
+        "weather" => weather::command(world, &args),
+        "occlusion" => occlusion::command(world, &args),
+        "speed-unit" => units::command(world, &args),
+        "road" if arg(0) == Some("new") => {
+            let coordinates = (1..5)
+                .map(|i| number(arg(i), "coordinate"))
+                .collect::<Result<Vec<f64>, _>>()?;
+            // Map y is north, which is -z in the viewer.
+            let from = DVec3::new(coordinates[0], 0.0, -coordinates[1]);
+            let to = DVec3::new(coordinates[2], 0.0, -coordinates[3]);
+            templates::new_road(world, from, to)
+        }
+        "road" if arg(0) == Some("split") => {
+            let road: u32 = number(arg(1), "road ID")?;
+            let s: f64 = number(arg(2), "station")?;
+            splice::split(world, road, s)
+        }
+        "road" if arg(0) == Some("join") => {
+            let first: u32 = number(arg(1), "road ID")?;
+            let second: u32 = number(arg(2), "road ID")?;
+            splice::join(world, first, second)
+        }
+        "undo" => edit::undo(world),
+        "route" => route_profile::command(world, &args),
+        "match" => traces::command(world, &args),
+        "goto" if arg(0) == Some("road") => {
+            let road: u32 = number(arg(1), "road ID")?;
+            let pick = select(world.resource::<RoadNetwork>(), road, None, None)?;
+            move_camera(world, |pose| pose.center = pick.position)
+        }
+        "goto" if arg(0) == Some("signal") => {
+            let id = arg(1).ok_or("expected a signal ID")?;
+            let icon = world
+                .resource::<OdrEntityIndex>()
+                .entities(&OdrId::Signal(id.to_string()))
+                .first()
+                .copied()
+                .ok_or_else(|| format!("no signal {id}"))?;
+            let position = world
+                .get::<WorldPosition>(icon)
+                .map(|p| p.0)
+                .ok_or_else(|| format!("no signal {id}"))?;
+            move_camera(world, |pose| pose.center = position)
+        }
+        "goto" if arg(0) == Some("note") => {
+            let id: u32 = number(arg(1), "annotation ID")?;
+            let position = world
+                .resource::<Annotations>()
+                .list
+                .iter()
+                .find(|note| note.id == id)
+                .map(|note| note.position)
+                .ok_or_else(|| format!("no annotation {id}"))?;
+            move_camera(world, |pose| pose.center = position)
+        }
+        "goto" => {
+            let x: f64 = number(arg(0), "x")?;
+            let y: f64 = number(arg(1), "y")?;
+            // Map y is north, which is -z in the viewer.
+            move_camera(world, |pose| {
+                pose.center = DVec3::new(x, pose.center.y, -y);
+            })
+        }
+        "zoom" => {
+            let distance: f32 = number(arg(0), "distance")?;
+            move_camera(world, |pose| pose.distance = distance)
+        }
+        "turn" => {
+            let azimuth: f32 = number(arg(0), "heading")?;
+            let elevation: Option<f32> =
+                arg(1).map(|a| number(Some(a), "elevation")).transpose()?;
+            move_camera(world, |pose| {
+                pose.azimuth = azimuth.to_radians();
+                if let Some(elevation) = elevation {
+                    pose.elevation = elevation.to_radians();
+                }
+            })
+        }
+        "bookmark" => {
+            let slot: u8 = number(arg(0), "bookmark slot")?;
+            let mark = world
+                .resource::<Bookmarks>()
+                .slots
+                .get(&slot)
+                .cloned()
+                .ok_or_else(|| format!("bookmark {slot} is empty"))?;
+            move_camera(world, |pose| *pose = mark.pose)?;
+            Ok(format!("bookmark {slot}: {}", mark.name))
+        }
+        "note" => {
+            // The severity is optional and defaults to a warning.
+            let (severity, text) = match arg(0).and_then(Severity::from_name) {
+                Some(severity) => (severity, args[1..].join(" ")),
+                None => (Severity::Warning, args.join(" ")),
+            };
+            if text.is_empty() {
+                return Err("expected a note".to_string());
+            }
+            annotations::annotate(world, severity, &text)
+        }
+        "notes" => Ok(annotations::describe(world)),
+        "decal" => decals::command(world, &args),
+        "decals" => Ok(decals::describe(world)),
+        "unnote" => {
+            let id: u32 = number(arg(0), "annotation ID")?;
+            annotations::remove(world, id)
+        }
+        "roads" => {
+            let network = world.resource::<RoadNetwork>();
+            Ok(format!(
+                "{} roads, {} lane segments, {} junctions",
+                network.roads.len(),
+                network.segments.len(),
+                network.junctions.len()
+            ))
+        }
+        "xml" => xml_tree::command(world, &args),
+        "display" => display::command(world, &args),
+        "failures" => Ok(failures::describe(world.resource::<RoadNetwork>())),
+        "nearest" => {
+            let x: f64 = number(arg(0), "x")?;
+            let y: f64 = number(arg(1), "y")?;
+            let heading: Option<f64> = arg(2)
+                .map(|a| number(Some(a), "heading").map(f64::to_radians))
+                .transpose()?;
+            let network = world.resource::<RoadNetwork>();
+            let matches = network.nearest_lane(DVec2::new(x, y), heading, NEAREST_DISTANCE);
+            if matches.is_empty() {
+                return Err(format!("no lane within {NEAREST_DISTANCE} m"));
+            }
+            let mut out = String::new();
+            for m in matches.iter().take(NEAREST_LISTED) {
+                let lane = &network.segments[m.segment];
+                let _ = writeln!(
+                    out,
+                    "road {} section {} lane {}: s {:.2}, {:+.2} m off center{}, {:.0} deg off heading",
+                    lane.road_id,
+                    lane.lane_section_id,
+                    lane.lane_id,
+                    m.s,
+                    m.lateral,
+                    if m.inside { "" } else { " (outside)" },
+                    m.heading_error.to_degrees()
+                );
+            }
+            Ok(out.trim_end().to_string())
+        }
+        "conflicts" => {
+            let junction: u32 = number(arg(0), "junction ID")?;
+            let network = world.resource::<RoadNetwork>();
+            if !conflicts::junctions(network).contains(&junction) {
+                return Err(format!("no junction {junction}"));
+            }
+            let lane = |i: usize| {
+                let lane = &network.segments[i];
+                format!("{}:{}:{}", lane.road_id, lane.lane_section_id, lane.lane_id)
+            };
+            let mut out = String::new();
+            for conflict in conflicts::conflict_points(network, junction) {
+                let at = route_export::to_odr(conflict.position);
+                let _ = writeln!(
+                    out,
+                    "{:?} of {} and {} at ({:.2}, {:.2}), {:.1} m and {:.1} m along",
+                    conflict.kind,
+                    lane(conflict.paths[0]),
+                    lane(conflict.paths[1]),
+                    at.x,
+                    at.y,
+                    conflict.along[0],
+                    conflict.along[1]
+                );
+            }
+            for stop in conflicts::stop_lines(network, junction) {
+                let center = route_export::to_odr((stop.left + stop.right) / 2.0);
+                let _ = writeln!(
+                    out,
+                    "stop line of {} at ({:.2}, {:.2})",
+                    lane(stop.segment),
+                    center.x,
+                    center.y
+                );
+            }
+            if out.is_empty() {
+                return Ok(format!(
+                    "junction {junction} has no conflicts or stop lines"
+                ));
+            }
+            Ok(out.trim_end().to_string())
+        }
+        "road" => {
+            let road: u32 = number(arg(0), "road ID")?;
+            describe_road(world.resource::<RoadNetwork>(), road)
+        }
+        "export-xodr" | "export-apollo" | "export-sumo" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            let network = world.resource::<RoadNetwork>();
+            let written = match command {
+                "export-xodr" => xodr::write_map(network, path),
+                "export-sumo" => sumo::write_map(network, path),
+                _ => apollo::write_map(network, path),
+            };
+            written.map_err(|e| format!("{}: {e}", path.display()))?;
+            Ok(format!("wrote {}", path.display()))
+        }
+        "export-plan" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            let scale = arg(1).map_or(Ok(plan_export::DEFAULT_SCALE), plan_export::parse_scale)?;
+            plan_export::write_plan(
+                world.resource::<RoadNetwork>(),
+                path,
+                scale,
+                world.resource::<Theme>(),
+            )?;
+            Ok(format!("wrote {}", path.display()))
+        }
+        "lane-report" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            lane_report::write_report(world.resource::<RoadNetwork>(), path)?;
+            Ok(format!("wrote {}", path.display()))
+        }
+        "export-issues" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            let annotations = world.resource::<Annotations>();
+            // The sidecar file sits next to the map, named after it.
+            let map = annotations.file.as_deref().and_then(|file| {
+                let name = file
+                    .file_name()?
+                    .to_str()?
+                    .strip_suffix(".annotations.json")?;
+                Some(file.with_file_name(name))
+            });
+            write_report(
+                path,
+                map.as_deref(),
+                &world.resource::<Report>().0,
+                &annotations.list,
+            )?;
+            Ok(format!("wrote {}", path.display()))
+        }
+        "screenshot" => {
+            let path = PathBuf::from(arg(0).ok_or("expected a file")?);
+            let window = world
+                .query_filtered::<Entity, With<PrimaryWindow>>()
+                .get_single(world)
+                .map_err(|_| "no window")?;
+            world
+                .resource_mut::<ScreenshotManager>()
+                .save_screenshot_to_disk(window, &path)
+                .map_err(|_| "a screenshot is already being taken")?;
+            Ok(format!("saving {}", path.display()))
+        }
+        "compare" => compare::command(world, &args),
+        "reload" => {
+            world.send_event(ReloadMap);
+            Ok(String::new())
+        }
+        "open" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            open_dialog::open_session(path)?;
+            world.send_event(AppExit);
+            Ok(format!("opening {}", path.display()))
+        }
+        "run" => {
+            let path = Path::new(arg(0).ok_or("expected a file")?);
+            let text =
+                std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+            if path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("rhai"))
+            {
+                return run_script(world, &text);
+            }
+            // The script's lines run next, before anything queued after it.
+            let mut console = world.resource_mut::<Console>();
+            for line in text.lines().rev() {
+                console.pending.push_front(line.to_string());
+            }
+            Ok(String::new())
+        }
+        "wait" => {
+            let seconds: f64 = number(arg(0), "duration")?;
+            let now = world.resource::<Time>().elapsed_seconds_f64();
+            world.resource_mut::<Console>().resume_at = now + seconds;
+            Ok(String::new())
+        }
+        "" => Ok(String::new()),
+        other => Err(format!("unknown command `{other}`; try `help`")),
+    }
+}
+
+// Runs a Rhai script to its end, returning what it printed. The script's
+// functions need the world for as long as the engine lives, so it is lent
+// to them and put back afterwards.
+fn run_script(world: &mut World, source: &str) -> Result<String, String> {
+    let lent = Rc::new(RefCell::new(std::mem::take(world)));
+    let printed = Rc::new(RefCell::new(Vec::<String>::new()));
+    let mut engine = rhai::Engine::new();
+    let commands = lent.clone();
+    engine.register_fn(
+        "command",
+        move |line: &str| -> Result<String, Box<rhai::EvalAltResult>> {
+            execute(&mut commands.borrow_mut(), line).map_err(|e| format!("{line}: {e}").into())
+        },
+    );
+    let roads = lent.clone();
+    engine.register_fn("road_ids", move || -> rhai::Array {
+        let world = roads.borrow();
+        let mut ids: Vec<u32> = world
+            .resource::<RoadNetwork>()
+            .roads
+            .keys()
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        ids.into_iter()
+            .map(|id| rhai::Dynamic::from(i64::from(id)))
+            .collect()
+    });
+    let prints = printed.clone();
+    engine.on_print(move |text| prints.borrow_mut().push(text.to_string()));
+    let result = engine.run(source);
+    drop(engine);
+    *world = Rc::try_unwrap(lent)
+        .map_err(|_| "the script kept the world")?
+        .into_inner();
+    result.map_err(|e| e.to_string())?;
+    let printed = printed.borrow().join("\n");
+    Ok(printed)
+}
+
+// An element named by the words after `hide`, checked against the network.
+fn element(network: &RoadNetwork, args: &[&str]) -> Result<OdrId, String> {
+    let arg = |i: usize| args.get(i).copied();
+    match arg(0) {
+        Some("road") => {
+            let road: u32 = number(arg(1), "road ID")?;
+            if !network.roads.contains_key(&road) {
+                return Err(format!("no road {road}"));
+            }
+            Ok(OdrId::Road(road))
+        }
+        Some("lane") => {
+            let road_id: u32 = number(arg(1), "road ID")?;
+            let lane_id: i32 = number(arg(2), "lane ID")?;
+            select(network, road_id, Some(lane_id), None)?;
+            Ok(OdrId::Lane { road_id, lane_id })
+        }
+        Some("junction") => {
+            let junction: u32 = number(arg(1), "junction ID")?;
+            if !network.junctions.contains_key(&junction) {
+                return Err(format!("no junction {junction}"));
+            }
+            Ok(OdrId::Junction(junction))
+        }
+        Some("signal") => {
+            let id = arg(1).ok_or("expected a signal ID")?;
+            if !network.signals.iter().any(|signal| signal.id == id) {
+                return Err(format!("no signal {id}"));
+            }
+            Ok(OdrId::Signal(id.to_string()))
+        }
+        _ => Err("expected road, lane, junction or signal".to_string()),
+    }
+}
+
+// A pick in the middle of a road, a lane, or a lane section.
+pub fn select(
+    network: &RoadNetwork,
+    road: u32,
+    lane: Option<i32>,
+    section: Option<u32>,
+) -> Result<Pick, String> {
+    let index = network
+        .segments
+        .iter()
+        .position(|segment| {
+            segment.road_id == road
+                && lane.is_none_or(|lane| segment.lane_id == lane)
+                && section.is_none_or(|section| segment.lane_section_id == section)
+        })
+        .ok_or_else(|| match (lane, section) {
+            (None, _) => format!("no road {road}"),
+            (Some(lane), None) => format!("road {road} has no lane {lane}"),
+            (Some(lane), Some(section)) => {
+                format!("road {road} has no lane {lane} in section {section}")
+            }
+        })?;
+    let segment = &network.segments[index];
+    let centerline = segment.centerline();
+    let position = centerline
+        .get(centerline.len() / 2)
+        .copied()
+        .unwrap_or(segment.start_pos);
+    let s = (segment.start_s + segment.end_s) / 2.0;
+    Ok(Pick {
+        detail: match (lane, section) {
+            (None, _) => Detail::Road,
+            (Some(_), None) => Detail::Lane,
+            (Some(_), Some(_)) => Detail::Section,
+        },
+        segment: index,
+        road_id: road,
+        lane_section_id: segment.lane_section_id,
+        lane_id: segment.lane_id,
+        s,
+        position,
+        t: lateral_offset(network, road, s, position),
+    })
+}
+
+fn describe_road(network: &RoadNetwork, road: u32) -> Result<String, String> {
+    let segments: Vec<_> = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road)
+        .collect();
+    if segments.is_empty() {
+        return Err(format!("no road {road}"));
+    }
+    let length = segments.iter().map(|s| s.end_s).fold(0.0, f64::max);
+    let mut lanes: Vec<i32> = segments.iter().map(|s| s.lane_id).collect();
+    lanes.sort_unstable();
+    lanes.dedup();
+    let mut sections: Vec<u32> = segments.iter().map(|s| s.lane_section_id).collect();
+    sections.sort_unstable();
+    sections.dedup();
+    let mut out = format!(
+        "road {road}: {length:.1} m, {} lane section(s), lanes {lanes:?}",
+        sections.len()
+    );
+    if let Some(junction) = network.roads.get(&road).and_then(|info| info.junction) {
+        let _ = write!(out, ", in junction {junction}");
+    }
+    Ok(out)
+}
+
+// Flies the main camera to a pose changed from the current one.
+fn move_camera(world: &mut World, change: impl FnOnce(&mut OrbitPose)) -> Result<String, String> {
+    let origin = *world.resource::<RenderOrigin>();
+    let (camera, orbit) = world
+        .query_filtered::<(Entity, &CameraOrbit), With<MainCamera>>()
+        .get_single(world)
+        .map_err(|_| "no camera")?;
+    let from = OrbitPose::of(orbit, &origin);
+    let mut to = from;
+    change(&mut to);
+    world.entity_mut(camera).insert(CameraTween::new(from, to));
+    Ok(String::new())
+}
+
+fn draw_highlights(
+    highlights: Res<Highlights>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    if highlights.0.is_empty() {
+        return;
+    }
+    let lift = Vec3::Y * HIGHLIGHT_LIFT;
+    for segment in &network.segments {
+        if !highlights.0.contains(&segment.road_id) {
+            continue;
+        }
+        for side in [&segment.left_side, &segment.right_side] {
+            gizmos.linestrip(
+                side.iter().map(|p| origin.to_render(*p) + lift),
+                theme.highlight,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{load, temp_dir};
+
+    // A world with the resources the commands below use and a camera
+    // looking at the origin.
+    fn world(map: &str) -> World {
+        let mut world = World::new();
+        world.insert_resource(load(map));
+        world.init_resource::<Selection>();
+        world.init_resource::<Highlights>();
+        world.init_resource::<RenderOrigin>();
+        world.spawn((
+            MainCamera,
+            CameraOrbit {
+                center: Vec3::ZERO,
+                distance: 100.0,
+                azimuth: 0.0,
+                elevation: 0.5,
+                pan: Vec2::ZERO,
+            },
+        ));
+        world
+    }
+
+    #[test]
+    fn select_picks_roads_lanes_and_sections() {
+        let mut world = world("elevation.xodr");
+        assert_eq!(
+            execute(&mut world, "select 1 -2 2"),
+            Ok("selected road 1 lane -2 section 2 (s 75.00)".to_string())
+        );
+        let pick = world.resource::<Selection>().0.unwrap();
+        assert_eq!((pick.road_id, pick.lane_id), (1, -2));
+        assert_eq!(pick.lane_section_id, 2);
+
+        // Lane -2 only opens in the second section.
+        let error = execute(&mut world, "select 1 -2 1").unwrap_err();
+        assert_eq!(error, "road 1 has no lane -2 in section 1");
+        assert!(execute(&mut world, "select 2").is_err());
+        assert!(execute(&mut world, "select one").is_err());
+        // A failed selection keeps the one before.
+        assert_eq!(world.resource::<Selection>().road_id(), Some(1));
+        execute(&mut world, "deselect").unwrap();
+        assert_eq!(world.resource::<Selection>().0, None);
+    }
+
+    #[test]
+    fn highlight_adds_roads_until_cleared() {
+        let mut world = world("junction.xodr");
+        execute(&mut world, "highlight 1 2").unwrap();
+        execute(&mut world, "highlight 3").unwrap();
+        assert_eq!(world.resource::<Highlights>().0, [1, 2, 3]);
+        // Nothing is highlighted if one of the roads does not exist.
+        assert_eq!(
+            execute(&mut world, "highlight 1 99"),
+            Err("no road 99".to_string())
+        );
+        assert_eq!(world.resource::<Highlights>().0, [1, 2, 3]);
+        execute(&mut world, "highlight clear").unwrap();
+        assert!(world.resource::<Highlights>().0.is_empty());
+    }
+
+    #[test]
+    fn camera_commands_fly_to_their_pose() {
+        let mut world = world("straight.xodr");
+        let target = |world: &mut World| world.query::<&CameraTween>().single(world).target();
+        // Map y is north, which is -z in the viewer.
+        execute(&mut world, "goto 10 20").unwrap();
+        let pose = target(&mut world);
+        assert_eq!(pose.center, DVec3::new(10.0, 0.0, -20.0));
+        assert_eq!(pose.distance, 100.0);
+
+        execute(&mut world, "zoom 40").unwrap();
+        assert_eq!(target(&mut world).distance, 40.0);
+        execute(&mut world, "turn 90 30").unwrap();
+        let pose = target(&mut world);
+        assert!((pose.azimuth - 90f32.to_radians()).abs() < 1e-6);
+        assert!((pose.elevation - 30f32.to_radians()).abs() < 1e-6);
+
+        // `goto road` looks at the middle of the road.
+        execute(&mut world, "goto road 1").unwrap();
+        assert!((target(&mut world).center.x - 50.0).abs() < 1.0);
+        assert!(execute(&mut world, "goto road 7").is_err());
+        assert!(execute(&mut world, "goto 10").is_err());
+    }
+
+    #[test]
+    fn exports_write_the_network() {
+        let mut world = world("straight.xodr");
+        let dir = temp_dir("console_exports");
+        for (command, file) in [
+            ("export-xodr", "map.xodr"),
+            ("export-apollo", "base_map.txt"),
+            ("export-sumo", "map.net.xml"),
+        ] {
+            let path = dir.join(file);
+            let line = format!("{command} {}", path.display());
+            assert_eq!(
+                execute(&mut world, &line),
+                Ok(format!("wrote {}", path.display()))
+            );
+            assert!(std::fs::metadata(&path).unwrap().len() > 0, "{line}");
+        }
+        let written = std::fs::read_to_string(dir.join("map.xodr")).unwrap();
+        assert!(written.contains("<road"));
+        assert!(execute(&mut world, "export-xodr").is_err());
+        let binary = dir.join("base_map.bin");
+        let error = execute(&mut world, &format!("export-apollo {}", binary.display()));
+        assert!(error.unwrap_err().contains("only the text format"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rhai_scripts_loop_over_the_roads() {
+        let mut world = World::new();
+        world.insert_resource(load("junction.xodr"));
+        let script = r#"
+            let outside = 0;
+            for id in road_ids() {
+                let text = command("road " + id);
+                if text.contains("in junction") { continue; }
+                outside += 1;
+            }
+            print(`${outside} roads outside junctions`);
+        "#;
+        let output = run_script(&mut world, script).unwrap();
+        assert!(output.ends_with("roads outside junctions"), "{output}");
+        assert!(!output.starts_with("0 "), "{output}");
+        // The world is put back after the script.
+        assert!(!world.resource::<RoadNetwork>().roads.is_empty());
+
+        let error = run_script(&mut world, r#"command("road 9999");"#).unwrap_err();
+        assert!(error.contains("no road 9999"), "{error}");
+        assert!(world.get_resource::<RoadNetwork>().is_some());
+    }
+}
@@ -0,0 +1,208 @@
+// Keeping linked roads continuous through edits.
+//
+// Moving a road (`move road`) pulls its ends away from the roads linked to
+// them, which would leave a gap or a kink at every joint. With continuity
+// on, each linked road is reshaped to follow: its lanes, signals and objects
+// are displaced along the road by an amount that equals the move at the
+// ends linked to the moved road and is zero at its other ends. The
+// displacement blends between the two with a quintic whose first and
+// second derivatives vanish at both ends, the lowest order that meets the
+// six end conditions, so each reshaped road keeps its heading and
+// curvature at both joints (C1 and C2 continuity) and only bends in
+// between. The stations of the reshaped road are then stretched to its new
+// length, and its plan view is measured off the reshaped lanes again.
+//
+// `continuity on|off` in the console switches this.
+
+use std::collections::BTreeMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::road_range;
+use crate::xodr::inner_edge;
+use crate::{ContactPoint, RoadNetwork, RoadSegment};
+
+// Whether edits reshape the roads linked to the ones they change.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Continuity(pub bool);
+
+impl Default for Continuity {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+// The share of the start's displacement at `u`, from 1 at the start (0) to
+// 0 at the end (1), with zero first and second derivatives at both.
+fn start_weight(u: f64) -> f64 {
+    let u = u.clamp(0.0, 1.0);
+    1.0 - u * u * u * (10.0 - 15.0 * u + 6.0 * u * u)
+}
+
+// The length of a road's reference line, along the inner edge of the
+// innermost lane of each lane section.
+fn reference_length(network: &RoadNetwork, road_id: u32) -> f64 {
+    let mut innermost: BTreeMap<u32, &RoadSegment> = BTreeMap::new();
+    for segment in network.segments.iter().filter(|s| s.road_id == road_id) {
+        let lane = innermost.entry(segment.lane_section_id).or_insert(segment);
+        if segment.lane_id.abs() < lane.lane_id.abs() {
+            *lane = segment;
+        }
+    }
+    innermost
+        .values()
+        .map(|lane| {
+            inner_edge(lane)
+                .windows(2)
+                .map(|pair| pair[0].distance(pair[1]))
+                .sum::<f64>()
+        })
+        .sum()
+}
+
+// Displaces a road by `at_start` at its start and `at_end` at its end,
+// blending in between, and stretches its stations to the new length.
+fn reshape(network: &mut RoadNetwork, road_id: u32, at_start: DVec3, at_end: DVec3) {
+    let Some((first, last)) = road_range(network, road_id) else {
+        return;
+    };
+    let span = last - first;
+    if span <= f64::EPSILON {
+        return;
+    }
+    let shift = |s: f64| {
+        let w = start_weight((s - first) / span);
+        at_start * w + at_end * (1.0 - w)
+    };
+    let before = reference_length(network, road_id);
+    for segment in network
+        .segments
+        .iter_mut()
+        .filter(|segment| segment.road_id == road_id)
+    {
+        let (start, end) = (segment.start_s, segment.end_s);
+        segment.start_pos += shift(start);
+        segment.end_pos += shift(end);
+        for side in [&mut segment.left_side, &mut segment.right_side] {
+            // Boundaries are sampled evenly between the stations.
+            let count = side.len().saturating_sub(1).max(1) as f64;
+            for (i, p) in side.iter_mut().enumerate() {
+                *p += shift(start + (end - start) * i as f64 / count);
+            }
+        }
+    }
+    for signal in network
+        .signals
+        .iter_mut()
+        .filter(|signal| signal.road_id == road_id)
+    {
+        signal.position += shift(signal.s);
+    }
+
+    let after = reference_length(network, road_id);
+    if before <= f64::EPSILON {
+        return;
+    }
+    let stretch = |s: f64| first + (s - first) * after / before;
+    for segment in network
+        .segments
+        .iter_mut()
+        .filter(|segment| segment.road_id == road_id)
+    {
+        segment.start_s = stretch(segment.start_s);
+        segment.end_s = stretch(segment.end_s);
+    }
+    for signal in network
+        .signals
+        .iter_mut()
+        .filter(|signal| signal.road_id == road_id)
+    {
+        signal.s = stretch(signal.s);
+        for repeat in &mut signal.repeats {
+            repeat.length = stretch(repeat.s + repeat.length) - stretch(repeat.s);
+            repeat.s = stretch(repeat.s);
+        }
+    }
+    if let Some(info) = network.roads.get_mut(&road_id) {
+        info.plan_view.clear();
+        info.xml.clear();
+    }
+}
+
+// Reshapes the roads linked to `moved` after it was moved by `offset`, and
+// returns their IDs.
+pub fn follow(network: &mut RoadNetwork, moved: u32, offset: DVec3) -> Vec<u32> {
+    // Which ends of each linked road meet the moved road.
+    let mut ends: BTreeMap<u32, (bool, bool)> = BTreeMap::new();
+    for link in &network.links {
+        let (road_id, contact) = if link.other_road_id == moved {
+            (link.road_id, link.contact)
+        } else if link.road_id == moved {
+            (link.other_road_id, link.other_contact)
+        } else {
+            continue;
+        };
+        if road_id == moved {
+            continue;
+        }
+        let end = ends.entry(road_id).or_default();
+        match contact {
+            ContactPoint::Start => end.0 = true,
+            ContactPoint::End => end.1 = true,
+        }
+    }
+    let moved_at = |linked: bool| if linked { offset } else { DVec3::ZERO };
+    for (&road_id, &(start, end)) in &ends {
+        reshape(network, road_id, moved_at(start), moved_at(end));
+    }
+    ends.into_keys().collect()
+}
+
+// `continuity` in the console: switches reshaping linked roads on or off.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut continuity = world.resource_mut::<Continuity>();
+    match args {
+        [] => {}
+        ["on"] => continuity.0 = true,
+        ["off"] => continuity.0 = false,
+        _ => return Err("expected on or off".to_string()),
+    }
+    Ok(if continuity.0 {
+        "linked roads follow edits".to_string()
+    } else {
+        "edits leave linked roads as they are".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cross_section;
+    use crate::sample_maps::{assert_points_near, load};
+
+    #[test]
+    fn linked_roads_follow_moves() {
+        let mut network = load("junction.xodr");
+        // Road 1 ends where the connecting road 3 starts; move it 2 m north.
+        let offset = DVec3::new(0.0, 0.0, -2.0);
+        for segment in network.segments.iter_mut().filter(|s| s.road_id == 1) {
+            segment.start_pos += offset;
+            segment.end_pos += offset;
+            for p in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                *p += offset;
+            }
+        }
+        assert_eq!(follow(&mut network, 1, offset), vec![3]);
+
+        let (first, last) = cross_section::road_range(&network, 3).unwrap();
+        let start = cross_section::cut(&network, 3, first).unwrap();
+        let end = cross_section::cut(&network, 3, last).unwrap();
+        assert_points_near(start.reference, DVec3::new(50.0, 0.0, -2.0));
+        assert_points_near(end.reference, DVec3::new(60.0, 0.0, 0.0));
+        // The headings at both joints are kept, and the road got longer.
+        assert!(start.direction.z.abs() < 0.02, "{:?}", start.direction);
+        assert!(end.direction.z.abs() < 0.02, "{:?}", end.direction);
+        assert!(last - first > 10.0);
+    }
+}
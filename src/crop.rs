@@ -0,0 +1,559 @@
+// Extraction of a sub-map covering a region.
+//
+// The region is a polygon in the map frame (x east, y north); rectangles are
+// just four-cornered polygons. A corridor is the region within some distance
+// of a set of polylines, such as the routes between two lanes (`corridor`).
+// Every lane section is clipped against it: the first lane's centerline
+// decides which stretches are inside, and all lanes of the section are cut
+// at the same polyline positions so they stay aligned. A road that leaves
+// and re-enters the region falls apart into several roads. Finally roads and
+// lane sections are renumbered from 1 so the result is a self-consistent map
+// of its own: links are kept between road ends that are both kept, and
+// junctions keep the connections whose roads are kept.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::lane_records;
+use crate::routing::Route;
+use crate::signals::{ObjectRepeat, Signal};
+use crate::xodr::{self, ElementXml};
+use crate::{ContactPoint, PlanSample, RoadInfo, RoadLink, RoadNetwork, RoadSegment};
+
+// Bisection steps when locating where a polyline crosses the region border.
+const BORDER_STEPS: usize = 24;
+
+// Route polylines are split into pieces of this many points, each with a
+// bounding box, so that points far from a piece skip its distance checks.
+const CORRIDOR_PIECE: usize = 32;
+
+// Cuts this close to either end of a polyline piece, as a fraction of it,
+// fall on the end.
+const CUT_EPSILON: f64 = 1e-6;
+
+// A closed polygon or a corridor in the map frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    Polygon(Vec<DVec2>),
+    // Polyline pieces with their bounding boxes grown by the half width.
+    Corridor {
+        pieces: Vec<(DVec2, DVec2, Vec<DVec2>)>,
+        half_width: f64,
+    },
+}
+
+impl Region {
+    pub fn polygon(corners: Vec<DVec2>) -> Self {
+        Self::Polygon(corners)
+    }
+
+    // The points within `half_width` of any of the polylines.
+    pub fn corridor(lines: &[Vec<DVec2>], half_width: f64) -> Self {
+        let mut pieces = Vec::new();
+        for line in lines.iter().filter(|line| !line.is_empty()) {
+            // Pieces share their end points so that no stretch is lost.
+            let mut start = 0;
+            loop {
+                let end = (start + CORRIDOR_PIECE).min(line.len());
+                let points = line[start..end].to_vec();
+                let (min, max) = points.iter().fold(
+                    (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+                    |(min, max), p| (min.min(*p), max.max(*p)),
+                );
+                pieces.push((min - half_width, max + half_width, points));
+                if end == line.len() {
+                    break;
+                }
+                start = end - 1;
+            }
+        }
+        Self::Corridor { pieces, half_width }
+    }
+
+    pub fn rectangle(min: DVec2, max: DVec2) -> Self {
+        Self::polygon(vec![
+            min,
+            DVec2::new(max.x, min.y),
+            max,
+            DVec2::new(min.x, max.y),
+        ])
+    }
+
+    pub fn contains(&self, p: DVec2) -> bool {
+        match self {
+            Region::Polygon(corners) => {
+                // Even-odd point in polygon test.
+                let mut inside = false;
+                let n = corners.len();
+                for i in 0..n {
+                    let a = corners[i];
+                    let b = corners[(i + n - 1) % n];
+                    if (a.y > p.y) != (b.y > p.y)
+                        && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+            Region::Corridor { pieces, half_width } => pieces.iter().any(|(min, max, points)| {
+                p.cmpge(*min).all()
+                    && p.cmple(*max).all()
+                    && (points.len() == 1 && points[0].distance(p) <= *half_width
+                        || points
+                            .windows(2)
+                            .any(|w| distance_to_piece(p, w[0], w[1]) <= *half_width))
+            }),
+        }
+    }
+
+    // Tests a viewer-space position against the region.
+    fn contains_world(&self, p: DVec3) -> bool {
+        self.contains(DVec2::new(p.x, -p.z))
+    }
+}
+
+fn distance_to_piece(p: DVec2, a: DVec2, b: DVec2) -> f64 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+// A position along a polyline: the piece index plus the fraction along it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cut {
+    piece: usize,
+    t: f64,
+}
+
+// A continuous stretch of a road inside the region, by lane section, with
+// whether it reaches the road's start and end.
+#[derive(Default)]
+struct Piece {
+    sections: Vec<Vec<RoadSegment>>,
+    starts_road: bool,
+    ends_road: bool,
+}
+
+// Returns the part of the network inside `region`, renumbered.
+pub fn crop(network: &RoadNetwork, region: &Region) -> RoadNetwork {
+    let mut sections: BTreeMap<(u32, u32), Vec<&RoadSegment>> = BTreeMap::new();
+    for segment in &network.segments {
+        sections
+            .entry((segment.road_id, segment.lane_section_id))
+            .or_default()
+            .push(segment);
+    }
+    // The first and last lane section of each road.
+    let mut extent: BTreeMap<u32, (u32, u32)> = BTreeMap::new();
+    for &(road_id, section) in sections.keys() {
+        let (first, last) = extent.entry(road_id).or_insert((section, section));
+        *first = (*first).min(section);
+        *last = (*last).max(section);
+    }
+
+    // Pieces of each original road, keyed by (road, run) where a run is one
+    // continuous stretch inside the region.
+    let mut pieces: BTreeMap<(u32, usize), Piece> = BTreeMap::new();
+    let mut runs_so_far: BTreeMap<u32, (usize, bool)> = BTreeMap::new();
+    for (&(road_id, section), lanes) in &sections {
+        let reference = lanes[0].centerline();
+        let runs = inside_runs(&reference, region);
+        // Lanes sampled differently from the first one cannot share its
+        // cuts. All importers sample a section's lanes together, so this
+        // only guards against hand-made data.
+        let lanes: Vec<&RoadSegment> = lanes
+            .iter()
+            .copied()
+            .filter(|lane| {
+                lane.left_side.len() == reference.len() && lane.right_side.len() == reference.len()
+            })
+            .collect();
+        let stations = reference_stations(&lanes);
+        let (first, last) = extent[&road_id];
+
+        // A run that starts at the very beginning of this section continues
+        // the run that ended the previous section of the same road.
+        let (mut run_index, previous_open) =
+            runs_so_far.get(&road_id).copied().unwrap_or((0, false));
+        let whole_start = Cut { piece: 0, t: 0.0 };
+        let reaches_end = |end: &Cut| end.piece + 2 >= reference.len() && end.t >= 1.0;
+        for (i, (start, end)) in runs.iter().enumerate() {
+            let continues = i == 0 && previous_open && *start == whole_start;
+            if !continues {
+                run_index += 1;
+            }
+            let clipped: Vec<RoadSegment> = lanes
+                .iter()
+                .map(|lane| clip(lane, &stations, *start, *end))
+                .collect();
+            let piece = pieces.entry((road_id, run_index)).or_default();
+            piece.starts_road |= section == first && *start == whole_start;
+            piece.ends_road |= section == last && reaches_end(end);
+            piece.sections.push(clipped);
+        }
+        let open_at_end = runs.last().is_some_and(|(_, end)| reaches_end(end));
+        runs_so_far.insert(road_id, (run_index, open_at_end));
+    }
+
+    // Renumber roads and sections from 1, keeping lanes as they were, and
+    // restart stations at zero for each new road. Each new road remembers
+    // the stretch of the original road it came from, to move signals over,
+    // and the ends of the original roads that are kept are noted, to move
+    // links over.
+    let mut segments = Vec::new();
+    let mut stretches = Vec::new();
+    let mut ends: HashMap<(u32, ContactPoint), u32> = HashMap::new();
+    for ((old_road, _), piece) in pieces {
+        let new_road = stretches.len() as u32 + 1;
+        let lanes = || piece.sections.iter().flatten();
+        let s_offset = lanes()
+            .map(|lane| lane.start_s)
+            .fold(f64::INFINITY, f64::min);
+        let s_end = lanes()
+            .map(|lane| lane.end_s)
+            .fold(f64::NEG_INFINITY, f64::max);
+        stretches.push((old_road, s_offset, s_end, new_road));
+        if piece.starts_road {
+            ends.insert((old_road, ContactPoint::Start), new_road);
+        }
+        if piece.ends_road {
+            ends.insert((old_road, ContactPoint::End), new_road);
+        }
+        for (new_section, lanes) in piece.sections.into_iter().enumerate() {
+            for mut lane in lanes {
+                lane.road_id = new_road;
+                lane.lane_section_id = new_section as u32 + 1;
+                lane.start_s -= s_offset;
+                lane.end_s -= s_offset;
+                segments.push(lane);
+            }
+        }
+    }
+    // Roads kept from end to end, which is how connecting roads must be
+    // kept for their junction to keep them.
+    let whole = |old_road: u32| {
+        let start = ends.get(&(old_road, ContactPoint::Start))?;
+        (ends.get(&(old_road, ContactPoint::End)) == Some(start)).then_some(*start)
+    };
+
+    // Links between road ends that are both kept.
+    let links = network
+        .links
+        .iter()
+        .filter_map(|link| {
+            Some(RoadLink {
+                road_id: *ends.get(&(link.road_id, link.contact))?,
+                other_road_id: *ends.get(&(link.other_road_id, link.other_contact))?,
+                ..*link
+            })
+        })
+        .collect();
+
+    // Junctions keep the connections whose roads are kept: the connecting
+    // road whole, and the incoming road at the end the connecting road is
+    // linked to.
+    let mut junctions = BTreeMap::new();
+    for (&junction, xml) in &network.junctions {
+        let mut roads: HashMap<String, u32> = HashMap::new();
+        let connecting = network
+            .roads
+            .iter()
+            .filter(|(_, info)| info.junction == Some(junction));
+        for (&old_road, _) in connecting {
+            let Some(new_road) = whole(old_road) else {
+                continue;
+            };
+            roads.insert(old_road.to_string(), new_road);
+            for link in network.links.iter().filter(|link| link.road_id == old_road) {
+                if let Some(&other) = ends.get(&(link.other_road_id, link.other_contact)) {
+                    roads.insert(link.other_road_id.to_string(), other);
+                }
+            }
+        }
+        let xml = xml.text().unwrap_or_default();
+        if let Some(xml) = xodr::remap_junction(&xml, junction, |road| roads.get(road).copied()) {
+            junctions.insert(junction, xml.into());
+        }
+    }
+
+    // Signals inside the region follow their stretch of road.
+    let signals = network
+        .signals
+        .iter()
+        .filter(|signal| region.contains_world(signal.position))
+        .filter_map(|signal| {
+            let &(_, s_offset, _, new_road) = stretches.iter().find(|(road, start, end, _)| {
+                *road == signal.road_id && (*start..=*end).contains(&signal.s)
+            })?;
+            Some(Signal {
+                road_id: new_road,
+                s: signal.s - s_offset,
+                repeats: signal
+                    .repeats
+                    .iter()
+                    .map(|repeat| ObjectRepeat {
+                        s: repeat.s - s_offset,
+                        ..repeat.clone()
+                    })
+                    .collect(),
+                ..signal.clone()
+            })
+        })
+        .collect();
+
+    // So does the plan view.
+    let roads = stretches
+        .iter()
+        .filter_map(|&(old_road, s_offset, s_end, new_road)| {
+            let info = network.roads.get(&old_road)?;
+            let plan_view = info
+                .plan_view
+                .iter()
+                .filter(|sample| (s_offset..=s_end).contains(&sample.s))
+                .map(|sample| PlanSample {
+                    s: sample.s - s_offset,
+                    ..*sample
+                })
+                .collect();
+            let junction = info.junction.filter(|junction| {
+                junctions.contains_key(junction) && whole(old_road) == Some(new_road)
+            });
+            Some((
+                new_road,
+                RoadInfo {
+                    name: info.name.clone(),
+                    plan_view,
+                    // Only written back for roads left whole: the lanes of a
+                    // cut road no longer start where the records do.
+                    reference: info.reference.clone(),
+                    junction,
+                    // The cut road is not the one that was read any more.
+                    xml: ElementXml::None,
+                },
+            ))
+        })
+        .collect();
+
+    RoadNetwork {
+        segments,
+        links,
+        signals,
+        roads,
+        junctions,
+        ..network.clone()
+    }
+}
+
+// Returns the part of the network within `width` meters of any of the
+// routes, renumbered.
+pub fn corridor(network: &RoadNetwork, routes: &[Route], width: f64) -> RoadNetwork {
+    let lines: Vec<Vec<DVec2>> = routes
+        .iter()
+        .map(|route| {
+            route
+                .polyline(network)
+                .iter()
+                .map(|point| DVec2::new(point.position.x, -point.position.z))
+                .collect()
+        })
+        .collect();
+    crop(network, &Region::corridor(&lines, width))
+}
+
+// Finds the stretches of a polyline that lie inside the region.
+fn inside_runs(points: &[DVec3], region: &Region) -> Vec<(Cut, Cut)> {
+    let mut runs = Vec::new();
+    if points.len() < 2 {
+        return runs;
+    }
+
+    let mut start = region
+        .contains_world(points[0])
+        .then_some(Cut { piece: 0, t: 0.0 });
+    for piece in 0..points.len() - 1 {
+        let (a, b) = (points[piece], points[piece + 1]);
+        let (a_in, b_in) = (region.contains_world(a), region.contains_world(b));
+        if a_in == b_in {
+            continue;
+        }
+        let t = border(a, b, a_in, region);
+        if b_in {
+            start = Some(Cut { piece, t });
+        } else if let Some(s) = start.take() {
+            runs.push((s, Cut { piece, t }));
+        }
+    }
+    if let Some(s) = start {
+        runs.push((
+            s,
+            Cut {
+                piece: points.len() - 2,
+                t: 1.0,
+            },
+        ));
+    }
+
+    // Drop runs that merely graze the region.
+    runs.retain(|(s, e)| (e.piece as f64 + e.t) - (s.piece as f64 + s.t) > 1e-3);
+    runs
+}
+
+// Locates the border crossing between `a` and `b` by bisection.
+fn border(a: DVec3, b: DVec3, a_in: bool, region: &Region) -> f64 {
+    let (mut lo, mut hi) = (0.0f64, 1.0f64);
+    for _ in 0..BORDER_STEPS {
+        let mid = (lo + hi) / 2.0;
+        if region.contains_world(a.lerp(b, mid)) == a_in {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+// Cuts a polyline between two positions. Which points are kept depends on
+// the positions alone, so every polyline of a lane section cut at the same
+// positions keeps as many.
+fn cut_polyline(points: &[DVec3], start: Cut, end: Cut) -> Vec<DVec3> {
+    let at = |cut: Cut| points[cut.piece].lerp(points[cut.piece + 1], cut.t);
+    let mut out = vec![at(start)];
+    // Cuts at the very end of a piece fall on the point after it.
+    let first = if start.t >= 1.0 - CUT_EPSILON {
+        start.piece + 2
+    } else {
+        start.piece + 1
+    };
+    out.extend_from_slice(points.get(first..=end.piece).unwrap_or_default());
+    if end.t > CUT_EPSILON || out.len() < 2 {
+        out.push(at(end));
+    }
+    out
+}
+
+// The reference line stations of the points of a lane section's polylines,
+// as fractions of the section: the length along the inner edge of its
+// innermost lane, which runs along the reference line.
+fn reference_stations(lanes: &[&RoadSegment]) -> Vec<f64> {
+    let Some(innermost) = lanes.iter().min_by_key(|lane| lane.lane_id.abs()) else {
+        return Vec::new();
+    };
+    let edge = xodr::inner_edge(innermost);
+    let mut along = vec![0.0];
+    for pair in edge.windows(2) {
+        along.push(along.last().unwrap() + pair[0].distance(pair[1]));
+    }
+    let total = along.last().copied().unwrap_or_default();
+    along
+        .into_iter()
+        .enumerate()
+        .map(|(i, length)| match total > 0.0 {
+            true => length / total,
+            false => i as f64 / (edge.len() - 1).max(1) as f64,
+        })
+        .collect()
+}
+
+// Clips a lane to the given stretch, updating its stations and end points.
+// `stations` are the reference line fractions of its section's points.
+fn clip(lane: &RoadSegment, stations: &[f64], start: Cut, end: Cut) -> RoadSegment {
+    let station = |cut: Cut| {
+        let fraction =
+            stations[cut.piece] + (stations[cut.piece + 1] - stations[cut.piece]) * cut.t;
+        lane.start_s + (lane.end_s - lane.start_s) * fraction
+    };
+
+    let left_side = cut_polyline(&lane.left_side, start, end);
+    let right_side = cut_polyline(&lane.right_side, start, end);
+    let middle = cut_polyline(&lane.centerline(), start, end);
+    let (from, to) = (station(start) - lane.start_s, station(end) - lane.start_s);
+    RoadSegment {
+        start_pos: middle[0],
+        end_pos: middle[middle.len() - 1],
+        start_s: station(start),
+        end_s: station(end),
+        left_side,
+        right_side,
+        road_marks: lane_records::within(&lane.road_marks, from, to),
+        center_marks: lane_records::within(&lane.center_marks, from, to),
+        materials: lane_records::within(&lane.materials, from, to),
+        ..lane.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    fn x_between(min: f64, max: f64) -> Region {
+        Region::rectangle(DVec2::new(min, -100.0), DVec2::new(max, 100.0))
+    }
+
+    #[test]
+    fn cut_lanes_keep_reference_stations() {
+        // The quarter circle of radius 50 around (0, 50), cut where the
+        // first lane's centerline reaches x = 25.
+        let cropped = crop(&load("curve.xodr"), &x_between(-10.0, 25.0));
+        assert_eq!(cropped.segments.len(), 2);
+        let first = &cropped.segments[0];
+        let end = DVec2::new(first.end_pos.x, -first.end_pos.z);
+        let angle = end.x.atan2(50.0 - end.y);
+        for lane in &cropped.segments {
+            assert!((lane.end_s - 50.0 * angle).abs() < 0.01, "{}", lane.end_s);
+            assert_eq!(lane.start_s, 0.0);
+            assert_eq!(lane.left_side.len(), first.left_side.len());
+            assert_eq!(lane.right_side.len(), first.left_side.len());
+        }
+    }
+
+    #[test]
+    fn cuts_at_sample_points_keep_polylines_aligned() {
+        let network = load("straight.xodr");
+        let points = network.segments[0].left_side.len();
+        // The region's edge falls on a sample point of every lane.
+        let cropped = crop(&network, &x_between(-10.0, 40.0));
+        for lane in &cropped.segments {
+            assert_eq!(lane.left_side.len(), lane.right_side.len());
+            assert_eq!(lane.left_side.len(), cropped.segments[0].left_side.len());
+            assert!(lane.left_side.len() < points);
+            assert!((lane.end_s - 40.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn links_and_junctions_follow_the_kept_roads() {
+        let network = load("junction.xodr");
+
+        // Everything inside: roads 1 to 3 keep their numbers.
+        let all = crop(&network, &x_between(-10.0, 120.0));
+        assert_eq!(all.links, network.links);
+        assert_eq!(all.roads[&3].junction, Some(100));
+        assert!(all.junctions[&100]
+            .text()
+            .unwrap()
+            .contains("incomingRoad=\"1\" connectingRoad=\"3\""));
+
+        // West cut short: the junction still connects its kept end.
+        let east_part = crop(&network, &x_between(20.0, 120.0));
+        assert_eq!(east_part.links.len(), network.links.len());
+        assert!(east_part.junctions.contains_key(&100));
+
+        // The connecting road cut: the junction goes and only its link to
+        // East stays.
+        let cut = crop(&network, &x_between(55.0, 120.0));
+        assert!(cut.junctions.is_empty());
+        assert!(cut.roads.values().all(|info| info.junction.is_none()));
+        let ids: Vec<u32> = cut.roads.keys().copied().collect();
+        assert_eq!(cut.links.len(), 1);
+        for link in &cut.links {
+            assert!(ids.contains(&link.road_id) && ids.contains(&link.other_road_id));
+        }
+    }
+}
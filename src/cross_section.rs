@@ -0,0 +1,387 @@
+// Cross-section tool for reviewing a road corridor.
+//
+// A vertical clipping plane is placed across a road at a chosen s-station.
+// Road meshes ahead of the plane are cut away, so the surface ends at the
+// plane and its profile can be seen edge-on, and a readout lists the profile
+// at that station: the lateral extent and width of each lane, its cross
+// slope (superelevation) and the height step at its inner edge, which is
+// where curbs show up. The plane is part of the road meshes like the debug
+// views, so moving it reloads the road tiles.
+//
+// Keys: X places the plane on the road nearest the camera focus (or removes
+// it), [ and ] move it along the road by a meter, ten with Shift.
+
+use std::fmt::Write as _;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::origin::RenderOrigin;
+use crate::theme::Theme;
+use crate::tiles::ReloadTiles;
+use crate::{camera_orbit, CameraOrbit, MainCamera, RoadNetwork, RoadSegment};
+
+// Half the width and the height of the drawn plane, in meters.
+const PLANE_HALF_WIDTH: f32 = 20.0;
+const PLANE_HEIGHT: f32 = 8.0;
+
+// Where the cross-section is taken, if anywhere.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct CrossSection {
+    pub station: Option<(u32, f64)>,
+}
+
+// A vertical plane; geometry on the side the normal points to is clipped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipPlane {
+    pub point: DVec3,
+    pub normal: DVec3,
+}
+
+impl ClipPlane {
+    // Whether a viewer-frame position is on the kept side.
+    pub fn keeps(&self, p: DVec3) -> bool {
+        (p - self.point).dot(self.normal) <= 0.0
+    }
+}
+
+// One lane where the plane cuts it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneCut {
+    pub lane_id: i32,
+    // Boundary positions at the station, inner edge first.
+    pub inner: DVec3,
+    pub outer: DVec3,
+    // Lateral offsets of the edges from the reference line, positive left.
+    pub inner_t: f64,
+    pub outer_t: f64,
+}
+
+impl LaneCut {
+    pub fn width(&self) -> f64 {
+        (self.outer_t - self.inner_t).abs()
+    }
+
+    // Rise per meter moving outwards from the reference line.
+    pub fn cross_slope(&self) -> f64 {
+        let width = self.width();
+        if width > f64::EPSILON {
+            (self.outer.y - self.inner.y) / width
+        } else {
+            0.0
+        }
+    }
+}
+
+// The road profile at one station.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cut {
+    pub road_id: u32,
+    pub s: f64,
+    // Reference line position and direction of increasing s.
+    pub reference: DVec3,
+    pub direction: DVec3,
+    // Lanes from the leftmost to the rightmost.
+    pub lanes: Vec<LaneCut>,
+}
+
+impl Cut {
+    // Rise per meter from the right edge of the road to the left edge.
+    pub fn superelevation(&self) -> Option<f64> {
+        let (left, right) = (self.lanes.first()?, self.lanes.last()?);
+        let span = left.outer_t - right.outer_t;
+        (span > f64::EPSILON).then(|| (left.outer.y - right.outer.y) / span)
+    }
+
+    pub fn plane(&self) -> ClipPlane {
+        ClipPlane {
+            point: self.reference,
+            normal: self.direction,
+        }
+    }
+}
+
+// Marks the on-screen readout.
+#[derive(Component)]
+struct Readout;
+
+pub struct CrossSectionPlugin;
+
+impl Plugin for CrossSectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrossSection>()
+            .add_systems(Startup, spawn_readout)
+            .add_systems(
+                Update,
+                (move_plane, (update_readout, draw_plane).after(camera_orbit)),
+            );
+    }
+}
+
+// The station range covered by a road's lanes.
+pub fn road_range(network: &RoadNetwork, road_id: u32) -> Option<(f64, f64)> {
+    network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .map(|segment| (segment.start_s, segment.end_s))
+        .reduce(|(a0, a1), (b0, b1)| (a0.min(b0), a1.max(b1)))
+}
+
+// Interpolates a boundary polyline at station `s`. Boundaries are sampled
+// evenly between the segment's start and end stations.
+pub fn boundary_at(segment: &RoadSegment, points: &[DVec3], s: f64) -> Option<DVec3> {
+    let last = points.len().checked_sub(1)?;
+    let length = segment.end_s - segment.start_s;
+    if last == 0 || length <= f64::EPSILON {
+        return points.first().copied();
+    }
+    let f = ((s - segment.start_s) / length).clamp(0.0, 1.0) * last as f64;
+    let i = (f.floor() as usize).min(last - 1);
+    Some(points[i].lerp(points[i + 1], f - i as f64))
+}
+
+// Cuts a road across at station `s`.
+pub fn cut(network: &RoadNetwork, road_id: u32, s: f64) -> Option<Cut> {
+    let road: Vec<&RoadSegment> = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .collect();
+    cut_road(&road, s)
+}
+
+// Like `cut`, given the lanes of one road.
+pub fn cut_road(road: &[&RoadSegment], s: f64) -> Option<Cut> {
+    let section = road
+        .iter()
+        .filter(|segment| segment.start_s <= s && s <= segment.end_s)
+        .map(|segment| segment.lane_section_id)
+        .min()?;
+    let mut lanes: Vec<&RoadSegment> = road
+        .iter()
+        .filter(|segment| segment.lane_section_id == section)
+        .copied()
+        .collect();
+    lanes.sort_by_key(|lane| -lane.lane_id);
+
+    // The reference line is the inner edge of the innermost lane; its
+    // direction is taken over a short stretch around the station.
+    let innermost = *lanes.iter().min_by_key(|lane| lane.lane_id.abs())?;
+    let edge = crate::xodr::inner_edge(innermost);
+    let reference = boundary_at(innermost, edge, s)?;
+    let step = 0.5;
+    let ahead = boundary_at(innermost, edge, s + step)? - boundary_at(innermost, edge, s - step)?;
+    let direction = DVec3::new(ahead.x, 0.0, ahead.z).normalize_or_zero();
+    if direction == DVec3::ZERO {
+        return None;
+    }
+    let left = DVec3::Y.cross(direction);
+
+    let lanes = lanes
+        .into_iter()
+        .filter_map(|lane| {
+            let l = boundary_at(lane, &lane.left_side, s)?;
+            let r = boundary_at(lane, &lane.right_side, s)?;
+            let (inner, outer) = if lane.lane_id > 0 { (r, l) } else { (l, r) };
+            Some(LaneCut {
+                lane_id: lane.lane_id,
+                inner,
+                outer,
+                inner_t: (inner - reference).dot(left),
+                outer_t: (outer - reference).dot(left),
+            })
+        })
+        .collect();
+    Some(Cut {
+        road_id: innermost.road_id,
+        s,
+        reference,
+        direction,
+        lanes,
+    })
+}
+
+// The road nearest to a viewer-frame point, and the station there.
+fn nearest_station(network: &RoadNetwork, p: DVec3) -> Option<(u32, f64)> {
+    let mut best: Option<(f64, u32, f64)> = None;
+    for segment in &network.segments {
+        let middle = segment.centerline();
+        let last = middle.len().saturating_sub(1).max(1) as f64;
+        for (i, point) in middle.iter().enumerate() {
+            let distance = point.distance(p);
+            if matches!(best, Some((d, _, _)) if d <= distance) {
+                continue;
+            }
+            let s = segment.start_s + (segment.end_s - segment.start_s) * i as f64 / last;
+            best = Some((distance, segment.road_id, s));
+        }
+    }
+    best.map(|(_, road_id, s)| (road_id, s))
+}
+
+fn move_plane(
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    cameras: Query<&CameraOrbit, With<MainCamera>>,
+    mut section: ResMut<CrossSection>,
+    mut reload: EventWriter<ReloadTiles>,
+) {
+    let before = *section;
+    if keys.just_pressed(KeyCode::KeyX) {
+        section.station = match section.station {
+            Some(_) => None,
+            None => cameras
+                .get_single()
+                .ok()
+                .and_then(|orbit| nearest_station(&network, origin.0 + orbit.center.as_dvec3())),
+        };
+    }
+    if let Some((road_id, s)) = section.station.as_mut() {
+        let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            10.0
+        } else {
+            1.0
+        };
+        if keys.just_pressed(KeyCode::BracketRight) {
+            *s += step;
+        }
+        if keys.just_pressed(KeyCode::BracketLeft) {
+            *s -= step;
+        }
+        if let Some((start, end)) = road_range(&network, *road_id) {
+            *s = s.clamp(start, end);
+        }
+    }
+    if *section != before {
+        reload.send(ReloadTiles);
+    }
+}
+
+fn spawn_readout(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Readout,
+    ));
+}
+
+// Lists the profile at the plane, one line per lane.
+fn update_readout(
+    section: Res<CrossSection>,
+    network: Res<RoadNetwork>,
+    mut readouts: Query<(&mut Text, &mut Visibility), With<Readout>>,
+) {
+    if !section.is_changed() {
+        return;
+    }
+    let Ok((mut text, mut visibility)) = readouts.get_single_mut() else {
+        return;
+    };
+    let Some(cut) = section
+        .station
+        .and_then(|(road_id, s)| cut(&network, road_id, s))
+    else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+    *text = Text::from_section(
+        describe(&cut),
+        TextStyle {
+            font_size: 14.0,
+            color: Color::WHITE,
+            ..default()
+        },
+    );
+}
+
+// Formats a cut as a small table. Offsets and heights are relative to the
+// reference line at the station; the step is the height change from the
+// neighbouring lane's edge to this lane's inner edge.
+pub fn describe(cut: &Cut) -> String {
+    let mut out = format!(
+        "road {}  s = {:.2} m  (z = {:.2} m)\n lane      t from .. to       width   slope    step\n",
+        cut.road_id, cut.s, cut.reference.y
+    );
+    // Steps are measured outwards, so each side starts at the reference.
+    let previous = |lane: &LaneCut| {
+        cut.lanes
+            .iter()
+            .filter(|other| other.lane_id.signum() == lane.lane_id.signum())
+            .filter(|other| other.lane_id.abs() + 1 == lane.lane_id.abs())
+            .map(|other| other.outer.y)
+            .next()
+            .unwrap_or(cut.reference.y)
+    };
+    for lane in &cut.lanes {
+        let step = lane.inner.y - previous(lane);
+        let _ = writeln!(
+            out,
+            "{:>5}  {:>8.2} .. {:>8.2}  {:>6.2} m  {:>5.1} %  {:>5.2} m",
+            lane.lane_id,
+            lane.inner_t,
+            lane.outer_t,
+            lane.width(),
+            lane.cross_slope() * 100.0,
+            step,
+        );
+    }
+    if let Some(superelevation) = cut.superelevation() {
+        let _ = write!(
+            out,
+            "superelevation {:.1} % (left edge over right edge)",
+            superelevation * 100.0
+        );
+    }
+    out
+}
+
+// Draws the plane and the profile line where it cuts the road.
+fn draw_plane(
+    section: Res<CrossSection>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let Some(cut) = section
+        .station
+        .and_then(|(road_id, s)| cut(&network, road_id, s))
+    else {
+        return;
+    };
+    let center = origin.to_render(cut.reference);
+    let across = DVec3::Y.cross(cut.direction).as_vec3() * PLANE_HALF_WIDTH;
+    let up = Vec3::Y * PLANE_HEIGHT;
+    let corners = [
+        center - across - up / 2.0,
+        center + across - up / 2.0,
+        center + across + up,
+        center - across + up,
+        center - across - up / 2.0,
+    ];
+    gizmos.linestrip(corners, theme.cut_plane);
+
+    // The profile runs across the lane edges from left to right.
+    let mut edges: Vec<(f64, DVec3)> = cut
+        .lanes
+        .iter()
+        .flat_map(|lane| [(lane.inner_t, lane.inner), (lane.outer_t, lane.outer)])
+        .collect();
+    edges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let profile = edges
+        .into_iter()
+        .map(|(_, p)| origin.to_render(p) + Vec3::Y * 0.05);
+    gizmos.linestrip(profile, theme.cut_profile);
+}
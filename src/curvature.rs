@@ -0,0 +1,194 @@
+// Heading and curvature plot of the selected road.
+//
+// Next to the elevation profile, a second chart shows the heading of the
+// selected road's reference line and its curvature against s. Both come from
+// the OpenDRIVE plan view where the map has one, sampled on either side of
+// every joint between geometry pieces, so a joint where the curvature jumps
+// shows up as a vertical step. Maps without a plan view are measured off the
+// sampled reference line instead. The title counts the steps found.
+
+use std::f64::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::chart::{
+    spawn_chart, ChartContent, ChartData, ChartHover, Panel, Series, BOTTOM_ROW, CHART_WIDTH,
+};
+use crate::cross_section::{cut, cut_road, road_range};
+use crate::i18n::Locale;
+use crate::origin::RenderOrigin;
+use crate::ribbons::Ribbons;
+use crate::selection::Selection;
+use crate::theme::Theme;
+use crate::{camera_orbit, PlanSample, RoadNetwork, RoadSegment};
+
+// Curvature changes larger than this (per meter) at a single station count
+// as a step.
+const CURVATURE_STEP: f64 = 1e-3;
+
+// Heading changes larger than this (in radians) at a single station count as
+// a kink.
+const HEADING_STEP: f64 = 0.01;
+
+// Sample spacing when measuring the reference line, in meters.
+const SAMPLE_STEP: f64 = 1.0;
+
+// Marks the curvature chart.
+#[derive(Component)]
+struct CurvatureChart;
+
+pub struct CurvaturePlugin;
+
+impl Plugin for CurvaturePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, |mut commands: Commands| {
+            spawn_chart(
+                &mut commands,
+                CHART_WIDTH as f32 + 30.0,
+                BOTTOM_ROW,
+                CurvatureChart,
+            );
+        })
+        .add_systems(Update, (show_curvature, mark_station.after(camera_orbit)));
+    }
+}
+
+// The plan view of a road, or an estimate from its lanes if it has none.
+pub fn plan_view(network: &RoadNetwork, road_id: u32) -> Vec<PlanSample> {
+    match network.roads.get(&road_id) {
+        Some(info) if !info.plan_view.is_empty() => info.plan_view.clone(),
+        _ => measure_reference(network, road_id),
+    }
+}
+
+// Measures heading and curvature off the sampled reference line.
+fn measure_reference(network: &RoadNetwork, road_id: u32) -> Vec<PlanSample> {
+    let Some((start, end)) = road_range(network, road_id) else {
+        return Vec::new();
+    };
+    let road: Vec<&RoadSegment> = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .collect();
+    let count = (((end - start) / SAMPLE_STEP).ceil() as usize).max(1);
+    let mut samples: Vec<PlanSample> = (0..=count)
+        .filter_map(|i| {
+            let s = start + (end - start) * i as f64 / count as f64;
+            let cut = cut_road(&road, s)?;
+            // North is -z in the viewer frame.
+            Some(PlanSample {
+                s,
+                heading: (-cut.direction.z).atan2(cut.direction.x),
+                curvature: 0.0,
+            })
+        })
+        .collect();
+    unwrap_headings(&mut samples);
+    for i in 0..samples.len() {
+        let (a, b) = (i.saturating_sub(1), (i + 1).min(samples.len() - 1));
+        let ds = samples[b].s - samples[a].s;
+        if ds > f64::EPSILON {
+            samples[i].curvature = (samples[b].heading - samples[a].heading) / ds;
+        }
+    }
+    samples
+}
+
+// Removes the jumps of 2π where a heading wraps around.
+fn unwrap_headings(samples: &mut [PlanSample]) {
+    for i in 1..samples.len() {
+        let previous = samples[i - 1].heading;
+        let mut heading = samples[i].heading;
+        while heading - previous > PI {
+            heading -= 2.0 * PI;
+        }
+        while heading - previous < -PI {
+            heading += 2.0 * PI;
+        }
+        samples[i].heading = heading;
+    }
+}
+
+// Counts the stations where heading or curvature jump: consecutive samples
+// at (nearly) the same station with different values.
+pub fn count_steps(samples: &[PlanSample]) -> usize {
+    samples
+        .windows(2)
+        .filter(|pair| pair[1].s - pair[0].s < 1e-6)
+        .filter(|pair| {
+            (pair[1].curvature - pair[0].curvature).abs() > CURVATURE_STEP
+                || (pair[1].heading - pair[0].heading).abs() > HEADING_STEP
+        })
+        .count()
+}
+
+// Rebuilds the chart when the selected road changes.
+fn show_curvature(
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    locale: Res<Locale>,
+    mut charts: Query<&mut ChartData, With<CurvatureChart>>,
+    mut shown: Local<Option<u32>>,
+) {
+    if selection.road_id() == *shown {
+        return;
+    }
+    *shown = selection.road_id();
+    let content = shown.and_then(|road_id| {
+        let mut samples = plan_view(&network, road_id);
+        if samples.is_empty() {
+            return None;
+        }
+        unwrap_headings(&mut samples);
+        let steps = count_steps(&samples);
+        Some(ChartContent {
+            title: locale.text("curvature-title", &[("road", &road_id), ("steps", &steps)]),
+            stations: samples.iter().map(|sample| sample.s).collect(),
+            panels: vec![
+                Panel {
+                    unit: "deg",
+                    series: vec![Series {
+                        label: locale.text("curvature-heading", &[]),
+                        values: samples.iter().map(|p| p.heading.to_degrees()).collect(),
+                        color: [200, 160, 255, 255],
+                    }],
+                },
+                Panel {
+                    unit: "1/m",
+                    series: vec![Series {
+                        label: locale.text("curvature-curvature", &[]),
+                        values: samples.iter().map(|p| p.curvature).collect(),
+                        color: [255, 110, 110, 255],
+                    }],
+                },
+            ],
+        })
+    });
+    for mut chart in &mut charts {
+        chart.0.clone_from(&content);
+    }
+}
+
+// Marks the station under the chart cursor on the road.
+fn mark_station(
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    charts: Query<&ChartHover, With<CurvatureChart>>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    let Some(road_id) = selection.road_id() else {
+        return;
+    };
+    for hover in &charts {
+        let Some(cut) = hover.0.and_then(|s| cut(&network, road_id, s)) else {
+            continue;
+        };
+        let p = origin.to_render(cut.reference);
+        let color = theme.curvature_cursor;
+        ribbons.line(p, p + Vec3::Y * 5.0, color);
+        ribbons.arrow(p, p + cut.direction.as_vec3() * 4.0, color);
+    }
+}
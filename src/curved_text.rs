@@ -0,0 +1,143 @@
+// Text laid along curves.
+//
+// Map labels read best when they follow what they name: a road's name
+// bending with the road, like on a printed map. Text nodes cannot bend, so
+// curved text is set one glyph at a time, each glyph centered on the curve
+// and turned to its direction there. Lines on screen run in any direction;
+// text along one that runs leftwards is laid from its other end, so that it
+// never reads upside down. Curves that are too short for the text, or that
+// bend too sharply under it, are refused, and the caller falls back to
+// straight text.
+
+use bevy::prelude::*;
+
+// Glyph widths are estimated, as a fraction of the font size; the labels are
+// short enough for the estimate to do.
+const GLYPH_WIDTH: f32 = 0.6;
+const SPACE_WIDTH: f32 = 0.35;
+
+// The most consecutive glyphs may turn against each other, in radians.
+pub const MAX_BEND: f32 = 0.6;
+
+// A glyph placed along a curve: its center on screen, and its angle from
+// the screen's x axis, clockwise as the screen's y axis points down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    pub character: char,
+    pub center: Vec2,
+    pub angle: f32,
+}
+
+// The estimated advance of each character of `text` at a font size.
+pub fn advances(text: &str, font_size: f32) -> Vec<f32> {
+    text.chars()
+        .map(|c| {
+            font_size
+                * if c.is_whitespace() {
+                    SPACE_WIDTH
+                } else {
+                    GLYPH_WIDTH
+                }
+        })
+        .collect()
+}
+
+// Lays `text` along the middle of a screen-space polyline, or returns `None`
+// if it does not fit.
+pub fn lay_out(text: &str, font_size: f32, path: &[Vec2]) -> Option<Vec<PlacedGlyph>> {
+    let advances = advances(text, font_size);
+    let mut path = path.to_vec();
+    let (first, last) = (*path.first()?, *path.last()?);
+    if last.x < first.x {
+        path.reverse();
+    }
+    let mut lengths = vec![0.0];
+    for pair in path.windows(2) {
+        lengths.push(lengths[lengths.len() - 1] + pair[0].distance(pair[1]));
+    }
+    let total = lengths[lengths.len() - 1];
+    let width: f32 = advances.iter().sum();
+    if width == 0.0 || width > total {
+        return None;
+    }
+
+    let mut glyphs = Vec::new();
+    let mut start = (total - width) / 2.0;
+    for (character, advance) in text.chars().zip(advances) {
+        let before = point_along(&path, &lengths, start);
+        let after = point_along(&path, &lengths, start + advance);
+        let direction = after - before;
+        let angle = direction.y.atan2(direction.x);
+        if let Some(previous) = glyphs.last().map(|glyph: &PlacedGlyph| glyph.angle) {
+            if turn(previous, angle).abs() > MAX_BEND {
+                return None;
+            }
+        }
+        glyphs.push(PlacedGlyph {
+            character,
+            center: (before + after) / 2.0,
+            angle,
+        });
+        start += advance;
+    }
+    Some(glyphs)
+}
+
+// The point at a distance along a polyline, given its cumulative lengths.
+fn point_along(path: &[Vec2], lengths: &[f32], distance: f32) -> Vec2 {
+    let i = lengths
+        .partition_point(|length| *length < distance)
+        .clamp(1, path.len() - 1);
+    let span = lengths[i] - lengths[i - 1];
+    let t = if span > 0.0 {
+        ((distance - lengths[i - 1]) / span).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    path[i - 1].lerp(path[i], t)
+}
+
+// The signed difference between two angles, within half a turn.
+fn turn(from: f32, to: f32) -> f32 {
+    let d = (to - from).rem_euclid(std::f32::consts::TAU);
+    if d > std::f32::consts::PI {
+        d - std::f32::consts::TAU
+    } else {
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_follows_curves() {
+        // Text is centered along the curve and follows its direction.
+        let arc: Vec<Vec2> = (0..=20)
+            .map(|k| {
+                let a = k as f32 * 0.05;
+                Vec2::new(a.sin(), 1.0 - a.cos()) * 200.0
+            })
+            .collect();
+        let glyphs = lay_out("road 7", 13.0, &arc).unwrap();
+        assert_eq!(glyphs.len(), 6);
+        assert_eq!(glyphs[5].character, '7');
+        assert!(glyphs.windows(2).all(|pair| pair[1].angle > pair[0].angle));
+        assert!(glyphs
+            .iter()
+            .all(|glyph| glyph.angle > 0.0 && glyph.angle < 1.0));
+
+        // A curve running leftwards is read from its other end, upright.
+        let reversed: Vec<Vec2> = arc.iter().rev().copied().collect();
+        let glyphs = lay_out("road 7", 13.0, &reversed).unwrap();
+        assert!(glyphs[0].center.x < glyphs[5].center.x);
+
+        // Too short, or too sharp a corner under the text.
+        let short = [Vec2::ZERO, Vec2::new(20.0, 0.0)];
+        assert!(lay_out("road 7", 13.0, &short).is_none());
+        let corner = [Vec2::ZERO, Vec2::new(40.0, 0.0), Vec2::new(40.0, 40.0)];
+        assert!(lay_out("s=50", 13.0, &corner).is_none());
+        assert!(lay_out("s=50", 13.0, &corner[..2]).is_some());
+    }
+}
@@ -0,0 +1,96 @@
+// Debug render modes for tessellation problems.
+//
+// F2 overlays the triangle edges of every road mesh, F3 draws each vertex
+// normal as a short line, and F4 highlights back faces: a second copy of each
+// surface is drawn with front-face culling in a loud color, so triangles
+// with flipped winding (which would otherwise vanish from above) show up
+// magenta. The extra meshes are built with the road meshes, so toggling a
+// mode reloads the road tiles.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::tessellation::TriangleMesh;
+use crate::tiles::ReloadTiles;
+
+// Lines are raised by this much (in meters) so they are not hidden inside the
+// surface they describe.
+const LINE_LIFT: f32 = 0.02;
+
+// Length of the drawn normals, in meters.
+const NORMAL_LENGTH: f32 = 0.5;
+
+// Which debug modes are on.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DebugView {
+    pub wireframe: bool,
+    pub normals: bool,
+    pub backfaces: bool,
+}
+
+pub struct DebugViewPlugin;
+
+impl Plugin for DebugViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugView>()
+            .add_systems(Update, toggle_debug_view);
+    }
+}
+
+fn toggle_debug_view(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut view: ResMut<DebugView>,
+    mut reload: EventWriter<ReloadTiles>,
+) {
+    let before = *view;
+    if keys.just_pressed(KeyCode::F2) {
+        view.wireframe = !view.wireframe;
+    }
+    if keys.just_pressed(KeyCode::F3) {
+        view.normals = !view.normals;
+    }
+    if keys.just_pressed(KeyCode::F4) {
+        view.backfaces = !view.backfaces;
+    }
+    if *view != before {
+        info!("debug view: {:?}", *view);
+        reload.send(ReloadTiles);
+    }
+}
+
+// The edges of every triangle, each drawn once.
+pub fn wireframe_lines(mesh: &TriangleMesh) -> Mesh {
+    let mut edges = HashSet::new();
+    for triangle in mesh.indices.chunks_exact(3) {
+        for k in 0..3 {
+            let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+            edges.insert((a.min(b), a.max(b)));
+        }
+    }
+    let lift = Vec3::Y * LINE_LIFT;
+    let positions: Vec<Vec3> = edges
+        .into_iter()
+        .flat_map(|(a, b)| [mesh.positions[a as usize], mesh.positions[b as usize]])
+        .map(|p| p + lift)
+        .collect();
+    line_list(positions)
+}
+
+// A short line along the normal of every vertex.
+pub fn normal_lines(mesh: &TriangleMesh) -> Mesh {
+    let positions: Vec<Vec3> = mesh
+        .positions
+        .iter()
+        .zip(&mesh.normals)
+        .flat_map(|(p, n)| [*p, *p + *n * NORMAL_LENGTH])
+        .collect();
+    line_list(positions)
+}
+
+fn line_list(positions: Vec<Vec3>) -> Mesh {
+    Mesh::new(PrimitiveTopology::LineList, RenderAssetUsages::RENDER_WORLD)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+}
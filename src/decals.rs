@@ -0,0 +1,620 @@
+// Decals: surface features painted onto the lanes.
+//
+// A decal lies flat on the road surface at a station and lateral offset of
+// a road, `length` meters along the road and `width` across it, bending with
+// the road. There are three kinds: a pothole marker, a patch of newer
+// asphalt, and a text stencil such as "BUS", whose letters are stretched
+// along the road as painted ones are and read in the direction of the lane's
+// traffic.
+//
+// Decals are kept in one of two places:
+//
+// - the annotation sidecar (see `annotations`), for findings that are not
+//   part of the map: `decal pothole|patch <road> <s> <t> [<length>
+//   <width>]` or `decal text <road> <s> <t> <text>` in the console add one,
+//   and `decal remove <id>` drops it;
+// - the map itself, as OpenDRIVE objects: `<object type="patch"
+//   name="pothole">` or `name="patch"`, and `<object type="roadMark"
+//   subtype="text">` named by its text. `decal to-map <id>` moves a sidecar
+//   decal into the map, as an edit that can be undone.
+//
+// `decals` lists both kinds.
+
+use std::collections::HashMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::annotations::Annotations;
+use crate::canvas::Canvas;
+use crate::cross_section::{cut, Cut};
+use crate::edit::NetworkChanged;
+use crate::json::Json;
+use crate::origin::WorldPosition;
+use crate::sign_edit::{facing, foot, next_id};
+use crate::signals::{Signal, SignalKind};
+use crate::transaction::{self, Transaction};
+use crate::xodr::ElementXml;
+use crate::RoadNetwork;
+
+// Height of decals above the road surface, in meters; above the markings.
+const LIFT: f64 = 0.02;
+
+// Spacing of the rows of a decal's mesh along the road, in meters.
+const ROW_SPACING: f64 = 1.0;
+
+// Pixels per font pixel in stencil textures.
+const STENCIL_SCALE: i32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecalKind {
+    Pothole,
+    Patch,
+    Stencil(String),
+}
+
+impl DecalKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DecalKind::Pothole => "pothole",
+            DecalKind::Patch => "patch",
+            DecalKind::Stencil(_) => "text",
+        }
+    }
+
+    // Length and width, in meters, if not given.
+    fn size(&self) -> (f64, f64) {
+        match self {
+            DecalKind::Pothole => (0.8, 0.6),
+            DecalKind::Patch => (3.0, 2.0),
+            DecalKind::Stencil(_) => (4.0, 2.0),
+        }
+    }
+
+    // The OpenDRIVE object type, subtype and name.
+    fn object_type(&self) -> (&'static str, &'static str, String) {
+        match self {
+            DecalKind::Pothole => ("patch", "", "pothole".to_string()),
+            DecalKind::Patch => ("patch", "", "patch".to_string()),
+            DecalKind::Stencil(text) => ("roadMark", "text", text.clone()),
+        }
+    }
+
+    // Identifies the texture of the kind.
+    fn key(&self) -> String {
+        match self {
+            DecalKind::Stencil(text) => format!("text:{text}"),
+            kind => kind.name().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decal {
+    // Numbers decals in the sidecar; 0 for those of the map, which are
+    // known by their object IDs.
+    pub id: u32,
+    pub kind: DecalKind,
+    pub road_id: u32,
+    pub s: f64,
+    pub t: f64,
+    pub length: f64,
+    pub width: f64,
+}
+
+impl Decal {
+    pub fn new(kind: DecalKind, road_id: u32, s: f64, t: f64) -> Self {
+        let (length, width) = kind.size();
+        Self {
+            id: 0,
+            kind,
+            road_id,
+            s,
+            t,
+            length,
+            width,
+        }
+    }
+
+    // The decal an object of the map stands for, if any.
+    pub fn of_object(object: &Signal) -> Option<Self> {
+        if object.kind != SignalKind::Object || !object.repeats.is_empty() {
+            return None;
+        }
+        let kind = match object.type_code.trim() {
+            "patch" if object.name.to_ascii_lowercase().contains("pothole") => DecalKind::Pothole,
+            "patch" => DecalKind::Patch,
+            "roadMark" if object.subtype.trim() == "text" && !object.name.trim().is_empty() => {
+                DecalKind::Stencil(object.name.trim().to_string())
+            }
+            _ => return None,
+        };
+        let (length, width) = kind.size();
+        let size = |value: f64, default: f64| if value > 0.0 { value } else { default };
+        Some(Self {
+            length: size(object.length, length),
+            width: size(object.width, width),
+            ..Self::new(kind, object.road_id, object.s, object.t)
+        })
+    }
+
+    // The object that stands for the decal in the map.
+    pub fn to_object(&self, network: &RoadNetwork, id: String) -> Result<Signal, String> {
+        let (type_code, subtype, name) = self.kind.object_type();
+        Ok(Signal {
+            id,
+            name,
+            road_id: self.road_id,
+            s: self.s,
+            t: self.t,
+            z_offset: 0.0,
+            height: 0.0,
+            width: self.width,
+            length: self.length,
+            radius: 0.0,
+            orientation: facing(network, self.road_id, self.s, self.t).to_string(),
+            kind: SignalKind::Object,
+            type_code: type_code.to_string(),
+            subtype: subtype.to_string(),
+            country: String::new(),
+            dynamic: false,
+            value: None,
+            unit: String::new(),
+            position: foot(network, self.road_id, self.s, self.t)?,
+            repeats: Vec::new(),
+            xml: ElementXml::None,
+        })
+    }
+
+    fn describe(&self) -> String {
+        let what = match &self.kind {
+            DecalKind::Stencil(text) => format!("text \"{text}\""),
+            kind => kind.name().to_string(),
+        };
+        format!(
+            "{what} on road {} at s {:.1}, t {:.1}, {:.1} by {:.1} m",
+            self.road_id, self.s, self.t, self.length, self.width
+        )
+    }
+}
+
+pub fn to_json(decals: &[Decal]) -> Json {
+    Json::Array(
+        decals
+            .iter()
+            .map(|decal| {
+                let mut fields = vec![
+                    ("id".to_string(), Json::from(decal.id)),
+                    ("kind".to_string(), Json::from(decal.kind.name())),
+                ];
+                if let DecalKind::Stencil(text) = &decal.kind {
+                    fields.push(("text".to_string(), Json::from(text.as_str())));
+                }
+                fields.extend([
+                    ("road".to_string(), Json::from(decal.road_id)),
+                    ("s".to_string(), Json::from(decal.s)),
+                    ("t".to_string(), Json::from(decal.t)),
+                    ("length".to_string(), Json::from(decal.length)),
+                    ("width".to_string(), Json::from(decal.width)),
+                ]);
+                Json::Object(fields)
+            })
+            .collect(),
+    )
+}
+
+// Reads the `decals` list of a sidecar file; none if it has none.
+pub fn from_json(root: &Json) -> Result<Vec<Decal>, String> {
+    let Some(items) = root.get("decals") else {
+        return Ok(Vec::new());
+    };
+    items
+        .as_array()
+        .ok_or("`decals` is not a list")?
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let invalid = |what: &str| format!("decal {}: {what}", index + 1);
+            let number = |key: &str| {
+                item.get(key)
+                    .and_then(Json::as_f64)
+                    .ok_or_else(|| invalid(&format!("no `{key}`")))
+            };
+            let kind = match item.get("kind").and_then(Json::as_str) {
+                Some("pothole") => DecalKind::Pothole,
+                Some("patch") => DecalKind::Patch,
+                Some("text") => DecalKind::Stencil(
+                    item.get("text")
+                        .and_then(Json::as_str)
+                        .ok_or_else(|| invalid("no `text`"))?
+                        .to_string(),
+                ),
+                _ => return Err(invalid("`kind` must be pothole, patch or text")),
+            };
+            Ok(Decal {
+                id: number("id")? as u32,
+                kind,
+                road_id: number("road")? as u32,
+                s: number("s")?,
+                t: number("t")?,
+                length: number("length")?,
+                width: number("width")?,
+            })
+        })
+        .collect()
+}
+
+// The decals of the sidecar and of the map.
+pub fn all(annotations: &Annotations, network: &RoadNetwork) -> Vec<Decal> {
+    annotations
+        .decals
+        .iter()
+        .cloned()
+        .chain(network.signals.iter().filter_map(Decal::of_object))
+        .collect()
+}
+
+// The height of the road surface at t, on the lane there; the reference
+// line's beside the road.
+fn surface_height(cut: &Cut, t: f64) -> f64 {
+    cut.lanes
+        .iter()
+        .find(|lane| {
+            let (low, high) = (
+                lane.inner_t.min(lane.outer_t),
+                lane.inner_t.max(lane.outer_t),
+            );
+            low <= t && t <= high && high > low
+        })
+        .map_or(cut.reference.y, |lane| {
+            let f = (t - lane.inner_t) / (lane.outer_t - lane.inner_t);
+            lane.inner.y + (lane.outer.y - lane.inner.y) * f
+        })
+}
+
+// A decal's mesh on the road: positions relative to `anchor`, and texture
+// coordinates that read in the direction of the lane's traffic. None if the
+// road does not reach.
+pub fn decal_mesh(network: &RoadNetwork, decal: &Decal) -> Option<(DVec3, Mesh)> {
+    let rows = (decal.length / ROW_SPACING).ceil().max(1.0) as usize;
+    let forward = facing(network, decal.road_id, decal.s, decal.t) == "+";
+    let mut points = Vec::new();
+    let mut uvs = Vec::new();
+    for row in 0..=rows {
+        let along = decal.length * (row as f64 / rows as f64 - 0.5);
+        let cut = cut(network, decal.road_id, decal.s + along)?;
+        let left = DVec3::Y.cross(cut.direction);
+        for across in [0.5, -0.5] {
+            let t = decal.t + decal.width * across;
+            let height = surface_height(&cut, t) + LIFT;
+            points.push(cut.reference + left * t + DVec3::Y * (height - cut.reference.y));
+            // Rows of the texture run from the far end down, columns from
+            // the driver's left.
+            let (u, v) = (0.5 - across, 0.5 - along / decal.length);
+            uvs.push(if forward {
+                [u as f32, v as f32]
+            } else {
+                [1.0 - u as f32, 1.0 - v as f32]
+            });
+        }
+    }
+    let anchor = points[0];
+    let positions: Vec<[f32; 3]> = points
+        .iter()
+        .map(|p| (*p - anchor).as_vec3().to_array())
+        .collect();
+    let mut indices = Vec::new();
+    for row in 0..rows as u32 {
+        let (a, b) = (row * 2, row * 2 + 2);
+        indices.extend([a, a + 1, b, b, a + 1, b + 1]);
+    }
+    let normals = vec![[0.0, 1.0, 0.0]; positions.len()];
+    let mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices));
+    Some((anchor, mesh))
+}
+
+// Paints the texture of a kind of decal.
+pub fn paint(kind: &DecalKind) -> Canvas {
+    match kind {
+        DecalKind::Pothole => {
+            let mut canvas = Canvas::new(64, 64);
+            let center = Vec2::splat(32.0);
+            // A ragged hole with a lighter, broken rim.
+            let edge = |p: Vec2, radius: f32| {
+                let d = p - center;
+                let angle = d.y.atan2(d.x);
+                let ragged = 1.0 + 0.12 * (5.0 * angle).sin() + 0.06 * (11.0 * angle + 1.0).sin();
+                (d / Vec2::new(1.0, 0.8)).length() < radius * ragged
+            };
+            canvas.fill(|p| edge(p, 28.0), [95, 92, 88, 255]);
+            canvas.fill(|p| edge(p, 24.0), [28, 26, 24, 255]);
+            canvas
+        }
+        DecalKind::Patch => {
+            let mut canvas = Canvas::new(64, 64);
+            canvas.fill(|_| true, [110, 110, 112, 255]);
+            canvas.fill(
+                |p| p.x > 2.0 && p.x < 62.0 && p.y > 2.0 && p.y < 62.0,
+                [38, 38, 41, 255],
+            );
+            canvas
+        }
+        DecalKind::Stencil(text) => {
+            let count = text.chars().count().max(1) as i32;
+            let margin = STENCIL_SCALE;
+            let mut canvas = Canvas::new(
+                (count * 4 - 1) * STENCIL_SCALE + 2 * margin,
+                5 * STENCIL_SCALE + 2 * margin,
+            );
+            let center = Vec2::new(
+                ((count * 4 - 1) * STENCIL_SCALE + 2 * margin) as f32 / 2.0,
+                (5 * STENCIL_SCALE + 2 * margin) as f32 / 2.0,
+            );
+            canvas.text(text, center, STENCIL_SCALE, [236, 236, 230, 255]);
+            canvas
+        }
+    }
+}
+
+fn number(text: &str, what: &str) -> Result<f64, String> {
+    text.parse().map_err(|_| format!("invalid {what} `{text}`"))
+}
+
+fn road(text: &str) -> Result<u32, String> {
+    text.parse().map_err(|_| format!("invalid road `{text}`"))
+}
+
+// Adds a decal to the sidecar and saves it.
+pub fn add(world: &mut World, mut decal: Decal) -> Result<String, String> {
+    let network = world.resource::<RoadNetwork>();
+    if cut(network, decal.road_id, decal.s).is_none() {
+        return Err(format!("road {} has no s {:.2}", decal.road_id, decal.s));
+    }
+    if decal.length <= 0.0 || decal.width <= 0.0 {
+        return Err("a decal needs a positive length and width".to_string());
+    }
+    let mut annotations = world.resource_mut::<Annotations>();
+    decal.id = annotations.decals.iter().map(|d| d.id).max().unwrap_or(0) + 1;
+    let id = decal.id;
+    annotations.decals.push(decal);
+    annotations.save()?;
+    Ok(format!("decal {id} added"))
+}
+
+fn take(world: &mut World, id: u32) -> Result<Decal, String> {
+    let mut annotations = world.resource_mut::<Annotations>();
+    let index = annotations
+        .decals
+        .iter()
+        .position(|decal| decal.id == id)
+        .ok_or_else(|| format!("no decal {id}"))?;
+    Ok(annotations.decals.remove(index))
+}
+
+// Moves a sidecar decal into the map as an object.
+pub fn to_map(world: &mut World, id: u32) -> Result<String, String> {
+    let decal = world
+        .resource::<Annotations>()
+        .decals
+        .iter()
+        .find(|decal| decal.id == id)
+        .cloned()
+        .ok_or_else(|| format!("no decal {id}"))?;
+    let road_id = decal.road_id;
+    let transaction = Transaction::begin(
+        world.resource::<RoadNetwork>(),
+        format!("adding a decal to road {road_id}"),
+        &[road_id],
+    );
+    let message = transaction::run(world, transaction, |network| {
+        let object = decal.to_object(network, next_id(network))?;
+        let message = format!("decal {id} is object {} of road {road_id}", object.id);
+        network.signals.push(object);
+        Ok((message, Vec::new()))
+    })?;
+    take(world, id)?;
+    world.resource::<Annotations>().save()?;
+    Ok(message)
+}
+
+// `decal` in the console.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let decal = |kind: DecalKind, place: &[&str]| -> Result<Decal, String> {
+        let [road_id, s, t] = place else {
+            return Err("expected <road> <s> <t>".to_string());
+        };
+        Ok(Decal::new(
+            kind,
+            road(road_id)?,
+            number(s, "s")?,
+            number(t, "t")?,
+        ))
+    };
+    match args {
+        [] => Ok(describe(world)),
+        [kind @ ("pothole" | "patch"), rest @ ..] => {
+            let kind = if *kind == "pothole" {
+                DecalKind::Pothole
+            } else {
+                DecalKind::Patch
+            };
+            let decal = match rest {
+                [place @ .., length, width] if rest.len() == 5 => Decal {
+                    length: number(length, "length")?,
+                    width: number(width, "width")?,
+                    ..decal(kind, place)?
+                },
+                place => decal(kind, place)?,
+            };
+            add(world, decal)
+        }
+        ["text", road_id, s, t, text @ ..] if !text.is_empty() => {
+            let decal = decal(DecalKind::Stencil(text.join(" ")), &[road_id, s, t])?;
+            add(world, decal)
+        }
+        ["remove", id] => {
+            let id = id.parse().map_err(|_| format!("invalid decal `{id}`"))?;
+            take(world, id)?;
+            world.resource::<Annotations>().save()?;
+            Ok(format!("decal {id} removed"))
+        }
+        ["to-map", id] => to_map(
+            world,
+            id.parse().map_err(|_| format!("invalid decal `{id}`"))?,
+        ),
+        _ => Err("expected pothole|patch <road> <s> <t> [<length> <width>], \
+             text <road> <s> <t> <text>, remove <id> or to-map <id>"
+            .to_string()),
+    }
+}
+
+// Lists the decals, the sidecar's by number and the map's by object ID.
+pub fn describe(world: &World) -> String {
+    let annotations = world.resource::<Annotations>();
+    let network = world.resource::<RoadNetwork>();
+    let mut lines: Vec<String> = annotations
+        .decals
+        .iter()
+        .map(|decal| format!("{} {}", decal.id, decal.describe()))
+        .collect();
+    lines.extend(network.signals.iter().filter_map(|object| {
+        let decal = Decal::of_object(object)?;
+        Some(format!("object {} {}", object.id, decal.describe()))
+    }));
+    if lines.is_empty() {
+        return "no decals".to_string();
+    }
+    lines.join("\n")
+}
+
+// Marks a decal's mesh.
+#[derive(Component)]
+struct DecalMesh;
+
+// Materials of the decal kinds, by texture.
+#[derive(Resource, Default)]
+struct DecalMaterials(HashMap<String, Handle<StandardMaterial>>);
+
+pub struct DecalPlugin;
+
+impl Plugin for DecalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DecalMaterials>()
+            .add_systems(Startup, spawn_decals)
+            .add_systems(
+                Update,
+                (despawn_decals, spawn_decals).chain().run_if(
+                    on_event::<NetworkChanged>().or_else(|annotations: Res<Annotations>| {
+                        annotations.is_changed() && !annotations.is_added()
+                    }),
+                ),
+            );
+    }
+}
+
+fn despawn_decals(mut commands: Commands, decals: Query<Entity, With<DecalMesh>>) {
+    for decal in &decals {
+        commands.entity(decal).despawn();
+    }
+}
+
+fn spawn_decals(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut decal_materials: ResMut<DecalMaterials>,
+    annotations: Res<Annotations>,
+    network: Res<RoadNetwork>,
+) {
+    for decal in all(&annotations, &network) {
+        let Some((anchor, mesh)) = decal_mesh(&network, &decal) else {
+            continue;
+        };
+        let material = decal_materials
+            .0
+            .entry(decal.kind.key())
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color_texture: Some(images.add(paint(&decal.kind).into_image())),
+                    alpha_mode: AlphaMode::Blend,
+                    perceptual_roughness: 0.9,
+                    // Whichever way the road runs, the decal faces up.
+                    cull_mode: None,
+                    ..default()
+                })
+            })
+            .clone();
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh),
+                material,
+                ..default()
+            },
+            WorldPosition(anchor),
+            DecalMesh,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use crate::sample_maps::{assert_points_near, load};
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    #[test]
+    fn sidecar() {
+        let stencil = Decal::new(DecalKind::Stencil("Bus".to_string()), 1, 50.0, -1.75);
+        let pothole = Decal::new(DecalKind::Pothole, 1, 20.0, 1.0);
+        let list = vec![Decal { id: 1, ..stencil }, Decal { id: 2, ..pothole }];
+        let sidecar = Json::Object(vec![("decals".to_string(), to_json(&list))]);
+        let root = json::parse(&sidecar.write()).unwrap();
+        assert_eq!(from_json(&root), Ok(list));
+    }
+
+    #[test]
+    fn decals_are_drawn_and_kept_as_objects() {
+        let mut network = load("straight.xodr");
+        let stencil = Decal::new(DecalKind::Stencil("Bus".to_string()), 1, 50.0, -1.75);
+        let (anchor, mesh) = decal_mesh(&network, &stencil).unwrap();
+        // Four rows a meter apart, two points each, just above the surface.
+        assert_eq!(mesh.count_vertices(), 10);
+        assert!((anchor.y - 0.02).abs() < 1e-9);
+        assert_points_near(anchor, DVec3::new(48.0, 0.02, 1.75 - 1.0));
+        assert!(decal_mesh(&network, &Decal::new(DecalKind::Patch, 9, 0.0, 0.0)).is_none());
+        assert!(paint(&stencil.kind).into_image().width() > 40);
+
+        // Decals round-trip through the map as objects.
+        let pothole = Decal {
+            id: 2,
+            ..Decal::new(DecalKind::Pothole, 1, 20.0, 1.0)
+        };
+        let object = stencil.to_object(&network, "7".to_string()).unwrap();
+        assert_eq!(
+            (object.type_code.as_str(), object.name.as_str()),
+            ("roadMark", "Bus")
+        );
+        network.signals.push(object);
+        network
+            .signals
+            .push(pothole.to_object(&network, "8".to_string()).unwrap());
+        let back = xodr::read_str(&xodr::to_xml(&network), &LoadTransform::default()).unwrap();
+        let read: Vec<Decal> = back.signals.iter().filter_map(Decal::of_object).collect();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].kind, stencil.kind);
+        assert_eq!(read[1].kind, DecalKind::Pothole);
+        assert!((read[1].length - 0.8).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,458 @@
+// Road design rule checks.
+//
+// A lightweight design audit of every road against user-set standards:
+//
+// - grade: the reference line may not climb or fall more steeply than
+//   `max_grade` percent;
+// - curve radius: the tighter the radius, the lower the speed it allows;
+//   each speed class has a minimum radius, and a curve on a road whose lanes
+//   give a higher speed limit than its radius allows breaks the rule. Roads
+//   without a speed limit are not held to it;
+// - transitions: curvature should build up along a spiral of at least
+//   `min_transition` meters. Spirals that are shorter break the rule, and so
+//   do places where the curvature jumps between pieces, unless both sides
+//   are gentler than `TRANSITION_RADIUS`.
+//
+// The reference line is the inner edge of the innermost lane of each lane
+// section, as in `xodr`'s writer; curvature comes from the plan view, so
+// only maps read from OpenDRIVE are checked for radius and transitions.
+//
+// Stretches that break a rule go into the validation report and are
+// highlighted along the reference line in the color of the rule.
+
+use std::collections::HashMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::edit::NetworkChanged;
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::ribbons::Ribbons;
+use crate::theme::Theme;
+use crate::units::KMH;
+use crate::validation::{Issue, Severity, ShowIssues, ValidationSettings};
+use crate::{camera_orbit, PlanSample, RoadNetwork, RoadSegment};
+
+// The speed classes, in km/h, each with a minimum radius in the settings.
+pub const SPEED_CLASSES: [f64; 9] = [30.0, 40.0, 50.0, 60.0, 70.0, 80.0, 90.0, 100.0, 120.0];
+
+// Curves gentler than this radius on both sides of a curvature jump need no
+// transition, in meters.
+const TRANSITION_RADIUS: f64 = 1000.0;
+
+// Highlights are lifted by this much to stay above the markings.
+const HIGHLIGHT_LIFT: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DesignRuleSettings {
+    // Steepest grade, in percent.
+    pub max_grade: f64,
+    // Smallest curve radius per speed class (see `SPEED_CLASSES`), in
+    // meters.
+    pub min_radius: [f64; 9],
+    // Shortest spiral, in meters.
+    pub min_transition: f64,
+}
+
+impl Default for DesignRuleSettings {
+    fn default() -> Self {
+        Self {
+            max_grade: 8.0,
+            min_radius: [25.0, 50.0, 80.0, 125.0, 175.0, 250.0, 340.0, 440.0, 750.0],
+            min_transition: 30.0,
+        }
+    }
+}
+
+impl DesignRuleSettings {
+    // The smallest radius a speed allows: that of the lowest class at or
+    // above it.
+    pub fn radius_for(&self, speed: f64) -> f64 {
+        SPEED_CLASSES
+            .iter()
+            .position(|&class| class >= speed - 1e-9)
+            .map_or(self.min_radius[SPEED_CLASSES.len() - 1], |class| {
+                self.min_radius[class]
+            })
+    }
+
+    // Sets the minimum radius of a class from `<speed>:<radius>`.
+    pub fn set_radius(&mut self, text: &str) -> Result<(), String> {
+        let invalid = || format!("invalid speed class `{text}`, expected <km/h>:<m>");
+        let (speed, radius) = text.split_once(':').ok_or_else(invalid)?;
+        let speed: f64 = speed.trim().parse().map_err(|_| invalid())?;
+        let radius: f64 = radius.trim().parse().map_err(|_| invalid())?;
+        let class = SPEED_CLASSES
+            .iter()
+            .position(|&class| (class - speed).abs() < 1e-9)
+            .ok_or_else(|| {
+                format!("no speed class {speed} km/h, expected one of {SPEED_CLASSES:?}")
+            })?;
+        if radius < 0.0 {
+            return Err(invalid());
+        }
+        self.min_radius[class] = radius;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Grade,
+    Radius,
+    Transition,
+}
+
+impl Rule {
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            Rule::Grade => theme.steep,
+            Rule::Radius => theme.tight,
+            Rule::Transition => theme.abrupt,
+        }
+    }
+}
+
+// A stretch of a road that breaks a rule.
+struct Stretch {
+    road_id: u32,
+    rule: Rule,
+    start: f64,
+    end: f64,
+    // The steepest grade, tightest radius or shortest transition, and the
+    // limit it broke.
+    worst: f64,
+    limit: f64,
+    // The reference line along the stretch, viewer frame.
+    points: Vec<DVec3>,
+}
+
+// The stretches to highlight, as polylines in the viewer frame.
+#[derive(Resource, Default)]
+struct DesignHighlights(Vec<(Rule, Vec<DVec3>)>);
+
+pub struct DesignRulePlugin;
+
+impl Plugin for DesignRulePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DesignHighlights>()
+            .add_systems(Startup, find_highlights)
+            .add_systems(Update, find_highlights.run_if(on_event::<NetworkChanged>()))
+            .add_systems(Update, draw_highlights.after(camera_orbit))
+            .add_systems(
+                Update,
+                add_legend
+                    .in_set(FillLegend)
+                    .run_if(|show: Res<ShowIssues>| show.0),
+            );
+    }
+}
+
+fn add_legend(
+    highlights: Res<DesignHighlights>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut legend: ResMut<Legend>,
+) {
+    let rows = [
+        (Rule::Grade, "legend-grade"),
+        (Rule::Radius, "legend-radius"),
+        (Rule::Transition, "legend-transition"),
+    ]
+    .into_iter()
+    .filter(|(rule, _)| highlights.0.iter().any(|(shown, _)| shown == rule))
+    .map(|(rule, message)| (rule.color(&theme), locale.text(message, &[])))
+    .collect();
+    legend.add(locale.text("legend-design", &[]), rows);
+}
+
+// Checks every road against the design rules.
+pub fn check(network: &RoadNetwork, settings: &DesignRuleSettings) -> Vec<Issue> {
+    stretches(network, settings)
+        .into_iter()
+        .map(|stretch| {
+            let (start, end) = (stretch.start, stretch.end);
+            let message = match stretch.rule {
+                Rule::Grade => format!(
+                    "grade up to {:.1}% from s={start:.1} to s={end:.1} (limit {:.1}%)",
+                    stretch.worst, stretch.limit
+                ),
+                Rule::Radius => format!(
+                    "radius down to {:.0} m from s={start:.1} to s={end:.1}, \
+                     below the {:.0} m its speed limit needs",
+                    stretch.worst, stretch.limit
+                ),
+                Rule::Transition if stretch.worst > 0.0 => format!(
+                    "transition of {:.1} m from s={start:.1} (at least {:.1} m)",
+                    stretch.worst, stretch.limit
+                ),
+                Rule::Transition => {
+                    format!("curvature changes at s={start:.1} without a transition")
+                }
+            };
+            Issue {
+                check: "design",
+                severity: Severity::Warning,
+                road_id: stretch.road_id,
+                lane_id: None,
+                s: start,
+                position: stretch.points.first().copied().unwrap_or_default(),
+                message,
+            }
+        })
+        .collect()
+}
+
+// The reference line of a road as stations and points: the inner edge of
+// the innermost lane of each lane section. Stations are spread over a
+// section's samples as `lane_width` does.
+fn reference_line(lanes: &[&RoadSegment]) -> Vec<(f64, DVec3)> {
+    let mut inner = lanes.to_vec();
+    inner.sort_by_key(|segment| (segment.lane_section_id, segment.lane_id.abs()));
+    inner.dedup_by_key(|segment| segment.lane_section_id);
+    let mut line = Vec::new();
+    for segment in inner {
+        let edge = if segment.lane_id > 0 {
+            &segment.right_side
+        } else {
+            &segment.left_side
+        };
+        let count = edge.len();
+        for (i, p) in edge.iter().enumerate() {
+            let fraction = i as f64 / count.saturating_sub(1).max(1) as f64;
+            let s = segment.start_s + (segment.end_s - segment.start_s) * fraction;
+            line.push((s, *p));
+        }
+    }
+    line
+}
+
+// The highest speed limit of a road's lanes at a station.
+fn speed_at(lanes: &[&RoadSegment], s: f64) -> Option<f64> {
+    lanes
+        .iter()
+        .filter(|segment| segment.start_s <= s + 1e-9 && s <= segment.end_s + 1e-9)
+        .filter_map(|segment| segment.speed)
+        .reduce(f64::max)
+}
+
+// Adds the stations from `from` to `to` to the last stretch if they
+// continue it, or starts a new one.
+fn extend(
+    out: &mut Vec<Stretch>,
+    road_id: u32,
+    rule: Rule,
+    (from, to): (f64, f64),
+    worst: f64,
+    limit: f64,
+) {
+    let tighter = |a: f64, b: f64| match rule {
+        Rule::Grade => a.max(b),
+        Rule::Radius | Rule::Transition => a.min(b),
+    };
+    match out.last_mut() {
+        Some(last) if last.road_id == road_id && last.rule == rule && last.end >= from - 1e-9 => {
+            last.end = last.end.max(to);
+            last.worst = tighter(last.worst, worst);
+            last.limit = last.limit.max(limit);
+        }
+        _ => out.push(Stretch {
+            road_id,
+            rule,
+            start: from,
+            end: to,
+            worst,
+            limit,
+            points: Vec::new(),
+        }),
+    }
+}
+
+fn stretches(network: &RoadNetwork, settings: &DesignRuleSettings) -> Vec<Stretch> {
+    let mut by_road: HashMap<u32, Vec<&RoadSegment>> = HashMap::new();
+    for segment in &network.segments {
+        by_road.entry(segment.road_id).or_default().push(segment);
+    }
+    let mut out = Vec::new();
+    for (&road_id, info) in &network.roads {
+        let lanes = by_road.get(&road_id).map_or(&[][..], Vec::as_slice);
+        let first_stretch = out.len();
+
+        // Grade, between consecutive reference line samples.
+        let line = reference_line(lanes);
+        for pair in line.windows(2) {
+            let (a, b) = (pair[0].1, pair[1].1);
+            let run = (b.x - a.x).hypot(b.z - a.z);
+            if run < 1e-6 {
+                continue;
+            }
+            let grade = 100.0 * (b.y - a.y).abs() / run;
+            if grade > settings.max_grade {
+                let stations = (pair[0].0, pair[1].0);
+                extend(
+                    &mut out,
+                    road_id,
+                    Rule::Grade,
+                    stations,
+                    grade,
+                    settings.max_grade,
+                );
+            }
+        }
+
+        // Radius, at each plan view sample; a stretch runs on while the
+        // samples after it break the rule too.
+        let mut previous: Option<f64> = None;
+        for sample in &info.plan_view {
+            let needed = speed_at(lanes, sample.s).map(|speed| settings.radius_for(speed / KMH));
+            let radius = 1.0 / sample.curvature.abs().max(f64::MIN_POSITIVE);
+            match needed {
+                Some(needed) if radius < needed => {
+                    let from = previous.unwrap_or(sample.s);
+                    extend(
+                        &mut out,
+                        road_id,
+                        Rule::Radius,
+                        (from, sample.s),
+                        radius,
+                        needed,
+                    );
+                    previous = Some(sample.s);
+                }
+                _ => previous = None,
+            }
+        }
+
+        // Transitions. The plan view repeats the station where two pieces
+        // meet, so pieces are the runs between repeats.
+        let mut pieces: Vec<&[PlanSample]> = Vec::new();
+        let mut first = 0;
+        for i in 1..=info.plan_view.len() {
+            let ends = i == info.plan_view.len()
+                || (info.plan_view[i].s - info.plan_view[i - 1].s).abs() < 1e-9;
+            if ends {
+                pieces.push(&info.plan_view[first..i]);
+                first = i;
+            }
+        }
+        let gentle = |k: f64| k.abs() < 1.0 / TRANSITION_RADIUS;
+        for (k, piece) in pieces.iter().enumerate() {
+            let (Some(start), Some(end)) = (piece.first(), piece.last()) else {
+                continue;
+            };
+            let length = end.s - start.s;
+            let spiral = (end.curvature - start.curvature).abs() > 1e-9;
+            if spiral && length < settings.min_transition {
+                let at = (start.s, start.s);
+                extend(
+                    &mut out,
+                    road_id,
+                    Rule::Transition,
+                    at,
+                    length,
+                    settings.min_transition,
+                );
+            }
+            // A jump from the end of the previous piece.
+            if let Some(before) = k.checked_sub(1).and_then(|j| pieces[j].last()) {
+                let jump = (before.curvature - start.curvature).abs() > 1e-6;
+                if jump && !(gentle(before.curvature) && gentle(start.curvature)) {
+                    let at = (start.s, start.s);
+                    extend(
+                        &mut out,
+                        road_id,
+                        Rule::Transition,
+                        at,
+                        0.0,
+                        settings.min_transition,
+                    );
+                }
+            }
+        }
+
+        for stretch in &mut out[first_stretch..] {
+            stretch.points = line
+                .iter()
+                .filter(|(s, _)| *s >= stretch.start - 1e-9 && *s <= stretch.end + 1e-9)
+                .map(|(_, p)| *p)
+                .collect();
+            // A single station is marked by the sample at or after it.
+            if stretch.points.is_empty() {
+                let after = line.iter().find(|(s, _)| *s >= stretch.start);
+                stretch.points.extend(after.map(|(_, p)| *p));
+            }
+        }
+    }
+    out
+}
+
+fn find_highlights(
+    network: Res<RoadNetwork>,
+    settings: Res<ValidationSettings>,
+    mut highlights: ResMut<DesignHighlights>,
+) {
+    highlights.0 = stretches(&network, &settings.design)
+        .into_iter()
+        .map(|stretch| (stretch.rule, stretch.points))
+        .collect();
+}
+
+fn draw_highlights(
+    show: Res<ShowIssues>,
+    highlights: Res<DesignHighlights>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    if !show.0 {
+        return;
+    }
+    for (rule, points) in &highlights.0 {
+        let lifted: Vec<Vec3> = points
+            .iter()
+            .map(|p| origin.to_render(*p) + Vec3::Y * HIGHLIGHT_LIFT)
+            .collect();
+        match lifted.as_slice() {
+            [] => {}
+            [p] => ribbons.line(*p, *p + Vec3::Y * 2.0, rule.color(&theme)),
+            _ => ribbons.linestrip(lifted, rule.color(&theme)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{load, SAMPLES};
+
+    #[test]
+    fn roads_are_held_to_grade_and_radius() {
+        let settings = DesignRuleSettings::default();
+        for name in SAMPLES {
+            assert!(check(&load(name), &settings).is_empty(), "{name}");
+        }
+
+        // The climb is at 5%.
+        let steep = DesignRuleSettings {
+            max_grade: 4.0,
+            ..settings
+        };
+        let found = check(&load("elevation.xodr"), &steep);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].message.starts_with("grade up to 5.0% from s=0.0"));
+
+        // The 50 m radius allows 40 km/h, not 60.
+        let mut curve = load("curve.xodr");
+        for segment in &mut curve.segments {
+            segment.speed = Some(60.0 * KMH);
+        }
+        let found = check(&curve, &settings);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].message,
+            "radius down to 50 m from s=0.0 to s=78.5, below the 125 m its speed limit needs"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Display preferences for coordinates and stations.
+//
+// How many decimals positions and (s, t) stations are written with, and
+// whether positions are written as map coordinates (x, y, z in meters) or as
+// latitude and longitude. Geographic coordinates need a georeferenced map
+// (see `geo`); other maps fall back to map coordinates. The inspector and
+// the status bar follow these preferences; logs, exports and the clipboard
+// keep full precision.
+//
+// The preferences are changed in the settings panel (see `settings`) or
+// with `display` in the console, and kept for the next session in
+// `display.txt` in the config directory.
+
+use std::path::PathBuf;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::bookmarks::config_dir;
+use crate::geo;
+use crate::RoadNetwork;
+
+// The most decimals offered.
+pub const MAX_DECIMALS: usize = 6;
+
+// Degrees take this many more decimals than meters: 1e-5° of latitude is
+// about a meter.
+const DEGREE_DECIMALS: usize = 5;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplaySettings {
+    // Decimals of positions, in meters.
+    pub coordinate_decimals: usize,
+    // Decimals of s and t, in meters.
+    pub station_decimals: usize,
+    // Whether positions are shown as latitude and longitude.
+    pub geographic: bool,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            coordinate_decimals: 2,
+            station_decimals: 2,
+            geographic: false,
+        }
+    }
+}
+
+impl DisplaySettings {
+    // Reads the saved preferences; the defaults if there are none.
+    pub fn load() -> Self {
+        file()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let file = file().ok_or("no config directory")?;
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        }
+        std::fs::write(&file, self.format()).map_err(|e| format!("{}: {e}", file.display()))
+    }
+
+    // One `key = value` per line. Unknown keys and bad values are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut settings = Self::default();
+        for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            let decimals = value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&n| n <= MAX_DECIMALS);
+            match (key.trim(), value.trim()) {
+                ("coordinate-decimals", _) => {
+                    settings.coordinate_decimals = decimals.unwrap_or(settings.coordinate_decimals)
+                }
+                ("station-decimals", _) => {
+                    settings.station_decimals = decimals.unwrap_or(settings.station_decimals)
+                }
+                ("coordinates", "local") => settings.geographic = false,
+                ("coordinates", "geographic") => settings.geographic = true,
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    pub fn format(&self) -> String {
+        format!(
+            "coordinate-decimals = {}\nstation-decimals = {}\ncoordinates = {}\n",
+            self.coordinate_decimals,
+            self.station_decimals,
+            self.coordinates_name()
+        )
+    }
+
+    pub fn coordinates_name(&self) -> &'static str {
+        if self.geographic {
+            "geographic"
+        } else {
+            "local"
+        }
+    }
+
+    // A station or lateral offset, in meters.
+    pub fn station(&self, s: f64) -> String {
+        format!("{s:.*}", self.station_decimals)
+    }
+
+    // A viewer-frame position: latitude and longitude if they are wanted and
+    // the map is georeferenced, else x, y and z in the map frame.
+    pub fn position(&self, network: &RoadNetwork, p: DVec3) -> String {
+        if self.geographic {
+            if let Some(text) = self.geographic_position(network, p) {
+                return text;
+            }
+        }
+        let decimals = self.coordinate_decimals;
+        format!(
+            "{:.*}, {:.*}, {:.*}",
+            decimals,
+            p.x,
+            decimals,
+            -p.z + 0.0,
+            decimals,
+            p.y
+        )
+    }
+
+    // Latitude and longitude of a viewer-frame position, whatever is wanted,
+    // if the map is georeferenced.
+    pub fn geographic_position(&self, network: &RoadNetwork, p: DVec3) -> Option<String> {
+        let lon_lat = geo::geographic(network, p)?;
+        let decimals = self.coordinate_decimals + DEGREE_DECIMALS;
+        Some(format!(
+            "{:.*}°, {:.*}°",
+            decimals, lon_lat.y, decimals, lon_lat.x
+        ))
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{} coordinates with {} decimals, stations with {}",
+            self.coordinates_name(),
+            self.coordinate_decimals,
+            self.station_decimals
+        )
+    }
+}
+
+fn file() -> Option<PathBuf> {
+    Some(config_dir()?.join("display.txt"))
+}
+
+// `display` in the console: describes the preferences, or changes one with
+// `coordinates <decimals>`, `stations <decimals>`, `local` or `geographic`.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let decimals = |text: &str| {
+        text.parse::<usize>()
+            .ok()
+            .filter(|&n| n <= MAX_DECIMALS)
+            .ok_or_else(|| format!("expected 0 to {MAX_DECIMALS} decimals, not `{text}`"))
+    };
+    let mut settings = world.resource_mut::<DisplaySettings>();
+    match args {
+        [] => return Ok(settings.describe()),
+        ["coordinates", n] => settings.coordinate_decimals = decimals(n)?,
+        ["stations", n] => settings.station_decimals = decimals(n)?,
+        ["local"] => settings.geographic = false,
+        ["geographic"] => settings.geographic = true,
+        _ => {
+            return Err(
+                "expected coordinates <decimals>, stations <decimals>, local or geographic"
+                    .to_string(),
+            )
+        }
+    }
+    Ok(String::new())
+}
+
+pub struct DisplayPlugin;
+
+impl Plugin for DisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DisplaySettings::load())
+            .add_systems(Update, save_settings);
+    }
+}
+
+fn save_settings(settings: Res<DisplaySettings>) {
+    if settings.is_changed() && !settings.is_added() {
+        if let Err(message) = settings.save() {
+            warn!("could not save the display settings: {message}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::GeoReference;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn settings() {
+        let display = DisplaySettings {
+            geographic: true,
+            coordinate_decimals: 1,
+            station_decimals: 3,
+        };
+        assert_eq!(display.station(12.34567), "12.346");
+        assert_eq!(DisplaySettings::parse(&display.format()), display);
+        assert_eq!(
+            DisplaySettings::parse("station-decimals = 99\ncoordinates = geographic"),
+            DisplaySettings {
+                geographic: true,
+                ..DisplaySettings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn positions_are_shown_to_the_chosen_precision() {
+        let mut network = load("straight.xodr");
+        let mut display = DisplaySettings::default();
+        // Maps without a georeference are shown in map coordinates.
+        assert_eq!(
+            display.position(&network, DVec3::new(1.0, 2.0, -3.0)),
+            "1.00, 3.00, 2.00"
+        );
+        network.geo = Some(GeoReference::parse("+proj=tmerc +lat_0=49 +lon_0=8").unwrap());
+        assert_eq!(display.position(&network, DVec3::ZERO), "0.00, 0.00, 0.00");
+        display.geographic = true;
+        display.coordinate_decimals = 1;
+        assert_eq!(
+            display.position(&network, DVec3::ZERO),
+            "49.000000°, 8.000000°"
+        );
+        display.station_decimals = 3;
+        assert_eq!(display.station(12.34567), "12.346");
+        network.geo = None;
+        assert_eq!(
+            display.position(&network, DVec3::new(1.0, 2.0, -3.0)),
+            "1.0, 3.0, 2.0"
+        );
+    }
+}
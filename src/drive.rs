@@ -0,0 +1,307 @@
+// Drive-along camera: following a lane from the driver's seat.
+//
+// With a lane selected, F12 puts the main camera at `EYE_HEIGHT` above the
+// lane's centerline where it was picked, looking the way traffic goes in
+// that lane: along the reference line or against it, as the road's traffic
+// rule (RHT or LHT) decides (see `RoadSegment::follows_reference`). The
+// camera drives at the lane's speed limit and carries on into the first
+// lane that follows at the lane's end (see `routing::lane_graph`), stopping
+// where none does. Leaving puts the orbit camera back where it was.
+//
+// Keys: F12 starts or stops driving, Up and Down change the speed, Space
+// pauses.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::camera_tween::OrbitPose;
+use crate::conflicts;
+use crate::origin::RenderOrigin;
+use crate::routing::{self, DEFAULT_SPEED};
+use crate::selection::Selection;
+use crate::units::KMH;
+use crate::walk::WalkMode;
+use crate::{camera_input, camera_orbit, CameraOrbit, MainCamera, RoadNetwork};
+
+// Height of the driver's eye above the lane, in meters.
+const EYE_HEIGHT: f64 = 1.2;
+
+// Up and Down change the speed by this much, in km/h.
+const SPEED_STEP: f64 = 10.0;
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct DriveMode {
+    pub active: bool,
+    paused: bool,
+    // Index into `RoadNetwork::segments`, and the distance driven along its
+    // path (see `conflicts::path`).
+    lane: usize,
+    along: f64,
+    // Added to the lanes' speed limits, in meters per second.
+    speed_change: f64,
+    // The lanes following each lane, worked out when driving starts.
+    graph: Vec<Vec<usize>>,
+    // The orbit camera to return to.
+    saved: Option<OrbitPose>,
+}
+
+pub struct DrivePlugin;
+
+impl Plugin for DrivePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DriveMode>().add_systems(
+            Update,
+            (
+                (toggle_drive, drive)
+                    .chain()
+                    .after(camera_input)
+                    .before(camera_orbit),
+                place_driver.after(camera_orbit),
+            ),
+        );
+    }
+}
+
+// Moves `distance` further along the lanes from `along` on `lane`, into
+// the first following lane wherever one ends. None past the end of a lane
+// that nothing follows.
+fn advance(
+    network: &RoadNetwork,
+    graph: &[Vec<usize>],
+    mut lane: usize,
+    mut along: f64,
+    distance: f64,
+) -> Option<(usize, f64)> {
+    along += distance;
+    loop {
+        let length = routing::segment_length(network, lane);
+        if along <= length {
+            return Some((lane, along));
+        }
+        along -= length;
+        let next = *graph.get(lane)?.first()?;
+        // A lane of no length followed by itself would never end.
+        if next == lane {
+            return None;
+        }
+        lane = next;
+    }
+}
+
+// The point `along` a lane's path and the direction of travel there.
+fn pose(network: &RoadNetwork, lane: usize, along: f64) -> Option<(DVec3, DVec3)> {
+    let path = conflicts::path(network.segments.get(lane)?);
+    let mut left = along;
+    for pair in path.windows(2) {
+        let step = pair[0].distance(pair[1]);
+        if step == 0.0 {
+            continue;
+        }
+        let direction = (pair[1] - pair[0]) / step;
+        if left <= step {
+            return Some((pair[0] + direction * left, direction));
+        }
+        left -= step;
+    }
+    let end = path.windows(2).rev().find(|pair| pair[0] != pair[1])?;
+    Some((end[1], (end[1] - end[0]).normalize()))
+}
+
+// How far along its path the lane's centerline passes closest to a point.
+fn along_path(network: &RoadNetwork, lane: usize, point: DVec3) -> f64 {
+    let path = conflicts::path(&network.segments[lane]);
+    let mut best = (f64::INFINITY, 0.0);
+    let mut start = 0.0;
+    for pair in path.windows(2) {
+        let step = pair[1] - pair[0];
+        let length = step.length();
+        let t = if length > 0.0 {
+            ((point - pair[0]).dot(step) / (length * length)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let distance = point.distance(pair[0] + step * t);
+        if distance < best.0 {
+            best = (distance, start + t * length);
+        }
+        start += length;
+    }
+    best.1
+}
+
+fn toggle_drive(
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    selection: Res<Selection>,
+    origin: Res<RenderOrigin>,
+    walk: Res<WalkMode>,
+    mut mode: ResMut<DriveMode>,
+    mut cameras: Query<&mut CameraOrbit, With<MainCamera>>,
+) {
+    if !keys.just_pressed(KeyCode::F12) {
+        return;
+    }
+    let Ok(mut orbit) = cameras.get_single_mut() else {
+        return;
+    };
+    if mode.active {
+        if let Some(pose) = mode.saved.take() {
+            orbit.center = origin.to_render(pose.center);
+            orbit.distance = pose.distance;
+            orbit.azimuth = pose.azimuth;
+            orbit.elevation = pose.elevation;
+            orbit.pan = pose.pan;
+        }
+        mode.active = false;
+        info!("drive mode off");
+        return;
+    }
+    if walk.active {
+        info!("leave walk mode (F7) before driving");
+        return;
+    }
+    let Some(pick) = selection
+        .0
+        .filter(|pick| pick.segment < network.segments.len())
+    else {
+        info!("drive mode needs a selected lane");
+        return;
+    };
+    let lane = &network.segments[pick.segment];
+    info!(
+        "driving road {} lane {} ({} traffic, {} the reference line): Up and Down \
+         change speed, Space pauses, F12 leaves",
+        lane.road_id,
+        lane.lane_id,
+        lane.rule.as_attribute(),
+        if lane.follows_reference() {
+            "along"
+        } else {
+            "against"
+        }
+    );
+    *mode = DriveMode {
+        active: true,
+        paused: false,
+        lane: pick.segment,
+        along: along_path(&network, pick.segment, pick.position),
+        speed_change: 0.0,
+        graph: routing::lane_graph(&network),
+        saved: Some(OrbitPose::of(&orbit, &origin)),
+    };
+    orbit.center = origin.to_render(pick.position + DVec3::Y * EYE_HEIGHT);
+    orbit.pan = Vec2::ZERO;
+}
+
+fn drive(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    mut mode: ResMut<DriveMode>,
+    mut cameras: Query<&mut CameraOrbit, With<MainCamera>>,
+) {
+    if !mode.active {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        mode.paused = !mode.paused;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) {
+        mode.speed_change += SPEED_STEP * KMH;
+    }
+    if keys.just_pressed(KeyCode::ArrowDown) {
+        mode.speed_change -= SPEED_STEP * KMH;
+    }
+    let Some(lane) = network.segments.get(mode.lane) else {
+        mode.paused = true;
+        return;
+    };
+    if !mode.paused {
+        let limit = lane.speed.unwrap_or(DEFAULT_SPEED * KMH);
+        let speed = (limit + mode.speed_change).max(0.0);
+        let distance = speed * f64::from(time.delta_seconds());
+        match advance(&network, &mode.graph, mode.lane, mode.along, distance) {
+            Some((lane, along)) => {
+                mode.lane = lane;
+                mode.along = along;
+            }
+            None => {
+                mode.along = routing::segment_length(&network, mode.lane);
+                mode.paused = true;
+                info!("the lane ends here; F12 leaves drive mode");
+            }
+        }
+    }
+    let Ok(mut orbit) = cameras.get_single_mut() else {
+        return;
+    };
+    if let Some((point, _)) = pose(&network, mode.lane, mode.along) {
+        orbit.center = origin.to_render(point + DVec3::Y * EYE_HEIGHT);
+    }
+}
+
+// Puts the camera in the driver's seat, replacing the orbit placement.
+fn place_driver(
+    mode: Res<DriveMode>,
+    network: Res<RoadNetwork>,
+    mut cameras: Query<(&mut Transform, &CameraOrbit), With<MainCamera>>,
+) {
+    if !mode.active {
+        return;
+    }
+    let Some((_, direction)) = pose(&network, mode.lane, mode.along) else {
+        return;
+    };
+    for (mut transform, orbit) in &mut cameras {
+        *transform =
+            Transform::from_translation(orbit.center).looking_to(direction.as_vec3(), Vec3::Y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+    use crate::TrafficRule;
+
+    fn lane(network: &RoadNetwork, id: i32) -> usize {
+        network
+            .segments
+            .iter()
+            .position(|s| s.lane_id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn the_camera_looks_the_way_the_traffic_rule_drives() {
+        let mut network = load("straight.xodr");
+        let (right, left) = (lane(&network, -1), lane(&network, 1));
+        let heading = |network: &RoadNetwork, lane: usize| pose(network, lane, 10.0).unwrap().1;
+        // The straight road runs along +x, which is +x in the viewer frame.
+        assert!(heading(&network, right).x > 0.99);
+        assert!(heading(&network, left).x < -0.99);
+
+        for segment in &mut network.segments {
+            segment.rule = TrafficRule::LeftHand;
+        }
+        assert!(heading(&network, right).x < -0.99);
+        assert!(heading(&network, left).x > 0.99);
+    }
+
+    #[test]
+    fn driving_stops_where_no_lane_follows() {
+        let network = load("straight.xodr");
+        let graph = routing::lane_graph(&network);
+        let right = lane(&network, -1);
+        let length = routing::segment_length(&network, right);
+        assert_eq!(
+            advance(&network, &graph, right, 10.0, 5.0),
+            Some((right, 15.0))
+        );
+        assert_eq!(advance(&network, &graph, right, 10.0, length), None);
+        // Picked 30 m in, the lane is driven from there.
+        let (point, _) = pose(&network, right, 30.0).unwrap();
+        assert!((along_path(&network, right, point) - 30.0).abs() < 1e-6);
+    }
+}
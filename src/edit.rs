@@ -0,0 +1,275 @@
+// In-memory edits of the road network.
+//
+// The network can be changed while the viewer runs, by console commands and
+// scripts (`move road`) or by extensions. An edit changes the `RoadNetwork`
+// resource, flags the roads it touched as dirty by marking their `OdrRoad`
+// components changed (`odr::touch`), and sends `NetworkChanged`. Change
+// detection on `OdrRoad` then has only the tiles showing those roads
+// retessellated (see `tiles`), while the event has the checks, labels,
+// signal icons, selection and annotations brought up to date. Reloading
+// the map sends the same event. Roads linked to a moved road are reshaped to
+// stay continuous with it (see `continuity`).
+//
+// Edits run in transactions (see `transaction`), which check the edited
+// roads and roll back edits that would break the map. Committed edits of
+// roads and lanes can be undone with `undo`, one at a time, back to the
+// last `UNDO_DEPTH`: before changing anything, an edit takes a snapshot of
+// the roads it is about to touch, with their lanes, signals, links and the
+// junctions, and undoing puts the snapshot back and drops roads the edit
+// added. A reload forgets them all.
+
+use std::collections::BTreeMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::continuity::{self, Continuity};
+use crate::odr::touch;
+use crate::signals::Signal;
+use crate::transaction::{self, ProblemKind, Transaction};
+use crate::xodr::ElementXml;
+use crate::{RoadInfo, RoadLink, RoadNetwork, RoadSegment};
+
+// How many edits can be undone.
+const UNDO_DEPTH: usize = 50;
+
+// Sent after the network was reloaded or edited, with the IDs of the roads
+// that were added, removed or changed.
+#[derive(Event, Debug, Clone, PartialEq)]
+pub struct NetworkChanged {
+    pub changed: Vec<u32>,
+}
+
+// Some roads as they were before an edit.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    description: String,
+    // The roads restored, including those the edit went on to add.
+    roads: Vec<u32>,
+    segments: Vec<RoadSegment>,
+    signals: Vec<Signal>,
+    infos: BTreeMap<u32, RoadInfo>,
+    // The links from or to the roads.
+    links: Vec<RoadLink>,
+    junctions: BTreeMap<u32, ElementXml>,
+}
+
+impl Snapshot {
+    // Takes a snapshot of the given roads, described as the edit about to
+    // change them, e.g. "split road 4".
+    pub fn take(network: &RoadNetwork, description: String, roads: &[u32]) -> Self {
+        let touched = |road_id: &u32| roads.contains(road_id);
+        Self {
+            description,
+            roads: roads.to_vec(),
+            segments: network
+                .segments
+                .iter()
+                .filter(|segment| touched(&segment.road_id))
+                .cloned()
+                .collect(),
+            signals: network
+                .signals
+                .iter()
+                .filter(|signal| touched(&signal.road_id))
+                .cloned()
+                .collect(),
+            infos: network
+                .roads
+                .iter()
+                .filter(|(road_id, _)| touched(road_id))
+                .map(|(road_id, info)| (*road_id, info.clone()))
+                .collect(),
+            links: network
+                .links
+                .iter()
+                .filter(|link| touched(&link.road_id) || touched(&link.other_road_id))
+                .copied()
+                .collect(),
+            junctions: network.junctions.clone(),
+        }
+    }
+
+    // Also restores a road the edit adds, by removing it.
+    pub fn adding(mut self, road_id: u32) -> Self {
+        self.roads.push(road_id);
+        self
+    }
+
+    // The roads that differ from the snapshot, including those added and
+    // removed.
+    pub fn changed(&self, network: &RoadNetwork) -> Vec<u32> {
+        let differs = |road_id: u32| {
+            let lanes = |segments: &[RoadSegment]| -> Vec<RoadSegment> {
+                let mut lanes: Vec<RoadSegment> = segments
+                    .iter()
+                    .filter(|segment| segment.road_id == road_id)
+                    .cloned()
+                    .collect();
+                lanes.sort_by_key(|lane| (lane.lane_section_id, lane.lane_id));
+                lanes
+            };
+            let signals = |signals: &[Signal]| -> Vec<Signal> {
+                signals
+                    .iter()
+                    .filter(|signal| signal.road_id == road_id)
+                    .cloned()
+                    .collect()
+            };
+            let links = |links: &[RoadLink]| -> Vec<RoadLink> {
+                links
+                    .iter()
+                    .filter(|link| link.road_id == road_id || link.other_road_id == road_id)
+                    .copied()
+                    .collect()
+            };
+            let plan_view = |info: Option<&RoadInfo>| info.map(|info| info.plan_view.clone());
+            lanes(&self.segments) != lanes(&network.segments)
+                || signals(&self.signals) != signals(&network.signals)
+                || links(&self.links) != links(&network.links)
+                || plan_view(self.infos.get(&road_id)) != plan_view(network.roads.get(&road_id))
+        };
+        self.roads
+            .iter()
+            .copied()
+            .filter(|&road_id| differs(road_id))
+            .collect()
+    }
+
+    pub fn restore(self, network: &mut RoadNetwork) {
+        let touched = |road_id: &u32| self.roads.contains(road_id);
+        network
+            .segments
+            .retain(|segment| !touched(&segment.road_id));
+        network.segments.extend(self.segments);
+        network.signals.retain(|signal| !touched(&signal.road_id));
+        network.signals.extend(self.signals);
+        network.roads.retain(|road_id, _| !touched(road_id));
+        network.roads.extend(self.infos);
+        network
+            .links
+            .retain(|link| !touched(&link.road_id) && !touched(&link.other_road_id));
+        network.links.extend(self.links);
+        network.junctions = self.junctions;
+    }
+}
+
+// The snapshots of the edits that can be undone, the last one last.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct UndoStack(Vec<Snapshot>);
+
+impl UndoStack {
+    // Forgets all edits, e.g. after the map was reloaded.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+pub struct EditPlugin;
+
+impl Plugin for EditPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<NetworkChanged>()
+            .init_resource::<Continuity>()
+            .init_resource::<UndoStack>();
+    }
+}
+
+// Keeps the snapshot taken before an edit that succeeded.
+pub fn remember(world: &mut World, snapshot: Snapshot) {
+    let mut stack = world.resource_mut::<UndoStack>();
+    stack.0.push(snapshot);
+    if stack.0.len() > UNDO_DEPTH {
+        stack.0.remove(0);
+    }
+}
+
+// Undoes the last edit.
+pub fn undo(world: &mut World) -> Result<String, String> {
+    let snapshot = world
+        .resource_mut::<UndoStack>()
+        .0
+        .pop()
+        .ok_or("nothing to undo")?;
+    let description = snapshot.description.clone();
+    let changed = snapshot.roads.clone();
+    snapshot.restore(&mut world.resource_mut::<RoadNetwork>());
+    for &road_id in &changed {
+        touch(world, road_id);
+    }
+    world.send_event(NetworkChanged { changed });
+    Ok(format!("undid {description}"))
+}
+
+// The roads linked to a road.
+pub fn linked_roads(network: &RoadNetwork, road_id: u32) -> Vec<u32> {
+    let mut linked: Vec<u32> = network
+        .links
+        .iter()
+        .filter_map(|link| {
+            if link.road_id == road_id {
+                Some(link.other_road_id)
+            } else if link.other_road_id == road_id {
+                Some(link.road_id)
+            } else {
+                None
+            }
+        })
+        .filter(|&other| other != road_id)
+        .collect();
+    linked.sort_unstable();
+    linked.dedup();
+    linked
+}
+
+// Moves a road, with its signals and objects, by a viewer-frame offset.
+pub fn move_road(world: &mut World, road_id: u32, offset: DVec3) -> Result<String, String> {
+    let keep_continuity = world.resource::<Continuity>().0;
+    let network = world.resource::<RoadNetwork>();
+    let mut roads = vec![road_id];
+    roads.extend(linked_roads(network, road_id));
+    let mut transaction = Transaction::begin(network, format!("moving road {road_id}"), &roads);
+    if !keep_continuity {
+        transaction = transaction
+            .allowing(ProblemKind::Gap)
+            .allowing(ProblemKind::Kink);
+    }
+    transaction::run(world, transaction, |network| {
+        let mut moved = 0;
+        for segment in network
+            .segments
+            .iter_mut()
+            .filter(|segment| segment.road_id == road_id)
+        {
+            segment.start_pos += offset;
+            segment.end_pos += offset;
+            for p in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                *p += offset;
+            }
+            moved += 1;
+        }
+        if moved == 0 {
+            return Err(format!("no road {road_id}"));
+        }
+        for signal in network
+            .signals
+            .iter_mut()
+            .filter(|signal| signal.road_id == road_id)
+        {
+            signal.position += offset;
+        }
+        if !keep_continuity {
+            return Ok((format!("moved road {road_id}"), Vec::new()));
+        }
+        let followed = continuity::follow(network, road_id, offset);
+        if followed.is_empty() {
+            return Ok((format!("moved road {road_id}"), Vec::new()));
+        }
+        let followed: Vec<String> = followed.iter().map(u32::to_string).collect();
+        let message = format!(
+            "moved road {road_id}, reshaped {} to follow",
+            followed.join(", ")
+        );
+        Ok((message, Vec::new()))
+    })
+}
@@ -0,0 +1,64 @@
+// Index of the entities spawned for OpenDRIVE elements.
+//
+// Road meshes come and go as tiles stream in and out, and a road may be
+// spread over several tiles and several meshes per tile. The index keeps
+// track of which entities currently show which road, lane, junction and
+// signal, both ways: the entities of an element by its ID, and the element
+// an entity shows. Lanes are merged into the meshes of their road, so a
+// lane's entities are those of its road that hold the lane; likewise a
+// junction's are those of its connecting roads. The spawning systems (see
+// `tiles` and `signals`) keep the index up to date; the console uses it to
+// hide and find elements, and reloads to find the roads to respawn.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+// An OpenDRIVE element that entities are spawned for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OdrId {
+    Road(u32),
+    Lane { road_id: u32, lane_id: i32 },
+    Junction(u32),
+    Signal(String),
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct OdrEntityIndex {
+    entities: HashMap<OdrId, Vec<Entity>>,
+    // What each entity shows, the element it was spawned for first.
+    elements: HashMap<Entity, Vec<OdrId>>,
+}
+
+impl OdrEntityIndex {
+    // Records an entity spawned for an element, and the elements it is
+    // also part of.
+    pub fn insert(&mut self, entity: Entity, ids: Vec<OdrId>) {
+        for id in &ids {
+            self.entities.entry(id.clone()).or_default().push(entity);
+        }
+        self.elements.insert(entity, ids);
+    }
+
+    // Forgets a despawned entity.
+    pub fn remove(&mut self, entity: Entity) {
+        for id in self.elements.remove(&entity).unwrap_or_default() {
+            if let Some(entities) = self.entities.get_mut(&id) {
+                entities.retain(|e| *e != entity);
+                if entities.is_empty() {
+                    self.entities.remove(&id);
+                }
+            }
+        }
+    }
+
+    // The entities currently showing an element.
+    pub fn entities(&self, id: &OdrId) -> &[Entity] {
+        self.entities.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    // The element an entity was spawned for.
+    pub fn element_of(&self, entity: Entity) -> Option<&OdrId> {
+        self.elements.get(&entity).and_then(|ids| ids.first())
+    }
+}
@@ -0,0 +1,470 @@
+// Sky and image-based lighting from an environment image.
+//
+// An environment image, given to `view --environment <file>` or loaded with
+// `environment <file>` in the console, surrounds the map as its sky and
+// lights it, which makes screenshots and recordings look finished. The image
+// is an equirectangular panorama, an HDRI (`.hdr`) or an ordinary picture,
+// whose middle looks north and whose top looks straight up. Once loaded it
+// is turned into a cube map for the sky, with a chain of ever blurrier
+// copies for the reflections of ever rougher surfaces, and a small cube of
+// the light falling in from each side for diffuse lighting.
+// `environment brightness <cd/m²>` sets how bright both are, and
+// `environment off` goes back to the plain background.
+
+use std::f32::consts::PI;
+use std::path::PathBuf;
+
+use bevy::asset::LoadState;
+use bevy::core_pipeline::Skybox;
+use bevy::pbr::environment_map::EnvironmentMapLight;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor, TextureViewDimension,
+};
+
+use crate::MainCamera;
+
+// Brightness of the sky and its light if not set, in cd/m².
+pub const DEFAULT_BRIGHTNESS: f32 = 1000.0;
+
+// Bounds on the edge length of a sky face, in pixels.
+const MIN_FACE: u32 = 64;
+const MAX_FACE: u32 = 1024;
+
+// Edge length of a face of the diffuse light cube, in pixels.
+const DIFFUSE_FACE: u32 = 16;
+
+// Size the panorama is averaged down to before diffuse lighting is
+// gathered from it, in pixels.
+const GATHER_WIDTH: u32 = 64;
+const GATHER_HEIGHT: u32 = 32;
+
+#[derive(Resource, Debug, Clone)]
+pub struct Environment {
+    pub path: Option<PathBuf>,
+    pub brightness: f32,
+    // The panorama being loaded, and the sky and diffuse cubes made of it.
+    source: Option<Handle<Image>>,
+    maps: Option<(Handle<Image>, Handle<Image>)>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            path: None,
+            brightness: DEFAULT_BRIGHTNESS,
+            source: None,
+            maps: None,
+        }
+    }
+}
+
+impl Environment {
+    pub fn from_file(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            ..default()
+        }
+    }
+}
+
+// An equirectangular panorama in linear color.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Panorama {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+impl Panorama {
+    // Reads an image as loaded: HDRIs come as 32-bit floats, pictures as
+    // 8-bit sRGB.
+    pub fn from_image(image: &Image) -> Result<Self, String> {
+        let (width, height) = (image.width(), image.height());
+        let pixels = match image.texture_descriptor.format {
+            TextureFormat::Rgba32Float => image
+                .data
+                .chunks_exact(16)
+                .map(|texel| {
+                    let channel =
+                        |i: usize| f32::from_le_bytes(texel[i * 4..i * 4 + 4].try_into().unwrap());
+                    Vec3::new(channel(0), channel(1), channel(2)).max(Vec3::ZERO)
+                })
+                .collect(),
+            TextureFormat::Rgba8UnormSrgb | TextureFormat::Rgba8Unorm => {
+                let linear = image.texture_descriptor.format == TextureFormat::Rgba8Unorm;
+                image
+                    .data
+                    .chunks_exact(4)
+                    .map(|texel| {
+                        let channel = |c: u8| {
+                            let c = c as f32 / 255.0;
+                            if linear {
+                                c
+                            } else if c <= 0.04045 {
+                                c / 12.92
+                            } else {
+                                ((c + 0.055) / 1.055).powf(2.4)
+                            }
+                        };
+                        Vec3::new(channel(texel[0]), channel(texel[1]), channel(texel[2]))
+                    })
+                    .collect()
+            }
+            format => return Err(format!("images in {format:?} are not supported")),
+        };
+        if width < 2 || height < 1 {
+            return Err("the image is empty".to_string());
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    // The color seen looking in a viewer-frame direction.
+    pub fn sample(&self, direction: Vec3) -> Vec3 {
+        let direction = direction.normalize_or_zero();
+        // North is -z, east +x.
+        let longitude = direction.x.atan2(-direction.z);
+        let latitude = direction.y.clamp(-1.0, 1.0).asin();
+        let u = 0.5 + longitude / (2.0 * PI);
+        let v = 0.5 - latitude / PI;
+        let x = ((u * self.width as f32) as u32).min(self.width - 1);
+        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // Averages the panorama down to a smaller one.
+    fn shrink(&self, width: u32, height: u32) -> Self {
+        let (width, height) = (width.min(self.width), height.min(self.height));
+        let mut pixels = vec![Vec3::ZERO; (width * height) as usize];
+        let mut counts = vec![0u32; pixels.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = (y * height / self.height * width + x * width / self.width) as usize;
+                pixels[i] += self.pixels[(y * self.width + x) as usize];
+                counts[i] += 1;
+            }
+        }
+        for (pixel, count) in pixels.iter_mut().zip(counts) {
+            *pixel /= count.max(1) as f32;
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // The light falling on a surface facing `normal`, as the radiance of a
+    // white surface lit by it.
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        let normal = normal.normalize_or_zero();
+        let mut sum = Vec3::ZERO;
+        let cell = (2.0 * PI / self.width as f32) * (PI / self.height as f32);
+        for y in 0..self.height {
+            let latitude = PI / 2.0 - PI * (y as f32 + 0.5) / self.height as f32;
+            let solid_angle = cell * latitude.cos();
+            for x in 0..self.width {
+                let longitude = 2.0 * PI * ((x as f32 + 0.5) / self.width as f32 - 0.5);
+                let direction = Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                );
+                let facing = normal.dot(direction);
+                if facing > 0.0 {
+                    sum += self.pixels[(y * self.width + x) as usize] * facing * solid_angle;
+                }
+            }
+        }
+        sum / PI
+    }
+}
+
+// The viewer-frame direction through a texel of a cube face, given as
+// coordinates from -1 to 1 across (right) and down the face. Faces are in
+// the order +x, -x, +y, -y, +z, -z of the cube; Bevy looks cube maps up
+// with z flipped.
+fn cube_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    let cube = match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    };
+    Vec3::new(cube.x, cube.y, -cube.z).normalize()
+}
+
+// The faces of a cube map of the given edge length.
+fn cube_faces(size: u32, color: impl Fn(Vec3) -> Vec3) -> Vec<Vec<Vec3>> {
+    (0..6)
+        .map(|face| {
+            let mut texels = Vec::with_capacity((size * size) as usize);
+            for y in 0..size {
+                for x in 0..size {
+                    let u = 2.0 * (x as f32 + 0.5) / size as f32 - 1.0;
+                    let v = 2.0 * (y as f32 + 0.5) / size as f32 - 1.0;
+                    texels.push(color(cube_direction(face, u, v)));
+                }
+            }
+            texels
+        })
+        .collect()
+}
+
+// A 16-bit float, truncated; subnormals are flushed to zero.
+fn half(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let value = value.abs();
+    if value.is_nan() {
+        return 0x7e00;
+    }
+    if value >= 65504.0 {
+        return sign | 0x7bff;
+    }
+    if value < 6.103_515_6e-5 {
+        return sign;
+    }
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) as i32 - 127 + 15) as u16;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+    sign | (exponent << 10) | mantissa
+}
+
+// A cube map image of faces of the given edge length, each followed by its
+// mip levels, halving down to a single texel when `mips` is set.
+fn cube_image(size: u32, faces: Vec<Vec<Vec3>>, mips: bool) -> Image {
+    let mut data = Vec::new();
+    let mut levels = 1;
+    for face in faces {
+        let (mut texels, mut edge) = (face, size);
+        levels = 1;
+        loop {
+            for texel in &texels {
+                for channel in [texel.x, texel.y, texel.z, 1.0] {
+                    data.extend(half(channel).to_le_bytes());
+                }
+            }
+            if !mips || edge == 1 {
+                break;
+            }
+            let half_edge = edge / 2;
+            let at = |x: u32, y: u32| texels[(y * edge + x) as usize];
+            texels = (0..half_edge * half_edge)
+                .map(|i| {
+                    let (x, y) = (i % half_edge * 2, i / half_edge * 2);
+                    (at(x, y) + at(x + 1, y) + at(x, y + 1) + at(x + 1, y + 1)) / 4.0
+                })
+                .collect();
+            edge = half_edge;
+            levels += 1;
+        }
+    }
+    Image {
+        data,
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: levels,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba16Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        texture_view_descriptor: Some(TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            ..default()
+        }),
+        asset_usage: RenderAssetUsages::RENDER_WORLD,
+        ..default()
+    }
+}
+
+// The sky cube, with its blurrier mip levels for reflections, and the
+// diffuse light cube of a panorama.
+pub fn cube_maps(panorama: &Panorama) -> (Image, Image) {
+    let size = (panorama.width / 4)
+        .next_power_of_two()
+        .clamp(MIN_FACE, MAX_FACE);
+    let sky = cube_image(size, cube_faces(size, |d| panorama.sample(d)), true);
+    let gathered = panorama.shrink(GATHER_WIDTH, GATHER_HEIGHT);
+    let diffuse = cube_image(
+        DIFFUSE_FACE,
+        cube_faces(DIFFUSE_FACE, |d| gathered.irradiance(d)),
+        false,
+    );
+    (sky, diffuse)
+}
+
+// `environment` in the console.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut environment = world.resource_mut::<Environment>();
+    match args {
+        [] => Ok(match &environment.path {
+            Some(path) => format!("{} at {} cd/m²", path.display(), environment.brightness),
+            None => "no environment image".to_string(),
+        }),
+        ["off"] => {
+            environment.path = None;
+            Ok(String::new())
+        }
+        ["brightness", value] => {
+            environment.brightness = value
+                .parse()
+                .ok()
+                .filter(|value: &f32| *value >= 0.0)
+                .ok_or_else(|| format!("invalid brightness `{value}`"))?;
+            Ok(String::new())
+        }
+        [path] => {
+            // The asset server reads paths relative to its own folder.
+            let path = std::env::current_dir()
+                .map(|dir| dir.join(path))
+                .map_err(|e| e.to_string())?;
+            if !path.is_file() {
+                return Err(format!("no file {}", path.display()));
+            }
+            let brightness = environment.brightness;
+            *environment = Environment {
+                brightness,
+                ..Environment::from_file(path)
+            };
+            Ok(String::new())
+        }
+        _ => Err("expected an image, brightness <cd/m²> or off".to_string()),
+    }
+}
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Environment>()
+            .add_systems(Update, update_environment);
+    }
+}
+
+// Loads the environment image, makes its cubes once it is there, and puts
+// them on the camera.
+fn update_environment(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut environment: ResMut<Environment>,
+    cameras: Query<Entity, With<MainCamera>>,
+) {
+    let Some(path) = environment.path.clone() else {
+        if environment.is_changed() {
+            environment.source = None;
+            environment.maps = None;
+            for camera in &cameras {
+                commands
+                    .entity(camera)
+                    .remove::<(Skybox, EnvironmentMapLight)>();
+            }
+        }
+        return;
+    };
+    let Some(source) = environment.source.clone() else {
+        environment.source = Some(server.load(path));
+        return;
+    };
+    if environment.maps.is_none() {
+        let Some(image) = images.get(&source) else {
+            if server.load_state(&source) == LoadState::Failed {
+                warn!("could not load the environment image {}", path.display());
+                environment.path = None;
+            }
+            return;
+        };
+        match Panorama::from_image(image) {
+            Ok(panorama) => {
+                let (sky, diffuse) = cube_maps(&panorama);
+                environment.maps = Some((images.add(sky), images.add(diffuse)));
+            }
+            Err(e) => {
+                warn!("{}: {e}", path.display());
+                environment.path = None;
+                return;
+            }
+        }
+    }
+    if !environment.is_changed() {
+        return;
+    }
+    let Some((sky, diffuse)) = environment.maps.clone() else {
+        return;
+    };
+    for camera in &cameras {
+        commands.entity(camera).insert((
+            Skybox {
+                image: sky.clone(),
+                brightness: environment.brightness,
+            },
+            EnvironmentMapLight {
+                diffuse_map: diffuse.clone(),
+                specular_map: sky.clone(),
+                intensity: environment.brightness,
+            },
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panoramas_light_the_scene() {
+        // A white sky over a black ground, 128 by 64 pixels.
+        let (width, height) = (128u32, 64u32);
+        let mut data = Vec::new();
+        for y in 0..height {
+            let value: f32 = if y < height / 2 { 1.0 } else { 0.0 };
+            for _ in 0..width {
+                for channel in [value, value, value, 1.0] {
+                    data.extend(channel.to_le_bytes());
+                }
+            }
+        }
+        let image = Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba32Float,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        let panorama = Panorama::from_image(&image).unwrap();
+        assert_eq!(panorama.sample(Vec3::new(0.3, 1.0, -0.2)), Vec3::ONE);
+        assert_eq!(panorama.sample(Vec3::new(0.3, -1.0, 0.2)), Vec3::ZERO);
+        // A roof sees all the sky, a wall half of it, the ground none.
+        assert!((panorama.irradiance(Vec3::Y).x - 1.0).abs() < 0.02);
+        assert!((panorama.irradiance(Vec3::X).x - 0.5).abs() < 0.02);
+        assert!(panorama.irradiance(-Vec3::Y).x < 0.02);
+
+        let (sky, diffuse) = cube_maps(&panorama);
+        assert_eq!(sky.texture_descriptor.size.depth_or_array_layers, 6);
+        assert_eq!(sky.texture_descriptor.size.width, 64);
+        assert_eq!(sky.texture_descriptor.mip_level_count, 7);
+        // Six faces of 64, 32, ... 1 texels across, four 16-bit channels each.
+        let texels: u32 = (0..7).map(|level| (64u32 >> level).pow(2)).sum();
+        assert_eq!(sky.data.len() as u32, 6 * texels * 8);
+        assert_eq!(diffuse.texture_descriptor.mip_level_count, 1);
+    }
+}
@@ -0,0 +1,139 @@
+// Extension points for custom analysis overlays.
+//
+// An extension implements `RsodrPlugin` and is listed in `installed`; it is
+// then built into the viewer like any other plugin. From there it can read
+// the `RoadNetwork` resource, react to `SelectionChanged` events, and
+// register overlays with `add_overlay`. A registered overlay gets a key that
+// shows and hides it and a run condition, `overlay_shown`, for the systems
+// that draw it, so extensions need not touch the viewer's own input
+// handling. Extensions are compiled into the viewer binary; there is no
+// library target or dynamic loading, so adding one means adding its module
+// and a line to `installed`.
+//
+// Keys: as registered by the extensions (see the log at startup).
+
+use bevy::prelude::*;
+
+use crate::friction::FrictionOverlay;
+use crate::junction_overlay::JunctionOverlay;
+use crate::priority::PriorityOverlay;
+use crate::road_types::RoadTypeOverlay;
+use crate::selection::{Pick, Selection};
+
+// A viewer extension.
+pub trait RsodrPlugin: Send + Sync + 'static {
+    // Name shown in the log.
+    fn name(&self) -> &'static str;
+
+    // Adds the extension's overlays, resources and systems.
+    fn build(&self, app: &mut App);
+}
+
+// The extensions built into this viewer.
+pub fn installed() -> Vec<Box<dyn RsodrPlugin>> {
+    vec![
+        Box::new(JunctionOverlay),
+        Box::new(PriorityOverlay),
+        Box::new(FrictionOverlay),
+        Box::new(RoadTypeOverlay),
+    ]
+}
+
+// Sent whenever the selection changes, with what was selected before.
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct SelectionChanged {
+    pub previous: Option<Pick>,
+    pub current: Option<Pick>,
+}
+
+// An overlay registered by an extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlay {
+    pub name: &'static str,
+    pub key: KeyCode,
+    pub shown: bool,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Overlays(pub Vec<Overlay>);
+
+impl Overlays {
+    pub fn shown(&self, name: &str) -> bool {
+        self.0
+            .iter()
+            .any(|overlay| overlay.name == name && overlay.shown)
+    }
+}
+
+// Registration of overlays on the app.
+pub trait AddOverlay {
+    fn add_overlay(&mut self, name: &'static str, key: KeyCode) -> &mut Self;
+}
+
+impl AddOverlay for App {
+    fn add_overlay(&mut self, name: &'static str, key: KeyCode) -> &mut Self {
+        let mut overlays = self.world.get_resource_or_insert_with(Overlays::default);
+        if let Some(taken) = overlays.0.iter().find(|overlay| overlay.key == key) {
+            warn!("overlays {} and {name} share the key {key:?}", taken.name);
+        }
+        overlays.0.push(Overlay {
+            name,
+            key,
+            shown: false,
+        });
+        self
+    }
+}
+
+// How far overlays drawn on the road surface are lifted off it, in meters.
+pub const OVERLAY_LIFT: f32 = 0.08;
+
+// A run condition for the systems drawing an overlay.
+pub fn overlay_shown(name: &'static str) -> impl Fn(Res<Overlays>) -> bool + Clone {
+    move |overlays: Res<Overlays>| overlays.shown(name)
+}
+
+pub struct ExtensionPlugin;
+
+impl Plugin for ExtensionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Overlays>()
+            .add_event::<SelectionChanged>()
+            .add_systems(Update, (announce_selection, toggle_overlays));
+        for extension in installed() {
+            extension.build(app);
+            info!("extension {} loaded", extension.name());
+        }
+        for overlay in &app.world.resource::<Overlays>().0 {
+            info!("overlay {}: key {:?}", overlay.name, overlay.key);
+        }
+    }
+}
+
+fn announce_selection(
+    selection: Res<Selection>,
+    mut previous: Local<Option<Pick>>,
+    mut changed: EventWriter<SelectionChanged>,
+) {
+    if selection.0 == *previous {
+        return;
+    }
+    changed.send(SelectionChanged {
+        previous: *previous,
+        current: selection.0,
+    });
+    *previous = selection.0;
+}
+
+fn toggle_overlays(keys: Res<ButtonInput<KeyCode>>, mut overlays: ResMut<Overlays>) {
+    for overlay in &mut overlays.0 {
+        if keys.just_pressed(overlay.key) {
+            overlay.shown = !overlay.shown;
+            info!(
+                "overlay {} {}",
+                overlay.name,
+                if overlay.shown { "on" } else { "off" }
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// Roads that could not be loaded.
+//
+// The viewer loads what it can of a broken map rather than nothing: a road
+// whose records make no sense (no reference line, no lanes) is left out, and
+// malformed XML ends reading where it starts, keeping the roads before it.
+// Each such failure is kept with the network and marked in the scene with a
+// placeholder, a red post over a cross where the road's reference line
+// starts, when that is known. The failures are listed in the console as the
+// map is loaded or reloaded, and again by `failures`.
+//
+// Command line tools load maps whole or not at all (see `loader`), since a
+// converted or validated map missing roads would go unnoticed.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::console::Console;
+use crate::origin::RenderOrigin;
+use crate::ribbons::{Ribbons, DEFAULT_WIDTH};
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+// Height of a placeholder's post and half the span of its cross, in meters.
+const POST_HEIGHT: f32 = 8.0;
+const CROSS_SIZE: f32 = 3.0;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadFailure {
+    // The OpenDRIVE ID of the road, empty if the failure is not a road's.
+    pub road: String,
+    pub message: String,
+    // Where to mark it, in the viewer frame.
+    pub position: Option<DVec3>,
+}
+
+impl LoadFailure {
+    pub fn describe(&self) -> String {
+        if self.road.is_empty() {
+            self.message.clone()
+        } else {
+            format!("road {}: {}", self.road, self.message)
+        }
+    }
+}
+
+// The failures of a network, one per line.
+pub fn describe(network: &RoadNetwork) -> String {
+    if network.failures.is_empty() {
+        return "every road was loaded".to_string();
+    }
+    let mut lines = vec![format!(
+        "{} failure(s) while loading:",
+        network.failures.len()
+    )];
+    lines.extend(
+        network
+            .failures
+            .iter()
+            .map(|f| format!("  {}", f.describe())),
+    );
+    lines.join("\n")
+}
+
+pub struct FailurePlugin;
+
+impl Plugin for FailurePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (list_failures, draw_placeholders.after(camera_orbit)),
+        );
+    }
+}
+
+// Opens the console on the failures of a newly loaded map. Edits keep the
+// failures, so they are only listed when they differ from the last ones.
+fn list_failures(
+    network: Res<RoadNetwork>,
+    mut listed: Local<Vec<LoadFailure>>,
+    mut console: ResMut<Console>,
+) {
+    if !network.is_changed() || network.failures == *listed {
+        return;
+    }
+    listed.clone_from(&network.failures);
+    if !network.failures.is_empty() {
+        warn!("{}", describe(&network));
+        console.print(&describe(&network));
+        console.open = true;
+    }
+}
+
+fn draw_placeholders(
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    let width = 2.0 * DEFAULT_WIDTH;
+    for position in network.failures.iter().filter_map(|f| f.position) {
+        let foot = origin.to_render(position);
+        ribbons.add([foot, foot + Vec3::Y * POST_HEIGHT], theme.error, width);
+        for corner in [Vec3::new(1.0, 0.0, 1.0), Vec3::new(1.0, 0.0, -1.0)] {
+            let corner = corner * CROSS_SIZE;
+            ribbons.add([foot - corner, foot + corner], theme.error, width);
+        }
+    }
+}
@@ -0,0 +1,688 @@
+// Semantic filter for what the map shows.
+//
+// Elements can be shown or hidden by category: lanes by their OpenDRIVE
+// type, road marks, signals, objects and the roads inside junctions. On top
+// of that, attribute conditions such as `speed < 30` or `type == sidewalk`
+// narrow down what is shown to the elements that pass all of them. The
+// fields are `road`, `lane`, `type`, `speed` (km/h) and `width` (m) of
+// lanes, `road` and `value` of signals, and `road`, `type` and `height` of
+// objects; a condition on a field an element does not have leaves the
+// element alone, while one on a limit it lacks (a lane without a speed
+// limit) hides it.
+//
+// Road marks, signals, objects and junction roads are separate entities and
+// are hidden through their `Visibility`, together with the elements hidden
+// from the console and the roads outside an isolated selection (see
+// `isolate`). Lanes are merged into the meshes of their road, so
+// lanes that are filtered out are left out of the meshes instead, and the
+// tiles are rebuilt when the lane filter changes (see `tiles`).
+//
+// The panel toggles the categories; conditions are added from the console
+// with `filter <field> <op> <value>`.
+//
+// Keys: F shows or hides the filter panel.
+
+use std::collections::{BTreeSet, HashSet};
+
+use bevy::prelude::*;
+
+use crate::console::Hidden;
+use crate::entity_index::OdrEntityIndex;
+use crate::i18n::Locale;
+use crate::isolate::Isolation;
+use crate::odr::{OdrObject, OdrSignal};
+use crate::tiles::ReloadTiles;
+use crate::units::KMH;
+use crate::{RoadLayer, RoadNetwork, RoadPart, RoadSegment};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Road,
+    Lane,
+    Type,
+    Speed,
+    Width,
+    Value,
+    Height,
+}
+
+impl Field {
+    fn named(name: &str) -> Option<Self> {
+        Some(match name {
+            "road" => Field::Road,
+            "lane" => Field::Lane,
+            "type" => Field::Type,
+            "speed" => Field::Speed,
+            "width" => Field::Width,
+            "value" => Field::Value,
+            "height" => Field::Height,
+            _ => return None,
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Road => "road",
+            Field::Lane => "lane",
+            Field::Type => "type",
+            Field::Speed => "speed",
+            Field::Width => "width",
+            Field::Value => "value",
+            Field::Height => "height",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+// Longer operators first, so that `<=` is not read as `<`.
+const OPS: [(&str, Op); 7] = [
+    ("<=", Op::LessOrEqual),
+    (">=", Op::GreaterOrEqual),
+    ("==", Op::Equal),
+    ("!=", Op::NotEqual),
+    ("<", Op::Less),
+    (">", Op::Greater),
+    ("=", Op::Equal),
+];
+
+// An attribute condition, e.g. `speed < 30`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    field: Field,
+    op: Op,
+    value: String,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (at, symbol, op) = OPS
+            .iter()
+            .filter_map(|(symbol, op)| Some((text.find(symbol)?, *symbol, *op)))
+            .min_by_key(|(at, symbol, _)| (*at, usize::MAX - symbol.len()))
+            .ok_or_else(|| format!("expected <field> <op> <value> in `{text}`"))?;
+        let name = text[..at].trim();
+        let field = Field::named(name).ok_or_else(|| {
+            format!("unknown field `{name}` (try road, lane, type, speed, width, value or height)")
+        })?;
+        let value = text[at + symbol.len()..].trim().to_string();
+        if value.is_empty() {
+            return Err(format!("expected a value after `{symbol}`"));
+        }
+        if field == Field::Type {
+            if !matches!(op, Op::Equal | Op::NotEqual) {
+                return Err("`type` can only be compared with == or !=".to_string());
+            }
+        } else if value.parse::<f64>().is_err() {
+            return Err(format!("`{value}` is not a number"));
+        }
+        Ok(Self { field, op, value })
+    }
+
+    fn number(&self, n: Option<f64>) -> bool {
+        let (Some(n), Ok(value)) = (n, self.value.parse::<f64>()) else {
+            return false;
+        };
+        match self.op {
+            Op::Less => n < value,
+            Op::LessOrEqual => n <= value,
+            Op::Greater => n > value,
+            Op::GreaterOrEqual => n >= value,
+            Op::Equal => n == value,
+            Op::NotEqual => n != value,
+        }
+    }
+
+    fn text(&self, text: &str) -> bool {
+        (text == self.value) == (self.op == Op::Equal)
+    }
+
+    fn passes_lane(&self, lane: &RoadSegment) -> bool {
+        match self.field {
+            Field::Road => self.number(Some(f64::from(lane.road_id))),
+            Field::Lane => self.number(Some(f64::from(lane.lane_id))),
+            Field::Type => self.text(&lane.lane_type),
+            Field::Speed => self.number(lane.speed.map(|speed| speed / KMH)),
+            Field::Width => self.number(Some(lane.width)),
+            Field::Value | Field::Height => true,
+        }
+    }
+
+    fn passes_signal(&self, signal: &OdrSignal) -> bool {
+        match self.field {
+            Field::Road => self.number(Some(f64::from(signal.road_id))),
+            Field::Value => self.number(signal.value),
+            _ => true,
+        }
+    }
+
+    fn passes_object(&self, object: &OdrObject) -> bool {
+        match self.field {
+            Field::Road => self.number(Some(f64::from(object.road_id))),
+            Field::Type => self.text(&object.type_code),
+            Field::Height => self.number(Some(object.height)),
+            _ => true,
+        }
+    }
+
+    fn about_lanes(&self) -> bool {
+        !matches!(self.field, Field::Value | Field::Height)
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let symbol = OPS
+            .iter()
+            .find(|(_, op)| *op == self.op)
+            .map_or("", |(symbol, _)| symbol);
+        write!(f, "{} {symbol} {}", self.field.name(), self.value)
+    }
+}
+
+// A category of elements that can be shown or hidden as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Category {
+    LaneType(String),
+    RoadMarks,
+    Signals,
+    Objects,
+    Junctions,
+}
+
+impl Category {
+    // Reads a category as the console names it: `marks`, `signals`,
+    // `objects`, `junctions` or `lanes <type>`.
+    fn parse(args: &[&str]) -> Result<Self, String> {
+        match args {
+            ["marks"] => Ok(Category::RoadMarks),
+            ["signals"] => Ok(Category::Signals),
+            ["objects"] => Ok(Category::Objects),
+            ["junctions"] => Ok(Category::Junctions),
+            ["lanes", lane_type] => Ok(Category::LaneType(lane_type.to_string())),
+            _ => Err("expected marks, signals, objects, junctions or lanes <type>".to_string()),
+        }
+    }
+
+    fn caption(&self, locale: &Locale) -> String {
+        match self {
+            Category::LaneType(lane_type) => {
+                locale.text("filter-lane-type", &[("type", lane_type)])
+            }
+            Category::RoadMarks => locale.text("filter-road-marks", &[]),
+            Category::Signals => locale.text("filter-signals", &[]),
+            Category::Objects => locale.text("filter-objects", &[]),
+            Category::Junctions => locale.text("filter-junctions", &[]),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Filter {
+    // Lane types left out of the road meshes.
+    pub hidden_lane_types: BTreeSet<String>,
+    pub road_marks: bool,
+    pub signals: bool,
+    pub objects: bool,
+    pub junctions: bool,
+    // Conditions every element shown passes.
+    pub conditions: Vec<Condition>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            hidden_lane_types: BTreeSet::new(),
+            road_marks: true,
+            signals: true,
+            objects: true,
+            junctions: true,
+            conditions: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    pub fn shows(&self, category: &Category) -> bool {
+        match category {
+            Category::LaneType(lane_type) => !self.hidden_lane_types.contains(lane_type),
+            Category::RoadMarks => self.road_marks,
+            Category::Signals => self.signals,
+            Category::Objects => self.objects,
+            Category::Junctions => self.junctions,
+        }
+    }
+
+    pub fn set(&mut self, category: &Category, show: bool) {
+        match category {
+            Category::LaneType(lane_type) => {
+                if show {
+                    self.hidden_lane_types.remove(lane_type);
+                } else {
+                    self.hidden_lane_types.insert(lane_type.clone());
+                }
+            }
+            Category::RoadMarks => self.road_marks = show,
+            Category::Signals => self.signals = show,
+            Category::Objects => self.objects = show,
+            Category::Junctions => self.junctions = show,
+        }
+    }
+
+    // Whether a lane goes into the road meshes.
+    pub fn shows_lane(&self, lane: &RoadSegment) -> bool {
+        !self.hidden_lane_types.contains(&lane.lane_type)
+            && self.conditions.iter().all(|c| c.passes_lane(lane))
+    }
+
+    // Whether any lanes are left out of the meshes.
+    pub fn hides_lanes(&self) -> bool {
+        !self.hidden_lane_types.is_empty() || self.conditions.iter().any(Condition::about_lanes)
+    }
+
+    // What decides which lanes go into the meshes.
+    fn lane_filter(&self) -> (BTreeSet<String>, Vec<Condition>) {
+        let conditions = self.conditions.iter().filter(|c| c.about_lanes());
+        (
+            self.hidden_lane_types.clone(),
+            conditions.cloned().collect(),
+        )
+    }
+
+    fn describe(&self) -> String {
+        let mut hidden: Vec<String> = self
+            .hidden_lane_types
+            .iter()
+            .map(|lane_type| format!("{lane_type} lanes"))
+            .collect();
+        for (shown, name) in [
+            (self.road_marks, "road marks"),
+            (self.signals, "signals"),
+            (self.objects, "objects"),
+            (self.junctions, "junction interiors"),
+        ] {
+            if !shown {
+                hidden.push(name.to_string());
+            }
+        }
+        let mut out = if hidden.is_empty() {
+            "all categories shown".to_string()
+        } else {
+            format!("hidden: {}", hidden.join(", "))
+        };
+        for condition in &self.conditions {
+            out.push_str(&format!("\nwhere {condition}"));
+        }
+        out
+    }
+}
+
+// Runs the `filter` console command.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut filter = world.resource_mut::<Filter>();
+    match args {
+        [] => return Ok(filter.describe()),
+        ["clear"] => *filter = Filter::default(),
+        ["show", category @ ..] => filter.set(&Category::parse(category)?, true),
+        ["hide", category @ ..] => filter.set(&Category::parse(category)?, false),
+        condition => filter
+            .conditions
+            .push(Condition::parse(&condition.join(" "))?),
+    }
+    Ok(String::new())
+}
+
+// The filter panel.
+#[derive(Component)]
+struct FilterPanel;
+
+// A panel button toggling a category.
+#[derive(Component)]
+struct Toggle(Category);
+
+pub struct FilterPlugin;
+
+impl Plugin for FilterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Filter>()
+            .add_systems(Startup, spawn_panel)
+            .add_systems(
+                Update,
+                (
+                    toggle_panel,
+                    toggle_on_click,
+                    fill_panel,
+                    rebuild_tiles,
+                    apply_visibility,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Percent(25.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        FilterPanel,
+    ));
+}
+
+fn toggle_panel(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panels: Query<&mut Visibility, With<FilterPanel>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn toggle_on_click(
+    mut filter: ResMut<Filter>,
+    buttons: Query<(&Interaction, &Toggle), Changed<Interaction>>,
+) {
+    for (interaction, toggle) in &buttons {
+        if *interaction == Interaction::Pressed {
+            let show = !filter.shows(&toggle.0);
+            filter.set(&toggle.0, show);
+        }
+    }
+}
+
+// Rebuilds the panel when the filter or the network changes.
+fn fill_panel(
+    mut commands: Commands,
+    filter: Res<Filter>,
+    network: Res<RoadNetwork>,
+    locale: Res<Locale>,
+    panels: Query<Entity, With<FilterPanel>>,
+) {
+    if !filter.is_changed() && !network.is_changed() {
+        return;
+    }
+    let mut lane_types: BTreeSet<&str> = network
+        .segments
+        .iter()
+        .map(|lane| lane.lane_type.as_str())
+        .collect();
+    lane_types.extend(filter.hidden_lane_types.iter().map(String::as_str));
+    let categories: Vec<Category> = lane_types
+        .into_iter()
+        .map(|lane_type| Category::LaneType(lane_type.to_string()))
+        .chain([
+            Category::RoadMarks,
+            Category::Signals,
+            Category::Objects,
+            Category::Junctions,
+        ])
+        .collect();
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+
+    for panel in &panels {
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                locale.text("filter-title", &[]),
+                style(Color::WHITE),
+            ));
+            for category in categories.iter().cloned() {
+                let color = if filter.shows(&category) {
+                    Color::rgb(1.0, 0.9, 0.1)
+                } else {
+                    Color::GRAY
+                };
+                let caption = category.caption(&locale);
+                panel
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::axes(Val::Px(6.0), Val::Px(1.0)),
+                                ..default()
+                            },
+                            background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                            ..default()
+                        },
+                        Toggle(category),
+                    ))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section(caption, style(color)));
+                    });
+            }
+            for condition in &filter.conditions {
+                panel.spawn(TextBundle::from_section(
+                    locale.text("filter-condition", &[("condition", condition)]),
+                    style(Color::WHITE),
+                ));
+            }
+            panel.spawn(TextBundle::from_section(
+                locale.text("filter-hint", &[]),
+                style(Color::GRAY),
+            ));
+        });
+    }
+}
+
+// Has the tiles rebuilt when the lanes that go into the meshes change.
+fn rebuild_tiles(
+    filter: Res<Filter>,
+    mut lanes: Local<(BTreeSet<String>, Vec<Condition>)>,
+    mut reload: EventWriter<ReloadTiles>,
+) {
+    if !filter.is_changed() {
+        return;
+    }
+    let now = filter.lane_filter();
+    if now != *lanes {
+        *lanes = now;
+        reload.send(ReloadTiles);
+    }
+}
+
+// Hides the entities filtered out or hidden from the console, including
+// ones spawned since, and shows again those no longer hidden.
+#[allow(clippy::too_many_arguments)]
+fn apply_visibility(
+    filter: Res<Filter>,
+    hidden: Res<Hidden>,
+    isolation: Res<Isolation>,
+    index: Res<OdrEntityIndex>,
+    network: Res<RoadNetwork>,
+    parts: Query<(Entity, &RoadPart)>,
+    signals: Query<(Entity, AnyOf<(&OdrSignal, &OdrObject)>)>,
+    mut was_hidden: Local<HashSet<Entity>>,
+    mut visibilities: Query<&mut Visibility>,
+) {
+    let mut now: HashSet<Entity> = hidden
+        .0
+        .iter()
+        .flat_map(|id| index.entities(id).iter().copied())
+        .collect();
+    if !filter.road_marks || !filter.junctions || isolation.0.is_some() {
+        let in_junction = |road_id: u32| {
+            network
+                .roads
+                .get(&road_id)
+                .is_some_and(|info| info.junction.is_some())
+        };
+        now.extend(
+            parts
+                .iter()
+                .filter(|(_, part)| {
+                    (!filter.road_marks && part.layer == RoadLayer::Marking)
+                        || (!filter.junctions && in_junction(part.road_id))
+                        || !isolation.shows(part.road_id)
+                })
+                .map(|(entity, _)| entity),
+        );
+    }
+    now.extend(
+        signals
+            .iter()
+            .filter(|(_, element)| match element {
+                (Some(signal), _) => {
+                    !filter.signals
+                        || !isolation.shows(signal.road_id)
+                        || !filter.conditions.iter().all(|c| c.passes_signal(signal))
+                }
+                (_, Some(object)) => {
+                    !filter.objects
+                        || !isolation.shows(object.road_id)
+                        || !filter.conditions.iter().all(|c| c.passes_object(object))
+                }
+                (None, None) => false,
+            })
+            .map(|(entity, _)| entity),
+    );
+
+    for &entity in was_hidden.difference(&now) {
+        if let Ok(mut visibility) = visibilities.get_mut(entity) {
+            *visibility = Visibility::Inherited;
+        }
+    }
+    for &entity in &now {
+        if let Ok(mut visibility) = visibilities.get_mut(entity) {
+            if *visibility != Visibility::Hidden {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+    *was_hidden = now;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{lane, load};
+    use crate::signals::SignalKind;
+
+    fn condition(text: &str) -> Condition {
+        Condition::parse(text).unwrap_or_else(|e| panic!("{text}: {e}"))
+    }
+
+    #[test]
+    fn conditions_are_parsed() {
+        let parsed = condition("speed<=30");
+        assert_eq!(
+            (parsed.field, parsed.op, parsed.value.as_str()),
+            (Field::Speed, Op::LessOrEqual, "30")
+        );
+        assert_eq!(condition(" width >  3.25 ").to_string(), "width > 3.25");
+        assert_eq!(condition("type = sidewalk").op, Op::Equal);
+        assert_eq!(condition("lane != -1").op, Op::NotEqual);
+
+        for (text, error) in [
+            ("speed 30", "expected <field> <op> <value>"),
+            ("colour == red", "unknown field `colour`"),
+            ("speed <", "expected a value after `<`"),
+            ("type < sidewalk", "only be compared with == or !="),
+            ("width > wide", "`wide` is not a number"),
+        ] {
+            let message = Condition::parse(text).unwrap_err();
+            assert!(message.contains(error), "{text}: {message}");
+        }
+    }
+
+    #[test]
+    fn lanes_pass_the_conditions_they_meet() {
+        let network = load("straight.xodr");
+        let (driving, sidewalk) = (lane(&network, 1, 1, -1), lane(&network, 1, 1, -2));
+        // The road's limit is 50 km/h.
+        assert!(condition("speed == 50").passes_lane(driving));
+        assert!(!condition("speed < 30").passes_lane(driving));
+        assert!(condition("type == sidewalk").passes_lane(sidewalk));
+        assert!(!condition("type == sidewalk").passes_lane(driving));
+        assert!(condition("width >= 3.5").passes_lane(driving));
+        assert!(!condition("width >= 3.5").passes_lane(sidewalk));
+        // Signal fields leave lanes alone.
+        assert!(condition("value > 100").passes_lane(driving));
+
+        // A lane without a limit fails any condition on it.
+        let mut unlimited = driving.clone();
+        unlimited.speed = None;
+        assert!(!condition("speed != 50").passes_lane(&unlimited));
+
+        let mut filter = Filter::default();
+        filter.conditions.push(condition("type != sidewalk"));
+        assert!(filter.hides_lanes());
+        assert!(filter.shows_lane(driving) && !filter.shows_lane(sidewalk));
+    }
+
+    #[test]
+    fn signals_and_objects_pass_on_their_own_fields() {
+        let signal = OdrSignal {
+            id: "1".to_string(),
+            name: String::new(),
+            road_id: 7,
+            s: 0.0,
+            t: 0.0,
+            kind: SignalKind::SpeedLimit,
+            dynamic: false,
+            value: Some(50.0),
+            unit: "km/h".to_string(),
+        };
+        assert!(condition("value < 60").passes_signal(&signal));
+        assert!(!condition("road == 3").passes_signal(&signal));
+        // Lane fields leave signals alone.
+        assert!(condition("width > 10").passes_signal(&signal));
+
+        let object = OdrObject {
+            id: "2".to_string(),
+            name: String::new(),
+            road_id: 7,
+            s: 0.0,
+            t: 0.0,
+            type_code: "pole".to_string(),
+            height: 4.0,
+        };
+        assert!(condition("type == pole").passes_object(&object));
+        assert!(!condition("height > 5").passes_object(&object));
+        assert!(!condition("height > 5").about_lanes());
+    }
+
+    #[test]
+    fn the_command_sets_categories_and_conditions() {
+        let mut world = World::new();
+        world.init_resource::<Filter>();
+        command(&mut world, &["hide", "lanes", "sidewalk"]).unwrap();
+        command(&mut world, &["hide", "signals"]).unwrap();
+        command(&mut world, &["speed", "<", "30"]).unwrap();
+        assert_eq!(
+            command(&mut world, &[]).unwrap(),
+            "hidden: sidewalk lanes, signals\nwhere speed < 30"
+        );
+        assert!(command(&mut world, &["hide", "trees"]).is_err());
+        assert!(command(&mut world, &["speed", "fast"]).is_err());
+        command(&mut world, &["clear"]).unwrap();
+        assert_eq!(*world.resource::<Filter>(), Filter::default());
+    }
+}
@@ -0,0 +1,109 @@
+// Friction overlay, built as a viewer extension (see `extensions`).
+//
+// Colors every lane with a `<material>` record by its friction, for
+// simulation users checking what surface their vehicles drive on: the lane
+// is outlined and hatched across in a blend from the theme's error color at
+// `LOW_FRICTION` and below (ice, wet paint) to its pass color at
+// `HIGH_FRICTION` and above (dry asphalt). Lanes without a material are
+// left out, as the map says nothing about them. A lane changing surface
+// partway changes color where its next material record starts.
+//
+// Keys: M shows or hides the friction overlay.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::extensions::{overlay_shown, AddOverlay, RsodrPlugin, OVERLAY_LIFT};
+use crate::i18n::Locale;
+use crate::lane_records;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+const NAME: &str = "friction";
+
+// The ends of the color scale.
+const LOW_FRICTION: f64 = 0.3;
+const HIGH_FRICTION: f64 = 1.0;
+
+pub struct FrictionOverlay;
+
+impl RsodrPlugin for FrictionOverlay {
+    fn name(&self) -> &'static str {
+        "friction overlay"
+    }
+
+    fn build(&self, app: &mut App) {
+        app.add_overlay(NAME, KeyCode::KeyM).add_systems(
+            Update,
+            (
+                draw_friction.after(camera_orbit),
+                add_legend.in_set(FillLegend),
+            )
+                .run_if(overlay_shown(NAME)),
+        );
+    }
+}
+
+// The color of a friction value on the theme's scale.
+fn friction_color(friction: f64, theme: &Theme) -> Color {
+    let t = ((friction - LOW_FRICTION) / (HIGH_FRICTION - LOW_FRICTION)).clamp(0.0, 1.0) as f32;
+    let low = theme.error.as_linear_rgba_f32();
+    let high = theme.pass.as_linear_rgba_f32();
+    let mix: [f32; 4] = std::array::from_fn(|i| low[i] + (high[i] - low[i]) * t);
+    Color::rgba_linear(mix[0], mix[1], mix[2], mix[3])
+}
+
+fn add_legend(theme: Res<Theme>, locale: Res<Locale>, mut legend: ResMut<Legend>) {
+    let middle = (LOW_FRICTION + HIGH_FRICTION) / 2.0;
+    let row = |friction: f64, message: &str| {
+        (
+            friction_color(friction, &theme),
+            locale.text(message, &[("friction", &format!("{friction:.2}"))]),
+        )
+    };
+    legend.add(
+        locale.text("legend-friction", &[]),
+        vec![
+            row(LOW_FRICTION, "legend-friction-low"),
+            row(middle, "legend-friction-mid"),
+            row(HIGH_FRICTION, "legend-friction-high"),
+        ],
+    );
+}
+
+fn draw_friction(
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let lift = Vec3::Y * OVERLAY_LIFT;
+    for segment in &network.segments {
+        if segment.materials.is_empty() {
+            continue;
+        }
+        // Vertices are spread evenly over the lane's stations; each piece
+        // takes the color of the material where it starts.
+        let count = segment.left_side.len().min(segment.right_side.len());
+        let length = segment.end_s - segment.start_s;
+        let color = |i: usize| {
+            let s = length * i as f64 / count.saturating_sub(1).max(1) as f64;
+            lane_records::at(&segment.materials, s)
+                .map(|material| friction_color(material.friction, &theme))
+        };
+        let at = |p: DVec3| origin.to_render(p) + lift;
+        for i in 0..count {
+            let Some(color) = color(i) else {
+                continue;
+            };
+            gizmos.line(at(segment.left_side[i]), at(segment.right_side[i]), color);
+            if i + 1 < count {
+                for side in [&segment.left_side, &segment.right_side] {
+                    gizmos.line(at(side[i]), at(side[i + 1]), color);
+                }
+            }
+        }
+    }
+}
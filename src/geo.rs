@@ -0,0 +1,364 @@
+// Geographic coordinates.
+//
+// A map is georeferenced when it says how its coordinates relate to latitude
+// and longitude: OpenDRIVE files give a PROJ string in
+// `<header><geoReference>`, and the OSM importer projects around the center
+// of the data, which it records as a PROJ string of its own. Only the
+// projections met in practice are understood: `utm` and `tmerc`, taken on the
+// WGS84 ellipsoid whatever datum the string names, and the spherical `eqc`
+// the OSM importer uses. Maps without a georeference, or with another
+// projection, are shown in local coordinates only.
+//
+// The georeference applies to the source coordinates of the file, before
+// any load transform; positions in the viewer are taken back through the
+// transform first.
+//
+// Point clouds name their projection by EPSG code rather than PROJ string;
+// the UTM zones on WGS84, ETRS89 and NAD83 are understood.
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::RoadNetwork;
+
+// The WGS84 ellipsoid.
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_223_563;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Projection {
+    TransverseMercator {
+        lat_0: f64,
+        lon_0: f64,
+        k: f64,
+        x_0: f64,
+        y_0: f64,
+    },
+    // On a sphere of the given radius.
+    Equirectangular {
+        lat_ts: f64,
+        lat_0: f64,
+        lon_0: f64,
+        x_0: f64,
+        y_0: f64,
+        radius: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoReference {
+    // The PROJ string as given.
+    pub proj: String,
+    projection: Projection,
+}
+
+impl GeoReference {
+    pub fn parse(proj: &str) -> Result<GeoReference, String> {
+        let parameters: Vec<(&str, &str)> = proj
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('+'))
+            .map(|word| word.split_once('=').unwrap_or((word, "")))
+            .collect();
+        let text = |key: &str| {
+            parameters
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| *value)
+        };
+        let number = |key: &str, default: f64| match text(key) {
+            None => Ok(default),
+            Some(value) => value
+                .parse::<f64>()
+                .map_err(|_| format!("+{key}={value} is not a number")),
+        };
+        let projection = match text("proj") {
+            Some("utm") => {
+                let zone = text("zone")
+                    .and_then(|zone| zone.parse::<u32>().ok())
+                    .filter(|zone| (1..=60).contains(zone))
+                    .ok_or("+proj=utm needs a +zone from 1 to 60")?;
+                Projection::TransverseMercator {
+                    lat_0: 0.0,
+                    lon_0: zone as f64 * 6.0 - 183.0,
+                    k: 0.9996,
+                    x_0: 500_000.0,
+                    y_0: if text("south").is_some() {
+                        10_000_000.0
+                    } else {
+                        0.0
+                    },
+                }
+            }
+            Some("tmerc") => Projection::TransverseMercator {
+                lat_0: number("lat_0", 0.0)?,
+                lon_0: number("lon_0", 0.0)?,
+                k: number("k", number("k_0", 1.0)?)?,
+                x_0: number("x_0", 0.0)?,
+                y_0: number("y_0", 0.0)?,
+            },
+            Some("eqc") => Projection::Equirectangular {
+                lat_ts: number("lat_ts", 0.0)?,
+                lat_0: number("lat_0", 0.0)?,
+                lon_0: number("lon_0", 0.0)?,
+                x_0: number("x_0", 0.0)?,
+                y_0: number("y_0", 0.0)?,
+                radius: number("R", SEMI_MAJOR_AXIS)?,
+            },
+            Some(other) => return Err(format!("projection `{other}` is not supported")),
+            None => return Err("no +proj".to_string()),
+        };
+        Ok(GeoReference {
+            proj: proj.trim().to_string(),
+            projection,
+        })
+    }
+
+    // The projection of an EPSG code, for the UTM zones.
+    pub fn from_epsg(code: u32) -> Option<GeoReference> {
+        let (zone, south) = match code {
+            32601..=32660 => (code - 32600, false),
+            32701..=32760 => (code - 32700, true),
+            25828..=25838 => (code - 25800, false),
+            26901..=26923 => (code - 26900, false),
+            _ => return None,
+        };
+        let south = if south { " +south" } else { "" };
+        GeoReference::parse(&format!("+proj=utm +zone={zone}{south}")).ok()
+    }
+
+    // Longitude and latitude, in degrees, of a projected position.
+    pub fn to_geographic(&self, p: DVec2) -> DVec2 {
+        match self.projection {
+            Projection::TransverseMercator {
+                lat_0,
+                lon_0,
+                k,
+                x_0,
+                y_0,
+            } => inverse_tmerc(p - DVec2::new(x_0, y_0), lat_0, lon_0, k),
+            Projection::Equirectangular {
+                lat_ts,
+                lat_0,
+                lon_0,
+                x_0,
+                y_0,
+                radius,
+            } => DVec2::new(
+                lon_0 + ((p.x - x_0) / (radius * lat_ts.to_radians().cos())).to_degrees(),
+                lat_0 + ((p.y - y_0) / radius).to_degrees(),
+            ),
+        }
+    }
+
+    // The projected position of a longitude and latitude, in degrees.
+    pub fn project(&self, lon_lat: DVec2) -> DVec2 {
+        match self.projection {
+            Projection::TransverseMercator {
+                lat_0,
+                lon_0,
+                k,
+                x_0,
+                y_0,
+            } => tmerc(lon_lat, lat_0, lon_0, k) + DVec2::new(x_0, y_0),
+            Projection::Equirectangular {
+                lat_ts,
+                lat_0,
+                lon_0,
+                x_0,
+                y_0,
+                radius,
+            } => DVec2::new(
+                x_0 + radius * (lon_lat.x - lon_0).to_radians() * lat_ts.to_radians().cos(),
+                y_0 + radius * (lon_lat.y - lat_0).to_radians(),
+            ),
+        }
+    }
+}
+
+// Longitude and latitude of a viewer-frame position, if the network is
+// georeferenced.
+pub fn geographic(network: &RoadNetwork, p: DVec3) -> Option<DVec2> {
+    let geo = network.geo.as_ref()?;
+    let source = network
+        .transform
+        .source_position(DVec3::new(p.x, -p.z, p.y));
+    Some(geo.to_geographic(source.truncate()))
+}
+
+// Map-frame position of a longitude and latitude, in degrees, if the network
+// is georeferenced; the inverse of `geographic` on the ground.
+pub fn map_position(network: &RoadNetwork, lon_lat: DVec2) -> Option<DVec2> {
+    let geo = network.geo.as_ref()?;
+    let source = geo.project(lon_lat).extend(0.0);
+    Some(network.transform.apply(source).truncate())
+}
+
+fn eccentricity_squared() -> f64 {
+    FLATTENING * (2.0 - FLATTENING)
+}
+
+// Distance along the meridian from the equator to `lat`, in radians, on the
+// ellipsoid.
+fn meridian_arc(lat: f64) -> f64 {
+    let e2 = eccentricity_squared();
+    let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+    SEMI_MAJOR_AXIS
+        * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * lat).sin())
+}
+
+// Transverse Mercator on the ellipsoid, after Snyder's "Map Projections: A
+// Working Manual", relative to the false origin.
+fn tmerc(lon_lat: DVec2, lat_0: f64, lon_0: f64, k: f64) -> DVec2 {
+    let e2 = eccentricity_squared();
+    let ep2 = e2 / (1.0 - e2);
+    let lat = lon_lat.y.to_radians();
+    let (sin, cos, tan) = (lat.sin(), lat.cos(), lat.tan());
+    let n = SEMI_MAJOR_AXIS / (1.0 - e2 * sin * sin).sqrt();
+    let t = tan * tan;
+    let c = ep2 * cos * cos;
+    let a = (lon_lat.x - lon_0).to_radians() * cos;
+    let x = k
+        * n
+        * (a + (1.0 - t + c) * a.powi(3) / 6.0
+            + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+    let y = k
+        * (meridian_arc(lat) - meridian_arc(lat_0.to_radians())
+            + n * tan
+                * (a * a / 2.0
+                    + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                    + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+    DVec2::new(x, y)
+}
+
+// Inverse Transverse Mercator on the ellipsoid, after Snyder's "Map
+// Projections: A Working Manual", for a position relative to the false
+// origin.
+fn inverse_tmerc(p: DVec2, lat_0: f64, lon_0: f64, k: f64) -> DVec2 {
+    let e2 = eccentricity_squared();
+    let ep2 = e2 / (1.0 - e2);
+    let m = meridian_arc(lat_0.to_radians()) + p.y / k;
+    let mu = m
+        / (SEMI_MAJOR_AXIS * (1.0 - e2 / 4.0 - 3.0 * e2 * e2 / 64.0 - 5.0 * e2 * e2 * e2 / 256.0));
+    let e1 = (1.0 - (1.0 - e2).sqrt()) / (1.0 + (1.0 - e2).sqrt());
+    // The footpoint latitude.
+    let lat_1 = mu
+        + (3.0 * e1 / 2.0 - 27.0 * e1.powi(3) / 32.0) * (2.0 * mu).sin()
+        + (21.0 * e1 * e1 / 16.0 - 55.0 * e1.powi(4) / 32.0) * (4.0 * mu).sin()
+        + (151.0 * e1.powi(3) / 96.0) * (6.0 * mu).sin()
+        + (1097.0 * e1.powi(4) / 512.0) * (8.0 * mu).sin();
+    let (sin, cos, tan) = (lat_1.sin(), lat_1.cos(), lat_1.tan());
+    let c = ep2 * cos * cos;
+    let t = tan * tan;
+    let n = SEMI_MAJOR_AXIS / (1.0 - e2 * sin * sin).sqrt();
+    let r = SEMI_MAJOR_AXIS * (1.0 - e2) / (1.0 - e2 * sin * sin).powf(1.5);
+    let d = p.x / (n * k);
+    let lat = lat_1
+        - n * tan / r
+            * (d * d / 2.0
+                - (5.0 + 3.0 * t + 10.0 * c - 4.0 * c * c - 9.0 * ep2) * d.powi(4) / 24.0
+                + (61.0 + 90.0 * t + 298.0 * c + 45.0 * t * t - 252.0 * ep2 - 3.0 * c * c)
+                    * d.powi(6)
+                    / 720.0);
+    let lon = (d - (1.0 + 2.0 * t + c) * d.powi(3) / 6.0
+        + (5.0 - 2.0 * c + 28.0 * t - 3.0 * c * c + 8.0 * ep2 + 24.0 * t * t) * d.powi(5) / 120.0)
+        / cos;
+    DVec2::new(lon_0 + lon.to_degrees(), lat.to_degrees())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+    use crate::loader::load_network;
+    use crate::sample_maps::{temp_dir, text};
+    use crate::transform::LoadTransform;
+    use crate::{compiled, osm, xodr};
+
+    #[test]
+    fn utm() {
+        let assert_lon_lat = |found: DVec2, lon: f64, lat: f64| {
+            assert!(
+                (found.x - lon).abs() < 1e-6 && (found.y - lat).abs() < 1e-6,
+                "{found} is not {lon}, {lat}"
+            );
+        };
+        // On the central meridian, northing is the scaled meridian arc, 4984944.378
+        // m from the equator to 45°.
+        let utm = GeoReference::parse("+proj=utm +zone=32 +datum=WGS84 +units=m").unwrap();
+        assert_lon_lat(utm.to_geographic(DVec2::new(500_000.0, 0.0)), 9.0, 0.0);
+        assert_lon_lat(
+            utm.to_geographic(DVec2::new(500_000.0, 0.9996 * 4_984_944.378)),
+            9.0,
+            45.0,
+        );
+        let east = utm.to_geographic(DVec2::new(520_000.0, 5_000_000.0));
+        let west = utm.to_geographic(DVec2::new(480_000.0, 5_000_000.0));
+        assert_lon_lat(west, 18.0 - east.x, east.y);
+        assert!(east.x > 9.25 && east.x < 9.27, "{east}");
+        // Projecting back lands where the position started, well within a
+        // millimeter.
+        for p in [
+            DVec2::new(520_000.0, 5_000_000.0),
+            DVec2::new(380_000.0, 6_100_000.0),
+        ] {
+            let back = utm.project(utm.to_geographic(p));
+            assert!(back.distance(p) < 1e-3, "{back} is not {p}");
+        }
+        assert_eq!(
+            GeoReference::from_epsg(32632).unwrap().proj,
+            "+proj=utm +zone=32"
+        );
+        assert!(GeoReference::from_epsg(32733)
+            .unwrap()
+            .proj
+            .ends_with("+south"));
+        assert!(GeoReference::from_epsg(4326).is_none());
+        assert!(GeoReference::parse("+proj=lcc +lat_1=49").is_err());
+        assert!(GeoReference::parse("+proj=utm").is_err());
+    }
+
+    #[test]
+    fn georeferences_apply_to_the_file_coordinates() {
+        let assert_lon_lat = |found: DVec2, lon: f64, lat: f64| {
+            assert!(
+                (found.x - lon).abs() < 1e-6 && (found.y - lat).abs() < 1e-6,
+                "{found} is not {lon}, {lat}"
+            );
+        };
+        // An OpenDRIVE georeference applies to the file's coordinates, before
+        // the load transform.
+        let xml = text("straight.xodr").replace(
+            r#"name="straight"/>"#,
+            r#"name="straight">
+        <geoReference><![CDATA[+proj=tmerc +lat_0=49 +lon_0=8 +k=1 +x_0=0 +y_0=0]]></geoReference>
+      </header>"#,
+        );
+        let transform = LoadTransform {
+            offset: DVec3::new(1000.0, -500.0, 0.0),
+            ..LoadTransform::default()
+        };
+        let network = xodr::read_str(&xml, &transform).unwrap();
+        let start = transform.viewer_position(DVec3::ZERO);
+        assert_lon_lat(geographic(&network, start).unwrap(), 8.0, 49.0);
+
+        // OSM data is georeferenced around its center, and compiled maps keep
+        // their georeference.
+        let osm = osm::import_str(
+            r#"<osm>
+      <node id="1" lat="48.0" lon="11.0"/>
+      <node id="2" lat="48.002" lon="11.004"/>
+      <way id="3"><nd ref="1"/><nd ref="2"/><tag k="highway" v="residential"/></way>
+    </osm>"#,
+        )
+        .unwrap();
+        assert_lon_lat(geographic(&osm, DVec3::ZERO).unwrap(), 11.002, 48.001);
+        let path = temp_dir("geographic_coordinates").join("geo.rsnet");
+        compiled::write_file(&network, &path).unwrap();
+        let compiled = load_network(Some(&path));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(compiled.unwrap().geo, network.geo);
+    }
+}
@@ -0,0 +1,238 @@
+// A small glTF 2.0 writer.
+//
+// Produces a single self-contained `.gltf` file with the binary buffer
+// embedded as a base64 data URI, which every DCC tool and game engine we care
+// about (Blender, Unreal, Unity, CARLA's importer) can read.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use crate::tessellation::TriangleMesh;
+
+// glTF constants for accessor component types and buffer view targets.
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// A named, flat-colored material.
+struct Material {
+    name: String,
+    color: [f32; 4],
+}
+
+// A mesh together with the node that places it in the scene.
+struct Node {
+    name: String,
+    material: usize,
+    position_accessor: usize,
+    normal_accessor: usize,
+    index_accessor: usize,
+}
+
+// Collects meshes and materials and serializes them as a glTF document.
+#[derive(Default)]
+pub struct GltfDocument {
+    buffer: Vec<u8>,
+    buffer_views: Vec<String>,
+    accessors: Vec<String>,
+    materials: Vec<Material>,
+    nodes: Vec<Node>,
+}
+
+impl GltfDocument {
+    // Adds a mesh as its own node. Empty meshes are skipped, since glTF does
+    // not allow zero-sized accessors.
+    pub fn add_mesh(&mut self, name: &str, mesh: &TriangleMesh, material: &str, color: [f32; 4]) {
+        if mesh.is_empty() {
+            return;
+        }
+
+        let material = self.material(material, color);
+
+        let positions: Vec<[f32; 3]> = mesh.positions.iter().map(|p| p.to_array()).collect();
+        let normals: Vec<[f32; 3]> = mesh.normals.iter().map(|n| n.to_array()).collect();
+
+        let position_accessor = self.add_vec3_accessor(&positions, true);
+        let normal_accessor = self.add_vec3_accessor(&normals, false);
+        let index_accessor = self.add_index_accessor(&mesh.indices);
+
+        self.nodes.push(Node {
+            name: name.to_string(),
+            material,
+            position_accessor,
+            normal_accessor,
+            index_accessor,
+        });
+    }
+
+    // Writes the document to `path`.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+
+    // Serializes the document as glTF JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        json.push_str("{\n");
+        json.push_str("  \"asset\": {\"version\": \"2.0\", \"generator\": \"rsodr\"},\n");
+        json.push_str("  \"scene\": 0,\n");
+
+        let node_ids: Vec<String> = (0..self.nodes.len()).map(|i| i.to_string()).collect();
+        let _ = writeln!(
+            json,
+            "  \"scenes\": [{{\"nodes\": [{}]}}],",
+            node_ids.join(", ")
+        );
+
+        let nodes: Vec<String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| format!("{{\"name\": {:?}, \"mesh\": {i}}}", node.name))
+            .collect();
+        write_array(&mut json, "nodes", &nodes);
+
+        let meshes: Vec<String> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    "{{\"name\": {:?}, \"primitives\": [{{\"attributes\": {{\"POSITION\": {}, \"NORMAL\": {}}}, \"indices\": {}, \"material\": {}}}]}}",
+                    node.name, node.position_accessor, node.normal_accessor, node.index_accessor, node.material
+                )
+            })
+            .collect();
+        write_array(&mut json, "meshes", &meshes);
+
+        let materials: Vec<String> = self
+            .materials
+            .iter()
+            .map(|m| {
+                let [r, g, b, a] = m.color;
+                format!(
+                    "{{\"name\": {:?}, \"pbrMetallicRoughness\": {{\"baseColorFactor\": [{r}, {g}, {b}, {a}], \"metallicFactor\": 0.0, \"roughnessFactor\": 0.9}}}}",
+                    m.name
+                )
+            })
+            .collect();
+        write_array(&mut json, "materials", &materials);
+
+        write_array(&mut json, "accessors", &self.accessors);
+        write_array(&mut json, "bufferViews", &self.buffer_views);
+
+        let _ = writeln!(
+            json,
+            "  \"buffers\": [{{\"byteLength\": {}, \"uri\": \"data:application/octet-stream;base64,{}\"}}]",
+            self.buffer.len(),
+            base64(&self.buffer)
+        );
+        json.push_str("}\n");
+        json
+    }
+
+    // Returns the index of the named material, creating it on first use.
+    fn material(&mut self, name: &str, color: [f32; 4]) -> usize {
+        if let Some(i) = self.materials.iter().position(|m| m.name == name) {
+            return i;
+        }
+        self.materials.push(Material {
+            name: name.to_string(),
+            color,
+        });
+        self.materials.len() - 1
+    }
+
+    // Appends raw bytes as a new buffer view and returns its index.
+    fn add_buffer_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        // Accessors must be aligned to their component size (4 bytes here).
+        while !self.buffer.len().is_multiple_of(4) {
+            self.buffer.push(0);
+        }
+        let offset = self.buffer.len();
+        self.buffer.extend_from_slice(bytes);
+        self.buffer_views.push(format!(
+            "{{\"buffer\": 0, \"byteOffset\": {offset}, \"byteLength\": {}, \"target\": {target}}}",
+            bytes.len()
+        ));
+        self.buffer_views.len() - 1
+    }
+
+    // Adds a VEC3 float accessor. Positions must carry their bounds.
+    fn add_vec3_accessor(&mut self, values: &[[f32; 3]], with_bounds: bool) -> usize {
+        let bytes: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let view = self.add_buffer_view(&bytes, ARRAY_BUFFER);
+
+        let mut accessor = format!(
+            "{{\"bufferView\": {view}, \"componentType\": {FLOAT}, \"count\": {}, \"type\": \"VEC3\"",
+            values.len()
+        );
+        if with_bounds {
+            let mut min = [f32::INFINITY; 3];
+            let mut max = [f32::NEG_INFINITY; 3];
+            for v in values {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(v[axis]);
+                    max[axis] = max[axis].max(v[axis]);
+                }
+            }
+            let _ = write!(
+                accessor,
+                ", \"min\": [{}, {}, {}], \"max\": [{}, {}, {}]",
+                min[0], min[1], min[2], max[0], max[1], max[2]
+            );
+        }
+        accessor.push('}');
+
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    // Adds a scalar u32 accessor for triangle indices.
+    fn add_index_accessor(&mut self, indices: &[u32]) -> usize {
+        let bytes: Vec<u8> = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = self.add_buffer_view(&bytes, ELEMENT_ARRAY_BUFFER);
+        self.accessors.push(format!(
+            "{{\"bufferView\": {view}, \"componentType\": {UNSIGNED_INT}, \"count\": {}, \"type\": \"SCALAR\"}}",
+            indices.len()
+        ));
+        self.accessors.len() - 1
+    }
+}
+
+// Writes a JSON array member, one element per line.
+fn write_array(json: &mut String, name: &str, items: &[String]) {
+    let _ = writeln!(json, "  \"{name}\": [");
+    for (i, item) in items.iter().enumerate() {
+        let comma = if i + 1 < items.len() { "," } else { "" };
+        let _ = writeln!(json, "    {item}{comma}");
+    }
+    json.push_str("  ],\n");
+}
+
+// Standard base64 encoding with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
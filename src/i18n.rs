@@ -0,0 +1,295 @@
+// Localized UI text.
+//
+// Text shown in the viewer's panels and charts is looked up by message ID in
+// the `Locale`. Messages are written in a small subset of Fluent's syntax:
+// one `id = text` per line, `{ $name }` placeholders and `#` comments.
+// English is built in (`ENGLISH`); another language is a file translating
+// the same IDs, given to `view --lang` as a path, or as a language code that
+// is looked up as `lang/<code>.ftl` in the config directory. Without
+// `--lang`, the language of `LANG` is used if there is a file for it.
+// Messages a translation lacks are shown in English. Log output and the
+// headless commands stay in English.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::bookmarks::config_dir;
+
+// The built-in messages, and the template for translations.
+pub const ENGLISH: &str = "\
+# Charts of the selected road
+profile-title = road { $road } elevation profile
+profile-elevation = elevation
+profile-grade = grade
+profile-superelevation = superelevation
+curvature-title = road { $road } heading and curvature, { $steps } step(s)
+curvature-heading = heading
+curvature-curvature = curvature
+sight-title = road { $road } lane { $lane } sight distance, { $stretches } stretch(es) below { $threshold } m
+sight-distance = sight distance
+sight-threshold = threshold
+route-title = route of { $length } m
+route-elevation = elevation
+route-speed = speed limit
+route-curvature = curvature
+
+# Selection and inspector panel
+selection-copied = (copied)
+inspector-road = road { $road }
+inspector-road-named = road { $road }, { $name }
+inspector-road-type = { $type } road
+inspector-speed = lane { $lane } speed limit { $speed }
+inspector-traffic-along = lane { $lane } in { $rule } traffic, driven along the reference line
+inspector-traffic-against = lane { $lane } in { $rule } traffic, driven against the reference line
+inspector-junction = junction { $junction }
+inspector-junction-roads = { $incoming } incoming roads, { $connecting } connecting roads
+inspector-junction-area = { $conflicts } conflict points, { $area } m² paved
+inspector-junction-findings = { $count } validation findings
+inspector-material = lane { $lane } { $surface }, friction { $friction }, roughness { $roughness }
+inspector-signal = signal { $id } { $type } ({ $distance } m away)
+inspector-object = object { $id } { $type } ({ $distance } m away)
+inspector-copy-xml = Copy XML
+inspector-point = s { $s } m, t { $t } m at { $position }
+
+# Status bar
+status-lane = road { $road } lane { $lane }, s { $s } m, t { $t } m
+status-nearest-lane = nearest road { $road } lane { $lane }, s { $s } m, t { $t } m, { $distance } m from its center
+status-no-lane = no lane nearby
+
+# Settings panel
+settings-title = Settings (D)
+settings-coordinates = positions: { $kind }
+settings-local = map x, y, z
+settings-geographic = latitude, longitude
+settings-not-georeferenced = (the map is not georeferenced)
+settings-coordinate-decimals = position decimals: { $count }
+settings-station-decimals = s and t decimals: { $count }
+settings-speed-unit = speeds in { $unit }
+
+# XML tree panel
+xml-title = XML as read (Z)
+xml-empty = no XML was kept for this map
+xml-more-above = ... { $count } more above
+xml-more-below = ... { $count } more below
+
+# Filter panel
+filter-title = Show
+filter-lane-type = { $type } lanes
+filter-road-marks = road marks
+filter-signals = signals
+filter-objects = objects
+filter-junctions = junction interiors
+filter-condition = where { $condition }
+templates-title = Cross-section templates
+templates-lane = lane { $lane } { $type } { $width } m
+templates-hint = draw a road with `road new` in the console
+filter-hint = add conditions with `filter` in the console
+
+# Lane connectivity graph
+topology-title = lanes around road { $road }, { $roads } road(s): { $broken } broken, { $dangling } dangling
+topology-hint = select a road to see how its lanes connect
+
+# Issue list
+issues-more = ... and { $count } more
+
+# Legend of the map colors
+legend-title = Legend
+legend-style = style sheet
+legend-issues = issues
+legend-info = info
+legend-warning = warning
+legend-error = error
+legend-design = design rules
+legend-grade = too steep
+legend-radius = too tight for the speed
+legend-transition = too short a transition
+legend-width = lane widths
+legend-narrow = too narrow
+legend-wide = too wide
+legend-sudden = changing too suddenly
+legend-lane-change = lane changes
+legend-lane-change-both = allowed both ways
+legend-lane-change-one = allowed one way
+legend-conflicts = junction conflicts
+legend-crossing = crossing
+legend-merging = merging
+legend-priority = right of way
+legend-signalized = traffic lights
+legend-priority-road = priority road
+legend-yield = yield
+legend-stop = stop
+legend-unknown = no rule found
+legend-friction = friction
+legend-friction-low = { $friction } or less
+legend-friction-mid = { $friction }
+legend-friction-high = { $friction } or more
+legend-road-type = road type
+legend-motorway = motorway
+legend-rural = rural
+legend-town = town
+legend-low-speed = low speed, pedestrians, bicycles
+";
+
+#[derive(Resource, Debug, Clone)]
+pub struct Locale {
+    messages: HashMap<String, String>,
+    english: HashMap<String, String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        let english = parse(ENGLISH);
+        Self {
+            messages: english.clone(),
+            english,
+        }
+    }
+}
+
+impl Locale {
+    // Loads a language: a `.ftl` file, or a code looked up in the config
+    // directory. "en" is the built-in English.
+    pub fn load(language: &str) -> Result<Self, String> {
+        if language == "en" {
+            return Ok(Self::default());
+        }
+        let file = if language.ends_with(".ftl") || language.contains(['/', '\\']) {
+            PathBuf::from(language)
+        } else {
+            language_file(language).ok_or("no config directory for language files")?
+        };
+        let text =
+            std::fs::read_to_string(&file).map_err(|e| format!("{}: {e}", file.display()))?;
+        Ok(Self {
+            messages: parse(&text),
+            ..Self::default()
+        })
+    }
+
+    // The language of the environment (`LANG`), if there is a file for it,
+    // and English otherwise.
+    pub fn from_environment() -> Self {
+        let lang = std::env::var("LANG").unwrap_or_default();
+        let code = language_code(&lang);
+        match language_file(code) {
+            Some(file) if !code.is_empty() && code != "en" && file.exists() => {
+                Self::load(code).unwrap_or_default()
+            }
+            _ => Self::default(),
+        }
+    }
+
+    // The message with an ID, with its placeholders filled in. An unknown ID
+    // is shown as is.
+    pub fn text(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        let Some(message) = self.messages.get(id).or_else(|| self.english.get(id)) else {
+            return id.to_string();
+        };
+        fill(message, args)
+    }
+}
+
+// The language code of a locale name: "de_DE.UTF-8" is German.
+fn language_code(lang: &str) -> &str {
+    lang.split(['_', '.', '@']).next().unwrap_or_default()
+}
+
+fn language_file(code: &str) -> Option<PathBuf> {
+    Some(
+        config_dir()?
+            .join("lang")
+            .join(Path::new(code).with_extension("ftl")),
+    )
+}
+
+// Reads `id = text` lines, skipping comments and anything else.
+fn parse(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, message)| (id.trim().to_string(), message.trim().to_string()))
+        .filter(|(id, _)| !id.is_empty())
+        .collect()
+}
+
+// Replaces `{ $name }` with the named argument. Unknown placeholders are
+// left in place.
+fn fill(message: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        let name = rest[open + 1..close].trim().trim_start_matches('$');
+        match args.iter().find(|(arg, _)| *arg == name) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::temp_dir;
+
+    #[test]
+    fn messages_are_parsed_and_filled() {
+        let messages = parse(
+            "# a comment\n  greeting = hello { $name }, { $name }!  \nnot a message\n = no ID\n",
+        );
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        let greeting = &messages["greeting"];
+        assert_eq!(greeting, "hello { $name }, { $name }!");
+        assert_eq!(fill(greeting, &[("name", &"map")]), "hello map, map!");
+        // Unknown placeholders stay, and so does an unclosed brace.
+        assert_eq!(fill("{ $who } at { $s", &[("s", &1.5)]), "{ $who } at { $s");
+        assert_eq!(fill("s { $s } m", &[("s", &1.5)]), "s 1.5 m");
+    }
+
+    #[test]
+    fn missing_messages_fall_back_to_english() {
+        let dir = temp_dir("i18n");
+        let file = dir.join("de.ftl");
+        std::fs::write(
+            &file,
+            "route-speed = Tempolimit\nroute-title = Route von { $length } m\n",
+        )
+        .unwrap();
+        let german = Locale::load(file.to_str().unwrap()).unwrap();
+        assert_eq!(german.text("route-speed", &[]), "Tempolimit");
+        assert_eq!(
+            german.text("route-title", &[("length", &120)]),
+            "Route von 120 m"
+        );
+        // Not translated: English.
+        assert_eq!(german.text("route-curvature", &[]), "curvature");
+        // Not a message at all: the ID.
+        assert_eq!(german.text("no-such-message", &[]), "no-such-message");
+        let _ = std::fs::remove_dir_all(dir);
+
+        assert_eq!(
+            Locale::load("en").unwrap().text("route-speed", &[]),
+            "speed limit"
+        );
+        assert!(Locale::load("/no/such/dir/xx.ftl").is_err());
+    }
+
+    #[test]
+    fn locale_names_give_their_language() {
+        assert_eq!(language_code("de_DE.UTF-8"), "de");
+        assert_eq!(language_code("fr.UTF-8"), "fr");
+        assert_eq!(language_code("sr@latin"), "sr");
+        assert_eq!(language_code("C"), "C");
+        assert_eq!(language_code(""), "");
+    }
+}
@@ -0,0 +1,499 @@
+// Inspector panel for the selection.
+//
+// The panel in the bottom right corner names the selected road, where it
+// was picked (written as the display settings say, see `display`), the
+// junction it belongs to, the traffic rule of the picked lane and whether it
+// is driven along the reference line or against it, the material of the
+// picked lane if the map gives one, and the signal or object nearest to the
+// picked point, each with a button that copies the element's XML, as it was
+// read from the OpenDRIVE file, to the clipboard for bug reports or editors.
+// Elements that were not read from a file, or that were changed since (like
+// cropped roads), have no XML to copy and no button.
+//
+// Under a junction, the panel adds figures derived from the map: the roads
+// leading into it (those with a stop line, see `conflicts`), its connecting
+// roads, the conflict points between its paths, the paved area of its
+// connecting roads' lanes, and the validation findings on those roads.
+
+use std::borrow::Cow;
+
+use bevy::prelude::*;
+
+use crate::clipboard;
+use crate::conflicts;
+use crate::display::DisplaySettings;
+use crate::i18n::Locale;
+use crate::lane_records;
+use crate::lane_report;
+use crate::selection::Selection;
+use crate::signals::SignalKind;
+use crate::units::SpeedUnit;
+use crate::validation::Report;
+use crate::RoadNetwork;
+
+// Signals further than this from the picked point are not offered, in meters.
+const SIGNAL_RANGE: f64 = 30.0;
+
+// Lane types left out of a junction's paved area.
+const UNPAVED_TYPES: &[&str] = &["none", "border", "curb", "rail"];
+
+// The findings listed under a junction; the rest are counted.
+const LISTED_FINDINGS: usize = 3;
+
+// An element whose XML can be copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Element {
+    Road(u32),
+    Junction(u32),
+    // Index into `RoadNetwork::segments`.
+    Lane(usize),
+    // Index into `RoadNetwork::signals`.
+    Signal(usize),
+}
+
+impl Element {
+    // Whether there is XML to copy, without reading it.
+    fn has_xml(self, network: &RoadNetwork) -> bool {
+        match self {
+            Element::Road(id) => network
+                .roads
+                .get(&id)
+                .is_some_and(|info| !info.xml.is_empty()),
+            Element::Junction(id) => network
+                .junctions
+                .get(&id)
+                .is_some_and(|xml| !xml.is_empty()),
+            // Lanes are copied with their road.
+            Element::Lane(_) => false,
+            Element::Signal(index) => network
+                .signals
+                .get(index)
+                .is_some_and(|s| !s.xml.is_empty()),
+        }
+    }
+
+    // The XML, read back from the map file for roads and signals.
+    fn xml(self, network: &RoadNetwork) -> Option<Cow<'_, str>> {
+        let xml = match self {
+            Element::Road(id) => network.roads.get(&id)?.xml.text(),
+            Element::Junction(id) => network.junctions.get(&id)?.text(),
+            Element::Lane(_) => None,
+            Element::Signal(index) => network.signals.get(index)?.xml.text(),
+        };
+        xml.filter(|xml| !xml.is_empty())
+    }
+}
+
+// Marks the inspector panel.
+#[derive(Component)]
+struct Inspector;
+
+// A button copying an element's XML.
+#[derive(Component)]
+struct CopyXml(Element);
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_panel)
+            .add_systems(Update, (fill_panel, copy_on_click));
+    }
+}
+
+// The figures shown for a junction.
+#[derive(Debug, Clone, PartialEq)]
+struct JunctionStats {
+    incoming_roads: usize,
+    connecting_roads: usize,
+    conflict_points: usize,
+    // In square meters.
+    paved_area: f64,
+    // Indices into the validation report.
+    findings: Vec<usize>,
+}
+
+fn junction_stats(network: &RoadNetwork, report: &Report, junction: u32) -> JunctionStats {
+    let connecting = |road_id: u32| {
+        network
+            .roads
+            .get(&road_id)
+            .is_some_and(|info| info.junction == Some(junction))
+    };
+    let mut incoming: Vec<u32> = conflicts::stop_lines(network, junction)
+        .iter()
+        .map(|stop| network.segments[stop.segment].road_id)
+        .collect();
+    incoming.sort_unstable();
+    incoming.dedup();
+    JunctionStats {
+        incoming_roads: incoming.len(),
+        connecting_roads: network.roads.keys().filter(|&&id| connecting(id)).count(),
+        conflict_points: conflicts::conflict_points(network, junction).len(),
+        paved_area: network
+            .segments
+            .iter()
+            .filter(|lane| {
+                connecting(lane.road_id) && !UNPAVED_TYPES.contains(&lane.lane_type.as_str())
+            })
+            .map(lane_report::surface_area)
+            .sum(),
+        findings: (0..report.0.len())
+            .filter(|&i| connecting(report.0[i].road_id))
+            .collect(),
+    }
+}
+
+// The elements to show for the selection, with their captions. Rows without
+// an element are figures with nothing to copy.
+fn elements(
+    network: &RoadNetwork,
+    report: &Report,
+    selection: &Selection,
+    locale: &Locale,
+    speed_unit: SpeedUnit,
+    display: &DisplaySettings,
+) -> Vec<(Option<Element>, String)> {
+    let Some(pick) = selection.0 else {
+        return Vec::new();
+    };
+    let name = network
+        .roads
+        .get(&pick.road_id)
+        .map_or("", |info| info.name.trim());
+    let mut out = vec![(
+        Some(Element::Road(pick.road_id)),
+        if name.is_empty() {
+            locale.text("inspector-road", &[("road", &pick.road_id)])
+        } else {
+            locale.text(
+                "inspector-road-named",
+                &[("road", &pick.road_id), ("name", &name)],
+            )
+        },
+    )];
+    out.push((
+        None,
+        locale.text(
+            "inspector-point",
+            &[
+                ("s", &display.station(pick.s)),
+                ("t", &pick.t.map_or("-".to_string(), |t| display.station(t))),
+                ("position", &display.position(network, pick.position)),
+            ],
+        ),
+    ));
+    if let Some(lane) = network
+        .segments
+        .get(pick.segment)
+        .filter(|lane| !lane.road_type.is_empty())
+    {
+        out.push((
+            None,
+            locale.text("inspector-road-type", &[("type", &lane.road_type)]),
+        ));
+    }
+    if let Some(speed) = network
+        .segments
+        .get(pick.segment)
+        .and_then(|lane| lane.speed)
+    {
+        out.push((
+            None,
+            locale.text(
+                "inspector-speed",
+                &[
+                    ("lane", &pick.lane_id),
+                    ("speed", &speed_unit.format(speed)),
+                ],
+            ),
+        ));
+    }
+    if let Some(lane) = network
+        .segments
+        .get(pick.segment)
+        .filter(|lane| lane.lane_id != 0)
+    {
+        out.push((
+            None,
+            locale.text(
+                if lane.follows_reference() {
+                    "inspector-traffic-along"
+                } else {
+                    "inspector-traffic-against"
+                },
+                &[("lane", &pick.lane_id), ("rule", &lane.rule.as_attribute())],
+            ),
+        ));
+    }
+    if let Some(junction) = network
+        .roads
+        .get(&pick.road_id)
+        .and_then(|info| info.junction)
+    {
+        out.push((
+            Some(Element::Junction(junction)),
+            locale.text("inspector-junction", &[("junction", &junction)]),
+        ));
+        let stats = junction_stats(network, report, junction);
+        out.push((
+            None,
+            locale.text(
+                "inspector-junction-roads",
+                &[
+                    ("incoming", &stats.incoming_roads),
+                    ("connecting", &stats.connecting_roads),
+                ],
+            ),
+        ));
+        out.push((
+            None,
+            locale.text(
+                "inspector-junction-area",
+                &[
+                    ("conflicts", &stats.conflict_points),
+                    ("area", &format!("{:.0}", stats.paved_area)),
+                ],
+            ),
+        ));
+        out.push((
+            None,
+            locale.text(
+                "inspector-junction-findings",
+                &[("count", &stats.findings.len())],
+            ),
+        ));
+        for &i in stats.findings.iter().take(LISTED_FINDINGS) {
+            let issue = &report.0[i];
+            out.push((
+                None,
+                format!(
+                    "  {} road {}: {}",
+                    issue.severity.name(),
+                    issue.road_id,
+                    issue.message
+                ),
+            ));
+        }
+        if stats.findings.len() > LISTED_FINDINGS {
+            out.push((
+                None,
+                locale.text(
+                    "issues-more",
+                    &[("count", &(stats.findings.len() - LISTED_FINDINGS))],
+                ),
+            ));
+        }
+    }
+    // The material in force where the lane was picked.
+    if let Some(material) = network
+        .segments
+        .get(pick.segment)
+        .and_then(|lane| lane_records::at(&lane.materials, pick.s - lane.start_s))
+    {
+        let surface = if material.surface.is_empty() {
+            "-"
+        } else {
+            material.surface.as_str()
+        };
+        out.push((
+            Some(Element::Lane(pick.segment)),
+            locale.text(
+                "inspector-material",
+                &[
+                    ("lane", &pick.lane_id),
+                    ("surface", &surface),
+                    ("friction", &format!("{:.2}", material.friction)),
+                    ("roughness", &format!("{:.3}", material.roughness)),
+                ],
+            ),
+        ));
+    }
+    let nearest = network
+        .signals
+        .iter()
+        .enumerate()
+        .filter(|(_, signal)| signal.road_id == pick.road_id)
+        .map(|(index, signal)| (index, signal.position.distance(pick.position)))
+        .filter(|&(_, distance)| distance <= SIGNAL_RANGE)
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    if let Some((index, distance)) = nearest {
+        let signal = &network.signals[index];
+        out.push((
+            Some(Element::Signal(index)),
+            locale.text(
+                if signal.kind == SignalKind::Object {
+                    "inspector-object"
+                } else {
+                    "inspector-signal"
+                },
+                &[
+                    ("id", &signal.id),
+                    ("type", &signal.type_code),
+                    ("distance", &format!("{distance:.0}")),
+                ],
+            ),
+        ));
+    }
+    out
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(30.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Inspector,
+    ));
+}
+
+// Rebuilds the panel when the selection, the findings or the units and
+// precision shown change.
+#[allow(clippy::too_many_arguments)]
+fn fill_panel(
+    mut commands: Commands,
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    report: Res<Report>,
+    locale: Res<Locale>,
+    speed_unit: Res<SpeedUnit>,
+    display: Res<DisplaySettings>,
+    mut panels: Query<(Entity, &mut Visibility), With<Inspector>>,
+) {
+    if !selection.is_changed()
+        && !report.is_changed()
+        && !speed_unit.is_changed()
+        && !display.is_changed()
+    {
+        return;
+    }
+    let elements = elements(
+        &network,
+        &report,
+        &selection,
+        &locale,
+        *speed_unit,
+        &display,
+    );
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+    for (panel, mut visibility) in &mut panels {
+        *visibility = if elements.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|panel| {
+            for (element, caption) in &elements {
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(8.0),
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(
+                            caption.clone(),
+                            style(Color::WHITE),
+                        ));
+                        let Some(element) = element.filter(|e| e.has_xml(&network)) else {
+                            return;
+                        };
+                        row.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    padding: UiRect::axes(Val::Px(6.0), Val::Px(1.0)),
+                                    ..default()
+                                },
+                                background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                                ..default()
+                            },
+                            CopyXml(element),
+                        ))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section(
+                                locale.text("inspector-copy-xml", &[]),
+                                style(Color::rgb(1.0, 0.9, 0.1)),
+                            ));
+                        });
+                    });
+            }
+        });
+    }
+}
+
+fn copy_on_click(
+    network: Res<RoadNetwork>,
+    buttons: Query<(&Interaction, &CopyXml), Changed<Interaction>>,
+) {
+    for (interaction, copy) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(xml) = copy.0.xml(&network) else {
+            continue;
+        };
+        match clipboard::copy(&xml) {
+            Ok(()) => info!("copied the XML of {:?} ({} bytes)", copy.0, xml.len()),
+            Err(message) => warn!("could not copy the XML: {message}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console;
+    use crate::sample_maps::load;
+    use crate::TrafficRule;
+
+    fn traffic(network: &RoadNetwork, lane: i32) -> String {
+        let pick = console::select(network, 1, Some(lane), None).unwrap();
+        elements(
+            network,
+            &Report::default(),
+            &Selection(Some(pick)),
+            &Locale::default(),
+            SpeedUnit::default(),
+            &DisplaySettings::default(),
+        )
+        .into_iter()
+        .map(|(_, caption)| caption)
+        .find(|caption| caption.contains("traffic"))
+        .unwrap()
+    }
+
+    #[test]
+    fn lanes_show_their_traffic_rule() {
+        let mut network = load("straight.xodr");
+        assert_eq!(
+            traffic(&network, -1),
+            "lane -1 in RHT traffic, driven along the reference line"
+        );
+        for segment in &mut network.segments {
+            segment.rule = TrafficRule::LeftHand;
+        }
+        assert_eq!(
+            traffic(&network, -1),
+            "lane -1 in LHT traffic, driven against the reference line"
+        );
+    }
+}
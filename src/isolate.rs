@@ -0,0 +1,120 @@
+// Isolating and hiding the selection.
+//
+// Untangling a dense junction is easier with the rest of the map out of the
+// way. Isolating the selection hides every road but the selected one, or
+// the whole junction it belongs to, and the roads directly linked to it,
+// together with the signals and objects of the hidden roads. Hiding the
+// selection hides the selected road as `hide road` does. `show` undoes both
+// (see `filter`, which applies them).
+//
+// Keys: H hides the selected road, Shift+H isolates it, Alt+H shows
+// everything again.
+
+use std::collections::BTreeSet;
+
+use bevy::prelude::*;
+
+use crate::console::Hidden;
+use crate::entity_index::OdrId;
+use crate::selection::Selection;
+use crate::RoadNetwork;
+
+// The roads left visible by isolation; none when not isolating.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Isolation(pub Option<BTreeSet<u32>>);
+
+impl Isolation {
+    pub fn shows(&self, road_id: u32) -> bool {
+        self.0.as_ref().is_none_or(|roads| roads.contains(&road_id))
+    }
+}
+
+pub struct IsolationPlugin;
+
+impl Plugin for IsolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Isolation>()
+            .add_systems(Update, isolate_on_key);
+    }
+}
+
+// A road, or all roads of its junction, and the roads linked to them.
+pub fn isolated_roads(network: &RoadNetwork, road_id: u32) -> BTreeSet<u32> {
+    let junction = network.roads.get(&road_id).and_then(|info| info.junction);
+    let mut roads: BTreeSet<u32> = match junction {
+        Some(junction) => network
+            .roads
+            .iter()
+            .filter(|(_, info)| info.junction == Some(junction))
+            .map(|(id, _)| *id)
+            .collect(),
+        None => BTreeSet::from([road_id]),
+    };
+    let neighbors: Vec<u32> = network
+        .links
+        .iter()
+        .filter_map(|link| {
+            if roads.contains(&link.road_id) {
+                Some(link.other_road_id)
+            } else if roads.contains(&link.other_road_id) {
+                Some(link.road_id)
+            } else {
+                None
+            }
+        })
+        .collect();
+    roads.extend(neighbors);
+    roads
+}
+
+// Isolates the selected road, for the console.
+pub fn isolate(world: &mut World) -> Result<String, String> {
+    let pick = world
+        .resource::<Selection>()
+        .0
+        .ok_or("nothing is selected")?;
+    let roads = isolated_roads(world.resource::<RoadNetwork>(), pick.road_id);
+    let count = roads.len();
+    world.resource_mut::<Isolation>().0 = Some(roads);
+    Ok(format!("isolated {count} road(s)"))
+}
+
+// Hides the selected road, for the console.
+pub fn hide_selected(world: &mut World) -> Result<String, String> {
+    let pick = world
+        .resource::<Selection>()
+        .0
+        .ok_or("nothing is selected")?;
+    world
+        .resource_mut::<Hidden>()
+        .0
+        .push(OdrId::Road(pick.road_id));
+    Ok(format!("hid road {}", pick.road_id))
+}
+
+fn isolate_on_key(
+    keys: Res<ButtonInput<KeyCode>>,
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    mut hidden: ResMut<Hidden>,
+    mut isolation: ResMut<Isolation>,
+) {
+    if !keys.just_pressed(KeyCode::KeyH) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    if alt {
+        hidden.0.clear();
+        isolation.0 = None;
+        return;
+    }
+    let Some(pick) = selection.0 else {
+        return;
+    };
+    if shift {
+        isolation.0 = Some(isolated_roads(&network, pick.road_id));
+    } else {
+        hidden.0.push(OdrId::Road(pick.road_id));
+    }
+}
@@ -0,0 +1,344 @@
+// Export of validation issues and annotations for review tools.
+//
+// The issues found by the checks and the annotations left on the map are
+// written as one report, in SARIF 2.1.0 (`.sarif`, `.json`) for code
+// scanning dashboards or as JUnit XML (`.xml`) for CI test reports. In
+// SARIF every finding is a result whose rule is its check, or `annotation`,
+// located by road, lane and station as a logical location and by map-frame
+// position in its properties. In JUnit every check is a test suite and every
+// finding a test case; warnings and errors fail, info annotations pass.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::annotations::{to_map, Anchor, Annotation};
+use crate::json::Json;
+use crate::validation::{Issue, Severity};
+use crate::xodr::escape;
+
+// Rule of the findings made by annotations.
+const ANNOTATION_RULE: &str = "annotation";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Sarif,
+    Junit,
+}
+
+impl Format {
+    // The format of a report file, by its extension.
+    pub fn of(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sarif" | "json") => Ok(Format::Sarif),
+            Some("xml") => Ok(Format::Junit),
+            _ => Err(format!(
+                "{}: a report is written as .sarif, .json (SARIF) or .xml (JUnit)",
+                path.display()
+            )),
+        }
+    }
+}
+
+// An issue or an annotation, as reported.
+struct Finding<'a> {
+    rule: &'a str,
+    severity: Severity,
+    road_id: Option<u32>,
+    lane_id: Option<i32>,
+    s: Option<f64>,
+    // Map-frame position.
+    x: f64,
+    y: f64,
+    z: f64,
+    message: &'a str,
+    screenshot: Option<&'a str>,
+}
+
+impl Finding<'_> {
+    // Whether the finding fails a JUnit test case.
+    fn fails(&self) -> bool {
+        self.severity != Severity::Info
+    }
+
+    // Where the finding is, for people: "road 3 lane -1 s=12.0".
+    fn place(&self) -> String {
+        let mut place = String::new();
+        if let Some(road) = self.road_id {
+            let _ = write!(place, "road {road}");
+        }
+        if let Some(lane) = self.lane_id {
+            let _ = write!(place, " lane {lane}");
+        }
+        match self.s {
+            Some(s) => {
+                let _ = write!(place, " s={s:.1}");
+            }
+            None => {
+                let _ = write!(place, "x={:.1} y={:.1}", self.x, self.y);
+            }
+        }
+        place
+    }
+}
+
+fn findings<'a>(issues: &'a [Issue], annotations: &'a [Annotation]) -> Vec<Finding<'a>> {
+    let issues = issues.iter().map(|issue| {
+        let p = to_map(issue.position);
+        Finding {
+            rule: issue.check,
+            severity: issue.severity,
+            road_id: Some(issue.road_id),
+            lane_id: issue.lane_id,
+            s: Some(issue.s),
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            message: &issue.message,
+            screenshot: None,
+        }
+    });
+    let annotations = annotations.iter().map(|note| {
+        let p = to_map(note.position);
+        let (road_id, s) = match note.anchor {
+            Anchor::Road { road_id, s, .. } => (Some(road_id), Some(s)),
+            Anchor::World(_) => (None, None),
+        };
+        Finding {
+            rule: ANNOTATION_RULE,
+            severity: note.severity,
+            road_id,
+            lane_id: None,
+            s,
+            x: p.x,
+            y: p.y,
+            z: p.z,
+            message: &note.text,
+            screenshot: note.screenshot.as_deref(),
+        }
+    });
+    issues.chain(annotations).collect()
+}
+
+// The rules of the findings, in order of first appearance.
+fn rules<'a>(findings: &[Finding<'a>]) -> Vec<&'a str> {
+    let mut rules = Vec::new();
+    for finding in findings {
+        if !rules.contains(&finding.rule) {
+            rules.push(finding.rule);
+        }
+    }
+    rules
+}
+
+// Writes the report of a map in the format its extension asks for.
+pub fn write_report(
+    path: &Path,
+    map: Option<&Path>,
+    issues: &[Issue],
+    annotations: &[Annotation],
+) -> Result<(), String> {
+    let map = map.map_or_else(|| "demo".to_string(), |map| map.display().to_string());
+    let findings = findings(issues, annotations);
+    let text = match Format::of(path)? {
+        Format::Sarif => to_sarif(&map, &findings).write(),
+        Format::Junit => to_junit(&map, &findings),
+    };
+    std::fs::write(path, text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn to_sarif(map: &str, findings: &[Finding]) -> Json {
+    let field = |key: &str, value: Json| (key.to_string(), value);
+    let rules = rules(findings)
+        .into_iter()
+        .map(|rule| Json::Object(vec![field("id", rule.into())]))
+        .collect();
+    let results = findings
+        .iter()
+        .map(|finding| {
+            let level = match finding.severity {
+                Severity::Info => "note",
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+            let mut location = vec![field(
+                "physicalLocation",
+                Json::Object(vec![field(
+                    "artifactLocation",
+                    Json::Object(vec![field("uri", map.into())]),
+                )]),
+            )];
+            if finding.road_id.is_some() {
+                location.push(field(
+                    "logicalLocations",
+                    Json::Array(vec![Json::Object(vec![
+                        field("name", finding.place().as_str().into()),
+                        field("kind", "element".into()),
+                    ])]),
+                ));
+            }
+            let mut properties = vec![
+                field("x", finding.x.into()),
+                field("y", finding.y.into()),
+                field("z", finding.z.into()),
+            ];
+            if let Some(road) = finding.road_id {
+                properties.push(field("road", road.into()));
+            }
+            if let Some(lane) = finding.lane_id {
+                properties.push(field("lane", f64::from(lane).into()));
+            }
+            if let Some(s) = finding.s {
+                properties.push(field("s", s.into()));
+            }
+            if let Some(screenshot) = finding.screenshot {
+                properties.push(field("screenshot", screenshot.into()));
+            }
+            Json::Object(vec![
+                field("ruleId", finding.rule.into()),
+                field("level", level.into()),
+                field(
+                    "message",
+                    Json::Object(vec![field("text", finding.message.into())]),
+                ),
+                field("locations", Json::Array(vec![Json::Object(location)])),
+                field("properties", Json::Object(properties)),
+            ])
+        })
+        .collect();
+    let driver = Json::Object(vec![
+        field("name", "road-visualizer".into()),
+        field("version", env!("CARGO_PKG_VERSION").into()),
+        field("rules", Json::Array(rules)),
+    ]);
+    Json::Object(vec![
+        field(
+            "$schema",
+            "https://json.schemastore.org/sarif-2.1.0.json".into(),
+        ),
+        field("version", "2.1.0".into()),
+        field(
+            "runs",
+            Json::Array(vec![Json::Object(vec![
+                field("tool", Json::Object(vec![field("driver", driver)])),
+                field("results", Json::Array(results)),
+            ])]),
+        ),
+    ])
+}
+
+fn to_junit(map: &str, findings: &[Finding]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">",
+        escape(map),
+        findings.len(),
+        findings.iter().filter(|f| f.fails()).count()
+    );
+    for rule in rules(findings) {
+        let cases: Vec<&Finding> = findings.iter().filter(|f| f.rule == rule).collect();
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+            escape(rule),
+            cases.len(),
+            cases.iter().filter(|f| f.fails()).count()
+        );
+        for finding in cases {
+            let _ = write!(
+                xml,
+                "    <testcase classname=\"{}.{}\" name=\"{}\"",
+                escape(map),
+                escape(rule),
+                escape(&finding.place())
+            );
+            if !finding.fails() {
+                let _ = writeln!(
+                    xml,
+                    ">\n      <system-out>{}</system-out>\n    </testcase>",
+                    escape(finding.message)
+                );
+                continue;
+            }
+            let _ = writeln!(
+                xml,
+                ">\n      <failure type=\"{}\" message=\"{}\"/>\n    </testcase>",
+                finding.severity.name(),
+                escape(finding.message)
+            );
+        }
+        xml.push_str("  </testsuite>\n");
+    }
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+
+    use super::*;
+    use crate::json;
+    use crate::lane_width::{self, LaneWidthSettings};
+    use crate::sample_maps::{load, path, temp_dir};
+
+    #[test]
+    fn issues_and_annotations_are_reported_by_format() {
+        let network = load("straight.xodr");
+        // The 3 m sidewalk, against a 3.2 m minimum.
+        let settings = LaneWidthSettings {
+            min: 3.2,
+            ..LaneWidthSettings::default()
+        };
+        let issues = lane_width::check(&network, &settings);
+        assert_eq!(issues.len(), 1);
+        let note = Annotation {
+            id: 1,
+            severity: Severity::Info,
+            text: "resurfaced in 2024".to_string(),
+            anchor: Anchor::Road {
+                road_id: 1,
+                s: 40.0,
+                t: -1.75,
+            },
+            position: DVec3::new(40.0, 0.0, 1.75),
+            screenshot: None,
+            created: 0,
+        };
+        let map = path("straight.xodr");
+        let dir = temp_dir("issue-export");
+
+        let sarif = dir.join("report.sarif");
+        write_report(&sarif, Some(&map), &issues, std::slice::from_ref(&note)).unwrap();
+        let report = json::parse(&std::fs::read_to_string(&sarif).unwrap()).unwrap();
+        let runs = report.get("runs").and_then(Json::as_array).unwrap();
+        let results = runs[0].get("results").and_then(Json::as_array).unwrap();
+        let field =
+            |result: &Json, key: &str| result.get(key).and_then(Json::as_str).map(String::from);
+        let property = |result: &Json, key: &str| {
+            result
+                .get("properties")
+                .and_then(|properties| properties.get(key))
+                .and_then(Json::as_f64)
+        };
+        assert_eq!(results.len(), 2);
+        assert_eq!(field(&results[0], "ruleId").as_deref(), Some("lane width"));
+        assert_eq!(field(&results[0], "level").as_deref(), Some("warning"));
+        assert_eq!(property(&results[0], "road"), Some(1.0));
+        assert_eq!(property(&results[0], "lane"), Some(-2.0));
+        assert_eq!(field(&results[1], "ruleId").as_deref(), Some("annotation"));
+        assert_eq!(field(&results[1], "level").as_deref(), Some("note"));
+        assert_eq!(property(&results[1], "s"), Some(40.0));
+
+        let junit = dir.join("report.xml");
+        write_report(&junit, Some(&map), &issues, &[note]).unwrap();
+        let xml = std::fs::read_to_string(&junit).unwrap();
+        assert!(xml.contains("tests=\"2\" failures=\"1\">"));
+        assert!(xml.contains("<testsuite name=\"lane width\" tests=\"1\" failures=\"1\">"));
+        assert!(xml.contains("<testsuite name=\"annotation\" tests=\"1\" failures=\"0\">"));
+        assert!(xml.contains("<system-out>resurfaced in 2024</system-out>"));
+
+        assert!(write_report(&dir.join("report.txt"), Some(&map), &issues, &[]).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+// A small JSON reader and writer for the viewer's own files.
+//
+// Numbers are read as f64, objects keep their key order, and the writer
+// indents with two spaces. That is all the sidecar files and reports need.
+// Arrays and objects nest at most `MAX_DEPTH` deep, so that a hostile file
+// cannot exhaust the stack.
+
+use std::fmt::Write as _;
+
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // Writes the value, indented.
+    pub fn write(&self) -> String {
+        let mut out = String::new();
+        write_value(&mut out, self, 0);
+        out.push('\n');
+        out
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self {
+        Json::Number(n)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Self {
+        Json::Number(f64::from(n))
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn write_value(out: &mut String, value: &Json, depth: usize) {
+    let indent = |out: &mut String, depth: usize| out.push_str(&"  ".repeat(depth));
+    match value {
+        Json::Null => out.push_str("null"),
+        Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        // Whole numbers are written without a fraction.
+        Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => {
+            let _ = write!(out, "{}", *n as i64);
+        }
+        Json::Number(n) if n.is_finite() => {
+            let _ = write!(out, "{n}");
+        }
+        Json::Number(_) => out.push_str("null"),
+        Json::String(s) => out.push_str(&escape(s)),
+        Json::Array(items) if items.is_empty() => out.push_str("[]"),
+        Json::Array(items) => {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                indent(out, depth + 1);
+                write_value(out, item, depth + 1);
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push(']');
+        }
+        Json::Object(fields) if fields.is_empty() => out.push_str("{}"),
+        Json::Object(fields) => {
+            out.push_str("{\n");
+            for (i, (key, item)) in fields.iter().enumerate() {
+                indent(out, depth + 1);
+                out.push_str(&escape(key));
+                out.push_str(": ");
+                write_value(out, item, depth + 1);
+                out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+            }
+            indent(out, depth);
+            out.push('}');
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        chars: text.char_indices().peekable(),
+        text,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_space();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(at, _)) => Err(parser.error(at, "trailing characters")),
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    // Arrays and objects open around the current value.
+    depth: usize,
+}
+
+// Whether a number is written as JSON has it: an optional minus, an integer
+// part without leading zeros, and optional fraction and exponent.
+fn is_number(text: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = text.strip_prefix('-').unwrap_or(text);
+    let whole = digits(rest);
+    if whole == 0 || (whole > 1 && rest.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &rest[whole..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let n = digits(fraction);
+        if n == 0 {
+            return false;
+        }
+        rest = &fraction[n..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let n = digits(exponent);
+        if n == 0 {
+            return false;
+        }
+        rest = &exponent[n..];
+    }
+    rest.is_empty()
+}
+
+impl Parser<'_> {
+    fn error(&self, at: usize, message: &str) -> String {
+        let line = self.text[..at].matches('\n').count() + 1;
+        format!("line {line}: {message}")
+    }
+
+    fn end(&self) -> String {
+        self.error(self.text.len(), "unexpected end")
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_space();
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((at, c)) => Err(self.error(at, &format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.end()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_space();
+        let Some(&(at, c)) = self.chars.peek() else {
+            return Err(self.end());
+        };
+        if (c == '{' || c == '[') && self.depth == MAX_DEPTH {
+            return Err(self.error(at, &format!("nested deeper than {MAX_DEPTH}")));
+        }
+        match c {
+            '{' => {
+                self.depth += 1;
+                let object = self.object();
+                self.depth -= 1;
+                object
+            }
+            '[' => {
+                self.depth += 1;
+                let array = self.array();
+                self.depth -= 1;
+                array
+            }
+            '"' => self.string().map(Json::String),
+            't' | 'f' | 'n' => {
+                let word: String =
+                    std::iter::from_fn(|| self.chars.next_if(|(_, c)| c.is_alphabetic()))
+                        .map(|(_, c)| c)
+                        .collect();
+                match word.as_str() {
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    "null" => Ok(Json::Null),
+                    _ => Err(self.error(at, &format!("unexpected `{word}`"))),
+                }
+            }
+            _ => {
+                let number: String = std::iter::from_fn(|| {
+                    self.chars
+                        .next_if(|(_, c)| c.is_ascii_digit() || "+-.eE".contains(*c))
+                })
+                .map(|(_, c)| c)
+                .collect();
+                if number.is_empty() {
+                    return Err(self.error(at, &format!("unexpected `{c}`")));
+                }
+                if !is_number(&number) {
+                    return Err(self.error(at, &format!("`{number}` is not a number")));
+                }
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| self.error(at, &format!("`{number}` is not a number")))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_space();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_space();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Json::Object(fields)),
+                Some((at, c)) => return Err(self.error(at, &format!("unexpected `{c}`"))),
+                None => return Err(self.end()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Json::Array(items)),
+                Some((at, c)) => return Err(self.error(at, &format!("unexpected `{c}`"))),
+                None => return Err(self.end()),
+            }
+        }
+    }
+
+    // Where the next character is.
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.text.len(), |&(at, _)| at)
+    }
+
+    // The four hex digits of a `\u` escape starting at `at`.
+    fn hex(&mut self, at: usize) -> Result<u32, String> {
+        let hex: String = (0..4)
+            .filter_map(|_| self.chars.next().map(|(_, c)| c))
+            .collect();
+        if !(hex.len() == 4 && hex.bytes().all(|b| b.is_ascii_hexdigit())) {
+            return Err(self.error(at, "invalid escape"));
+        }
+        u32::from_str_radix(&hex, 16).map_err(|_| self.error(at, "invalid escape"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let Some((at, c)) = self.chars.next() else {
+                return Err(self.end());
+            };
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some((_, escaped)) = self.chars.next() else {
+                        return Err(self.end());
+                    };
+                    match escaped {
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => {
+                            let code = self.hex(at)?;
+                            // Characters outside the basic plane come as a
+                            // pair of surrogates; a lone one is replaced.
+                            let code = if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.offset()..].starts_with("\\u")
+                            {
+                                self.chars.nth(1);
+                                let low = self.hex(at)?;
+                                if (0xdc00..0xe000).contains(&low) {
+                                    0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+                                } else {
+                                    out.push('\u{fffd}');
+                                    low
+                                }
+                            } else {
+                                code
+                            };
+                            out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        '"' | '\\' | '/' => out.push(escaped),
+                        other => return Err(self.error(at, &format!("invalid escape `\\{other}`"))),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_are_read_back_as_written() {
+        let text = "quote \" backslash \\ tab \t newline \n bell \u{7} é";
+        assert_eq!(parse(&escape(text)), Ok(Json::from(text)));
+        assert_eq!(
+            parse(r#""a\/b\b\f\ré""#),
+            Ok(Json::from("a/b\u{8}\u{c}\ré"))
+        );
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse(r#""\u12""#).is_err());
+        assert!(parse(r#""\u+123""#).is_err());
+    }
+
+    #[test]
+    fn surrogate_pairs_make_one_character() {
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok(Json::from("😀")));
+        assert_eq!(parse(r#""\ud83dx""#), Ok(Json::from("\u{fffd}x")));
+        assert_eq!(parse(r#""\ude00""#), Ok(Json::from("\u{fffd}")));
+        assert_eq!(parse(r#""\ud83dA""#), Ok(Json::from("\u{fffd}A")));
+    }
+
+    #[test]
+    fn numbers_follow_the_json_grammar() {
+        for (text, value) in [
+            ("0", 0.0),
+            ("-1.5e3", -1500.0),
+            ("2E-2", 0.02),
+            ("10", 10.0),
+        ] {
+            assert_eq!(parse(text), Ok(Json::Number(value)), "{text}");
+        }
+        for text in ["-", "1.2.3", "01", ".5", "1.", "1e", "+1", "1e+"] {
+            assert!(parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(
+            parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err(),
+            "line 3: expected `:`, found `2`"
+        );
+        assert_eq!(parse("[1, 2").unwrap_err(), "line 1: unexpected end");
+        assert!(parse("[1] x").is_err());
+        assert!(parse("nope").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(parse(&nested(MAX_DEPTH + 1))
+            .unwrap_err()
+            .contains("nested deeper"));
+        assert!(parse(&"[".repeat(100_000)).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+// Junction overlay, built as a viewer extension (see `extensions`).
+//
+// Outlines the connecting roads of every junction, each junction in its own
+// color, and reports the junction of a newly selected road with the number
+// of roads in it, which it finds through the `OdrRoad` components.
+//
+// With the outlines it draws the junctions' conflict points (crossings in
+// the error color, merges in the warning color) and the stop lines of the
+// lanes entering them, from `conflicts`. They are worked out again only
+// when the network changes.
+//
+// Keys: U shows or hides the junction outlines.
+
+use bevy::prelude::*;
+
+use crate::conflicts::{self, ConflictKind, ConflictPoint, StopLine};
+use crate::extensions::{overlay_shown, AddOverlay, RsodrPlugin, SelectionChanged};
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::odr::{OdrLanes, OdrRoad};
+use crate::origin::RenderOrigin;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+const NAME: &str = "junctions";
+
+// Lifts the outlines off the road surface, in meters.
+const OUTLINE_LIFT: f32 = 0.1;
+
+// The radius of a conflict point's marker, in meters.
+const CONFLICT_RADIUS: f32 = 0.5;
+
+// The conflict points and stop lines of every junction in the network.
+#[derive(Resource, Default)]
+struct Conflicts {
+    points: Vec<ConflictPoint>,
+    stops: Vec<StopLine>,
+}
+
+pub struct JunctionOverlay;
+
+impl RsodrPlugin for JunctionOverlay {
+    fn name(&self) -> &'static str {
+        "junction overlay"
+    }
+
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Conflicts>()
+            .add_overlay(NAME, KeyCode::KeyU)
+            .add_systems(
+                Update,
+                (
+                    report_junction,
+                    find_conflicts.run_if(resource_changed::<RoadNetwork>),
+                    (draw_junctions, draw_conflicts)
+                        .after(camera_orbit)
+                        .run_if(overlay_shown(NAME)),
+                    add_legend.in_set(FillLegend).run_if(overlay_shown(NAME)),
+                ),
+            );
+    }
+}
+
+// A color per junction, spread around the hue circle.
+fn junction_color(junction: u32) -> Color {
+    Color::hsl((junction as f32 * 137.5) % 360.0, 0.8, 0.6)
+}
+
+fn draw_junctions(network: Res<RoadNetwork>, origin: Res<RenderOrigin>, mut gizmos: Gizmos) {
+    let lift = Vec3::Y * OUTLINE_LIFT;
+    for segment in &network.segments {
+        let Some(junction) = network
+            .roads
+            .get(&segment.road_id)
+            .and_then(|info| info.junction)
+        else {
+            continue;
+        };
+        for side in [&segment.left_side, &segment.right_side] {
+            gizmos.linestrip(
+                side.iter().map(|p| origin.to_render(*p) + lift),
+                junction_color(junction),
+            );
+        }
+    }
+}
+
+fn find_conflicts(network: Res<RoadNetwork>, mut conflicts: ResMut<Conflicts>) {
+    let junctions = conflicts::junctions(&network);
+    conflicts.points = junctions
+        .iter()
+        .flat_map(|&junction| conflicts::conflict_points(&network, junction))
+        .collect();
+    conflicts.stops = junctions
+        .iter()
+        .flat_map(|&junction| conflicts::stop_lines(&network, junction))
+        .collect();
+}
+
+fn draw_conflicts(
+    conflicts: Res<Conflicts>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let lift = Vec3::Y * OUTLINE_LIFT;
+    for point in &conflicts.points {
+        let color = match point.kind {
+            ConflictKind::Crossing => theme.error,
+            ConflictKind::Merging => theme.warning,
+        };
+        gizmos.sphere(
+            origin.to_render(point.position) + lift,
+            Quat::IDENTITY,
+            CONFLICT_RADIUS,
+            color,
+        );
+    }
+    for stop in &conflicts.stops {
+        gizmos.line(
+            origin.to_render(stop.left) + lift,
+            origin.to_render(stop.right) + lift,
+            junction_color(stop.junction),
+        );
+    }
+}
+
+fn add_legend(
+    conflicts: Res<Conflicts>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut legend: ResMut<Legend>,
+) {
+    let rows = [
+        (ConflictKind::Crossing, theme.error, "legend-crossing"),
+        (ConflictKind::Merging, theme.warning, "legend-merging"),
+    ]
+    .into_iter()
+    .filter(|(kind, ..)| conflicts.points.iter().any(|point| point.kind == *kind))
+    .map(|(_, color, message)| (color, locale.text(message, &[])))
+    .collect();
+    legend.add(locale.text("legend-conflicts", &[]), rows);
+}
+
+fn report_junction(
+    roads: Query<&OdrRoad, Without<OdrLanes>>,
+    mut changed: EventReader<SelectionChanged>,
+) {
+    for event in changed.read() {
+        let (Some(pick), previous) = (event.current, event.previous) else {
+            continue;
+        };
+        if previous.is_some_and(|previous| previous.road_id == pick.road_id) {
+            continue;
+        }
+        let Some(junction) = roads
+            .iter()
+            .find(|road| road.id == pick.road_id)
+            .and_then(|road| road.junction)
+        else {
+            continue;
+        };
+        let roads = roads
+            .iter()
+            .filter(|road| road.junction == Some(junction))
+            .count();
+        info!(
+            "road {} is in junction {junction}, with {roads} connecting road(s)",
+            pick.road_id
+        );
+    }
+}
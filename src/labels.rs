@@ -0,0 +1,398 @@
+// Text labels for roads, lanes and stations.
+//
+// Labels are drawn as screen-space text pinned to points on the map, so they
+// always face the camera and stay legible at any angle. Road names, or IDs
+// for roads without one, sit at the middle of each road, lane IDs at the
+// start of each lane section, and s-station ticks along the reference line
+// every `STATION_SPACING` meters.
+// To keep the screen readable, each kind only shows up once the camera is
+// close enough, and only the `MAX_LABELS` labels nearest the camera are drawn.
+//
+// Looking down on the map, road names and stations are written along the
+// reference line instead, as on a printed map (see `curved_text`), wherever
+// the line is long and straight enough on screen to carry them.
+//
+// Keys: L toggles the labels.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::curved_text::{self, PlacedGlyph};
+use crate::edit::NetworkChanged;
+use crate::origin::RenderOrigin;
+use crate::tessellation::point_at;
+use crate::theme::Theme;
+use crate::{camera_orbit, MainCamera, RoadNetwork, RoadSegment};
+
+// Distance between s-station ticks, in meters.
+const STATION_SPACING: f64 = 50.0;
+
+// Most labels drawn at once.
+const MAX_LABELS: usize = 150;
+
+// Labels are bucketed into square cells of this size for lookup.
+const CELL_SIZE: f64 = 250.0;
+
+// Most glyphs of curved labels drawn at once.
+const MAX_GLYPHS: usize = 600;
+
+// How far the camera may tilt from looking straight down for labels to
+// follow the roads, in radians.
+const TOP_DOWN_TILT: f32 = 0.5;
+
+// How far along the reference line, either way, road names and stations
+// may run when they follow it, and the spacing of the points taken along it,
+// in meters.
+const ROAD_NAME_REACH: f64 = 150.0;
+const STATION_REACH: f64 = 25.0;
+const PATH_STEP: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LabelKind {
+    Road,
+    Lane,
+    Station,
+}
+
+impl LabelKind {
+    // Labels of this kind are hidden when the camera is farther away than
+    // this, in meters.
+    fn range(self) -> f64 {
+        match self {
+            LabelKind::Road => 2_000.0,
+            LabelKind::Lane => 300.0,
+            LabelKind::Station => 200.0,
+        }
+    }
+
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            LabelKind::Road => theme.road_label,
+            LabelKind::Lane => theme.lane_label,
+            LabelKind::Station => theme.station_label,
+        }
+    }
+
+    fn font_size(self) -> f32 {
+        match self {
+            LabelKind::Road => 18.0,
+            LabelKind::Lane | LabelKind::Station => 13.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Label {
+    kind: LabelKind,
+    position: DVec3,
+    text: String,
+    // The stretch of reference line the label follows, if it is written
+    // along the road.
+    path: Vec<DVec3>,
+}
+
+// All labels of the network, bucketed by ground-plane cell.
+#[derive(Resource, Debug, Default)]
+struct LabelIndex {
+    labels: Vec<Label>,
+    cells: HashMap<IVec2, Vec<usize>>,
+}
+
+// Whether labels are shown.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShowLabels(pub bool);
+
+impl Default for ShowLabels {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+// A reusable on-screen text used to draw one label.
+#[derive(Component)]
+struct LabelSlot;
+
+// A reusable on-screen text used to draw one glyph of a curved label.
+#[derive(Component)]
+struct GlyphSlot;
+
+pub struct LabelPlugin;
+
+impl Plugin for LabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShowLabels>()
+            .add_systems(Startup, (build_labels, spawn_slots))
+            .add_systems(Update, build_labels.run_if(on_event::<NetworkChanged>()))
+            .add_systems(Update, (toggle_labels, place_labels.after(camera_orbit)));
+    }
+}
+
+fn cell_of(p: DVec3) -> IVec2 {
+    IVec2::new(
+        (p.x / CELL_SIZE).floor() as i32,
+        (p.z / CELL_SIZE).floor() as i32,
+    )
+}
+
+fn build_labels(mut commands: Commands, network: Res<RoadNetwork>) {
+    let mut labels = Vec::new();
+
+    // Group lanes by road and section to find road middles and reference
+    // lines.
+    let mut roads: BTreeMap<u32, BTreeMap<u32, Vec<&RoadSegment>>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads
+            .entry(segment.road_id)
+            .or_default()
+            .entry(segment.lane_section_id)
+            .or_default()
+            .push(segment);
+    }
+
+    for (road_id, sections) in &roads {
+        let mut reference: Vec<(f64, Vec<DVec3>)> = Vec::new();
+        for lanes in sections.values() {
+            for lane in lanes {
+                let middle = lane.centerline();
+                if let Some(start) = middle.first() {
+                    labels.push(Label {
+                        kind: LabelKind::Lane,
+                        position: *start,
+                        text: format!("{}:{}", lane.lane_section_id, lane.lane_id),
+                        path: Vec::new(),
+                    });
+                }
+            }
+            // The reference line runs along the inner edge of the innermost
+            // lane.
+            if let Some(innermost) = lanes.iter().min_by_key(|lane| lane.lane_id.abs()) {
+                let edge = if innermost.lane_id < 0 {
+                    &innermost.left_side
+                } else {
+                    &innermost.right_side
+                };
+                reference.push((innermost.start_s, edge.clone()));
+            }
+        }
+
+        let start = reference.first().map_or(0.0, |(s, _)| *s);
+        let end = sections
+            .values()
+            .flatten()
+            .map(|lane| lane.end_s)
+            .fold(start, f64::max);
+        let at = |s: f64| {
+            reference
+                .iter()
+                .rev()
+                .find(|(section_s, _)| *section_s <= s)
+                .or(reference.first())
+                .and_then(|(section_s, edge)| point_at(edge, s - section_s))
+                .map(|(p, _)| p)
+        };
+        let path = |s: f64, reach: f64| {
+            let (from, to) = ((s - reach).max(start), (s + reach).min(end));
+            let steps = ((to - from) / PATH_STEP).ceil().max(1.0) as usize;
+            (0..=steps)
+                .filter_map(|k| at(from + (to - from) * k as f64 / steps as f64))
+                .collect::<Vec<_>>()
+        };
+
+        if let Some(middle) = at((start + end) / 2.0) {
+            labels.push(Label {
+                kind: LabelKind::Road,
+                position: middle,
+                text: road_name(&network, *road_id),
+                path: path((start + end) / 2.0, ROAD_NAME_REACH),
+            });
+        }
+        let mut s = (start / STATION_SPACING).ceil() * STATION_SPACING;
+        while s <= end {
+            if let Some(position) = at(s) {
+                labels.push(Label {
+                    kind: LabelKind::Station,
+                    position,
+                    text: format!("s={s:.0}"),
+                    path: path(s, STATION_REACH),
+                });
+            }
+            s += STATION_SPACING;
+        }
+    }
+
+    let mut index = LabelIndex::default();
+    for (i, label) in labels.iter().enumerate() {
+        index
+            .cells
+            .entry(cell_of(label.position))
+            .or_default()
+            .push(i);
+    }
+    index.labels = labels;
+    commands.insert_resource(index);
+}
+
+// A road's label: its name, or its ID if it has none.
+pub fn road_name(network: &RoadNetwork, road_id: u32) -> String {
+    match network.roads.get(&road_id) {
+        Some(info) if !info.name.trim().is_empty() => info.name.trim().to_string(),
+        _ => format!("road {road_id}"),
+    }
+}
+
+fn spawn_slots(mut commands: Commands) {
+    for _ in 0..MAX_LABELS {
+        commands.spawn((
+            TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            LabelSlot,
+        ));
+    }
+    for _ in 0..MAX_GLYPHS {
+        commands.spawn((
+            TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            GlyphSlot,
+        ));
+    }
+}
+
+fn toggle_labels(keys: Res<ButtonInput<KeyCode>>, mut show: ResMut<ShowLabels>) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        show.0 = !show.0;
+    }
+}
+
+// Picks the labels nearest the camera and moves the text slots onto them;
+// looking down, those that can follow their road take glyph slots instead.
+#[allow(clippy::type_complexity)]
+fn place_labels(
+    show: Res<ShowLabels>,
+    index: Option<Res<LabelIndex>>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut slots: Query<
+        (&mut Text, &mut Style, &mut Visibility),
+        (With<LabelSlot>, Without<GlyphSlot>),
+    >,
+    mut glyph_slots: Query<
+        (&mut Text, &mut Style, &mut Transform, &mut Visibility),
+        (With<GlyphSlot>, Without<LabelSlot>),
+    >,
+) {
+    let mut shown: Vec<(f64, &Label, Vec2)> = Vec::new();
+    let mut curved: Vec<(&Label, PlacedGlyph)> = Vec::new();
+    if let (true, Some(index), Ok((camera, camera_transform))) =
+        (show.0, index.as_deref(), cameras.get_single())
+    {
+        let eye = origin.0 + camera_transform.translation().as_dvec3();
+        let reach = (LabelKind::Road.range() / CELL_SIZE).ceil() as i32;
+        let center = cell_of(eye);
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                let Some(cell) = index.cells.get(&(center + IVec2::new(x, y))) else {
+                    continue;
+                };
+                for label in cell.iter().map(|&i| &index.labels[i]) {
+                    let distance = eye.distance(label.position);
+                    if distance > label.kind.range() {
+                        continue;
+                    }
+                    let render = origin.to_render(label.position);
+                    if let Some(screen) = camera.world_to_viewport(camera_transform, render) {
+                        // Road names win over lane and station labels.
+                        let rank = match label.kind {
+                            LabelKind::Road => 0.0,
+                            _ => distance,
+                        };
+                        shown.push((rank, label, screen));
+                    }
+                }
+            }
+        }
+        shown.sort_by(|a, b| a.0.total_cmp(&b.0));
+        shown.truncate(MAX_LABELS);
+
+        if camera_transform.forward().y < -TOP_DOWN_TILT.cos() {
+            shown.retain(|(_, label, _)| {
+                let screen: Option<Vec<Vec2>> = label
+                    .path
+                    .iter()
+                    .map(|p| camera.world_to_viewport(camera_transform, origin.to_render(*p)))
+                    .collect();
+                let glyphs = screen.and_then(|screen| {
+                    curved_text::lay_out(&label.text, label.kind.font_size(), &screen)
+                });
+                match glyphs {
+                    Some(glyphs) if curved.len() + glyphs.len() <= MAX_GLYPHS => {
+                        curved.extend(glyphs.into_iter().map(|glyph| (*label, glyph)));
+                        false
+                    }
+                    _ => true,
+                }
+            });
+        }
+    }
+
+    let mut shown = shown.into_iter();
+    for (mut text, mut style, mut visibility) in &mut slots {
+        let Some((_, label, screen)) = shown.next() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Visible;
+        style.left = Val::Px(screen.x);
+        style.top = Val::Px(screen.y);
+        set_text(&mut text, &label.text, label.kind, &theme);
+    }
+
+    let mut curved = curved.into_iter();
+    for (mut text, mut style, mut transform, mut visibility) in &mut glyph_slots {
+        let Some((label, glyph)) = curved.next() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        // The slot is centered on the glyph, and turns about its center.
+        let size = label.kind.font_size();
+        let width = curved_text::advances(&glyph.character.to_string(), size)[0];
+        *visibility = Visibility::Visible;
+        style.left = Val::Px(glyph.center.x - width / 2.0);
+        style.top = Val::Px(glyph.center.y - size / 2.0);
+        transform.rotation = Quat::from_rotation_z(glyph.angle);
+        set_text(&mut text, &glyph.character.to_string(), label.kind, &theme);
+    }
+}
+
+// Only touches the text when it changes, to avoid re-laying it out.
+fn set_text(text: &mut Text, value: &str, kind: LabelKind, theme: &Res<Theme>) {
+    let section = text.sections.first();
+    if theme.is_changed()
+        || section.map(|section| section.value.as_str()) != Some(value)
+        || section.map(|section| (section.style.font_size, section.style.color))
+            != Some((kind.font_size(), kind.color(theme)))
+    {
+        *text = Text::from_section(
+            value,
+            TextStyle {
+                font_size: kind.font_size(),
+                color: kind.color(theme),
+                ..default()
+            },
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Lane change zones.
+//
+// Marks where the lane markings let traffic change lanes, from the lane
+// change relation of `routing::lane_changes`: the boundary between two
+// driving lanes is drawn in the pass color where the change is allowed both
+// ways, and in the warning color with arrows across it where only one way
+// is. Boundaries that may not be crossed are left out.
+//
+// Only the first `<roadMark>` of a lane is read, so a marking that changes
+// within a lane section counts as the first one throughout.
+//
+// Keys: K shows or hides the lane change zones.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::ribbons::Ribbons;
+use crate::routing::lane_changes;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+// Lifts the zones off the road surface, in meters.
+const ZONE_LIFT: f32 = 0.12;
+
+// Distance between the arrows of a one-way zone, and their length, in
+// meters.
+const ARROW_SPACING: f64 = 5.0;
+const ARROW_LENGTH: f64 = 1.0;
+
+// A boundary that may be crossed: its points, and the direction across it
+// a one-way change goes in.
+#[derive(Debug, Clone, PartialEq)]
+struct Zone {
+    boundary: Vec<DVec3>,
+    one_way: Option<DVec3>,
+}
+
+#[derive(Resource, Debug, Default)]
+struct Zones(Vec<Zone>);
+
+// Whether the lane change zones are shown.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ShowZones(bool);
+
+pub struct LaneChangePlugin;
+
+impl Plugin for LaneChangePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Zones>()
+            .init_resource::<ShowZones>()
+            .add_systems(
+                Update,
+                (
+                    toggle_zones,
+                    find_zones.run_if(resource_changed::<RoadNetwork>),
+                    draw_zones.after(camera_orbit),
+                    add_legend
+                        .in_set(FillLegend)
+                        .run_if(|show: Res<ShowZones>| show.0),
+                ),
+            );
+    }
+}
+
+fn toggle_zones(keys: Res<ButtonInput<KeyCode>>, mut show: ResMut<ShowZones>) {
+    if keys.just_pressed(KeyCode::KeyK) {
+        show.0 = !show.0;
+    }
+}
+
+fn add_legend(theme: Res<Theme>, locale: Res<Locale>, mut legend: ResMut<Legend>) {
+    legend.add(
+        locale.text("legend-lane-change", &[]),
+        vec![
+            (theme.pass, locale.text("legend-lane-change-both", &[])),
+            (theme.warning, locale.text("legend-lane-change-one", &[])),
+        ],
+    );
+}
+
+fn find_zones(network: Res<RoadNetwork>, mut zones: ResMut<Zones>) {
+    let changes = lane_changes(&network);
+    zones.0.clear();
+    for (from, targets) in changes.iter().enumerate() {
+        for &to in targets {
+            let both_ways = changes[to].contains(&from);
+            // Each two-way boundary once.
+            if both_ways && to < from {
+                continue;
+            }
+            let (lane, other) = (&network.segments[from], &network.segments[to]);
+            // The shared boundary is the inner lane's outer side.
+            let inner = if lane.lane_id.abs() < other.lane_id.abs() {
+                lane
+            } else {
+                other
+            };
+            let boundary = if inner.lane_id > 0 {
+                inner.left_side.clone()
+            } else {
+                inner.right_side.clone()
+            };
+            let middle =
+                |points: Vec<DVec3>| points.iter().sum::<DVec3>() / points.len().max(1) as f64;
+            let one_way = (!both_ways).then(|| {
+                let across = middle(other.centerline()) - middle(lane.centerline());
+                (across * DVec3::new(1.0, 0.0, 1.0)).normalize_or_zero()
+            });
+            zones.0.push(Zone { boundary, one_way });
+        }
+    }
+}
+
+fn draw_zones(
+    show: Res<ShowZones>,
+    zones: Res<Zones>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    if !show.0 {
+        return;
+    }
+    let lift = |p: DVec3| origin.to_render(p) + Vec3::Y * ZONE_LIFT;
+    for zone in &zones.0 {
+        let color = match zone.one_way {
+            Some(_) => theme.warning,
+            None => theme.pass,
+        };
+        ribbons.linestrip(zone.boundary.iter().map(|p| lift(*p)), color);
+        let Some(across) = zone.one_way else {
+            continue;
+        };
+        let mut next = 0.0;
+        let mut along = 0.0;
+        for (k, p) in zone.boundary.iter().enumerate() {
+            if k > 0 {
+                along += p.distance(zone.boundary[k - 1]);
+            }
+            if along < next {
+                continue;
+            }
+            next = along + ARROW_SPACING;
+            ribbons.arrow(
+                lift(*p - across * ARROW_LENGTH / 2.0),
+                lift(*p + across * ARROW_LENGTH / 2.0),
+                color,
+            );
+        }
+    }
+}
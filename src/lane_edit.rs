@@ -0,0 +1,591 @@
+// Editing the lane sections and lanes of a road.
+//
+// `lanes` in the console changes the selected road:
+//
+// - `lanes split <s>` splits the lane section at station `s` in two;
+// - `lanes add left|right <s> <taper> [width]` adds a lane outside the
+//   outermost one on that side, from `s` to the end of the road, widening
+//   from nothing to `width` meters (3.5 if not given) over the first `taper`
+//   meters;
+// - `lanes remove <lane> <s> <taper>` narrows an outermost lane to nothing
+//   over `taper` meters from `s` and drops it after that;
+// - `lanes merge` joins neighbouring lane sections that have the same lanes
+//   with the same attributes and meet without a step.
+//
+// The two parts of a split lane are linked by its ID, a merged lane keeps
+// the links of its outer ends, added lanes start without a predecessor and
+// removed lanes end without a successor.
+// Sections are split where needed so that tapers start and end on a section
+// boundary, as OpenDRIVE lane widths would. Tapers follow a cubic, which
+// starts and ends without a kink in the lane edge. Boundaries are sampled
+// again about every meter wherever a section changes.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::{boundary_at, road_range};
+use crate::lane_records;
+use crate::selection::Selection;
+use crate::transaction::{self, Transaction};
+use crate::{RoadMark, RoadNetwork, RoadSegment};
+
+// Boundaries are sampled no further apart than this, in meters.
+const SAMPLE_STEP: f64 = 1.0;
+
+// Stations closer than this are the same, in meters.
+const EPSILON: f64 = 1e-6;
+
+// Boundary points of neighbouring sections further apart than this are a
+// step, in meters.
+const JOIN_TOLERANCE: f64 = 0.01;
+
+// Width of added lanes if none is given, in meters.
+const DEFAULT_WIDTH: f64 = 3.5;
+
+// The share of a taper done at `u`, from 0 at its start to 1 at its end.
+fn ramp(u: f64) -> f64 {
+    let u = u.clamp(0.0, 1.0);
+    u * u * (3.0 - 2.0 * u)
+}
+
+// The station of each boundary point of a segment.
+fn station(segment: &RoadSegment, count: usize, i: usize) -> f64 {
+    let last = count.saturating_sub(1).max(1) as f64;
+    segment.start_s + (segment.end_s - segment.start_s) * i as f64 / last
+}
+
+// Evenly spaced samples between two stations.
+fn sample_stations(from: f64, to: f64) -> Vec<f64> {
+    let count = (((to - from) / SAMPLE_STEP).ceil() as usize).max(1);
+    (0..=count)
+        .map(|i| from + (to - from) * i as f64 / count as f64)
+        .collect()
+}
+
+// Brings the lane's end points and mean width up to date with its
+// boundaries.
+fn refresh(segment: &mut RoadSegment) {
+    let middle = |i: usize| (segment.left_side[i] + segment.right_side[i]) / 2.0;
+    let last = segment.left_side.len().min(segment.right_side.len());
+    if last == 0 {
+        return;
+    }
+    segment.start_pos = middle(0);
+    segment.end_pos = middle(last - 1);
+    segment.width = (0..last)
+        .map(|i| segment.left_side[i].distance(segment.right_side[i]))
+        .sum::<f64>()
+        / last as f64;
+}
+
+// The part of a lane between two stations within it.
+fn part(segment: &RoadSegment, from: f64, to: f64) -> RoadSegment {
+    let stations = sample_stations(from, to);
+    let start = segment.start_s;
+    let side = |points: &[DVec3]| -> Vec<DVec3> {
+        stations
+            .iter()
+            .filter_map(|s| boundary_at(segment, points, *s))
+            .collect()
+    };
+    let mut part = RoadSegment {
+        start_s: from,
+        end_s: to,
+        left_side: side(&segment.left_side),
+        right_side: side(&segment.right_side),
+        road_marks: lane_records::within(&segment.road_marks, from - start, to - start),
+        center_marks: lane_records::within(&segment.center_marks, from - start, to - start),
+        materials: lane_records::within(&segment.materials, from - start, to - start),
+        ..segment.clone()
+    };
+    refresh(&mut part);
+    part
+}
+
+// Points along a boundary with their stations.
+fn stationed(segment: &RoadSegment, points: &[DVec3]) -> Vec<(f64, DVec3)> {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (station(segment, points.len(), i), *p))
+        .collect()
+}
+
+// Interpolates stationed points.
+fn interpolate(points: &[(f64, DVec3)], s: f64) -> DVec3 {
+    let i = points
+        .partition_point(|(station, _)| *station < s)
+        .clamp(1, points.len() - 1);
+    let ((s0, p0), (s1, p1)) = (points[i - 1], points[i]);
+    if s1 - s0 <= EPSILON {
+        return p1;
+    }
+    p0.lerp(p1, ((s - s0) / (s1 - s0)).clamp(0.0, 1.0))
+}
+
+// A lane running through two neighbouring sections as one.
+fn join(first: &RoadSegment, second: &RoadSegment) -> RoadSegment {
+    let stations = sample_stations(first.start_s, second.end_s);
+    let side = |a: &[DVec3], b: &[DVec3]| -> Vec<DVec3> {
+        let mut points = stationed(first, a);
+        points.extend(stationed(second, b).into_iter().skip(1));
+        stations.iter().map(|s| interpolate(&points, *s)).collect()
+    };
+    let at = second.start_s - first.start_s;
+    let mut joined = RoadSegment {
+        end_s: second.end_s,
+        successor: second.successor,
+        left_side: side(&first.left_side, &second.left_side),
+        right_side: side(&first.right_side, &second.right_side),
+        road_marks: lane_records::chain(&first.road_marks, &second.road_marks, at),
+        center_marks: lane_records::chain(&first.center_marks, &second.center_marks, at),
+        materials: lane_records::chain(&first.materials, &second.materials, at),
+        ..first.clone()
+    };
+    refresh(&mut joined);
+    joined
+}
+
+// Splits the lane section of a road at station `s`, unless a section
+// already starts there, and returns the ID of the section starting at `s`.
+pub fn split(network: &mut RoadNetwork, road_id: u32, s: f64) -> Result<u32, String> {
+    let (first, last) = road_range(network, road_id).ok_or(format!("no road {road_id}"))?;
+    if !(first - EPSILON..=last + EPSILON).contains(&s) {
+        return Err(format!(
+            "road {road_id} runs from {first:.2} to {last:.2}, not through {s:.2}"
+        ));
+    }
+    let road = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id);
+    if let Some(starting) = road
+        .clone()
+        .find(|segment| (segment.start_s - s).abs() <= EPSILON)
+    {
+        return Ok(starting.lane_section_id);
+    }
+    let section = road
+        .filter(|segment| segment.start_s < s && s < segment.end_s)
+        .map(|segment| segment.lane_section_id)
+        .min()
+        .ok_or(format!("road {road_id} has no lane section at {s:.2}"))?;
+
+    let mut added = Vec::new();
+    for segment in network.segments.iter_mut() {
+        if segment.road_id != road_id || segment.lane_section_id < section {
+            continue;
+        }
+        if segment.lane_section_id > section {
+            segment.lane_section_id += 1;
+            continue;
+        }
+        let mut after = part(segment, s, segment.end_s);
+        after.lane_section_id = section + 1;
+        after.predecessor = Some(after.lane_id);
+        *segment = part(segment, segment.start_s, s);
+        segment.successor = Some(segment.lane_id);
+        added.push(after);
+    }
+    network.segments.extend(added);
+    Ok(section + 1)
+}
+
+// Whether two lanes of neighbouring sections could be one.
+fn same_lane(a: &RoadSegment, b: &RoadSegment) -> bool {
+    let meet = |a: &[DVec3], b: &[DVec3]| match (a.last(), b.first()) {
+        (Some(a), Some(b)) => a.distance(*b) <= JOIN_TOLERANCE,
+        _ => false,
+    };
+    a.lane_id == b.lane_id
+        && a.lane_type == b.lane_type
+        && a.road_type == b.road_type
+        && a.rule == b.rule
+        && a.speed == b.speed
+        && lane_records::continues(&a.materials, &b.materials)
+        && a.access == b.access
+        && lane_records::continues(&a.road_marks, &b.road_marks)
+        && lane_records::continues(&a.center_marks, &b.center_marks)
+        && meet(&a.left_side, &b.left_side)
+        && meet(&a.right_side, &b.right_side)
+}
+
+// Joins the neighbouring lane sections of a road that have the same lanes,
+// and returns how many joins were made.
+pub fn merge(network: &mut RoadNetwork, road_id: u32) -> usize {
+    let sections = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .map(|segment| segment.lane_section_id);
+    let (Some(mut section), Some(mut last)) = (sections.clone().min(), sections.max()) else {
+        return 0;
+    };
+    let mut merged = 0;
+    while section < last {
+        let lanes = |section: u32| -> Vec<&RoadSegment> {
+            let mut lanes: Vec<&RoadSegment> = network
+                .segments
+                .iter()
+                .filter(|s| s.road_id == road_id && s.lane_section_id == section)
+                .collect();
+            lanes.sort_by_key(|lane| lane.lane_id);
+            lanes
+        };
+        let (this, next) = (lanes(section), lanes(section + 1));
+        let same = this.len() == next.len() && this.iter().zip(&next).all(|(a, b)| same_lane(a, b));
+        if !same {
+            section += 1;
+            continue;
+        }
+        let joined: Vec<RoadSegment> = this.iter().zip(&next).map(|(a, b)| join(a, b)).collect();
+        network.segments.retain(|s| {
+            s.road_id != road_id
+                || (s.lane_section_id != section && s.lane_section_id != section + 1)
+        });
+        for segment in network.segments.iter_mut() {
+            if segment.road_id == road_id && segment.lane_section_id > section + 1 {
+                segment.lane_section_id -= 1;
+            }
+        }
+        network.segments.extend(joined);
+        merged += 1;
+        last -= 1;
+    }
+    merged
+}
+
+// The outermost lane on one side of a section, as an index into the
+// network's segments.
+fn outermost(network: &RoadNetwork, road_id: u32, section: u32, left: bool) -> Option<usize> {
+    network
+        .segments
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| {
+            s.road_id == road_id && s.lane_section_id == section && (s.lane_id > 0) == left
+        })
+        .max_by_key(|(_, s)| s.lane_id.abs())
+        .map(|(i, _)| i)
+}
+
+// The directions pointing away from the reference line along a boundary.
+fn outwards(points: &[DVec3], left: bool) -> Vec<DVec3> {
+    let last = points.len().saturating_sub(1);
+    (0..points.len())
+        .map(|i| {
+            let along = points[(i + 1).min(last)] - points[i.saturating_sub(1)];
+            let side = DVec3::Y
+                .cross(DVec3::new(along.x, 0.0, along.z))
+                .normalize_or_zero();
+            if left {
+                side
+            } else {
+                -side
+            }
+        })
+        .collect()
+}
+
+// The lane mark between two lanes that used to be the road edge.
+fn lane_line() -> RoadMark {
+    RoadMark {
+        kind: "broken".to_string(),
+        color: "standard".to_string(),
+        weight: "standard".to_string(),
+        material: "standard".to_string(),
+        ..RoadMark::default()
+    }
+}
+
+// Adds a lane outside the outermost lane on one side, from station `s` to
+// the end of the road, widening to `width` over `taper` meters.
+pub fn add_lane(
+    network: &mut RoadNetwork,
+    road_id: u32,
+    left: bool,
+    s: f64,
+    taper: f64,
+    width: f64,
+) -> Result<i32, String> {
+    let start = split(network, road_id, s)?;
+    let (_, end) = road_range(network, road_id).ok_or(format!("no road {road_id}"))?;
+    let mut lane_id = None;
+    let mut section = start;
+    while let Some(index) = outermost(network, road_id, section, left) {
+        let next = &mut network.segments[index];
+        let id = next.lane_id + next.lane_id.signum();
+        let mut added = next.clone();
+        // The new lane takes over the edge line.
+        if !next.road_marks.is_empty() {
+            next.road_marks = vec![lane_line()];
+        }
+        added.lane_id = id;
+        added.center_marks = Vec::new();
+        added.predecessor = (section != start).then_some(id);
+        added.successor = Some(id);
+        let inner = if left {
+            added.left_side.clone()
+        } else {
+            added.right_side.clone()
+        };
+        let directions = outwards(&inner, left);
+        let outer: Vec<DVec3> = inner
+            .iter()
+            .zip(&directions)
+            .enumerate()
+            .map(|(i, (p, out))| {
+                let at = station(&added, inner.len(), i);
+                *p + *out * width * ramp((at - s) / taper)
+            })
+            .collect();
+        if left {
+            added.right_side = inner;
+            added.left_side = outer;
+        } else {
+            added.left_side = inner;
+            added.right_side = outer;
+        }
+        refresh(&mut added);
+        network.segments.push(added);
+        lane_id.get_or_insert(id);
+        section += 1;
+    }
+    // The added lane ends with the road.
+    if let Some(last) = network.segments.last_mut().filter(|_| section != start) {
+        last.successor = None;
+    }
+    if section == start {
+        return Err(format!(
+            "road {road_id} has no {} lanes at {s:.2}",
+            if left { "left" } else { "right" }
+        ));
+    }
+    // The end of the taper is a section boundary, if it is on the road.
+    if s + taper < end - EPSILON {
+        split(network, road_id, s + taper)?;
+    }
+    Ok(lane_id.unwrap_or_default())
+}
+
+// Narrows an outermost lane to nothing over `taper` meters from station
+// `s`, and removes it from there on.
+pub fn remove_lane(
+    network: &mut RoadNetwork,
+    road_id: u32,
+    lane_id: i32,
+    s: f64,
+    taper: f64,
+) -> Result<(), String> {
+    let start = split(network, road_id, s)?;
+    let (_, end) = road_range(network, road_id).ok_or(format!("no road {road_id}"))?;
+    let taper_end = (s + taper).min(end);
+    let after = if taper_end < end - EPSILON {
+        Some(split(network, road_id, taper_end)?)
+    } else {
+        None
+    };
+    let left = lane_id > 0;
+    let sections = (start..).take_while(|section| {
+        network
+            .segments
+            .iter()
+            .any(|s| s.road_id == road_id && s.lane_section_id == *section)
+    });
+    let lanes: Vec<usize> = sections
+        .filter_map(|section| outermost(network, road_id, section, left))
+        .collect();
+    if lanes.is_empty()
+        || lanes
+            .iter()
+            .any(|&i| network.segments[i].lane_id != lane_id)
+    {
+        return Err(format!(
+            "lane {lane_id} is not the outermost lane of road {road_id} from {s:.2} on"
+        ));
+    }
+    let others = network.segments.iter().any(|seg| {
+        seg.road_id == road_id && seg.lane_section_id >= start && seg.lane_id != lane_id
+    });
+    if !others {
+        return Err(format!("lane {lane_id} is the only lane of road {road_id}"));
+    }
+
+    // The lane inside takes over the edge line.
+    let edge_line = network.segments[lanes[0]].road_marks.clone();
+    for &index in &lanes {
+        let lane = &mut network.segments[index];
+        if after.is_some_and(|after| lane.lane_section_id >= after) {
+            continue;
+        }
+        let count = lane.left_side.len();
+        for i in 0..count {
+            let at = station(lane, count, i);
+            let keep = 1.0 - ramp((at - s) / (taper_end - s).max(EPSILON));
+            let (inner, outer) = if left {
+                (lane.right_side[i], &mut lane.left_side[i])
+            } else {
+                (lane.left_side[i], &mut lane.right_side[i])
+            };
+            *outer = inner + (*outer - inner) * keep;
+        }
+        refresh(lane);
+    }
+    network.segments.retain(|seg| {
+        seg.road_id != road_id
+            || seg.lane_id != lane_id
+            || after.is_none_or(|after| seg.lane_section_id < after)
+    });
+    let next = lane_id - lane_id.signum();
+    for seg in network.segments.iter_mut() {
+        if seg.road_id == road_id
+            && seg.lane_id == lane_id
+            && after.is_some_and(|after| seg.lane_section_id + 1 == after)
+        {
+            seg.successor = None;
+        }
+        if seg.road_id == road_id
+            && seg.lane_id == next
+            && after.is_some_and(|after| seg.lane_section_id >= after)
+        {
+            seg.road_marks = edge_line.clone();
+        }
+    }
+    Ok(())
+}
+
+// Runs one `lanes` command on a road.
+fn edit(network: &mut RoadNetwork, road_id: u32, args: &[&str]) -> Result<String, String> {
+    // Rust reads "inf" and "NaN" as numbers, but no station or length is.
+    let number = |text: Option<&&str>, what: &str| -> Result<f64, String> {
+        let text = text.ok_or(format!("missing {what}"))?;
+        text.parse()
+            .ok()
+            .filter(|value: &f64| value.is_finite())
+            .ok_or(format!("`{text}` is not a valid {what}"))
+    };
+    Ok(match args {
+        ["split", s] => {
+            let section = split(network, road_id, number(Some(s), "station")?)?;
+            format!("lane section {section} of road {road_id} starts at {s}")
+        }
+        ["add", side @ ("left" | "right"), rest @ ..] => {
+            let s = number(rest.first(), "station")?;
+            let taper = number(rest.get(1), "taper length")?;
+            let width = rest
+                .get(2)
+                .map_or(Ok(DEFAULT_WIDTH), |w| number(Some(w), "width"))?;
+            if taper <= 0.0 || width <= 0.0 {
+                return Err("the taper length and width must be positive".to_string());
+            }
+            let lane_id = add_lane(network, road_id, *side == "left", s, taper, width)?;
+            format!("added lane {lane_id} to road {road_id}")
+        }
+        ["remove", lane, s, taper] => {
+            let lane_id: i32 = lane
+                .parse()
+                .ok()
+                .filter(|id| *id != 0)
+                .ok_or(format!("`{lane}` is not a valid lane ID"))?;
+            let taper = number(Some(taper), "taper length")?;
+            if taper <= 0.0 {
+                return Err("the taper length must be positive".to_string());
+            }
+            remove_lane(
+                network,
+                road_id,
+                lane_id,
+                number(Some(s), "station")?,
+                taper,
+            )?;
+            format!("removed lane {lane_id} from road {road_id}")
+        }
+        ["merge"] => {
+            let merged = merge(network, road_id);
+            format!("merged {merged} lane sections of road {road_id}")
+        }
+        _ => {
+            return Err("expected split <s>, add left|right <s> <taper> [width], \
+                 remove <lane> <s> <taper> or merge"
+                .to_string())
+        }
+    })
+}
+
+// `lanes` in the console: edits the lanes of the selected road.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let road_id = world
+        .resource::<Selection>()
+        .0
+        .map(|pick| pick.road_id)
+        .ok_or("select a road first")?;
+    let description = format!("editing the lanes of road {road_id}");
+    let transaction = Transaction::begin(world.resource::<RoadNetwork>(), description, &[road_id]);
+    transaction::run(world, transaction, |network| {
+        Ok((edit(network, road_id, args)?, Vec::new()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cross_section;
+    use crate::sample_maps::{assert_near, lane, load, TOLERANCE};
+
+    #[test]
+    fn lane_edits() {
+        let mut network = load("straight.xodr");
+        let width = |network: &RoadNetwork, s: f64, lane_id: i32| {
+            let cut = cross_section::cut(network, 1, s).unwrap();
+            let lane = cut.lanes.iter().find(|lane| lane.lane_id == lane_id);
+            lane.map_or(0.0, |lane| lane.width())
+        };
+
+        assert_eq!(split(&mut network, 1, 40.0), Ok(2));
+        assert_near(lane(&network, 1, 2, -1).start_s, 40.0, TOLERANCE);
+        assert_eq!(merge(&mut network, 1), 1);
+        assert_near(lane(&network, 1, 1, -1).end_s, 100.0, TOLERANCE);
+
+        // A lane widening to 3 m over 30 m from s = 20, in a section of its own.
+        assert_eq!(add_lane(&mut network, 1, false, 20.0, 30.0, 3.0), Ok(-3));
+        assert_near(width(&network, 20.0, -3), 0.0, TOLERANCE);
+        assert_near(width(&network, 35.0, -3), 1.5, TOLERANCE);
+        assert_near(width(&network, 70.0, -3), 3.0, TOLERANCE);
+        assert_near(lane(&network, 1, 3, -3).start_s, 50.0, TOLERANCE);
+        // Narrowed away again from s = 70, and gone after the taper.
+        remove_lane(&mut network, 1, -3, 70.0, 20.0).unwrap();
+        assert_near(width(&network, 80.0, -3), 1.5, TOLERANCE);
+        assert_eq!(width(&network, 95.0, -3), 0.0);
+        assert!(remove_lane(&mut network, 1, -1, 10.0, 5.0).is_err());
+    }
+
+    #[test]
+    fn commands_take_only_finite_numbers() {
+        let mut network = load("straight.xodr");
+        let original = network.clone();
+        for args in [
+            ["add", "right", "NaN", "10"],
+            ["add", "right", "20", "inf"],
+            ["add", "left", "-infinity", "10"],
+            ["remove", "-2", "nan", "10"],
+        ] {
+            let error = edit(&mut network, 1, &args).unwrap_err();
+            assert!(error.contains("is not a valid"), "{args:?}: {error}");
+        }
+        assert_eq!(
+            edit(&mut network, 1, &["add", "right", "20", "10", "1e400"]).unwrap_err(),
+            "`1e400` is not a valid width"
+        );
+        assert!(network.segments == original.segments);
+
+        // Called directly, a station off the road is refused too.
+        assert!(add_lane(&mut network, 1, false, f64::NAN, 10.0, 3.0).is_err());
+        assert!(remove_lane(&mut network, 1, -2, f64::NAN, 10.0).is_err());
+        assert!(split(&mut network, 1, f64::INFINITY).is_err());
+        assert!(network.segments == original.segments);
+        assert_eq!(
+            edit(&mut network, 1, &["add", "right", "20", "10"]),
+            Ok("added lane -3 to road 1".to_string())
+        );
+    }
+}
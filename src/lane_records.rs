@@ -0,0 +1,112 @@
+// Lane records by station.
+//
+// Road marks and materials hold from their `sOffset` into the lane section
+// up to the next record of their kind. Cutting a lane, joining two and
+// merging sections keep them in step with the lane's stations.
+
+use crate::{LaneMaterial, RoadMark};
+
+// A record that holds from `s_offset` meters into its lane section on.
+pub trait LaneRecord: Clone + PartialEq {
+    fn s_offset(&self) -> f64;
+    // The same record, starting elsewhere.
+    fn at(&self, s_offset: f64) -> Self;
+}
+
+impl LaneRecord for RoadMark {
+    fn s_offset(&self) -> f64 {
+        self.s_offset
+    }
+
+    fn at(&self, s_offset: f64) -> Self {
+        Self {
+            s_offset,
+            ..self.clone()
+        }
+    }
+}
+
+impl LaneRecord for LaneMaterial {
+    fn s_offset(&self) -> f64 {
+        self.s_offset
+    }
+
+    fn at(&self, s_offset: f64) -> Self {
+        Self {
+            s_offset,
+            ..self.clone()
+        }
+    }
+}
+
+// The record in force `s` meters into the lane section, if any.
+pub fn at<T: LaneRecord>(records: &[T], s: f64) -> Option<&T> {
+    records.iter().rev().find(|record| record.s_offset() <= s)
+}
+
+// The records of a lane between `from` and `to` meters into its section,
+// counted from `from`: the record in force there starts at zero.
+pub fn within<T: LaneRecord>(records: &[T], from: f64, to: f64) -> Vec<T> {
+    let first = records
+        .iter()
+        .rposition(|record| record.s_offset() <= from)
+        .unwrap_or(0);
+    records[first..]
+        .iter()
+        .take_while(|record| record.s_offset() <= from || record.s_offset() < to)
+        .map(|record| record.at((record.s_offset() - from).max(0.0)))
+        .collect()
+}
+
+// The records of two stretches of a lane run together, the second starting
+// `at` meters into the first. Records the same as the one before them are
+// left out.
+pub fn chain<T: LaneRecord>(first: &[T], second: &[T], at: f64) -> Vec<T> {
+    let mut records = first.to_vec();
+    for record in second {
+        let record = record.at(record.s_offset() + at);
+        let repeated = records
+            .last()
+            .is_some_and(|last| record.at(last.s_offset()) == *last);
+        if !repeated {
+            records.push(record);
+        }
+    }
+    records
+}
+
+// Whether the records of a stretch of a lane carry on those of the stretch
+// before it: it starts with the record the other ends with, or neither has
+// any.
+pub fn continues<T: LaneRecord>(first: &[T], second: &[T]) -> bool {
+    match (first.last(), second.first()) {
+        (Some(last), Some(next)) => next.s_offset() <= 0.0 && next.at(last.s_offset()) == *last,
+        (last, next) => last.is_none() && next.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mark(s_offset: f64, kind: &str) -> RoadMark {
+        RoadMark {
+            s_offset,
+            kind: kind.to_string(),
+            ..RoadMark::default()
+        }
+    }
+
+    #[test]
+    fn records_are_cut_and_run_together_by_station() {
+        let marks = [mark(0.0, "broken"), mark(50.0, "solid")];
+        let (first, second) = (within(&marks, 0.0, 40.0), within(&marks, 40.0, 100.0));
+        assert_eq!(first, [mark(0.0, "broken")]);
+        assert_eq!(second, [mark(0.0, "broken"), mark(10.0, "solid")]);
+        assert!(continues(&first, &second));
+        assert!(!continues(&second, &first[..0]));
+        assert_eq!(chain(&first, &second, 40.0), marks);
+        assert_eq!(at(&marks, 60.0), Some(&marks[1]));
+        assert_eq!(at(&second, 5.0), Some(&second[0]));
+    }
+}
@@ -0,0 +1,113 @@
+// Per-lane length and area.
+//
+// Measures every lane segment on its sampled boundaries rather than from
+// the section's station range: the length is that of the centerline in 3D,
+// so curves and grades count, and the area is the sum of the triangles
+// between the two boundary polylines, so width changes and the inside and
+// outside of curves count too. The report lists one row per lane and lane
+// section, for coverage and costing figures.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use bevy::math::DVec3;
+
+use crate::{RoadNetwork, RoadSegment};
+
+// The measurements of one lane segment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneMeasure {
+    // Index into `RoadNetwork::segments`.
+    pub segment: usize,
+    // Centerline length, in meters.
+    pub length: f64,
+    // Surface area, in square meters.
+    pub area: f64,
+}
+
+fn polyline_length(points: &[DVec3]) -> f64 {
+    points.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+// The area of the strip between a lane's boundaries, as two triangles per
+// pair of boundary samples.
+pub fn surface_area(lane: &RoadSegment) -> f64 {
+    let triangle = |a: DVec3, b: DVec3, c: DVec3| (b - a).cross(c - a).length() / 2.0;
+    lane.left_side
+        .windows(2)
+        .zip(lane.right_side.windows(2))
+        .map(|(l, r)| triangle(l[0], r[0], r[1]) + triangle(l[0], r[1], l[1]))
+        .sum()
+}
+
+// Measures every lane segment of a network, in segment order.
+pub fn measure(network: &RoadNetwork) -> Vec<LaneMeasure> {
+    network
+        .segments
+        .iter()
+        .enumerate()
+        .map(|(segment, lane)| LaneMeasure {
+            segment,
+            length: polyline_length(&lane.centerline()),
+            area: surface_area(lane),
+        })
+        .collect()
+}
+
+// Renders the measurements as CSV, with the mean width (area over length)
+// alongside.
+pub fn to_csv(network: &RoadNetwork, measures: &[LaneMeasure]) -> String {
+    let mut csv = String::from("road,section,lane,type,length,area,mean_width\n");
+    for measure in measures {
+        let lane = &network.segments[measure.segment];
+        let width = if measure.length > 0.0 {
+            measure.area / measure.length
+        } else {
+            0.0
+        };
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{:.3},{:.3},{:.3}",
+            lane.road_id,
+            lane.lane_section_id,
+            lane.lane_id,
+            lane.lane_type,
+            measure.length,
+            measure.area,
+            width
+        );
+    }
+    csv
+}
+
+// Writes the report of a network to a CSV file.
+pub fn write_report(network: &RoadNetwork, path: &Path) -> Result<(), String> {
+    std::fs::write(path, to_csv(network, &measure(network)))
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_near, lane, length, load, TOLERANCE};
+
+    #[test]
+    fn straight_lanes_cover_width_times_length() {
+        let network = load("straight.xodr");
+        for id in [1, -1] {
+            let lane = lane(&network, 1, 1, id);
+            assert_near(length(&network, lane), 100.0, TOLERANCE);
+            assert_near(surface_area(lane), 3.5 * 100.0, TOLERANCE);
+        }
+        // The sidewalk's curb drops at its inner edge halfway along, which
+        // tilts the second half and makes it a little wider than 3 m.
+        let sidewalk = surface_area(lane(&network, 1, 1, -2));
+        assert!(sidewalk > 300.0 && sidewalk < 300.1, "{sidewalk}");
+
+        let csv = to_csv(&network, &measure(&network));
+        assert!(
+            csv.contains("1,1,-1,driving,100.000,350.000,3.500\n"),
+            "{csv}"
+        );
+    }
+}
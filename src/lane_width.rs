@@ -0,0 +1,331 @@
+// Lane width sanity checks.
+//
+// Every lane is measured at each sample of its boundaries and held against
+// user-set limits: a minimum and a maximum width, and a maximum rate at which
+// the width may change along the lane. Converted maps with a unit error stand
+// out immediately, with lanes a hundred times too wide (centimeters read as
+// meters) or too narrow. The lane model does not know lane types, so every
+// lane counts as a driving lane. Lanes that are opening up or closing down
+// are only held to the rate limit, as they are meant to be narrow somewhere,
+// and so are lanes of no width at all.
+//
+// Stretches that break a rule go into the validation report and are
+// highlighted on the map in the color of the rule: blue for too narrow,
+// magenta for too wide, orange for too sudden a change.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::edit::NetworkChanged;
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::tessellation::boundaries;
+use crate::theme::Theme;
+use crate::validation::{Issue, Severity, ShowIssues, ValidationSettings};
+use crate::{camera_orbit, RoadNetwork, RoadSegment};
+
+// Lanes changing width faster than this (meters per meter) are tapering.
+const TAPER_RATE: f64 = 0.01;
+
+// Lanes narrower than this are not there at all, like the zero-width
+// placeholder lanes some maps use, in meters.
+const ABSENT_WIDTH: f64 = 0.01;
+
+// Widths beyond this many meters are most likely in the wrong unit.
+const UNIT_ERROR_WIDTH: f64 = 100.0;
+
+// Highlights are lifted by this much to stay above the markings.
+const HIGHLIGHT_LIFT: f32 = 0.08;
+
+// Limits on lane widths, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneWidthSettings {
+    pub min: f64,
+    pub max: f64,
+    // Width change per meter along the lane.
+    pub max_change: f64,
+}
+
+impl Default for LaneWidthSettings {
+    fn default() -> Self {
+        Self {
+            min: 2.5,
+            max: 5.0,
+            max_change: 0.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    Narrow,
+    Wide,
+    Sudden,
+}
+
+impl Rule {
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            Rule::Narrow => theme.narrow,
+            Rule::Wide => theme.wide,
+            Rule::Sudden => theme.sudden,
+        }
+    }
+}
+
+// A run of boundary samples of one lane that breaks a rule.
+struct Stretch {
+    segment: usize,
+    rule: Rule,
+    first: usize,
+    last: usize,
+    // The width furthest from the limit, or the steepest change.
+    worst: f64,
+}
+
+// The stretches to highlight, with their outline in the viewer frame.
+#[derive(Resource, Default)]
+struct WidthHighlights(Vec<(Rule, Vec<DVec3>)>);
+
+pub struct LaneWidthPlugin;
+
+impl Plugin for LaneWidthPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WidthHighlights>()
+            .add_systems(Startup, find_highlights)
+            .add_systems(Update, find_highlights.run_if(on_event::<NetworkChanged>()))
+            .add_systems(Update, draw_highlights.after(camera_orbit))
+            .add_systems(
+                Update,
+                add_legend
+                    .in_set(FillLegend)
+                    .run_if(|show: Res<ShowIssues>| show.0),
+            );
+    }
+}
+
+fn add_legend(
+    highlights: Res<WidthHighlights>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut legend: ResMut<Legend>,
+) {
+    let rows = [
+        (Rule::Narrow, "legend-narrow"),
+        (Rule::Wide, "legend-wide"),
+        (Rule::Sudden, "legend-sudden"),
+    ]
+    .into_iter()
+    .filter(|(rule, _)| highlights.0.iter().any(|(shown, _)| shown == rule))
+    .map(|(rule, message)| (rule.color(&theme), locale.text(message, &[])))
+    .collect();
+    legend.add(locale.text("legend-width", &[]), rows);
+}
+
+// Checks the widths of all lanes.
+pub fn check(network: &RoadNetwork, settings: &LaneWidthSettings) -> Vec<Issue> {
+    stretches(network, settings)
+        .into_iter()
+        .map(|stretch| {
+            let segment = &network.segments[stretch.segment];
+            let (left, right) = boundaries(segment);
+            let count = left.len().min(right.len());
+            let (start, end) = (
+                station(segment, stretch.first, count),
+                station(segment, stretch.last, count),
+            );
+            let mut message = match stretch.rule {
+                Rule::Narrow => format!(
+                    "narrower than {:.2} m from s={start:.1} to s={end:.1}, down to {:.2} m",
+                    settings.min, stretch.worst
+                ),
+                Rule::Wide => format!(
+                    "wider than {:.2} m from s={start:.1} to s={end:.1}, up to {:.2} m",
+                    settings.max, stretch.worst
+                ),
+                Rule::Sudden => format!(
+                    "width changes by up to {:.3} m/m from s={start:.1} to s={end:.1} \
+                     (limit {:.3} m/m)",
+                    stretch.worst, settings.max_change
+                ),
+            };
+            if stretch.rule == Rule::Wide && stretch.worst > UNIT_ERROR_WIDTH {
+                message.push_str("; centimeters read as meters?");
+            }
+            Issue {
+                check: "lane width",
+                severity: Severity::Warning,
+                road_id: segment.road_id,
+                lane_id: Some(segment.lane_id),
+                s: start,
+                position: (left[stretch.first] + right[stretch.first]) / 2.0,
+                message,
+            }
+        })
+        .collect()
+}
+
+// The station of a boundary sample.
+fn station(segment: &RoadSegment, index: usize, count: usize) -> f64 {
+    let fraction = index as f64 / count.saturating_sub(1).max(1) as f64;
+    segment.start_s + (segment.end_s - segment.start_s) * fraction
+}
+
+// Finds the stretches of every lane that break a rule.
+fn stretches(network: &RoadNetwork, settings: &LaneWidthSettings) -> Vec<Stretch> {
+    let mut out: Vec<Stretch> = Vec::new();
+    for (index, segment) in network.segments.iter().enumerate() {
+        let (left, right) = boundaries(segment);
+        let count = left.len().min(right.len());
+        // Widths are measured across the ground, ignoring crossfall.
+        let widths: Vec<f64> = (0..count)
+            .map(|i| {
+                let across = left[i] - right[i];
+                across.x.hypot(across.z)
+            })
+            .collect();
+        let stations: Vec<f64> = (0..count).map(|i| station(segment, i, count)).collect();
+        // The change rate at each sample, between its neighbours.
+        let rates: Vec<f64> = (0..count)
+            .map(|i| {
+                let (a, b) = (i.saturating_sub(1), (i + 1).min(count - 1));
+                let ds = stations[b] - stations[a];
+                if ds > f64::EPSILON {
+                    (widths[b] - widths[a]).abs() / ds
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+
+        let mut extend = |rule: Rule, i: usize, worst: f64| match out.last_mut() {
+            Some(last) if last.segment == index && last.rule == rule && last.last + 1 == i => {
+                last.last = i;
+                last.worst = match rule {
+                    Rule::Narrow => last.worst.min(worst),
+                    Rule::Wide | Rule::Sudden => last.worst.max(worst),
+                };
+            }
+            _ => out.push(Stretch {
+                segment: index,
+                rule,
+                first: i,
+                last: i,
+                worst,
+            }),
+        };
+        for i in 0..count {
+            let tapering = rates[i] > TAPER_RATE;
+            if widths[i] < settings.min && widths[i] > ABSENT_WIDTH && !tapering {
+                extend(Rule::Narrow, i, widths[i]);
+            } else if widths[i] > settings.max {
+                extend(Rule::Wide, i, widths[i]);
+            }
+        }
+        for (i, &rate) in rates.iter().enumerate() {
+            if rate > settings.max_change {
+                extend(Rule::Sudden, i, rate);
+            }
+        }
+    }
+    out
+}
+
+fn find_highlights(
+    network: Res<RoadNetwork>,
+    settings: Res<ValidationSettings>,
+    mut highlights: ResMut<WidthHighlights>,
+) {
+    highlights.0 = stretches(&network, &settings.lane_width)
+        .into_iter()
+        .map(|stretch| {
+            let (left, right) = boundaries(&network.segments[stretch.segment]);
+            // Around the stretch: up the left boundary and back down the right.
+            let mut outline: Vec<DVec3> = left[stretch.first..=stretch.last].to_vec();
+            outline.extend(right[stretch.first..=stretch.last].iter().rev());
+            outline.push(left[stretch.first]);
+            (stretch.rule, outline)
+        })
+        .collect();
+}
+
+fn draw_highlights(
+    show: Res<ShowIssues>,
+    highlights: Res<WidthHighlights>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    if !show.0 {
+        return;
+    }
+    for (rule, outline) in &highlights.0 {
+        let points = outline
+            .iter()
+            .map(|p| origin.to_render(*p) + Vec3::Y * HIGHLIGHT_LIFT);
+        gizmos.linestrip(points, rule.color(&theme));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    fn messages(network: &RoadNetwork, settings: &LaneWidthSettings) -> Vec<(i32, String)> {
+        check(network, settings)
+            .into_iter()
+            .map(|issue| (issue.lane_id.unwrap(), issue.message))
+            .collect()
+    }
+
+    #[test]
+    fn lanes_are_held_to_the_width_limits() {
+        let mut network = load("straight.xodr");
+        let settings = LaneWidthSettings::default();
+        assert!(check(&network, &settings).is_empty());
+
+        // The 3 m sidewalk counts as a driving lane.
+        let wider = LaneWidthSettings {
+            min: 3.2,
+            ..settings
+        };
+        assert_eq!(
+            messages(&network, &wider),
+            [(
+                -2,
+                "narrower than 3.20 m from s=0.0 to s=100.0, down to 3.00 m".to_string()
+            )]
+        );
+
+        for segment in &mut network.segments {
+            for point in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                point.x *= 100.0;
+                point.z *= 100.0;
+            }
+        }
+        let found = messages(&network, &settings);
+        assert_eq!(found.len(), 3);
+        assert!(found
+            .iter()
+            .all(|(_, message)| message.ends_with("; centimeters read as meters?")));
+    }
+
+    #[test]
+    fn opening_lanes_are_only_held_to_the_rate() {
+        // Lane -2 opens at 0.07 m per meter.
+        let network = load("elevation.xodr");
+        assert!(check(&network, &LaneWidthSettings::default()).is_empty());
+        let strict = LaneWidthSettings {
+            max_change: 0.05,
+            ..LaneWidthSettings::default()
+        };
+        let found = messages(&network, &strict);
+        assert!(!found.is_empty());
+        assert!(found
+            .iter()
+            .all(|(lane, message)| *lane == -2
+                && message.starts_with("width changes by up to 0.070")));
+    }
+}
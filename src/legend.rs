@@ -0,0 +1,147 @@
+// Legend panel.
+//
+// Lists what the colors on the map stand for, for whatever is shown at the
+// moment, so that screenshots explain themselves. Overlays and checks that
+// color the map add a section to `Legend` every frame they are shown, from
+// systems in the `FillLegend` set; the style sheet does too while it colors
+// anything. The panel in the bottom right corner, above the inspector, is
+// rebuilt only when the sections change, and hidden while there are none.
+// Extensions add their sections the same way.
+
+use bevy::prelude::*;
+
+use crate::i18n::Locale;
+
+// A titled list of colors and what they mean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegendSection {
+    pub title: String,
+    pub rows: Vec<(Color, String)>,
+}
+
+// The sections of this frame.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct Legend(pub Vec<LegendSection>);
+
+impl Legend {
+    pub fn add(&mut self, title: String, rows: Vec<(Color, String)>) {
+        if !rows.is_empty() {
+            self.0.push(LegendSection { title, rows });
+        }
+    }
+}
+
+// Systems adding to the legend run in this set.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FillLegend;
+
+// Marks the legend panel.
+#[derive(Component)]
+struct LegendPanel;
+
+pub struct LegendPlugin;
+
+impl Plugin for LegendPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Legend>()
+            .configure_sets(Update, FillLegend)
+            .add_systems(Startup, spawn_panel)
+            .add_systems(
+                Update,
+                (
+                    clear_legend.before(FillLegend),
+                    fill_panel.after(FillLegend),
+                ),
+            );
+    }
+}
+
+fn clear_legend(mut legend: ResMut<Legend>) {
+    legend.0.clear();
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Percent(20.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        LegendPanel,
+    ));
+}
+
+// Rebuilds the panel when the sections differ from the last frame's.
+fn fill_panel(
+    mut commands: Commands,
+    legend: Res<Legend>,
+    locale: Res<Locale>,
+    mut shown: Local<Legend>,
+    mut panels: Query<(Entity, &mut Visibility), With<LegendPanel>>,
+) {
+    if *legend == *shown {
+        return;
+    }
+    *shown = legend.clone();
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+    for (panel, mut visibility) in &mut panels {
+        *visibility = if legend.0.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                locale.text("legend-title", &[]),
+                style(Color::rgb(1.0, 0.9, 0.1)),
+            ));
+            for section in &legend.0 {
+                panel.spawn(TextBundle::from_section(
+                    section.title.clone(),
+                    style(Color::WHITE),
+                ));
+                for (color, label) in &section.rows {
+                    panel
+                        .spawn(NodeBundle {
+                            style: Style {
+                                column_gap: Val::Px(6.0),
+                                align_items: AlignItems::Center,
+                                padding: UiRect::left(Val::Px(8.0)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|row| {
+                            row.spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(12.0),
+                                    height: Val::Px(12.0),
+                                    ..default()
+                                },
+                                background_color: (*color).into(),
+                                ..default()
+                            });
+                            row.spawn(TextBundle::from_section(
+                                label.clone(),
+                                style(Color::rgb(0.85, 0.85, 0.85)),
+                            ));
+                        });
+                }
+            }
+        });
+    }
+}
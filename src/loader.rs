@@ -0,0 +1,167 @@
+// Picks the right importer for a map file.
+
+use std::path::Path;
+
+use crate::compressed::{read_map, Compression};
+use crate::transform::{LoadTransform, UpAxis};
+use crate::{compiled, generate_road_data, osm, xodr, RoadNetwork};
+
+// Warnings about a map printed when it is loaded; the rest are counted.
+const PRINTED_WARNINGS: usize = 10;
+
+// Loads a road network from `path`, or the built-in demo network if no path
+// is given. Errors are returned as human-readable messages.
+pub fn load_network(path: Option<&Path>) -> Result<RoadNetwork, String> {
+    load_network_with(path, &LoadTransform::default())
+}
+
+// Like `load_network`, bringing the source coordinates into the map frame
+// with `transform`. Maps with roads that could not be read are refused.
+pub fn load_network_with(
+    path: Option<&Path>,
+    transform: &LoadTransform,
+) -> Result<RoadNetwork, String> {
+    let network = load_partial(path, transform)?;
+    let Some(first) = network.failures.first() else {
+        return Ok(network);
+    };
+    let path = path.map_or_else(String::new, |path| format!("{}: ", path.display()));
+    let more = match network.failures.len() {
+        1 => String::new(),
+        count => format!(" (and {} more)", count - 1),
+    };
+    Err(format!(
+        "{path}{}{more}; `view` loads the rest of the map",
+        first.describe()
+    ))
+}
+
+// Like `load_network_with`, but keeps what could be read of a broken map,
+// with the roads that could not in `RoadNetwork::failures`.
+pub fn load_partial(path: Option<&Path>, transform: &LoadTransform) -> Result<RoadNetwork, String> {
+    let Some(path) = path else {
+        return Ok(apply(RoadNetwork::new(generate_road_data()), transform));
+    };
+
+    // Compressed maps are read as the map inside.
+    let result = match Compression::of(path) {
+        Some(compression) => read_map(path, compression)
+            .and_then(|(name, xml)| read_text(Path::new(&name), xml, transform)),
+        None => match extension(path).as_deref() {
+            // OSM data is projected around its own center and transformed
+            // afterwards.
+            Some("osm") => osm::import_file(path).map(|network| apply(network, transform)),
+            // The OpenDRIVE reader applies the transform while sampling.
+            Some("xodr") => xodr::read_file(path, transform),
+            // Compiled networks are stored in the frame they were compiled
+            // in.
+            Some("rsnet") => compiled::read_file(path).map(|network| apply(network, transform)),
+            _ => Err("unsupported map format".to_string()),
+        },
+    };
+    let network = result.map_err(|e| format!("{}: {e}", path.display()))?;
+    for warning in network.warnings.iter().take(PRINTED_WARNINGS) {
+        eprintln!("warning: {}: {warning}", path.display());
+    }
+    if network.warnings.len() > PRINTED_WARNINGS {
+        eprintln!(
+            "warning: {}: ... and {} more",
+            path.display(),
+            network.warnings.len() - PRINTED_WARNINGS
+        );
+    }
+    Ok(network)
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+// Reads a map already in memory, of the format its name tells.
+fn read_text(name: &Path, xml: String, transform: &LoadTransform) -> Result<RoadNetwork, String> {
+    match extension(name).as_deref() {
+        Some("osm") => osm::import_str(&xml).map(|network| apply(network, transform)),
+        Some("xodr") => xodr::read_string(xml, transform),
+        _ => Err(format!("{}: unsupported map format", name.display())),
+    }
+}
+
+// Applies a transform to a network already in the viewer frame.
+fn apply(mut network: RoadNetwork, transform: &LoadTransform) -> RoadNetwork {
+    if !transform.is_identity() {
+        for segment in &mut network.segments {
+            segment.start_pos = transform.apply_viewer(segment.start_pos);
+            segment.end_pos = transform.apply_viewer(segment.end_pos);
+            for p in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                *p = transform.apply_viewer(*p);
+            }
+        }
+        // Reference line records only stay in the ground plane with Z up.
+        for info in network.roads.values_mut() {
+            info.reference = info
+                .reference
+                .take()
+                .filter(|_| transform.up == UpAxis::Z)
+                .map(|line| line.moved(transform.rotation, transform.offset));
+        }
+    }
+    network.transform = *transform;
+    network
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec3;
+
+    use super::*;
+    use crate::sample_maps::{assert_points_near, load, temp_dir, text};
+    use crate::xodr;
+
+    #[test]
+    fn partial_loading() {
+        // A road without lanes, then one cut short by mismatched tags.
+        let xml = text("straight.xodr").replace(
+            "</OpenDRIVE>",
+            r#"  <road name="Bare" length="20.0" id="2" junction="-1">
+        <planView>
+          <geometry s="0.0" x="0.0" y="50.0" hdg="0.0" length="20.0"><line/></geometry>
+        </planView>
+      </road>
+      <road name="Cut" length="20.0" id="3" junction="-1">
+        <planView>
+          <geometry s="0.0" x="0.0" y="-50.0" hdg="0.0" length="20.0"><line/></geometry>
+        </planView>
+        <lanes>
+          <laneSection s="0.0">
+        </lanes>
+      </road>
+    </OpenDRIVE>"#,
+        );
+        let network = xodr::read_str(&xml, &LoadTransform::default()).unwrap();
+        assert_eq!(network.roads.keys().collect::<Vec<_>>(), [&1]);
+        assert_eq!(network.segments, load("straight.xodr").segments);
+        let failures = &network.failures;
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].describe(), "road 2: no lane sections");
+        assert_points_near(failures[0].position.unwrap(), DVec3::new(0.0, 0.0, -50.0));
+        assert_eq!(failures[1].road, "3");
+        assert!(failures[1].message.starts_with("XML error"));
+        assert_points_near(failures[1].position.unwrap(), DVec3::new(0.0, 0.0, 50.0));
+
+        // The viewer takes what could be read; other commands refuse the map.
+        let path = temp_dir("partial_loading").join("partial.xodr");
+        std::fs::write(&path, &xml).unwrap();
+        let partial = load_partial(Some(&path), &LoadTransform::default());
+        let strict = load_network(Some(&path));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(partial.unwrap().failures, network.failures);
+        let message = strict.unwrap_err();
+        assert!(
+            message.contains("road 2: no lane sections (and 1 more)"),
+            "{message}"
+        );
+        assert!(load("junction.xodr").failures.is_empty());
+    }
+}
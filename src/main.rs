@@ -4,37 +4,517 @@
 use bevy::prelude::*;
 use bevy::input::mouse::MouseWheel;
 use std::f32::consts::PI;
-use bevy::math::primitives::Rectangle;
+use bevy::math::DVec3;
+use bevy::render::render_resource::Face;
+use bevy::window::PrimaryWindow;
+use std::collections::{BTreeMap, HashMap};
+use std::process::ExitCode;
+
+mod annotations;
+mod apollo;
+mod asset_packs;
+mod barriers;
+mod batch;
+mod bookmarks;
+mod byte_reader;
+mod camera_tween;
+mod canvas;
+mod capture;
+mod carla;
+mod chart;
+mod clearance;
+mod cli;
+mod clipboard;
+mod compare;
+mod compiled;
+mod compressed;
+mod conflicts;
+mod console;
+mod continuity;
+mod crop;
+mod cross_section;
+mod curved_text;
+mod curvature;
+mod debug_view;
+mod decals;
+mod design_rules;
+mod display;
+mod drive;
+mod edit;
+mod entity_index;
+mod environment;
+mod extensions;
+mod failures;
+mod filter;
+mod friction;
+mod geo;
+mod gltf;
+mod i18n;
+mod inspector;
+mod isolate;
+mod issue_export;
+mod json;
+mod junction_overlay;
+mod labels;
+mod lane_change;
+mod lane_edit;
+mod lane_records;
+mod lane_report;
+mod lane_width;
+mod legend;
+mod loader;
+mod map_matching;
+mod map_tiles;
+mod origin;
+mod merge;
+mod mesh_cache;
+mod mesh_qa;
+mod night;
+mod normalize;
+mod numbers;
+mod occlusion;
+mod odr;
+mod open_dialog;
+mod osm;
+mod overlap;
+mod overlay_pass;
+mod overlays;
+mod placement;
+mod plan_export;
+mod pointcloud;
+mod priority;
+mod profile;
+mod project;
+mod reload;
+mod ribbons;
+mod road_marks;
+mod road_types;
+mod route_export;
+mod route_profile;
+mod routing;
+#[cfg(test)]
+mod sample_maps;
+mod selection;
+mod settings;
+mod sight;
+mod sign_edit;
+mod sign_models;
+mod snapping;
+mod signals;
+mod simplify;
+mod splice;
+mod split;
+mod status_bar;
+mod style;
+mod sumo;
+mod templates;
+mod tessellation;
+mod theme;
+mod tiles;
+mod topology;
+mod traces;
+mod trajectories;
+mod transaction;
+mod transform;
+mod units;
+mod validation;
+mod walk;
+mod weather;
+mod xml_tree;
+mod xodr;
 
 // This is the main function where the Bevy application starts.
-fn main() {
+fn main() -> ExitCode {
+    // Command-line tools (exporters and friends) run headless and exit early.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match cli::run(&args) {
+        cli::Launch::Viewer(options) => *options,
+        cli::Launch::Exit(code) => return code,
+    };
+
+    // Notes left on this map in earlier sessions, placed on the roads as
+    // they are now.
+    let annotations = match options.annotations {
+        Some(file) => annotations::Annotations::open(Some(file), &options.network),
+        None => annotations::Annotations::load(options.map.as_deref(), &options.network),
+    };
+    // A network merged from several maps is neither cached nor reloaded, as
+    // neither file alone describes it.
+    let map = options.map.clone().filter(|_| options.layers.is_empty());
+    // Meshes tessellated when this map was opened before.
+    let mesh_cache =
+        mesh_cache::MeshCache::open(map.as_deref(), &options.network.transform, &options.tiles);
+
     // A Bevy app is created and configured with the `DefaultPlugins`.
     App::new()
         // Add Bevy's default plugins, which provide functionality for rendering,
         // input, UI, and more.
         .add_plugins(DefaultPlugins)
+        // The road network shared by rendering and exporting.
+        .insert_resource(options.network)
+        // Which entities show which road, lane, junction and signal.
+        .init_resource::<entity_index::OdrEntityIndex>()
+        // Edits of the network while the viewer runs.
+        .add_plugins(edit::EditPlugin)
+        // Cross sections for roads drawn in the viewer.
+        .insert_resource(options.templates)
+        .add_plugins(templates::TemplatePlugin)
+        .add_plugins(snapping::SnappingPlugin)
+        // The network's roads, lanes and signals as components.
+        .add_plugins(odr::OdrPlugin)
+        // Road meshes are streamed in tiles around the camera.
+        .insert_resource(options.tiles)
+        .insert_resource(mesh_cache)
+        .init_resource::<RoadMaterials>()
+        .insert_resource(options.theme)
+        // UI text in the chosen language.
+        .insert_resource(options.locale)
+        .insert_resource(options.speed_unit)
+        .add_plugins(units::UnitPlugin)
+        .add_plugins(display::DisplayPlugin)
+        .add_plugins(settings::SettingsPlugin)
+        .add_plugins(theme::ThemePlugin)
+        .insert_resource(options.environment)
+        .add_plugins(environment::EnvironmentPlugin)
+        .insert_resource(options.weather)
+        .add_plugins(weather::WeatherPlugin)
+        .insert_resource(options.occlusion)
+        .add_plugins(occlusion::OcclusionPlugin)
+        // Organization-specific looks for lanes and objects.
+        .insert_resource(options.style)
+        .add_plugins(style::StylePlugin)
+        .add_plugins(tiles::TileStreamingPlugin)
+        .add_plugins(debug_view::DebugViewPlugin)
+        .add_plugins(overlays::OverlayPlugin)
+        .add_plugins(overlay_pass::OverlayPassPlugin)
+        .add_plugins(ribbons::RibbonPlugin)
+        .add_plugins(labels::LabelPlugin)
+        .insert_resource(options.sign_catalogs)
+        .add_plugins(signals::SignalPlugin)
+        .add_plugins(sign_models::SignModelPlugin)
+        .add_plugins(barriers::BarrierPlugin)
+        .insert_resource(options.asset_packs)
+        .add_plugins(asset_packs::AssetPackPlugin)
+        .add_plugins(placement::PlacementPlugin)
+        .insert_resource(options.night)
+        .add_plugins(night::NightPlugin)
+        .add_plugins(sign_edit::SignEditPlugin)
+        .add_plugins(cross_section::CrossSectionPlugin)
+        // Showing and hiding elements by category and attribute.
+        .add_plugins(filter::FilterPlugin)
+        .add_plugins(isolate::IsolationPlugin)
+        // Picking and the analysis views of the selected road.
+        .add_plugins(selection::SelectionPlugin)
+        .add_plugins(inspector::InspectorPlugin)
+        .add_plugins(xml_tree::XmlTreePlugin)
+        .add_plugins(status_bar::StatusBarPlugin)
+        // What the colors on the map stand for.
+        .add_plugins(legend::LegendPlugin)
+        .add_plugins(topology::TopologyPlugin)
+        .add_plugins(lane_change::LaneChangePlugin)
+        // Viewpoints saved for this map in earlier sessions.
+        .insert_resource(bookmarks::Bookmarks::load(options.map.as_deref()))
+        .add_plugins(bookmarks::BookmarkPlugin)
+        .insert_resource(annotations)
+        .add_plugins(annotations::AnnotationPlugin)
+        .add_plugins(decals::DecalPlugin)
+        // Reloading the map when its file changes, keeping the session.
+        .insert_resource(reload::MapSource::new(map))
+        .add_plugins(reload::ReloadPlugin)
+        .add_plugins(open_dialog::OpenDialogPlugin)
+        // Placeholders and a list for the roads that could not be loaded.
+        .add_plugins(failures::FailurePlugin)
+        .add_plugins(chart::ChartPlugin)
+        .add_plugins(profile::ProfilePlugin)
+        .add_plugins(curvature::CurvaturePlugin)
+        .insert_resource(options.sight)
+        .add_plugins(sight::SightPlugin)
+        .add_plugins(route_profile::RouteProfilePlugin)
+        // Map checks, reported on startup and marked on the map.
+        .insert_resource(options.validation)
+        .add_plugins(validation::ValidationPlugin)
+        .add_plugins(lane_width::LaneWidthPlugin)
+        .add_plugins(design_rules::DesignRulePlugin)
+        // Lidar overlays requested on the command line.
+        .insert_resource(pointcloud::PointCloudFiles(options.point_clouds))
+        .add_plugins(pointcloud::PointCloudPlugin)
+        .insert_resource(trajectories::Trajectories(options.trajectories))
+        .add_plugins(trajectories::TrajectoryPlugin)
+        .add_plugins(traces::TracePlugin)
+        // Keep render-space coordinates small around the camera.
+        .add_plugins(origin::FloatingOriginPlugin)
         // Add a system that will be run once at the start of the application.
         .add_systems(Startup, setup)
         // Add a system to handle camera movement and interaction.
         .add_systems(Update, (camera_input, camera_orbit).chain())
+        .add_plugins(camera_tween::CameraTweenPlugin)
+        // Perspective and plan side by side.
+        .add_plugins(split::SplitViewPlugin)
+        // The view against a snapshot taken before, on either side of a divider.
+        .add_plugins(compare::ComparePlugin)
+        // Eye-level review of the network at 1:1 scale.
+        .add_plugins(walk::WalkPlugin)
+        // Following a lane from the driver's seat.
+        .add_plugins(drive::DrivePlugin)
+        // Review clips, recorded on request.
+        .insert_resource(capture::CaptureRequest(options.capture))
+        .add_plugins(capture::CapturePlugin)
+        // Overlays and analyses added by extensions.
+        .add_plugins(extensions::ExtensionPlugin)
+        // Typed and scripted commands.
+        .insert_resource(console::StartupScript(options.script))
+        .add_plugins(console::ConsolePlugin)
         // Run the app.
         .run();
+
+    ExitCode::SUCCESS
 }
 
 // A struct to hold the data for a single segment of the road.
 // This mirrors the information you described from your library API.
-#[derive(Debug, Clone)]
+// Positions are kept in f64 so that maps with large projected coordinates
+// stay precise; they are only converted to f32 when meshes are built.
+#[derive(Debug, Clone, PartialEq)]
 struct RoadSegment {
-    start_pos: Vec3,
-    end_pos: Vec3,
-    start_s: f32,
-    end_s: f32,
-    width: f32,
-    left_side: Vec<Vec3>,
-    right_side: Vec<Vec3>,
+    start_pos: DVec3,
+    end_pos: DVec3,
+    start_s: f64,
+    end_s: f64,
+    width: f64,
+    left_side: Vec<DVec3>,
+    right_side: Vec<DVec3>,
     road_id: u32,
-    lane_id: u32,
+    lane_id: i32,
     lane_section_id: u32,
+    // The traffic rule of the road the lane belongs to.
+    rule: TrafficRule,
+    // The OpenDRIVE lane type, e.g. "driving" or "sidewalk".
+    lane_type: String,
+    // The OpenDRIVE road type in force where the lane section starts, e.g.
+    // "town" or "motorway"; empty if the map gives none.
+    road_type: String,
+    // The speed limit in m/s, if the map gives one (see `units`).
+    speed: Option<f64>,
+    // The lane's surface records by `s_offset`, each up to the next; empty
+    // if the map gives none.
+    materials: Vec<LaneMaterial>,
+    // Who may use the lane, beyond what its type implies.
+    access: Vec<LaneAccess>,
+    // The markings on the lane's outer boundary, by `s_offset`, each up to
+    // the next; empty if the map gives none.
+    road_marks: Vec<RoadMark>,
+    // The markings of the center lane, on the inner boundary of lanes 1 and
+    // -1, likewise.
+    center_marks: Vec<RoadMark>,
+    // The lanes this one continues from and into, as its `<link>` gives
+    // them: lanes of the neighbouring lane sections, or at the ends of the
+    // road lanes of the linked roads.
+    predecessor: Option<i32>,
+    successor: Option<i32>,
+}
+
+// An OpenDRIVE `<roadMark>`: the marking along a lane boundary.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct RoadMark {
+    // Where the mark starts, in meters from the start of the lane section.
+    s_offset: f64,
+    // The type, e.g. "broken" or "solid broken".
+    kind: String,
+    // "standard", "white", "yellow" and so on, as in OpenDRIVE.
+    color: String,
+    // "standard" or "bold".
+    weight: String,
+    material: String,
+    // The line width in meters, if given.
+    width: Option<f64>,
+    // The lines of an explicit `<type>` definition, used instead of those
+    // the type implies, with the definition's name and width as read.
+    lines: Vec<MarkLine>,
+    type_name: String,
+    type_width: Option<f64>,
+}
+
+// A `<line>` of a road mark `<type>`: painted for `length` meters, then left
+// out for `space` meters (continuous if `space` is zero), starting `s_offset`
+// meters into the lane section and `t_offset` meters left of the boundary.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct MarkLine {
+    length: f64,
+    space: f64,
+    t_offset: f64,
+    s_offset: f64,
+    width: Option<f64>,
+    // Overrides the color of the road mark.
+    color: Option<String>,
+}
+
+// An OpenDRIVE `<material>` record of a lane: what its surface is, and how
+// it grips and how rough it is, for simulation.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct LaneMaterial {
+    // Where the record starts, in meters from the start of the lane section.
+    s_offset: f64,
+    // E.g. "asphalt"; empty if not given.
+    surface: String,
+    friction: f64,
+    roughness: f64,
+}
+
+// An OpenDRIVE `<access>` rule of a lane: the road users it names, e.g.
+// "bus" or "bicycle", are either the only ones allowed or shut out.
+#[derive(Debug, Clone, PartialEq)]
+struct LaneAccess {
+    allow: bool,
+    restriction: String,
+}
+
+// Which side of the road traffic keeps to, from the OpenDRIVE `rule`
+// attribute of a road. It decides which lanes run along the reference line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+enum TrafficRule {
+    // Right-hand traffic (RHT), the OpenDRIVE default.
+    #[default]
+    RightHand,
+    // Left-hand traffic (LHT), as in the UK or Japan.
+    LeftHand,
+}
+
+impl TrafficRule {
+    // Parses the OpenDRIVE attribute value; anything unknown is right-hand.
+    fn from_attribute(value: &str) -> Self {
+        if value.eq_ignore_ascii_case("LHT") {
+            TrafficRule::LeftHand
+        } else {
+            TrafficRule::RightHand
+        }
+    }
+
+    pub fn as_attribute(self) -> &'static str {
+        match self {
+            TrafficRule::RightHand => "RHT",
+            TrafficRule::LeftHand => "LHT",
+        }
+    }
+}
+
+impl RoadSegment {
+    // Whether traffic in this lane travels towards increasing s. In
+    // right-hand traffic that is the right lanes (negative IDs), in
+    // left-hand traffic the left lanes.
+    fn follows_reference(&self) -> bool {
+        match self.rule {
+            TrafficRule::RightHand => self.lane_id < 0,
+            TrafficRule::LeftHand => self.lane_id > 0,
+        }
+    }
+
+    // The line halfway between the two boundaries, where a vehicle drives.
+    fn centerline(&self) -> Vec<DVec3> {
+        self.left_side
+            .iter()
+            .zip(&self.right_side)
+            .map(|(l, r)| (*l + *r) / 2.0)
+            .collect()
+    }
+}
+
+// One end of a road.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ContactPoint {
+    Start,
+    End,
+}
+
+// A connection from one end of a road to an end of another, as in OpenDRIVE
+// road links. Links are stored from the point of view of `road_id`, so a
+// connection between two roads appears once for each of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct RoadLink {
+    road_id: u32,
+    contact: ContactPoint,
+    other_road_id: u32,
+    other_contact: ContactPoint,
+}
+
+// The heading and curvature of a road's reference line at one station.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PlanSample {
+    s: f64,
+    // Counter-clockwise from east in the map frame, in radians.
+    heading: f64,
+    // Per meter, positive when turning left.
+    curvature: f64,
+}
+
+// What is known about a road beyond its lanes.
+#[derive(Debug, Clone, Default)]
+struct RoadInfo {
+    // The road's `name` attribute, empty if the map gives none.
+    name: String,
+    // Samples of the OpenDRIVE plan view, taken along each geometry piece
+    // from its start to its end, so that a station where two pieces meet
+    // appears twice. Empty for maps that do not come with a plan view.
+    plan_view: Vec<PlanSample>,
+    // The OpenDRIVE records of the reference line, written back instead of
+    // the sampled outline while the lanes follow them. None for maps that
+    // do not come with them.
+    reference: Option<xodr::ReferenceLine>,
+    // The junction the road belongs to, for connecting roads.
+    junction: Option<u32>,
+    // The road's element as it was read, empty for roads not read from
+    // OpenDRIVE or changed since.
+    xml: xodr::ElementXml,
+}
+
+// The full set of road segments making up the loaded map.
+#[derive(Resource, Debug, Clone, Default)]
+struct RoadNetwork {
+    // The `name` of the OpenDRIVE header, empty if the map gives none.
+    name: String,
+    segments: Vec<RoadSegment>,
+    links: Vec<RoadLink>,
+    signals: Vec<signals::Signal>,
+    // Per-road data, by road ID.
+    roads: BTreeMap<u32, RoadInfo>,
+    // The OpenDRIVE elements of the junctions as they were read, by ID.
+    junctions: BTreeMap<u32, xodr::ElementXml>,
+    // The transform the map was loaded with, kept so that reloads and
+    // exports can refer back to the source coordinates.
+    transform: transform::LoadTransform,
+    // Problems the reader worked around, such as malformed numbers, for the
+    // user to be told about.
+    warnings: Vec<String>,
+    // Roads that could not be read and were left out (see `failures`).
+    failures: Vec<failures::LoadFailure>,
+    // How the source coordinates relate to latitude and longitude, if the
+    // map says.
+    geo: Option<geo::GeoReference>,
+}
+
+impl RoadNetwork {
+    fn new(segments: Vec<RoadSegment>) -> Self {
+        Self {
+            name: String::new(),
+            segments,
+            links: Vec::new(),
+            signals: Vec::new(),
+            roads: BTreeMap::new(),
+            junctions: BTreeMap::new(),
+            transform: transform::LoadTransform::default(),
+            warnings: Vec::new(),
+            failures: Vec::new(),
+            geo: None,
+        }
+    }
+
+    // Looks up a segment by its road, lane section and lane IDs.
+    fn find_segment(&self, road_id: u32, lane_section_id: u32, lane_id: i32) -> Option<usize> {
+        self.segments.iter().position(|segment| {
+            segment.road_id == road_id
+                && segment.lane_section_id == lane_section_id
+                && segment.lane_id == lane_id
+        })
+    }
 }
 
 // Generates some dummy road data for visualization.
@@ -42,66 +522,289 @@ struct RoadSegment {
 fn generate_road_data() -> Vec<RoadSegment> {
     // We'll create a simple straight road for demonstration purposes.
     let segment = RoadSegment {
-        start_pos: Vec3::new(0.0, 0.0, 0.0),
-        end_pos: Vec3::new(100.0, 0.0, 0.0),
+        start_pos: DVec3::new(0.0, 0.0, 0.0),
+        end_pos: DVec3::new(100.0, 0.0, 0.0),
         start_s: 0.0,
         end_s: 100.0,
         width: 4.0,
         // For a straight road, the left and right sides are simple offsets.
-        left_side: vec![Vec3::new(0.0, 0.0, 2.0), Vec3::new(100.0, 0.0, 2.0)],
-        right_side: vec![Vec3::new(0.0, 0.0, -2.0), Vec3::new(100.0, 0.0, -2.0)],
+        // North is -z, so the left side of an eastbound road has negative z.
+        left_side: vec![DVec3::new(0.0, 0.0, -2.0), DVec3::new(100.0, 0.0, -2.0)],
+        right_side: vec![DVec3::new(0.0, 0.0, 2.0), DVec3::new(100.0, 0.0, 2.0)],
         road_id: 1,
-        lane_id: 1,
+        lane_id: -1,
         lane_section_id: 1,
+        rule: TrafficRule::RightHand,
+        lane_type: "driving".to_string(),
+        road_type: String::new(),
+        speed: None,
+        materials: Vec::new(),
+        access: Vec::new(),
+        road_marks: Vec::new(),
+        center_marks: Vec::new(),
+        predecessor: None,
+        successor: Some(-1),
     };
 
     // Create a second segment at an angle.
     let segment_2 = RoadSegment {
-        start_pos: Vec3::new(100.0, 0.0, 0.0),
-        end_pos: Vec3::new(150.0, 0.0, 50.0),
+        start_pos: DVec3::new(100.0, 0.0, 0.0),
+        end_pos: DVec3::new(150.0, 0.0, 50.0),
         start_s: 100.0,
-        end_s: 150.0 + 50.0, // using pythagoras theorem to calculate longitudinal length
+        end_s: 100.0 + 50.0 * std::f64::consts::SQRT_2, // using pythagoras theorem to calculate longitudinal length
         width: 4.0,
-        left_side: vec![Vec3::new(100.0, 0.0, 2.0), Vec3::new(150.0, 0.0, 52.0)],
-        right_side: vec![Vec3::new(100.0, 0.0, -2.0), Vec3::new(150.0, 0.0, 48.0)],
+        left_side: vec![DVec3::new(100.0, 0.0, -2.0), DVec3::new(150.0, 0.0, 48.0)],
+        right_side: vec![DVec3::new(100.0, 0.0, 2.0), DVec3::new(150.0, 0.0, 52.0)],
         road_id: 1,
-        lane_id: 1,
+        lane_id: -1,
         lane_section_id: 2,
+        rule: TrafficRule::RightHand,
+        lane_type: "driving".to_string(),
+        road_type: String::new(),
+        speed: None,
+        materials: Vec::new(),
+        access: Vec::new(),
+        road_marks: Vec::new(),
+        center_marks: Vec::new(),
+        predecessor: Some(-1),
+        successor: None,
     };
 
     vec![segment, segment_2]
 }
 
-// Spawns the 3D entities for the road network.
-fn spawn_roads(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let road_data = generate_road_data();
+// The kinds of road mesh the viewer draws. Each gets one shared material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum RoadLayer {
+    // The surface of lanes of one class, see `theme`.
+    Surface(theme::LaneClass),
+    Marking,
+    Arrow,
+    // Debug overlays, see `debug_view`.
+    Wireframe,
+    Normals,
+    Backface,
+}
+
+impl RoadLayer {
+    fn material(self, theme: &theme::Theme) -> StandardMaterial {
+        // Overlays are unlit so that they read the same from any angle.
+        let overlay = |color: Color| StandardMaterial {
+            base_color: color,
+            unlit: true,
+            ..default()
+        };
+        let color = theme.layer(self);
+        match self {
+            RoadLayer::Surface(_) | RoadLayer::Marking => StandardMaterial::from(color),
+            RoadLayer::Arrow => StandardMaterial {
+                depth_bias: overlay_pass::OverlayLevel::Surface.depth_bias(),
+                ..overlay(color)
+            },
+            RoadLayer::Wireframe | RoadLayer::Normals => StandardMaterial {
+                depth_bias: overlay_pass::OverlayLevel::Line.depth_bias(),
+                ..overlay(color)
+            },
+            // Culling front faces leaves only the back faces visible.
+            RoadLayer::Backface => StandardMaterial {
+                cull_mode: Some(Face::Front),
+                ..overlay(color)
+            },
+        }
+    }
+
+    // Whether the layer is drawn in the overlay pass, see `overlay_pass`.
+    fn is_overlay(self) -> bool {
+        matches!(
+            self,
+            RoadLayer::Arrow | RoadLayer::Wireframe | RoadLayer::Normals
+        )
+    }
+}
 
-    for segment in road_data {
-        // Create a custom mesh for the road segment.
-        let mesh = Mesh::from(Rectangle::new(
-            segment.end_pos.distance(segment.start_pos),
-            segment.width,
+// Materials shared by all road meshes. Entities with the same material and
+// pipeline are batched by the renderer, which one material per segment would
+// prevent.
+#[derive(Resource, Debug, Default)]
+struct RoadMaterials {
+    handles: HashMap<RoadLayer, Handle<StandardMaterial>>,
+    // The theme the materials are colored in, see `theme`, and the weather
+    // they are shown in, see `weather`.
+    theme: theme::Theme,
+    weather: weather::Weather,
+}
+
+impl RoadMaterials {
+    fn get(
+        &mut self,
+        materials: &mut Assets<StandardMaterial>,
+        layer: RoadLayer,
+    ) -> Handle<StandardMaterial> {
+        let (theme, weather) = (&self.theme, self.weather);
+        self.handles
+            .entry(layer)
+            .or_insert_with(|| {
+                let mut material = layer.material(theme);
+                weather.adjust(layer, &mut material);
+                materials.add(material)
+            })
+            .clone()
+    }
+}
+
+// What goes into the road meshes besides the surface and markings.
+#[derive(Debug, Clone, Copy, Default)]
+struct RoadMeshOptions {
+    debug: debug_view::DebugView,
+    overlays: overlays::Overlays,
+    // Geometry beyond this plane is left out, see `cross_section`.
+    clip: Option<cross_section::ClipPlane>,
+}
+
+// Tessellates consecutive segments of one road into a single surface mesh
+// and a single marking mesh, so that a road costs two draw calls however
+// many lanes and sections it has. The meshes are simplified to within
+// `max_error` meters if given. These are what the mesh cache keeps.
+fn tessellate_road(segments: &[&RoadSegment], max_error: Option<f64>) -> mesh_cache::RoadMeshes {
+    // Meshes are built around the road's first point; the floating origin
+    // places that anchor in render space.
+    let Some(anchor) = segments.iter().find_map(|s| s.left_side.first().copied()) else {
+        return mesh_cache::RoadMeshes::default();
+    };
+
+    let mut surface = tessellation::TriangleMesh::default();
+    let mut markings = tessellation::TriangleMesh::default();
+    for segment in segments {
+        surface.append(&tessellation::road_surface(segment, anchor));
+        markings.append(&tessellation::boundary_markings(
+            segment,
+            tessellation::MARKING_WIDTH,
+            tessellation::MARKING_LIFT,
+            anchor,
         ));
+    }
+    if let Some(max_error) = max_error {
+        surface = simplify::simplify(&surface, max_error);
+        markings = simplify::simplify(&markings, max_error);
+    }
+    mesh_cache::RoadMeshes {
+        road_id: segments[0].road_id,
+        anchor,
+        surface,
+        markings,
+    }
+}
 
-        // Calculate the direction and rotation of the road segment.
-        let direction = (segment.end_pos - segment.start_pos).normalize();
-        let rotation = Quat::from_rotation_y(direction.z.atan2(direction.x));
+// Spawns the meshes of one road, plus any direction arrows and debug
+// overlays that are switched on. Returns the entities together with the
+// number of bytes their meshes occupy, for the tile budget. A surface
+// material, if given, replaces the shared one (see `style`). Every mesh
+// carries the road's `OdrRoad` and the `OdrLanes` it draws (see `odr`).
+#[allow(clippy::too_many_arguments)]
+fn spawn_road(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    road_materials: &mut RoadMaterials,
+    odr_road: &odr::OdrRoad,
+    road: mesh_cache::RoadMeshes,
+    segments: &[&RoadSegment],
+    options: &RoadMeshOptions,
+    surface_material: Option<Handle<StandardMaterial>>,
+) -> (Vec<Entity>, usize) {
+    let mesh_cache::RoadMeshes {
+        road_id,
+        anchor,
+        mut surface,
+        mut markings,
+        ..
+    } = road;
+    if surface.is_empty() && markings.is_empty() {
+        return (Vec::new(), 0);
+    }
 
-        // Calculate the center position of the road segment.
-        let position = (segment.start_pos + segment.end_pos) / 2.0;
+    let mut arrows = tessellation::TriangleMesh::default();
+    if options.overlays.arrows {
+        for segment in segments {
+            // Arrows sit just above the markings they may cross.
+            arrows.append(&tessellation::direction_arrows(
+                segment,
+                overlays::ARROW_SPACING,
+                2.0 * tessellation::MARKING_LIFT,
+                anchor,
+            ));
+        }
+    }
+    if let Some(plane) = options.clip {
+        let keep = |p: Vec3| plane.keeps(anchor + p.as_dvec3());
+        for mesh in [&mut surface, &mut markings, &mut arrows] {
+            mesh.retain_triangles(keep);
+        }
+    }
 
-        // Spawn a PbrBundle to represent the road segment in 3D.
-        commands.spawn(PbrBundle {
-            mesh: meshes.add(mesh),
-            material: materials.add(StandardMaterial::from(Color::rgb(0.2, 0.2, 0.2))),
-            transform: Transform::from_translation(position).with_rotation(rotation),
-            ..default()
-        });
+    let mut parts = Vec::new();
+    let mut bytes = 0;
+    let mut add = |mesh: Mesh| {
+        bytes += tiles::mesh_bytes(&mesh);
+        meshes.add(mesh)
+    };
+    if !arrows.is_empty() {
+        parts.push((add(arrows.to_mesh()), RoadLayer::Arrow));
+    }
+    let debug = options.debug;
+    let class = theme::LaneClass::of(&segments[0].lane_type);
+    for (mesh, layer) in [
+        (surface, RoadLayer::Surface(class)),
+        (markings, RoadLayer::Marking),
+    ] {
+        if mesh.is_empty() {
+            continue;
+        }
+        if debug.wireframe {
+            parts.push((add(debug_view::wireframe_lines(&mesh)), RoadLayer::Wireframe));
+        }
+        if debug.normals {
+            parts.push((add(debug_view::normal_lines(&mesh)), RoadLayer::Normals));
+        }
+        let handle = add(mesh.to_mesh());
+        if debug.backfaces {
+            parts.push((handle.clone(), RoadLayer::Backface));
+        }
+        parts.push((handle, layer));
     }
+
+    let lanes = odr::OdrLanes(segments.iter().map(|&lane| odr::OdrLane::from(lane)).collect());
+    let entities = parts
+        .into_iter()
+        .map(|(mesh, layer)| {
+            let mut part = commands.spawn((
+                PbrBundle {
+                    mesh,
+                    material: match (layer, &surface_material) {
+                        (RoadLayer::Surface(_), Some(material)) => material.clone(),
+                        _ => road_materials.get(materials, layer),
+                    },
+                    ..default()
+                },
+                origin::WorldPosition(anchor),
+                RoadPart { road_id, layer },
+                odr_road.clone(),
+                lanes.clone(),
+            ));
+            if layer.is_overlay() {
+                part.insert(overlay_pass::overlay_layers());
+            }
+            part.id()
+        })
+        .collect();
+    (entities, bytes)
+}
+
+// What a road mesh entity shows.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+struct RoadPart {
+    road_id: u32,
+    layer: RoadLayer,
 }
 
 // A component to mark the main camera.
@@ -118,12 +821,9 @@ struct CameraOrbit {
     pan: Vec2, // For panning the camera.
 }
 
-// A system to set up the scene: camera, light, and roads.
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
+// A system to set up the scene: camera and light. Roads are streamed in by
+// the tile plugin.
+fn setup(mut commands: Commands) {
     // Add a directional light source to illuminate the scene.
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
@@ -140,9 +840,6 @@ fn setup(
         ..default()
     });
 
-    // Spawn the roads.
-    spawn_roads(commands.reborrow(), meshes, materials);
-
     // Spawn the camera with its custom components.
     commands.spawn((
         Camera3dBundle {
@@ -162,17 +859,28 @@ fn setup(
 }
 
 // A system to handle mouse input for the camera.
+#[allow(clippy::too_many_arguments)]
 fn camera_input(
     mut query: Query<&mut CameraOrbit, With<MainCamera>>,
     mut mouse_wheel: EventReader<MouseWheel>,
     mut cursor_moved: EventReader<CursorMoved>,
     mouse_buttons: Res<ButtonInput<MouseButton>>,
+    split: Res<split::SplitView>,
+    signs: Res<sign_edit::SignTool>,
+    comparison: Res<compare::Comparison>,
+    windows: Query<&Window, With<PrimaryWindow>>,
     mut last_cursor_position: Local<Option<Vec2>>,
 ) {
     let mut orbit = query.single_mut();
 
+    // In split view, the mouse over the plan moves the plan instead.
+    let over_plan = windows.get_single().is_ok_and(|window| split.in_plan(window));
+
     // Zoom with the mouse wheel.
     for event in mouse_wheel.read() {
+        if over_plan {
+            continue;
+        }
         let zoom_factor = 1.0 + event.y * -0.1;
         orbit.distance = (orbit.distance * zoom_factor).clamp(5.0, 500.0);
     }
@@ -183,7 +891,7 @@ fn camera_input(
         current_cursor_position = Some(event.position);
     }
 
-    if let (Some(current_pos), Some(last_pos)) = (*last_cursor_position, current_cursor_position) {
+    if let (Some(current_pos), Some(last_pos), false) = (*last_cursor_position, current_cursor_position, over_plan) {
         let delta = current_pos - last_pos;
 
         // Pan with the middle mouse button.
@@ -191,8 +899,9 @@ fn camera_input(
             orbit.pan += delta * 0.1;
         }
 
-        // Orbit with the left mouse button.
-        if mouse_buttons.pressed(MouseButton::Left) {
+        // Orbit with the left mouse button, unless it is dragging a sign or
+        // the divider of a comparison.
+        if mouse_buttons.pressed(MouseButton::Left) && !signs.dragging() && !comparison.active() {
             orbit.azimuth -= delta.x * 0.005;
             orbit.elevation = (orbit.elevation + delta.y * 0.005).clamp(-PI / 2.0, PI / 2.0);
         }
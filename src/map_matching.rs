@@ -0,0 +1,606 @@
+// Matching positions to lanes.
+//
+// Log replay and trajectory overlays need to know which lane a recorded
+// position lies on. `RoadNetwork::nearest_lane` projects a point onto the
+// centerline of every lane near it and ranks the lanes by how far the point
+// is off the centerline and, given the heading the point was recorded with,
+// by how well that heading agrees with the lane's direction of travel.
+// Lanes heading the other way are left out. Heights are not compared, so on
+// overpasses both levels are candidates. The console's `nearest` command
+// lists the matches for a point.
+//
+// On top of that, `match_trace` snaps a whole GPS or odometry trace to a
+// path through the lanes (see there). Traces are CSV files in the map
+// frame, or in latitude and longitude on maps with a georeference, which
+// takes the fixes into the map frame.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::f64::consts::PI;
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::geo::map_position;
+use crate::routing::{lane_graph, segment_length, Route};
+use crate::RoadNetwork;
+
+// Lanes whose direction differs from the heading by more than this, in
+// radians, are not candidates.
+const MAX_HEADING_ERROR: f64 = PI / 3.0;
+
+// How much a heading error counts in the ranking against the lateral
+// offset, in meters per radian.
+const HEADING_WEIGHT: f64 = 5.0;
+
+// A lane near a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaneMatch {
+    // Index into `RoadNetwork::segments`.
+    pub segment: usize,
+    // Station of the projection along the road.
+    pub s: f64,
+    // Offset of the point from the lane centerline, left of the direction
+    // of travel positive, in meters.
+    pub lateral: f64,
+    // Angle between the heading and the lane's direction of travel, in
+    // radians; zero without a heading.
+    pub heading_error: f64,
+    // Whether the point lies between the lane's boundaries.
+    pub inside: bool,
+    // The ranking, lower is better.
+    pub score: f64,
+}
+
+// A viewer-frame position in the map plane.
+fn flat(p: DVec3) -> DVec2 {
+    DVec2::new(p.x, -p.z)
+}
+
+impl RoadNetwork {
+    // The lanes within `max_distance` meters of a map-frame point, best
+    // first. The heading is counter-clockwise from east, in radians.
+    pub fn nearest_lane(
+        &self,
+        point: DVec2,
+        heading: Option<f64>,
+        max_distance: f64,
+    ) -> Vec<LaneMatch> {
+        let mut matches = Vec::new();
+        for (index, lane) in self.segments.iter().enumerate() {
+            let boundary = lane.left_side.iter().chain(&lane.right_side);
+            let (min, max) = boundary.fold(
+                (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+                |(min, max), p| (min.min(flat(*p)), max.max(flat(*p))),
+            );
+            if point.cmplt(min - max_distance).any() || point.cmpgt(max + max_distance).any() {
+                continue;
+            }
+
+            // The nearest point on the centerline, with the length along it.
+            let centerline: Vec<DVec2> = lane.centerline().into_iter().map(flat).collect();
+            let mut best: Option<(f64, f64, DVec2, usize)> = None;
+            let mut along = 0.0;
+            for (piece, pair) in centerline.windows(2).enumerate() {
+                let (a, b) = (pair[0], pair[1]);
+                let ab = b - a;
+                let t = if ab.length_squared() > 0.0 {
+                    ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let foot = a + ab * t;
+                let distance = point.distance(foot);
+                if best.is_none_or(|(d, ..)| distance < d) {
+                    best = Some((distance, along + ab.length() * t, ab, piece));
+                }
+                along += ab.length();
+            }
+            let Some((distance, at, direction, piece)) = best else {
+                continue;
+            };
+            if distance > max_distance || direction.length_squared() == 0.0 {
+                continue;
+            }
+
+            // Lanes driven against the reference line run the other way.
+            let travel = if lane.follows_reference() {
+                direction
+            } else {
+                -direction
+            };
+            let side = travel.perp_dot(point - centerline[piece]).signum();
+            let heading_error = match heading {
+                Some(heading) => {
+                    let lane_heading = travel.y.atan2(travel.x);
+                    ((heading - lane_heading + PI).rem_euclid(2.0 * PI) - PI).abs()
+                }
+                None => 0.0,
+            };
+            let bidirectional = lane.lane_type == "bidirectional";
+            let heading_error = if bidirectional {
+                heading_error.min(PI - heading_error)
+            } else {
+                heading_error
+            };
+            if heading_error > MAX_HEADING_ERROR {
+                continue;
+            }
+
+            let fraction = if along > 0.0 { at / along } else { 0.0 };
+            matches.push(LaneMatch {
+                segment: index,
+                s: lane.start_s + (lane.end_s - lane.start_s) * fraction,
+                lateral: side * distance,
+                heading_error,
+                inside: distance <= lane.width / 2.0,
+                score: distance + HEADING_WEIGHT * heading_error,
+            });
+        }
+        matches.sort_by(|a, b| a.score.total_cmp(&b.score));
+        matches
+    }
+}
+
+// A recorded position, in the map frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TracePoint {
+    pub position: DVec2,
+    pub height: Option<f64>,
+    // Counter-clockwise from east, in radians.
+    pub heading: Option<f64>,
+}
+
+// Reads a trace from CSV with a header naming the columns: `x` and `y`
+// (map frame, meters) or `lat` and `lon` (degrees, on a georeferenced
+// network) are required, `z` and `heading` (radians, counter-clockwise from
+// east) optional. Route files written by `export-route` are traces too.
+pub fn read_trace(text: &str, network: &RoadNetwork) -> Result<Vec<TracePoint>, String> {
+    let mut lines = text.lines().enumerate();
+    let header: Vec<&str> = lines
+        .next()
+        .map(|(_, line)| line.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+    let column = |name: &str| header.iter().position(|h| h.eq_ignore_ascii_case(name));
+    // Fixes in latitude and longitude are projected: `x` is then the
+    // longitude and `y` the latitude.
+    let (x, y, geographic) = match (column("x"), column("y"), column("lon"), column("lat")) {
+        (Some(x), Some(y), _, _) => (x, y, false),
+        (_, _, Some(lon), Some(lat)) => {
+            if network.geo.is_none() {
+                return Err("lat and lon need a map with a georeference".to_string());
+            }
+            (lon, lat, true)
+        }
+        _ => return Err("expected a header with x and y or lat and lon columns".to_string()),
+    };
+    let (z, heading) = (column("z"), column("heading"));
+
+    let mut points = Vec::new();
+    for (number, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let value = |i: Option<usize>| -> Result<Option<f64>, String> {
+            match i.and_then(|i| fields.get(i)).filter(|f| !f.is_empty()) {
+                Some(field) => field
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("line {}: `{field}` is not a number", number + 1)),
+                None => Ok(None),
+            }
+        };
+        let (Some(px), Some(py)) = (value(Some(x))?, value(Some(y))?) else {
+            return Err(format!("line {}: expected x and y", number + 1));
+        };
+        let mut position = DVec2::new(px, py);
+        if geographic {
+            position = map_position(network, position).unwrap_or(position);
+        }
+        points.push(TracePoint {
+            position,
+            height: value(z)?,
+            heading: value(heading)?,
+        });
+    }
+    if points.len() < 2 {
+        return Err("a trace needs at least two points".to_string());
+    }
+    Ok(points)
+}
+
+// Standard deviation of the position error, in meters, and of the heading
+// error, in radians.
+const POSITION_SIGMA: f64 = 5.0;
+const HEADING_SIGMA: f64 = 0.5;
+
+// Scale of the difference between the distance driven along the lanes and
+// the distance between two fixes, in meters.
+const TRANSITION_BETA: f64 = 5.0;
+
+// Lanes this far from a fix, in meters, are candidates, at most this many.
+const SEARCH_RADIUS: f64 = 20.0;
+const MAX_CANDIDATES: usize = 8;
+
+// Log-probability of jumping between lanes not connected within reach,
+// which keeps one bad stretch from breaking the match.
+const JUMP_COST: f64 = 50.0;
+
+// Fixes closer than this, in meters, give no heading.
+const MIN_MOVE: f64 = 1.0;
+
+// A trace snapped to the lanes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedTrace {
+    // The fixes, in the viewer frame; fixes without a height take the one
+    // of their lane.
+    pub raw: Vec<DVec3>,
+    // The matched lane position of each fix, none where no lane was near.
+    pub matched: Vec<Option<DVec3>>,
+    // The lanes driven, in order.
+    pub route: Route,
+}
+
+// The lane graph with both ends of every lane as nodes (2i the start, 2i+1
+// the end of segment i), undirected, so a trace may run along a lane either
+// way.
+struct EndGraph {
+    lengths: Vec<f64>,
+    // Ends of other lanes joined to each end.
+    joined: Vec<Vec<usize>>,
+}
+
+impl EndGraph {
+    fn new(network: &RoadNetwork) -> Self {
+        let lengths: Vec<f64> = (0..network.segments.len())
+            .map(|i| segment_length(network, i))
+            .collect();
+        let mut joined = vec![Vec::new(); lengths.len() * 2];
+        // The graph runs in driving direction; lanes driven against the
+        // reference line are left by their start and entered by their end.
+        let exit = |i: usize| 2 * i + usize::from(network.segments[i].follows_reference());
+        let entry = |i: usize| 2 * i + usize::from(!network.segments[i].follows_reference());
+        for (from, next) in lane_graph(network).into_iter().enumerate() {
+            for to in next {
+                joined[exit(from)].push(entry(to));
+                joined[entry(to)].push(exit(from));
+            }
+        }
+        Self { lengths, joined }
+    }
+
+    // Distances from a point `along` meters into segment `from` to the lane
+    // ends within `limit`, with the end each was reached from.
+    fn explore(&self, from: usize, along: f64, limit: f64) -> HashMap<usize, (f64, Option<usize>)> {
+        let mut reached: HashMap<usize, (f64, Option<usize>)> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let seeds = [
+            (2 * from, along),
+            (2 * from + 1, self.lengths[from] - along),
+        ];
+        for (node, cost) in seeds {
+            reached.insert(node, (cost, None));
+            queue.push(Reverse((OrderedCost(cost), node)));
+        }
+        while let Some(Reverse((OrderedCost(cost), node))) = queue.pop() {
+            if cost > reached[&node].0 {
+                continue;
+            }
+            // The other end of the lane, then the lanes joined here.
+            let across = (node ^ 1, self.lengths[node / 2]);
+            let joined = self.joined[node].iter().map(|&next| (next, 0.0));
+            for (next, step) in std::iter::once(across).chain(joined) {
+                let candidate = cost + step;
+                if candidate > limit || reached.get(&next).is_some_and(|(c, _)| *c <= candidate) {
+                    continue;
+                }
+                reached.insert(next, (candidate, Some(node)));
+                queue.push(Reverse((OrderedCost(candidate), next)));
+            }
+        }
+        reached
+    }
+
+    // The distance along the lanes to a point `along` meters into segment
+    // `to`, and the end node it is reached through; none for the same lane.
+    fn distance_to(
+        &self,
+        reached: &HashMap<usize, (f64, Option<usize>)>,
+        from: (usize, f64),
+        to: (usize, f64),
+    ) -> Option<(f64, Option<usize>)> {
+        let (segment, along) = to;
+        let mut best = (from.0 == segment).then(|| ((along - from.1).abs(), None));
+        for (node, rest) in [
+            (2 * segment, along),
+            (2 * segment + 1, self.lengths[segment] - along),
+        ] {
+            if let Some((cost, _)) = reached.get(&node) {
+                if best.is_none_or(|(b, _)| cost + rest < b) {
+                    best = Some((cost + rest, Some(node)));
+                }
+            }
+        }
+        best
+    }
+
+    // The segments passed on the way to an end node, in order, without the
+    // one started on.
+    fn path(reached: &HashMap<usize, (f64, Option<usize>)>, end: usize) -> Vec<usize> {
+        let mut nodes = vec![end];
+        while let Some(&(_, Some(previous))) = reached.get(nodes.last().unwrap()) {
+            nodes.push(previous);
+        }
+        nodes.reverse();
+        let mut segments: Vec<usize> = Vec::new();
+        for pair in nodes.windows(2) {
+            // Crossing a lane from one end to the other drives it.
+            if pair[0] / 2 == pair[1] / 2 && segments.last() != Some(&(pair[0] / 2)) {
+                segments.push(pair[0] / 2);
+            }
+        }
+        segments.retain(|&segment| segment != nodes[0] / 2);
+        segments
+    }
+}
+
+// Orders costs for the queue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OrderedCost(f64);
+
+impl Eq for OrderedCost {}
+
+impl Ord for OrderedCost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialOrd for OrderedCost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// A point `along` meters into a polyline.
+fn point_along(points: &[DVec3], along: f64) -> DVec3 {
+    let mut rest = along;
+    for pair in points.windows(2) {
+        let length = pair[0].distance(pair[1]);
+        if rest <= length && length > 0.0 {
+            return pair[0].lerp(pair[1], rest / length);
+        }
+        rest -= length;
+    }
+    points.last().copied().unwrap_or_default()
+}
+
+// Snaps a trace to the lanes with a hidden Markov model: the lanes near
+// each fix are the states, likelier the nearer the fix is and the better
+// its heading agrees, and moving from one to the next is likelier the
+// closer the distance along the lanes comes to the distance between the
+// fixes. The most likely sequence (Viterbi) gives the matched lanes.
+pub fn match_trace(network: &RoadNetwork, trace: &[TracePoint]) -> Option<MatchedTrace> {
+    let graph = EndGraph::new(network);
+    let heading = |i: usize| {
+        trace[i].heading.or_else(|| {
+            let (a, b) = if i + 1 < trace.len() {
+                (i, i + 1)
+            } else {
+                (i.checked_sub(1)?, i)
+            };
+            let d = trace[b].position - trace[a].position;
+            (d.length() >= MIN_MOVE).then(|| d.y.atan2(d.x))
+        })
+    };
+    let along = |m: &LaneMatch| {
+        let lane = &network.segments[m.segment];
+        let span = lane.end_s - lane.start_s;
+        let fraction = if span > 0.0 {
+            (m.s - lane.start_s) / span
+        } else {
+            0.0
+        };
+        fraction.clamp(0.0, 1.0) * graph.lengths[m.segment]
+    };
+
+    // Candidates and Viterbi scores per fix with candidates; back pointers
+    // into the previous such fix.
+    struct Step {
+        fix: usize,
+        candidates: Vec<(LaneMatch, f64)>,
+        scores: Vec<f64>,
+        back: Vec<Option<usize>>,
+    }
+    let mut steps: Vec<Step> = Vec::new();
+    for (fix, point) in trace.iter().enumerate() {
+        let candidates: Vec<(LaneMatch, f64)> = network
+            .nearest_lane(point.position, heading(fix), SEARCH_RADIUS)
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|m| (m, along(&m)))
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        let emission = |m: &LaneMatch| {
+            -0.5 * (m.lateral / POSITION_SIGMA).powi(2)
+                - 0.5 * (m.heading_error / HEADING_SIGMA).powi(2)
+        };
+        let mut scores: Vec<f64> = candidates.iter().map(|(m, _)| emission(m)).collect();
+        let mut back = vec![None; candidates.len()];
+        if let Some(previous) = steps.last() {
+            let straight = trace[previous.fix].position.distance(point.position);
+            let limit = 2.0 * straight + 2.0 * SEARCH_RADIUS;
+            let mut best = vec![f64::NEG_INFINITY; candidates.len()];
+            for (i, (m, at)) in previous.candidates.iter().enumerate() {
+                let reached = graph.explore(m.segment, *at, limit);
+                for (j, (next, next_at)) in candidates.iter().enumerate() {
+                    let transition = match graph.distance_to(
+                        &reached,
+                        (m.segment, *at),
+                        (next.segment, *next_at),
+                    ) {
+                        Some((driven, _)) => -(driven - straight).abs() / TRANSITION_BETA,
+                        None => -JUMP_COST,
+                    };
+                    let score = previous.scores[i] + transition;
+                    if score > best[j] {
+                        best[j] = score;
+                        back[j] = Some(i);
+                    }
+                }
+            }
+            for (score, best) in scores.iter_mut().zip(best) {
+                *score += best;
+            }
+        }
+        steps.push(Step {
+            fix,
+            candidates,
+            scores,
+            back,
+        });
+    }
+
+    // Follows the back pointers from the likeliest final state.
+    let last = steps.last()?;
+    let mut state =
+        (0..last.scores.len()).max_by(|&a, &b| last.scores[a].total_cmp(&last.scores[b]))?;
+    let mut chosen = vec![0; steps.len()];
+    for k in (0..steps.len()).rev() {
+        chosen[k] = state;
+        state = steps[k].back[state].unwrap_or(0);
+    }
+
+    let mut matched = vec![None; trace.len()];
+    let mut segments: Vec<usize> = Vec::new();
+    for (k, step) in steps.iter().enumerate() {
+        let (m, at) = step.candidates[chosen[k]];
+        let lane = &network.segments[m.segment];
+        matched[step.fix] = Some(point_along(&lane.centerline(), at));
+        if k > 0 {
+            let (previous, previous_at) = steps[k - 1].candidates[chosen[k - 1]];
+            let straight = trace[steps[k - 1].fix]
+                .position
+                .distance(trace[step.fix].position);
+            let reached = graph.explore(
+                previous.segment,
+                previous_at,
+                2.0 * straight + 2.0 * SEARCH_RADIUS,
+            );
+            if let Some((_, Some(end))) =
+                graph.distance_to(&reached, (previous.segment, previous_at), (m.segment, at))
+            {
+                segments.extend(EndGraph::path(&reached, end));
+            }
+        }
+        if segments.last() != Some(&m.segment) {
+            segments.push(m.segment);
+        }
+    }
+
+    let raw = trace
+        .iter()
+        .zip(&matched)
+        .map(|(point, snapped)| {
+            let height = point.height.or(snapped.map(|p| p.y)).unwrap_or_default();
+            DVec3::new(point.position.x, height, -point.position.y)
+        })
+        .collect();
+    Some(MatchedTrace {
+        raw,
+        matched,
+        route: Route::new(network, segments),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{load, text};
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    // Fixes every 10 m along a line at `y`, a little off to the side.
+    fn trace(y: f64, from: f64, to: f64) -> Vec<TracePoint> {
+        (0..=8)
+            .map(|i| TracePoint {
+                position: DVec2::new(from + (to - from) * i as f64 / 8.0, y + 0.3),
+                height: None,
+                heading: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn traces_read_from_csv() {
+        let network = load("straight.xodr");
+        let read = |text| read_trace(text, &network);
+        let points = read("x,y,heading\n1,2,0.5\n3,4,\n").unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].heading, Some(0.5));
+        assert_eq!(points[1].heading, None);
+        assert!(read("a,b\n1,2\n3,4\n").is_err());
+        assert!(read("x,y\n1,2\n").is_err());
+        assert!(read("x,y\n1,2\n3,four\n").is_err());
+        // The map has no georeference.
+        assert!(read("lat,lon\n49,8\n49,8.001\n").is_err());
+    }
+
+    #[test]
+    fn geographic_traces_are_projected() {
+        let xml = text("straight.xodr").replace(
+            r#"name="straight"/>"#,
+            r#"name="straight">
+        <geoReference><![CDATA[+proj=tmerc +lat_0=49 +lon_0=8 +k=1 +x_0=0 +y_0=0]]></geoReference>
+      </header>"#,
+        );
+        let transform = LoadTransform {
+            offset: DVec3::new(1000.0, -500.0, 0.0),
+            ..LoadTransform::default()
+        };
+        let network = xodr::read_str(&xml, &transform).unwrap();
+        // Fixes along the right lane, given by their longitude and latitude.
+        let geo = network.geo.as_ref().unwrap();
+        let mut csv = "lat,lon\n".to_string();
+        for i in 1..=9 {
+            let lon_lat = geo.to_geographic(DVec2::new(i as f64 * 10.0, -1.75));
+            csv.push_str(&format!("{},{}\n", lon_lat.y, lon_lat.x));
+        }
+        let points = read_trace(&csv, &network).unwrap();
+        assert!(points[0].position.distance(DVec2::new(1010.0, -501.75)) < 1e-3);
+        let matched = match_trace(&network, &points).unwrap();
+        let lanes: Vec<i32> = matched
+            .route
+            .segments
+            .iter()
+            .map(|&i| network.segments[i].lane_id)
+            .collect();
+        assert_eq!(lanes, [-1]);
+    }
+
+    #[test]
+    fn fixes_snap_to_the_lane_driven() {
+        let network = load("straight.xodr");
+        let lane_of = |trace: &[TracePoint]| {
+            let matched = match_trace(&network, trace).unwrap();
+            assert_eq!(matched.raw.len(), trace.len());
+            assert!(matched.matched.iter().all(Option::is_some));
+            let lanes: Vec<i32> = matched
+                .route
+                .segments
+                .iter()
+                .map(|&i| network.segments[i].lane_id)
+                .collect();
+            lanes
+        };
+        // Eastwards on the right of the reference line, westwards on its
+        // left, each in the direction the lane is driven.
+        assert_eq!(lane_of(&trace(-1.75, 10.0, 90.0)), [-1]);
+        assert_eq!(lane_of(&trace(1.75, 90.0, 10.0)), [1]);
+        // Driving the wrong way along a lane matches the lane of the
+        // direction driven, next to it.
+        assert_eq!(lane_of(&trace(-1.75, 90.0, 10.0)), [1]);
+    }
+}
@@ -0,0 +1,331 @@
+// Tile pyramids of the map.
+//
+// `render-tiles` draws the network from above into a pyramid of PNG tiles,
+// `<zoom>/<x>/<y>.png`, the layout web maps (Leaflet, OpenLayers, MapLibre)
+// read as XYZ tiles. Georeferenced maps (see `geo`) are tiled in Web
+// Mercator like any web map, so that the tiles line up with base maps.
+// Other maps get a local pyramid whose zoom 0 tile spans the map's bounding
+// square, for a map view without a base map (Leaflet's CRS.Simple).
+//
+// Tiles are drawn in software, needing neither a window nor a GPU: the lane
+// surfaces and road marks the viewer shows (see `tessellation`), in the
+// colors of a theme, on a transparent background so that they can be laid
+// over a base map. Where roads cross, the higher one is drawn. Every pixel a
+// triangle touches is filled, so lanes and marks narrower than a pixel still
+// show at low zoom levels. Tiles the map does not touch are not written.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::Color;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::Image;
+
+use crate::geo;
+use crate::tessellation::{boundary_markings, road_surface, MARKING_LIFT, MARKING_WIDTH};
+use crate::theme::{LaneClass, Theme};
+use crate::RoadNetwork;
+
+// Edge length of a tile, in pixels.
+pub const TILE_SIZE: u32 = 256;
+
+// Without `--zoom`, this many levels are drawn, starting at the one where
+// the whole map fits into a tile.
+pub const DEFAULT_LEVELS: u8 = 6;
+
+// The deepest zoom level drawn, as in most web maps.
+pub const MAX_ZOOM: u8 = 22;
+
+// Web Mercator stops short of the poles.
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+// A triangle of the map in pyramid coordinates: the zoom 0 tile spans 0 to
+// 1 with y down.
+struct Triangle {
+    corners: [DVec2; 3],
+    heights: [f64; 3],
+    color: [u8; 4],
+}
+
+// Where the map's triangles lie in the pyramid.
+enum Pyramid {
+    WebMercator,
+    // The map frame's bounding square: its north-west corner and edge length.
+    Local { corner: DVec2, size: f64 },
+}
+
+impl Pyramid {
+    fn of(network: &RoadNetwork) -> Pyramid {
+        if network.geo.is_some() {
+            return Pyramid::WebMercator;
+        }
+        let (min, max) = network
+            .segments
+            .iter()
+            .flat_map(|segment| segment.left_side.iter().chain(&segment.right_side))
+            .map(|p| DVec2::new(p.x, -p.z))
+            .fold(
+                (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+                |(min, max), p| (min.min(p), max.max(p)),
+            );
+        let size = (max - min).max_element().max(1.0);
+        let center = (min + max) / 2.0;
+        Pyramid::Local {
+            corner: DVec2::new(center.x - size / 2.0, center.y + size / 2.0),
+            size,
+        }
+    }
+
+    // A viewer-frame position in pyramid coordinates.
+    fn project(&self, network: &RoadNetwork, p: DVec3) -> DVec2 {
+        match self {
+            Pyramid::WebMercator => {
+                let lon_lat = geo::geographic(network, p).unwrap_or_default();
+                let lat = lon_lat.y.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+                DVec2::new(
+                    (lon_lat.x + 180.0) / 360.0,
+                    (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / std::f64::consts::PI) / 2.0,
+                )
+            }
+            Pyramid::Local { corner, size } => DVec2::new(p.x - corner.x, corner.y + p.z) / *size,
+        }
+    }
+}
+
+// The lane surfaces and road marks of the network, surfaces first.
+fn triangles(network: &RoadNetwork, pyramid: &Pyramid, theme: &Theme) -> Vec<Triangle> {
+    let srgb = |color: Color| color.as_rgba_u8();
+    let marking = theme.marking.as_linear_rgba_f32();
+    let mut triangles = Vec::new();
+    for segment in &network.segments {
+        // Meshes are f32; keep them small around the segment.
+        let origin = segment.start_pos;
+        let surface = road_surface(segment, origin);
+        let marks = boundary_markings(segment, MARKING_WIDTH, MARKING_LIFT, origin);
+        for (mesh, is_mark) in [(surface, false), (marks, true)] {
+            let corners: Vec<(DVec2, f64)> = mesh
+                .positions
+                .iter()
+                .map(|p| {
+                    let p = origin + p.as_dvec3();
+                    (pyramid.project(network, p), p.y)
+                })
+                .collect();
+            for corner in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| corners[corner[i] as usize]);
+                let color = if is_mark {
+                    let tint = mesh.colors.get(corner[0] as usize).copied();
+                    let [r, g, b, a] = tint.unwrap_or([1.0; 4]);
+                    srgb(Color::rgba_linear(
+                        r * marking[0],
+                        g * marking[1],
+                        b * marking[2],
+                        a * marking[3],
+                    ))
+                } else {
+                    srgb(theme.lane_surface(LaneClass::of(&segment.lane_type)))
+                };
+                triangles.push(Triangle {
+                    corners: [a.0, b.0, c.0],
+                    heights: [a.1, b.1, c.1],
+                    color,
+                });
+            }
+        }
+    }
+    triangles
+}
+
+// The zoom levels drawn without `--zoom`.
+pub fn default_zoom(network: &RoadNetwork) -> RangeInclusive<u8> {
+    let pyramid = Pyramid::of(network);
+    let (min, max) = network
+        .segments
+        .iter()
+        .flat_map(|segment| [segment.start_pos, segment.end_pos])
+        .map(|p| pyramid.project(network, p))
+        .fold(
+            (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+            |(min, max), p| (min.min(p), max.max(p)),
+        );
+    let extent = (max - min).max_element();
+    let first = if extent > 0.0 {
+        ((1.0 / extent).log2().floor().max(0.0) as u8).min(MAX_ZOOM)
+    } else {
+        0
+    };
+    first..=(first + DEFAULT_LEVELS - 1).min(MAX_ZOOM)
+}
+
+// Draws the tiles of the given zoom levels into `dir` and returns how many
+// were written.
+pub fn render_tiles(
+    network: &RoadNetwork,
+    dir: &Path,
+    zoom: RangeInclusive<u8>,
+    theme: &Theme,
+) -> Result<usize, String> {
+    if *zoom.end() > MAX_ZOOM {
+        return Err(format!("zoom levels go up to {MAX_ZOOM}"));
+    }
+    let pyramid = Pyramid::of(network);
+    let triangles = triangles(network, &pyramid, theme);
+    let mut written = 0;
+    for level in zoom {
+        let scale = (TILE_SIZE as f64) * (1u64 << level) as f64;
+        let tiles = 1u64 << level;
+        // The triangles touching each tile.
+        let mut bins: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            let (min, max) = bounds(triangle, scale);
+            let tile = |pixel: f64| {
+                (pixel / TILE_SIZE as f64)
+                    .floor()
+                    .clamp(0.0, (tiles - 1) as f64) as u64
+            };
+            if max.x < 0.0 || max.y < 0.0 || min.x > scale || min.y > scale {
+                continue;
+            }
+            for x in tile(min.x)..=tile(max.x) {
+                for y in tile(min.y)..=tile(max.y) {
+                    bins.entry((x, y)).or_default().push(index);
+                }
+            }
+        }
+        for ((x, y), indices) in bins {
+            let offset = DVec2::new(x as f64, y as f64) * TILE_SIZE as f64;
+            let pixels = draw_tile(indices.iter().map(|&i| &triangles[i]), scale, offset);
+            // Long triangles pass over tiles they leave empty.
+            if pixels.iter().all(|&value| value == 0) {
+                continue;
+            }
+            let path = dir.join(format!("{level}/{x}/{y}.png"));
+            write_png(&path, pixels)?;
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+// The bounding box of a triangle, in pixels at a scale.
+fn bounds(triangle: &Triangle, scale: f64) -> (DVec2, DVec2) {
+    triangle.corners.iter().fold(
+        (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+        |(min, max), corner| (min.min(*corner * scale), max.max(*corner * scale)),
+    )
+}
+
+// Fills every pixel of a tile that a triangle touches, the highest triangle
+// winning, and returns the tile's RGBA pixels.
+fn draw_tile<'a>(
+    triangles: impl Iterator<Item = &'a Triangle>,
+    scale: f64,
+    offset: DVec2,
+) -> Vec<u8> {
+    let size = TILE_SIZE as usize;
+    let mut pixels = vec![0; size * size * 4];
+    let mut heights = vec![f64::NEG_INFINITY; size * size];
+    for triangle in triangles {
+        let [a, b, c] = triangle.corners.map(|corner| corner * scale - offset);
+        let area = (b - a).perp_dot(c - a);
+        if area == 0.0 {
+            continue;
+        }
+        let (min, max) = (a.min(b).min(c), a.max(b).max(c));
+        let columns = (min.x.floor().max(0.0) as usize)..(max.x.ceil().min(size as f64) as usize);
+        let rows = (min.y.floor().max(0.0) as usize)..(max.y.ceil().min(size as f64) as usize);
+        // Edge functions, positive inside; each is widened by half a pixel
+        // along both axes so that any pixel the triangle touches passes.
+        let edges = [(b, c), (c, a), (a, b)].map(|(from, to)| {
+            let edge = (to - from) * area.signum();
+            let slack = (edge.x.abs() + edge.y.abs()) / 2.0;
+            (from, edge, slack)
+        });
+        for row in rows {
+            for column in columns.clone() {
+                let center = DVec2::new(column as f64 + 0.5, row as f64 + 0.5);
+                let weights = edges.map(|(from, edge, _)| edge.perp_dot(center - from));
+                if edges
+                    .iter()
+                    .zip(weights)
+                    .any(|((_, _, slack), weight)| weight < -slack)
+                {
+                    continue;
+                }
+                let [wa, wb, wc] = weights.map(|w| w / area.abs());
+                let height =
+                    wa * triangle.heights[0] + wb * triangle.heights[1] + wc * triangle.heights[2];
+                let at = row * size + column;
+                if height < heights[at] {
+                    continue;
+                }
+                heights[at] = height;
+                pixels[at * 4..at * 4 + 4].copy_from_slice(&triangle.color);
+            }
+        }
+    }
+    pixels
+}
+
+fn write_png(path: &Path, pixels: Vec<u8>) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    }
+    let image = Image::new(
+        Extent3d {
+            width: TILE_SIZE,
+            height: TILE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        pixels,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image
+        .try_into_dynamic()
+        .map_err(|e| format!("{}: {e}", path.display()))?
+        .save(path)
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare;
+    use crate::geo::GeoReference;
+    use crate::sample_maps::{load, temp_dir};
+
+    #[test]
+    fn tile_pyramid() {
+        let network = load("straight.xodr");
+        assert_eq!(default_zoom(&network), 0..=5);
+        let dir = temp_dir("tile_pyramid");
+        let theme = Theme::default();
+        let written = render_tiles(&network, &dir, 0..=2, &theme).unwrap();
+        // The road spans the map's bounding square from west to east, across
+        // the middle: the two middle rows of tiles at zoom 1 and 2, and no others.
+        assert_eq!(written, 1 + 4 + 8);
+        let tile = compare::read_snapshot(&dir.join("0/0/0.png")).unwrap();
+        let pixel = |x: usize, y: usize| {
+            let at = (y * TILE_SIZE as usize + x) * 4;
+            tile.data[at..at + 4].to_vec()
+        };
+        // The middle of the bounding square is on the first right lane; the
+        // corners are clear.
+        assert_eq!(pixel(128, 128), theme.surface.as_rgba_u8().to_vec());
+        assert_eq!(pixel(0, 0), vec![0; 4]);
+        assert!(dir.join("2/3/2.png").exists() && !dir.join("2/3/0.png").exists());
+        assert!(render_tiles(&network, &dir, 0..=30, &theme).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Georeferenced, a 100 m road at 49° north fits into a Web Mercator
+        // tile from zoom 18 on, where a tile spans about 100 m.
+        let mut network = network;
+        network.geo = Some(GeoReference::parse("+proj=tmerc +lat_0=49 +lon_0=8").unwrap());
+        assert_eq!(default_zoom(&network), 18..=22);
+    }
+}
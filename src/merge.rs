@@ -0,0 +1,240 @@
+// Merging two maps into one, for tile-based map production.
+//
+// The second map is rotated and shifted (in the map frame, x east, y north)
+// and its roads and junctions are renumbered past the first map's highest
+// IDs, in the junctions' elements as well. Road ends of the two maps that
+// meet within a tolerance are then linked to each other, so that tiles
+// produced separately become one connected network.
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::failures::LoadFailure;
+use crate::signals::Signal;
+use crate::xodr::{self, ElementXml};
+use crate::{ContactPoint, PlanSample, RoadInfo, RoadLink, RoadNetwork, RoadSegment};
+
+// How the second map is placed relative to the first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    // Shift in meters, applied after the rotation.
+    pub offset: DVec2,
+    // Counter-clockwise rotation in radians about the map origin.
+    pub rotation: f64,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            offset: DVec2::ZERO,
+            rotation: 0.0,
+        }
+    }
+}
+
+impl Placement {
+    // Applies the placement to a viewer-space position (north is -z).
+    fn apply(&self, p: DVec3) -> DVec3 {
+        let map = DVec2::new(p.x, -p.z);
+        let moved = DVec2::from_angle(self.rotation).rotate(map) + self.offset;
+        DVec3::new(moved.x, p.y, -moved.y)
+    }
+}
+
+// Merges `b`, placed as given, into `a`. Returns the merged network and the
+// number of links created between the two.
+pub fn merge(
+    a: &RoadNetwork,
+    b: &RoadNetwork,
+    placement: Placement,
+    tolerance: f64,
+) -> (RoadNetwork, usize) {
+    let id_offset = a.segments.iter().map(|s| s.road_id).max().unwrap_or(0);
+    let junction_offset = a
+        .roads
+        .values()
+        .filter_map(|info| info.junction)
+        .chain(a.junctions.keys().copied())
+        .max()
+        .map_or(0, |id| id + 1);
+
+    // The second map's own references, renumbered the same way.
+    let road = |id: &str| id.parse::<u32>().ok().map(|id| id + id_offset);
+    let junction = |id: &str| id.parse::<u32>().ok().map(|id| id + junction_offset);
+    let moved = placement != Placement::default();
+
+    let mut merged = a.clone();
+    let first_b = merged.segments.len();
+    merged.segments.extend(b.segments.iter().map(|segment| {
+        let place = |points: &[DVec3]| points.iter().map(|p| placement.apply(*p)).collect();
+        RoadSegment {
+            start_pos: placement.apply(segment.start_pos),
+            end_pos: placement.apply(segment.end_pos),
+            left_side: place(&segment.left_side),
+            right_side: place(&segment.right_side),
+            road_id: segment.road_id + id_offset,
+            ..segment.clone()
+        }
+    }));
+    merged.signals.extend(b.signals.iter().map(|signal| Signal {
+        road_id: signal.road_id + id_offset,
+        position: placement.apply(signal.position),
+        ..signal.clone()
+    }));
+    merged
+        .failures
+        .extend(b.failures.iter().map(|failure| LoadFailure {
+            position: failure.position.map(|p| placement.apply(p)),
+            ..failure.clone()
+        }));
+    merged.roads.extend(b.roads.iter().map(|(road_id, info)| {
+        let plan_view = info
+            .plan_view
+            .iter()
+            .map(|sample| PlanSample {
+                heading: sample.heading + placement.rotation,
+                ..*sample
+            })
+            .collect();
+        (
+            road_id + id_offset,
+            RoadInfo {
+                name: info.name.clone(),
+                plan_view,
+                reference: info
+                    .reference
+                    .as_ref()
+                    .map(|line| line.moved(placement.rotation, placement.offset.extend(0.0))),
+                junction: info.junction.map(|id| id + junction_offset),
+                // Moved roads no longer match their element as it was read.
+                xml: match info.xml.text() {
+                    Some(xml) if !moved => xodr::renumber(&xml, road, junction).into(),
+                    _ => ElementXml::None,
+                },
+            },
+        )
+    }));
+    merged.junctions.extend(b.junctions.iter().map(|(id, xml)| {
+        let xml = xodr::renumber(&xml.text().unwrap_or_default(), road, junction);
+        (id + junction_offset, xml.into())
+    }));
+    merged.links.extend(b.links.iter().map(|link| RoadLink {
+        road_id: link.road_id + id_offset,
+        other_road_id: link.other_road_id + id_offset,
+        ..*link
+    }));
+
+    let ends_a = road_ends(&merged.segments[..first_b]);
+    let ends_b = road_ends(&merged.segments[first_b..]);
+    let mut created = 0;
+    for &(road_a, contact_a, point_a) in &ends_a {
+        for &(road_b, contact_b, point_b) in &ends_b {
+            if point_a.distance(point_b) > tolerance {
+                continue;
+            }
+            // Each road end takes at most one road link.
+            let taken = |road: u32, contact: ContactPoint| {
+                merged
+                    .links
+                    .iter()
+                    .any(|l| l.road_id == road && l.contact == contact)
+            };
+            if taken(road_a, contact_a) || taken(road_b, contact_b) {
+                continue;
+            }
+            merged.links.push(RoadLink {
+                road_id: road_a,
+                contact: contact_a,
+                other_road_id: road_b,
+                other_contact: contact_b,
+            });
+            merged.links.push(RoadLink {
+                road_id: road_b,
+                contact: contact_b,
+                other_road_id: road_a,
+                other_contact: contact_a,
+            });
+            created += 1;
+        }
+    }
+
+    (merged, created)
+}
+
+// Finds where each road starts and ends: the middle of the cross-section at
+// the start of its first lane section and at the end of its last one.
+fn road_ends(segments: &[RoadSegment]) -> Vec<(u32, ContactPoint, DVec3)> {
+    let mut roads: Vec<u32> = segments.iter().map(|s| s.road_id).collect();
+    roads.sort_unstable();
+    roads.dedup();
+
+    let mut ends = Vec::new();
+    for road in roads {
+        let lanes: Vec<&RoadSegment> = segments.iter().filter(|s| s.road_id == road).collect();
+        let first = lanes.iter().map(|s| s.lane_section_id).min().unwrap();
+        let last = lanes.iter().map(|s| s.lane_section_id).max().unwrap();
+        let middle = |section: u32, pick: fn(&[DVec3]) -> Option<&DVec3>| {
+            let points: Vec<DVec3> = lanes
+                .iter()
+                .filter(|s| s.lane_section_id == section)
+                .flat_map(|s| [pick(&s.left_side), pick(&s.right_side)])
+                .flatten()
+                .copied()
+                .collect();
+            points.iter().sum::<DVec3>() / points.len().max(1) as f64
+        };
+        ends.push((road, ContactPoint::Start, middle(first, <[DVec3]>::first)));
+        ends.push((road, ContactPoint::End, middle(last, <[DVec3]>::last)));
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn junctions_are_renumbered_with_their_roads() {
+        let a = load("junction.xodr");
+        let (merged, _) = merge(&a, &a, Placement::default(), 0.0);
+        // Roads 1 to 3 become 4 to 6, junction 100 becomes 201.
+        assert_eq!(merged.roads[&6].junction, Some(201));
+        let junction = merged.junctions[&201].text().unwrap();
+        assert!(junction.contains("id=\"201\""));
+        assert!(junction.contains("incomingRoad=\"4\" connectingRoad=\"6\""));
+        assert!(!junction.contains("incomingRoad=\"1\""));
+        let road = merged.roads[&6].xml.text().unwrap();
+        assert!(road.contains("id=\"6\" junction=\"201\""));
+        assert!(road.contains("elementId=\"4\""));
+        let xml = |road: u32| merged.roads[&road].xml.text().unwrap();
+        assert!(xml(1).contains("elementId=\"100\""));
+        assert!(xml(4).contains("elementId=\"201\""));
+        assert_eq!(merged.junctions[&100], a.junctions[&100]);
+
+        // Moved roads keep no element.
+        let placement = Placement {
+            offset: DVec2::new(200.0, 0.0),
+            rotation: 0.0,
+        };
+        let (moved, _) = merge(&a, &a, placement, 0.0);
+        assert!(moved.roads[&6].xml.is_empty());
+        assert!(moved.junctions[&201]
+            .text()
+            .unwrap()
+            .contains("connectingRoad=\"6\""));
+    }
+
+    #[test]
+    fn moved_roads_keep_their_reference_line_records() {
+        let a = load("straight.xodr");
+        let placement = Placement {
+            offset: DVec2::new(200.0, 50.0),
+            rotation: 0.5,
+        };
+        let (merged, _) = merge(&a, &a, placement, 0.0);
+        let xml = xodr::to_xml(&merged);
+        let moved = &xml[xml.find("id=\"2\"").unwrap()..];
+        assert_eq!(moved.matches("<geometry ").count(), 1);
+        assert!(moved.contains("x=\"200\" y=\"50\" hdg=\"0.5\" length=\"100\"><line/>"));
+    }
+}
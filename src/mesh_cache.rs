@@ -0,0 +1,334 @@
+// Cache of tessellated road meshes on disk.
+//
+// Tessellating and simplifying the roads of a big map takes a long time and
+// comes out the same every time the map is opened. So the surface and
+// marking meshes of every tile are written to a cache directory once built,
+// and read back from there when the tile is loaded again, in this session
+// or a later one. A map's cache is keyed by a hash of the map file's
+// contents together with the load transform, the tile size and the
+// simplification tolerance, so changing any of them starts a fresh cache.
+// The hash is FNV-1a over fixed byte layouts, so that the key of a map stays
+// the same from one build of the viewer to the next.
+// Files are written on a background thread so that streaming does not
+// stall. Tiles with roads edited in the session are tessellated afresh and
+// not cached. The demo network is not cached.
+//
+// Caches live in `road-visualizer/meshes` under the user's cache directory,
+// one directory per key; deleting it clears them.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::byte_reader::Reader;
+use crate::edit::NetworkChanged;
+use crate::reload::MapSource;
+use crate::tessellation::TriangleMesh;
+use crate::tiles::TileSettings;
+use crate::transform::{LoadTransform, UpAxis};
+use crate::RoadNetwork;
+
+// Bumped whenever the file layout, the tessellation or the key hash changes.
+const FORMAT: u32 = 5;
+const MAGIC: &[u8; 4] = b"RSMC";
+
+// The cached meshes of one road in one tile, built around the road's first
+// point.
+#[derive(Debug, Clone, Default)]
+pub struct RoadMeshes {
+    pub road_id: u32,
+    pub anchor: DVec3,
+    pub surface: TriangleMesh,
+    pub markings: TriangleMesh,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MeshCache {
+    // The directory of this map's cache; none if it is not cached.
+    dir: Option<PathBuf>,
+    // Hash of the map file the cache was opened for.
+    file_hash: u64,
+    // Roads edited since the map was loaded.
+    edited: HashSet<u32>,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(base.join("road-visualizer").join("meshes"))
+}
+
+// 64-bit FNV-1a, continuing from `hash`; start from `FNV_OFFSET`.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    let bytes = std::fs::read(path).ok()?;
+    Some(fnv(FNV_OFFSET, &bytes))
+}
+
+// The key of a map's cache: everything the tiles' meshes depend on, hashed
+// as little-endian bytes so that it is the same on every build and platform.
+fn cache_key(file_hash: u64, transform: &LoadTransform, settings: &TileSettings) -> u64 {
+    let mut key = fnv(FNV_OFFSET, &FORMAT.to_le_bytes());
+    key = fnv(key, &file_hash.to_le_bytes());
+    let up: u8 = match transform.up {
+        UpAxis::Z => 0,
+        UpAxis::Y => 1,
+    };
+    key = fnv(key, &[up]);
+    key = fnv(key, &transform.rotation.to_le_bytes());
+    for v in transform.offset.to_array() {
+        key = fnv(key, &v.to_le_bytes());
+    }
+    key = fnv(key, &settings.tile_size.to_le_bytes());
+    // No tolerance is told apart from every tolerance by its NaN bits.
+    fnv(key, &settings.max_error.unwrap_or(f64::NAN).to_le_bytes())
+}
+
+impl MeshCache {
+    pub fn open(map: Option<&Path>, transform: &LoadTransform, settings: &TileSettings) -> Self {
+        let Some((map, file_hash)) = map.and_then(|map| Some((map, hash_file(map)?))) else {
+            return Self::default();
+        };
+        let key = cache_key(file_hash, transform, settings);
+        let stem = map.file_stem().and_then(|s| s.to_str()).unwrap_or("map");
+        Self {
+            dir: cache_dir().map(|dir| dir.join(format!("{stem}-{key:016x}"))),
+            file_hash,
+            edited: HashSet::new(),
+        }
+    }
+
+    fn tile_file(&self, tile: IVec2) -> Option<PathBuf> {
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{}_{}.bin", tile.x, tile.y)),
+        )
+    }
+
+    // The cached meshes of a tile holding these roads, in this order.
+    pub fn read(&self, tile: IVec2, roads: &[u32]) -> Option<Vec<RoadMeshes>> {
+        if roads.iter().any(|road| self.edited.contains(road)) {
+            return None;
+        }
+        let bytes = std::fs::read(self.tile_file(tile)?).ok()?;
+        let meshes = decode(&bytes).ok()?;
+        let ids = meshes.iter().map(|road| road.road_id);
+        ids.eq(roads.iter().copied()).then_some(meshes)
+    }
+
+    // Writes the meshes of a tile in the background.
+    pub fn write(&self, tile: IVec2, meshes: &[RoadMeshes]) {
+        if meshes
+            .iter()
+            .any(|road| self.edited.contains(&road.road_id))
+        {
+            return;
+        }
+        let Some(file) = self.tile_file(tile) else {
+            return;
+        };
+        let bytes = encode(meshes);
+        std::thread::spawn(move || {
+            // Written aside and renamed, so that a reader never sees half a
+            // file.
+            let part = file.with_extension("part");
+            let written = file
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&part, bytes))
+                .and_then(|()| std::fs::rename(&part, &file));
+            if let Err(e) = written {
+                warn!("mesh cache {}: {e}", file.display());
+            }
+        });
+    }
+}
+
+// After a reload the cache follows the file; after an edit the edited
+// roads are left out of it.
+pub fn follow_changes(
+    mut cache: ResMut<MeshCache>,
+    mut changed: EventReader<NetworkChanged>,
+    source: Res<MapSource>,
+    network: Res<RoadNetwork>,
+    settings: Res<TileSettings>,
+) {
+    let roads: Vec<u32> = changed
+        .read()
+        .flat_map(|event| event.changed.iter().copied())
+        .collect();
+    let Some(path) = &source.path else {
+        return;
+    };
+    if hash_file(path).is_some_and(|hash| hash != cache.file_hash) {
+        *cache = MeshCache::open(Some(path), &network.transform, &settings);
+    } else {
+        cache.edited.extend(roads);
+    }
+}
+
+fn encode(meshes: &[RoadMeshes]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT.to_le_bytes());
+    out.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+    for road in meshes {
+        out.extend_from_slice(&road.road_id.to_le_bytes());
+        for v in road.anchor.to_array() {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        for mesh in [&road.surface, &road.markings] {
+            out.extend_from_slice(&(mesh.positions.len() as u32).to_le_bytes());
+            for v in mesh.positions.iter().chain(&mesh.normals) {
+                for c in v.to_array() {
+                    out.extend_from_slice(&c.to_le_bytes());
+                }
+            }
+            out.extend_from_slice(&(mesh.indices.len() as u32).to_le_bytes());
+            for i in &mesh.indices {
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+            out.extend_from_slice(&(mesh.colors.len() as u32).to_le_bytes());
+            for c in mesh.colors.iter().flatten() {
+                out.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+    }
+    out
+}
+
+// The records of a cache file, read with the shared byte reader.
+impl Reader<'_> {
+    fn vec3s(&mut self, count: usize) -> Result<Vec<Vec3>, String> {
+        (0..count)
+            .map(|_| Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?)))
+            .collect()
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<RoadMeshes>, String> {
+    let mut reader = Reader::new(bytes, "mesh cache file");
+    if &reader.take::<4>()? != MAGIC || reader.u32()? != FORMAT {
+        return Err("not a mesh cache file of this version".to_string());
+    }
+    let roads = reader.len()?;
+    let mut meshes = Vec::new();
+    for _ in 0..roads {
+        let road_id = reader.u32()?;
+        let anchor = DVec3::new(reader.f64()?, reader.f64()?, reader.f64()?);
+        let mut read_mesh = || {
+            let vertices = reader.len()?;
+            let positions = reader.vec3s(vertices)?;
+            let normals = reader.vec3s(vertices)?;
+            let indices = (0..reader.len()?)
+                .map(|_| reader.u32())
+                .collect::<Result<Vec<u32>, String>>()?;
+            let colors = (0..reader.len()?)
+                .map(|_| Ok([reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?]))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok::<_, String>(TriangleMesh {
+                positions,
+                normals,
+                indices,
+                colors,
+            })
+        };
+        let surface = read_mesh()?;
+        let markings = read_mesh()?;
+        meshes.push(RoadMeshes {
+            road_id,
+            anchor,
+            surface,
+            markings,
+        });
+    }
+    if !reader.rest().is_empty() {
+        return Err("mesh cache file has trailing data".to_string());
+    }
+    Ok(meshes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_fnv_1a() {
+        // Test vectors of the reference implementation.
+        assert_eq!(fnv(FNV_OFFSET, b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(FNV_OFFSET, b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(FNV_OFFSET, b"foobar"), 0x8594_4171_f739_67e8);
+        assert_eq!(
+            fnv(fnv(FNV_OFFSET, b"foo"), b"bar"),
+            fnv(FNV_OFFSET, b"foobar")
+        );
+    }
+
+    #[test]
+    fn keys_tell_transforms_apart() {
+        let settings = TileSettings::default();
+        let key = |transform: LoadTransform| cache_key(7, &transform, &settings);
+        let identity = LoadTransform::default();
+        assert_eq!(key(identity), key(identity));
+        let transforms = [
+            identity,
+            LoadTransform {
+                up: UpAxis::Y,
+                ..identity
+            },
+            LoadTransform {
+                rotation: 0.5,
+                ..identity
+            },
+            LoadTransform {
+                offset: DVec3::new(0.0, 0.0, 1.0),
+                ..identity
+            },
+            LoadTransform {
+                offset: DVec3::new(1.0, 0.0, 0.0),
+                ..identity
+            },
+        ];
+        for (i, a) in transforms.iter().enumerate() {
+            for b in &transforms[i + 1..] {
+                assert_ne!(key(*a), key(*b), "{a:?} and {b:?}");
+            }
+        }
+        assert_ne!(cache_key(8, &identity, &settings), key(identity));
+    }
+
+    #[test]
+    fn tiles_are_read_back_as_written() {
+        let road = RoadMeshes {
+            road_id: 4,
+            anchor: DVec3::new(1.0, 2.0, 3.0),
+            surface: TriangleMesh {
+                positions: vec![Vec3::X, Vec3::Y, Vec3::Z],
+                normals: vec![Vec3::Y; 3],
+                indices: vec![0, 1, 2],
+                colors: vec![[1.0, 0.5, 0.25, 1.0]; 3],
+            },
+            markings: TriangleMesh::default(),
+        };
+        let bytes = encode(std::slice::from_ref(&road));
+        let read = decode(&bytes).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].road_id, 4);
+        assert_eq!(read[0].anchor, road.anchor);
+        assert_eq!(read[0].surface.positions, road.surface.positions);
+        assert_eq!(read[0].surface.colors, road.surface.colors);
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
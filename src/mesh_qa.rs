@@ -0,0 +1,209 @@
+// Quality checks on the tessellated lane surfaces.
+//
+// The tessellator quietly repairs what it can: it drops triangles too small
+// to matter and turns every triangle to face up. Both hide problems in the
+// geometry it was given, which this pass digs out again by going over the
+// quads of every lane strip as the tessellator triangulates them:
+//
+// - degenerate triangles, with next to no area although the lane has width
+//   there (repeated boundary points, for instance); lanes narrowing down to
+//   nothing are fine,
+// - flipped triangles, wound against the rest of their strip, where the
+//   boundaries of a lane cross each other,
+// - self-intersecting strips, where a boundary runs backwards against the
+//   lane, typically on the inside of a curve tighter than the lane is wide.
+//
+// Consecutive quads with the same problem are reported as one stretch.
+
+use bevy::math::DVec3;
+
+use crate::tessellation::{boundaries, MIN_TRIANGLE_AREA};
+use crate::validation::{Issue, Severity};
+use crate::RoadNetwork;
+
+// Lanes narrower than this at a quad are tapering to nothing, in meters.
+const MIN_LANE_WIDTH: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Defect {
+    Degenerate,
+    Flipped,
+    SelfIntersecting,
+}
+
+impl Defect {
+    fn severity(self) -> Severity {
+        match self {
+            Defect::Degenerate => Severity::Warning,
+            Defect::Flipped | Defect::SelfIntersecting => Severity::Error,
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Defect::Degenerate => "degenerate triangles",
+            Defect::Flipped => "flipped triangles (lane boundaries cross)",
+            Defect::SelfIntersecting => "self-intersecting lane strip (boundary runs backwards)",
+        }
+    }
+}
+
+// Finds defects in the surfaces of all lanes.
+pub fn check(network: &RoadNetwork) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for segment in &network.segments {
+        let (left, right) = boundaries(segment);
+        let count = left.len().min(right.len());
+        if count < 2 {
+            continue;
+        }
+        let defects = quad_defects(&left[..count], &right[..count]);
+        let station = |quad: f64| {
+            segment.start_s + (segment.end_s - segment.start_s) * quad / (count - 1) as f64
+        };
+        for (defect, first, last) in stretches(&defects) {
+            let middle = (left[first] + right[first] + left[first + 1] + right[first + 1]) / 4.0;
+            let (start, end) = (station(first as f64), station(last as f64 + 1.0));
+            issues.push(Issue {
+                check: "mesh",
+                severity: defect.severity(),
+                road_id: segment.road_id,
+                lane_id: Some(segment.lane_id),
+                s: start,
+                position: middle,
+                message: format!(
+                    "{} from s={start:.1} to s={end:.1} in lane section {}",
+                    defect.describe(),
+                    segment.lane_section_id
+                ),
+            });
+        }
+    }
+    issues
+}
+
+// The defects of every quad of a strip, the worst one per quad.
+fn quad_defects(left: &[DVec3], right: &[DVec3]) -> Vec<Option<Defect>> {
+    // Which way triangles are wound in plan, as the tessellator splits each
+    // quad: (l0, r0, l1) and (r0, r1, l1).
+    let winding = |[a, b, c]: [DVec3; 3]| (b - a).cross(c - a);
+    let triangles: Vec<[[DVec3; 3]; 2]> = (1..left.len())
+        .map(|i| {
+            [
+                [left[i - 1], right[i - 1], left[i]],
+                [right[i - 1], right[i], left[i]],
+            ]
+        })
+        .collect();
+
+    // The strip's own winding is the one most of its triangles share.
+    let upward = triangles
+        .iter()
+        .flatten()
+        .map(|&triangle| winding(triangle).y)
+        .filter(|y| y.abs() > f64::EPSILON)
+        .fold(0i64, |balance, y| balance + if y > 0.0 { 1 } else { -1 });
+    let expected = if upward >= 0 { 1.0 } else { -1.0 };
+
+    triangles
+        .iter()
+        .enumerate()
+        .map(|(i, quad)| {
+            let (l0, l1, r0, r1) = (left[i], left[i + 1], right[i], right[i + 1]);
+            let along = (l1 + r1 - l0 - r0) / 2.0;
+            let backwards = (l1 - l0).dot(along) < 0.0 || (r1 - r0).dot(along) < 0.0;
+            if backwards {
+                return Some(Defect::SelfIntersecting);
+            }
+            let flipped = quad
+                .iter()
+                .any(|&triangle| winding(triangle).y * expected < -f64::EPSILON);
+            if flipped {
+                return Some(Defect::Flipped);
+            }
+            let wide = l0.distance(r0) > MIN_LANE_WIDTH && l1.distance(r1) > MIN_LANE_WIDTH;
+            let degenerate = quad
+                .iter()
+                .any(|&triangle| winding(triangle).length() / 2.0 < f64::from(MIN_TRIANGLE_AREA));
+            (wide && degenerate).then_some(Defect::Degenerate)
+        })
+        .collect()
+}
+
+// Groups runs of quads with the same defect into (defect, first, last).
+fn stretches(defects: &[Option<Defect>]) -> Vec<(Defect, usize, usize)> {
+    let mut out: Vec<(Defect, usize, usize)> = Vec::new();
+    for (i, defect) in defects.iter().enumerate() {
+        let Some(defect) = *defect else {
+            continue;
+        };
+        match out.last_mut() {
+            Some((last, _, end)) if *last == defect && *end + 1 == i => *end = i,
+            _ => out.push((defect, i, i)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    // A straight strip 3.5 m wide with boundary points every meter.
+    fn strip(points: usize) -> (Vec<DVec3>, Vec<DVec3>) {
+        (0..points)
+            .map(|i| {
+                (
+                    DVec3::new(i as f64, 0.0, 0.0),
+                    DVec3::new(i as f64, 0.0, 3.5),
+                )
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn clean_surfaces_pass() {
+        let (left, right) = strip(10);
+        assert!(quad_defects(&left, &right).iter().all(Option::is_none));
+        for name in ["straight.xodr", "curve.xodr", "junction.xodr"] {
+            let issues = check(&load(name));
+            assert!(issues.is_empty(), "{name}: {issues:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_are_flagged() {
+        let mut network = load("straight.xodr");
+        let lane = &mut network.segments[0];
+        // A repeated pair of boundary points leaves a quad with no area.
+        for side in [&mut lane.left_side, &mut lane.right_side] {
+            side[3] = side[2];
+        }
+        let issues = check(&network);
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert!(
+            issues[0]
+                .message
+                .starts_with("degenerate triangles from s="),
+            "{}",
+            issues[0].message
+        );
+    }
+
+    #[test]
+    fn flipped_triangles_are_flagged() {
+        // The boundaries swap sides at the sixth point and back, so the two
+        // quads next to it face down.
+        let (mut left, mut right) = strip(10);
+        std::mem::swap(&mut left[5], &mut right[5]);
+        let defects = quad_defects(&left, &right);
+        let flipped: Vec<usize> = (0..defects.len())
+            .filter(|&i| defects[i] == Some(Defect::Flipped))
+            .collect();
+        assert_eq!(flipped, [4, 5]);
+        assert_eq!(stretches(&defects), [(Defect::Flipped, 4, 5)]);
+        assert_eq!(Defect::Flipped.severity(), Severity::Error);
+    }
+}
@@ -0,0 +1,334 @@
+// Night mode: the map after dark, lit by its street lights.
+//
+// `night on` in the console, or `view --night`, dims the sun to moonlight,
+// darkens the sky and the ambient light and opens the camera's exposure, and
+// every street lamp of the map (objects of type `streetLamp` or
+// `streetLight`, one per `<repeat>` copy) shines a spot light down from its
+// head, whose material glows. This shows at a glance where a map's lighting
+// objects stand and which stretches they leave dark. `night off` brings the
+// day back as it was.
+//
+// Lights cost rendering time, so there is a budget of them, `night budget
+// <n>` (DEFAULT_BUDGET if not set). A map with more lamps than that has
+// them gathered into clusters on a grid, whose cells grow until the budget
+// is met, and each cluster shines as one brighter, wider light from the
+// middle of its lamps. An environment image (see `environment`) is left as
+// it is; `environment off` gives the dark sky.
+
+use std::collections::BTreeMap;
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::camera::Exposure;
+
+use crate::asset_packs::stands;
+use crate::edit::NetworkChanged;
+use crate::origin::WorldPosition;
+use crate::placement::LampHeads;
+use crate::signals::{Signal, SignalKind};
+use crate::{MainCamera, RoadNetwork};
+
+// Number of lights if not set.
+pub const DEFAULT_BUDGET: usize = 128;
+
+// Height of lamps whose object gives none, in meters.
+const DEFAULT_LAMP_HEIGHT: f64 = 6.0;
+
+// Luminous power of a lamp, in lumens, and how far its light reaches, in
+// meters.
+const LAMP_LUMENS: f32 = 12_000.0;
+const LAMP_RANGE: f32 = 30.0;
+
+// Half the angle of a lamp's cone of light, in radians.
+const LAMP_ANGLE: f32 = 1.1;
+
+// Edge length of the smallest cluster cell, in meters.
+const MIN_CELL: f64 = 10.0;
+
+// Light from the sky, in lux, and the camera's exposure at night.
+const MOONLIGHT: f32 = 0.3;
+const NIGHT_EV100: f32 = 2.0;
+const NIGHT_AMBIENT: f32 = 0.5;
+
+#[derive(Resource, Debug, Clone)]
+pub struct Night {
+    pub on: bool,
+    pub budget: usize,
+    // The lighting of the day, kept while it is night.
+    day: Option<Daylight>,
+}
+
+impl Default for Night {
+    fn default() -> Self {
+        Self {
+            on: false,
+            budget: DEFAULT_BUDGET,
+            day: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Daylight {
+    sun: Vec<(f32, Color)>,
+    ambient: AmbientLight,
+    sky: Color,
+    exposure: f32,
+}
+
+pub fn is_street_light(object: &Signal) -> bool {
+    let kind = object.type_code.trim();
+    object.kind == SignalKind::Object
+        && (kind.eq_ignore_ascii_case("streetLamp") || kind.eq_ignore_ascii_case("streetLight"))
+}
+
+// The heads of the street lamps of a map.
+pub fn lamps(network: &RoadNetwork) -> Vec<DVec3> {
+    network
+        .signals
+        .iter()
+        .filter(|object| is_street_light(object))
+        .flat_map(|object| stands(network, object))
+        .map(|(at, height, _)| {
+            let height = if height > 0.0 {
+                height
+            } else {
+                DEFAULT_LAMP_HEIGHT
+            };
+            at + DVec3::Y * height
+        })
+        .collect()
+}
+
+// Lamps that shine as one light.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LampCluster {
+    // The middle of the lamps' heads.
+    pub at: DVec3,
+    pub lamps: usize,
+    // The farthest a lamp stands from the middle, across the ground.
+    pub spread: f64,
+}
+
+// Gathers lamps into at most `budget` clusters, one per lamp if the budget
+// allows.
+pub fn cluster(lamps: &[DVec3], budget: usize) -> Vec<LampCluster> {
+    if budget == 0 {
+        return Vec::new();
+    }
+    if lamps.len() <= budget {
+        return lamps
+            .iter()
+            .map(|&at| LampCluster {
+                at,
+                lamps: 1,
+                spread: 0.0,
+            })
+            .collect();
+    }
+    let mut size = MIN_CELL;
+    loop {
+        let mut cells: BTreeMap<(i64, i64), Vec<DVec3>> = BTreeMap::new();
+        for &lamp in lamps {
+            let cell = (
+                (lamp.x / size).floor() as i64,
+                (lamp.z / size).floor() as i64,
+            );
+            cells.entry(cell).or_default().push(lamp);
+        }
+        if cells.len() <= budget {
+            return cells
+                .into_values()
+                .map(|members| {
+                    let at = members.iter().sum::<DVec3>() / members.len() as f64;
+                    let spread = members
+                        .iter()
+                        .map(|lamp| DVec3::new(lamp.x - at.x, 0.0, lamp.z - at.z).length())
+                        .fold(0.0, f64::max);
+                    LampCluster {
+                        at,
+                        lamps: members.len(),
+                        spread,
+                    }
+                })
+                .collect();
+        }
+        size *= 2.0;
+    }
+}
+
+// `night` in the console: turns night mode on or off, or sets its budget.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut night = world.resource_mut::<Night>();
+    match args {
+        [] => Ok(format!(
+            "night {}, up to {} lights",
+            if night.on { "on" } else { "off" },
+            night.budget
+        )),
+        ["on"] => {
+            night.on = true;
+            Ok(String::new())
+        }
+        ["off"] => {
+            night.on = false;
+            Ok(String::new())
+        }
+        ["budget", value] => {
+            night.budget = value
+                .parse()
+                .map_err(|_| format!("invalid budget `{value}`"))?;
+            Ok(String::new())
+        }
+        _ => Err("expected on, off or budget <lights>".to_string()),
+    }
+}
+
+// Marks the light of a street lamp or cluster.
+#[derive(Component)]
+struct StreetLight;
+
+pub struct NightPlugin;
+
+impl Plugin for NightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Night>().add_systems(
+            Update,
+            (
+                switch_lighting.run_if(resource_changed::<Night>),
+                (despawn_lights, spawn_lights)
+                    .chain()
+                    .run_if(on_event::<NetworkChanged>().or_else(resource_changed::<Night>)),
+            ),
+        );
+    }
+}
+
+// Dims the scene for the night, keeping the day's lighting, or puts it back.
+fn switch_lighting(
+    mut night: ResMut<Night>,
+    mut suns: Query<&mut DirectionalLight>,
+    mut ambient: ResMut<AmbientLight>,
+    mut sky: ResMut<ClearColor>,
+    mut cameras: Query<&mut Exposure, With<MainCamera>>,
+    heads: Res<LampHeads>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Keeping the day is no change to the mode, which would respawn the
+    // lights.
+    let night = night.bypass_change_detection();
+    if night.on == night.day.is_some() {
+        return;
+    }
+    let glow = if night.on {
+        night.day = Some(Daylight {
+            sun: suns
+                .iter()
+                .map(|sun| (sun.illuminance, sun.color))
+                .collect(),
+            ambient: ambient.clone(),
+            sky: sky.0,
+            exposure: cameras
+                .iter()
+                .next()
+                .map_or(Exposure::default().ev100, |e| e.ev100),
+        });
+        for mut sun in &mut suns {
+            sun.illuminance = MOONLIGHT;
+            sun.color = Color::rgb(0.6, 0.7, 1.0);
+        }
+        *ambient = AmbientLight {
+            color: Color::rgb(0.4, 0.5, 0.8),
+            brightness: NIGHT_AMBIENT,
+        };
+        sky.0 = Color::rgb(0.01, 0.012, 0.03);
+        for mut exposure in &mut cameras {
+            exposure.ev100 = NIGHT_EV100;
+        }
+        Color::rgb(8.0, 7.0, 5.0)
+    } else {
+        let Some(day) = night.day.take() else {
+            return;
+        };
+        for (mut sun, (illuminance, color)) in suns.iter_mut().zip(day.sun) {
+            sun.illuminance = illuminance;
+            sun.color = color;
+        }
+        *ambient = day.ambient;
+        sky.0 = day.sky;
+        for mut exposure in &mut cameras {
+            exposure.ev100 = day.exposure;
+        }
+        Color::BLACK
+    };
+    if let Some(material) = materials.get_mut(&heads.0) {
+        material.emissive = glow;
+    }
+}
+
+fn despawn_lights(mut commands: Commands, lights: Query<Entity, With<StreetLight>>) {
+    for light in &lights {
+        commands.entity(light).despawn();
+    }
+}
+
+fn spawn_lights(mut commands: Commands, night: Res<Night>, network: Res<RoadNetwork>) {
+    if !night.on {
+        return;
+    }
+    for cluster in cluster(&lamps(&network), night.budget) {
+        let height = DEFAULT_LAMP_HEIGHT as f32;
+        let spread = cluster.spread as f32;
+        // A cluster's cone widens to take in its lamps' pools of light.
+        let reach = height * LAMP_ANGLE.tan() + spread;
+        let angle = (reach / height).atan().min(FRAC_PI_2 - 0.05);
+        commands.spawn((
+            SpotLightBundle {
+                spot_light: SpotLight {
+                    color: Color::rgb(1.0, 0.85, 0.6),
+                    intensity: LAMP_LUMENS * cluster.lamps as f32,
+                    range: LAMP_RANGE + spread,
+                    outer_angle: angle,
+                    inner_angle: angle * 0.6,
+                    shadows_enabled: false,
+                    ..default()
+                },
+                transform: Transform::default().looking_to(Vec3::NEG_Y, Vec3::Z),
+                ..default()
+            },
+            WorldPosition(cluster.at),
+            StreetLight,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::placement::{self, PlacedKind};
+    use crate::sample_maps::load;
+
+    #[test]
+    fn street_lights() {
+        let mut network = load("straight.xodr");
+        let lights = placement::generate(&network, 1, PlacedKind::Light, 10.0, 1.0, false).unwrap();
+        let trees = placement::generate(&network, 1, PlacedKind::Tree, 10.0, 1.0, true).unwrap();
+        assert!(is_street_light(&lights) && !is_street_light(&trees));
+        network.signals.extend([lights, trees]);
+        let lamps = lamps(&network);
+        assert_eq!(lamps.len(), 10);
+        assert!(lamps.iter().all(|lamp| lamp.y > 1.0));
+
+        // Within the budget every lamp is a light of its own.
+        let clusters = cluster(&lamps, 16);
+        assert_eq!(clusters.len(), 10);
+        assert!(clusters.iter().all(|c| c.lamps == 1 && c.spread == 0.0));
+        // Beyond it lamps are gathered, none lost, into brighter lights.
+        let clusters = cluster(&lamps, 4);
+        assert!(clusters.len() <= 4);
+        assert_eq!(clusters.iter().map(|c| c.lamps).sum::<usize>(), 10);
+        assert!(clusters.iter().all(|c| c.spread > 0.0));
+        assert!(cluster(&lamps, 0).is_empty());
+    }
+}
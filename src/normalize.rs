@@ -0,0 +1,279 @@
+// OpenDRIVE cleanup for cleaner diffs.
+//
+// Normalizing rewrites the lane model into a canonical form before it is
+// written out with `xodr::to_xml`, so that two versions of a map differ
+// only where their roads do. The geometry is left alone: roads keep the
+// plan view, elevation and lane offset records they were read with, and
+// the writer gives every record the same attribute order and number
+// format. What changes is
+//
+// - lane sections shorter than `DEGENERATE_LENGTH` are folded into the
+//   section before them (or after, for the first one), and consecutive
+//   sections whose lanes agree in ID, lane and road type, speed limit,
+//   material and access, and whose road marks carry on, are merged into
+//   one;
+// - road marks without a type or the same as the one before them, and
+//   duplicate access rules, are dropped;
+// - signals and road links are sorted, and lane sections renumbered
+//   from 1.
+//
+// Roads, lane sections and lanes are already written in ID order.
+
+use std::collections::BTreeMap;
+
+use bevy::math::DVec3;
+
+use crate::lane_records;
+use crate::xodr::inner_edge;
+use crate::{ContactPoint, RoadNetwork, RoadSegment};
+
+// Lane sections shorter than this, in meters, are degenerate.
+const DEGENERATE_LENGTH: f64 = 0.05;
+
+// What a normalization changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalized {
+    pub roads: usize,
+    pub degenerate: usize,
+    pub merged: usize,
+}
+
+// Whether two consecutive sections split a road for no reason.
+fn same_lanes(a: &[RoadSegment], b: &[RoadSegment]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.lane_id == b.lane_id
+                && a.lane_type == b.lane_type
+                && a.road_type == b.road_type
+                && a.speed == b.speed
+                && lane_records::continues(&a.materials, &b.materials)
+                && a.access == b.access
+                && lane_records::continues(&a.road_marks, &b.road_marks)
+                && lane_records::continues(&a.center_marks, &b.center_marks)
+        })
+}
+
+// Appends a section's lanes to the matching lanes of the one before it.
+fn append(section: &mut [RoadSegment], next: Vec<RoadSegment>) {
+    for (lane, next) in section.iter_mut().zip(next) {
+        let (length, more) = (lane.end_s - lane.start_s, next.end_s - next.start_s);
+        if length + more > 0.0 {
+            lane.width = (lane.width * length + next.width * more) / (length + more);
+        }
+        lane.road_marks = lane_records::chain(&lane.road_marks, &next.road_marks, length);
+        lane.center_marks = lane_records::chain(&lane.center_marks, &next.center_marks, length);
+        lane.materials = lane_records::chain(&lane.materials, &next.materials, length);
+        lane.left_side.extend(next.left_side.into_iter().skip(1));
+        lane.right_side.extend(next.right_side.into_iter().skip(1));
+        lane.end_s = next.end_s;
+        lane.end_pos = next.end_pos;
+    }
+}
+
+// Where a section's reference line edge goes from its start to its end.
+fn span(section: &[RoadSegment]) -> DVec3 {
+    section
+        .iter()
+        .min_by_key(|lane| lane.lane_id.abs())
+        .map(inner_edge)
+        .and_then(|edge| Some(*edge.last()? - *edge.first()?))
+        .unwrap_or(DVec3::ZERO)
+}
+
+// Carries the lanes of a section over a degenerate one next to it, so that
+// they still reach its far end: by the far point of the same lane there,
+// or shifted as the reference line is for lanes it does not have. `after`
+// tells whether the degenerate section follows the section.
+fn extend(section: &mut [RoadSegment], degenerate: &[RoadSegment], after: bool) {
+    let shift = span(degenerate);
+    for lane in section.iter_mut() {
+        let other = degenerate
+            .iter()
+            .find(|other| other.lane_id == lane.lane_id);
+        for (side, other) in [
+            (&mut lane.left_side, other.map(|o| &o.left_side)),
+            (&mut lane.right_side, other.map(|o| &o.right_side)),
+        ] {
+            let far = if after {
+                other
+                    .and_then(|o| o.last().copied())
+                    .or(side.last().map(|p| *p + shift))
+            } else {
+                other
+                    .and_then(|o| o.first().copied())
+                    .or(side.first().map(|p| *p - shift))
+            };
+            match far {
+                Some(far) if after => side.push(far),
+                Some(far) => side.insert(0, far),
+                None => {}
+            }
+        }
+        if after {
+            lane.end_s = degenerate[0].end_s;
+        } else {
+            lane.start_s = degenerate[0].start_s;
+        }
+    }
+}
+
+// Normalizes a network in place.
+pub fn normalize(network: &mut RoadNetwork) -> Normalized {
+    let mut summary = Normalized::default();
+    let mut roads: BTreeMap<u32, BTreeMap<u32, Vec<RoadSegment>>> = BTreeMap::new();
+    for segment in network.segments.drain(..) {
+        roads
+            .entry(segment.road_id)
+            .or_default()
+            .entry(segment.lane_section_id)
+            .or_default()
+            .push(segment);
+    }
+
+    for sections in roads.into_values() {
+        summary.roads += 1;
+        let mut sections: Vec<Vec<RoadSegment>> = sections.into_values().collect();
+        for lanes in &mut sections {
+            lanes.sort_by_key(|lane| lane.lane_id);
+        }
+        let degenerate = |lanes: &[RoadSegment]| {
+            lanes
+                .first()
+                .is_some_and(|lane| lane.end_s - lane.start_s < DEGENERATE_LENGTH)
+        };
+
+        // A degenerate first section gives its start to the next one.
+        while sections.len() > 1 && degenerate(&sections[0]) {
+            let removed = sections.remove(0);
+            extend(&mut sections[0], &removed, false);
+            summary.degenerate += 1;
+        }
+        let mut kept: Vec<Vec<RoadSegment>> = Vec::new();
+        for lanes in sections {
+            match kept.last_mut() {
+                Some(previous) if degenerate(&lanes) => {
+                    extend(previous, &lanes, true);
+                    summary.degenerate += 1;
+                }
+                Some(previous) if same_lanes(previous, &lanes) => {
+                    append(previous, lanes);
+                    summary.merged += 1;
+                }
+                _ => kept.push(lanes),
+            }
+        }
+
+        for (index, lanes) in kept.into_iter().enumerate() {
+            for mut lane in lanes {
+                lane.lane_section_id = index as u32 + 1;
+                lane.road_marks.retain(|mark| !mark.kind.is_empty());
+                lane.road_marks = lane_records::chain(&[], &lane.road_marks, 0.0);
+                lane.center_marks = lane_records::chain(&[], &lane.center_marks, 0.0);
+                lane.materials = lane_records::chain(&[], &lane.materials, 0.0);
+                let mut access = Vec::new();
+                for rule in lane.access.drain(..) {
+                    if !access.contains(&rule) {
+                        access.push(rule);
+                    }
+                }
+                lane.access = access;
+                network.segments.push(lane);
+            }
+        }
+    }
+
+    network.signals.sort_by(|a, b| {
+        a.road_id
+            .cmp(&b.road_id)
+            .then(a.s.total_cmp(&b.s))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    let end = |contact: ContactPoint| contact == ContactPoint::End;
+    network.links.sort_by_key(|link| {
+        (
+            link.road_id,
+            end(link.contact),
+            link.other_road_id,
+            end(link.other_contact),
+        )
+    });
+    network.links.dedup();
+    // The roads' source elements no longer match them.
+    for info in network.roads.values_mut() {
+        info.xml.clear();
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    #[test]
+    fn sample_maps_keep_their_geometry_and_come_back_the_same() {
+        for name in [
+            "straight.xodr",
+            "curve.xodr",
+            "elevation.xodr",
+            "junction.xodr",
+            "signals.xodr",
+        ] {
+            let mut network = load(name);
+            normalize(&mut network);
+            let xml = xodr::to_xml(&network);
+            let mut again = xodr::read_str(&xml, &LoadTransform::default()).unwrap();
+            normalize(&mut again);
+            assert_eq!(xodr::to_xml(&again), xml, "{name}");
+        }
+
+        let mut curve = load("curve.xodr");
+        normalize(&mut curve);
+        let xml = xodr::to_xml(&curve);
+        assert_eq!(xml.matches("<geometry ").count(), 1);
+        assert!(xml.contains("<arc curvature=\"0.02\"/>"));
+        let mut climb = load("elevation.xodr");
+        normalize(&mut climb);
+        let xml = xodr::to_xml(&climb);
+        assert!(xml.contains("<elevation s=\"0\" a=\"0\" b=\"0.05\" c=\"0\" d=\"0\"/>"));
+    }
+
+    #[test]
+    fn degenerate_sections_are_folded_into_their_neighbours() {
+        let xml = r#"<OpenDRIVE>
+              <road id="1" length="100" junction="-1">
+                <planView>
+                  <geometry s="0" x="0" y="0" hdg="0" length="100"><line/></geometry>
+                </planView>
+                <lanes>
+                  <laneSection s="0">
+                    <right>
+                      <lane id="-1" type="driving">
+                        <width sOffset="0" a="3" b="0" c="0" d="0"/>
+                      </lane>
+                    </right>
+                  </laneSection>
+                  <laneSection s="99.99">
+                    <right>
+                      <lane id="-1" type="sidewalk">
+                        <width sOffset="0" a="2" b="0" c="0" d="0"/>
+                      </lane>
+                    </right>
+                  </laneSection>
+                </lanes>
+              </road>
+            </OpenDRIVE>"#;
+        let mut network = xodr::read_str(xml, &LoadTransform::default()).unwrap();
+        let summary = normalize(&mut network);
+        assert_eq!(summary.degenerate, 1);
+        assert_eq!(network.segments.len(), 1);
+        assert_eq!(network.segments[0].end_s, 100.0);
+        // The lane still reaches the end of the reference line, which is
+        // written as read.
+        let written = xodr::to_xml(&network);
+        assert!(written.contains("length=\"100\"><line/>"));
+        assert_eq!(written.matches("<laneSection ").count(), 1);
+    }
+}
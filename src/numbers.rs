@@ -0,0 +1,143 @@
+// Lenient reading of numbers in map files.
+//
+// Exporters do not all write numbers the way XML Schema's `double` has them.
+// Seen in real files: decimal commas from a localized number format,
+// Fortran-style `D` exponents, typographic minus signs, spaces between the
+// sign and the digits, and units left behind the value ("3.5m"). Rather
+// than reading such a value as zero, or failing the whole map, `parse`
+// repairs it and says how, so that the reader can warn about it. Whatever is
+// still not a finite number after that is refused; infinities and NaN, which
+// Rust's own parser accepts, are of no use as coordinates.
+
+// Ways a number was repaired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+    DecimalComma,
+    FortranExponent,
+    TypographicMinus,
+    InnerSpace,
+    TrailingText,
+}
+
+impl Repair {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Repair::DecimalComma => "decimal comma",
+            Repair::FortranExponent => "Fortran exponent",
+            Repair::TypographicMinus => "typographic minus",
+            Repair::InnerSpace => "space inside",
+            Repair::TrailingText => "text after the number",
+        }
+    }
+}
+
+// Reads a number, repairing it if need be. `None` if there is no finite
+// number to be had; no repairs for numbers that were fine. Surrounding
+// whitespace is fine.
+pub fn parse(text: &str) -> Option<(f64, Vec<Repair>)> {
+    let text = text.trim();
+    if let Ok(value) = text.parse::<f64>() {
+        return value.is_finite().then(|| (value, Vec::new()));
+    }
+
+    let mut repairs = Vec::new();
+    let text = if text.contains('\u{2212}') {
+        repairs.push(Repair::TypographicMinus);
+        text.replace('\u{2212}', "-")
+    } else {
+        text.to_string()
+    };
+    let (number, rest) = text.split_at(number_end(&text));
+    if !rest.is_empty() {
+        // Not a second number, though.
+        if rest.contains(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        repairs.push(Repair::TrailingText);
+    }
+    let mut number = number.trim_end().to_string();
+    if number.contains(' ') {
+        number.retain(|c| c != ' ');
+        repairs.push(Repair::InnerSpace);
+    }
+    if let Some((whole, fraction)) = number.split_once(',') {
+        // A decimal comma, but not a thousands separator. "1,234" could be
+        // either, so it is refused too.
+        let whole = whole.trim_start_matches(['+', '-']);
+        let digits = fraction.len()
+            - fraction
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let grouped = (1..=3).contains(&whole.len()) && !whole.starts_with('0') && digits == 3;
+        if fraction.contains(',') || number.contains('.') || grouped {
+            return None;
+        }
+        number = number.replace(',', ".");
+        repairs.push(Repair::DecimalComma);
+    }
+    if number.contains(['d', 'D']) {
+        number = number.replace(['d', 'D'], "e");
+        repairs.push(Repair::FortranExponent);
+    }
+    let value = number.parse::<f64>().ok().filter(|v| v.is_finite())?;
+    Some((value, repairs))
+}
+
+// Where the number at the start of `text` ends, even a misspelled one: an
+// exponent letter belongs to it only if digits follow, so that units such
+// as "deg" do not. Spaces belong to it only between the sign and the
+// digits; after that, they end it.
+fn number_end(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b' ' if !bytes[..i].iter().all(|b| matches!(b, b'+' | b'-' | b' ')) => break,
+            b'0'..=b'9' | b' ' | b'+' | b'-' | b'.' | b',' => i += 1,
+            b'e' | b'E' | b'd' | b'D' => {
+                let sign = matches!(bytes.get(i + 1), Some(b'+' | b'-')) as usize;
+                if !bytes.get(i + 1 + sign).is_some_and(u8::is_ascii_digit) {
+                    break;
+                }
+                i += 1 + sign;
+            }
+            _ => break,
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs() {
+        assert_eq!(parse(" 2.5 "), Some((2.5, vec![])));
+        assert_eq!(parse("+1e3"), Some((1000.0, vec![])));
+        assert_eq!(parse("1,5"), Some((1.5, vec![Repair::DecimalComma])));
+        assert_eq!(
+            parse("1.0D+03"),
+            Some((1000.0, vec![Repair::FortranExponent]))
+        );
+        assert_eq!(
+            parse("\u{2212}2"),
+            Some((-2.0, vec![Repair::TypographicMinus]))
+        );
+        assert_eq!(parse("- 5"), Some((-5.0, vec![Repair::InnerSpace])));
+        assert_eq!(parse("3.5m"), Some((3.5, vec![Repair::TrailingText])));
+        assert_eq!(parse("5deg"), Some((5.0, vec![Repair::TrailingText])));
+        assert_eq!(parse("3.5 m"), Some((3.5, vec![Repair::TrailingText])));
+        assert_eq!(parse("1,25"), Some((1.25, vec![Repair::DecimalComma])));
+        assert_eq!(parse("0,125"), Some((0.125, vec![Repair::DecimalComma])));
+        assert_eq!(
+            parse("1234,567"),
+            Some((1234.567, vec![Repair::DecimalComma]))
+        );
+        for bad in [
+            "inf", "NaN", "1,234.5", "1,2,3", "1.5 m 2", "", "no limit", "1 2", "1,234", "-12,500",
+        ] {
+            assert_eq!(parse(bad), None, "{bad}");
+        }
+    }
+}
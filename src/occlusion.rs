@@ -0,0 +1,182 @@
+// Ambient occlusion.
+//
+// Screen-space ambient occlusion darkens creases and corners that little
+// light reaches: the foot of a curb, the sides of a median, the seams of a
+// junction's surfaces. It makes the height of such geometry much easier to
+// judge, but costs GPU time, so it is off unless asked for and can be
+// switched at any time. Quality levels trade noise for speed; `low` suits
+// large maps on integrated graphics. The occlusion cannot be combined with
+// multisampling, so edges are not smoothed while it is on.
+//
+// `occlusion on|off|low|medium|high|ultra` in the console, or `view
+// --occlusion <quality>`, switches it; a quality turns it on.
+//
+// Keys: C switches ambient occlusion on or off.
+
+use bevy::core_pipeline::prepass::{DepthPrepass, NormalPrepass};
+use bevy::pbr::{
+    ScreenSpaceAmbientOcclusionBundle, ScreenSpaceAmbientOcclusionQualityLevel,
+    ScreenSpaceAmbientOcclusionSettings,
+};
+use bevy::prelude::*;
+
+use crate::MainCamera;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quality {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Ultra,
+}
+
+pub const QUALITIES: [Quality; 4] = [Quality::Low, Quality::Medium, Quality::High, Quality::Ultra];
+
+impl Quality {
+    pub fn name(self) -> &'static str {
+        match self {
+            Quality::Low => "low",
+            Quality::Medium => "medium",
+            Quality::High => "high",
+            Quality::Ultra => "ultra",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Quality, String> {
+        QUALITIES
+            .into_iter()
+            .find(|quality| quality.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = QUALITIES.iter().map(|quality| quality.name()).collect();
+                format!("unknown quality `{name}` (try {})", names.join(", "))
+            })
+    }
+
+    fn level(self) -> ScreenSpaceAmbientOcclusionQualityLevel {
+        match self {
+            Quality::Low => ScreenSpaceAmbientOcclusionQualityLevel::Low,
+            Quality::Medium => ScreenSpaceAmbientOcclusionQualityLevel::Medium,
+            Quality::High => ScreenSpaceAmbientOcclusionQualityLevel::High,
+            Quality::Ultra => ScreenSpaceAmbientOcclusionQualityLevel::Ultra,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Occlusion {
+    pub on: bool,
+    pub quality: Quality,
+}
+
+// `occlusion` in the console: describes the setting, or changes it.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut occlusion = world.resource_mut::<Occlusion>();
+    match args {
+        [] => Ok(if occlusion.on {
+            format!("ambient occlusion on, {} quality", occlusion.quality.name())
+        } else {
+            "ambient occlusion off".to_string()
+        }),
+        ["on"] => {
+            occlusion.on = true;
+            Ok(String::new())
+        }
+        ["off"] => {
+            occlusion.on = false;
+            Ok(String::new())
+        }
+        [quality] => {
+            *occlusion = Occlusion {
+                on: true,
+                quality: Quality::parse(quality)?,
+            };
+            Ok(String::new())
+        }
+        _ => Err("expected on, off or a quality".to_string()),
+    }
+}
+
+pub struct OcclusionPlugin;
+
+impl Plugin for OcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Occlusion>().add_systems(
+            Update,
+            (
+                toggle_occlusion,
+                update_occlusion.run_if(resource_changed::<Occlusion>),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn toggle_occlusion(keys: Res<ButtonInput<KeyCode>>, mut occlusion: ResMut<Occlusion>) {
+    if keys.just_pressed(KeyCode::KeyC) {
+        occlusion.on = !occlusion.on;
+        info!(
+            "ambient occlusion {}",
+            if occlusion.on { "on" } else { "off" }
+        );
+    }
+}
+
+// Puts the occlusion and the depth and normal passes it needs on the camera,
+// or takes them off, and multisampling with them.
+fn update_occlusion(
+    mut commands: Commands,
+    occlusion: Res<Occlusion>,
+    mut msaa: ResMut<Msaa>,
+    cameras: Query<Entity, With<MainCamera>>,
+) {
+    for camera in &cameras {
+        if occlusion.on {
+            commands
+                .entity(camera)
+                .insert(ScreenSpaceAmbientOcclusionBundle {
+                    settings: ScreenSpaceAmbientOcclusionSettings {
+                        quality_level: occlusion.quality.level(),
+                    },
+                    ..default()
+                });
+        } else {
+            commands.entity(camera).remove::<(
+                ScreenSpaceAmbientOcclusionSettings,
+                DepthPrepass,
+                NormalPrepass,
+            )>();
+        }
+    }
+    *msaa = if occlusion.on {
+        Msaa::Off
+    } else {
+        Msaa::Sample4
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        assert_eq!(Quality::parse("low"), Ok(Quality::Low));
+        assert!(Quality::parse("extreme").is_err());
+        assert!(!Occlusion::default().on);
+
+        let mut world = World::new();
+        world.init_resource::<Occlusion>();
+        assert!(command(&mut world, &["ultra"]).is_ok());
+        assert_eq!(
+            *world.resource::<Occlusion>(),
+            Occlusion {
+                on: true,
+                quality: Quality::Ultra
+            }
+        );
+        command(&mut world, &["off"]).unwrap();
+        assert!(!world.resource::<Occlusion>().on);
+        assert!(command(&mut world, &["on", "now"]).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+// OpenDRIVE metadata as ECS components.
+//
+// The road network stays the shared model: exporters and command-line
+// tools work on it without an ECS. Its semantics are mirrored into
+// components so that systems and extensions can query them like any other
+// part of the scene. Every road is an entity with `OdrRoad`, holding its
+// lane sections (`OdrLaneSection`) as children, which in turn hold their
+// lanes (`OdrLane`). Signal and object icons carry `OdrSignal` or
+// `OdrObject` (see `signals`). The road meshes, streamed in tiles, carry the
+// `OdrRoad` of their road and the `OdrLanes` they draw, so a system finds
+// the semantics of what is on screen by querying the meshes themselves.
+// Systems that want the road entities alone query `Without<OdrLanes>`.
+//
+// On a map reload, roads are kept by ID: only the entities of roads that
+// changed, appeared or went away are respawned.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::ecs::system::EntityCommands;
+use bevy::prelude::*;
+
+use crate::edit::NetworkChanged;
+use crate::signals::{Signal, SignalKind};
+use crate::{LaneAccess, LaneMaterial, RoadMark, RoadNetwork, RoadSegment, TrafficRule};
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrRoad {
+    pub id: u32,
+    // The road's `name` attribute, empty if the map gives none.
+    pub name: String,
+    // The junction, for connecting roads.
+    pub junction: Option<u32>,
+    pub length: f64,
+    pub rule: TrafficRule,
+}
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrLaneSection {
+    pub road_id: u32,
+    pub id: u32,
+    pub start_s: f64,
+    pub end_s: f64,
+    // The OpenDRIVE road type in force, e.g. "town"; empty if none is given.
+    pub road_type: String,
+}
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrLane {
+    pub road_id: u32,
+    pub lane_section_id: u32,
+    // Positive on the left of the reference line.
+    pub id: i32,
+    // The OpenDRIVE lane type, e.g. "driving" or "sidewalk".
+    pub lane_type: String,
+    pub width: f64,
+    // The speed limit in m/s, if the map gives one.
+    pub speed: Option<f64>,
+    // The surface, friction and roughness records, if the map gives them.
+    pub materials: Vec<LaneMaterial>,
+    // Who may use the lane, beyond what its type implies.
+    pub access: Vec<LaneAccess>,
+    // The markings on the lane's outer boundary, and on its inner one for
+    // lanes next to the center lane, by `s_offset`.
+    pub road_marks: Vec<RoadMark>,
+    pub center_marks: Vec<RoadMark>,
+    // The lanes it continues from and into.
+    pub predecessor: Option<i32>,
+    pub successor: Option<i32>,
+}
+
+impl From<&RoadSegment> for OdrLane {
+    fn from(lane: &RoadSegment) -> Self {
+        Self {
+            road_id: lane.road_id,
+            lane_section_id: lane.lane_section_id,
+            id: lane.lane_id,
+            lane_type: lane.lane_type.clone(),
+            width: lane.width,
+            speed: lane.speed,
+            materials: lane.materials.clone(),
+            access: lane.access.clone(),
+            road_marks: lane.road_marks.clone(),
+            center_marks: lane.center_marks.clone(),
+            predecessor: lane.predecessor,
+            successor: lane.successor,
+        }
+    }
+}
+
+// The lanes a road mesh draws, on the mesh entity next to its `OdrRoad`.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrLanes(pub Vec<OdrLane>);
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrSignal {
+    pub id: String,
+    pub name: String,
+    pub road_id: u32,
+    pub s: f64,
+    pub t: f64,
+    pub kind: SignalKind,
+    pub dynamic: bool,
+    // The shown value (e.g. a speed limit) and its unit.
+    pub value: Option<f64>,
+    pub unit: String,
+}
+
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct OdrObject {
+    pub id: String,
+    pub name: String,
+    pub road_id: u32,
+    pub s: f64,
+    pub t: f64,
+    // The OpenDRIVE object type, e.g. "pole".
+    pub type_code: String,
+    pub height: f64,
+}
+
+// Inserts the component of a signal or an object.
+pub fn insert_signal(entity: &mut EntityCommands, signal: &Signal) {
+    if signal.kind == SignalKind::Object {
+        entity.insert(OdrObject {
+            id: signal.id.clone(),
+            name: signal.name.clone(),
+            road_id: signal.road_id,
+            s: signal.s,
+            t: signal.t,
+            type_code: signal.type_code.clone(),
+            height: signal.height,
+        });
+    } else {
+        entity.insert(OdrSignal {
+            id: signal.id.clone(),
+            name: signal.name.clone(),
+            road_id: signal.road_id,
+            s: signal.s,
+            t: signal.t,
+            kind: signal.kind,
+            dynamic: signal.dynamic,
+            value: signal.value,
+            unit: signal.unit.clone(),
+        });
+    }
+}
+
+// Flags a road as dirty after an edit, which has its meshes rebuilt (see
+// `edit`).
+pub fn touch(world: &mut World, road_id: u32) {
+    let Some(&entity) = world.resource::<RoadEntities>().0.get(&road_id) else {
+        return;
+    };
+    if let Some(mut road) = world.get_mut::<OdrRoad>(entity) {
+        road.set_changed();
+    }
+}
+
+// The road entities, by road ID.
+#[derive(Resource, Debug, Clone, Default)]
+struct RoadEntities(HashMap<u32, Entity>);
+
+pub struct OdrPlugin;
+
+impl Plugin for OdrPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RoadEntities>()
+            .add_systems(Startup, spawn_roads)
+            .add_systems(Update, respawn_roads.run_if(on_event::<NetworkChanged>()));
+    }
+}
+
+// The segments of a network, by road and lane section.
+fn sections(network: &RoadNetwork) -> BTreeMap<u32, BTreeMap<u32, Vec<&RoadSegment>>> {
+    let mut roads: BTreeMap<u32, BTreeMap<u32, Vec<&RoadSegment>>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads
+            .entry(segment.road_id)
+            .or_default()
+            .entry(segment.lane_section_id)
+            .or_default()
+            .push(segment);
+    }
+    roads
+}
+
+// The component of every road of a network, by road ID.
+pub fn roads(network: &RoadNetwork) -> BTreeMap<u32, OdrRoad> {
+    sections(network)
+        .iter()
+        .map(|(&road_id, sections)| (road_id, road(network, road_id, sections)))
+        .collect()
+}
+
+fn road(
+    network: &RoadNetwork,
+    road_id: u32,
+    sections: &BTreeMap<u32, Vec<&RoadSegment>>,
+) -> OdrRoad {
+    let segments = sections.values().flatten();
+    let info = network.roads.get(&road_id);
+    OdrRoad {
+        id: road_id,
+        name: info.map(|info| info.name.clone()).unwrap_or_default(),
+        junction: info.and_then(|info| info.junction),
+        length: segments.clone().map(|s| s.end_s).fold(0.0, f64::max),
+        rule: segments.clone().next().map(|s| s.rule).unwrap_or_default(),
+    }
+}
+
+fn spawn_road(
+    commands: &mut Commands,
+    network: &RoadNetwork,
+    road_id: u32,
+    sections: &BTreeMap<u32, Vec<&RoadSegment>>,
+) -> Entity {
+    let road = road(network, road_id, sections);
+    commands
+        .spawn((Name::new(format!("road {road_id}")), road))
+        .with_children(|road| {
+            for (&section_id, lanes) in sections {
+                let start_s = lanes.iter().map(|s| s.start_s).fold(f64::MAX, f64::min);
+                let end_s = lanes.iter().map(|s| s.end_s).fold(0.0, f64::max);
+                road.spawn(OdrLaneSection {
+                    road_id,
+                    id: section_id,
+                    start_s,
+                    end_s,
+                    road_type: lanes[0].road_type.clone(),
+                })
+                .with_children(|section| {
+                    for &lane in lanes {
+                        section.spawn(OdrLane::from(lane));
+                    }
+                });
+            }
+        })
+        .id()
+}
+
+fn spawn_roads(
+    mut commands: Commands,
+    network: Res<RoadNetwork>,
+    mut entities: ResMut<RoadEntities>,
+) {
+    for (road_id, sections) in sections(&network) {
+        let entity = spawn_road(&mut commands, &network, road_id, &sections);
+        entities.0.insert(road_id, entity);
+    }
+}
+
+// Respawns the roads that changed in a reload, and drops those that went
+// away; the others keep their entities.
+fn respawn_roads(
+    mut commands: Commands,
+    network: Res<RoadNetwork>,
+    mut entities: ResMut<RoadEntities>,
+    mut reloaded: EventReader<NetworkChanged>,
+    mut odr_roads: Query<&mut OdrRoad>,
+) {
+    let roads = sections(&network);
+    for event in reloaded.read() {
+        for road_id in &event.changed {
+            if let Some(entity) = entities.0.remove(road_id) {
+                commands.entity(entity).despawn_recursive();
+            }
+            if let Some(sections) = roads.get(road_id) {
+                let entity = spawn_road(&mut commands, &network, *road_id, sections);
+                entities.0.insert(*road_id, entity);
+            }
+        }
+    }
+    // A road's junction may change without its lanes changing.
+    for mut road in &mut odr_roads {
+        let junction = network.roads.get(&road.id).and_then(|info| info.junction);
+        if road.junction != junction {
+            road.junction = junction;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
+
+    use super::*;
+    use crate::sample_maps::{lane, load, text};
+    use crate::transform::LoadTransform;
+    use crate::{xodr, RoadMaterials, RoadMeshOptions, RoadPart};
+
+    // The straight road, with an access rule and links on lane -1.
+    fn straight() -> RoadNetwork {
+        let xml = text("straight.xodr").replacen(
+            r#"<roadMark sOffset="0.0" type="broken"/>"#,
+            r#"<link><predecessor id="-1"/><successor id="-2"/></link>
+            <roadMark sOffset="0.0" type="broken"/>
+            <access sOffset="0.0" rule="deny" restriction="bus"/>"#,
+            1,
+        );
+        xodr::read_str(&xml, &LoadTransform::default()).unwrap()
+    }
+
+    fn spawn(network: RoadNetwork) -> App {
+        let mut app = App::new();
+        app.insert_resource(network)
+            .add_event::<NetworkChanged>()
+            .add_plugins(OdrPlugin);
+        app.update();
+        app
+    }
+
+    #[test]
+    fn lanes_carry_the_lane_record() {
+        let mut app = spawn(straight());
+        let world = &mut app.world;
+
+        let road = world.query::<&OdrRoad>().single(world);
+        assert_eq!((road.id, road.name.as_str()), (1, "Straight"));
+        let section = world.query::<&OdrLaneSection>().single(world);
+        assert_eq!(section.road_type, "town");
+
+        let mut lanes = world.query::<&OdrLane>();
+        let mut lane = |id| {
+            lanes
+                .iter(world)
+                .find(|lane| lane.id == id)
+                .unwrap()
+                .clone()
+        };
+        let driving = lane(-1);
+        assert_eq!(driving.lane_type, "driving");
+        assert_eq!(driving.speed, Some(50.0 / 3.6));
+        assert_eq!(driving.road_marks[0].kind, "broken");
+        assert_eq!(driving.materials.len(), 2);
+        assert_eq!(
+            driving.access,
+            vec![LaneAccess {
+                allow: false,
+                restriction: "bus".to_string()
+            }]
+        );
+        assert_eq!(
+            (driving.predecessor, driving.successor),
+            (Some(-1), Some(-2))
+        );
+        let sidewalk = lane(-2);
+        assert_eq!(sidewalk.lane_type, "sidewalk");
+        assert_eq!(sidewalk.road_marks.len(), 2);
+        assert_eq!(sidewalk.predecessor, None);
+    }
+
+    #[test]
+    fn road_meshes_carry_their_road_and_lanes() {
+        let network = load("straight.xodr");
+        let mut world = spawn(network.clone()).world;
+        let mut queue = CommandQueue::default();
+        let road = &roads(&network)[&1];
+        let segments: Vec<&RoadSegment> = network.segments.iter().collect();
+        let (entities, _) = crate::spawn_road(
+            &mut Commands::new(&mut queue, &world),
+            &mut Assets::<Mesh>::default(),
+            &mut Assets::<StandardMaterial>::default(),
+            &mut RoadMaterials::default(),
+            road,
+            crate::tessellate_road(&segments, None),
+            &segments,
+            &RoadMeshOptions::default(),
+            None,
+        );
+        queue.apply(&mut world);
+
+        assert!(!entities.is_empty());
+        let mut parts = world.query::<(&RoadPart, &OdrRoad, &OdrLanes)>();
+        assert_eq!(parts.iter(&world).count(), entities.len());
+        for (part, odr_road, lanes) in parts.iter(&world) {
+            assert_eq!(part.road_id, 1);
+            assert_eq!(odr_road, road);
+            let ids: Vec<i32> = lanes.0.iter().map(|lane| lane.id).collect();
+            assert_eq!(ids.len(), segments.len());
+            assert!(ids.contains(&-2), "{ids:?}");
+            let driving = lanes.0.iter().find(|lane| lane.id == -1).unwrap();
+            assert_eq!(
+                driving,
+                &OdrLane::from(lane(&network, 1, driving.lane_section_id, -1))
+            );
+        }
+        // The road entities alone are told apart from the meshes.
+        let mut roads = world.query_filtered::<&OdrRoad, Without<OdrLanes>>();
+        assert_eq!(roads.iter(&world).count(), 1);
+    }
+}
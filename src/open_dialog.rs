@@ -0,0 +1,313 @@
+// Open dialog.
+//
+// Ctrl+O opens a panel listing the folders, projects and maps in the folder
+// of the current map, or the working directory for the demo network. A
+// project sets up a whole session, settings included, so opening a project
+// or a map starts a new viewer on it, as `view <file>` does, and closes this
+// one. The console's `open <file>` does the same without the panel.
+//
+// While the panel is open it takes all keyboard input.
+//
+// Keys: Ctrl+O opens or closes the panel; in it, Up and Down move, Enter
+// opens the folder or file and Escape closes.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use bevy::app::AppExit;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+use crate::compressed::is_xodr;
+use crate::project;
+use crate::reload::MapSource;
+
+// Map files other than OpenDRIVE that `view` loads.
+const MAP_SUFFIXES: [&str; 5] = [".osm", ".osm.gz", ".osm.zst", ".rsnet", ".zip"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum EntryKind {
+    Parent,
+    Folder,
+    Project,
+    Map,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    kind: EntryKind,
+    name: String,
+    path: PathBuf,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+struct OpenDialog {
+    open: bool,
+    dir: PathBuf,
+    entries: Vec<Entry>,
+    cursor: usize,
+}
+
+impl OpenDialog {
+    fn show(&mut self, dir: PathBuf) {
+        self.entries = entries(&dir).unwrap_or_else(|e| {
+            warn!("{}: {e}", dir.display());
+            Vec::new()
+        });
+        self.dir = dir;
+        self.cursor = 0;
+    }
+}
+
+// Marks the panel.
+#[derive(Component)]
+struct DialogPanel;
+
+// Marks the panel's text.
+#[derive(Component)]
+struct DialogText;
+
+pub struct OpenDialogPlugin;
+
+impl Plugin for OpenDialogPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OpenDialog>()
+            .add_systems(Startup, spawn_panel)
+            .add_systems(PreUpdate, read_keys.after(InputSystem))
+            .add_systems(Update, show_dialog);
+    }
+}
+
+// What a file is to the dialog; none for files it does not list.
+fn file_kind(path: &Path) -> Option<EntryKind> {
+    let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+    if project::is_project(&name) {
+        Some(EntryKind::Project)
+    } else if is_xodr(path) || MAP_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        Some(EntryKind::Map)
+    } else {
+        None
+    }
+}
+
+// The way up, the folders, then the projects and maps in `dir`, each by
+// name. Hidden files are left out.
+fn entries(dir: &Path) -> std::io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for item in std::fs::read_dir(dir)? {
+        let path = item?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let kind = if path.is_dir() {
+            Some(EntryKind::Folder)
+        } else {
+            file_kind(&path)
+        };
+        if let Some(kind) = kind {
+            entries.push(Entry {
+                kind,
+                name: name.to_string(),
+                path,
+            });
+        }
+    }
+    entries.sort_by(|a, b| {
+        let file = |e: &Entry| e.kind != EntryKind::Folder;
+        (file(a), &a.name).cmp(&(file(b), &b.name))
+    });
+    if let Some(parent) = dir.parent() {
+        let up = Entry {
+            kind: EntryKind::Parent,
+            name: "..".to_string(),
+            path: parent.to_path_buf(),
+        };
+        entries.insert(0, up);
+    }
+    Ok(entries)
+}
+
+// Starts a new viewer on a project or map; the caller closes this one.
+pub fn open_session(path: &Path) -> Result<(), String> {
+    if !path.is_file() {
+        return Err(format!("{}: no such file", path.display()));
+    }
+    if file_kind(path).is_none() {
+        return Err(format!("{}: not a project or map", path.display()));
+    }
+    let viewer = std::env::current_exe().map_err(|e| e.to_string())?;
+    Command::new(viewer)
+        .arg("view")
+        .arg(path)
+        .spawn()
+        .map_err(|e| format!("could not start the viewer: {e}"))?;
+    Ok(())
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(30.0),
+                    top: Val::Percent(15.0),
+                    width: Val::Percent(40.0),
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.85).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            DialogPanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 14.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                DialogText,
+            ));
+        });
+}
+
+fn read_keys(
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut dialog: ResMut<OpenDialog>,
+    source: Res<MapSource>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let control = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if control && keys.just_pressed(KeyCode::KeyO) {
+        dialog.open = !dialog.open;
+        if dialog.open {
+            let dir = source
+                .path
+                .as_deref()
+                .and_then(Path::parent)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(Path::to_path_buf)
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default();
+            dialog.show(dir);
+        }
+        keys.reset_all();
+        return;
+    }
+    if !dialog.open {
+        return;
+    }
+    let last = dialog.entries.len().saturating_sub(1);
+    if keys.just_pressed(KeyCode::Escape) {
+        dialog.open = false;
+    } else if keys.just_pressed(KeyCode::ArrowUp) {
+        dialog.cursor = dialog.cursor.saturating_sub(1);
+    } else if keys.just_pressed(KeyCode::ArrowDown) {
+        dialog.cursor = (dialog.cursor + 1).min(last);
+    } else if keys.just_pressed(KeyCode::Enter) {
+        if let Some(entry) = dialog.entries.get(dialog.cursor).cloned() {
+            match entry.kind {
+                EntryKind::Parent | EntryKind::Folder => dialog.show(entry.path),
+                EntryKind::Project | EntryKind::Map => match open_session(&entry.path) {
+                    Ok(()) => {
+                        info!("opening {}", entry.path.display());
+                        exit.send(AppExit);
+                    }
+                    Err(message) => warn!("{message}"),
+                },
+            }
+        }
+    }
+    keys.reset_all();
+}
+
+fn show_dialog(
+    dialog: Res<OpenDialog>,
+    mut panels: Query<&mut Visibility, With<DialogPanel>>,
+    mut texts: Query<&mut Text, With<DialogText>>,
+) {
+    if !dialog.is_changed() {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = if dialog.open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    let mut shown = format!("Open: {}\n", dialog.dir.display());
+    if dialog.entries.is_empty() {
+        shown.push_str("  (no projects or maps here)\n");
+    }
+    for (i, entry) in dialog.entries.iter().enumerate() {
+        let cursor = if i == dialog.cursor { '>' } else { ' ' };
+        let slash = if entry.kind <= EntryKind::Folder {
+            "/"
+        } else {
+            ""
+        };
+        let _ = writeln!(shown, "{cursor} {}{slash}", entry.name);
+    }
+    for mut text in &mut texts {
+        text.sections[0].value.clone_from(&shown);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::temp_dir;
+
+    #[test]
+    fn folders_come_before_projects_and_maps() {
+        let dir = temp_dir("open_dialog");
+        std::fs::create_dir(dir.join("zones")).unwrap();
+        std::fs::create_dir(dir.join(".cache")).unwrap();
+        for file in [
+            "town.xodr",
+            "review.rsodr",
+            "area.osm.gz",
+            "notes.txt",
+            "Town.RSODR",
+        ] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let listed: Vec<(EntryKind, String)> = entries(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.kind, entry.name))
+            .collect();
+        let names: Vec<&str> = listed.iter().map(|(_, name)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "..",
+                "zones",
+                "Town.RSODR",
+                "area.osm.gz",
+                "review.rsodr",
+                "town.xodr"
+            ]
+        );
+        assert_eq!(listed[0].0, EntryKind::Parent);
+        assert_eq!(listed[1].0, EntryKind::Folder);
+        assert_eq!(listed[2].0, EntryKind::Project);
+        assert_eq!(listed[3].0, EntryKind::Map);
+
+        // Only projects and maps are opened.
+        assert!(open_session(&dir.join("notes.txt")).is_err());
+        assert!(open_session(&dir.join("missing.rsodr")).is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,90 @@
+// Floating origin for rendering.
+//
+// Map geometry is kept in f64. Bevy renders in f32, which at projected (UTM)
+// coordinates resolves only to a fraction of a meter, so meshes placed there
+// jitter. Instead, entities carry their f64 `WorldPosition` and their render
+// translation is taken relative to the `RenderOrigin`. The origin starts at
+// the middle of the map and follows the camera: once the camera strays more
+// than `RECENTER_DISTANCE` from it, the origin jumps to the camera and every
+// entity is re-translated.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::{camera_orbit, CameraOrbit, MainCamera, RoadNetwork};
+
+// How far (in meters, render space) the camera may move before recentering.
+const RECENTER_DISTANCE: f32 = 2_000.0;
+
+// The f64 viewer-frame position that is rendered at the render-space origin.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct RenderOrigin(pub DVec3);
+
+impl RenderOrigin {
+    // Converts an f64 position into render space.
+    pub fn to_render(self, p: DVec3) -> Vec3 {
+        (p - self.0).as_vec3()
+    }
+}
+
+// The f64 position of an entity. Its `Transform` translation is derived from
+// this and must not be set directly.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct WorldPosition(pub DVec3);
+
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderOrigin>()
+            .add_systems(Startup, center_on_network)
+            .add_systems(Update, recenter.before(camera_orbit))
+            .add_systems(
+                PostUpdate,
+                sync_world_positions.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+// Starts with the origin in the middle of the map's bounding box.
+fn center_on_network(mut origin: ResMut<RenderOrigin>, network: Res<RoadNetwork>) {
+    let mut points = network
+        .segments
+        .iter()
+        .flat_map(|s| s.left_side.iter().chain(&s.right_side));
+    let Some(first) = points.next() else {
+        return;
+    };
+    let (min, max) = points.fold((*first, *first), |(min, max), p| (min.min(*p), max.max(*p)));
+    origin.0 = (min + max) / 2.0;
+}
+
+// Moves the origin to the camera once it has travelled far enough. The
+// camera is shifted back by the same amount so nothing visibly moves.
+fn recenter(
+    mut origin: ResMut<RenderOrigin>,
+    mut cameras: Query<(&Transform, &mut CameraOrbit), With<MainCamera>>,
+) {
+    for (transform, mut orbit) in &mut cameras {
+        let ground = Vec3::new(transform.translation.x, 0.0, transform.translation.z);
+        if ground.length() < RECENTER_DISTANCE {
+            continue;
+        }
+        origin.0 += ground.as_dvec3();
+        orbit.center -= ground;
+    }
+}
+
+// Updates render translations after the origin or a world position moved.
+fn sync_world_positions(
+    origin: Res<RenderOrigin>,
+    mut all: Query<(Ref<WorldPosition>, &mut Transform)>,
+) {
+    let everything = origin.is_changed();
+    for (position, mut transform) in &mut all {
+        if everything || position.is_changed() {
+            transform.translation = origin.to_render(position.0);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// Import of OpenStreetMap data.
+//
+// OSM only describes road centerlines (ways) with a handful of tags, so the
+// lane layout is synthesized: lane counts come from the `lanes`,
+// `lanes:forward`/`lanes:backward` and `oneway` tags, falling back to typical
+// values per `highway` class, and lane widths from `width` or a default. The
+// resulting network is an approximation good enough to bootstrap a map.
+//
+// Coordinates are projected with an equirectangular projection around the
+// center of the data, which is accurate to well under a meter for city-sized
+// extracts. East maps to +x and north to -z, as everywhere in the viewer.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use bevy::math::{DVec2, DVec3};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::geo::GeoReference;
+use crate::tessellation::offset_line;
+use crate::units::{KMH, MPH};
+use crate::{RoadNetwork, RoadSegment, TrafficRule};
+
+// Mean earth radius used by the projection.
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+// Lane width used when a way has no usable `width` tag.
+const DEFAULT_LANE_WIDTH: f64 = 3.5;
+
+// A way as read from the file, before lanes are synthesized.
+#[derive(Debug, Default)]
+struct Way {
+    nodes: Vec<i64>,
+    tags: HashMap<String, String>,
+}
+
+// Reads an `.osm` XML file and synthesizes a road network from it.
+pub fn import_file(path: &Path) -> Result<RoadNetwork, String> {
+    let xml = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    import_str(&xml)
+}
+
+// Synthesizes a road network from OSM XML.
+pub fn import_str(xml: &str) -> Result<RoadNetwork, String> {
+    let (nodes, ways) = parse(xml)?;
+
+    // Project around the mean position so coordinates stay small.
+    if nodes.is_empty() {
+        return Ok(RoadNetwork::default());
+    }
+    let origin = nodes.values().fold(DVec2::ZERO, |sum, p| sum + *p) / nodes.len() as f64;
+
+    let mut segments = Vec::new();
+    for (index, way) in ways.iter().enumerate() {
+        let Some(highway) = way.tags.get("highway") else {
+            continue;
+        };
+        let Some(layout) = LaneLayout::from_tags(highway, &way.tags) else {
+            continue;
+        };
+
+        let points: Vec<DVec3> = way
+            .nodes
+            .iter()
+            .filter_map(|id| nodes.get(id))
+            .map(|p| project(*p, origin))
+            .collect();
+        if points.len() < 2 {
+            continue;
+        }
+        let centerline = smooth(&points);
+
+        let road_id = index as u32 + 1;
+        segments.extend(layout.lanes(road_id, &centerline));
+    }
+
+    // The projection above, for geographic coordinates.
+    let proj = format!(
+        "+proj=eqc +lat_ts={lat} +lat_0={lat} +lon_0={lon} +R={EARTH_RADIUS}",
+        lat = origin.y,
+        lon = origin.x
+    );
+    Ok(RoadNetwork {
+        geo: GeoReference::parse(&proj).ok(),
+        ..RoadNetwork::new(segments)
+    })
+}
+
+// Collects node positions (as lon/lat) and ways. Relations only carry turn
+// restrictions and route memberships, which the lane model cannot express
+// yet, so they are skipped.
+fn parse(xml: &str) -> Result<(HashMap<i64, DVec2>, Vec<Way>), String> {
+    let mut reader = Reader::from_str(xml);
+    let mut nodes = HashMap::new();
+    let mut ways = Vec::new();
+    let mut current: Option<Way> = None;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("XML error at byte {}: {e}", reader.buffer_position()))?;
+        let opening = matches!(event, Event::Start(_));
+        match event {
+            Event::Start(e) | Event::Empty(e) => match e.name().as_ref() {
+                b"node" => {
+                    let id = attribute(&e, "id").and_then(|v| v.parse().ok());
+                    let lat = attribute(&e, "lat").and_then(|v| v.parse().ok());
+                    let lon = attribute(&e, "lon").and_then(|v| v.parse().ok());
+                    if let (Some(id), Some(lat), Some(lon)) = (id, lat, lon) {
+                        nodes.insert(id, DVec2::new(lon, lat));
+                    }
+                }
+                // Self-closing ways have no nodes and are useless.
+                b"way" if opening => current = Some(Way::default()),
+                b"nd" => {
+                    let id = attribute(&e, "ref").and_then(|v| v.parse().ok());
+                    if let (Some(way), Some(id)) = (current.as_mut(), id) {
+                        way.nodes.push(id);
+                    }
+                }
+                b"tag" => {
+                    if let (Some(way), Some(k), Some(v)) =
+                        (current.as_mut(), attribute(&e, "k"), attribute(&e, "v"))
+                    {
+                        way.tags.insert(k, v);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(e) if e.name().as_ref() == b"way" => {
+                ways.extend(current.take());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok((nodes, ways))
+}
+
+// Reads an attribute as an owned string.
+fn attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+// Projects a lon/lat pair onto the local ground plane around `origin`.
+fn project(lon_lat: DVec2, origin: DVec2) -> DVec3 {
+    let east = (lon_lat.x - origin.x).to_radians() * EARTH_RADIUS * origin.y.to_radians().cos();
+    let north = (lon_lat.y - origin.y).to_radians() * EARTH_RADIUS;
+    DVec3::new(east, 0.0, -north)
+}
+
+// OSM ways are coarse polylines. One round of Chaikin corner cutting rounds
+// off the corners into something closer to the surveyed curve while keeping
+// the end points, where ways connect to each other, in place.
+fn smooth(points: &[DVec3]) -> Vec<DVec3> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut out = Vec::with_capacity(points.len() * 2);
+    out.push(points[0]);
+    for pair in points.windows(2) {
+        out.push(pair[0].lerp(pair[1], 0.25));
+        out.push(pair[0].lerp(pair[1], 0.75));
+    }
+    out.push(points[points.len() - 1]);
+    // The first and last cut points sit right next to the kept end points.
+    out.remove(1);
+    out.remove(out.len() - 2);
+    out
+}
+
+// Number and width of lanes on a way, split by direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct LaneLayout {
+    forward: u32,
+    backward: u32,
+    width: f64,
+    rule: TrafficRule,
+    // From `maxspeed`, in m/s.
+    speed: Option<f64>,
+}
+
+impl LaneLayout {
+    // Derives the lane layout from a way's tags. Returns `None` for ways that
+    // are not meant for cars (footways, cycleways and the like).
+    fn from_tags(highway: &str, tags: &HashMap<String, String>) -> Option<Self> {
+        // Typical lane count per direction when the way is not tagged.
+        let per_direction = match highway {
+            "motorway" | "trunk" => 2,
+            "primary" | "secondary" | "tertiary" | "unclassified" | "residential"
+            | "living_street" | "service" => 1,
+            _ if highway.ends_with("_link") => 1,
+            _ => return None,
+        };
+
+        let oneway = matches!(
+            tags.get("oneway").map(String::as_str),
+            Some("yes" | "1" | "true")
+        ) || highway == "motorway"
+            || tags.get("junction").is_some_and(|j| j == "roundabout");
+        let count = |key: &str| tags.get(key).and_then(|v| v.trim().parse::<u32>().ok());
+
+        let (forward, backward) = match (count("lanes:forward"), count("lanes:backward")) {
+            (Some(f), Some(b)) => (f, b),
+            _ => match (count("lanes"), oneway) {
+                (Some(total), true) => (total, 0),
+                (Some(total), false) => (total.div_ceil(2), total / 2),
+                (None, true) => (per_direction, 0),
+                (None, false) => (per_direction, per_direction),
+            },
+        };
+        if forward + backward == 0 {
+            return None;
+        }
+
+        // `width` describes the whole carriageway, e.g. "7" or "7 m".
+        let width = tags
+            .get("width")
+            .and_then(|w| w.trim_end_matches('m').trim().parse::<f64>().ok())
+            .map(|w| w / (forward + backward) as f64)
+            .filter(|w| (2.0..=6.0).contains(w))
+            .unwrap_or(DEFAULT_LANE_WIDTH);
+
+        // OSM has no per-way traffic rule, but `driving_side=left` is
+        // commonly tagged on ways in left-hand traffic countries.
+        let rule = match tags.get("driving_side").map(String::as_str) {
+            Some("left") => TrafficRule::LeftHand,
+            _ => TrafficRule::RightHand,
+        };
+
+        // `maxspeed` is in km/h unless given in mph, e.g. "30 mph"; values
+        // like "none" or "signals" give no limit.
+        let speed = tags.get("maxspeed").and_then(|v| {
+            let v = v.trim();
+            match v.strip_suffix("mph") {
+                Some(mph) => mph.trim().parse().ok().map(|mph: f64| mph * MPH),
+                None => v.parse().ok().map(|kmh: f64| kmh * KMH),
+            }
+        });
+
+        Some(Self {
+            forward,
+            backward,
+            width,
+            rule,
+            speed,
+        })
+    }
+
+    // Builds one segment per lane. The way traces the middle of the
+    // carriageway and serves as reference line; in right-hand traffic
+    // backward lanes lie on its left and forward lanes on its right, in
+    // left-hand traffic the other way round. As in OpenDRIVE, lanes are
+    // numbered outwards from the reference line, with positive IDs on the
+    // left and negative IDs on the right.
+    fn lanes(&self, road_id: u32, centerline: &[DVec3]) -> Vec<RoadSegment> {
+        let total = self.forward + self.backward;
+        let on_left = match self.rule {
+            TrafficRule::RightHand => self.backward,
+            TrafficRule::LeftHand => self.forward,
+        };
+        let length: f64 = centerline.windows(2).map(|w| w[0].distance(w[1])).sum();
+        let leftmost = total as f64 * self.width / 2.0;
+
+        (0..total)
+            .map(|lane| {
+                let left_offset = leftmost - lane as f64 * self.width;
+                let left_side = offset_line(centerline, left_offset);
+                let right_side = offset_line(centerline, left_offset - self.width);
+                let middle = offset_line(centerline, left_offset - self.width / 2.0);
+                RoadSegment {
+                    start_pos: middle[0],
+                    end_pos: middle[middle.len() - 1],
+                    start_s: 0.0,
+                    end_s: length,
+                    width: self.width,
+                    left_side,
+                    right_side,
+                    road_id,
+                    lane_id: if lane < on_left {
+                        (on_left - lane) as i32
+                    } else {
+                        -((lane - on_left + 1) as i32)
+                    },
+                    lane_section_id: 1,
+                    rule: self.rule,
+                    lane_type: "driving".to_string(),
+                    road_type: String::new(),
+                    speed: self.speed,
+                    materials: Vec::new(),
+                    access: Vec::new(),
+                    road_marks: Vec::new(),
+                    center_marks: Vec::new(),
+                    predecessor: None,
+                    successor: None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three nodes about 110 m apart along a meridian and a parallel, and
+    // ways over them with the given tags.
+    fn osm(ways: &[&[(&str, &str)]]) -> String {
+        let mut xml = String::from(
+            "<osm>\n\
+             <node id=\"1\" lat=\"48.000\" lon=\"11.000\"/>\n\
+             <node id=\"2\" lat=\"48.001\" lon=\"11.000\"/>\n\
+             <node id=\"3\" lat=\"48.001\" lon=\"11.0015\"/>\n",
+        );
+        for (i, tags) in ways.iter().enumerate() {
+            xml += &format!(
+                "<way id=\"{}\">\n<nd ref=\"1\"/><nd ref=\"2\"/><nd ref=\"3\"/>\n",
+                10 + i
+            );
+            for (k, v) in *tags {
+                xml += &format!("<tag k=\"{k}\" v=\"{v}\"/>\n");
+            }
+            xml += "</way>\n";
+        }
+        xml + "</osm>\n"
+    }
+
+    fn lane_ids(network: &RoadNetwork) -> Vec<i32> {
+        network.segments.iter().map(|s| s.lane_id).collect()
+    }
+
+    #[test]
+    fn lanes_from_tags() {
+        let network = import_str(&osm(&[&[("highway", "residential")]])).unwrap();
+        assert_eq!(lane_ids(&network), [1, -1]);
+        assert!(network.geo.is_some());
+        let lane = &network.segments[1];
+        assert_eq!(lane.width, DEFAULT_LANE_WIDTH);
+        assert_eq!(lane.speed, None);
+        // One round of corner cutting keeps the ends and adds a point.
+        assert_eq!(lane.left_side.len(), 4);
+        assert!(lane.end_s > 200.0 && lane.end_s < 230.0);
+
+        let tags = [
+            ("highway", "primary"),
+            ("oneway", "yes"),
+            ("lanes", "2"),
+            ("width", "7 m"),
+            ("maxspeed", "30 mph"),
+        ];
+        let network = import_str(&osm(&[&tags])).unwrap();
+        assert_eq!(lane_ids(&network), [-1, -2]);
+        assert_eq!(network.segments[0].width, 3.5);
+        assert!((network.segments[0].speed.unwrap() - 30.0 * MPH).abs() < 1e-9);
+
+        let tags = [
+            ("highway", "tertiary"),
+            ("driving_side", "left"),
+            ("lanes:forward", "2"),
+            ("lanes:backward", "1"),
+        ];
+        let network = import_str(&osm(&[&tags])).unwrap();
+        assert_eq!(lane_ids(&network), [2, 1, -1]);
+        assert!(network
+            .segments
+            .iter()
+            .all(|s| s.rule == TrafficRule::LeftHand));
+    }
+
+    #[test]
+    fn ways_not_for_cars_are_skipped() {
+        let network = import_str(&osm(&[
+            &[("highway", "footway")],
+            &[("building", "yes")],
+            &[("highway", "service"), ("lanes", "0")],
+        ]))
+        .unwrap();
+        assert!(network.segments.is_empty());
+        assert!(import_str("<osm><way></osm>").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+// Overlaps between roads that do not meet.
+//
+// Two road surfaces should only share ground where the roads are connected:
+// at a link between them, or inside a junction, where connecting roads are
+// laid over each other by design. Anywhere else one surface on top of another
+// is almost always an artifact of generating the map, such as a road
+// digitized twice or two carriageways drawn into each other.
+//
+// Surfaces are compared in plan. The middle of every quad of a lane strip is
+// tested against the triangles of the other roads' lanes and overlaps if it
+// lies within `VERTICAL_TOLERANCE` of one in height; roads further apart pass
+// over each other, as on a bridge. Each pair of roads is reported once, with
+// the stretch of the first road that the other covers.
+
+use std::collections::BTreeMap;
+
+use bevy::math::{DVec2, DVec3};
+
+use crate::validation::{Issue, Severity};
+use crate::{RoadNetwork, RoadSegment};
+
+// Surfaces closer than this in height are on top of each other, in meters.
+const VERTICAL_TOLERANCE: f64 = 2.0;
+
+// A point of one road's surface that lies on another road.
+#[derive(Debug, Clone, Copy)]
+struct Hit {
+    road_id: u32,
+    lane_id: i32,
+    s: f64,
+    position: DVec3,
+}
+
+// Finds overlapping roads.
+pub fn check(network: &RoadNetwork) -> Vec<Issue> {
+    let boxes: Vec<(DVec3, DVec3)> = network.segments.iter().map(bounds).collect();
+    let mut pairs: BTreeMap<(u32, u32), Vec<Hit>> = BTreeMap::new();
+    for (a, segment) in network.segments.iter().enumerate() {
+        for (b, other) in network.segments.iter().enumerate() {
+            if segment.road_id == other.road_id
+                || !boxes_touch(boxes[a], boxes[b])
+                || connected(network, segment.road_id, other.road_id)
+            {
+                continue;
+            }
+            let key = (
+                segment.road_id.min(other.road_id),
+                segment.road_id.max(other.road_id),
+            );
+            for (s, middle) in quad_middles(segment) {
+                if lies_on(other, middle) {
+                    pairs.entry(key).or_default().push(Hit {
+                        road_id: segment.road_id,
+                        lane_id: segment.lane_id,
+                        s,
+                        position: middle,
+                    });
+                }
+            }
+        }
+    }
+
+    pairs
+        .into_iter()
+        .map(|((first, second), hits)| {
+            // Describe the overlap on the first road where it has points.
+            let on_first: Vec<&Hit> = hits.iter().filter(|hit| hit.road_id == first).collect();
+            let hits: Vec<&Hit> = if on_first.is_empty() {
+                hits.iter().collect()
+            } else {
+                on_first
+            };
+            let start = hits.iter().copied().min_by(|a, b| a.s.total_cmp(&b.s)).unwrap();
+            let end = hits.iter().map(|hit| hit.s).fold(start.s, f64::max);
+            let other = if start.road_id == first { second } else { first };
+            Issue {
+                check: "overlap",
+                severity: Severity::Error,
+                road_id: start.road_id,
+                lane_id: Some(start.lane_id),
+                s: start.s,
+                position: start.position,
+                message: format!(
+                    "overlaps road {other} from s={:.1} to s={end:.1} without being connected to it",
+                    start.s
+                ),
+            }
+        })
+        .collect()
+}
+
+// Whether two roads are meant to meet: linked to each other, or parts of the
+// same junction.
+fn connected(network: &RoadNetwork, a: u32, b: u32) -> bool {
+    let junction = |road_id: u32| network.roads.get(&road_id).and_then(|info| info.junction);
+    network.links.iter().any(|link| {
+        (link.road_id == a && link.other_road_id == b)
+            || (link.road_id == b && link.other_road_id == a)
+    }) || junction(a).is_some_and(|id| junction(b) == Some(id))
+}
+
+// The bounding box of a segment.
+fn bounds(segment: &RoadSegment) -> (DVec3, DVec3) {
+    segment
+        .left_side
+        .iter()
+        .chain(&segment.right_side)
+        .fold((DVec3::INFINITY, DVec3::NEG_INFINITY), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        })
+}
+
+// Whether two boxes overlap in plan and are close enough in height.
+fn boxes_touch((min_a, max_a): (DVec3, DVec3), (min_b, max_b): (DVec3, DVec3)) -> bool {
+    min_a.x <= max_b.x
+        && min_b.x <= max_a.x
+        && min_a.z <= max_b.z
+        && min_b.z <= max_a.z
+        && min_a.y <= max_b.y + VERTICAL_TOLERANCE
+        && min_b.y <= max_a.y + VERTICAL_TOLERANCE
+}
+
+// The middle of every quad of a lane strip, with its station.
+fn quad_middles(segment: &RoadSegment) -> Vec<(f64, DVec3)> {
+    let (left, right) = (&segment.left_side, &segment.right_side);
+    let count = left.len().min(right.len());
+    (1..count)
+        .map(|i| {
+            let fraction = (i as f64 - 0.5) / (count - 1) as f64;
+            (
+                segment.start_s + (segment.end_s - segment.start_s) * fraction,
+                (left[i - 1] + right[i - 1] + left[i] + right[i]) / 4.0,
+            )
+        })
+        .collect()
+}
+
+// Whether a point lies on a segment's surface, seen from above.
+fn lies_on(segment: &RoadSegment, point: DVec3) -> bool {
+    let (left, right) = (&segment.left_side, &segment.right_side);
+    let count = left.len().min(right.len());
+    (1..count).any(|i| {
+        [
+            [left[i - 1], right[i - 1], left[i]],
+            [right[i - 1], right[i], left[i]],
+        ]
+        .into_iter()
+        .any(|triangle| {
+            height_at(triangle, point)
+                .is_some_and(|height| (height - point.y).abs() < VERTICAL_TOLERANCE)
+        })
+    })
+}
+
+// The height of a triangle above a point in plan, if the point is inside it.
+fn height_at([a, b, c]: [DVec3; 3], point: DVec3) -> Option<f64> {
+    let plan = |p: DVec3| DVec2::new(p.x, p.z);
+    let (ab, ac, ap) = (plan(b) - plan(a), plan(c) - plan(a), plan(point) - plan(a));
+    let det = ab.perp_dot(ac);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let u = ap.perp_dot(ac) / det;
+    let v = ab.perp_dot(ap) / det;
+    (u >= 0.0 && v >= 0.0 && u + v <= 1.0).then_some(a.y + (b.y - a.y) * u + (c.y - a.y) * v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::text;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    // The straight sample with its road digitized a second time as road 2,
+    // raised by `height` meters, and road 1's `<link>` given.
+    fn twice(height: f64, link: &str) -> RoadNetwork {
+        let xml = text("straight.xodr");
+        let (start, end) = (
+            xml.find("<road ").unwrap(),
+            xml.find("</road>").unwrap() + 7,
+        );
+        let copy = xml[start..end].replace(r#"id="1""#, r#"id="2""#).replace(
+            r#"<elevation s="0.0" a="0.0""#,
+            &format!(r#"<elevation s="0.0" a="{height}""#),
+        );
+        let original =
+            xml[start..end].replacen("<planView>", &format!("<link>{link}</link><planView>"), 1);
+        let xml = format!("{}{original}{copy}{}", &xml[..start], &xml[end..]);
+        xodr::read_str(&xml, &LoadTransform::default()).unwrap()
+    }
+
+    #[test]
+    fn unconnected_roads_on_top_of_each_other_are_reported() {
+        let issues = check(&twice(0.0, ""));
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!((issues[0].check, issues[0].road_id), ("overlap", 1));
+        assert!(
+            issues[0].message.starts_with("overlaps road 2 from s=0."),
+            "{}",
+            issues[0].message
+        );
+        assert!(
+            issues[0].message.contains("to s=99."),
+            "{}",
+            issues[0].message
+        );
+    }
+
+    #[test]
+    fn linked_roads_and_bridges_are_not() {
+        let linked = twice(
+            0.0,
+            r#"<successor elementType="road" elementId="2" contactPoint="start"/>"#,
+        );
+        assert!(check(&linked).is_empty());
+        // A road passing 5 m above the other.
+        assert!(check(&twice(5.0, "")).is_empty());
+    }
+}
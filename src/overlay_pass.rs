@@ -0,0 +1,157 @@
+// The overlay pass: overlays drawn over the map without fighting it.
+//
+// Overlays lie on the road surface or just above it: highlights, check
+// results, routes, direction arrows, debug lines. Drawn along with the map
+// they flicker wherever their depth and the surface's round to the same
+// value, which at a distance is everywhere. So they are drawn in a pass of
+// their own, by a camera that follows the main one and draws the overlay
+// render layer after the map. It keeps the map's depth, so that overlays
+// are still hidden behind hills and bridges, but draws them with a depth
+// bias that lets them win over the surface they lie on.
+//
+// Overlays are stacked by level: check results and arrows on the surface,
+// lines above them, highlights such as the selection's fill on top. Each
+// level is biased further towards the camera than the one below. Overlay
+// meshes are blended additively (see `overlay_material`), so overlapping
+// ones come out the same whichever the renderer happens to draw first.
+//
+// Gizmos, which most overlays are drawn with, all go into the pass.
+
+use bevy::core_pipeline::core_3d::Camera3dDepthLoadOp;
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+
+use crate::MainCamera;
+
+// The render layer of the pass; layer 1 is the signal icons' (see
+// `signals`).
+pub const MAP_OVERLAY_LAYER: u8 = 2;
+
+// How far gizmos are biased towards the camera, as the exponent the gizmo
+// shader takes: a thousandth moves a line about a meter forward at 200 m.
+const GIZMO_DEPTH_BIAS: f32 = -0.001;
+
+// The camera order of the pass, after the map's and before the signal
+// icons'.
+pub const OVERLAY_CAMERA_ORDER: isize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverlayLevel {
+    // Check results, heatmaps and arrows painted on the surface.
+    Surface,
+    // Lines along or across the roads: routes, debug lines.
+    Line,
+    // The selection and other highlights.
+    Highlight,
+}
+
+impl OverlayLevel {
+    // The material depth bias of the level, in steps of the depth buffer;
+    // 4000 steps are about 0.05 % of the distance to the camera.
+    pub fn depth_bias(self) -> f32 {
+        match self {
+            OverlayLevel::Surface => 4_000.0,
+            OverlayLevel::Line => 8_000.0,
+            OverlayLevel::Highlight => 12_000.0,
+        }
+    }
+}
+
+// The render layers an overlay entity goes on.
+pub fn overlay_layers() -> RenderLayers {
+    RenderLayers::layer(MAP_OVERLAY_LAYER)
+}
+
+// A material for overlay meshes of a level: unlit and blended additively.
+pub fn overlay_material(color: Color, level: OverlayLevel) -> StandardMaterial {
+    StandardMaterial {
+        base_color: color,
+        unlit: true,
+        alpha_mode: AlphaMode::Add,
+        depth_bias: level.depth_bias(),
+        cull_mode: None,
+        ..default()
+    }
+}
+
+pub struct OverlayPassPlugin;
+
+impl Plugin for OverlayPassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, configure_gizmos)
+            .add_systems(Update, attach_overlay_camera);
+    }
+}
+
+fn configure_gizmos(mut store: ResMut<GizmoConfigStore>) {
+    let (config, _) = store.config_mut::<DefaultGizmoConfigGroup>();
+    config.depth_bias = GIZMO_DEPTH_BIAS;
+    config.render_layers = overlay_layers();
+}
+
+// Gives the main camera a child camera that draws the overlay layer after
+// the map, onto the map's depth.
+fn attach_overlay_camera(
+    mut commands: Commands,
+    main: Query<Entity, With<MainCamera>>,
+    mut attached: Local<bool>,
+) {
+    if *attached {
+        return;
+    }
+    let Ok(main) = main.get_single() else {
+        return;
+    };
+    *attached = true;
+    let overlay = commands
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    order: OVERLAY_CAMERA_ORDER,
+                    clear_color: ClearColorConfig::None,
+                    ..default()
+                },
+                camera_3d: Camera3d {
+                    depth_load_op: Camera3dDepthLoadOp::Load,
+                    ..default()
+                },
+                ..default()
+            },
+            overlay_layers(),
+        ))
+        .id();
+    commands.entity(main).add_child(overlay);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::{LaneClass, Theme};
+    use crate::RoadLayer;
+
+    #[test]
+    fn levels_stack_up() {
+        // Each level is drawn over the one below it, and all over the map.
+        let levels = [
+            OverlayLevel::Surface,
+            OverlayLevel::Line,
+            OverlayLevel::Highlight,
+        ];
+        assert!(levels
+            .windows(2)
+            .all(|pair| pair[0].depth_bias() < pair[1].depth_bias()));
+        let theme = Theme::default();
+        assert_eq!(
+            RoadLayer::Surface(LaneClass::Driving)
+                .material(&theme)
+                .depth_bias,
+            0.0
+        );
+        let arrow = RoadLayer::Arrow.material(&theme);
+        assert_eq!(arrow.depth_bias, OverlayLevel::Surface.depth_bias());
+        assert!(RoadLayer::Arrow.is_overlay() && !RoadLayer::Backface.is_overlay());
+
+        let ribbon = overlay_material(Color::rgb(1.0, 0.5, 0.0), OverlayLevel::Line);
+        assert!(ribbon.unlit && ribbon.alpha_mode == AlphaMode::Add);
+    }
+}
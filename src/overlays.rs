@@ -0,0 +1,40 @@
+// Map overlays that can be switched on and off while viewing.
+//
+// Overlays are drawn on top of the road surface to show information that is
+// otherwise only found in the lane data. Like the debug views they are part
+// of the road meshes, so toggling one reloads the road tiles.
+//
+// Keys: A toggles lane direction arrows.
+
+use bevy::prelude::*;
+
+use crate::tiles::ReloadTiles;
+
+// Distance between direction arrows along a lane, in meters.
+pub const ARROW_SPACING: f64 = 25.0;
+
+// Which overlays are shown.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overlays {
+    pub arrows: bool,
+}
+
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Overlays>()
+            .add_systems(Update, toggle_overlays);
+    }
+}
+
+fn toggle_overlays(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut overlays: ResMut<Overlays>,
+    mut reload: EventWriter<ReloadTiles>,
+) {
+    if keys.just_pressed(KeyCode::KeyA) {
+        overlays.arrows = !overlays.arrows;
+        reload.send(ReloadTiles);
+    }
+}
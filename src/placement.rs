@@ -0,0 +1,392 @@
+// Street lights and trees along roads.
+//
+// `place light|tree <interval> [offset] [left|right]` in the console lines
+// one edge of the selected road (the right one unless told otherwise) with
+// street lights or trees every `interval` meters, `offset` meters outside
+// the outermost lane (1 m if not given). They go into the model as an
+// `<object>` with `<repeat>` records, one per stretch of the edge as for
+// barriers (see `barriers`), spaced so that the interval carries on across
+// the stretches. Running the command again replaces the objects of that
+// kind on that side; `place clear` removes the selected road's.
+//
+// Every copy of a repeated object of the map, placed or read, is drawn at
+// its own station: trees as a trunk and a crown, street lamps and poles as
+// a pole with a lamp head, and anything else as a box of the object's size.
+// The copies share their meshes and materials, so they are drawn instanced.
+// Objects an asset pack has a model for are drawn with it (see
+// `asset_packs`).
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::asset_packs::AssetPacks;
+use crate::barriers::edge_stretches;
+use crate::cross_section::cut;
+use crate::edit::NetworkChanged;
+use crate::odr::touch;
+use crate::origin::WorldPosition;
+use crate::selection::Selection;
+use crate::signals::{ObjectRepeat, Signal, SignalKind};
+use crate::xodr::ElementXml;
+use crate::RoadNetwork;
+
+// Gap between the road edge and placed objects if none is given, in meters.
+const DEFAULT_OFFSET: f64 = 1.0;
+
+// Size of copies whose object gives none, in meters.
+const DEFAULT_SIZE: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlacedKind {
+    Light,
+    Tree,
+}
+
+impl PlacedKind {
+    fn named(name: &str) -> Option<Self> {
+        match name {
+            "light" => Some(PlacedKind::Light),
+            "tree" => Some(PlacedKind::Tree),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PlacedKind::Light => "light",
+            PlacedKind::Tree => "tree",
+        }
+    }
+
+    // The OpenDRIVE object type and name.
+    fn object_type(self) -> (&'static str, &'static str) {
+        match self {
+            PlacedKind::Light => ("streetLamp", "streetLight"),
+            PlacedKind::Tree => ("tree", "tree"),
+        }
+    }
+
+    // Height and footprint radius, in meters.
+    fn size(self) -> (f64, f64) {
+        match self {
+            PlacedKind::Light => (8.0, 0.1),
+            PlacedKind::Tree => (6.0, 1.5),
+        }
+    }
+}
+
+// The ID of the objects of a kind placed on one side of a road.
+fn placed_id(kind: PlacedKind, road_id: u32, left: bool) -> String {
+    let side = if left { "left" } else { "right" };
+    format!("{}-{road_id}-{side}", kind.label())
+}
+
+// Objects every `interval` meters along one edge of a road, `offset`
+// meters outside it, or `None` if the road has no lanes on that side.
+pub fn generate(
+    network: &RoadNetwork,
+    road_id: u32,
+    kind: PlacedKind,
+    interval: f64,
+    offset: f64,
+    left: bool,
+) -> Option<Signal> {
+    let stretches = edge_stretches(network, road_id, left, offset);
+    let &(first_s, ..) = stretches.first()?;
+    let (height, radius) = kind.size();
+    let last = stretches.len() - 1;
+    let mut repeats = Vec::new();
+    for (i, &(start, end, t_start, t_end)) in stretches.iter().enumerate() {
+        // Copies on the end of a stretch belong to the next one.
+        let first = first_s + ((start - first_s) / interval - 1e-9).ceil() * interval;
+        let mut count = ((end - first) / interval + 1e-9).floor() as i64 + 1;
+        if i != last && first + (count - 1) as f64 * interval >= end - 1e-9 {
+            count -= 1;
+        }
+        if count <= 0 {
+            continue;
+        }
+        let length = (count - 1) as f64 * interval;
+        let t_at = |s: f64| t_start + (t_end - t_start) * (s - start) / (end - start);
+        repeats.push(ObjectRepeat {
+            s: first,
+            length,
+            distance: interval,
+            t_start: t_at(first),
+            t_end: t_at(first + length),
+            height_start: height,
+            height_end: height,
+            ..ObjectRepeat::default()
+        });
+    }
+    let first = repeats.first()?;
+    let (s, t) = (first.s, first.t_start);
+    let foot = cut(network, road_id, s)?;
+    let (type_code, name) = kind.object_type();
+    Some(Signal {
+        id: placed_id(kind, road_id, left),
+        name: name.to_string(),
+        road_id,
+        s,
+        t,
+        z_offset: 0.0,
+        height,
+        width: 0.0,
+        length: 0.0,
+        radius,
+        orientation: "none".to_string(),
+        kind: SignalKind::Object,
+        type_code: type_code.to_string(),
+        subtype: String::new(),
+        country: String::new(),
+        dynamic: false,
+        value: None,
+        unit: String::new(),
+        position: foot.reference + DVec3::Y.cross(foot.direction) * t,
+        repeats,
+        xml: ElementXml::None,
+    })
+}
+
+// `place` in the console: places objects along the selected road or
+// removes them.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let road_id = world
+        .resource::<Selection>()
+        .0
+        .map(|pick| pick.road_id)
+        .ok_or("select a road first")?;
+    let mut network = world.resource_mut::<RoadNetwork>();
+    let message = match args {
+        ["clear"] => {
+            let ids: Vec<String> = [PlacedKind::Light, PlacedKind::Tree]
+                .into_iter()
+                .flat_map(|kind| [true, false].map(|left| placed_id(kind, road_id, left)))
+                .collect();
+            network.signals.retain(|signal| !ids.contains(&signal.id));
+            format!("removed the placed objects of road {road_id}")
+        }
+        [kind, interval, rest @ ..] => {
+            let kind = PlacedKind::named(kind)
+                .ok_or_else(|| format!("unknown object `{kind}` (try light or tree)"))?;
+            let interval: f64 = interval
+                .parse()
+                .ok()
+                .filter(|interval| *interval > 0.0)
+                .ok_or_else(|| format!("invalid interval `{interval}`"))?;
+            let (offset, side) = match rest {
+                [offset, side @ ..] if offset.parse::<f64>().is_ok() => {
+                    (offset.parse().unwrap_or(DEFAULT_OFFSET), side)
+                }
+                side => (DEFAULT_OFFSET, side),
+            };
+            let left = match side {
+                [] | ["right"] => false,
+                ["left"] => true,
+                _ => return Err("expected left or right".to_string()),
+            };
+            let id = placed_id(kind, road_id, left);
+            network.signals.retain(|signal| signal.id != id);
+            let object = generate(&network, road_id, kind, interval, offset, left)
+                .ok_or_else(|| format!("road {road_id} has no lanes on that side"))?;
+            let count: usize = object
+                .repeats
+                .iter()
+                .map(|repeat| (repeat.length / repeat.distance).round() as usize + 1)
+                .sum();
+            network.signals.push(object);
+            format!("placed {count} {}s along road {road_id}", kind.label())
+        }
+        _ => {
+            return Err(
+                "expected light|tree <interval> [offset] [left|right], or clear".to_string(),
+            )
+        }
+    };
+    // The element as read no longer describes the road; exports write it
+    // from the model.
+    if let Some(info) = network.roads.get_mut(&road_id) {
+        info.xml.clear();
+    }
+
+    touch(world, road_id);
+    world.send_event(NetworkChanged {
+        changed: vec![road_id],
+    });
+    Ok(message)
+}
+
+// The copies of a repeated object, as their positions on the ground and
+// their heights; continuous repeats are left to `barriers`.
+pub fn copies(network: &RoadNetwork, object: &Signal) -> Vec<(DVec3, f64)> {
+    let mut out = Vec::new();
+    for repeat in object.repeats.iter().filter(|r| !r.is_continuous()) {
+        let count = (repeat.length / repeat.distance + 1e-9).floor() as usize + 1;
+        for i in 0..count {
+            let along = i as f64 * repeat.distance;
+            let f = if repeat.length > 0.0 {
+                along / repeat.length
+            } else {
+                0.0
+            };
+            let Some(cut) = cut(network, object.road_id, repeat.s + along) else {
+                continue;
+            };
+            let t = repeat.t_start + (repeat.t_end - repeat.t_start) * f;
+            let z = repeat.z_offset_start + (repeat.z_offset_end - repeat.z_offset_start) * f;
+            let height = if repeat.height_start > 0.0 || repeat.height_end > 0.0 {
+                repeat.height_start + (repeat.height_end - repeat.height_start) * f
+            } else {
+                object.height
+            };
+            out.push((
+                cut.reference + DVec3::Y.cross(cut.direction) * t + DVec3::Y * z,
+                height,
+            ));
+        }
+    }
+    out
+}
+
+// Marks the entity of a part of a copy.
+#[derive(Component)]
+struct PlacedCopy;
+
+// Meshes and materials of unit height, shared by all copies.
+#[derive(Resource)]
+struct CopyModels {
+    tree: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
+    lamp: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
+    other: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
+}
+
+// The material of lamp heads, which glow at night (see `night`).
+#[derive(Resource)]
+pub struct LampHeads(pub Handle<StandardMaterial>);
+
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (setup, spawn_copies).chain())
+            .add_systems(
+                Update,
+                (despawn_copies, spawn_copies).chain().run_if(
+                    on_event::<NetworkChanged>()
+                        .or_else(|packs: Res<AssetPacks>| packs.is_changed() && !packs.is_added()),
+                ),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            perceptual_roughness: 0.8,
+            ..default()
+        })
+    };
+    let bark = material(Color::rgb(0.36, 0.25, 0.16));
+    let leaves = material(Color::rgb(0.2, 0.45, 0.18));
+    let steel = material(Color::rgb(0.55, 0.56, 0.58));
+    let lamp = material(Color::rgb(1.0, 0.95, 0.8));
+    let concrete = material(Color::rgb(0.6, 0.6, 0.58));
+    commands.insert_resource(LampHeads(lamp.clone()));
+    // Parts stand on the origin and reach up to 1.
+    let mut mesh = |mesh: Mesh, up: f32| meshes.add(mesh.translated_by(Vec3::Y * up));
+    commands.insert_resource(CopyModels {
+        tree: vec![
+            (mesh(Cylinder::new(0.04, 0.4).into(), 0.2), bark),
+            (mesh(Sphere::new(0.25).into(), 0.7), leaves),
+        ],
+        lamp: vec![
+            (mesh(Cylinder::new(0.012, 1.0).into(), 0.5), steel),
+            (mesh(Cuboid::new(0.12, 0.03, 0.06).into(), 0.99), lamp),
+        ],
+        other: vec![(mesh(Cuboid::new(1.0, 1.0, 1.0).into(), 0.5), concrete)],
+    });
+}
+
+fn spawn_copies(
+    mut commands: Commands,
+    models: Res<CopyModels>,
+    packs: Res<AssetPacks>,
+    network: Res<RoadNetwork>,
+) {
+    for object in network
+        .signals
+        .iter()
+        .filter(|object| packs.asset(object).is_none())
+    {
+        let kind = object.type_code.to_ascii_lowercase();
+        let (parts, uniform) = match kind.as_str() {
+            "tree" | "vegetation" => (&models.tree, true),
+            "streetlamp" | "pole" => (&models.lamp, true),
+            _ => (&models.other, false),
+        };
+        for (at, height) in copies(&network, object) {
+            let height = if height > 0.0 { height } else { DEFAULT_SIZE } as f32;
+            let scale = if uniform {
+                Vec3::splat(height)
+            } else {
+                let across = 2.0 * object.half_width();
+                let size = |v: f64| {
+                    if v > 0.0 {
+                        v as f32
+                    } else {
+                        DEFAULT_SIZE as f32
+                    }
+                };
+                Vec3::new(size(across), height, size(object.length))
+            };
+            for (mesh, material) in parts {
+                commands.spawn((
+                    PbrBundle {
+                        mesh: mesh.clone(),
+                        material: material.clone(),
+                        transform: Transform::from_scale(scale),
+                        ..default()
+                    },
+                    WorldPosition(at),
+                    PlacedCopy,
+                ));
+            }
+        }
+    }
+}
+
+fn despawn_copies(mut commands: Commands, copies: Query<Entity, With<PlacedCopy>>) {
+    for entity in &copies {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_near, assert_points_near, load, TOLERANCE};
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    #[test]
+    fn placed_objects() {
+        let mut network = load("straight.xodr");
+        // Trees every 10 m, 1 m outside the right edge 6.5 m from the road.
+        let trees = generate(&network, 1, PlacedKind::Tree, 10.0, 1.0, false).unwrap();
+        assert_eq!(trees.type_code, "tree");
+        assert_near(trees.repeats[0].distance, 10.0, TOLERANCE);
+        let placed = copies(&network, &trees);
+        assert_eq!(placed.len(), 10);
+        assert_points_near(placed[1].0, DVec3::new(10.0, 0.0, 7.5));
+
+        network.signals.push(trees.clone());
+        let read = xodr::read_str(&xodr::to_xml(&network), &LoadTransform::default()).unwrap();
+        let read_trees = read.signals.iter().find(|s| s.id == trees.id).unwrap();
+        assert_eq!(copies(&read, read_trees).len(), 10);
+    }
+}
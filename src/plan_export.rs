@@ -0,0 +1,602 @@
+// Printable plans of the map.
+//
+// `export-plan` writes the network seen from above as a vector drawing, SVG
+// or PDF by the file's extension, at a scale such as 1:500, for
+// documentation and printing. The plan shows the lane surfaces with their
+// boundaries, the road marks as strokes of their painted width (see
+// `tessellation::painted_lines`), road names at the middle of each road,
+// written along it, and each junction's outline, dashed, with its ID. The
+// outline is the convex hull of the junction's connecting roads, which
+// overstates junctions with curved edges but never hides a part of one.
+// North is up, and the page is the map's extent with a margin around it.
+//
+// Colors are the theme's. The PDF is written directly: one page of paths,
+// with the labels in Helvetica, which PDF readers provide, so no font is
+// embedded and text outside Latin-1 is written as question marks.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::Color;
+
+use crate::labels::road_name;
+use crate::tessellation::{boundaries, painted_lines, point_at, MARKING_WIDTH, PLAIN};
+use crate::theme::{LaneClass, Theme};
+use crate::{xodr, RoadNetwork};
+
+// The scale without `--scale`: 1 m of the map to 1 mm of paper.
+pub const DEFAULT_SCALE: f64 = 1000.0;
+
+// Blank paper around the map, in millimeters.
+const MARGIN: f64 = 10.0;
+
+// Lane boundaries and junction outlines, in millimeters of paper.
+const BOUNDARY_WIDTH: f64 = 0.05;
+const OUTLINE_WIDTH: f64 = 0.3;
+const OUTLINE_DASH: [f64; 2] = [2.0, 1.0];
+
+// Label heights, in millimeters of paper.
+const ROAD_LABEL_SIZE: f64 = 2.5;
+const JUNCTION_LABEL_SIZE: f64 = 3.0;
+
+// PDF pages may be at most 200 inches on a side.
+const MAX_PDF_PAGE: f64 = 5080.0;
+
+const POINTS_PER_MM: f64 = 72.0 / 25.4;
+
+// What is drawn, in millimeters from the page's top left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Area {
+        points: Vec<DVec2>,
+        fill: Color,
+    },
+    Line {
+        points: Vec<DVec2>,
+        color: Color,
+        width: f64,
+        dash: Option<[f64; 2]>,
+    },
+    Dot {
+        center: DVec2,
+        radius: f64,
+        color: Color,
+    },
+    // Centered on `at`, turned clockwise by `angle` radians.
+    Text {
+        at: DVec2,
+        size: f64,
+        angle: f64,
+        text: String,
+        color: Color,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    // The page size, in millimeters.
+    pub width: f64,
+    pub height: f64,
+    // In drawing order.
+    pub shapes: Vec<Shape>,
+}
+
+// Parses a scale given as `1:500` or `500`.
+pub fn parse_scale(text: &str) -> Result<f64, String> {
+    let denominator = text.strip_prefix("1:").unwrap_or(text);
+    denominator
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|d| d.is_finite() && *d > 0.0)
+        .ok_or_else(|| format!("invalid scale `{text}`, expected e.g. 1:500"))
+}
+
+// Draws the network at 1:`scale`.
+pub fn plan(network: &RoadNetwork, scale: f64, theme: &Theme) -> Plan {
+    let flat = |p: DVec3| DVec2::new(p.x, -p.z);
+    let (min, max) = network
+        .segments
+        .iter()
+        .flat_map(|segment| segment.left_side.iter().chain(&segment.right_side))
+        .map(|p| flat(*p))
+        .fold(
+            (DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)),
+            |(min, max), p| (min.min(p), max.max(p)),
+        );
+    if !min.is_finite() {
+        return Plan {
+            width: 2.0 * MARGIN,
+            height: 2.0 * MARGIN,
+            shapes: Vec::new(),
+        };
+    }
+    let mm = 1000.0 / scale;
+    // Map frame to page, y down.
+    let page = |p: DVec3| DVec2::new(p.x - min.x, max.y + p.z) * mm + MARGIN;
+    let pages = |points: &[DVec3]| points.iter().map(|p| page(*p)).collect::<Vec<_>>();
+    let mut shapes = Vec::new();
+
+    for segment in &network.segments {
+        let (left, right) = boundaries(segment);
+        let mut points = pages(&left);
+        points.extend(pages(&right).into_iter().rev());
+        shapes.push(Shape::Area {
+            points,
+            fill: theme.lane_surface(LaneClass::of(&segment.lane_type)),
+        });
+    }
+    for segment in &network.segments {
+        let (left, right) = boundaries(segment);
+        for side in [left, right] {
+            shapes.push(Shape::Line {
+                points: pages(&side),
+                color: theme.marking,
+                width: BOUNDARY_WIDTH,
+                dash: None,
+            });
+        }
+    }
+    let marking = theme.marking.as_linear_rgba_f32();
+    for segment in &network.segments {
+        for (stroke, dash) in painted_lines(segment, MARKING_WIDTH) {
+            let color = if stroke.color == PLAIN {
+                theme.marking
+            } else {
+                let [r, g, b, a] = stroke.color;
+                Color::rgba_linear(
+                    r * marking[0],
+                    g * marking[1],
+                    b * marking[2],
+                    a * marking[3],
+                )
+            };
+            if stroke.raised {
+                let length: f64 = dash.windows(2).map(|w| w[0].distance(w[1])).sum();
+                if let Some((center, _)) = point_at(&dash, length / 2.0) {
+                    shapes.push(Shape::Dot {
+                        center: page(center),
+                        radius: stroke.width / 2.0 * mm,
+                        color,
+                    });
+                }
+                continue;
+            }
+            shapes.push(Shape::Line {
+                points: pages(&dash),
+                color,
+                width: stroke.width * mm,
+                dash: None,
+            });
+        }
+    }
+
+    // Junction outlines.
+    let mut junctions: BTreeMap<u32, Vec<DVec2>> = BTreeMap::new();
+    for segment in &network.segments {
+        let junction = network
+            .roads
+            .get(&segment.road_id)
+            .and_then(|info| info.junction);
+        if let Some(junction) = junction {
+            let (left, right) = boundaries(segment);
+            junctions
+                .entry(junction)
+                .or_default()
+                .extend(left.iter().chain(right.iter()).map(|p| page(*p)));
+        }
+    }
+    for (junction, points) in junctions {
+        let mut hull = convex_hull(points);
+        let Some(&first) = hull.first() else {
+            continue;
+        };
+        let center = hull.iter().copied().sum::<DVec2>() / hull.len() as f64;
+        hull.push(first);
+        shapes.push(Shape::Line {
+            points: hull,
+            color: theme.highlight,
+            width: OUTLINE_WIDTH,
+            dash: Some(OUTLINE_DASH),
+        });
+        shapes.push(Shape::Text {
+            at: center,
+            size: JUNCTION_LABEL_SIZE,
+            angle: 0.0,
+            text: format!("junction {junction}"),
+            color: theme.highlight,
+        });
+    }
+
+    // Road names, along the inner edge of the innermost lane at the middle
+    // of each road.
+    let mut roads: BTreeMap<u32, (f64, f64)> = BTreeMap::new();
+    for segment in &network.segments {
+        let span = roads
+            .entry(segment.road_id)
+            .or_insert((f64::INFINITY, f64::NEG_INFINITY));
+        *span = (span.0.min(segment.start_s), span.1.max(segment.end_s));
+    }
+    for (road_id, (start, end)) in roads {
+        let middle = (start + end) / 2.0;
+        let innermost = network
+            .segments
+            .iter()
+            .filter(|s| s.road_id == road_id && s.start_s <= middle && middle <= s.end_s)
+            .min_by_key(|s| s.lane_id.abs());
+        let Some(lane) = innermost else {
+            continue;
+        };
+        let edge = if lane.lane_id < 0 {
+            &lane.left_side
+        } else {
+            &lane.right_side
+        };
+        let Some((at, direction)) = point_at(edge, middle - lane.start_s) else {
+            continue;
+        };
+        // Turned along the road, the right way up.
+        let mut angle = (-direction.z).atan2(direction.x);
+        if angle.abs() > std::f64::consts::FRAC_PI_2 {
+            angle -= std::f64::consts::PI.copysign(angle);
+        }
+        shapes.push(Shape::Text {
+            at: page(at),
+            size: ROAD_LABEL_SIZE,
+            angle: -angle,
+            text: road_name(network, road_id),
+            color: theme.road_label,
+        });
+    }
+
+    let size = (max - min) * mm + 2.0 * MARGIN;
+    Plan {
+        width: size.x,
+        height: size.y,
+        shapes,
+    }
+}
+
+// The convex hull of points, counter-clockwise on the page (y down).
+pub fn convex_hull(mut points: Vec<DVec2>) -> Vec<DVec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull: Vec<DVec2> = Vec::new();
+    for pass in [false, true] {
+        let start = hull.len();
+        let ordered: Box<dyn Iterator<Item = &DVec2>> = if pass {
+            Box::new(points.iter().rev())
+        } else {
+            Box::new(points.iter())
+        };
+        for &p in ordered {
+            while hull.len() >= start + 2 {
+                let (a, b) = (hull[hull.len() - 2], hull[hull.len() - 1]);
+                if (b - a).perp_dot(p - a) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        hull.pop();
+    }
+    hull
+}
+
+// An sRGB color for SVG, with its opacity.
+fn svg_color(color: Color) -> (String, f32) {
+    let [r, g, b, a] = color.as_rgba_u8();
+    (format!("#{r:02x}{g:02x}{b:02x}"), a as f32 / 255.0)
+}
+
+fn svg_points(points: &[DVec2]) -> String {
+    let mut text = String::new();
+    for p in points {
+        let _ = write!(text, "{:.3},{:.3} ", p.x, p.y);
+    }
+    text.trim_end().to_string()
+}
+
+pub fn write_svg(plan: &Plan) -> String {
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.3}mm\" height=\"{h:.3}mm\" \
+         viewBox=\"0 0 {w:.3} {h:.3}\">\n",
+        w = plan.width,
+        h = plan.height
+    );
+    for shape in &plan.shapes {
+        let _ = match shape {
+            Shape::Area { points, fill } => {
+                let (fill, opacity) = svg_color(*fill);
+                writeln!(
+                    svg,
+                    "  <polygon points=\"{}\" fill=\"{fill}\" fill-opacity=\"{opacity}\"/>",
+                    svg_points(points)
+                )
+            }
+            Shape::Line {
+                points,
+                color,
+                width,
+                dash,
+            } => {
+                let (stroke, opacity) = svg_color(*color);
+                let dash = dash.map_or(String::new(), |[on, off]| {
+                    format!(" stroke-dasharray=\"{on} {off}\"")
+                });
+                writeln!(
+                    svg,
+                    "  <polyline points=\"{}\" fill=\"none\" stroke=\"{stroke}\" \
+                     stroke-opacity=\"{opacity}\" stroke-width=\"{width:.3}\"{dash}/>",
+                    svg_points(points)
+                )
+            }
+            Shape::Dot {
+                center,
+                radius,
+                color,
+            } => {
+                let (fill, opacity) = svg_color(*color);
+                writeln!(
+                    svg,
+                    "  <circle cx=\"{:.3}\" cy=\"{:.3}\" r=\"{radius:.3}\" fill=\"{fill}\" \
+                     fill-opacity=\"{opacity}\"/>",
+                    center.x, center.y
+                )
+            }
+            Shape::Text {
+                at,
+                size,
+                angle,
+                text,
+                color,
+            } => {
+                let (fill, opacity) = svg_color(*color);
+                writeln!(
+                    svg,
+                    "  <text x=\"{x:.3}\" y=\"{y:.3}\" font-family=\"Helvetica, Arial, sans-serif\" \
+                     font-size=\"{size}\" text-anchor=\"middle\" dominant-baseline=\"middle\" \
+                     fill=\"{fill}\" fill-opacity=\"{opacity}\" \
+                     transform=\"rotate({:.2} {x:.3} {y:.3})\">{}</text>",
+                    angle.to_degrees(),
+                    xodr::escape(text),
+                    x = at.x,
+                    y = at.y
+                )
+            }
+        };
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// A PDF string literal in WinAnsi, which Latin-1 letters share.
+fn pdf_string(text: &str) -> String {
+    let mut literal = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            ' '..='~' => literal.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(literal, "\\{:03o}", c as u32);
+            }
+            _ => literal.push('?'),
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+pub fn write_pdf(plan: &Plan) -> Vec<u8> {
+    let pt = |mm: f64| mm * POINTS_PER_MM;
+    // Page millimeters, y down, to PDF points, y up.
+    let point = |p: DVec2| format!("{:.2} {:.2}", pt(p.x), pt(plan.height - p.y));
+    let rgb = |color: Color| {
+        let [r, g, b, _] = color.as_rgba_f32();
+        format!("{r:.3} {g:.3} {b:.3}")
+    };
+    let mut content = String::from("1 J 1 j\n");
+    for shape in &plan.shapes {
+        let _ = match shape {
+            Shape::Area { points, fill } if points.len() >= 3 => {
+                let _ = write!(content, "{} rg {} m", rgb(*fill), point(points[0]));
+                for p in &points[1..] {
+                    let _ = write!(content, " {} l", point(*p));
+                }
+                writeln!(content, " h f")
+            }
+            Shape::Line {
+                points,
+                color,
+                width,
+                dash,
+            } if points.len() >= 2 => {
+                let dash = dash.map_or("[] 0 d".to_string(), |[on, off]| {
+                    format!("[{:.2} {:.2}] 0 d", pt(on), pt(off))
+                });
+                let _ = write!(
+                    content,
+                    "{} RG {:.3} w {dash} {} m",
+                    rgb(*color),
+                    pt(*width),
+                    point(points[0])
+                );
+                for p in &points[1..] {
+                    let _ = write!(content, " {} l", point(*p));
+                }
+                writeln!(content, " S")
+            }
+            Shape::Dot {
+                center,
+                radius,
+                color,
+            } => {
+                // A round dot: a zero-length line with round caps.
+                writeln!(
+                    content,
+                    "{} RG {:.3} w [] 0 d {p} m {p} l S",
+                    rgb(*color),
+                    pt(2.0 * radius),
+                    p = point(*center)
+                )
+            }
+            Shape::Text {
+                at,
+                size,
+                angle,
+                text,
+                color,
+            } => {
+                // Helvetica is about half an em wide per letter; centered by
+                // that estimate.
+                let (sin, cos) = (-angle).sin_cos();
+                let half = DVec2::new(
+                    pt(0.25 * size * text.chars().count() as f64),
+                    pt(0.35 * size),
+                );
+                let origin = DVec2::new(pt(at.x), pt(plan.height - at.y))
+                    - DVec2::new(cos * half.x - sin * half.y, sin * half.x + cos * half.y);
+                writeln!(
+                    content,
+                    "BT {} rg /F1 {:.2} Tf {cos:.4} {sin:.4} {:.4} {cos:.4} {:.2} {:.2} Tm {} Tj ET",
+                    rgb(*color),
+                    pt(*size),
+                    -sin,
+                    origin.x,
+                    origin.y,
+                    pdf_string(text)
+                )
+            }
+            _ => Ok(()),
+        };
+    }
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>",
+            pt(plan.width),
+            pt(plan.height)
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        let _ = write!(pdf, "{} 0 obj\n{object}\nendobj\n", i + 1);
+    }
+    let xref = pdf.len();
+    let _ = write!(pdf, "xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(pdf, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    pdf.into_bytes()
+}
+
+// Writes the plan of a network at 1:`scale`, as PDF for a `.pdf` file and
+// as SVG otherwise.
+pub fn write_plan(
+    network: &RoadNetwork,
+    path: &Path,
+    scale: f64,
+    theme: &Theme,
+) -> Result<(), String> {
+    let plan = plan(network, scale, theme);
+    let pdf = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+    let bytes = if pdf {
+        if plan.width.max(plan.height) > MAX_PDF_PAGE {
+            return Err(format!(
+                "at 1:{scale} the page would be {:.0} by {:.0} mm, and PDF pages go up to \
+                 {MAX_PDF_PAGE} mm; choose a smaller scale",
+                plan.width, plan.height
+            ));
+        }
+        write_pdf(&plan)
+    } else {
+        write_svg(&plan).into_bytes()
+    };
+    std::fs::write(path, bytes).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn scales() {
+        assert_eq!(parse_scale("1:500"), Ok(500.0));
+        assert_eq!(parse_scale("250"), Ok(250.0));
+        assert!(parse_scale("1:0").is_err());
+    }
+
+    #[test]
+    fn vector_plan() {
+        // 100 m by 10 m of road at 1:1000, with a 10 mm margin around it.
+        let theme = Theme::default();
+        let network = load("straight.xodr");
+        let drawn = plan(&network, 1000.0, &theme);
+        assert!((drawn.width - 120.0).abs() < 1e-6 && (drawn.height - 30.0).abs() < 1e-6);
+        let count = |kind: fn(&Shape) -> bool| drawn.shapes.iter().filter(|s| kind(s)).count();
+        assert_eq!(
+            count(|s| matches!(s, Shape::Area { .. })),
+            network.segments.len()
+        );
+        // Botts' dots are drawn as dots, one per meter or so.
+        assert!(count(|s| matches!(s, Shape::Dot { .. })) > 50);
+        assert!(drawn.shapes.iter().any(|s| matches!(
+            s,
+            Shape::Text { text, angle, .. } if text == "Straight" && *angle == 0.0
+        )));
+
+        let junction = plan(&load("junction.xodr"), 500.0, &theme);
+        let svg = write_svg(&junction);
+        assert!(svg.starts_with("<?xml") && svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("stroke-dasharray") && svg.contains(">junction "));
+
+        // Every object of the PDF is where its cross-reference table says.
+        let pdf = String::from_utf8(write_pdf(&junction)).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4") && pdf.ends_with("%%EOF\n"));
+        let xref = pdf
+            .rsplit("startxref\n")
+            .next()
+            .unwrap()
+            .lines()
+            .next()
+            .unwrap();
+        let table = &pdf[xref.parse::<usize>().unwrap()..];
+        for (i, line) in table.lines().skip(3).take(5).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(
+                pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)),
+                "{line}"
+            );
+        }
+        assert!(pdf.contains("(junction "));
+    }
+}
@@ -0,0 +1,725 @@
+// Point cloud overlays (LAS and PCD).
+//
+// Surveyed lidar data is drawn as a layer of single-pixel points on top of the
+// road meshes so that map geometry can be checked against reality. Points are
+// brought into the map frame (x east, y north, z up) by the layer's load
+// transform. When both the map and the cloud are georeferenced, the cloud by
+// the GeoTIFF keys of a LAS file or by `--proj`, the points are projected
+// into the map's coordinates first and placed like the map, so the two line
+// up without a hand-tuned offset. Like road meshes, the point mesh is built
+// around an f64 anchor, so precision holds even for large projected
+// coordinates.
+
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::geo::GeoReference;
+use crate::origin::WorldPosition;
+use crate::transform::LoadTransform;
+use crate::RoadNetwork;
+
+// Clouds larger than this are thinned out by uniform striding so the GPU
+// buffers stay manageable.
+const MAX_POINTS: usize = 4_000_000;
+
+// A point cloud file passed on the command line, with its load transform.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloudSource {
+    pub path: PathBuf,
+    pub transform: LoadTransform,
+    // The cloud's projection as a PROJ string, over the one in the file.
+    pub proj: Option<String>,
+}
+
+// Point cloud files passed on the command line.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PointCloudFiles(pub Vec<PointCloudSource>);
+
+// A loaded cloud: positions in the map frame plus optional per-point colors.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloud {
+    pub positions: Vec<DVec3>,
+    pub colors: Option<Vec<[f32; 3]>>,
+    // How the coordinates relate to latitude and longitude, if the file says.
+    pub geo: Option<GeoReference>,
+}
+
+// Loads the clouds listed in `PointCloudFiles` and spawns them as overlays.
+pub struct PointCloudPlugin;
+
+impl Plugin for PointCloudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PointCloudFiles>()
+            .add_systems(Startup, spawn_point_clouds);
+    }
+}
+
+// Marks an entity holding a point cloud layer.
+#[derive(Component)]
+pub struct PointCloudLayer;
+
+fn spawn_point_clouds(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    files: Res<PointCloudFiles>,
+    network: Res<RoadNetwork>,
+) {
+    // Vertex colors carry all the shading; lighting would only darken points.
+    let material = materials.add(StandardMaterial {
+        unlit: true,
+        ..default()
+    });
+
+    for source in &files.0 {
+        let path = &source.path;
+        let mut cloud = match load(path) {
+            Ok(cloud) => cloud,
+            Err(message) => {
+                error!("{}: {message}", path.display());
+                continue;
+            }
+        };
+        if let Some(proj) = &source.proj {
+            match GeoReference::parse(proj) {
+                Ok(geo) => cloud.geo = Some(geo),
+                Err(message) => error!("{}: {message}", path.display()),
+            }
+        }
+        info!("{}: {} points", path.display(), cloud.positions.len());
+        if cloud.geo.is_some() && network.geo.is_none() {
+            warn!(
+                "{}: the map is not georeferenced, so the cloud is placed by its load transform alone",
+                path.display()
+            );
+        }
+
+        let positions = register(&cloud, &source.transform, &network);
+        let Some(anchor) = positions.first().copied() else {
+            continue;
+        };
+
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(build_mesh(&cloud, &positions, anchor)),
+                material: material.clone(),
+                ..default()
+            },
+            WorldPosition(anchor),
+            PointCloudLayer,
+            source.transform,
+            Name::new(path.display().to_string()),
+        ));
+    }
+}
+
+// The viewer positions of a cloud's points. With both the cloud and the map
+// georeferenced, the points go through latitude and longitude into the map's
+// source coordinates and take the map's load transform; the cloud's own
+// transform applies before, as a correction in the cloud's coordinates.
+// Otherwise the cloud's transform alone places them.
+pub fn register(
+    cloud: &PointCloud,
+    transform: &LoadTransform,
+    network: &RoadNetwork,
+) -> Vec<DVec3> {
+    let (Some(from), Some(to)) = (&cloud.geo, &network.geo) else {
+        return cloud
+            .positions
+            .iter()
+            .map(|p| transform.viewer_position(*p))
+            .collect();
+    };
+    cloud
+        .positions
+        .iter()
+        .map(|p| {
+            let p = transform.apply(*p);
+            let ground = if from.proj == to.proj {
+                p.truncate()
+            } else {
+                to.project(from.to_geographic(p.truncate()))
+            };
+            network.transform.viewer_position(ground.extend(p.z))
+        })
+        .collect()
+}
+
+// Builds a point-list mesh from viewer-frame positions, relative to `anchor`.
+fn build_mesh(cloud: &PointCloud, positions: &[DVec3], anchor: DVec3) -> Mesh {
+    let positions: Vec<[f32; 3]> = positions
+        .iter()
+        .map(|p| (*p - anchor).as_vec3().to_array())
+        .collect();
+
+    // Without colors in the file, shade by height so structure stays visible.
+    let colors: Vec<[f32; 4]> = match &cloud.colors {
+        Some(colors) => colors.iter().map(|[r, g, b]| [*r, *g, *b, 1.0]).collect(),
+        None => {
+            let (low, high) = cloud
+                .positions
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+                    (lo.min(p.z), hi.max(p.z))
+                });
+            let range = (high - low).max(1e-6);
+            cloud
+                .positions
+                .iter()
+                .map(|p| {
+                    let t = ((p.z - low) / range) as f32;
+                    Color::hsl(240.0 * (1.0 - t), 0.9, 0.5).as_rgba_f32()
+                })
+                .collect()
+        }
+    };
+
+    Mesh::new(
+        PrimitiveTopology::PointList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+}
+
+// Loads a point cloud, choosing the reader by file extension.
+pub fn load(path: &Path) -> Result<PointCloud, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let mut cloud = match extension.as_deref() {
+        Some("las") => read_las(&bytes)?,
+        Some("laz") => {
+            return Err("LAZ is compressed; decompress it to LAS first (e.g. `laszip`)".into())
+        }
+        Some("pcd") => read_pcd(&bytes)?,
+        _ => return Err("unsupported point cloud format".into()),
+    };
+    thin(&mut cloud);
+    Ok(cloud)
+}
+
+// Keeps every n-th point so that at most `MAX_POINTS` remain.
+fn thin(cloud: &mut PointCloud) {
+    let stride = cloud.positions.len().div_ceil(MAX_POINTS);
+    if stride <= 1 {
+        return;
+    }
+    cloud.positions = cloud.positions.iter().copied().step_by(stride).collect();
+    if let Some(colors) = &mut cloud.colors {
+        *colors = colors.iter().copied().step_by(stride).collect();
+    }
+}
+
+// Reads an uncompressed LAS file (versions 1.0 to 1.4, all point formats).
+fn read_las(bytes: &[u8]) -> Result<PointCloud, String> {
+    if bytes.get(0..4) != Some(b"LASF") {
+        return Err("not a LAS file".into());
+    }
+    let u8_at = |at: usize| bytes.get(at).copied().ok_or("truncated LAS header");
+    let u16_at =
+        |at: usize| -> Result<u16, &str> { Ok(u16::from_le_bytes([u8_at(at)?, u8_at(at + 1)?])) };
+    let u32_at = |at: usize| -> Result<u32, &str> {
+        Ok(u32::from_le_bytes([
+            u8_at(at)?,
+            u8_at(at + 1)?,
+            u8_at(at + 2)?,
+            u8_at(at + 3)?,
+        ]))
+    };
+    let f64_at = |at: usize| -> Result<f64, &str> {
+        let slice = bytes.get(at..at + 8).ok_or("truncated LAS header")?;
+        Ok(f64::from_le_bytes(slice.try_into().unwrap()))
+    };
+
+    let data_offset = u32_at(96)? as usize;
+    // The top bits flag compression; LAZ files renamed to .las end up here.
+    let format = u8_at(104)?;
+    if format & 0x80 != 0 {
+        return Err("LAS point data is compressed (LAZ)".into());
+    }
+    let record_length = u16_at(105)? as usize;
+    // Records may carry extra bytes after the fields of their format, but
+    // never fewer.
+    let minimum = match format {
+        0 => 20,
+        1 => 28,
+        2 => 26,
+        3 => 34,
+        4 => 57,
+        5 => 63,
+        6 => 30,
+        7 => 36,
+        8 => 38,
+        9 => 59,
+        10 => 67,
+        _ => return Err(format!("unknown LAS point format {format}")),
+    };
+    if record_length < minimum {
+        return Err(format!(
+            "LAS point records of {record_length} bytes are too short for point format {format}"
+        ));
+    }
+    let mut count = u32_at(107)? as usize;
+    // LAS 1.4 moved the point count to a 64-bit field for large files.
+    if count == 0 && u8_at(25)? >= 4 {
+        let slice = bytes.get(247..255).ok_or("truncated LAS header")?;
+        count = u64::from_le_bytes(slice.try_into().unwrap()) as usize;
+    }
+    let scale = DVec3::new(f64_at(131)?, f64_at(139)?, f64_at(147)?);
+    let offset = DVec3::new(f64_at(155)?, f64_at(163)?, f64_at(171)?);
+
+    // Byte offset of the 16-bit RGB triple within a record, if the format has one.
+    let rgb_offset = match format {
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        _ => None,
+    };
+
+    let records = bytes
+        .get(data_offset..)
+        .ok_or("LAS point data offset is past the end of the file")?;
+    let count = count.min(records.len() / record_length);
+
+    let mut cloud = PointCloud {
+        positions: Vec::with_capacity(count),
+        colors: rgb_offset.map(|_| Vec::with_capacity(count)),
+        geo: las_projection(bytes, u16_at(94)? as usize, u32_at(100)?),
+    };
+    for record in records.chunks_exact(record_length).take(count) {
+        let i32_at = |at: usize| i32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+        let raw = DVec3::new(i32_at(0) as f64, i32_at(4) as f64, i32_at(8) as f64);
+        cloud.positions.push(raw * scale + offset);
+
+        if let (Some(colors), Some(at)) = (&mut cloud.colors, rgb_offset) {
+            let channel = |i: usize| {
+                u16::from_le_bytes([record[at + 2 * i], record[at + 2 * i + 1]]) as f32 / 65535.0
+            };
+            colors.push([channel(0), channel(1), channel(2)]);
+        }
+    }
+    Ok(cloud)
+}
+
+// The projection named by the GeoTIFF keys among the variable length records
+// after the header, if it is one `GeoReference` knows.
+fn las_projection(bytes: &[u8], header_size: usize, records: u32) -> Option<GeoReference> {
+    let u16_at = |data: &[u8], at: usize| {
+        data.get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+    };
+    let mut at = header_size;
+    for _ in 0..records {
+        let header = bytes.get(at..at + 54)?;
+        let length = u16_at(header, 20)? as usize;
+        let data = bytes.get(at + 54..at + 54 + length)?;
+        at += 54 + length;
+        if !header[2..18].starts_with(b"LASF_Projection") || u16_at(header, 18)? != 34735 {
+            continue;
+        }
+        // Four numbers of directory header, then four per key: the key,
+        // where its value is (0 for inline) and the count and value.
+        let keys = u16_at(data, 6)? as usize;
+        for key in 0..keys {
+            let entry = 8 + 8 * key;
+            // ProjectedCSTypeGeoKey.
+            if u16_at(data, entry)? == 3072 && u16_at(data, entry + 2)? == 0 {
+                return GeoReference::from_epsg(u16_at(data, entry + 6)? as u32);
+            }
+        }
+    }
+    None
+}
+
+// Reads a PCD file with `ascii` or uncompressed `binary` data. Only the x, y, z
+// and (packed) rgb fields are used; any others are skipped.
+fn read_pcd(bytes: &[u8]) -> Result<PointCloud, String> {
+    let mut fields: Vec<String> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let mut points: Option<usize> = None;
+    let (mut width, mut height): (Option<usize>, Option<usize>) = (None, None);
+
+    // The header is line based text ending with the DATA line.
+    let mut cursor = 0;
+    let data_kind = loop {
+        let end = bytes[cursor..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| cursor + i)
+            .ok_or("PCD header has no DATA line")?;
+        let line = String::from_utf8_lossy(&bytes[cursor..end])
+            .trim()
+            .to_string();
+        cursor = end + 1;
+
+        let mut words = line.split_whitespace();
+        let values = |words: std::str::SplitWhitespace| -> Vec<String> {
+            words.map(str::to_string).collect()
+        };
+        match words.next() {
+            Some("FIELDS") => fields = values(words),
+            Some("SIZE") => {
+                sizes = values(words)
+                    .iter()
+                    .filter_map(|v| v.parse().ok())
+                    .collect()
+            }
+            Some("TYPE") => types = values(words),
+            Some("COUNT") => {
+                counts = values(words)
+                    .iter()
+                    .filter_map(|v| v.parse().ok())
+                    .collect()
+            }
+            Some("WIDTH") => width = words.next().and_then(|v| v.parse().ok()),
+            Some("HEIGHT") => height = words.next().and_then(|v| v.parse().ok()),
+            Some("POINTS") => points = words.next().and_then(|v| v.parse().ok()),
+            Some("DATA") => break words.next().unwrap_or("").to_string(),
+            _ => {}
+        }
+    };
+    if counts.is_empty() {
+        counts = vec![1; fields.len()];
+    }
+    // The point count is only a claim about the data that follows, so it
+    // must agree with the cloud's dimensions and is never trusted for
+    // allocation.
+    let points = match (points, width.zip(height)) {
+        (Some(points), Some((width, height))) if width.checked_mul(height) != Some(points) => {
+            return Err(format!(
+                "PCD header gives {points} points for a {width} by {height} cloud"
+            ));
+        }
+        (Some(points), _) => points,
+        (None, Some((width, height))) => width.saturating_mul(height),
+        (None, None) => usize::MAX,
+    };
+    if sizes.len() != fields.len() || types.len() != fields.len() || counts.len() != fields.len() {
+        return Err("PCD header fields, sizes and types disagree".into());
+    }
+
+    let index = |name: &str| fields.iter().position(|f| f == name);
+    let (Some(x), Some(y), Some(z)) = (index("x"), index("y"), index("z")) else {
+        return Err("PCD file has no x/y/z fields".into());
+    };
+    let rgb = index("rgb").or_else(|| index("rgba"));
+    if data_kind == "binary" {
+        for field in [x, y, z] {
+            if counts[field] == 0
+                || !matches!(
+                    (types[field].as_str(), sizes[field]),
+                    ("F", 4 | 8) | ("I", 4) | ("U", 4)
+                )
+            {
+                return Err(format!(
+                    "PCD field {} of type {} and size {} is not supported",
+                    fields[field], types[field], sizes[field]
+                ));
+            }
+        }
+        if let Some(field) = rgb.filter(|&field| sizes[field] != 4 || counts[field] == 0) {
+            return Err(format!(
+                "PCD field {} must hold one packed 4 byte color",
+                fields[field]
+            ));
+        }
+    }
+
+    let mut cloud = PointCloud {
+        positions: Vec::new(),
+        colors: rgb.map(|_| Vec::new()),
+        // PCD has no way to say; `--proj` gives one.
+        geo: None,
+    };
+
+    match data_kind.as_str() {
+        "ascii" => {
+            let text = String::from_utf8_lossy(&bytes[cursor..]);
+            for line in text.lines().filter(|l| !l.trim().is_empty()).take(points) {
+                let values: Vec<&str> = line.split_whitespace().collect();
+                // Fields with COUNT > 1 take several columns.
+                let column = |field: usize| counts[..field].iter().sum::<usize>();
+                let number = |field: usize| {
+                    values
+                        .get(column(field))
+                        .and_then(|v| v.parse::<f64>().ok())
+                        .ok_or_else(|| format!("bad PCD data line `{line}`"))
+                };
+                cloud
+                    .positions
+                    .push(DVec3::new(number(x)?, number(y)?, number(z)?));
+                if let (Some(colors), Some(field)) = (&mut cloud.colors, rgb) {
+                    // Packed colors are written as the float reinterpretation
+                    // of the 0x00RRGGBB integer.
+                    let packed = (number(field)? as f32).to_bits();
+                    colors.push(unpack_rgb(packed));
+                }
+            }
+        }
+        "binary" => {
+            let stride: usize = sizes.iter().zip(&counts).map(|(s, c)| s * c).sum();
+            // A point count beyond the data is cut to the records there are.
+            let records = points.min(bytes[cursor..].len() / stride);
+            cloud.positions.reserve(records);
+            if let Some(colors) = &mut cloud.colors {
+                colors.reserve(records);
+            }
+            let offset = |field: usize| {
+                sizes[..field]
+                    .iter()
+                    .zip(&counts[..field])
+                    .map(|(s, c)| s * c)
+                    .sum::<usize>()
+            };
+            let read = |record: &[u8], field: usize| -> f64 {
+                let at = offset(field);
+                let raw = &record[at..at + sizes[field]];
+                match (types[field].as_str(), sizes[field]) {
+                    ("F", 4) => f32::from_le_bytes(raw.try_into().unwrap()) as f64,
+                    ("F", 8) => f64::from_le_bytes(raw.try_into().unwrap()),
+                    ("I", 4) => i32::from_le_bytes(raw.try_into().unwrap()) as f64,
+                    _ => u32::from_le_bytes(raw.try_into().unwrap()) as f64,
+                }
+            };
+            for record in bytes[cursor..].chunks_exact(stride).take(records) {
+                cloud.positions.push(DVec3::new(
+                    read(record, x),
+                    read(record, y),
+                    read(record, z),
+                ));
+                if let (Some(colors), Some(field)) = (&mut cloud.colors, rgb) {
+                    let at = offset(field);
+                    let packed = u32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+                    colors.push(unpack_rgb(packed));
+                }
+            }
+        }
+        other => return Err(format!("unsupported PCD data encoding `{other}`")),
+    }
+
+    Ok(cloud)
+}
+
+// Splits a packed 0x00RRGGBB color into normalized channels.
+fn unpack_rgb(packed: u32) -> [f32; 3] {
+    let channel = |shift: u32| ((packed >> shift) & 0xff) as f32 / 255.0;
+    [channel(16), channel(8), channel(0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::DVec2;
+
+    use super::*;
+
+    // A LAS 1.2 file in point format 2 (XYZ plus RGB), scaled to
+    // centimeters around an offset of (1000, 2000, 0).
+    fn las(points: &[([i32; 3], [u16; 3])]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 227];
+        bytes[0..4].copy_from_slice(b"LASF");
+        bytes[24] = 1;
+        bytes[25] = 2;
+        bytes[94..96].copy_from_slice(&227u16.to_le_bytes());
+        bytes[96..100].copy_from_slice(&227u32.to_le_bytes());
+        bytes[104] = 2;
+        bytes[105..107].copy_from_slice(&26u16.to_le_bytes());
+        bytes[107..111].copy_from_slice(&(points.len() as u32).to_le_bytes());
+        for (i, value) in [0.01, 0.01, 0.01, 1000.0, 2000.0, 0.0].iter().enumerate() {
+            bytes[131 + 8 * i..139 + 8 * i].copy_from_slice(&f64::to_le_bytes(*value));
+        }
+        for (xyz, rgb) in points {
+            let mut record = vec![0u8; 26];
+            for (i, v) in xyz.iter().enumerate() {
+                record[4 * i..4 * i + 4].copy_from_slice(&v.to_le_bytes());
+            }
+            for (i, v) in rgb.iter().enumerate() {
+                record[20 + 2 * i..22 + 2 * i].copy_from_slice(&v.to_le_bytes());
+            }
+            bytes.extend(record);
+        }
+        bytes
+    }
+
+    #[test]
+    fn las_points_are_scaled_and_colored() {
+        let bytes = las(&[
+            ([150, -250, 1000], [65535, 0, 0]),
+            ([0, 0, 0], [0, 0, 65535]),
+        ]);
+        let cloud = read_las(&bytes).unwrap();
+        assert_eq!(
+            cloud.positions,
+            [
+                DVec3::new(1001.5, 1997.5, 10.0),
+                DVec3::new(1000.0, 2000.0, 0.0)
+            ]
+        );
+        assert_eq!(cloud.colors.unwrap(), [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]);
+
+        // A point count beyond the data is cut to the records there are.
+        let mut short = bytes.clone();
+        short.truncate(227 + 26);
+        assert_eq!(read_las(&short).unwrap().positions.len(), 1);
+
+        let mut compressed = bytes.clone();
+        compressed[104] |= 0x80;
+        assert!(read_las(&compressed).is_err());
+        assert!(read_las(b"LASF").is_err());
+        assert!(read_las(b"PK\x03\x04").is_err());
+    }
+
+    #[test]
+    fn las_records_too_short_are_refused() {
+        for length in [0u16, 12, 25] {
+            let mut bytes = las(&[([0; 3], [0; 3])]);
+            bytes[105..107].copy_from_slice(&length.to_le_bytes());
+            assert!(read_las(&bytes).is_err(), "{length} byte records");
+        }
+        let mut bytes = las(&[([0; 3], [0; 3])]);
+        bytes[104] = 11;
+        assert!(read_las(&bytes).is_err());
+    }
+
+    #[test]
+    fn las_projection_from_geo_keys() {
+        let mut bytes = las(&[([0; 3], [0; 3])]);
+        let mut keys: Vec<u8> = Vec::new();
+        // Directory version 1.1.0 with two keys: the model type (projected)
+        // and the projected system, UTM zone 33 north.
+        for value in [1u16, 1, 0, 2, 1024, 0, 1, 1, 3072, 0, 1, 32633] {
+            keys.extend(value.to_le_bytes());
+        }
+        let mut record = vec![0u8; 54];
+        record[2..17].copy_from_slice(b"LASF_Projection");
+        record[18..20].copy_from_slice(&34735u16.to_le_bytes());
+        record[20..22].copy_from_slice(&(keys.len() as u16).to_le_bytes());
+        record.extend(keys);
+        let offset = 227 + record.len() as u32;
+        bytes.splice(227..227, record);
+        bytes[96..100].copy_from_slice(&offset.to_le_bytes());
+        bytes[100..104].copy_from_slice(&1u32.to_le_bytes());
+
+        let cloud = read_las(&bytes).unwrap();
+        assert_eq!(cloud.positions, [DVec3::new(1000.0, 2000.0, 0.0)]);
+        assert_eq!(cloud.geo.unwrap().proj, "+proj=utm +zone=33");
+        assert_eq!(read_las(&las(&[])).unwrap().geo, None);
+    }
+
+    #[test]
+    fn georeferenced_clouds_are_registered_with_the_map() {
+        let map = GeoReference::parse("+proj=tmerc +lat_0=48 +lon_0=11").unwrap();
+        let utm = GeoReference::parse("+proj=utm +zone=32").unwrap();
+        // The map's origin, in UTM.
+        let origin = utm.project(DVec2::new(11.0, 48.0));
+        let network = RoadNetwork {
+            geo: Some(map),
+            transform: LoadTransform {
+                offset: DVec3::new(10.0, 0.0, 0.0),
+                ..default()
+            },
+            ..default()
+        };
+        let cloud = PointCloud {
+            positions: vec![origin.extend(2.0)],
+            colors: None,
+            geo: Some(utm),
+        };
+        let shift = LoadTransform {
+            offset: DVec3::new(0.0, 0.0, 0.5),
+            ..default()
+        };
+        // Onto the map's origin, moved with the map and lifted by the cloud's
+        // own correction; the viewer is Y-up with north along -z.
+        let placed = register(&cloud, &shift, &network)[0];
+        assert!(
+            placed.distance(DVec3::new(10.0, 2.5, 0.0)) < 1e-3,
+            "{placed}"
+        );
+
+        // Without a georeference on the map, the cloud's transform alone.
+        let placed = register(&cloud, &shift, &RoadNetwork::default())[0];
+        assert_eq!(placed, DVec3::new(origin.x, 2.5, -origin.y));
+    }
+
+    #[test]
+    fn pcd_ascii_and_binary() {
+        let ascii = "# .PCD v0.7\nVERSION 0.7\nFIELDS x y z rgb\nSIZE 4 4 4 4\nTYPE F F F F\n\
+                     COUNT 1 1 1 1\nWIDTH 2\nHEIGHT 1\nPOINTS 2\nDATA ascii\n\
+                     1 2 3 4.2108e+06\n-1.5 0 0.25 0\n";
+        let cloud = read_pcd(ascii.as_bytes()).unwrap();
+        assert_eq!(
+            cloud.positions,
+            [DVec3::new(1.0, 2.0, 3.0), DVec3::new(-1.5, 0.0, 0.25)]
+        );
+        assert_eq!(cloud.colors.as_ref().unwrap()[1], [0.0; 3]);
+
+        let mut binary = b"FIELDS x y z intensity rgb\nSIZE 4 4 8 2 4\nTYPE F F F U U\n\
+                           POINTS 1\nDATA binary\n"
+            .to_vec();
+        binary.extend(1.5f32.to_le_bytes());
+        binary.extend((-2.0f32).to_le_bytes());
+        binary.extend(0.5f64.to_le_bytes());
+        binary.extend(7u16.to_le_bytes());
+        binary.extend(0x00ff8000u32.to_le_bytes());
+        let cloud = read_pcd(&binary).unwrap();
+        assert_eq!(cloud.positions, [DVec3::new(1.5, -2.0, 0.5)]);
+        assert_eq!(cloud.colors.unwrap(), [[1.0, 128.0 / 255.0, 0.0]]);
+
+        assert!(read_pcd(b"FIELDS x y\nSIZE 4 4\nTYPE F F\nDATA ascii\n").is_err());
+        assert!(read_pcd(b"FIELDS x y z\nSIZE 4 4\nTYPE F F F\nDATA ascii\n").is_err());
+        assert!(
+            read_pcd(b"FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nDATA binary_compressed\n").is_err()
+        );
+        assert!(read_pcd(b"FIELDS x y z\n").is_err());
+        // Colors packed in fewer than four bytes, and coordinates in a type
+        // the reader does not take.
+        assert!(read_pcd(
+            b"FIELDS x y z rgb\nSIZE 4 4 4 2\nTYPE F F F U\nPOINTS 1\nDATA binary\n\
+              \0\0\0\0\0\0\0\0\0\0\0\0\0\0"
+        )
+        .is_err());
+        assert!(read_pcd(b"FIELDS x y z\nSIZE 2 4 4\nTYPE I F F\nDATA binary\n").is_err());
+    }
+
+    #[test]
+    fn pcd_point_counts_are_not_trusted() {
+        // Far more points claimed than the data holds: only the records
+        // there are are read.
+        let mut binary = b"FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nWIDTH 999999999999999\n\
+                           HEIGHT 1\nPOINTS 999999999999999\nDATA binary\n"
+            .to_vec();
+        for v in [1.0f32, 2.0, 3.0] {
+            binary.extend(v.to_le_bytes());
+        }
+        let cloud = read_pcd(&binary).unwrap();
+        assert_eq!(cloud.positions, [DVec3::new(1.0, 2.0, 3.0)]);
+
+        let ascii = "FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nPOINTS 999999999999999\n\
+                     DATA ascii\n1 2 3\n";
+        assert_eq!(read_pcd(ascii.as_bytes()).unwrap().positions.len(), 1);
+
+        // Point counts that disagree with the dimensions.
+        for dimensions in [
+            "WIDTH 2\nHEIGHT 1\n",
+            "WIDTH 18446744073709551615\nHEIGHT 2\n",
+        ] {
+            let header = format!(
+                "FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\n{dimensions}POINTS 1\nDATA ascii\n1 2 3\n"
+            );
+            assert!(read_pcd(header.as_bytes()).is_err(), "{dimensions}");
+        }
+        // Coordinates that take no bytes.
+        assert!(
+            read_pcd(b"FIELDS x y z\nSIZE 4 4 4\nTYPE F F F\nCOUNT 0 0 0\nDATA binary\n").is_err()
+        );
+    }
+}
@@ -0,0 +1,391 @@
+// Right-of-way overlay, built as a viewer extension (see `extensions`).
+//
+// Infers which movements through each junction have priority and draws
+// every movement (a driving lane of a connecting road, see `conflicts`) as
+// an arrow colored by its rule. A movement takes the rule of the lane it
+// enters from, decided by the first of these that applies:
+//
+// - a traffic light, stop sign or yield sign facing the entering lane
+//   within `SIGN_REACH` of the junction;
+// - the junction's `<priority high low>` records, naming either the
+//   connecting road or the incoming road (numeric road IDs only);
+// - other entries of the junction having stop or yield signs, which makes
+//   the unsigned ones the main road.
+//
+// Movements no rule covers are drawn gray, and their junction gets a ring
+// in the error color.
+//
+// Keys: R shows or hides the right-of-way arrows.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::conflicts::{self, END_TOLERANCE};
+use crate::extensions::{overlay_shown, AddOverlay, RsodrPlugin};
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::signals::SignalKind;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork, RoadSegment};
+
+const NAME: &str = "priority";
+
+// Signs further than this from the junction, in meters, do not govern it.
+const SIGN_REACH: f64 = 50.0;
+
+// Lifts the arrows off the road surface, in meters.
+const ARROW_LIFT: f32 = 0.15;
+
+// Length of an arrow's tip, in meters.
+const TIP_LENGTH: f32 = 1.0;
+
+// Radius of the ring around a junction without a rule, in meters.
+const RING_RADIUS: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    // Controlled by traffic lights.
+    Signalized,
+    Priority,
+    Yield,
+    Stop,
+    // No rule could be determined.
+    Unknown,
+}
+
+impl Rule {
+    fn color(self, theme: &Theme) -> Color {
+        match self {
+            Rule::Signalized => theme.info,
+            Rule::Priority => theme.pass,
+            Rule::Yield => theme.warning,
+            Rule::Stop => theme.error,
+            Rule::Unknown => Color::GRAY,
+        }
+    }
+}
+
+// A movement through a junction and its rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    pub junction: u32,
+    // The connecting lane and the lane entering it, as indices into
+    // `RoadNetwork::segments`.
+    pub path: usize,
+    pub entry: Option<usize>,
+    pub rule: Rule,
+}
+
+// The rules of the movements through a junction.
+pub fn movements(network: &RoadNetwork, junction: u32) -> Vec<Movement> {
+    let records = priority_records(network, junction);
+    let entries: Vec<usize> = conflicts::stop_lines(network, junction)
+        .into_iter()
+        .map(|stop| stop.segment)
+        .collect();
+    let signed: Vec<Option<Rule>> = entries
+        .iter()
+        .map(|&entry| sign_rule(network, &network.segments[entry]))
+        .collect();
+    let any_yield = signed
+        .iter()
+        .any(|rule| matches!(rule, Some(Rule::Yield | Rule::Stop)));
+
+    conflicts::paths(network, junction)
+        .into_iter()
+        .map(|path| {
+            let lane = &network.segments[path];
+            let start = conflicts::path(lane).first().copied();
+            let entry = entries.iter().position(|&entry| {
+                let end = conflicts::path(&network.segments[entry]).last().copied();
+                matches!((start, end), (Some(a), Some(b)) if a.distance(b) < END_TOLERANCE)
+            });
+            let incoming = entry.map(|k| network.segments[entries[k]].road_id);
+            let recorded = |road: u32| {
+                records.iter().find_map(|&(high, low)| {
+                    if high == road {
+                        Some(Rule::Priority)
+                    } else if low == road {
+                        Some(Rule::Yield)
+                    } else {
+                        None
+                    }
+                })
+            };
+            let rule = entry
+                .and_then(|k| signed[k])
+                .or_else(|| recorded(lane.road_id))
+                .or_else(|| incoming.and_then(recorded))
+                .or_else(|| (entry.is_some() && any_yield).then_some(Rule::Priority))
+                .unwrap_or(Rule::Unknown);
+            Movement {
+                junction,
+                path,
+                entry: entry.map(|k| entries[k]),
+                rule,
+            }
+        })
+        .collect()
+}
+
+// The rule the signs facing a lane near its travel end give it.
+fn sign_rule(network: &RoadNetwork, lane: &RoadSegment) -> Option<Rule> {
+    let forward = lane.follows_reference();
+    let end = if forward { lane.end_s } else { lane.start_s };
+    network
+        .signals
+        .iter()
+        .filter(|signal| signal.road_id == lane.road_id)
+        .filter(|signal| match signal.orientation.trim() {
+            "+" => forward,
+            "-" => !forward,
+            _ => true,
+        })
+        .filter(|signal| {
+            let before = if forward {
+                end - signal.s
+            } else {
+                signal.s - end
+            };
+            (-END_TOLERANCE..=SIGN_REACH).contains(&before)
+        })
+        .filter_map(|signal| match signal.kind {
+            SignalKind::TrafficLight => Some(Rule::Signalized),
+            SignalKind::Stop => Some(Rule::Stop),
+            SignalKind::Yield => Some(Rule::Yield),
+            _ => None,
+        })
+        // A light overrides signs, which only apply when it is off.
+        .min_by_key(|rule| match rule {
+            Rule::Signalized => 0,
+            Rule::Stop => 1,
+            _ => 2,
+        })
+}
+
+// The (high, low) road pairs of a junction's `<priority>` records.
+fn priority_records(network: &RoadNetwork, junction: u32) -> Vec<(u32, u32)> {
+    let Some(xml) = network.junctions.get(&junction).and_then(|xml| xml.text()) else {
+        return Vec::new();
+    };
+    let mut reader = Reader::from_str(&xml);
+    let mut records = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) if e.name().as_ref() == b"priority" => {
+                let road = |name: &str| {
+                    e.try_get_attribute(name)
+                        .ok()
+                        .flatten()
+                        .and_then(|a| a.unescape_value().ok())
+                        .and_then(|v| v.trim().parse::<u32>().ok())
+                };
+                if let (Some(high), Some(low)) = (road("high"), road("low")) {
+                    records.push((high, low));
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    records
+}
+
+// The movements of every junction in the network, and the centers of the
+// junctions where some movement has no rule.
+#[derive(Resource, Default)]
+struct Movements {
+    movements: Vec<Movement>,
+    unruled: Vec<DVec3>,
+}
+
+pub struct PriorityOverlay;
+
+impl RsodrPlugin for PriorityOverlay {
+    fn name(&self) -> &'static str {
+        "right-of-way overlay"
+    }
+
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Movements>()
+            .add_overlay(NAME, KeyCode::KeyR)
+            .add_systems(
+                Update,
+                (
+                    infer_rules.run_if(resource_changed::<RoadNetwork>),
+                    draw_rules.after(camera_orbit).run_if(overlay_shown(NAME)),
+                    add_legend.in_set(FillLegend).run_if(overlay_shown(NAME)),
+                ),
+            );
+    }
+}
+
+fn add_legend(
+    movements: Res<Movements>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut legend: ResMut<Legend>,
+) {
+    let rows = [
+        (Rule::Signalized, "legend-signalized"),
+        (Rule::Priority, "legend-priority-road"),
+        (Rule::Yield, "legend-yield"),
+        (Rule::Stop, "legend-stop"),
+        (Rule::Unknown, "legend-unknown"),
+    ]
+    .into_iter()
+    .filter(|(rule, _)| movements.movements.iter().any(|m| m.rule == *rule))
+    .map(|(rule, message)| (rule.color(&theme), locale.text(message, &[])))
+    .collect();
+    legend.add(locale.text("legend-priority", &[]), rows);
+}
+
+fn infer_rules(network: Res<RoadNetwork>, mut movements: ResMut<Movements>) {
+    let mut unknown = Vec::new();
+    movements.movements.clear();
+    movements.unruled.clear();
+    for junction in conflicts::junctions(&network) {
+        let found = self::movements(&network, junction);
+        if found.iter().any(|movement| movement.rule == Rule::Unknown) {
+            // The middle of the junction: the mean of its paths' midpoints.
+            let middles: Vec<DVec3> = found
+                .iter()
+                .filter_map(|movement| {
+                    let points = conflicts::path(&network.segments[movement.path]);
+                    points.get(points.len() / 2).copied()
+                })
+                .collect();
+            let center = middles.iter().sum::<DVec3>() / middles.len().max(1) as f64;
+            movements.unruled.push(center);
+            unknown.push(junction);
+        }
+        movements.movements.extend(found);
+    }
+    if !unknown.is_empty() {
+        info!("no right-of-way rule for junction(s) {unknown:?}");
+    }
+}
+
+fn draw_rules(
+    network: Res<RoadNetwork>,
+    movements: Res<Movements>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let lift = |p: DVec3| origin.to_render(p) + Vec3::Y * ARROW_LIFT;
+    for movement in &movements.movements {
+        let points = conflicts::path(&network.segments[movement.path]);
+        let color = movement.rule.color(&theme);
+        let [.., before, last] = points.as_slice() else {
+            continue;
+        };
+        gizmos.linestrip(points[..points.len() - 1].iter().map(|p| lift(*p)), color);
+        gizmos
+            .arrow(lift(*before), lift(*last), color)
+            .with_tip_length(TIP_LENGTH);
+    }
+    for center in &movements.unruled {
+        gizmos.circle(lift(*center), Direction3d::Y, RING_RADIUS, theme.error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::sample_maps::text;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    // A one-lane road heading north from (55, y), in junction `junction`
+    // (-1 for none), with the given signals.
+    fn north(id: u32, junction: i32, y: f64, length: f64, signals: &str) -> String {
+        format!(
+            r#"<road length="{length}" id="{id}" junction="{junction}">
+              <planView>
+                <geometry s="0" x="55" y="{y}" hdg="{FRAC_PI_2}" length="{length}"><line/></geometry>
+              </planView>
+              <lanes>
+                <laneSection s="0">
+                  <center><lane id="0" type="none"/></center>
+                  <right>
+                    <lane id="-1" type="driving"><width sOffset="0" a="3.5" b="0" c="0" d="0"/></lane>
+                  </right>
+                </laneSection>
+              </lanes>
+              <signals>{signals}</signals>
+            </road>"#
+        )
+    }
+
+    // The junction sample with a side road 4 coming up from the south, its
+    // sign (if any) 2 m before the junction, which connecting road 5 takes
+    // north across the through road 3.
+    fn side_road(sign: Option<&str>, records: &str) -> RoadNetwork {
+        let signal = sign.map_or(String::new(), |kind| {
+            format!(
+                r#"<signal s="28" t="-4" id="9" orientation="+" dynamic="no" country="DE" type="{kind}" subtype="-1"/>"#
+            )
+        });
+        let roads = [
+            north(4, -1, -40.0, 30.0, &signal),
+            north(5, 100, -10.0, 20.0, ""),
+        ]
+        .concat();
+        let xml = text("junction.xodr")
+            .replace("<junction ", &format!("{roads}<junction "))
+            .replace("</junction>", &format!("{records}</junction>"));
+        xodr::read_str(&xml, &LoadTransform::default()).unwrap()
+    }
+
+    // The rule of the movement along connecting road `road`, and the road it
+    // is entered from.
+    fn rule(network: &RoadNetwork, road: u32) -> (Rule, Option<u32>) {
+        let movement = movements(network, 100)
+            .into_iter()
+            .find(|m| network.segments[m.path].road_id == road)
+            .unwrap();
+        let entry = movement.entry.map(|e| network.segments[e].road_id);
+        (movement.rule, entry)
+    }
+
+    #[test]
+    fn side_roads_with_signs_give_way() {
+        let network = side_road(Some("206"), "");
+        assert_eq!(rule(&network, 5), (Rule::Stop, Some(4)));
+        // The unsigned entry becomes the main road.
+        assert_eq!(rule(&network, 3), (Rule::Priority, Some(1)));
+
+        let network = side_road(Some("205"), "");
+        assert_eq!(rule(&network, 5), (Rule::Yield, Some(4)));
+        assert_eq!(rule(&network, 3), (Rule::Priority, Some(1)));
+
+        // Without signs or records no one is known to give way.
+        let network = side_road(None, "");
+        assert_eq!(rule(&network, 5), (Rule::Unknown, Some(4)));
+        assert_eq!(rule(&network, 3), (Rule::Unknown, Some(1)));
+    }
+
+    #[test]
+    fn priority_records_name_who_gives_way() {
+        // A record naming incoming roads, and one naming the side road's
+        // connecting road, which comes first.
+        let network = side_road(None, r#"<priority high="4" low="1"/>"#);
+        assert_eq!(rule(&network, 5), (Rule::Priority, Some(4)));
+        assert_eq!(rule(&network, 3), (Rule::Yield, Some(1)));
+
+        let network = side_road(
+            None,
+            r#"<priority high="3" low="5"/><priority high="4" low="1"/>"#,
+        );
+        assert_eq!(rule(&network, 5), (Rule::Yield, Some(4)));
+        // A sign overrides the records.
+        let network = side_road(Some("206"), r#"<priority high="4" low="1"/>"#);
+        assert_eq!(rule(&network, 5), (Rule::Stop, Some(4)));
+    }
+}
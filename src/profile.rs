@@ -0,0 +1,175 @@
+// Elevation profile of the selected road.
+//
+// While a road is selected, a chart shows the height of its reference line
+// against s, and below it the grade and the superelevation in percent.
+// Hovering over the chart marks the station under the cursor on the road.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::chart::{spawn_chart, ChartContent, ChartData, ChartHover, Panel, Series, BOTTOM_ROW};
+use crate::cross_section::{cut_road, road_range};
+use crate::i18n::Locale;
+use crate::origin::RenderOrigin;
+use crate::selection::Selection;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork, RoadSegment};
+
+// Distance between profile samples, in meters. Long roads are sampled more
+// coarsely so the chart stays within a sample or two per pixel.
+const SAMPLE_STEP: f64 = 1.0;
+const MAX_SAMPLES: usize = 2_000;
+
+// The elevation profile of a road.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub stations: Vec<f64>,
+    // Reference line positions and heights.
+    pub positions: Vec<DVec3>,
+    pub elevation: Vec<f64>,
+    // Rise per meter along the road and across it (left over right).
+    pub grade: Vec<f64>,
+    pub superelevation: Vec<f64>,
+}
+
+impl Profile {
+    // The reference line position nearest to station `s`.
+    pub fn position_at(&self, s: f64) -> Option<DVec3> {
+        let i = self.stations.partition_point(|&station| station < s);
+        self.positions.get(i).or(self.positions.last()).copied()
+    }
+}
+
+// Marks the profile chart.
+#[derive(Component)]
+struct ProfileChart;
+
+// The profile of the selected road, kept for the hover marker.
+#[derive(Resource, Debug, Default)]
+struct SelectedProfile(Option<Profile>);
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedProfile>()
+            .add_systems(Startup, |mut commands: Commands| {
+                spawn_chart(&mut commands, 10.0, BOTTOM_ROW, ProfileChart);
+            })
+            .add_systems(Update, (show_profile, mark_station.after(camera_orbit)));
+    }
+}
+
+// Samples a road's elevation profile.
+pub fn road_profile(network: &RoadNetwork, road_id: u32) -> Option<Profile> {
+    let (start, end) = road_range(network, road_id)?;
+    let road: Vec<&RoadSegment> = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .collect();
+    let count = (((end - start) / SAMPLE_STEP).ceil() as usize).clamp(1, MAX_SAMPLES);
+
+    let mut profile = Profile::default();
+    for i in 0..=count {
+        let s = start + (end - start) * i as f64 / count as f64;
+        let Some(cut) = cut_road(&road, s) else {
+            continue;
+        };
+        profile.stations.push(s);
+        profile.positions.push(cut.reference);
+        profile.elevation.push(cut.reference.y);
+        profile
+            .superelevation
+            .push(cut.superelevation().unwrap_or(0.0));
+    }
+
+    // Central differences, one-sided at the ends.
+    let n = profile.stations.len();
+    profile.grade = (0..n)
+        .map(|i| {
+            let (a, b) = (i.saturating_sub(1), (i + 1).min(n - 1));
+            let ds = profile.stations[b] - profile.stations[a];
+            if ds > f64::EPSILON {
+                (profile.elevation[b] - profile.elevation[a]) / ds
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    (n > 0).then_some(profile)
+}
+
+// Rebuilds the chart when the selected road changes.
+fn show_profile(
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    locale: Res<Locale>,
+    mut selected: ResMut<SelectedProfile>,
+    mut charts: Query<&mut ChartData, With<ProfileChart>>,
+    mut shown: Local<Option<u32>>,
+) {
+    if selection.road_id() == *shown {
+        return;
+    }
+    *shown = selection.road_id();
+    let profile = shown.and_then(|road_id| road_profile(&network, road_id));
+    let content = profile.as_ref().zip(*shown).map(|(profile, road_id)| {
+        let percent = |values: &[f64]| values.iter().map(|v| v * 100.0).collect();
+        ChartContent {
+            title: locale.text("profile-title", &[("road", &road_id)]),
+            stations: profile.stations.clone(),
+            panels: vec![
+                Panel {
+                    unit: "m",
+                    series: vec![Series {
+                        label: locale.text("profile-elevation", &[]),
+                        values: profile.elevation.clone(),
+                        color: [120, 200, 255, 255],
+                    }],
+                },
+                Panel {
+                    unit: "%",
+                    series: vec![
+                        Series {
+                            label: locale.text("profile-grade", &[]),
+                            values: percent(&profile.grade),
+                            color: [255, 170, 60, 255],
+                        },
+                        Series {
+                            label: locale.text("profile-superelevation", &[]),
+                            values: percent(&profile.superelevation),
+                            color: [140, 230, 120, 255],
+                        },
+                    ],
+                },
+            ],
+        }
+    });
+    for mut chart in &mut charts {
+        chart.0.clone_from(&content);
+    }
+    selected.0 = profile;
+}
+
+// Marks the station under the chart cursor on the road.
+fn mark_station(
+    selected: Res<SelectedProfile>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    charts: Query<&ChartHover, With<ProfileChart>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(profile) = &selected.0 else {
+        return;
+    };
+    for hover in &charts {
+        let Some(position) = hover.0.and_then(|s| profile.position_at(s)) else {
+            continue;
+        };
+        let p = origin.to_render(position);
+        let color = theme.profile_cursor;
+        gizmos.line(p, p + Vec3::Y * 5.0, color);
+        gizmos.sphere(p, Quat::IDENTITY, 0.6, color);
+    }
+}
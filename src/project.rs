@@ -0,0 +1,207 @@
+// Project files.
+//
+// A `.rsodr` project gathers a viewing session into one file: the map
+// layers, point clouds and trajectories to show, the annotations file and
+// the viewer settings. `view <project.rsodr>` opens it, as do the open
+// dialog and `open` in the console (see `open_dialog`); options given after
+// it on the command line override the project's. The file is JSON, with
+// paths relative to the project file:
+//
+//   {
+//     "maps": [
+//       { "path": "town.xodr" },
+//       { "path": "extension.xodr.gz", "offset": [500, 0], "rotate": 90 }
+//     ],
+//     "point_clouds": [
+//       { "path": "scan.las", "offset": [0, 0, 1.5], "y_up": true },
+//       { "path": "drive.pcd", "proj": "+proj=utm +zone=32" }
+//     ],
+//     "trajectories": ["drive.csv"],
+//     "annotations": "review.annotations.json",
+//     "settings": { "tile-size": 250, "theme": "colorblind" }
+//   }
+//
+// The first map is the one reloaded on change; the others are merged into
+// it as layers. A cloud's `proj` places it through the map's georeference.
+// The settings are the `view` options of the same names.
+
+use std::path::Path;
+
+use crate::json::{self, Json};
+
+// The `view` options a project may set.
+const SETTINGS: [&str; 12] = [
+    "tile-budget",
+    "tile-size",
+    "simplify",
+    "sight-threshold",
+    "lateral-clearance",
+    "vertical-clearance",
+    "min-lane-width",
+    "max-lane-width",
+    "max-width-change",
+    "theme",
+    "lang",
+    "script",
+];
+
+pub fn is_project(arg: &str) -> bool {
+    Path::new(arg)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("rsodr"))
+}
+
+// Replaces the project files among `view` arguments with the arguments they
+// stand for.
+pub fn expand(args: &[String]) -> Result<Vec<String>, String> {
+    let mut out = Vec::new();
+    for arg in args {
+        if is_project(arg) {
+            out.extend(read(Path::new(arg)).map_err(|e| format!("{arg}: {e}"))?);
+        } else {
+            out.push(arg.clone());
+        }
+    }
+    Ok(out)
+}
+
+// Reads a project as `view` arguments.
+fn read(path: &Path) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let project = json::parse(&text)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let relative = |file: &str| dir.join(file).to_string_lossy().into_owned();
+    let list = |key: &str| project.get(key).and_then(Json::as_array).unwrap_or(&[]);
+
+    let mut args = Vec::new();
+    for (index, map) in list("maps").iter().enumerate() {
+        if index > 0 {
+            args.push("--layer".to_string());
+        }
+        args.extend(source(map, &relative).map_err(|e| format!("maps: {e}"))?);
+    }
+    for cloud in list("point_clouds") {
+        args.push("--points".to_string());
+        args.extend(source(cloud, &relative).map_err(|e| format!("point_clouds: {e}"))?);
+    }
+    for trajectory in list("trajectories") {
+        let file = trajectory
+            .as_str()
+            .ok_or("trajectories: expected file names")?;
+        args.extend(["--trajectory".to_string(), relative(file)]);
+    }
+    if let Some(file) = project.get("annotations") {
+        let file = file.as_str().ok_or("annotations: expected a file name")?;
+        args.extend(["--annotations".to_string(), relative(file)]);
+    }
+
+    if let Some(Json::Object(settings)) = project.get("settings") {
+        for (key, value) in settings {
+            if !SETTINGS.contains(&key.as_str()) {
+                return Err(format!("unknown setting `{key}`"));
+            }
+            let value = match value {
+                Json::Number(n) => n.to_string(),
+                // Scripts and message files are looked up beside the
+                // project; languages by code are not files.
+                Json::String(s) if key == "script" || s.ends_with(".ftl") => relative(s),
+                Json::String(s) => s.clone(),
+                _ => return Err(format!("invalid value for setting `{key}`")),
+            };
+            args.extend([format!("--{key}"), value]);
+        }
+    }
+    Ok(args)
+}
+
+// The arguments for a map or point cloud: its path and transform options.
+fn source(entry: &Json, relative: &impl Fn(&str) -> String) -> Result<Vec<String>, String> {
+    let path = entry
+        .get("path")
+        .and_then(Json::as_str)
+        .ok_or("expected a `path`")?;
+    let mut args = vec![relative(path)];
+    if let Some(offset) = entry.get("offset") {
+        let numbers = offset
+            .as_array()
+            .map(|items| items.iter().map(Json::as_f64).collect::<Option<Vec<_>>>())
+            .unwrap_or_default()
+            .filter(|numbers| matches!(numbers.len(), 2 | 3))
+            .ok_or("`offset` takes two or three numbers")?;
+        let numbers: Vec<String> = numbers.iter().map(f64::to_string).collect();
+        args.extend(["--offset".to_string(), numbers.join(",")]);
+    }
+    if let Some(rotate) = entry.get("rotate") {
+        let degrees = rotate.as_f64().ok_or("`rotate` takes degrees")?;
+        args.extend(["--rotate".to_string(), degrees.to_string()]);
+    }
+    if entry.get("y_up") == Some(&Json::Bool(true)) {
+        args.push("--y-up".to_string());
+    }
+    if let Some(proj) = entry.get("proj") {
+        let proj = proj.as_str().ok_or("`proj` takes a PROJ string")?;
+        args.extend(["--proj".to_string(), proj.to_string()]);
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::load_network;
+    use crate::sample_maps::{path, temp_dir};
+
+    #[test]
+    fn projects_stand_for_their_view_arguments() {
+        let dir = temp_dir("project");
+        for name in ["straight.xodr", "junction.xodr"] {
+            std::fs::copy(path(name), dir.join(name)).unwrap();
+        }
+        let project = dir.join("review.rsodr");
+        std::fs::write(
+            &project,
+            r#"{
+                "maps": [
+                    { "path": "straight.xodr" },
+                    { "path": "junction.xodr", "offset": [0, 20], "rotate": 90 }
+                ],
+                "trajectories": ["drive.csv"],
+                "settings": { "tile-size": 250, "theme": "colorblind" }
+            }"#,
+        )
+        .unwrap();
+        let beside = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let args = expand(&[
+            project.to_string_lossy().into_owned(),
+            "--simplify".to_string(),
+        ]);
+        let (straight, junction, drive) = (
+            beside("straight.xodr"),
+            beside("junction.xodr"),
+            beside("drive.csv"),
+        );
+        let expected = [
+            straight.as_str(),
+            "--layer",
+            &junction,
+            "--offset",
+            "0,20",
+            "--rotate",
+            "90",
+            "--trajectory",
+            &drive,
+            "--tile-size",
+            "250",
+            "--theme",
+            "colorblind",
+            "--simplify",
+        ];
+        assert_eq!(args.unwrap(), expected);
+        // The maps are found beside the project.
+        assert!(load_network(Some(Path::new(&straight))).is_ok());
+
+        std::fs::write(&project, r#"{ "settings": { "speed": 3 } }"#).unwrap();
+        let error = expand(&[project.to_string_lossy().into_owned()]).unwrap_err();
+        assert!(error.ends_with("unknown setting `speed`"), "{error}");
+    }
+}
@@ -0,0 +1,187 @@
+// Map reloading.
+//
+// The map file is watched while the viewer runs: when it is saved again it
+// is loaded anew, as it is with F10 or the `reload` console command. A
+// reload keeps the session. Roads are matched by OpenDRIVE ID, so only the
+// roads whose lanes changed are respawned (see `tiles`); the selected lane
+// and the annotations anchored to roads are placed on the roads as they are
+// now, as after any change to the network (see `edit`); and the camera
+// stays where it is. A selected lane that is gone is deselected. The
+// checks, labels and signal icons are redone. A file that does not load,
+// e.g. one caught half-written, leaves the map as it was.
+//
+// Keys: F10 reloads the map.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+
+use crate::annotations::{road_point, Anchor, Annotations};
+use crate::edit::{NetworkChanged, UndoStack};
+use crate::loader::load_partial;
+use crate::selection::{Pick, Selection};
+use crate::{camera_orbit, RoadNetwork, RoadSegment};
+
+// How often the map file is looked at, in seconds.
+const POLL_INTERVAL: f32 = 1.0;
+
+// The file the map was loaded from, none for the demo network.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MapSource {
+    pub path: Option<PathBuf>,
+    // When the file was last written as of the last load.
+    modified: Option<SystemTime>,
+}
+
+impl MapSource {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let modified = path.as_deref().and_then(modified_time);
+        Self { path, modified }
+    }
+}
+
+// Asks for the map to be reloaded.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ReloadMap;
+
+pub struct ReloadPlugin;
+
+impl Plugin for ReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapSource>()
+            .add_event::<ReloadMap>()
+            .add_systems(
+                Update,
+                (
+                    reload_on_key,
+                    watch_file,
+                    reload_map,
+                    follow_changes.run_if(on_event::<NetworkChanged>()),
+                )
+                    .chain()
+                    .before(camera_orbit),
+            );
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn reload_on_key(keys: Res<ButtonInput<KeyCode>>, mut requests: EventWriter<ReloadMap>) {
+    if keys.just_pressed(KeyCode::F10) {
+        requests.send(ReloadMap);
+    }
+}
+
+fn watch_file(
+    time: Res<Time>,
+    source: Res<MapSource>,
+    mut since: Local<f32>,
+    mut requests: EventWriter<ReloadMap>,
+) {
+    *since += time.delta_seconds();
+    if *since < POLL_INTERVAL {
+        return;
+    }
+    *since = 0.0;
+    let Some(path) = &source.path else {
+        return;
+    };
+    let modified = modified_time(path);
+    if modified.is_some() && modified != source.modified {
+        requests.send(ReloadMap);
+    }
+}
+
+// The segments of a network, by road.
+fn by_road(network: &RoadNetwork) -> BTreeMap<u32, Vec<&RoadSegment>> {
+    let mut roads: BTreeMap<u32, Vec<&RoadSegment>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads.entry(segment.road_id).or_default().push(segment);
+    }
+    roads
+}
+
+// The roads whose lanes differ between two networks, by ID.
+fn changed_roads(old: &RoadNetwork, new: &RoadNetwork) -> Vec<u32> {
+    let (old, new) = (by_road(old), by_road(new));
+    let mut changed: Vec<u32> = old
+        .iter()
+        .filter(|(id, segments)| new.get(id) != Some(segments))
+        .map(|(id, _)| *id)
+        .chain(new.keys().filter(|id| !old.contains_key(id)).copied())
+        .collect();
+    changed.sort_unstable();
+    changed
+}
+
+fn reload_map(
+    mut requests: EventReader<ReloadMap>,
+    mut source: ResMut<MapSource>,
+    mut network: ResMut<RoadNetwork>,
+    mut reloaded: EventWriter<NetworkChanged>,
+    mut undo: ResMut<UndoStack>,
+) {
+    if requests.read().count() == 0 {
+        return;
+    }
+    let Some(path) = source.path.clone() else {
+        info!("the demo network has no file to reload");
+        return;
+    };
+    source.modified = modified_time(&path);
+    let fresh = match load_partial(Some(&path), &network.transform) {
+        Ok(fresh) => fresh,
+        Err(message) => {
+            warn!("{}: {message}; the map is left as it was", path.display());
+            return;
+        }
+    };
+    let changed = changed_roads(&network, &fresh);
+    *network = fresh;
+    undo.clear();
+    info!(
+        "reloaded {}: {} road(s) changed",
+        path.display(),
+        changed.len()
+    );
+    reloaded.send(NetworkChanged { changed });
+}
+
+// Places the selection and the annotations anchored to roads on the roads
+// as they are now.
+fn follow_changes(
+    network: Res<RoadNetwork>,
+    mut selection: ResMut<Selection>,
+    mut annotations: ResMut<Annotations>,
+) {
+    // The selection is looked up again by road, lane section and lane.
+    if let Some(pick) = selection.0 {
+        selection.0 = network
+            .find_segment(pick.road_id, pick.lane_section_id, pick.lane_id)
+            .map(|segment| {
+                let position = pick
+                    .t
+                    .and_then(|t| road_point(&network, pick.road_id, pick.s, t))
+                    .unwrap_or(pick.position);
+                Pick {
+                    segment,
+                    position,
+                    ..pick
+                }
+            });
+        if selection.0.is_none() {
+            info!("{} is gone; deselected", pick.describe());
+        }
+    }
+    for note in &mut annotations.list {
+        if let Anchor::Road { road_id, s, t } = note.anchor {
+            if let Some(position) = road_point(&network, road_id, s, t) {
+                note.position = position;
+            }
+        }
+    }
+}
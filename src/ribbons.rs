@@ -0,0 +1,248 @@
+// Ribbons: lines that stay readable at any zoom.
+//
+// Gizmo lines are a pixel wide, which is fine up close but lets a route or a
+// boundary fade to nothing once the camera pulls back over a large map.
+// Ribbons are flat strips instead, turned to face the camera and as wide in
+// meters as a few pixels are at their distance, so they keep the same width
+// on screen however far away they are. Like gizmos they are drawn afresh
+// every frame: systems add lines, strips and arrows to `Ribbons` in render
+// space, and the lot is built into a single mesh in the overlay pass (see
+// `overlay_pass`).
+
+use bevy::prelude::*;
+use bevy::render::view::NoFrustumCulling;
+
+use crate::overlay_pass::{overlay_layers, overlay_material, OverlayLevel};
+use crate::tessellation::TriangleMesh;
+use crate::MainCamera;
+
+// Height of route and trace lines above the lanes, in meters, so that they
+// are not hidden in the road surface.
+pub const LINE_LIFT: f32 = 0.3;
+
+// The width of a ribbon on screen, in pixels.
+pub const DEFAULT_WIDTH: f32 = 3.0;
+
+// The length of an arrow's head, as a fraction of the arrow's.
+const HEAD_LENGTH: f32 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ribbon {
+    pub points: Vec<Vec3>,
+    pub color: Color,
+    // Width on screen, in pixels.
+    pub width: f32,
+}
+
+// The ribbons to draw this frame, in render space.
+#[derive(Resource, Debug, Default)]
+pub struct Ribbons(pub Vec<Ribbon>);
+
+impl Ribbons {
+    pub fn linestrip(&mut self, points: impl IntoIterator<Item = Vec3>, color: Color) {
+        self.add(points, color, DEFAULT_WIDTH);
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Color) {
+        self.linestrip([start, end], color);
+    }
+
+    // A line with a head at `end`, spread out horizontally.
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, color: Color) {
+        let along = end - start;
+        let side = along.cross(Vec3::Y).try_normalize().unwrap_or(Vec3::X);
+        let back = end - along * HEAD_LENGTH;
+        let spread = side * along.length() * HEAD_LENGTH / 2.0;
+        self.line(start, end, color);
+        self.linestrip([back + spread, end, back - spread], color);
+    }
+
+    pub fn add(&mut self, points: impl IntoIterator<Item = Vec3>, color: Color, width: f32) {
+        let points: Vec<Vec3> = points.into_iter().collect();
+        if points.len() >= 2 {
+            self.0.push(Ribbon {
+                points,
+                color,
+                width,
+            });
+        }
+    }
+}
+
+// What ribbons are sized and turned for: where the camera is and how large
+// a pixel is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RibbonView {
+    pub eye: Vec3,
+    // The direction the camera looks back along.
+    pub back: Vec3,
+    // For a perspective camera, the size of a pixel one meter away; for an
+    // orthographic one, its size everywhere.
+    pub pixel: f32,
+    pub perspective: bool,
+}
+
+impl RibbonView {
+    fn pixel_at(&self, p: Vec3) -> f32 {
+        if self.perspective {
+            self.pixel * self.eye.distance(p)
+        } else {
+            self.pixel
+        }
+    }
+
+    fn toward_eye(&self, p: Vec3) -> Vec3 {
+        if self.perspective {
+            self.eye - p
+        } else {
+            self.back
+        }
+    }
+}
+
+// Builds ribbons into one mesh. Each point is pushed out to either side,
+// across the line and the direction to the camera, by half the ribbon's
+// width at its distance.
+pub fn ribbon_mesh(ribbons: &[Ribbon], view: &RibbonView) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+    for ribbon in ribbons {
+        let points = &ribbon.points;
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        for (i, p) in points.iter().enumerate() {
+            let before = points[i.saturating_sub(1)];
+            let after = points[(i + 1).min(points.len() - 1)];
+            let tangent = (after - before).normalize_or_zero();
+            let side = tangent
+                .cross(view.toward_eye(*p))
+                .try_normalize()
+                .unwrap_or_else(|| tangent.cross(Vec3::Y).normalize_or_zero());
+            let offset = side * ribbon.width * view.pixel_at(*p) / 2.0;
+            left.push(*p + offset);
+            right.push(*p - offset);
+        }
+        mesh.add_colored_strip(&left, &right, ribbon.color.as_linear_rgba_f32());
+    }
+    mesh
+}
+
+pub struct RibbonPlugin;
+
+impl Plugin for RibbonPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Ribbons>()
+            .add_systems(Startup, spawn_ribbon_mesh)
+            .add_systems(PostUpdate, build_ribbons);
+    }
+}
+
+#[derive(Component)]
+struct RibbonMesh;
+
+fn spawn_ribbon_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(TriangleMesh::default().to_mesh()),
+            material: materials.add(overlay_material(Color::WHITE, OverlayLevel::Line)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        overlay_layers(),
+        // The mesh changes every frame, its bounds with it.
+        NoFrustumCulling,
+        RibbonMesh,
+    ));
+}
+
+// Replaces the ribbon mesh with this frame's ribbons, and starts the next
+// frame's afresh.
+fn build_ribbons(
+    mut ribbons: ResMut<Ribbons>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    cameras: Query<(&Camera, &Projection, &Transform), With<MainCamera>>,
+    mut targets: Query<(&Handle<Mesh>, &mut Visibility), With<RibbonMesh>>,
+) {
+    let drawn = std::mem::take(&mut ribbons.0);
+    let Ok((mesh, mut visibility)) = targets.get_single_mut() else {
+        return;
+    };
+    let view = cameras
+        .get_single()
+        .ok()
+        .and_then(|(camera, projection, transform)| {
+            let height = camera.logical_viewport_size()?.y;
+            let (pixel, perspective) = match projection {
+                Projection::Perspective(p) => (2.0 * (p.fov / 2.0).tan() / height, true),
+                Projection::Orthographic(o) => (o.area.height() / height, false),
+            };
+            Some(RibbonView {
+                eye: transform.translation,
+                back: transform.back().into(),
+                pixel,
+                perspective,
+            })
+        });
+    let built = match view {
+        Some(view) => ribbon_mesh(&drawn, &view),
+        None => TriangleMesh::default(),
+    };
+    *visibility = if built.is_empty() {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    if !built.is_empty() {
+        meshes.insert(mesh, built.to_mesh());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_stay_constant_on_screen() {
+        // Ribbons are as wide on screen near the camera as far away from it.
+        let mut ribbons = Ribbons::default();
+        ribbons.line(
+            Vec3::new(-1.0, 0.0, -10.0),
+            Vec3::new(1.0, 0.0, -10.0),
+            Color::RED,
+        );
+        ribbons.line(
+            Vec3::new(-1.0, 0.0, -100.0),
+            Vec3::new(1.0, 0.0, -100.0),
+            Color::RED,
+        );
+        ribbons.linestrip([Vec3::ZERO], Color::RED);
+        assert_eq!(ribbons.0.len(), 2);
+        let mut view = RibbonView {
+            eye: Vec3::ZERO,
+            back: Vec3::Z,
+            pixel: 0.001,
+            perspective: true,
+        };
+        let width = |mesh: &TriangleMesh, ribbon: usize| {
+            mesh.positions[4 * ribbon].distance(mesh.positions[4 * ribbon + 1])
+        };
+        let mesh = ribbon_mesh(&ribbons.0, &view);
+        assert_eq!(mesh.positions.len(), 8);
+        let near = width(&mesh, 0);
+        assert!((near - DEFAULT_WIDTH * 0.001 * 10.0).abs() < 1e-3, "{near}");
+        assert!((width(&mesh, 1) / near - 10.0).abs() < 0.1);
+        // The ribbons face the camera.
+        assert!(mesh.positions[..4].iter().all(|p| p.z == -10.0));
+
+        view.perspective = false;
+        let mesh = ribbon_mesh(&ribbons.0, &view);
+        assert!((width(&mesh, 1) - width(&mesh, 0)).abs() < 1e-6);
+
+        let mut arrow = Ribbons::default();
+        arrow.arrow(Vec3::ZERO, Vec3::X * 4.0, Color::RED);
+        assert_eq!(arrow.0.len(), 2);
+        assert_eq!(arrow.0[1].points[1], Vec3::X * 4.0);
+    }
+}
@@ -0,0 +1,131 @@
+// Road mark lines.
+//
+// Turns a lane's `<roadMark>` into the strokes painted along its boundary.
+// A mark with an explicit `<type>` is painted as its `<line>`s say. The
+// others go by their type: a continuous line for solid marks, curbs, edges
+// and custom marks, dashes for broken ones, raised markers for Botts' dots
+// and studs, and two lines side by side for double types. In a double type the first line is
+// the inner one, as in `routing::crossable`; on the center lane it is the
+// one on the left of the reference line. Grass and "none" paint nothing.
+//
+// Strokes are as wide as the line, the mark or its weight says, in that
+// order, and colored by the line or the mark. The material is kept and
+// written back, but all paint is drawn alike. Raised markers are drawn as
+// domes rather than paint, one per dash of their lines, however the lines
+// are given.
+
+use bevy::prelude::Color;
+
+use crate::tessellation::PLAIN;
+use crate::RoadMark;
+
+// Dashes of broken lines and their gaps, in meters.
+const BROKEN_LENGTH: f64 = 3.0;
+const BROKEN_SPACE: f64 = 9.0;
+
+// Raised markers: their size and spacing, in meters.
+const DOT_SIZE: f64 = 0.1;
+const DOT_SPACE: f64 = 1.2;
+
+// Road mark types of raised markers rather than paint.
+pub const RAISED_TYPES: [&str; 2] = ["botts dots", "studs"];
+
+// Width of bold lines, and the gap between the lines of a double type, in
+// meters.
+const BOLD_WIDTH: f64 = 0.3;
+const DOUBLE_GAP: f64 = 0.12;
+
+// One line of a road mark, placed relative to the boundary it marks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+    // Meters to the left of the boundary, facing along the reference line.
+    pub t_offset: f64,
+    pub width: f64,
+    // Painted for `length` meters, then left out for `space` meters; a zero
+    // space paints it throughout.
+    pub length: f64,
+    pub space: f64,
+    // Where the first dash starts, in meters from the boundary's start.
+    pub s_offset: f64,
+    // Linear RGBA, see `TriangleMesh::colors`.
+    pub color: [f32; 4],
+    // Raised markers, one in the middle of each dash, instead of paint.
+    pub raised: bool,
+}
+
+// The vertex color of an OpenDRIVE road mark color. White and "standard"
+// are the theme's marking color.
+pub fn color(name: &str) -> [f32; 4] {
+    let srgb = match name.trim().to_ascii_lowercase().as_str() {
+        "yellow" => Color::rgb(0.96, 0.76, 0.1),
+        "red" => Color::rgb(0.85, 0.15, 0.1),
+        "blue" => Color::rgb(0.15, 0.35, 0.85),
+        "green" => Color::rgb(0.15, 0.65, 0.25),
+        "orange" => Color::rgb(0.95, 0.5, 0.1),
+        "violet" => Color::rgb(0.55, 0.25, 0.75),
+        "black" => Color::rgb(0.05, 0.05, 0.05),
+        _ => return PLAIN,
+    };
+    srgb.as_linear_rgba_f32()
+}
+
+// The strokes of a road mark. `inward` is the sign of offsets toward the
+// reference line, +1 or -1, where the first line of a double type goes.
+// `default_width` is the width of standard lines.
+pub fn strokes(mark: &RoadMark, inward: f64, default_width: f64) -> Vec<Stroke> {
+    let width = mark.width.filter(|width| *width > 0.0).unwrap_or(
+        if mark.weight.eq_ignore_ascii_case("bold") {
+            BOLD_WIDTH
+        } else {
+            default_width
+        },
+    );
+    let mark_color = color(&mark.color);
+    let kind = mark.kind.trim().to_ascii_lowercase();
+    let raised = RAISED_TYPES.contains(&kind.as_str());
+
+    if !mark.lines.is_empty() {
+        return mark
+            .lines
+            .iter()
+            .map(|line| Stroke {
+                t_offset: line.t_offset,
+                width: line.width.filter(|width| *width > 0.0).unwrap_or(width),
+                length: line.length,
+                space: line.space,
+                s_offset: line.s_offset,
+                color: line.color.as_deref().map_or(mark_color, color),
+                raised,
+            })
+            .collect();
+    }
+
+    let stroke = |kind: &str, t_offset: f64| {
+        let (length, space) = match kind {
+            "broken" => (BROKEN_LENGTH, BROKEN_SPACE),
+            _ if raised => (DOT_SIZE, DOT_SPACE),
+            _ => (0.0, 0.0),
+        };
+        Stroke {
+            t_offset,
+            width: if raised { width.max(DOT_SIZE) } else { width },
+            length,
+            space,
+            s_offset: 0.0,
+            color: mark_color,
+            raised,
+        }
+    };
+    match kind.as_str() {
+        "none" | "grass" => Vec::new(),
+        double @ ("solid solid" | "solid broken" | "broken solid" | "broken broken") => {
+            let apart = (width + DOUBLE_GAP) / 2.0;
+            let (first, second) = double.split_once(' ').unwrap_or((double, double));
+            vec![
+                stroke(first, inward * apart),
+                stroke(second, -inward * apart),
+            ]
+        }
+        single => vec![stroke(single, 0.0)],
+    }
+}
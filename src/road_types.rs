@@ -0,0 +1,142 @@
+// Road type overlay, built as a viewer extension (see `extensions`).
+//
+// Outlines every lane in the color of its road's OpenDRIVE `<type>`, so
+// that a map can be talked through with people who do not read lane data:
+// which roads are motorways, which rural and which in town. The many
+// OpenDRIVE types are shown in four classes; town expressways count as
+// motorways, and play streets, pedestrian and bicycle roads as low speed.
+// Lanes whose road gives no type, or "unknown", are left out. The type is
+// the one in force where the lane section starts.
+//
+// Keys: Y shows or hides the road type overlay.
+
+use bevy::prelude::*;
+
+use crate::extensions::{overlay_shown, AddOverlay, RsodrPlugin, OVERLAY_LIFT};
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::ribbons::Ribbons;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+const NAME: &str = "road types";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoadClass {
+    Motorway,
+    Rural,
+    Town,
+    LowSpeed,
+}
+
+pub const CLASSES: [RoadClass; 4] = [
+    RoadClass::Motorway,
+    RoadClass::Rural,
+    RoadClass::Town,
+    RoadClass::LowSpeed,
+];
+
+impl RoadClass {
+    // The class of an OpenDRIVE road type, if it has one.
+    pub fn of(road_type: &str) -> Option<RoadClass> {
+        match road_type {
+            "motorway" | "townExpressway" => Some(RoadClass::Motorway),
+            "rural" => Some(RoadClass::Rural),
+            "town" | "townArterial" | "townCollector" | "townLocal" | "townPrivate" => {
+                Some(RoadClass::Town)
+            }
+            "lowSpeed" | "townPlayStreet" | "pedestrian" | "bicycle" => Some(RoadClass::LowSpeed),
+            _ => None,
+        }
+    }
+
+    pub fn color(self, theme: &Theme) -> Color {
+        match self {
+            RoadClass::Motorway => theme.motorway,
+            RoadClass::Rural => theme.rural,
+            RoadClass::Town => theme.town,
+            RoadClass::LowSpeed => theme.low_speed,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            RoadClass::Motorway => "legend-motorway",
+            RoadClass::Rural => "legend-rural",
+            RoadClass::Town => "legend-town",
+            RoadClass::LowSpeed => "legend-low-speed",
+        }
+    }
+}
+
+pub struct RoadTypeOverlay;
+
+impl RsodrPlugin for RoadTypeOverlay {
+    fn name(&self) -> &'static str {
+        "road type overlay"
+    }
+
+    fn build(&self, app: &mut App) {
+        app.add_overlay(NAME, KeyCode::KeyY).add_systems(
+            Update,
+            (
+                draw_road_types.after(camera_orbit),
+                add_legend.in_set(FillLegend),
+            )
+                .run_if(overlay_shown(NAME)),
+        );
+    }
+}
+
+fn add_legend(theme: Res<Theme>, locale: Res<Locale>, mut legend: ResMut<Legend>) {
+    legend.add(
+        locale.text("legend-road-type", &[]),
+        CLASSES
+            .iter()
+            .map(|class| (class.color(&theme), locale.text(class.message(), &[])))
+            .collect(),
+    );
+}
+
+fn draw_road_types(
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    let lift = Vec3::Y * OVERLAY_LIFT;
+    for segment in &network.segments {
+        let Some(class) = RoadClass::of(&segment.road_type) else {
+            continue;
+        };
+        let color = class.color(&theme);
+        for side in [&segment.left_side, &segment.right_side] {
+            ribbons.linestrip(side.iter().map(|p| origin.to_render(*p) + lift), color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn classes() {
+        assert_eq!(RoadClass::of("townExpressway"), Some(RoadClass::Motorway));
+        assert_eq!(RoadClass::of("bicycle"), Some(RoadClass::LowSpeed));
+        assert_eq!(RoadClass::of("unknown"), None);
+    }
+
+    #[test]
+    fn sample_roads_are_classed() {
+        let straight = load("straight.xodr");
+        let classes: Vec<Option<RoadClass>> = straight
+            .segments
+            .iter()
+            .map(|lane| RoadClass::of(&lane.road_type))
+            .collect();
+        assert!(classes.iter().all(|class| *class == Some(RoadClass::Town)));
+    }
+}
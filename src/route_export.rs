@@ -0,0 +1,155 @@
+// Export of computed routes for scenario authoring.
+//
+// Two formats are supported, picked by file extension:
+// - `.csv`: the route centerline as a polyline with s, position, heading and
+//   the road/lane it runs on.
+// - `.xosc`: an OpenSCENARIO catalog holding the route both as a `Route`
+//   (one lane waypoint per segment) and as a polyline `Trajectory`.
+//
+// Both use OpenDRIVE's frame (x east, y north, z up), not the viewer's.
+
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use bevy::math::DVec3;
+
+use crate::routing::{Route, RoutePoint};
+use crate::RoadNetwork;
+
+// Writes the route to `path` in the format implied by its extension.
+pub fn write_route(network: &RoadNetwork, route: &Route, path: &Path) -> io::Result<()> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let text = match extension.as_deref() {
+        Some("csv") => to_csv(network, route),
+        Some("xosc") => to_openscenario(network, route),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "route files must end in .csv or .xosc",
+            ))
+        }
+    };
+    std::fs::write(path, text)
+}
+
+// Converts a viewer position into OpenDRIVE's frame.
+pub fn to_odr(p: DVec3) -> DVec3 {
+    DVec3::new(p.x, -p.z + 0.0, p.y)
+}
+
+// Reads the positions of a route written by `to_csv`, in the viewer frame.
+pub fn read_csv(text: &str) -> Result<Vec<DVec3>, String> {
+    let mut points = Vec::new();
+    for (number, line) in text.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let coordinate = |i: usize| -> Result<f64, String> {
+            fields
+                .get(i)
+                .and_then(|field| field.trim().parse().ok())
+                .ok_or_else(|| format!("line {}: expected s,x,y,z,...", number + 1))
+        };
+        let (x, y, z) = (coordinate(1)?, coordinate(2)?, coordinate(3)?);
+        points.push(DVec3::new(x, z, -y));
+    }
+    if points.len() < 2 {
+        return Err("a route needs at least two points".to_string());
+    }
+    Ok(points)
+}
+
+// Heading at each polyline point, taken from the following piece (or the
+// previous one at the very end).
+fn headings(points: &[RoutePoint]) -> Vec<f64> {
+    (0..points.len())
+        .map(|i| {
+            let (a, b) = if i + 1 < points.len() {
+                (points[i].position, points[i + 1].position)
+            } else if i > 0 {
+                (points[i - 1].position, points[i].position)
+            } else {
+                return 0.0;
+            };
+            let d = to_odr(b) - to_odr(a);
+            d.y.atan2(d.x)
+        })
+        .collect()
+}
+
+// Renders the route polyline as CSV.
+pub fn to_csv(network: &RoadNetwork, route: &Route) -> String {
+    let points = route.polyline(network);
+    let mut csv = String::from("s,x,y,z,heading,road_id,lane_section_id,lane_id\n");
+    for (point, heading) in points.iter().zip(headings(&points)) {
+        let p = to_odr(point.position);
+        let segment = &network.segments[point.segment];
+        let _ = writeln!(
+            csv,
+            "{:.3},{:.3},{:.3},{:.3},{:.6},{},{},{}",
+            point.s,
+            p.x,
+            p.y,
+            p.z,
+            heading,
+            segment.road_id,
+            segment.lane_section_id,
+            segment.lane_id
+        );
+    }
+    csv
+}
+
+// Renders the route as an OpenSCENARIO 1.1 catalog.
+pub fn to_openscenario(network: &RoadNetwork, route: &Route) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<OpenSCENARIO>\n");
+    xml.push_str("  <FileHeader revMajor=\"1\" revMinor=\"1\" date=\"1970-01-01T00:00:00\" description=\"Route exported from rsodr\" author=\"rsodr\"/>\n");
+    xml.push_str("  <Catalog name=\"rsodr_routes\">\n");
+
+    // The trajectory follows the sampled centerline exactly.
+    let points = route.polyline(network);
+    xml.push_str("    <Trajectory name=\"route_trajectory\" closed=\"false\">\n");
+    xml.push_str("      <Shape>\n        <Polyline>\n");
+    for (point, heading) in points.iter().zip(headings(&points)) {
+        let p = to_odr(point.position);
+        let _ = writeln!(
+            xml,
+            "          <Vertex><Position><WorldPosition x=\"{:.3}\" y=\"{:.3}\" z=\"{:.3}\" h=\"{:.6}\"/></Position></Vertex>",
+            p.x, p.y, p.z, heading
+        );
+    }
+    xml.push_str("        </Polyline>\n      </Shape>\n");
+    xml.push_str("    </Trajectory>\n");
+
+    // The route leaves the exact path to the simulator, pinning one waypoint
+    // at the start of every segment plus one at the very end.
+    xml.push_str("    <Route name=\"route\" closed=\"false\">\n");
+    for &index in &route.segments {
+        let segment = &network.segments[index];
+        write_waypoint(&mut xml, segment.road_id, segment.lane_id, segment.start_s);
+    }
+    if let Some(&last) = route.segments.last() {
+        let segment = &network.segments[last];
+        write_waypoint(&mut xml, segment.road_id, segment.lane_id, segment.end_s);
+    }
+    xml.push_str("    </Route>\n");
+
+    xml.push_str("  </Catalog>\n");
+    xml.push_str("</OpenSCENARIO>\n");
+    xml
+}
+
+// Writes one lane-position waypoint of a route.
+fn write_waypoint(xml: &mut String, road_id: u32, lane_id: i32, s: f64) {
+    let _ = writeln!(
+        xml,
+        "      <Waypoint routeStrategy=\"shortest\"><Position><LanePosition roadId=\"{road_id}\" laneId=\"{lane_id}\" s=\"{s:.3}\" offset=\"0\"/></Position></Waypoint>"
+    );
+}
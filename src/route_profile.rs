@@ -0,0 +1,342 @@
+// Elevation, speed limit and curvature along a route.
+//
+// For energy and comfort analyses, a route is sampled every meter and each
+// sample gets the height of the route, the speed limit of the lane it runs
+// on and the curvature of its path. In the viewer, `route <from> <to>` in
+// the console finds a route between two lanes, draws it and charts the three
+// against the distance along the route; hovering over the chart marks the
+// point on the route. `route export <file.csv>` writes the samples out, as
+// does `export-route --profile` on the command line. Both take the routing
+// options (see `routing`).
+//
+// The CSV has the columns `s,x,y,z,speed,curvature` in OpenDRIVE's frame,
+// with the speed in km/h (empty where the lane has no limit) and the
+// curvature in 1/m, positive to the left.
+
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::chart::{
+    spawn_chart, ChartContent, ChartData, ChartHover, Panel, Series, CHART_WIDTH, SECOND_ROW,
+};
+use crate::i18n::Locale;
+use crate::origin::RenderOrigin;
+use crate::ribbons::{Ribbons, LINE_LIFT};
+use crate::route_export::to_odr;
+use crate::routing::{shortest_route, Route, RouteOptions};
+use crate::theme::Theme;
+use crate::units::{SpeedUnit, KMH};
+use crate::{camera_orbit, cli, RoadNetwork};
+
+// Distance between samples, in meters.
+const SAMPLE_STEP: f64 = 1.0;
+
+// Curvature is measured over this many samples on either side, which
+// smooths out the kinks where tessellated pieces meet.
+const CURVATURE_SPAN: usize = 3;
+
+// A route sampled at regular distances.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteProfile {
+    pub stations: Vec<f64>,
+    // Viewer-frame positions; the elevation is their height.
+    pub positions: Vec<DVec3>,
+    // Speed limits in m/s, NaN where the lane has none.
+    pub speed: Vec<f64>,
+    pub curvature: Vec<f64>,
+}
+
+impl RouteProfile {
+    // The sampled position nearest to distance `s`.
+    pub fn position_at(&self, s: f64) -> Option<DVec3> {
+        let i = self.stations.partition_point(|&station| station < s);
+        self.positions.get(i).or(self.positions.last()).copied()
+    }
+}
+
+// Samples a route.
+pub fn route_profile(network: &RoadNetwork, route: &Route) -> RouteProfile {
+    let points = route.polyline(network);
+    let mut profile = RouteProfile::default();
+    let Some(end) = points.last().map(|point| point.s) else {
+        return profile;
+    };
+    let count = (end / SAMPLE_STEP).ceil().max(1.0) as usize;
+    let mut piece = 0;
+    for i in 0..=count {
+        let s = end * i as f64 / count as f64;
+        while piece + 2 < points.len() && points[piece + 1].s < s {
+            piece += 1;
+        }
+        let (a, b) = match (points.get(piece), points.get(piece + 1)) {
+            (Some(a), Some(b)) => (a, b),
+            (Some(a), None) => (a, a),
+            _ => break,
+        };
+        let f = if b.s > a.s {
+            ((s - a.s) / (b.s - a.s)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        // The sample belongs to the lane it lies on, the later one at a seam.
+        let segment = if f < 1.0 { b.segment } else { a.segment };
+        profile.stations.push(s);
+        profile.positions.push(a.position.lerp(b.position, f));
+        profile
+            .speed
+            .push(network.segments[segment].speed.unwrap_or(f64::NAN));
+    }
+
+    // Heading changes between the pieces before and after each sample,
+    // measured in the map plane (x east, -z north).
+    let n = profile.positions.len();
+    let heading = |a: DVec3, b: DVec3| (a.z - b.z).atan2(b.x - a.x);
+    profile.curvature = (0..n)
+        .map(|i| {
+            let (before, after) = (
+                i.saturating_sub(CURVATURE_SPAN),
+                (i + CURVATURE_SPAN).min(n - 1),
+            );
+            if i == before || i == after {
+                return 0.0;
+            }
+            let p = &profile.positions;
+            let turn = heading(p[i], p[after]) - heading(p[before], p[i]);
+            let turn = (turn + PI).rem_euclid(2.0 * PI) - PI;
+            let length = profile.stations[after] - profile.stations[before];
+            if length > f64::EPSILON {
+                2.0 * turn / length
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    profile
+}
+
+// Renders a profile as CSV.
+pub fn to_csv(profile: &RouteProfile) -> String {
+    let mut csv = String::from("s,x,y,z,speed,curvature\n");
+    for i in 0..profile.stations.len() {
+        let p = to_odr(profile.positions[i]);
+        let speed = profile.speed[i] / KMH;
+        let speed = if speed.is_finite() {
+            format!("{speed:.1}")
+        } else {
+            String::new()
+        };
+        let _ = writeln!(
+            csv,
+            "{:.3},{:.3},{:.3},{:.3},{speed},{:.6}",
+            profile.stations[i], p.x, p.y, p.z, profile.curvature[i]
+        );
+    }
+    csv
+}
+
+// The route shown in the viewer.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct ActiveRoute(pub Option<(Route, RouteProfile)>);
+
+// Marks the route chart.
+#[derive(Component)]
+struct RouteChart;
+
+pub struct RouteProfilePlugin;
+
+impl Plugin for RouteProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveRoute>()
+            .add_systems(Startup, |mut commands: Commands| {
+                spawn_chart(
+                    &mut commands,
+                    CHART_WIDTH as f32 + 30.0,
+                    SECOND_ROW,
+                    RouteChart,
+                );
+            })
+            .add_systems(
+                Update,
+                (forget_route, show_chart, draw_route.after(camera_orbit)).chain(),
+            );
+    }
+}
+
+// Backs the console's `route` commands.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["clear"] => {
+            world.resource_mut::<ActiveRoute>().0 = None;
+            Ok(String::new())
+        }
+        ["export", file] => {
+            let active = world.resource::<ActiveRoute>();
+            let (_, profile) = active.0.as_ref().ok_or("no route; find one with `route`")?;
+            std::fs::write(file, to_csv(profile)).map_err(|e| format!("{file}: {e}"))?;
+            Ok(format!("wrote {file}"))
+        }
+        _ => {
+            let (options, lanes) = RouteOptions::parse(args)?;
+            let [from, to] = lanes[..] else {
+                return Err("expected `route [options] <from> <to>` with lanes as road:section:lane, `route export <file.csv>` or `route clear`".to_string());
+            };
+            let network = world.resource::<RoadNetwork>();
+            let from = cli::lane_argument(network, from)?;
+            let to = cli::lane_argument(network, to)?;
+            let route = shortest_route(network, from, to, &options)
+                .ok_or("no route between those lanes")?;
+            let profile = route_profile(network, &route);
+            let message = format!(
+                "route of {:.0} m over {} lane(s), {:.0} s at the speed limits",
+                route.length,
+                route.segments.len(),
+                route.time
+            );
+            world.resource_mut::<ActiveRoute>().0 = Some((route, profile));
+            Ok(message)
+        }
+    }
+}
+
+// Drops the route when the network changes under it.
+fn forget_route(network: Res<RoadNetwork>, mut active: ResMut<ActiveRoute>) {
+    if network.is_changed() && !network.is_added() && active.0.is_some() {
+        active.0 = None;
+    }
+}
+
+fn show_chart(
+    active: Res<ActiveRoute>,
+    locale: Res<Locale>,
+    speed_unit: Res<SpeedUnit>,
+    mut charts: Query<&mut ChartData, With<RouteChart>>,
+) {
+    if !active.is_changed() && !speed_unit.is_changed() {
+        return;
+    }
+    let content = active.0.as_ref().map(|(route, profile)| ChartContent {
+        title: locale.text(
+            "route-title",
+            &[("length", &format!("{:.0}", route.length))],
+        ),
+        stations: profile.stations.clone(),
+        panels: vec![
+            Panel {
+                unit: "m",
+                series: vec![Series {
+                    label: locale.text("route-elevation", &[]),
+                    values: profile.positions.iter().map(|p| p.y).collect(),
+                    color: [120, 200, 255, 255],
+                }],
+            },
+            Panel {
+                unit: speed_unit.name(),
+                series: vec![Series {
+                    label: locale.text("route-speed", &[]),
+                    values: profile
+                        .speed
+                        .iter()
+                        .map(|speed| speed_unit.value(*speed))
+                        .collect(),
+                    color: [255, 170, 60, 255],
+                }],
+            },
+            Panel {
+                unit: "1/m",
+                series: vec![Series {
+                    label: locale.text("route-curvature", &[]),
+                    values: profile.curvature.clone(),
+                    color: [255, 110, 110, 255],
+                }],
+            },
+        ],
+    });
+    for mut chart in &mut charts {
+        chart.0.clone_from(&content);
+    }
+}
+
+// Draws the route, and marks the point under the chart cursor.
+fn draw_route(
+    active: Res<ActiveRoute>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    charts: Query<&ChartHover, With<RouteChart>>,
+    mut ribbons: ResMut<Ribbons>,
+    mut gizmos: Gizmos,
+) {
+    let Some((_, profile)) = &active.0 else {
+        return;
+    };
+    ribbons.linestrip(
+        profile
+            .positions
+            .iter()
+            .map(|p| origin.to_render(*p) + Vec3::Y * LINE_LIFT),
+        theme.trajectory,
+    );
+    for hover in &charts {
+        let Some(position) = hover.0.and_then(|s| profile.position_at(s)) else {
+            continue;
+        };
+        let p = origin.to_render(position);
+        let color = theme.profile_cursor;
+        ribbons.line(p, p + Vec3::Y * 5.0, color);
+        gizmos.sphere(p, Quat::IDENTITY, 0.6, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_near, load, TOLERANCE};
+
+    // The profile of the route from one lane to another.
+    fn profile(name: &str, from: (u32, u32, i32), to: (u32, u32, i32)) -> RouteProfile {
+        let network = load(name);
+        let from = network.find_segment(from.0, from.1, from.2).unwrap();
+        let to = network.find_segment(to.0, to.1, to.2).unwrap();
+        let route = shortest_route(&network, from, to, &RouteOptions::default()).unwrap();
+        route_profile(&network, &route)
+    }
+
+    #[test]
+    fn heights_follow_the_climb_across_sections() {
+        let profile = profile("elevation.xodr", (1, 1, -1), (1, 2, -1));
+        // Distances are measured along the slope, which is a little longer
+        // than its run of 100 m.
+        let slope = 1.0_f64.hypot(0.05);
+        assert_near(*profile.stations.last().unwrap(), 100.0 * slope, TOLERANCE);
+        for (s, p) in profile.stations.iter().zip(&profile.positions) {
+            assert_near(p.x, s / slope, TOLERANCE);
+            assert_near(p.y, 0.05 * s / slope, TOLERANCE);
+        }
+        assert!(profile.speed.iter().all(|speed| speed.is_nan()));
+        assert!(profile.curvature.iter().all(|k| k.abs() < 1e-9));
+        assert_eq!(profile.position_at(42.4), Some(profile.positions[43]));
+    }
+
+    #[test]
+    fn curvature_is_that_of_the_lane_driven() {
+        // The right lane follows a left bend 1.75 m outside its radius of 50 m.
+        let profile = profile("curve.xodr", (1, 1, -1), (1, 1, -1));
+        let middle = profile.curvature.len() / 2;
+        assert!((profile.curvature[middle] - 1.0 / 51.75).abs() < 1e-3);
+        assert!(profile.curvature.iter().all(|&k| k >= 0.0));
+    }
+
+    #[test]
+    fn speed_limits_are_written_in_km_h() {
+        let profile = profile("straight.xodr", (1, 1, -1), (1, 1, -1));
+        assert!(profile
+            .speed
+            .iter()
+            .all(|&speed| (speed / KMH - 50.0).abs() < 1e-9));
+        let csv = to_csv(&profile);
+        assert_eq!(csv.lines().count(), profile.stations.len() + 1);
+        assert!(csv.contains("\n0.000,0.000,-1.750,0.000,50.0,0.000000\n"));
+    }
+}
@@ -0,0 +1,805 @@
+// Lane-level routing over the road network.
+//
+// The lane graph connects a segment, in the direction it is driven, to the
+// next lane section of the same lane, to the lanes of linked roads, and to
+// any segment whose driving path starts where this one's ends. Routes are
+// found with Dijkstra's algorithm, weighted by centerline length or by the
+// time to drive it at the speed limit, and alternatives to the best route
+// with Yen's algorithm.
+//
+// Beside the graph, `lane_changes` relates each driving lane to the
+// neighbouring lanes of its section going the same way that the marking
+// between them lets it change into. Routes do not use lane changes; they
+// are there for tools that plan manoeuvres along a route.
+//
+// Options restrict the graph for a road user: with U-turns forbidden, a
+// route never turns back by more than `U_TURN_ANGLE`, either from one lane
+// into the next or along a junction's connecting road. With a vehicle
+// named, only lanes open to it are used; a lane's type decides (bus lanes
+// for buses, biking lanes for bicycles, sidewalks for pedestrians, the
+// driving lanes for everyone but pedestrians), unless its `<access>` rules
+// allow or deny the vehicle by name. Vehicles are named as OpenDRIVE access
+// restrictions are, e.g. `passengerCar`, `bus`, `bicycle` or `pedestrian`.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::f64::consts::PI;
+
+use bevy::math::{DVec3, IVec3};
+
+use crate::conflicts;
+use crate::units::KMH;
+use crate::{ContactPoint, RoadLink, RoadNetwork, RoadSegment};
+
+// Centerline ends closer than this (in meters) are considered connected.
+const CONNECTION_TOLERANCE: f64 = 0.5;
+
+// Turning back by more than this, in radians, is a U-turn.
+const U_TURN_ANGLE: f64 = 150.0 * PI / 180.0;
+
+// Speed assumed on lanes without a limit, when weighting by time, driving
+// along and exporting, in km/h.
+pub const DEFAULT_SPEED: f64 = 50.0;
+
+// The lane types for general traffic.
+pub const DRIVING_TYPES: [&str; 10] = [
+    "driving",
+    "entry",
+    "exit",
+    "onRamp",
+    "offRamp",
+    "connectingRamp",
+    "slipLane",
+    "mwyEntry",
+    "mwyExit",
+    "bidirectional",
+];
+
+// What a route minimizes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weight {
+    #[default]
+    Length,
+    // Driving time at the speed limits.
+    Time,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteOptions {
+    pub no_u_turns: bool,
+    // The road user routed; none may use every lane.
+    pub vehicle: Option<String>,
+    pub weight: Weight,
+}
+
+impl RouteOptions {
+    // Takes the routing options (`--no-u-turns`, `--vehicle <name>`,
+    // `--fastest`) out of command arguments, returning the rest.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<(Self, Vec<&str>), String> {
+        let mut options = Self::default();
+        let mut rest = Vec::new();
+        let mut args = args.iter().map(AsRef::as_ref);
+        while let Some(arg) = args.next() {
+            match arg {
+                "--no-u-turns" => options.no_u_turns = true,
+                "--fastest" => options.weight = Weight::Time,
+                "--vehicle" => {
+                    let vehicle = args.next().ok_or("--vehicle needs a value")?;
+                    options.vehicle = Some(vehicle.to_string());
+                }
+                _ => rest.push(arg),
+            }
+        }
+        Ok((options, rest))
+    }
+}
+
+// A path through the lane graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    // Indices into `RoadNetwork::segments`, in driving order.
+    pub segments: Vec<usize>,
+    // Total centerline length in meters.
+    pub length: f64,
+    // Driving time at the speed limits, in seconds.
+    pub time: f64,
+}
+
+// A point along a route with its distance from the route start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutePoint {
+    pub s: f64,
+    pub position: DVec3,
+    pub segment: usize,
+}
+
+impl Route {
+    pub fn new(network: &RoadNetwork, segments: Vec<usize>) -> Self {
+        Self {
+            length: segments.iter().map(|&i| segment_length(network, i)).sum(),
+            time: segments.iter().map(|&i| segment_time(network, i)).sum(),
+            segments,
+        }
+    }
+
+    // Samples the route as a polyline in driving direction, dropping the
+    // duplicated points where consecutive segments meet.
+    pub fn polyline(&self, network: &RoadNetwork) -> Vec<RoutePoint> {
+        let mut points: Vec<RoutePoint> = Vec::new();
+        for &index in &self.segments {
+            for position in conflicts::path(&network.segments[index]) {
+                let s = match points.last() {
+                    Some(last) if last.position.distance(position) < CONNECTION_TOLERANCE => {
+                        continue
+                    }
+                    Some(last) => last.s + last.position.distance(position),
+                    None => 0.0,
+                };
+                points.push(RoutePoint {
+                    s,
+                    position,
+                    segment: index,
+                });
+            }
+        }
+        points
+    }
+}
+
+// The successors of every segment in driving direction: the next lane
+// section of the same lane (the one before it for lanes driven against the
+// reference line), the lanes of linked roads that carry on from the end the
+// lane leaves by, and any lane whose driving path starts where this one's
+// ends. Lane starts are found through a grid rather than a scan of all
+// lanes per lane.
+pub fn lane_graph(network: &RoadNetwork) -> Vec<Vec<usize>> {
+    let cell = |p: DVec3| (p / CONNECTION_TOLERANCE).floor().as_ivec3();
+    let paths: Vec<Vec<DVec3>> = network.segments.iter().map(conflicts::path).collect();
+    let mut starts: HashMap<IVec3, Vec<(usize, DVec3)>> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        if let Some(&start) = path.first() {
+            starts.entry(cell(start)).or_default().push((index, start));
+        }
+    }
+    let mut lanes: HashMap<(u32, u32, i32), usize> = HashMap::new();
+    let mut last_sections: HashMap<u32, u32> = HashMap::new();
+    for (index, segment) in network.segments.iter().enumerate() {
+        lanes
+            .entry((segment.road_id, segment.lane_section_id, segment.lane_id))
+            .or_insert(index);
+        let last = last_sections.entry(segment.road_id).or_default();
+        *last = (*last).max(segment.lane_section_id);
+    }
+    let mut links: HashMap<(u32, ContactPoint), Vec<&RoadLink>> = HashMap::new();
+    for link in &network.links {
+        links
+            .entry((link.road_id, link.contact))
+            .or_default()
+            .push(link);
+    }
+
+    (0..network.segments.len())
+        .map(|from| {
+            let segment = &network.segments[from];
+            let forward = segment.follows_reference();
+            let mut next = Vec::new();
+            let mut add = |index: usize| {
+                if index != from && !next.contains(&index) {
+                    next.push(index);
+                }
+            };
+
+            // The next lane section along the lane, even if the boundaries
+            // do not line up exactly.
+            let section = if forward {
+                Some(segment.lane_section_id + 1)
+            } else {
+                segment.lane_section_id.checked_sub(1)
+            };
+            let explicit = section
+                .and_then(|section| lanes.get(&(segment.road_id, section, segment.lane_id)))
+                .copied();
+            explicit.into_iter().for_each(&mut add);
+
+            // Leaving the road: lane IDs carry over between roads that meet
+            // start to end, and change sign where they meet head to head.
+            if explicit.is_none() {
+                let leaving = if forward {
+                    ContactPoint::End
+                } else {
+                    ContactPoint::Start
+                };
+                for link in links.get(&(segment.road_id, leaving)).into_iter().flatten() {
+                    let (section, lane_id) = match link.other_contact {
+                        ContactPoint::Start => (1, segment.lane_id),
+                        ContactPoint::End => {
+                            let Some(&last) = last_sections.get(&link.other_road_id) else {
+                                continue;
+                            };
+                            (last, segment.lane_id)
+                        }
+                    };
+                    let lane_id = if link.other_contact == leaving {
+                        -lane_id
+                    } else {
+                        lane_id
+                    };
+                    let Some(&index) = lanes.get(&(link.other_road_id, section, lane_id)) else {
+                        continue;
+                    };
+                    // The lane must drive away from the end it is entered by.
+                    let entering_forward = link.other_contact == ContactPoint::Start;
+                    if network.segments[index].follows_reference() == entering_forward {
+                        add(index);
+                    }
+                }
+            }
+
+            // Lanes starting where this one ends, such as the connecting
+            // roads of junctions, whose links the lane model does not keep.
+            if let Some(&end) = paths[from].last() {
+                let around = cell(end);
+                for dx in -1..=1 {
+                    for dy in -1..=1 {
+                        for dz in -1..=1 {
+                            let nearby = starts.get(&(around + IVec3::new(dx, dy, dz)));
+                            for &(index, start) in nearby.into_iter().flatten() {
+                                if start.distance(end) < CONNECTION_TOLERANCE {
+                                    add(index);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            next
+        })
+        .collect()
+}
+
+// Whether the marking on a lane's outer boundary can be crossed from the
+// inner lane and from the outer one. In a double line the first type is the
+// inner line. An unmarked boundary can be crossed both ways.
+pub fn crossable(mark: Option<&str>) -> (bool, bool) {
+    match mark.unwrap_or("none") {
+        "none" | "" | "broken" | "broken broken" | "botts dots" => (true, true),
+        "broken solid" => (true, false),
+        "solid broken" => (false, true),
+        // Solid lines, curbs, grass, edges and custom marks.
+        _ => (false, false),
+    }
+}
+
+// The lanes each segment may change into: the driving lanes either side of
+// it in the same lane section and going the same way, across a marking
+// that may be crossed from its side.
+pub fn lane_changes(network: &RoadNetwork) -> Vec<Vec<usize>> {
+    let driving = |lane: &RoadSegment| DRIVING_TYPES.contains(&lane.lane_type.as_str());
+    (0..network.segments.len())
+        .map(|from| {
+            let lane = &network.segments[from];
+            if !driving(lane) {
+                return Vec::new();
+            }
+            [lane.lane_id - 1, lane.lane_id + 1]
+                .into_iter()
+                .filter(|&id| id != 0 && id.signum() == lane.lane_id.signum())
+                .filter_map(|id| {
+                    let to = network.find_segment(lane.road_id, lane.lane_section_id, id)?;
+                    let other = &network.segments[to];
+                    if !driving(other) || other.follows_reference() != lane.follows_reference() {
+                        return None;
+                    }
+                    // The marking between them is the inner lane's, as it
+                    // is where the section starts.
+                    let outward = id.abs() > lane.lane_id.abs();
+                    let inner = if outward { lane } else { other };
+                    let (from_inner, from_outer) =
+                        crossable(inner.road_marks.first().map(|mark| mark.kind.as_str()));
+                    (if outward { from_inner } else { from_outer }).then_some(to)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Length of a segment's centerline.
+pub fn segment_length(network: &RoadNetwork, index: usize) -> f64 {
+    let centerline = network.segments[index].centerline();
+    centerline.windows(2).map(|w| w[0].distance(w[1])).sum()
+}
+
+// Time to drive a segment at its speed limit, in seconds.
+fn segment_time(network: &RoadNetwork, index: usize) -> f64 {
+    let speed = network.segments[index].speed.unwrap_or(DEFAULT_SPEED * KMH);
+    segment_length(network, index) / speed.max(KMH)
+}
+
+// Whether a road user may use a lane.
+pub fn allows(lane: &RoadSegment, vehicle: &str) -> bool {
+    let named = |restriction: &str| restriction.eq_ignore_ascii_case(vehicle);
+    if lane
+        .access
+        .iter()
+        .any(|a| !a.allow && named(&a.restriction))
+    {
+        return false;
+    }
+    let allowed: Vec<&str> = lane
+        .access
+        .iter()
+        .filter(|a| a.allow)
+        .map(|a| a.restriction.as_str())
+        .collect();
+    if !allowed.is_empty() {
+        return allowed.into_iter().any(named);
+    }
+    match lane.lane_type.as_str() {
+        t if DRIVING_TYPES.contains(&t) => !named("pedestrian"),
+        "biking" => named("bicycle"),
+        "sidewalk" | "walking" => named("pedestrian"),
+        t @ ("bus" | "taxi" | "HOV" | "tram") => named(t),
+        _ => false,
+    }
+}
+
+// The direction of travel at the start or end of a polyline, flat.
+fn direction(points: &[DVec3], at_end: bool) -> Option<DVec3> {
+    let pair = if at_end {
+        points.get(points.len().checked_sub(2)?..)?
+    } else {
+        points.get(..2)?
+    };
+    let d = (pair[1] - pair[0]) * DVec3::new(1.0, 0.0, 1.0);
+    (d.length() > f64::EPSILON).then(|| d.normalize())
+}
+
+fn turns_back(a: Option<DVec3>, b: Option<DVec3>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.dot(b).clamp(-1.0, 1.0).acos() > U_TURN_ANGLE,
+        _ => false,
+    }
+}
+
+// Whether going from one segment into the next makes a U-turn: turning
+// back where they meet, or on a connecting road that turns back along its
+// length.
+fn is_u_turn(network: &RoadNetwork, from: usize, next: usize) -> bool {
+    let before = conflicts::path(&network.segments[from]);
+    let after = conflicts::path(&network.segments[next]);
+    if turns_back(direction(&before, true), direction(&after, false)) {
+        return true;
+    }
+    let road_id = network.segments[next].road_id;
+    let in_junction = network
+        .roads
+        .get(&road_id)
+        .is_some_and(|info| info.junction.is_some());
+    in_junction && turns_back(direction(&after, false), direction(&after, true))
+}
+
+// Finds the best route from segment `from` to segment `to`, both driven
+// completely, under the given options.
+pub fn shortest_route(
+    network: &RoadNetwork,
+    from: usize,
+    to: usize,
+    options: &RouteOptions,
+) -> Option<Route> {
+    let graph = lane_graph(network);
+    let segments = search(network, &graph, from, to, options, &Banned::default())?;
+    Some(Route::new(network, segments))
+}
+
+// Finds up to `k` routes from segment `from` to segment `to`, best first,
+// with Yen's algorithm: each next route branches off one of the earlier
+// ones at some lane and avoids the ways they went on from there.
+pub fn k_shortest_paths(
+    network: &RoadNetwork,
+    from: usize,
+    to: usize,
+    k: usize,
+    options: &RouteOptions,
+) -> Vec<Route> {
+    let cost = |segments: &[usize]| -> f64 {
+        segments
+            .iter()
+            .map(|&i| weight(network, i, options.weight))
+            .sum()
+    };
+    let graph = lane_graph(network);
+    let mut found: Vec<Vec<usize>> = Vec::new();
+    if k > 0 {
+        found.extend(search(
+            network,
+            &graph,
+            from,
+            to,
+            options,
+            &Banned::default(),
+        ));
+    }
+    let mut candidates: Vec<Vec<usize>> = Vec::new();
+    while found.len() < k {
+        let Some(last) = found.last() else {
+            break;
+        };
+        for branch in 0..last.len() - 1 {
+            let root = &last[..=branch];
+            let mut banned = Banned {
+                segments: root[..branch].iter().copied().collect(),
+                ..Banned::default()
+            };
+            for path in &found {
+                if path.len() > branch + 1 && path[..=branch] == *root {
+                    banned.steps.insert((path[branch], path[branch + 1]));
+                }
+            }
+            let Some(spur) = search(network, &graph, root[branch], to, options, &banned) else {
+                continue;
+            };
+            let mut path = root[..branch].to_vec();
+            path.extend(spur);
+            if !found.contains(&path) && !candidates.contains(&path) {
+                candidates.push(path);
+            }
+        }
+        let best = (0..candidates.len())
+            .min_by(|&a, &b| cost(&candidates[a]).total_cmp(&cost(&candidates[b])));
+        match best {
+            Some(best) => found.push(candidates.swap_remove(best)),
+            None => break,
+        }
+    }
+    found
+        .into_iter()
+        .map(|segments| Route::new(network, segments))
+        .collect()
+}
+
+// Lanes and steps from one lane to the next that a search may not use.
+#[derive(Debug, Default)]
+struct Banned {
+    segments: HashSet<usize>,
+    steps: HashSet<(usize, usize)>,
+}
+
+// The cost of driving a segment.
+fn weight(network: &RoadNetwork, index: usize, weight: Weight) -> f64 {
+    match weight {
+        Weight::Length => segment_length(network, index),
+        Weight::Time => segment_time(network, index),
+    }
+}
+
+// Dijkstra's algorithm over the lane graph (see `lane_graph`), returning the
+// segments of the cheapest path.
+fn search(
+    network: &RoadNetwork,
+    graph: &[Vec<usize>],
+    from: usize,
+    to: usize,
+    options: &RouteOptions,
+    banned: &Banned,
+) -> Option<Vec<usize>> {
+    let count = network.segments.len();
+    if from >= count || to >= count {
+        return None;
+    }
+    let usable = |index: usize| {
+        !banned.segments.contains(&index)
+            && options
+                .vehicle
+                .as_deref()
+                .is_none_or(|vehicle| allows(&network.segments[index], vehicle))
+    };
+    if !usable(from) || !usable(to) {
+        return None;
+    }
+
+    let mut cost = vec![f64::INFINITY; count];
+    let mut previous: Vec<Option<usize>> = vec![None; count];
+    let mut queue = BinaryHeap::new();
+
+    cost[from] = weight(network, from, options.weight);
+    queue.push(Candidate {
+        cost: cost[from],
+        segment: from,
+    });
+
+    while let Some(Candidate {
+        cost: current,
+        segment,
+    }) = queue.pop()
+    {
+        if segment == to {
+            break;
+        }
+        if current > cost[segment] {
+            continue;
+        }
+        for &next in &graph[segment] {
+            if !usable(next)
+                || banned.steps.contains(&(segment, next))
+                || options.no_u_turns && is_u_turn(network, segment, next)
+            {
+                continue;
+            }
+            let candidate = current + weight(network, next, options.weight);
+            if candidate < cost[next] {
+                cost[next] = candidate;
+                previous[next] = Some(segment);
+                queue.push(Candidate {
+                    cost: candidate,
+                    segment: next,
+                });
+            }
+        }
+    }
+
+    if !cost[to].is_finite() {
+        return None;
+    }
+    let mut segments = vec![to];
+    while let Some(prev) = previous[*segments.last().unwrap()] {
+        segments.push(prev);
+    }
+    segments.reverse();
+    Some(segments)
+}
+
+// Queue entry for Dijkstra's algorithm, ordered so the cheapest pops first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate {
+    cost: f64,
+    segment: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    // A road of straight pieces (x, y, heading, length) with one lane
+    // either side of the reference line, in lane sections starting at the
+    // given stations, and with the given links.
+    fn road(id: u32, pieces: &[(f64, f64, f64, f64)], sections: &[f64], link: &str) -> String {
+        let mut s = 0.0;
+        let mut geometry = String::new();
+        for (x, y, hdg, length) in pieces {
+            geometry += &format!(
+                r#"<geometry s="{s}" x="{x}" y="{y}" hdg="{hdg}" length="{length}"><line/></geometry>"#
+            );
+            s += length;
+        }
+        let sections: String = sections
+            .iter()
+            .map(|s| {
+                format!(
+                    r#"<laneSection s="{s}">
+                      <left><lane id="1" type="driving"><width sOffset="0" a="3.5" b="0" c="0" d="0"/></lane></left>
+                      <center><lane id="0" type="none"/></center>
+                      <right><lane id="-1" type="driving"><width sOffset="0" a="3.5" b="0" c="0" d="0"/></lane></right>
+                    </laneSection>"#
+                )
+            })
+            .collect();
+        format!(
+            r#"<road id="{id}" length="{s}" junction="-1">
+              <link>{link}</link>
+              <planView>{geometry}</planView>
+              <lanes>{sections}</lanes>
+            </road>"#
+        )
+    }
+
+    fn network(roads: &[String]) -> RoadNetwork {
+        let xml = format!("<OpenDRIVE><header/>{}</OpenDRIVE>", roads.concat());
+        xodr::read_str(&xml, &LoadTransform::default()).unwrap()
+    }
+
+    fn lane(network: &RoadNetwork, road: u32, section: u32, lane: i32) -> usize {
+        network.find_segment(road, section, lane).unwrap()
+    }
+
+    fn route(network: &RoadNetwork, from: usize, to: usize) -> Option<Vec<usize>> {
+        shortest_route(network, from, to, &RouteOptions::default()).map(|r| r.segments)
+    }
+
+    #[test]
+    fn lanes_are_routed_in_driving_direction() {
+        let network = network(&[road(1, &[(0.0, 0.0, 0.0, 100.0)], &[0.0, 50.0], "")]);
+        let (right_1, right_2) = (lane(&network, 1, 1, -1), lane(&network, 1, 2, -1));
+        let (left_1, left_2) = (lane(&network, 1, 1, 1), lane(&network, 1, 2, 1));
+
+        // Right lanes follow the reference line, left lanes run against it.
+        assert_eq!(
+            route(&network, right_1, right_2),
+            Some(vec![right_1, right_2])
+        );
+        assert_eq!(route(&network, right_2, right_1), None);
+        assert_eq!(route(&network, left_2, left_1), Some(vec![left_2, left_1]));
+        assert_eq!(route(&network, left_1, left_2), None);
+
+        let points = Route::new(&network, vec![left_2, left_1]).polyline(&network);
+        assert!(points.first().unwrap().position.x > points.last().unwrap().position.x);
+        assert!((points.last().unwrap().s - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn linked_roads_are_followed_both_ways() {
+        // Road 2 runs back towards road 1 and ends 5 m short of its end, so
+        // only the link joins them.
+        let network = network(&[
+            road(
+                1,
+                &[(0.0, 0.0, 0.0, 100.0)],
+                &[0.0],
+                r#"<successor elementType="road" elementId="2" contactPoint="end"/>"#,
+            ),
+            road(
+                2,
+                &[(205.0, 0.0, std::f64::consts::PI, 100.0)],
+                &[0.0],
+                r#"<successor elementType="road" elementId="1" contactPoint="end"/>"#,
+            ),
+        ]);
+        let graph = lane_graph(&network);
+        let (out_1, back_1) = (lane(&network, 1, 1, -1), lane(&network, 1, 1, 1));
+        let (out_2, back_2) = (lane(&network, 2, 1, 1), lane(&network, 2, 1, -1));
+        assert_eq!(graph[out_1], vec![out_2]);
+        assert_eq!(graph[back_2], vec![back_1]);
+        assert!(graph[out_2].is_empty() && graph[back_1].is_empty());
+        assert_eq!(route(&network, out_1, out_2), Some(vec![out_1, out_2]));
+        assert_eq!(route(&network, back_2, back_1), Some(vec![back_2, back_1]));
+        assert_eq!(route(&network, out_2, out_1), None);
+    }
+
+    // From road 1 to road 4 either straight along road 2 (110 m) or around
+    // a detour on road 3 (130 m), with each road's XML passed through `edit`.
+    fn detour(edit: impl Fn(u32, String) -> String) -> RoadNetwork {
+        let roads = [
+            road(1, &[(0.0, 0.0, 0.0, 100.0)], &[0.0], ""),
+            road(2, &[(100.0, 0.0, 0.0, 110.0)], &[0.0], ""),
+            road(
+                3,
+                &[
+                    (100.0, 0.0, 0.0, 10.0),
+                    (110.0, 0.0, FRAC_PI_2, 10.0),
+                    (110.0, 10.0, 0.0, 80.0),
+                    (190.0, 10.0, -FRAC_PI_2, 10.0),
+                    (190.0, 0.0, 0.0, 20.0),
+                ],
+                &[0.0],
+                "",
+            ),
+            road(4, &[(210.0, 0.0, 0.0, 50.0)], &[0.0], ""),
+        ];
+        let roads: Vec<String> = (1..).zip(roads).map(|(id, xml)| edit(id, xml)).collect();
+        network(&roads)
+    }
+
+    #[test]
+    fn alternatives_come_cheapest_first() {
+        let network = detour(|_, xml| xml);
+        let [from, straight, detour, to] = [1, 2, 3, 4].map(|road| lane(&network, road, 1, -1));
+        let routes = k_shortest_paths(&network, from, to, 3, &RouteOptions::default());
+        let found: Vec<&[usize]> = routes.iter().map(|r| r.segments.as_slice()).collect();
+        assert_eq!(
+            found,
+            [[from, straight, to].as_slice(), &[from, detour, to]]
+        );
+        assert!(routes[0].length < routes[1].length);
+
+        // Against the traffic there is no way at all.
+        let back = [4, 1].map(|road| lane(&network, road, 1, 1));
+        assert!(
+            k_shortest_paths(&network, back[1], back[0], 3, &RouteOptions::default()).is_empty()
+        );
+    }
+
+    #[test]
+    fn forbidden_u_turns_take_the_way_around() {
+        // Road 1 runs east into road 2, which turns straight back west.
+        // Road 3 goes on east and comes round into road 2 the same way.
+        let network = network(&[
+            road(
+                1,
+                &[(0.0, 0.0, 0.0, 100.0)],
+                &[0.0],
+                r#"<successor elementType="road" elementId="2" contactPoint="start"/>"#,
+            ),
+            road(2, &[(100.0, 0.0, PI, 100.0)], &[0.0], ""),
+            road(
+                3,
+                &[
+                    (100.0, 0.0, 0.0, 10.0),
+                    (110.0, 0.0, FRAC_PI_2, 20.0),
+                    (110.0, 20.0, PI, 10.0),
+                ],
+                &[0.0],
+                r#"<successor elementType="road" elementId="2" contactPoint="start"/>"#,
+            ),
+        ]);
+        let [from, around, to] = [1, 3, 2].map(|road| lane(&network, road, 1, -1));
+        assert_eq!(route(&network, from, to), Some(vec![from, to]));
+
+        let (options, _) = RouteOptions::parse(&["--no-u-turns"]).unwrap();
+        let route = shortest_route(&network, from, to, &options).unwrap();
+        assert_eq!(route.segments, vec![from, around, to]);
+    }
+
+    #[test]
+    fn lanes_closed_to_a_vehicle_are_refused() {
+        // The straight way is a bus lane, the detour a bicycle lane.
+        let lane_type = |id, xml: String| match id {
+            2 => xml.replace(
+                r#"<lane id="-1" type="driving">"#,
+                r#"<lane id="-1" type="bus">"#,
+            ),
+            3 => xml.replace(
+                r#"<lane id="-1" type="driving">"#,
+                r#"<lane id="-1" type="biking">"#,
+            ),
+            _ => xml,
+        };
+        let network = detour(lane_type);
+        let [from, straight, around, to] = [1, 2, 3, 4].map(|road| lane(&network, road, 1, -1));
+        let routed = |vehicle: &str| {
+            let (options, _) = RouteOptions::parse(&["--vehicle", vehicle]).unwrap();
+            shortest_route(&network, from, to, &options).map(|r| r.segments)
+        };
+        assert_eq!(routed("car"), None);
+        assert_eq!(routed("bus"), Some(vec![from, straight, to]));
+        // Driving lanes are open to everyone but pedestrians.
+        assert_eq!(routed("bicycle"), Some(vec![from, around, to]));
+        assert_eq!(routed("pedestrian"), None);
+
+        // With only the bus lane closed to it, a car takes the detour.
+        let network = detour(|id, xml| if id == 2 { lane_type(id, xml) } else { xml });
+        let (options, _) = RouteOptions::parse(&["--vehicle", "car"]).unwrap();
+        let route = shortest_route(&network, from, to, &options).unwrap();
+        assert_eq!(route.segments, vec![from, around, to]);
+    }
+
+    #[test]
+    fn time_weighting_takes_a_longer_faster_way() {
+        // The straight way is limited to 10 km/h, the detour to 100 km/h.
+        let network = detour(|id, xml| {
+            let speed = match id {
+                2 => 10,
+                3 => 100,
+                _ => return xml,
+            };
+            let limit =
+                format!(r#"<type s="0" type="town"><speed max="{speed}" unit="km/h"/></type>"#);
+            xml.replacen("<link>", &format!("{limit}<link>"), 1)
+        });
+        let [from, straight, around, to] = [1, 2, 3, 4].map(|road| lane(&network, road, 1, -1));
+        let shortest = shortest_route(&network, from, to, &RouteOptions::default()).unwrap();
+        assert_eq!(shortest.segments, vec![from, straight, to]);
+
+        let (options, _) = RouteOptions::parse(&["--fastest"]).unwrap();
+        let fastest = shortest_route(&network, from, to, &options).unwrap();
+        assert_eq!(fastest.segments, vec![from, around, to]);
+        assert!(fastest.length > shortest.length);
+        assert!(fastest.time < shortest.time);
+    }
+}
@@ -0,0 +1,82 @@
+// The sample maps in `tests/maps`, for the tests of every module.
+//
+// Each sample is a small map written for the tests, covering one part of
+// the reader: a straight road, an arc, a junction, an elevation profile with
+// a lane section change, and signals. Tests load them the way the viewer
+// does, with `load`, and find their lanes with `lane`; the checks on what
+// comes out live in the tests of the module doing the work.
+
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+
+use crate::lane_report::measure;
+use crate::loader::load_network;
+use crate::{RoadNetwork, RoadSegment};
+
+// Sampled geometry is compared to the exact one within this, in meters.
+pub const TOLERANCE: f64 = 0.01;
+
+pub const SAMPLES: [&str; 5] = [
+    "straight.xodr",
+    "curve.xodr",
+    "junction.xodr",
+    "elevation.xodr",
+    "signals.xodr",
+];
+
+pub fn path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/maps")
+        .join(name)
+}
+
+// The file as it is, for tests that change it before reading.
+pub fn text(name: &str) -> String {
+    std::fs::read_to_string(path(name)).unwrap_or_else(|e| panic!("{name}: {e}"))
+}
+
+pub fn load(name: &str) -> RoadNetwork {
+    load_network(Some(&path(name))).unwrap_or_else(|e| panic!("{e}"))
+}
+
+pub fn lane(network: &RoadNetwork, road: u32, section: u32, lane: i32) -> &RoadSegment {
+    network
+        .segments
+        .iter()
+        .find(|s| s.road_id == road && s.lane_section_id == section && s.lane_id == lane)
+        .unwrap_or_else(|| panic!("no lane {road}:{section}:{lane}"))
+}
+
+// The centerline length of a lane segment.
+pub fn length(network: &RoadNetwork, segment: &RoadSegment) -> f64 {
+    let index = network
+        .segments
+        .iter()
+        .position(|s| std::ptr::eq(s, segment))
+        .unwrap();
+    measure(network)[index].length
+}
+
+// A fresh directory for one test's files, so that tests running at the same
+// time do not share any.
+pub fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("road-visualizer-{}-{test}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{actual} is not within {tolerance} of {expected}"
+    );
+}
+
+pub fn assert_points_near(actual: DVec3, expected: DVec3) {
+    assert!(
+        actual.distance(expected) <= TOLERANCE,
+        "{actual} is not within {TOLERANCE} of {expected}"
+    );
+}
@@ -0,0 +1,446 @@
+// Picking roads with the mouse.
+//
+// A left click that does not drag the camera casts a ray from the cursor and
+// selects the lane surface it hits first; a click that hits nothing clears
+// the selection. Clicks on UI nodes that track interaction, such as charts,
+// are left to the UI. Analysis views follow the selected road.
+//
+// Selection is progressive: the first click on a road selects the road, and
+// further clicks on it narrow the selection down to the lane, then the lane
+// section, then the exact point, whose (s, t) is copied to the clipboard.
+// Clicking another road starts over. The selection is outlined and filled
+// on the map, in the overlay pass (see `overlay_pass`), and described at the
+// top of the window.
+//
+// Keys: P steps the detail of the current selection, as another click would.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::clipboard;
+use crate::cross_section::cut;
+use crate::edit::NetworkChanged;
+use crate::i18n::Locale;
+use crate::origin::{RenderOrigin, WorldPosition};
+use crate::overlay_pass::{overlay_layers, overlay_material, OverlayLevel};
+use crate::tessellation::road_surface;
+use crate::theme::Theme;
+use crate::{camera_orbit, MainCamera, RoadNetwork, RoadSegment};
+
+// A press and release further apart than this, in pixels, is a drag.
+pub const CLICK_TOLERANCE: f32 = 4.0;
+
+// The outline is lifted by this much to stay above the markings.
+const OUTLINE_LIFT: f32 = 0.05;
+
+// Opacity of the selection's fill.
+const FILL_ALPHA: f32 = 0.3;
+
+// How much of the road under the cursor is selected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Detail {
+    #[default]
+    Road,
+    Lane,
+    Section,
+    Point,
+}
+
+impl Detail {
+    // The next finer detail; points stay points.
+    pub fn next(self) -> Self {
+        match self {
+            Detail::Road => Detail::Lane,
+            Detail::Lane => Detail::Section,
+            Detail::Section | Detail::Point => Detail::Point,
+        }
+    }
+}
+
+// A point on a lane surface hit by a pick ray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pick {
+    pub detail: Detail,
+    // Index into `RoadNetwork::segments`.
+    pub segment: usize,
+    pub road_id: u32,
+    pub lane_section_id: u32,
+    pub lane_id: i32,
+    // Station along the road and the viewer-frame position of the hit.
+    pub s: f64,
+    pub position: DVec3,
+    // Lateral offset of the hit from the reference line, left positive.
+    pub t: Option<f64>,
+}
+
+impl Pick {
+    // Describes the selection at its detail.
+    pub fn describe(&self) -> String {
+        match self.detail {
+            Detail::Road => format!("road {}", self.road_id),
+            Detail::Lane => format!("road {} lane {}", self.road_id, self.lane_id),
+            Detail::Section => format!(
+                "road {} lane {} section {} (s {:.2})",
+                self.road_id, self.lane_id, self.lane_section_id, self.s
+            ),
+            Detail::Point => match self.t {
+                Some(t) => format!("road {} s={:.3} t={t:.3}", self.road_id, self.s),
+                None => format!("road {} s={:.3}", self.road_id, self.s),
+            },
+        }
+    }
+}
+
+// What is currently selected, if anything.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Selection(pub Option<Pick>);
+
+impl Selection {
+    pub fn road_id(&self) -> Option<u32> {
+        self.0.map(|pick| pick.road_id)
+    }
+}
+
+// Marks the selection readout.
+#[derive(Component)]
+struct SelectionLabel;
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .add_systems(Startup, spawn_label)
+            .add_systems(
+                Update,
+                (
+                    (select_on_click, refine_on_key, report_selection).chain(),
+                    draw_outline,
+                    fill_selection.run_if(
+                        resource_changed::<Selection>.or_else(on_event::<NetworkChanged>()),
+                    ),
+                )
+                    .after(camera_orbit),
+            );
+    }
+}
+
+// Intersects a ray with a triangle, returning the distance along the ray.
+pub fn ray_triangle(origin: DVec3, direction: DVec3, [a, b, c]: [DVec3; 3]) -> Option<f64> {
+    let (ab, ac) = (b - a, c - a);
+    let p = direction.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) / det;
+    let q = to_origin.cross(ab);
+    let v = direction.dot(q) / det;
+    if u < 0.0 || v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(q) / det;
+    (distance > 0.0).then_some(distance)
+}
+
+// Where a ray hits a segment's surface first: the distance along the ray and
+// the station of the hit.
+pub fn hit_segment(segment: &RoadSegment, origin: DVec3, direction: DVec3) -> Option<(f64, f64)> {
+    let (left, right) = (&segment.left_side, &segment.right_side);
+    let count = left.len().min(right.len());
+    if count < 2 {
+        return None;
+    }
+    let mut best: Option<(f64, f64)> = None;
+    for i in 0..count - 1 {
+        let quad = [
+            [left[i], right[i], left[i + 1]],
+            [right[i], right[i + 1], left[i + 1]],
+        ];
+        let Some(distance) = quad
+            .into_iter()
+            .filter_map(|triangle| ray_triangle(origin, direction, triangle))
+            .reduce(f64::min)
+        else {
+            continue;
+        };
+        if matches!(best, Some((d, _)) if d <= distance) {
+            continue;
+        }
+        // Project the hit onto the middle of the quad for the station.
+        let hit = origin + direction * distance;
+        let (m0, m1) = (
+            (left[i] + right[i]) / 2.0,
+            (left[i + 1] + right[i + 1]) / 2.0,
+        );
+        let along = (hit - m0).dot(m1 - m0) / (m1 - m0).length_squared().max(1e-12);
+        let fraction = (i as f64 + along.clamp(0.0, 1.0)) / (count - 1) as f64;
+        best = Some((
+            distance,
+            segment.start_s + (segment.end_s - segment.start_s) * fraction,
+        ));
+    }
+    best
+}
+
+// Casts a viewer-frame ray at the network and returns the nearest hit.
+pub fn pick(network: &RoadNetwork, origin: DVec3, direction: DVec3) -> Option<Pick> {
+    let mut best: Option<(f64, Pick)> = None;
+    for (index, segment) in network.segments.iter().enumerate() {
+        let Some((distance, s)) = hit_segment(segment, origin, direction) else {
+            continue;
+        };
+        if matches!(best, Some((d, _)) if d <= distance) {
+            continue;
+        }
+        best = Some((
+            distance,
+            Pick {
+                detail: Detail::Road,
+                segment: index,
+                road_id: segment.road_id,
+                lane_section_id: segment.lane_section_id,
+                lane_id: segment.lane_id,
+                s,
+                position: origin + direction * distance,
+                t: None,
+            },
+        ));
+    }
+    best.map(|(_, mut pick)| {
+        pick.t = lateral_offset(network, pick.road_id, pick.s, pick.position);
+        pick
+    })
+}
+
+// The offset of a point from the reference line of a road at station `s`,
+// left positive.
+pub fn lateral_offset(network: &RoadNetwork, road_id: u32, s: f64, position: DVec3) -> Option<f64> {
+    let cut = cut(network, road_id, s)?;
+    Some((position - cut.reference).dot(DVec3::Y.cross(cut.direction)))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn select_on_click(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    interactions: Query<&Interaction>,
+    origin: Res<RenderOrigin>,
+    network: Res<RoadNetwork>,
+    mut selection: ResMut<Selection>,
+    mut pressed_at: Local<Option<Vec2>>,
+) {
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    if buttons.just_pressed(MouseButton::Left) {
+        let over_ui = interactions
+            .iter()
+            .any(|interaction| *interaction != Interaction::None);
+        *pressed_at = (!over_ui).then_some(cursor);
+    }
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(pressed) = pressed_at.take() else {
+        return;
+    };
+    if pressed.distance(cursor) > CLICK_TOLERANCE {
+        return;
+    }
+    let Ok((camera, transform)) = cameras.get_single() else {
+        return;
+    };
+    // In split view, clicks on the plan do not pick.
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+    if !viewport.contains(cursor) {
+        return;
+    }
+    let Some(ray) = camera.viewport_to_world(transform, cursor - viewport.min) else {
+        return;
+    };
+    let hit = pick(
+        &network,
+        origin.0 + ray.origin.as_dvec3(),
+        ray.direction.as_dvec3(),
+    );
+    // Clicking the selected road again narrows the selection down.
+    selection.0 = hit.map(|hit| match selection.0 {
+        Some(current) if current.road_id == hit.road_id => Pick {
+            detail: current.detail.next(),
+            ..hit
+        },
+        _ => hit,
+    });
+}
+
+fn refine_on_key(keys: Res<ButtonInput<KeyCode>>, mut selection: ResMut<Selection>) {
+    if !keys.just_pressed(KeyCode::KeyP) {
+        return;
+    }
+    if let Some(pick) = selection.0.as_mut() {
+        pick.detail = match pick.detail {
+            Detail::Point => Detail::Road,
+            detail => detail.next(),
+        };
+    }
+}
+
+// Logs and shows the selection when it changes, copying points to the
+// clipboard.
+fn report_selection(
+    selection: Res<Selection>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut labels: Query<(&mut Text, &mut Visibility), With<SelectionLabel>>,
+) {
+    if theme.is_changed() {
+        for (mut text, _) in &mut labels {
+            for section in &mut text.sections {
+                section.style.color = theme.highlight;
+            }
+        }
+    }
+    if !selection.is_changed() {
+        return;
+    }
+    let description = selection.0.map(|pick| {
+        let mut description = pick.describe();
+        if pick.detail == Detail::Point {
+            match clipboard::copy(&description) {
+                Ok(()) => {
+                    description.push_str("  ");
+                    description.push_str(&locale.text("selection-copied", &[]));
+                }
+                Err(message) => warn!("could not copy the point: {message}"),
+            }
+        }
+        description
+    });
+    match &description {
+        Some(description) => info!("selected {description}"),
+        None => info!("selection cleared"),
+    }
+    for (mut text, mut visibility) in &mut labels {
+        *visibility = if description.is_some() {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        *text = Text::from_section(
+            description.clone().unwrap_or_default(),
+            TextStyle {
+                font_size: 14.0,
+                color: theme.highlight,
+                ..default()
+            },
+        );
+    }
+}
+
+fn spawn_label(mut commands: Commands) {
+    commands.spawn((
+        TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(40.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        SelectionLabel,
+    ));
+}
+
+// Outlines the selected lanes: the whole road, one lane through all its
+// sections, or one lane section, which a selected point is marked on.
+// Whether a lane is part of the selection.
+fn selected(pick: Pick, segment: &RoadSegment) -> bool {
+    segment.road_id == pick.road_id
+        && match pick.detail {
+            Detail::Road => true,
+            Detail::Lane => segment.lane_id == pick.lane_id,
+            Detail::Section | Detail::Point => {
+                segment.lane_id == pick.lane_id && segment.lane_section_id == pick.lane_section_id
+            }
+        }
+}
+
+fn draw_outline(
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let Some(pick) = selection.0 else {
+        return;
+    };
+    let lift = Vec3::Y * OUTLINE_LIFT;
+    if pick.detail == Detail::Point {
+        let p = origin.to_render(pick.position) + lift;
+        gizmos.sphere(p, Quat::IDENTITY, 0.3, theme.highlight);
+        gizmos.line(p, p + Vec3::Y * 3.0, theme.highlight);
+    }
+    for segment in network.segments.iter().filter(|s| selected(pick, s)) {
+        for side in [&segment.left_side, &segment.right_side] {
+            gizmos.linestrip(
+                side.iter().map(|p| origin.to_render(*p) + lift),
+                theme.highlight,
+            );
+        }
+    }
+}
+
+// Marks the fill of a selected lane.
+#[derive(Component)]
+struct SelectionFill;
+
+// Covers the selected lanes with a translucent fill.
+fn fill_selection(
+    mut commands: Commands,
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    theme: Res<Theme>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    fills: Query<Entity, With<SelectionFill>>,
+) {
+    for fill in &fills {
+        commands.entity(fill).despawn();
+    }
+    let Some(pick) = selection.0 else {
+        return;
+    };
+    let material = materials.add(overlay_material(
+        theme.highlight.with_a(FILL_ALPHA),
+        OverlayLevel::Highlight,
+    ));
+    for segment in network.segments.iter().filter(|s| selected(pick, s)) {
+        let Some(anchor) = segment.left_side.first().copied() else {
+            continue;
+        };
+        let surface = road_surface(segment, anchor);
+        if surface.is_empty() {
+            continue;
+        }
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(surface.to_mesh()),
+                material: material.clone(),
+                ..default()
+            },
+            WorldPosition(anchor),
+            overlay_layers(),
+            SelectionFill,
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+// Settings panel.
+//
+// A panel for the viewer's display preferences: whether positions are shown
+// in map or geographic coordinates, how many decimals positions and
+// stations get (see `display`), and the unit of speeds (see `units`). Each
+// row is a button; clicking it, or its - and + buttons, changes the
+// setting at once.
+//
+// Keys: D shows or hides the settings panel.
+
+use bevy::prelude::*;
+
+use crate::display::{DisplaySettings, MAX_DECIMALS};
+use crate::i18n::Locale;
+use crate::units::{SpeedUnit, SPEED_UNITS};
+use crate::RoadNetwork;
+
+#[derive(Component)]
+struct SettingsPanel;
+
+// What a panel button changes.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum Setting {
+    Coordinates,
+    CoordinateDecimals(isize),
+    StationDecimals(isize),
+    SpeedUnit,
+}
+
+// How a row changes its setting: by clicking its caption, or its - and +
+// buttons.
+enum Control {
+    Caption(Setting),
+    Steps(Setting, Setting),
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_panel)
+            .add_systems(Update, (toggle_panel, change_on_click, fill_panel).chain());
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(40.0),
+                top: Val::Px(40.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        SettingsPanel,
+    ));
+}
+
+fn toggle_panel(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panels: Query<&mut Visibility, With<SettingsPanel>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyD) {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn change_on_click(
+    mut display: ResMut<DisplaySettings>,
+    mut speed_unit: ResMut<SpeedUnit>,
+    buttons: Query<(&Interaction, &Setting), Changed<Interaction>>,
+) {
+    let step = |decimals: usize, by: isize| decimals.saturating_add_signed(by).min(MAX_DECIMALS);
+    for (interaction, setting) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *setting {
+            Setting::Coordinates => display.geographic = !display.geographic,
+            Setting::CoordinateDecimals(by) => {
+                display.coordinate_decimals = step(display.coordinate_decimals, by)
+            }
+            Setting::StationDecimals(by) => {
+                display.station_decimals = step(display.station_decimals, by)
+            }
+            Setting::SpeedUnit => {
+                let next = SPEED_UNITS
+                    .iter()
+                    .position(|unit| unit == &*speed_unit)
+                    .map_or(0, |i| (i + 1) % SPEED_UNITS.len());
+                *speed_unit = SPEED_UNITS[next];
+            }
+        }
+    }
+}
+
+// Rebuilds the panel when a setting, or whether the map is georeferenced,
+// changes.
+fn fill_panel(
+    mut commands: Commands,
+    display: Res<DisplaySettings>,
+    speed_unit: Res<SpeedUnit>,
+    network: Res<RoadNetwork>,
+    locale: Res<Locale>,
+    panels: Query<Entity, With<SettingsPanel>>,
+) {
+    if !display.is_changed() && !speed_unit.is_changed() && !network.is_changed() {
+        return;
+    }
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+    let mut coordinates = locale.text(
+        if display.geographic {
+            "settings-geographic"
+        } else {
+            "settings-local"
+        },
+        &[],
+    );
+    if display.geographic && network.geo.is_none() {
+        coordinates.push(' ');
+        coordinates.push_str(&locale.text("settings-not-georeferenced", &[]));
+    }
+    let rows = [
+        (
+            locale.text("settings-coordinates", &[("kind", &coordinates)]),
+            Control::Caption(Setting::Coordinates),
+        ),
+        (
+            locale.text(
+                "settings-coordinate-decimals",
+                &[("count", &display.coordinate_decimals)],
+            ),
+            Control::Steps(
+                Setting::CoordinateDecimals(-1),
+                Setting::CoordinateDecimals(1),
+            ),
+        ),
+        (
+            locale.text(
+                "settings-station-decimals",
+                &[("count", &display.station_decimals)],
+            ),
+            Control::Steps(Setting::StationDecimals(-1), Setting::StationDecimals(1)),
+        ),
+        (
+            locale.text("settings-speed-unit", &[("unit", &speed_unit.name())]),
+            Control::Caption(Setting::SpeedUnit),
+        ),
+    ];
+
+    for panel in &panels {
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                locale.text("settings-title", &[]),
+                style(Color::WHITE),
+            ));
+            for (caption, control) in &rows {
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(6.0),
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        let mut button = |setting: Setting, caption: String| {
+                            row.spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(6.0), Val::Px(1.0)),
+                                        ..default()
+                                    },
+                                    background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                                    ..default()
+                                },
+                                setting,
+                            ))
+                            .with_children(|button| {
+                                button.spawn(TextBundle::from_section(
+                                    caption,
+                                    style(Color::rgb(1.0, 0.9, 0.1)),
+                                ));
+                            });
+                        };
+                        match control {
+                            Control::Caption(setting) => button(*setting, caption.clone()),
+                            Control::Steps(less, more) => {
+                                button(*less, "-".to_string());
+                                button(*more, "+".to_string());
+                                row.spawn(TextBundle::from_section(
+                                    caption.clone(),
+                                    style(Color::WHITE),
+                                ));
+                            }
+                        }
+                    });
+            }
+        });
+    }
+}
@@ -0,0 +1,353 @@
+// Sight distance analysis along a lane.
+//
+// For the selected lane, a driver is placed on its centerline every
+// `DRIVER_SPACING` meters at eye height, looking the way traffic flows. The
+// available sight distance there is how far along the lane an object of the
+// target height stays visible: lines of sight to targets further and further
+// ahead are tested against the road surfaces of the whole map, and the first
+// one that is blocked (by a crest, or a road passing above) ends it. Other
+// occluders such as buildings or terrain are not part of the map and are
+// not considered.
+//
+// The result is charted against s with the threshold, and stretches below
+// the threshold are marked red on the lane and logged.
+//
+// Keys: V analyzes the selected lane (or clears the analysis).
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::chart::{spawn_chart, ChartContent, ChartData, Panel, Series, SECOND_ROW};
+use crate::i18n::Locale;
+use crate::origin::RenderOrigin;
+use crate::selection::{hit_segment, Selection};
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork, RoadSegment};
+
+// Distance between driver positions, and between targets ahead, in meters.
+const DRIVER_SPACING: f64 = 10.0;
+const TARGET_SPACING: f64 = 5.0;
+
+// How the analysis is run.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct SightSettings {
+    // Driver eye and target object heights above the lane, in meters.
+    pub eye_height: f64,
+    pub target_height: f64,
+    // Sight distances below this are flagged, in meters.
+    pub threshold: f64,
+    // Sight is not checked further than this, in meters.
+    pub max_distance: f64,
+}
+
+impl Default for SightSettings {
+    // Passenger car eye height and the common 0.6 m object (a taillight).
+    fn default() -> Self {
+        Self {
+            eye_height: 1.08,
+            target_height: 0.6,
+            threshold: 100.0,
+            max_distance: 300.0,
+        }
+    }
+}
+
+// The available sight distance at one driver position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SightSample {
+    // Road station of the driver.
+    pub s: f64,
+    pub position: DVec3,
+    pub distance: f64,
+}
+
+// The analysis of one lane.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct SightAnalysis {
+    pub lane: Option<(u32, i32)>,
+    // In order of increasing s.
+    pub samples: Vec<SightSample>,
+}
+
+// Marks the sight distance chart.
+#[derive(Component)]
+struct SightChart;
+
+pub struct SightPlugin;
+
+impl Plugin for SightPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SightSettings>()
+            .init_resource::<SightAnalysis>()
+            .add_systems(Startup, |mut commands: Commands| {
+                spawn_chart(&mut commands, 10.0, SECOND_ROW, SightChart);
+            })
+            .add_systems(Update, (analyze_on_key, draw_flags.after(camera_orbit)));
+    }
+}
+
+// The centerline of a lane through all sections of its road, in driving
+// order, with the road station of every point.
+fn lane_path(network: &RoadNetwork, road_id: u32, lane_id: i32) -> Vec<(f64, DVec3)> {
+    let mut sections: Vec<&RoadSegment> = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id && segment.lane_id == lane_id)
+        .collect();
+    sections.sort_by_key(|segment| segment.lane_section_id);
+
+    let mut path: Vec<(f64, DVec3)> = Vec::new();
+    for segment in &sections {
+        let middle = segment.centerline();
+        let last = middle.len().saturating_sub(1).max(1) as f64;
+        for (i, point) in middle.into_iter().enumerate() {
+            // Sections share their end points.
+            if path.last().is_some_and(|(_, p)| p.distance(point) < 1e-6) {
+                continue;
+            }
+            let s = segment.start_s + (segment.end_s - segment.start_s) * i as f64 / last;
+            path.push((s, point));
+        }
+    }
+    if sections
+        .first()
+        .is_some_and(|lane| !lane.follows_reference())
+    {
+        path.reverse();
+    }
+    path
+}
+
+// Distance along a path to each of its points.
+fn path_lengths(path: &[(f64, DVec3)]) -> Vec<f64> {
+    let mut length = 0.0;
+    let mut out = Vec::with_capacity(path.len());
+    for (i, (_, p)) in path.iter().enumerate() {
+        if i > 0 {
+            length += p.distance(path[i - 1].1);
+        }
+        out.push(length);
+    }
+    out
+}
+
+// The point `along` meters into a path.
+fn path_point(path: &[(f64, DVec3)], lengths: &[f64], along: f64) -> Option<(f64, DVec3)> {
+    let i = lengths.partition_point(|&l| l < along);
+    if i == 0 {
+        return path.first().copied();
+    }
+    let (a, b) = (path[i - 1], *path.get(i)?);
+    let t = (along - lengths[i - 1]) / (lengths[i] - lengths[i - 1]).max(1e-12);
+    Some((a.0 + (b.0 - a.0) * t, a.1.lerp(b.1, t)))
+}
+
+// The bounding box of a segment, for culling occluders.
+fn bounds(segment: &RoadSegment) -> (DVec3, DVec3) {
+    segment
+        .left_side
+        .iter()
+        .chain(&segment.right_side)
+        .fold((DVec3::INFINITY, DVec3::NEG_INFINITY), |(min, max), p| {
+            (min.min(*p), max.max(*p))
+        })
+}
+
+// Whether the straight line from `a` to `b` passes through a road surface.
+fn blocked(network: &RoadNetwork, boxes: &[(DVec3, DVec3)], a: DVec3, b: DVec3) -> bool {
+    let (low, high) = (a.min(b), a.max(b));
+    let length = a.distance(b);
+    let direction = (b - a) / length.max(1e-12);
+    network
+        .segments
+        .iter()
+        .zip(boxes)
+        .any(|(segment, (min, max))| {
+            let overlaps = min.x <= high.x
+                && max.x >= low.x
+                && min.z <= high.z
+                && max.z >= low.z
+                && min.y <= high.y;
+            overlaps
+                && hit_segment(segment, a, direction).is_some_and(|(distance, _)| distance < length)
+        })
+}
+
+// Runs the analysis on one lane.
+pub fn analyze(
+    network: &RoadNetwork,
+    settings: &SightSettings,
+    road_id: u32,
+    lane_id: i32,
+) -> SightAnalysis {
+    let path = lane_path(network, road_id, lane_id);
+    let lengths = path_lengths(&path);
+    let boxes: Vec<(DVec3, DVec3)> = network.segments.iter().map(bounds).collect();
+    let total = lengths.last().copied().unwrap_or(0.0);
+    let eye = DVec3::Y * settings.eye_height;
+    let target = DVec3::Y * settings.target_height;
+
+    let mut samples = Vec::new();
+    let mut along = 0.0;
+    while along <= total {
+        let Some((s, driver)) = path_point(&path, &lengths, along) else {
+            break;
+        };
+        let mut distance = 0.0;
+        let mut ahead = TARGET_SPACING;
+        while ahead <= settings.max_distance && along + ahead <= total {
+            let Some((_, object)) = path_point(&path, &lengths, along + ahead) else {
+                break;
+            };
+            if blocked(network, &boxes, driver + eye, object + target) {
+                break;
+            }
+            distance = ahead;
+            ahead += TARGET_SPACING;
+        }
+        // Near the end of the lane the view is cut short by the lane itself,
+        // not by an obstruction; count it as unlimited.
+        if along + ahead > total && ahead <= settings.max_distance {
+            distance = settings.max_distance;
+        }
+        samples.push(SightSample {
+            s,
+            position: driver,
+            distance,
+        });
+        along += DRIVER_SPACING;
+    }
+    samples.sort_by(|a, b| a.s.total_cmp(&b.s));
+    SightAnalysis {
+        lane: Some((road_id, lane_id)),
+        samples,
+    }
+}
+
+// The stretches of road stations where sight falls below the threshold.
+pub fn short_stretches(analysis: &SightAnalysis, threshold: f64) -> Vec<(f64, f64)> {
+    let mut stretches: Vec<(f64, f64)> = Vec::new();
+    let mut open: Option<(f64, f64)> = None;
+    for sample in &analysis.samples {
+        if sample.distance < threshold {
+            let start = open.map_or(sample.s, |(start, _)| start);
+            open = Some((start, sample.s));
+        } else if let Some(stretch) = open.take() {
+            stretches.push(stretch);
+        }
+    }
+    stretches.extend(open);
+    stretches
+}
+
+fn analyze_on_key(
+    keys: Res<ButtonInput<KeyCode>>,
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    settings: Res<SightSettings>,
+    locale: Res<Locale>,
+    mut analysis: ResMut<SightAnalysis>,
+    mut charts: Query<&mut ChartData, With<SightChart>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyV) {
+        return;
+    }
+    *analysis = match (analysis.lane, selection.0) {
+        (None, Some(pick)) => analyze(&network, &settings, pick.road_id, pick.lane_id),
+        _ => SightAnalysis::default(),
+    };
+
+    let content = analysis.lane.map(|(road_id, lane_id)| {
+        let stretches = short_stretches(&analysis, settings.threshold);
+        for (start, end) in &stretches {
+            warn!(
+                "road {road_id} lane {lane_id}: sight distance below {} m from s = {start:.0} to {end:.0}",
+                settings.threshold
+            );
+        }
+        ChartContent {
+            title: locale.text(
+                "sight-title",
+                &[
+                    ("road", &road_id),
+                    ("lane", &lane_id),
+                    ("stretches", &stretches.len()),
+                    ("threshold", &settings.threshold),
+                ],
+            ),
+            stations: analysis.samples.iter().map(|sample| sample.s).collect(),
+            panels: vec![Panel {
+                unit: "m",
+                series: vec![
+                    Series {
+                        label: locale.text("sight-distance", &[]),
+                        values: analysis.samples.iter().map(|x| x.distance).collect(),
+                        color: [120, 220, 255, 255],
+                    },
+                    Series {
+                        label: locale.text("sight-threshold", &[]),
+                        values: vec![settings.threshold; analysis.samples.len()],
+                        color: [255, 80, 80, 255],
+                    },
+                ],
+            }],
+        }
+    });
+    for mut chart in &mut charts {
+        chart.0.clone_from(&content);
+    }
+}
+
+// Marks driver positions with too little sight distance.
+fn draw_flags(
+    analysis: Res<SightAnalysis>,
+    settings: Res<SightSettings>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    for sample in &analysis.samples {
+        let p = origin.to_render(sample.position);
+        let color = if sample.distance < settings.threshold {
+            theme.error
+        } else {
+            theme.pass
+        };
+        gizmos.line(p, p + Vec3::Y * settings.eye_height as f32, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn a_crest_shortens_the_sight_ahead_of_it() {
+        let mut network = load("straight.xodr");
+        let settings = SightSettings::default();
+        let flat = analyze(&network, &settings, 1, -1);
+        assert!(flat.samples.len() > 5);
+        assert!(flat.samples.iter().all(|sample| sample.distance == 300.0));
+        assert!(short_stretches(&flat, settings.threshold).is_empty());
+
+        // A 3 m hump peaking at 50 m, 40 m long.
+        for segment in &mut network.segments {
+            for point in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                point.y += 3.0 * (1.0 - (point.x - 50.0).abs() / 20.0).max(0.0);
+            }
+        }
+        let hump = analyze(&network, &settings, 1, -1);
+        let stretches = short_stretches(&hump, settings.threshold);
+        assert_eq!(stretches.len(), 1);
+        let (start, end) = stretches[0];
+        assert_eq!(start, 0.0);
+        assert!(end < 50.0, "{end}");
+        // Over the top the road falls away out of the line of sight.
+        assert!(hump
+            .samples
+            .iter()
+            .filter(|sample| sample.s >= 50.0)
+            .all(|sample| sample.distance == 300.0));
+    }
+}
@@ -0,0 +1,794 @@
+// Placing, moving and turning traffic signs.
+//
+// The sign tool adds `<signal>` records from a catalog of common sign types.
+// Catalogs belong to a country code, whose codes the signs carry: "DE" uses
+// the German catalogue (StVO), which most OpenDRIVE files follow, and "US"
+// the MUTCD. More catalogs are read from a JSON file with `signs load
+// <file>`, and replace the built-in ones of the same country:
+//
+//   { "catalogs": [
+//     { "country": "DE",
+//       "signs": [ { "name": "speed limit 30", "type": "274", "subtype": "53",
+//                    "value": 30, "unit": "km/h", "height": 0.6,
+//                    "width": 0.6 } ] }
+//   ] }
+//
+// With the tool on, a click on a road places the current sign at the (s, t)
+// clicked, on a post, facing the traffic of the lane it stands in (so
+// orientation "+" for lanes driven along the reference line, "-" for the
+// others). Pressing on a sign's icon and dragging moves it to where the
+// mouse is released, along the road and across it, or onto another road. The
+// sign placed or moved last is the current one, which can be turned to face
+// the other way. Every change is an edit, run as a transaction, and can be
+// undone (see `transaction`). While the tool is on, each sign shows the
+// direction of the traffic it is for as an arrow.
+//
+// `signs` in the console lists the catalog in use; `signs country <code>`
+// and `signs use <name>` pick the catalog and the sign, and `signs place
+// <road> <s> <t>`, `signs move <id> <road> <s> <t>`, `signs flip [id]` and
+// `signs remove <id>` edit without the mouse.
+//
+// Keys: S switches the sign tool on and off, Q steps through the catalog,
+// E turns the current sign around.
+
+use std::path::Path;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::cross_section::cut;
+use crate::json::{self, Json};
+use crate::origin::RenderOrigin;
+use crate::selection::{pick, CLICK_TOLERANCE};
+use crate::signals::{Signal, SignalKind};
+use crate::theme::Theme;
+use crate::transaction::{self, Transaction};
+use crate::xodr::ElementXml;
+use crate::{camera_orbit, MainCamera, RoadNetwork};
+
+// The catalog in use at start.
+const DEFAULT_COUNTRY: &str = "DE";
+
+// How close to a sign's icon a press grabs it, in meters.
+const GRAB_RADIUS: f64 = 1.0;
+
+// Height of the bottom of placed signs above the road, in meters.
+const SIGN_Z_OFFSET: f64 = 2.0;
+
+// Length of the arrows showing what traffic signs are for, in meters.
+const ARROW_LENGTH: f64 = 2.0;
+
+// A sign type of a catalog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignType {
+    pub name: String,
+    // The OpenDRIVE type and subtype codes.
+    pub type_code: String,
+    pub subtype: String,
+    pub value: Option<f64>,
+    pub unit: String,
+    // Size of the sign plate, in meters.
+    pub height: f64,
+    pub width: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catalog {
+    pub country: String,
+    pub signs: Vec<SignType>,
+}
+
+fn sign(name: &str, type_code: &str, subtype: &str, value: Option<f64>, unit: &str) -> SignType {
+    SignType {
+        name: name.to_string(),
+        type_code: type_code.to_string(),
+        subtype: subtype.to_string(),
+        value,
+        unit: unit.to_string(),
+        height: 0.6,
+        width: 0.6,
+    }
+}
+
+pub fn built_in() -> Vec<Catalog> {
+    let de = vec![
+        sign("stop", "206", "-1", None, ""),
+        sign("yield", "205", "-1", None, ""),
+        sign("priority road", "306", "-1", None, ""),
+        sign("no entry", "267", "-1", None, ""),
+        sign("speed limit 30", "274", "53", Some(30.0), "km/h"),
+        sign("speed limit 50", "274", "55", Some(50.0), "km/h"),
+        sign("speed limit 70", "274", "57", Some(70.0), "km/h"),
+        sign("speed limit 100", "274", "60", Some(100.0), "km/h"),
+        sign("pedestrian crossing", "350", "10", None, ""),
+    ];
+    let us = vec![
+        sign("stop", "R1-1", "", None, ""),
+        sign("yield", "R1-2", "", None, ""),
+        sign("do not enter", "R5-1", "", None, ""),
+        sign("speed limit 25", "R2-1", "", Some(25.0), "mph"),
+        sign("speed limit 35", "R2-1", "", Some(35.0), "mph"),
+        sign("speed limit 55", "R2-1", "", Some(55.0), "mph"),
+        sign("pedestrian crossing", "W11-2", "", None, ""),
+    ];
+    vec![
+        Catalog {
+            country: "DE".to_string(),
+            signs: de,
+        },
+        Catalog {
+            country: "US".to_string(),
+            signs: us,
+        },
+    ]
+}
+
+fn parse_sign(sign: &Json) -> Result<SignType, String> {
+    let text = |key: &str| sign.get(key).and_then(Json::as_str).map(str::to_string);
+    let name = text("name").ok_or("no `name`")?;
+    let size = |key: &str| match sign.get(key) {
+        None => Ok(0.6),
+        Some(size) => size
+            .as_f64()
+            .filter(|size| *size > 0.0)
+            .ok_or_else(|| format!("{name}: `{key}` must be a positive number")),
+    };
+    Ok(SignType {
+        type_code: text("type").ok_or_else(|| format!("{name}: no `type`"))?,
+        subtype: text("subtype").unwrap_or_else(|| "-1".to_string()),
+        value: sign.get("value").and_then(Json::as_f64),
+        unit: text("unit").unwrap_or_default(),
+        height: size("height")?,
+        width: size("width")?,
+        name,
+    })
+}
+
+// Reads catalogs in the format described at the top.
+pub fn parse(text: &str) -> Result<Vec<Catalog>, String> {
+    let json = json::parse(text)?;
+    json.get("catalogs")
+        .and_then(Json::as_array)
+        .ok_or("expected an object with a `catalogs` array")?
+        .iter()
+        .enumerate()
+        .map(|(i, catalog)| {
+            let country = catalog
+                .get("country")
+                .and_then(Json::as_str)
+                .ok_or_else(|| format!("catalog {}: no `country`", i + 1))?;
+            let signs = catalog
+                .get("signs")
+                .and_then(Json::as_array)
+                .ok_or_else(|| format!("{country}: no `signs` array"))?
+                .iter()
+                .map(parse_sign)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{country}: {e}"))?;
+            Ok(Catalog {
+                country: country.to_string(),
+                signs,
+            })
+        })
+        .collect()
+}
+
+// An edit asked for with the mouse or the keys, run with the world.
+#[derive(Debug, Clone, PartialEq)]
+enum SignAction {
+    Place(u32, f64, f64),
+    Move(String, u32, f64, f64),
+    Flip,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct SignTool {
+    pub active: bool,
+    pub catalogs: Vec<Catalog>,
+    pub country: String,
+    // Index of the current sign in the catalog in use.
+    pub current: usize,
+    // The ID of the sign placed or moved last.
+    pub last: Option<String>,
+    // The sign being dragged.
+    dragging: Option<String>,
+    pending: Vec<SignAction>,
+}
+
+impl Default for SignTool {
+    fn default() -> Self {
+        Self {
+            active: false,
+            catalogs: built_in(),
+            country: DEFAULT_COUNTRY.to_string(),
+            current: 0,
+            last: None,
+            dragging: None,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl SignTool {
+    pub fn catalog(&self) -> Option<&Catalog> {
+        self.catalogs.iter().find(|c| c.country == self.country)
+    }
+
+    pub fn sign(&self) -> Option<&SignType> {
+        self.catalog()?.signs.get(self.current)
+    }
+
+    // Whether a sign is being dragged, which keeps the camera still.
+    pub fn dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
+    fn read(&mut self, path: &Path) -> Result<usize, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let catalogs = parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let count = catalogs.len();
+        for catalog in catalogs {
+            match self
+                .catalogs
+                .iter_mut()
+                .find(|c| c.country == catalog.country)
+            {
+                Some(existing) => *existing = catalog,
+                None => self.catalogs.push(catalog),
+            }
+        }
+        self.current = 0;
+        Ok(count)
+    }
+
+    fn describe(&self) -> String {
+        let Some(catalog) = self.catalog() else {
+            return format!("no catalog for {}", self.country);
+        };
+        let mut lines = vec![format!(
+            "{} signs (catalogs: {})",
+            catalog.country,
+            self.catalogs
+                .iter()
+                .map(|c| c.country.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )];
+        lines.extend(catalog.signs.iter().enumerate().map(|(i, sign)| {
+            let marker = if i == self.current { '*' } else { ' ' };
+            format!(
+                "{marker} {}: {} {}",
+                sign.name, sign.type_code, sign.subtype
+            )
+        }));
+        lines.join("\n")
+    }
+}
+
+// The orientation of a sign at (s, t) on a road facing the traffic of the
+// lane there: "+" if that traffic follows the reference line.
+pub fn facing(network: &RoadNetwork, road_id: u32, s: f64, t: f64) -> &'static str {
+    let lane = network
+        .segments
+        .iter()
+        .filter(|lane| lane.road_id == road_id && lane.start_s <= s && s <= lane.end_s)
+        .find(|lane| (lane.lane_id > 0) == (t > 0.0));
+    let forward = match lane {
+        Some(lane) => lane.follows_reference(),
+        None => t <= 0.0,
+    };
+    if forward {
+        "+"
+    } else {
+        "-"
+    }
+}
+
+// The viewer-frame point at (s, t) on a road.
+pub fn foot(network: &RoadNetwork, road_id: u32, s: f64, t: f64) -> Result<DVec3, String> {
+    let cut = cut(network, road_id, s).ok_or_else(|| format!("road {road_id} has no s {s:.2}"))?;
+    Ok(cut.reference + DVec3::Y.cross(cut.direction) * t)
+}
+
+// The next free numeric signal ID.
+pub fn next_id(network: &RoadNetwork) -> String {
+    let last = network
+        .signals
+        .iter()
+        .filter_map(|signal| signal.id.parse::<u64>().ok())
+        .max();
+    last.map_or(1, |id| id + 1).to_string()
+}
+
+fn find<'a>(network: &'a mut RoadNetwork, id: &str) -> Result<&'a mut Signal, String> {
+    network
+        .signals
+        .iter_mut()
+        .find(|signal| signal.id == id && signal.kind != SignalKind::Object)
+        .ok_or_else(|| format!("no sign {id}"))
+}
+
+// Adds a sign of a type at (s, t) on a road and returns its ID.
+pub fn place(
+    network: &mut RoadNetwork,
+    road_id: u32,
+    s: f64,
+    t: f64,
+    sign: &SignType,
+    country: &str,
+) -> Result<String, String> {
+    let position = foot(network, road_id, s, t)?;
+    let id = next_id(network);
+    network.signals.push(Signal {
+        id: id.clone(),
+        name: sign.name.clone(),
+        road_id,
+        s,
+        t,
+        z_offset: SIGN_Z_OFFSET,
+        height: sign.height,
+        width: sign.width,
+        length: 0.0,
+        radius: 0.0,
+        orientation: facing(network, road_id, s, t).to_string(),
+        kind: SignalKind::classify(&sign.type_code, &sign.name, false),
+        type_code: sign.type_code.clone(),
+        subtype: sign.subtype.clone(),
+        country: country.to_string(),
+        dynamic: false,
+        value: sign.value,
+        unit: sign.unit.clone(),
+        position,
+        repeats: Vec::new(),
+        xml: ElementXml::None,
+    });
+    Ok(id)
+}
+
+// Moves a sign to (s, t) on a road and returns the road it was on.
+pub fn move_sign(
+    network: &mut RoadNetwork,
+    id: &str,
+    road_id: u32,
+    s: f64,
+    t: f64,
+) -> Result<u32, String> {
+    let position = foot(network, road_id, s, t)?;
+    let signal = find(network, id)?;
+    let from = signal.road_id;
+    signal.road_id = road_id;
+    signal.s = s;
+    signal.t = t;
+    signal.position = position;
+    signal.xml.clear();
+    Ok(from)
+}
+
+// Turns a sign to face the other way and returns its new orientation.
+pub fn flip(network: &mut RoadNetwork, id: &str) -> Result<String, String> {
+    let signal = find(network, id)?;
+    let orientation = if signal.orientation.trim() == "+" {
+        "-"
+    } else {
+        "+"
+    };
+    signal.orientation = orientation.to_string();
+    signal.xml.clear();
+    Ok(orientation.to_string())
+}
+
+fn road_of(world: &World, id: &str) -> Result<u32, String> {
+    world
+        .resource::<RoadNetwork>()
+        .signals
+        .iter()
+        .find(|signal| signal.id == id && signal.kind != SignalKind::Object)
+        .map(|signal| signal.road_id)
+        .ok_or_else(|| format!("no sign {id}"))
+}
+
+fn begin(world: &World, description: String, roads: &[u32]) -> Transaction {
+    Transaction::begin(world.resource::<RoadNetwork>(), description, roads)
+}
+
+// Places the current sign, as an edit.
+pub fn place_current(world: &mut World, road_id: u32, s: f64, t: f64) -> Result<String, String> {
+    let tool = world.resource::<SignTool>();
+    let sign = tool.sign().cloned().ok_or("no sign chosen")?;
+    let country = tool.country.clone();
+    let description = format!("placing a sign on road {road_id}");
+    let transaction = begin(world, description, &[road_id]);
+    let mut placed = None;
+    let message = transaction::run(world, transaction, |network| {
+        let id = place(network, road_id, s, t, &sign, &country)?;
+        let message = format!(
+            "placed {} sign {id} on road {road_id} at s {s:.2}, t {t:.2}",
+            sign.name
+        );
+        placed = Some(id);
+        Ok((message, Vec::new()))
+    })?;
+    world.resource_mut::<SignTool>().last = placed;
+    Ok(message)
+}
+
+// Moves a sign, as an edit.
+pub fn relocate(
+    world: &mut World,
+    id: &str,
+    road_id: u32,
+    s: f64,
+    t: f64,
+) -> Result<String, String> {
+    let from = road_of(world, id)?;
+    let transaction = begin(world, format!("moving sign {id}"), &[from, road_id]);
+    let message = transaction::run(world, transaction, |network| {
+        move_sign(network, id, road_id, s, t)?;
+        Ok((
+            format!("moved sign {id} to road {road_id} at s {s:.2}, t {t:.2}"),
+            Vec::new(),
+        ))
+    })?;
+    world.resource_mut::<SignTool>().last = Some(id.to_string());
+    Ok(message)
+}
+
+// Turns a sign around, as an edit.
+pub fn turn(world: &mut World, id: &str) -> Result<String, String> {
+    let road_id = road_of(world, id)?;
+    let transaction = begin(world, format!("turning sign {id}"), &[road_id]);
+    transaction::run(world, transaction, |network| {
+        let orientation = flip(network, id)?;
+        Ok((format!("sign {id} is now {orientation}"), Vec::new()))
+    })
+}
+
+// Removes a sign, as an edit.
+pub fn remove(world: &mut World, id: &str) -> Result<String, String> {
+    let road_id = road_of(world, id)?;
+    let transaction = begin(world, format!("removing sign {id}"), &[road_id]);
+    transaction::run(world, transaction, |network| {
+        network.signals.retain(|signal| signal.id != id);
+        Ok((format!("removed sign {id}"), Vec::new()))
+    })
+}
+
+fn number(text: &str, what: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|_| format!("`{text}` is not a valid {what}"))
+}
+
+fn road(text: &str) -> Result<u32, String> {
+    text.parse()
+        .map_err(|_| format!("`{text}` is not a valid road ID"))
+}
+
+// `signs` in the console.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<SignTool>().describe()),
+        ["country", code] => {
+            let code = code.to_ascii_uppercase();
+            let mut tool = world.resource_mut::<SignTool>();
+            if !tool.catalogs.iter().any(|c| c.country == code) {
+                return Err(format!("no catalog for {code}"));
+            }
+            tool.country = code;
+            tool.current = 0;
+            Ok(tool.describe())
+        }
+        ["use", name @ ..] => {
+            let name = name.join(" ");
+            let mut tool = world.resource_mut::<SignTool>();
+            tool.current = tool
+                .catalog()
+                .and_then(|catalog| catalog.signs.iter().position(|sign| sign.name == name))
+                .ok_or_else(|| format!("no sign `{name}`"))?;
+            Ok(String::new())
+        }
+        ["load", path] => {
+            let count = world.resource_mut::<SignTool>().read(Path::new(path))?;
+            Ok(format!("read {count} catalogs"))
+        }
+        ["place", road_id, s, t] => {
+            let (s, t) = (number(s, "station")?, number(t, "offset")?);
+            place_current(world, road(road_id)?, s, t)
+        }
+        ["move", id, road_id, s, t] => {
+            let (s, t) = (number(s, "station")?, number(t, "offset")?);
+            relocate(world, id, road(road_id)?, s, t)
+        }
+        ["flip"] => {
+            let id = world
+                .resource::<SignTool>()
+                .last
+                .clone()
+                .ok_or("no current sign")?;
+            turn(world, &id)
+        }
+        ["flip", id] => turn(world, id),
+        ["remove", id] => remove(world, id),
+        _ => Err(
+            "expected country <code>, use <name>, load <file>, place <road> <s> <t>, \
+             move <id> <road> <s> <t>, flip [id] or remove <id>"
+                .to_string(),
+        ),
+    }
+}
+
+pub struct SignEditPlugin;
+
+impl Plugin for SignEditPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SignTool>().add_systems(
+            Update,
+            (
+                (use_keys, edit_with_mouse, apply_actions).chain(),
+                draw_signs.after(camera_orbit),
+            ),
+        );
+    }
+}
+
+fn use_keys(keys: Res<ButtonInput<KeyCode>>, mut tool: ResMut<SignTool>) {
+    if keys.just_pressed(KeyCode::KeyS) {
+        tool.active = !tool.active;
+        tool.dragging = None;
+        info!("sign tool {}", if tool.active { "on" } else { "off" });
+    }
+    if !tool.active {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyQ) {
+        let count = tool.catalog().map_or(0, |catalog| catalog.signs.len());
+        if count > 0 {
+            tool.current = (tool.current + 1) % count;
+            if let Some(sign) = tool.sign() {
+                info!("placing {} signs", sign.name);
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyE) {
+        tool.pending.push(SignAction::Flip);
+    }
+}
+
+// The viewer-frame ray under the cursor, if it is over the 3D view.
+fn cursor_ray(
+    window: &Window,
+    camera: &Camera,
+    transform: &GlobalTransform,
+    origin: &RenderOrigin,
+) -> Option<(Vec2, DVec3, DVec3)> {
+    let cursor = window.cursor_position()?;
+    let viewport = camera.logical_viewport_rect()?;
+    if !viewport.contains(cursor) {
+        return None;
+    }
+    let ray = camera.viewport_to_world(transform, cursor - viewport.min)?;
+    Some((
+        cursor,
+        origin.0 + ray.origin.as_dvec3(),
+        ray.direction.as_dvec3(),
+    ))
+}
+
+// The sign whose icon a ray passes closest to, within `GRAB_RADIUS`.
+fn grabbed(network: &RoadNetwork, origin: DVec3, direction: DVec3) -> Option<String> {
+    network
+        .signals
+        .iter()
+        .filter(|signal| signal.kind != SignalKind::Object)
+        .filter_map(|signal| {
+            // Where `signals` draws the icon.
+            let lift = (signal.z_offset + signal.height / 2.0).max(2.0);
+            let center = signal.position + DVec3::Y * lift;
+            let along = (center - origin).dot(direction).max(0.0);
+            let miss = center.distance(origin + direction * along);
+            (miss <= GRAB_RADIUS).then_some((miss, signal.id.clone()))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, id)| id)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn edit_with_mouse(
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    interactions: Query<&Interaction>,
+    origin: Res<RenderOrigin>,
+    network: Res<RoadNetwork>,
+    mut tool: ResMut<SignTool>,
+    mut pressed_at: Local<Option<Vec2>>,
+) {
+    if !tool.active {
+        return;
+    }
+    let (Ok(window), Ok((camera, transform))) = (windows.get_single(), cameras.get_single()) else {
+        return;
+    };
+    let Some((cursor, from, direction)) = cursor_ray(window, camera, transform, &origin) else {
+        return;
+    };
+    if buttons.just_pressed(MouseButton::Left) {
+        let over_ui = interactions
+            .iter()
+            .any(|interaction| *interaction != Interaction::None);
+        *pressed_at = (!over_ui).then_some(cursor);
+        if !over_ui {
+            tool.dragging = grabbed(&network, from, direction);
+        }
+    }
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(pressed) = pressed_at.take() else {
+        return;
+    };
+    let hit = pick(&network, from, direction);
+    if let Some(id) = tool.dragging.take() {
+        if let Some(hit) = hit.filter(|_| pressed.distance(cursor) > CLICK_TOLERANCE) {
+            let t = hit.t.unwrap_or_default();
+            tool.pending
+                .push(SignAction::Move(id, hit.road_id, hit.s, t));
+        } else {
+            // A click on a sign makes it the current one.
+            tool.last = Some(id);
+        }
+        return;
+    }
+    if pressed.distance(cursor) > CLICK_TOLERANCE {
+        return;
+    }
+    if let Some(hit) = hit {
+        let t = hit.t.unwrap_or_default();
+        tool.pending.push(SignAction::Place(hit.road_id, hit.s, t));
+    }
+}
+
+// Runs the edits asked for with the mouse and the keys.
+fn apply_actions(world: &mut World) {
+    let actions = std::mem::take(&mut world.resource_mut::<SignTool>().pending);
+    for action in actions {
+        let result = match action {
+            SignAction::Place(road_id, s, t) => place_current(world, road_id, s, t),
+            SignAction::Move(id, road_id, s, t) => relocate(world, &id, road_id, s, t),
+            SignAction::Flip => match world.resource::<SignTool>().last.clone() {
+                Some(id) => turn(world, &id),
+                None => Err("no current sign".to_string()),
+            },
+        };
+        match result {
+            Ok(message) => info!("{message}"),
+            Err(message) => warn!("{message}"),
+        }
+    }
+}
+
+// Shows which traffic each sign is for, the current sign, and where a
+// dragged sign would go.
+#[allow(clippy::too_many_arguments)]
+fn draw_signs(
+    mut gizmos: Gizmos,
+    tool: Res<SignTool>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if !tool.active {
+        return;
+    }
+    for signal in network
+        .signals
+        .iter()
+        .filter(|signal| signal.kind != SignalKind::Object)
+    {
+        let at = origin.to_render(signal.position) + Vec3::Y * 0.1;
+        let current = tool.last.as_ref() == Some(&signal.id);
+        let color = if current {
+            theme.highlight
+        } else {
+            Color::WHITE
+        };
+        if current {
+            gizmos.circle(at, Direction3d::Y, GRAB_RADIUS as f32, color);
+        }
+        let sense = match signal.orientation.trim() {
+            "+" => 1.0,
+            "-" => -1.0,
+            _ => continue,
+        };
+        let Some(cut) = cut(&network, signal.road_id, signal.s) else {
+            continue;
+        };
+        let arrow = (cut.direction * ARROW_LENGTH * sense).as_vec3();
+        gizmos.arrow(at - arrow / 2.0, at + arrow / 2.0, color);
+    }
+
+    let Some(id) = &tool.dragging else {
+        return;
+    };
+    let (Ok(window), Ok((camera, transform))) = (windows.get_single(), cameras.get_single()) else {
+        return;
+    };
+    let Some((_, from, direction)) = cursor_ray(window, camera, transform, &origin) else {
+        return;
+    };
+    let (Some(hit), Some(signal)) = (
+        pick(&network, from, direction),
+        network.signals.iter().find(|signal| &signal.id == id),
+    ) else {
+        return;
+    };
+    let to = origin.to_render(hit.position) + Vec3::Y * 0.1;
+    gizmos.line(
+        origin.to_render(signal.position) + Vec3::Y * 0.1,
+        to,
+        theme.highlight,
+    );
+    gizmos.circle(to, Direction3d::Y, GRAB_RADIUS as f32, theme.highlight);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+    use crate::signals::SignalKind;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    #[test]
+    fn catalogs() {
+        let catalogs = parse(
+            r#"{ "catalogs": [ { "country": "FR", "signs": [
+                 { "name": "stop", "type": "AB4", "height": 0.8 } ] } ] }"#,
+        )
+        .unwrap();
+        assert_eq!(catalogs[0].signs[0].height, 0.8);
+        assert!(parse(r#"{ "catalogs": [ { "country": "FR" } ] }"#).is_err());
+    }
+
+    #[test]
+    fn placed_signs_are_written_with_their_size() {
+        let transform = LoadTransform::default();
+        let mut network = load("straight.xodr");
+        let stop = SignType {
+            height: 0.8,
+            width: 0.6,
+            ..sign("stop", "206", "", None, "")
+        };
+        let id = place(&mut network, 1, 20.0, -5.0, &stop, "DE").unwrap();
+        let read = xodr::read_str(&xodr::to_xml(&network), &transform).unwrap();
+        let signal = read.signals.iter().find(|signal| signal.id == id).unwrap();
+        assert_eq!((signal.width, signal.height), (0.6, 0.8));
+    }
+
+    #[test]
+    fn sign_edits() {
+        let mut network = load("straight.xodr");
+        let catalogs = built_in();
+        let stop = &catalogs[0].signs[0];
+        let id = place(&mut network, 1, 10.0, -2.0, stop, "DE").unwrap();
+        let signal = network.signals.iter().find(|s| s.id == id).unwrap();
+        assert_eq!(signal.kind, SignalKind::Stop);
+        // On the right of a right-hand road, it is for traffic along the road.
+        assert_eq!(signal.orientation, "+");
+        assert!(signal.position.distance(DVec3::new(10.0, 0.0, 2.0)) < 1e-6);
+        assert!(place(&mut network, 1, 150.0, 0.0, stop, "DE").is_err());
+
+        assert_eq!(move_sign(&mut network, &id, 1, 40.0, 2.0), Ok(1));
+        assert_eq!(flip(&mut network, &id).unwrap(), "-");
+        let read = xodr::read_str(&xodr::to_xml(&network), &LoadTransform::default()).unwrap();
+        let signal = read.signals.iter().find(|s| s.id == id).unwrap();
+        assert_eq!((signal.s, signal.t), (40.0, 2.0));
+        assert_eq!(signal.orientation, "-");
+        assert_eq!(
+            (signal.country.as_str(), signal.type_code.as_str()),
+            ("DE", "206")
+        );
+    }
+}
@@ -0,0 +1,756 @@
+// Signs drawn as signs.
+//
+// A sign catalog maps the type and subtype codes of a signal convention to
+// what the sign looks like: the shape and size of its plate and the picture
+// on it, or a 3D model that stands in for plate and post. Two conventions
+// are built in, with pictures painted in code for common signs: "vienna",
+// the Vienna Convention codes of the German catalogue that most OpenDRIVE
+// files use, and "mutcd", the US codes. Catalog files add conventions or
+// replace the built-in ones of the same name; they are given to `view
+// --sign-catalog` or loaded with `sign-catalog load <file>`:
+//
+//   { "conventions": [
+//     { "name": "mutcd", "countries": ["US", "USA"],
+//       "signs": [
+//         { "type": "R1-1", "shape": "octagon", "width": 0.75,
+//           "texture": "mutcd/R1-1.png" },
+//         { "type": "R2-1", "shape": "rectangle", "width": 0.6,
+//           "height": 0.75, "face": "us-regulatory" },
+//         { "type": "W11-2", "subtype": "", "mesh": "mutcd/W11-2.glb" } ] }
+//   ] }
+//
+// Paths are relative to the file. Shapes are octagon, triangle, inverted
+// triangle, circle, rectangle and diamond; a texture covers the plate's
+// bounding box, and a model (the first scene of a glTF file) stands on its
+// origin facing +z. Entries without a subtype match every subtype. A signal
+// is looked up in the convention of its country, and in all of them if its
+// country has none.
+//
+// Signals a catalog knows stand in the scene as they would on the road: a
+// post up to the plate, and the plate turned to face the traffic the sign is
+// for (its orientation), the picture on the front and bare metal on the
+// back. The others keep their icons, as do all signals in occlusion-free
+// mode (see `signals`).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::canvas::Canvas;
+use crate::cross_section::cut;
+use crate::edit::NetworkChanged;
+use crate::entity_index::{OdrEntityIndex, OdrId};
+use crate::json::{self, Json};
+use crate::odr::insert_signal;
+use crate::origin::WorldPosition;
+use crate::signals::{Signal, SignalKind};
+use crate::style::StyleSheet;
+use crate::RoadNetwork;
+
+// Edge length of a painted picture, in pixels.
+const FACE_SIZE: u32 = 64;
+
+// Gap between the front and the back of a plate, in meters.
+const PLATE_THICKNESS: f32 = 0.02;
+
+const POST_RADIUS: f32 = 0.04;
+
+// Size of a plate whose entry gives none, in meters.
+const DEFAULT_SIZE: f64 = 0.6;
+
+// The number of corners of a circular plate.
+const CIRCLE_CORNERS: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Shape {
+    Octagon,
+    // Point up, as warning signs.
+    Triangle,
+    // Point down, as yield signs.
+    InvertedTriangle,
+    Circle,
+    Rectangle,
+    Diamond,
+}
+
+impl Shape {
+    fn named(name: &str) -> Option<Self> {
+        match name {
+            "octagon" => Some(Shape::Octagon),
+            "triangle" => Some(Shape::Triangle),
+            "inverted triangle" => Some(Shape::InvertedTriangle),
+            "circle" => Some(Shape::Circle),
+            "rectangle" => Some(Shape::Rectangle),
+            "diamond" => Some(Shape::Diamond),
+            _ => None,
+        }
+    }
+
+    // The corners of a plate of unit size, x right and y up from its
+    // middle, counterclockwise seen from the front.
+    pub fn outline(self) -> Vec<Vec2> {
+        let regular = |corners: usize, first: f32, radius: f32| -> Vec<Vec2> {
+            (0..corners)
+                .map(|i| {
+                    let angle = first + std::f32::consts::TAU * i as f32 / corners as f32;
+                    Vec2::new(angle.cos(), angle.sin()) * radius
+                })
+                .collect()
+        };
+        match self {
+            // Flat top and bottom, one unit apart.
+            Shape::Octagon => regular(8, std::f32::consts::PI / 8.0, 0.5 / 0.9238795),
+            Shape::Triangle => vec![
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.0, 0.5),
+            ],
+            Shape::InvertedTriangle => vec![
+                Vec2::new(-0.5, 0.5),
+                Vec2::new(0.0, -0.5),
+                Vec2::new(0.5, 0.5),
+            ],
+            Shape::Circle => regular(CIRCLE_CORNERS, 0.0, 0.5),
+            Shape::Rectangle => vec![
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+            ],
+            Shape::Diamond => vec![
+                Vec2::new(0.0, -0.5),
+                Vec2::new(0.5, 0.0),
+                Vec2::new(0.0, 0.5),
+                Vec2::new(-0.5, 0.0),
+            ],
+        }
+    }
+
+    // Whether a point of the unit plate lies at least `inset` inside it.
+    fn contains(self, p: Vec2, inset: f32) -> bool {
+        let outline = self.outline();
+        (0..outline.len()).all(|i| {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            let inwards = (b - a).perp().normalize_or_zero();
+            (p - a).dot(inwards) >= inset
+        })
+    }
+}
+
+// A picture painted in code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    Stop,
+    Yield,
+    // A red ring, with the signal's value inside if it has one.
+    Prohibition,
+    NoEntry,
+    Priority,
+    // A blue plate with a white triangle, as a pedestrian crossing.
+    Crossing,
+    // A red-edged triangle.
+    Warning,
+    // A black-edged white plate with the signal's value.
+    UsRegulatory,
+    // A black-edged yellow plate.
+    UsWarning,
+}
+
+impl Face {
+    fn named(name: &str) -> Option<Self> {
+        match name {
+            "stop" => Some(Face::Stop),
+            "yield" => Some(Face::Yield),
+            "prohibition" => Some(Face::Prohibition),
+            "no entry" => Some(Face::NoEntry),
+            "priority" => Some(Face::Priority),
+            "crossing" => Some(Face::Crossing),
+            "warning" => Some(Face::Warning),
+            "us-regulatory" => Some(Face::UsRegulatory),
+            "us-warning" => Some(Face::UsWarning),
+            _ => None,
+        }
+    }
+}
+
+// What a sign is drawn with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Look {
+    Painted(Face),
+    Texture(PathBuf),
+    Mesh(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignModel {
+    pub type_code: String,
+    // Matches every subtype if not given.
+    pub subtype: Option<String>,
+    pub shape: Shape,
+    // Size of the plate, in meters.
+    pub width: f64,
+    pub height: f64,
+    pub look: Look,
+}
+
+impl SignModel {
+    fn matches(&self, signal: &Signal) -> bool {
+        self.type_code == signal.type_code.trim()
+            && self
+                .subtype
+                .as_ref()
+                .is_none_or(|subtype| subtype == signal.subtype.trim())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Convention {
+    pub name: String,
+    // Country codes of the signals the convention is for, upper case.
+    pub countries: Vec<String>,
+    pub signs: Vec<SignModel>,
+}
+
+fn model(type_code: &str, shape: Shape, width: f64, height: f64, face: Face) -> SignModel {
+    SignModel {
+        type_code: type_code.to_string(),
+        subtype: None,
+        shape,
+        width,
+        height,
+        look: Look::Painted(face),
+    }
+}
+
+pub fn built_in() -> Vec<Convention> {
+    let countries = |codes: &[&str]| codes.iter().map(|code| code.to_string()).collect();
+    vec![
+        Convention {
+            name: "vienna".to_string(),
+            countries: countries(&[
+                "DE", "DEU", "AT", "AUT", "CH", "CHE", "NL", "NLD", "BE", "BEL", "FR", "FRA", "IT",
+                "ITA", "ES", "ESP", "SE", "SWE", "PL", "POL", "CZ", "CZE",
+            ]),
+            signs: vec![
+                model("206", Shape::Octagon, 0.9, 0.9, Face::Stop),
+                model("205", Shape::InvertedTriangle, 0.9, 0.78, Face::Yield),
+                model("274", Shape::Circle, 0.6, 0.6, Face::Prohibition),
+                model("276", Shape::Circle, 0.6, 0.6, Face::Prohibition),
+                model("267", Shape::Circle, 0.6, 0.6, Face::NoEntry),
+                model("306", Shape::Diamond, 0.6, 0.6, Face::Priority),
+                model("350", Shape::Rectangle, 0.6, 0.6, Face::Crossing),
+                model("101", Shape::Triangle, 0.9, 0.78, Face::Warning),
+            ],
+        },
+        Convention {
+            name: "mutcd".to_string(),
+            countries: countries(&["US", "USA"]),
+            signs: vec![
+                model("R1-1", Shape::Octagon, 0.75, 0.75, Face::Stop),
+                model("R1-2", Shape::InvertedTriangle, 0.9, 0.78, Face::Yield),
+                model("R2-1", Shape::Rectangle, 0.6, 0.75, Face::UsRegulatory),
+                model("R5-1", Shape::Rectangle, 0.75, 0.75, Face::NoEntry),
+                model("W1-1", Shape::Diamond, 0.75, 0.75, Face::UsWarning),
+                model("W11-2", Shape::Diamond, 0.75, 0.75, Face::UsWarning),
+            ],
+        },
+    ]
+}
+
+fn parse_model(sign: &Json, base: &Path) -> Result<SignModel, String> {
+    let text = |key: &str| sign.get(key).and_then(Json::as_str);
+    let type_code = text("type").ok_or("no `type`")?;
+    let invalid = |e: &str| format!("{type_code}: {e}");
+    let size = |key: &str| match sign.get(key) {
+        None => Ok(None),
+        Some(size) => size
+            .as_f64()
+            .filter(|size| *size > 0.0)
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("`{key}` must be a positive number"))),
+    };
+    let shape = match text("shape") {
+        None => Shape::Rectangle,
+        Some(name) => Shape::named(name).ok_or_else(|| invalid(&format!("no shape `{name}`")))?,
+    };
+    let look = match (text("face"), text("texture"), text("mesh")) {
+        (Some(name), None, None) => {
+            Look::Painted(Face::named(name).ok_or_else(|| invalid(&format!("no face `{name}`")))?)
+        }
+        (None, Some(path), None) => Look::Texture(base.join(path)),
+        (None, None, Some(path)) => Look::Mesh(base.join(path)),
+        _ => return Err(invalid("expected one of `face`, `texture` or `mesh`")),
+    };
+    let width = size("width")?.unwrap_or(DEFAULT_SIZE);
+    Ok(SignModel {
+        type_code: type_code.to_string(),
+        subtype: text("subtype").map(str::to_string),
+        shape,
+        width,
+        // Plates are as high as they are wide unless told otherwise.
+        height: size("height")?.unwrap_or(width),
+        look,
+    })
+}
+
+// Reads conventions in the format described at the top, with paths taken
+// relative to `base`.
+pub fn parse(text: &str, base: &Path) -> Result<Vec<Convention>, String> {
+    let json = json::parse(text)?;
+    json.get("conventions")
+        .and_then(Json::as_array)
+        .ok_or("expected an object with a `conventions` array")?
+        .iter()
+        .enumerate()
+        .map(|(i, convention)| {
+            let name = convention
+                .get("name")
+                .and_then(Json::as_str)
+                .ok_or_else(|| format!("convention {}: no `name`", i + 1))?;
+            let countries = match convention.get("countries") {
+                None => Vec::new(),
+                Some(countries) => countries
+                    .as_array()
+                    .ok_or_else(|| format!("{name}: `countries` must be an array"))?
+                    .iter()
+                    .map(|code| {
+                        code.as_str()
+                            .map(str::to_ascii_uppercase)
+                            .ok_or_else(|| format!("{name}: country codes must be strings"))
+                    })
+                    .collect::<Result<_, _>>()?,
+            };
+            let signs = convention
+                .get("signs")
+                .and_then(Json::as_array)
+                .ok_or_else(|| format!("{name}: no `signs` array"))?
+                .iter()
+                .map(|sign| parse_model(sign, base))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("{name}: {e}"))?;
+            Ok(Convention {
+                name: name.to_string(),
+                countries,
+                signs,
+            })
+        })
+        .collect()
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SignCatalogs(pub Vec<Convention>);
+
+impl Default for SignCatalogs {
+    fn default() -> Self {
+        Self(built_in())
+    }
+}
+
+impl SignCatalogs {
+    // The built-in conventions, with those in a file added or replacing
+    // them.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut catalogs = Self::default();
+        catalogs.read(path)?;
+        Ok(catalogs)
+    }
+
+    fn read(&mut self, path: &Path) -> Result<usize, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        // The asset server reads paths relative to its own folder.
+        let base = std::env::current_dir()
+            .map(|dir| dir.join(base))
+            .unwrap_or_else(|_| base.to_path_buf());
+        let conventions = parse(&text, &base).map_err(|e| format!("{}: {e}", path.display()))?;
+        let count = conventions.len();
+        for convention in conventions {
+            match self.0.iter_mut().find(|c| c.name == convention.name) {
+                Some(existing) => *existing = convention,
+                None => self.0.push(convention),
+            }
+        }
+        Ok(count)
+    }
+
+    // How a signal is drawn, if a catalog knows it.
+    pub fn model(&self, signal: &Signal) -> Option<&SignModel> {
+        if matches!(signal.kind, SignalKind::Object | SignalKind::TrafficLight) {
+            return None;
+        }
+        let country = signal.country.trim().to_ascii_uppercase();
+        let conventions = match self.0.iter().position(|c| c.countries.contains(&country)) {
+            Some(own) => &self.0[own..=own],
+            None => &self.0[..],
+        };
+        conventions
+            .iter()
+            .flat_map(|convention| &convention.signs)
+            .find(|model| model.matches(signal))
+    }
+
+    fn describe(&self) -> String {
+        self.0
+            .iter()
+            .map(|convention| {
+                format!(
+                    "{}: {} signs, for {}",
+                    convention.name,
+                    convention.signs.len(),
+                    if convention.countries.is_empty() {
+                        "no country".to_string()
+                    } else {
+                        convention.countries.join(", ")
+                    }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// `sign-catalog` in the console: lists the conventions, or loads a file.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut catalogs = world.resource_mut::<SignCatalogs>();
+    match args {
+        [] => Ok(catalogs.describe()),
+        ["load", path] => {
+            let count = catalogs.read(Path::new(path))?;
+            Ok(format!("read {count} conventions"))
+        }
+        _ => Err("expected load <file>".to_string()),
+    }
+}
+
+// Paints a picture for a plate of the given shape.
+fn paint_face(face: Face, shape: Shape, value: Option<u32>) -> Image {
+    const RED: [u8; 4] = [200, 30, 30, 255];
+    const WHITE: [u8; 4] = [250, 250, 250, 255];
+    const BLACK: [u8; 4] = [20, 20, 20, 255];
+    const YELLOW: [u8; 4] = [245, 195, 20, 255];
+    const BLUE: [u8; 4] = [30, 90, 200, 255];
+    let size = FACE_SIZE as f32;
+    let mut canvas = Canvas::new(FACE_SIZE as i32, FACE_SIZE as i32);
+    // Pixels to the unit plate, y up.
+    let unit = |p: Vec2| Vec2::new(p.x / size - 0.5, 0.5 - p.y / size);
+    let mut plate = |inset: f32, color: [u8; 4]| {
+        canvas.fill(|p| shape.contains(unit(p), inset), color);
+    };
+    match face {
+        Face::Stop => {
+            plate(0.0, WHITE);
+            plate(0.04, RED);
+        }
+        Face::Yield | Face::Warning | Face::Prohibition => {
+            plate(0.0, RED);
+            plate(0.1, WHITE);
+        }
+        Face::NoEntry => {
+            plate(0.0, WHITE);
+            plate(0.02, RED);
+            canvas.fill(
+                |p| {
+                    let q = unit(p);
+                    q.x.abs() <= 0.3 && q.y.abs() <= 0.07
+                },
+                WHITE,
+            );
+        }
+        Face::Priority => {
+            plate(0.0, WHITE);
+            plate(0.1, YELLOW);
+        }
+        Face::Crossing => {
+            plate(0.0, BLUE);
+            canvas.fill(|p| Shape::Triangle.contains(unit(p) * 1.4, 0.0), WHITE);
+        }
+        Face::UsRegulatory => {
+            plate(0.0, BLACK);
+            plate(0.03, WHITE);
+        }
+        Face::UsWarning => {
+            plate(0.0, BLACK);
+            plate(0.03, YELLOW);
+        }
+    }
+    if matches!(face, Face::Prohibition | Face::UsRegulatory) {
+        if let Some(value) = value {
+            canvas.number(value, Vec2::splat(size / 2.0), BLACK);
+        }
+    }
+    canvas.into_image()
+}
+
+// The front or back of a plate, in the xy plane with the front facing +z.
+fn plate_mesh(shape: Shape, width: f32, height: f32, front: bool) -> Mesh {
+    let outline = shape.outline();
+    let (z, normal) = if front {
+        (0.0, Vec3::Z)
+    } else {
+        (-PLATE_THICKNESS, -Vec3::Z)
+    };
+    let mut positions = vec![Vec3::new(0.0, 0.0, z)];
+    let mut uvs = vec![Vec2::splat(0.5)];
+    for corner in &outline {
+        positions.push(Vec3::new(corner.x * width, corner.y * height, z));
+        uvs.push(Vec2::new(corner.x + 0.5, 0.5 - corner.y));
+    }
+    let count = outline.len() as u32;
+    let mut indices = Vec::new();
+    for i in 0..count {
+        let (a, b) = (i + 1, (i + 1) % count + 1);
+        if front {
+            indices.extend([0, a, b]);
+        } else {
+            indices.extend([0, b, a]);
+        }
+    }
+    let normals = vec![normal; positions.len()];
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+// The turn of a sign standing on a road so its front faces the traffic it
+// is for.
+fn facing(network: &RoadNetwork, signal: &Signal) -> Quat {
+    let Some(cut) = cut(network, signal.road_id, signal.s) else {
+        return Quat::IDENTITY;
+    };
+    let along = if signal.orientation.trim() == "-" {
+        -cut.direction
+    } else {
+        cut.direction
+    };
+    let toward_traffic = Vec3::new(-along.x as f32, 0.0, -along.z as f32).normalize_or_zero();
+    if toward_traffic == Vec3::ZERO {
+        return Quat::IDENTITY;
+    }
+    Quat::from_rotation_arc(Vec3::Z, toward_traffic)
+}
+
+// Marks the entities of a drawn sign.
+#[derive(Component)]
+struct SignPart;
+
+pub struct SignModelPlugin;
+
+impl Plugin for SignModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SignCatalogs>()
+            .add_systems(Startup, spawn_signs)
+            .add_systems(
+                Update,
+                (despawn_signs, spawn_signs).chain().run_if(
+                    on_event::<NetworkChanged>()
+                        .or_else(|catalogs: Res<SignCatalogs>| {
+                            catalogs.is_changed() && !catalogs.is_added()
+                        })
+                        .or_else(|sheet: Res<StyleSheet>| sheet.is_changed() && !sheet.is_added()),
+                ),
+            );
+    }
+}
+
+fn despawn_signs(
+    mut commands: Commands,
+    mut index: ResMut<OdrEntityIndex>,
+    parts: Query<Entity, With<SignPart>>,
+) {
+    for part in &parts {
+        index.remove(part);
+        commands.entity(part).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_signs(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    server: Res<AssetServer>,
+    network: Res<RoadNetwork>,
+    catalogs: Res<SignCatalogs>,
+    style: Res<StyleSheet>,
+    mut index: ResMut<OdrEntityIndex>,
+) {
+    let metal = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.55, 0.56, 0.58),
+        perceptual_roughness: 0.6,
+        ..default()
+    });
+    // Fronts and backs of plates by shape and size.
+    let mut plates: HashMap<(Shape, u32, u32), [Handle<Mesh>; 2]> = HashMap::new();
+    let mut painted: HashMap<(Face, Shape, Option<u32>), Handle<StandardMaterial>> = HashMap::new();
+    let mut textures: HashMap<PathBuf, Handle<StandardMaterial>> = HashMap::new();
+    let mut posts: HashMap<u32, Handle<Mesh>> = HashMap::new();
+
+    for signal in network.signals.iter().filter(|s| style.shows_signal(s)) {
+        let Some(model) = catalogs.model(signal) else {
+            continue;
+        };
+        let rotation = facing(&network, signal);
+        let face = match &model.look {
+            Look::Mesh(path) => {
+                let mut scene = commands.spawn((
+                    SceneBundle {
+                        scene: server.load(format!("{}#Scene0", path.display())),
+                        transform: Transform::from_rotation(rotation),
+                        ..default()
+                    },
+                    WorldPosition(signal.position),
+                    SignPart,
+                ));
+                insert_signal(&mut scene, signal);
+                index.insert(scene.id(), vec![OdrId::Signal(signal.id.clone())]);
+                continue;
+            }
+            Look::Painted(face) => {
+                let value = signal.value.map(|v| v.round() as u32);
+                painted
+                    .entry((*face, model.shape, value))
+                    .or_insert_with(|| {
+                        let image = images.add(paint_face(*face, model.shape, value));
+                        materials.add(StandardMaterial {
+                            base_color_texture: Some(image),
+                            alpha_mode: AlphaMode::Mask(0.5),
+                            perceptual_roughness: 0.5,
+                            ..default()
+                        })
+                    })
+                    .clone()
+            }
+            Look::Texture(path) => textures
+                .entry(path.clone())
+                .or_insert_with(|| {
+                    materials.add(StandardMaterial {
+                        base_color_texture: Some(server.load(path.clone())),
+                        alpha_mode: AlphaMode::Mask(0.5),
+                        perceptual_roughness: 0.5,
+                        ..default()
+                    })
+                })
+                .clone(),
+        };
+
+        let (width, height) = (model.width as f32, model.height as f32);
+        // Sizes are kept to the millimeter.
+        let key = (
+            model.shape,
+            (width * 1000.0).round() as u32,
+            (height * 1000.0).round() as u32,
+        );
+        let [front, back] = plates
+            .entry(key)
+            .or_insert_with(|| {
+                [true, false].map(|front| meshes.add(plate_mesh(model.shape, width, height, front)))
+            })
+            .clone();
+        let middle = signal.position + DVec3::Y * (signal.z_offset + model.height / 2.0);
+        let mut plate = commands.spawn((
+            PbrBundle {
+                mesh: back,
+                material: metal.clone(),
+                transform: Transform::from_rotation(rotation),
+                ..default()
+            },
+            WorldPosition(middle),
+            SignPart,
+        ));
+        plate.with_children(|plate| {
+            plate.spawn(PbrBundle {
+                mesh: front,
+                material: face,
+                ..default()
+            });
+        });
+        // The plate stands for the signal when picked.
+        insert_signal(&mut plate, signal);
+        index.insert(plate.id(), vec![OdrId::Signal(signal.id.clone())]);
+
+        // The post reaches from the road to the middle of the plate, just
+        // behind it.
+        if signal.z_offset <= 0.0 {
+            continue;
+        }
+        let length = signal.z_offset + model.height / 2.0;
+        let post = posts
+            .entry((length * 1000.0).round() as u32)
+            .or_insert_with(|| meshes.add(Cylinder::new(POST_RADIUS, length as f32)))
+            .clone();
+        let behind = rotation * -Vec3::Z * (PLATE_THICKNESS + POST_RADIUS);
+        commands.spawn((
+            PbrBundle {
+                mesh: post,
+                material: metal.clone(),
+                ..default()
+            },
+            WorldPosition(signal.position + behind.as_dvec3() + DVec3::Y * length / 2.0),
+            SignPart,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn conventions() {
+        // Octagons are as high as they are wide.
+        let outline = Shape::Octagon.outline();
+        let top = outline.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        let right = outline.iter().map(|p| p.x).fold(f32::MIN, f32::max);
+        assert!((top - 0.5).abs() < 1e-5 && (right - 0.5).abs() < 1e-5);
+
+        let conventions = parse(
+            r#"{ "conventions": [ { "name": "vienna", "countries": ["de"], "signs": [
+                 { "type": "206", "shape": "octagon", "texture": "stop.png" } ] } ] }"#,
+            Path::new("/maps"),
+        )
+        .unwrap();
+        let model = &conventions[0].signs[0];
+        assert_eq!(conventions[0].countries, vec!["DE"]);
+        assert_eq!((model.width, model.height), (0.6, 0.6));
+        assert_eq!(
+            model.look,
+            Look::Texture(Path::new("/maps/stop.png").to_path_buf())
+        );
+        let twice = r#"{ "conventions": [ { "name": "x", "signs": [
+                        { "type": "1", "face": "stop", "mesh": "a.glb" } ] } ] }"#;
+        assert!(parse(twice, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn sign_catalogs() {
+        let network = load("signals.xodr");
+        let catalogs = SignCatalogs::default();
+        let shapes: Vec<Option<Shape>> = network
+            .signals
+            .iter()
+            .map(|signal| catalogs.model(signal).map(|model| model.shape))
+            .collect();
+        // Traffic lights keep their icons.
+        assert_eq!(
+            shapes,
+            vec![Some(Shape::Circle), Some(Shape::Octagon), None]
+        );
+        // The code of another convention is not taken for a German sign.
+        let mut stop = network.signals[1].clone();
+        stop.type_code = "R1-1".to_string();
+        assert_eq!(catalogs.model(&stop), None);
+        stop.country = "US".to_string();
+        assert_eq!(catalogs.model(&stop).unwrap().shape, Shape::Octagon);
+    }
+}
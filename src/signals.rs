@@ -0,0 +1,423 @@
+// Road signals and objects, and their icons in the viewer.
+//
+// OpenDRIVE places signals (signs, traffic lights) and objects (poles,
+// crosswalks, trees, ...) along a road by station `s` and lateral offset
+// `t`. They are kept with the attributes needed to write them back, plus
+// their position in the viewer frame.
+//
+// In the viewer every signal is drawn as a camera-facing icon for its
+// category: a stop octagon, a yield triangle, a speed limit roundel with its
+// value, a traffic light housing, or a generic marker. Icons are textures
+// painted in code, one per category and speed value, so no asset files are
+// needed. A style sheet can tint icons or hide them (see `style`). By
+// default icons are depth tested like the rest of the scene; the
+// occlusion-free mode draws them through a second camera on top of
+// everything, which makes signal review possible from plan view. Signs a
+// sign catalog knows are drawn as signs instead (see `sign_models`), and only
+// get icons in occlusion-free mode.
+//
+// Keys: O toggles occlusion-free icons.
+
+use std::collections::HashMap;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::camera::ClearColorConfig;
+use bevy::render::view::RenderLayers;
+
+use crate::canvas::Canvas;
+use crate::edit::NetworkChanged;
+use crate::entity_index::{OdrEntityIndex, OdrId};
+use crate::odr::insert_signal;
+use crate::origin::WorldPosition;
+use crate::overlay_pass::OVERLAY_CAMERA_ORDER;
+use crate::sign_models::SignCatalogs;
+use crate::style::StyleSheet;
+use crate::xodr::ElementXml;
+use crate::{camera_orbit, MainCamera, RoadNetwork};
+
+// Edge length of an icon, in meters.
+const ICON_SIZE: f32 = 1.5;
+
+// Edge length of an icon texture, in pixels.
+const TEXTURE_SIZE: u32 = 64;
+
+// Render layer of icons in occlusion-free mode.
+const OVERLAY_LAYER: u8 = 1;
+
+// What a signal shows, as far as the viewer is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+    Stop,
+    Yield,
+    SpeedLimit,
+    TrafficLight,
+    // Any other sign.
+    Other,
+    // An `<object>` rather than a `<signal>`.
+    Object,
+}
+
+impl SignalKind {
+    // Classifies a signal by its type code. OpenDRIVE files mostly use the
+    // German catalogue (StVO) codes, which many tools adopted regardless of
+    // country; signals marked dynamic are taken to be traffic lights.
+    pub fn classify(type_code: &str, name: &str, dynamic: bool) -> Self {
+        let name = name.to_ascii_lowercase();
+        match type_code.trim() {
+            "206" => SignalKind::Stop,
+            "205" => SignalKind::Yield,
+            "274" => SignalKind::SpeedLimit,
+            code if code.starts_with("1000") => SignalKind::TrafficLight,
+            _ if dynamic => SignalKind::TrafficLight,
+            _ if name.contains("stop") => SignalKind::Stop,
+            _ if name.contains("yield") || name.contains("give way") => SignalKind::Yield,
+            _ if name.contains("speed") => SignalKind::SpeedLimit,
+            _ => SignalKind::Other,
+        }
+    }
+}
+
+// A signal or object placed along a road.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signal {
+    pub id: String,
+    pub name: String,
+    pub road_id: u32,
+    // Station and lateral offset along the road's reference line.
+    pub s: f64,
+    pub t: f64,
+    // Height of the signal's bottom above the road, and its own height.
+    pub z_offset: f64,
+    pub height: f64,
+    // Footprint: a box of width (across) and length (along the road), or a
+    // circle if the radius is set. Zero where not given.
+    pub width: f64,
+    pub length: f64,
+    pub radius: f64,
+    // "+", "-" or "none", as in OpenDRIVE.
+    pub orientation: String,
+    pub kind: SignalKind,
+    // The OpenDRIVE type, subtype and country codes, kept for writing.
+    pub type_code: String,
+    pub subtype: String,
+    pub country: String,
+    pub dynamic: bool,
+    // The shown value (e.g. a speed limit) and its unit.
+    pub value: Option<f64>,
+    pub unit: String,
+    // Viewer-frame position of the signal's foot on the road surface.
+    pub position: DVec3,
+    // The `<repeat>` records of an object, in the order read.
+    pub repeats: Vec<ObjectRepeat>,
+    // The element as it was read, empty if not read from OpenDRIVE.
+    pub xml: ElementXml,
+}
+
+// An OpenDRIVE `<repeat>` of an object: copies of it every `distance`
+// meters for `length` meters from station `s`, or, where `distance` is
+// zero, one continuous object such as a guardrail. The lateral offset and
+// the sizes change linearly from the start to the end; zero sizes are not
+// given and are taken from the object.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectRepeat {
+    pub s: f64,
+    pub length: f64,
+    pub distance: f64,
+    pub t_start: f64,
+    pub t_end: f64,
+    pub z_offset_start: f64,
+    pub z_offset_end: f64,
+    pub height_start: f64,
+    pub height_end: f64,
+    pub width_start: f64,
+    pub width_end: f64,
+}
+
+impl ObjectRepeat {
+    pub fn is_continuous(&self) -> bool {
+        self.distance <= 0.0
+    }
+}
+
+impl Signal {
+    // Half the extent of the footprint across the road.
+    pub fn half_width(&self) -> f64 {
+        if self.radius > 0.0 {
+            self.radius
+        } else {
+            self.width / 2.0
+        }
+    }
+}
+
+// Whether icons are drawn on top of everything.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcclusionFree(pub bool);
+
+// Marks a signal icon.
+#[derive(Component)]
+struct SignalIcon;
+
+pub struct SignalPlugin;
+
+impl Plugin for SignalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OcclusionFree>()
+            .add_systems(Startup, spawn_icons)
+            .add_systems(
+                Update,
+                (despawn_icons, spawn_icons).chain().run_if(
+                    on_event::<NetworkChanged>()
+                        .or_else(|sheet: Res<StyleSheet>| sheet.is_changed() && !sheet.is_added())
+                        .or_else(|catalogs: Res<SignCatalogs>| {
+                            catalogs.is_changed() && !catalogs.is_added()
+                        })
+                        .or_else(|mode: Res<OcclusionFree>| mode.is_changed() && !mode.is_added()),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_occlusion,
+                    (attach_overlay_camera, face_camera).after(camera_orbit),
+                ),
+            );
+    }
+}
+
+fn despawn_icons(
+    mut commands: Commands,
+    mut index: ResMut<OdrEntityIndex>,
+    icons: Query<Entity, With<SignalIcon>>,
+) {
+    for icon in &icons {
+        index.remove(icon);
+        commands.entity(icon).despawn();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_icons(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    network: Res<RoadNetwork>,
+    style: Res<StyleSheet>,
+    catalogs: Res<SignCatalogs>,
+    occlusion_free: Res<OcclusionFree>,
+    mut index: ResMut<OdrEntityIndex>,
+) {
+    if network.signals.is_empty() {
+        return;
+    }
+    let quad = meshes.add(Rectangle::new(ICON_SIZE, ICON_SIZE));
+    let mut icons: HashMap<(SignalKind, Option<u32>, Option<usize>), Handle<StandardMaterial>> =
+        HashMap::new();
+    for signal in network
+        .signals
+        .iter()
+        .filter(|s| style.shows_signal(s))
+        .filter(|s| occlusion_free.0 || catalogs.model(s).is_none())
+    {
+        // Only speed limits show their value on the icon.
+        let value = match signal.kind {
+            SignalKind::SpeedLimit => signal.value.map(|v| v.round() as u32),
+            _ => None,
+        };
+        let rule = style.signal_rule(signal);
+        let material = icons
+            .entry((signal.kind, value, rule))
+            .or_insert_with(|| {
+                let texture = images.add(paint_icon(signal.kind, value));
+                let tint = rule.and_then(|rule| style.rules[rule].color);
+                materials.add(StandardMaterial {
+                    base_color: tint.unwrap_or(Color::WHITE),
+                    base_color_texture: Some(texture),
+                    alpha_mode: AlphaMode::Blend,
+                    unlit: true,
+                    double_sided: true,
+                    cull_mode: None,
+                    ..default()
+                })
+            })
+            .clone();
+
+        // Icons float at the height of the sign, or at eye level for signals
+        // without one.
+        let lift = (signal.z_offset + signal.height / 2.0).max(2.0);
+        let mut icon = commands.spawn((
+            PbrBundle {
+                mesh: quad.clone(),
+                material,
+                ..default()
+            },
+            WorldPosition(signal.position + DVec3::Y * lift),
+            SignalIcon,
+        ));
+        insert_signal(&mut icon, signal);
+        let icon = icon.id();
+        index.insert(icon, vec![OdrId::Signal(signal.id.clone())]);
+    }
+}
+
+fn toggle_occlusion(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut mode: ResMut<OcclusionFree>,
+    mut commands: Commands,
+    icons: Query<Entity, With<SignalIcon>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyO) {
+        return;
+    }
+    mode.0 = !mode.0;
+    let layer = if mode.0 {
+        RenderLayers::layer(OVERLAY_LAYER)
+    } else {
+        RenderLayers::layer(0)
+    };
+    for icon in &icons {
+        commands.entity(icon).insert(layer);
+    }
+}
+
+// Gives the main camera a child camera that draws only the overlay layer,
+// after the main pass and with a cleared depth buffer.
+fn attach_overlay_camera(
+    mut commands: Commands,
+    main: Query<Entity, With<MainCamera>>,
+    mut attached: Local<bool>,
+) {
+    if *attached {
+        return;
+    }
+    let Ok(main) = main.get_single() else {
+        return;
+    };
+    *attached = true;
+    let overlay = commands
+        .spawn((
+            Camera3dBundle {
+                camera: Camera {
+                    // After the map's overlays, see `overlay_pass`.
+                    order: OVERLAY_CAMERA_ORDER + 1,
+                    clear_color: ClearColorConfig::None,
+                    ..default()
+                },
+                ..default()
+            },
+            RenderLayers::layer(OVERLAY_LAYER),
+        ))
+        .id();
+    commands.entity(main).add_child(overlay);
+}
+
+// Turns every icon to face the camera.
+fn face_camera(
+    cameras: Query<&Transform, (With<MainCamera>, Without<SignalIcon>)>,
+    mut icons: Query<&mut Transform, With<SignalIcon>>,
+) {
+    let Ok(camera) = cameras.get_single() else {
+        return;
+    };
+    for mut icon in &mut icons {
+        icon.rotation = camera.rotation;
+    }
+}
+
+// Paints the icon texture for a signal category.
+fn paint_icon(kind: SignalKind, value: Option<u32>) -> Image {
+    let size = TEXTURE_SIZE as i32;
+    let mut canvas = Canvas::new(size, size);
+    let center = Vec2::splat(size as f32 / 2.0);
+    let radius = size as f32 / 2.0 - 1.0;
+    const RED: [u8; 4] = [200, 30, 30, 255];
+    const WHITE: [u8; 4] = [250, 250, 250, 255];
+    const BLACK: [u8; 4] = [20, 20, 20, 255];
+
+    match kind {
+        SignalKind::Stop => {
+            canvas.fill(|p| octagon(p - center) <= radius, WHITE);
+            canvas.fill(|p| octagon(p - center) <= radius - 4.0, RED);
+        }
+        SignalKind::Yield => {
+            // Point down, as the sign does. Image rows run top to bottom;
+            // the triangle is shifted up a little to center it.
+            let middle = center - Vec2::Y * radius / 4.0;
+            let triangle = |p: Vec2, inset: f32| {
+                let q = p - middle;
+                q.y >= -radius / 2.0 + inset
+                    && 0.866 * q.x.abs() + 0.5 * q.y <= radius / 2.0 - inset
+            };
+            canvas.fill(|p| triangle(p, 0.0), RED);
+            canvas.fill(|p| triangle(p, 7.0), WHITE);
+        }
+        SignalKind::SpeedLimit => {
+            canvas.fill(|p| p.distance(center) <= radius, RED);
+            canvas.fill(|p| p.distance(center) <= radius - 7.0, WHITE);
+            if let Some(value) = value {
+                canvas.number(value, center, BLACK);
+            }
+        }
+        SignalKind::TrafficLight => {
+            canvas.fill(
+                |p| (p.x - center.x).abs() <= radius * 0.45 && (p.y - center.y).abs() <= radius,
+                BLACK,
+            );
+            for (row, color) in [
+                (-1.0, RED),
+                (0.0, [240, 190, 20, 255]),
+                (1.0, [30, 190, 60, 255]),
+            ] {
+                let light = center + Vec2::new(0.0, row * radius * 0.62);
+                canvas.fill(|p| p.distance(light) <= radius * 0.26, color);
+            }
+        }
+        SignalKind::Other => {
+            let diamond = |p: Vec2| (p.x - center.x).abs() + (p.y - center.y).abs();
+            canvas.fill(|p| diamond(p) <= radius, WHITE);
+            canvas.fill(|p| diamond(p) <= radius - 5.0, [30, 90, 200, 255]);
+        }
+        SignalKind::Object => {
+            canvas.fill(|p| p.distance(center) <= radius * 0.6, [150, 150, 150, 255]);
+        }
+    }
+    canvas.into_image()
+}
+
+// Distance measure whose level sets are regular octagons.
+fn octagon(q: Vec2) -> f32 {
+    let a = q.abs();
+    a.x.max(a.y)
+        .max((a.x + a.y) * std::f32::consts::FRAC_1_SQRT_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_points_near, load};
+
+    #[test]
+    fn signals_are_read_with_their_kinds() {
+        let network = load("signals.xodr");
+        let kinds: Vec<_> = network
+            .signals
+            .iter()
+            .map(|signal| (signal.id.as_str(), signal.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("1", SignalKind::SpeedLimit),
+                ("2", SignalKind::Stop),
+                ("3", SignalKind::TrafficLight),
+            ]
+        );
+        let limit = &network.signals[0];
+        assert_eq!(limit.road_id, 1);
+        assert_eq!(limit.value, Some(50.0));
+        assert_points_near(limit.position, DVec3::new(10.0, 0.0, 4.0));
+        assert_points_near(network.signals[2].position, DVec3::new(95.0, 0.0, 5.0));
+    }
+}
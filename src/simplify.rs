@@ -0,0 +1,408 @@
+// Mesh simplification by quadric error edge collapse.
+//
+// Tessellated lanes carry a vertex pair for every sample of their boundary
+// polylines, one per meter for OpenDRIVE input. On straight or gently curved
+// stretches most of those pairs add nothing, so exports and low-end rendering
+// can do with far fewer triangles.
+//
+// Vertices are merged into a neighbour as long as the surface moves by less
+// than a target error. The error of a vertex is tracked with a quadric: the
+// sum of squared distances to the planes of the triangles it absorbed
+// (Garland and Heckbert). Open boundaries add planes standing on the boundary
+// edges, so lane outlines keep their shape while collinear boundary vertices
+// are still welded away. Vertices only ever move onto existing ones, so the
+// result is a subset of the original positions.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use bevy::math::{DVec3, Vec3};
+
+use crate::tessellation::{TriangleMesh, PLAIN};
+
+// Positions closer than this (in meters) are treated as the same vertex.
+const WELD_DISTANCE: f64 = 1e-5;
+
+// Returns a copy of `mesh` with as many vertices removed as possible while
+// staying within `max_error` meters of the original surface.
+pub fn simplify(mesh: &TriangleMesh, max_error: f64) -> TriangleMesh {
+    let mut state = Collapser::new(mesh);
+    state.run(max_error * max_error);
+    state.finish()
+}
+
+// A symmetric 4x4 matrix measuring squared distance to a set of planes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // The quadric of the plane through `p` with unit normal `n`.
+    fn plane(n: DVec3, p: DVec3) -> Self {
+        let d = -n.dot(p);
+        Self([
+            n.x * n.x,
+            n.x * n.y,
+            n.x * n.z,
+            n.x * d,
+            n.y * n.y,
+            n.y * n.z,
+            n.y * d,
+            n.z * n.z,
+            n.z * d,
+            d * d,
+        ])
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+
+    // Sum of squared distances from `p` to the planes.
+    fn error(&self, p: DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+// A proposed collapse of `from` onto `to`, ordered by ascending cost.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    cost: f64,
+    from: usize,
+    to: usize,
+    // The version of `from` when the candidate was made; stale entries are
+    // skipped when popped.
+    version: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // Reversed, so that the max-heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Collapser {
+    positions: Vec<DVec3>,
+    // Vertex colors, empty if the mesh has none.
+    colors: Vec<[f32; 4]>,
+    triangles: Vec<[usize; 3]>,
+    live: Vec<bool>,
+    // Triangles around each vertex, including ones since removed.
+    around: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    removed: Vec<bool>,
+    versions: Vec<u32>,
+}
+
+impl Collapser {
+    fn new(mesh: &TriangleMesh) -> Self {
+        // Strips are built per lane, so neighbouring lanes duplicate their
+        // shared boundary. Weld those first so the mesh is connected.
+        // Vertices of different colors are kept apart.
+        let mut welded: HashMap<([i64; 3], [u32; 4]), usize> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut colors = Vec::new();
+        let remap: Vec<usize> = mesh
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let p = p.as_dvec3();
+                let color = mesh.colors.get(i).copied();
+                let key = (
+                    (p / WELD_DISTANCE).round().as_i64vec3().to_array(),
+                    color.unwrap_or(PLAIN).map(f32::to_bits),
+                );
+                *welded.entry(key).or_insert_with(|| {
+                    positions.push(p);
+                    colors.extend(color);
+                    positions.len() - 1
+                })
+            })
+            .collect();
+
+        let triangles: Vec<[usize; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    remap[t[0] as usize],
+                    remap[t[1] as usize],
+                    remap[t[2] as usize],
+                ]
+            })
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .collect();
+
+        // Lanes sharing a boundary both paint it, so marking meshes carry
+        // identical triangles twice. Keep one of each.
+        let mut seen = HashSet::new();
+        let triangles: Vec<[usize; 3]> = triangles
+            .into_iter()
+            .filter(|triangle| {
+                let mut key = *triangle;
+                key.sort_unstable();
+                seen.insert(key)
+            })
+            .collect();
+
+        let mut around = vec![Vec::new(); positions.len()];
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut edge_use: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (index, triangle) in triangles.iter().enumerate() {
+            let normal = face_normal(&positions, triangle).normalize_or_zero();
+            let plane = Quadric::plane(normal, positions[triangle[0]]);
+            for (k, &v) in triangle.iter().enumerate() {
+                around[v].push(index);
+                quadrics[v].add(&plane);
+                let w = triangle[(k + 1) % 3];
+                edge_use
+                    .entry((v.min(w), v.max(w)))
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        // Boundary edges get a plane standing upright on them, so moving a
+        // vertex off the outline costs as much as moving it off the surface.
+        for (&(a, b), used_by) in &edge_use {
+            if used_by.len() != 1 {
+                continue;
+            }
+            let normal = face_normal(&positions, &triangles[used_by[0]]).normalize_or_zero();
+            let side = (positions[b] - positions[a])
+                .cross(normal)
+                .normalize_or_zero();
+            let plane = Quadric::plane(side, positions[a]);
+            quadrics[a].add(&plane);
+            quadrics[b].add(&plane);
+        }
+
+        let count = positions.len();
+        Self {
+            positions,
+            colors,
+            live: vec![true; triangles.len()],
+            triangles,
+            around,
+            quadrics,
+            removed: vec![false; count],
+            versions: vec![0; count],
+        }
+    }
+
+    // The live neighbours of a vertex.
+    fn neighbours(&self, v: usize) -> HashSet<usize> {
+        self.around[v]
+            .iter()
+            .filter(|&&t| self.live[t])
+            .flat_map(|&t| self.triangles[t])
+            .filter(|&w| w != v)
+            .collect()
+    }
+
+    fn candidates(&self, v: usize, heap: &mut BinaryHeap<Candidate>) {
+        for to in self.neighbours(v) {
+            let mut quadric = self.quadrics[v];
+            quadric.add(&self.quadrics[to]);
+            heap.push(Candidate {
+                cost: quadric.error(self.positions[to]),
+                from: v,
+                to,
+                version: self.versions[v],
+            });
+        }
+    }
+
+    fn run(&mut self, max_cost: f64) {
+        let mut heap = BinaryHeap::new();
+        for v in 0..self.positions.len() {
+            self.candidates(v, &mut heap);
+        }
+
+        while let Some(candidate) = heap.pop() {
+            if candidate.cost > max_cost {
+                break;
+            }
+            let Candidate {
+                from, to, version, ..
+            } = candidate;
+            if self.removed[from] || self.removed[to] || self.versions[from] != version {
+                continue;
+            }
+            if !self.can_collapse(from, to) {
+                continue;
+            }
+            self.collapse(from, to);
+            let mut touched = self.neighbours(to);
+            touched.insert(to);
+            for v in touched {
+                self.versions[v] += 1;
+                self.candidates(v, &mut heap);
+            }
+        }
+    }
+
+    // A collapse must keep the mesh manifold and must not flip or flatten
+    // any of the triangles that survive it.
+    fn can_collapse(&self, from: usize, to: usize) -> bool {
+        let shared_triangles = self.around[from]
+            .iter()
+            .filter(|&&t| self.live[t] && self.triangles[t].contains(&to))
+            .count();
+        let shared_neighbours = self
+            .neighbours(from)
+            .intersection(&self.neighbours(to))
+            .count();
+        if shared_neighbours != shared_triangles {
+            return false;
+        }
+
+        self.around[from]
+            .iter()
+            .filter(|&&t| self.live[t] && !self.triangles[t].contains(&to))
+            .all(|&t| {
+                let before = face_normal(&self.positions, &self.triangles[t]);
+                let moved = self.triangles[t].map(|v| if v == from { to } else { v });
+                let after = face_normal(&self.positions, &moved);
+                after.length() > 1e-9 && after.dot(before) > 0.0
+            })
+    }
+
+    fn collapse(&mut self, from: usize, to: usize) {
+        for t in std::mem::take(&mut self.around[from]) {
+            if !self.live[t] {
+                continue;
+            }
+            if self.triangles[t].contains(&to) {
+                self.live[t] = false;
+            } else {
+                for v in &mut self.triangles[t] {
+                    if *v == from {
+                        *v = to;
+                    }
+                }
+                self.around[to].push(t);
+            }
+        }
+        let absorbed = self.quadrics[from];
+        self.quadrics[to].add(&absorbed);
+        self.removed[from] = true;
+    }
+
+    // Compacts the surviving triangles into a new mesh with smooth normals.
+    fn finish(self) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+        let mut index: Vec<Option<u32>> = vec![None; self.positions.len()];
+        let mut normals: Vec<DVec3> = Vec::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            if !self.live[t] {
+                continue;
+            }
+            // Area-weighted, as the cross product is twice the area.
+            let normal = face_normal(&self.positions, triangle);
+            for &v in triangle {
+                let i = *index[v].get_or_insert_with(|| {
+                    mesh.positions.push(self.positions[v].as_vec3());
+                    mesh.colors.extend(self.colors.get(v));
+                    normals.push(DVec3::ZERO);
+                    mesh.positions.len() as u32 - 1
+                });
+                normals[i as usize] += normal;
+                mesh.indices.push(i);
+            }
+        }
+        mesh.normals = normals
+            .into_iter()
+            .map(|n| n.normalize_or_zero().as_vec3())
+            .map(|n| if n == Vec3::ZERO { Vec3::Y } else { n })
+            .collect();
+        mesh
+    }
+}
+
+// The unnormalized normal of a triangle.
+fn face_normal(positions: &[DVec3], [a, b, c]: &[usize; 3]) -> DVec3 {
+    (positions[*b] - positions[*a]).cross(positions[*c] - positions[*a])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A lane-like strip along x, `width` wide, with a vertex pair every
+    // meter and heights from `height`.
+    fn strip(length: u32, width: f32, height: impl Fn(f32) -> f32) -> TriangleMesh {
+        let mut mesh = TriangleMesh::default();
+        for i in 0..=length {
+            let x = i as f32;
+            mesh.positions.push(Vec3::new(x, height(x), 0.0));
+            mesh.positions.push(Vec3::new(x, height(x), width));
+            mesh.normals.extend([Vec3::Y; 2]);
+        }
+        for i in 0..length {
+            let (a, b, c, d) = (2 * i, 2 * i + 1, 2 * i + 2, 2 * i + 3);
+            mesh.indices.extend([a, b, c, c, b, d]);
+        }
+        mesh
+    }
+
+    fn extent(mesh: &TriangleMesh) -> (Vec3, Vec3) {
+        let min = mesh.positions.iter().fold(Vec3::INFINITY, |m, p| m.min(*p));
+        let max = mesh
+            .positions
+            .iter()
+            .fold(Vec3::NEG_INFINITY, |m, p| m.max(*p));
+        (min, max)
+    }
+
+    #[test]
+    fn flat_strips_collapse_to_their_outline() {
+        let mesh = strip(100, 3.5, |_| 0.0);
+        let simple = simplify(&mesh, 0.01);
+        assert!(
+            simple.indices.len() / 3 <= 4,
+            "{} triangles",
+            simple.indices.len() / 3
+        );
+        assert_eq!(extent(&simple), extent(&mesh));
+        assert!(simple.positions.iter().all(|p| mesh.positions.contains(p)));
+        assert!(simple.normals.iter().all(|n| n.abs_diff_eq(Vec3::Y, 1e-6)));
+    }
+
+    #[test]
+    fn bumps_larger_than_the_error_stay() {
+        // A 0.5 m crest in the middle of the strip.
+        let mesh = strip(100, 3.5, |x| 0.5 - (x - 50.0).abs() / 100.0);
+        let simple = simplify(&mesh, 0.01);
+        assert!(simple.indices.len() < mesh.indices.len());
+        assert!(simple.positions.iter().any(|p| p.x == 50.0 && p.y == 0.5));
+    }
+}
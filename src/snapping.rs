@@ -0,0 +1,325 @@
+// Snapping for edits.
+//
+// Points and sizes given to the edit commands are pulled onto the nearest
+// thing worth lining up with, so that drawn roads meet the ones already
+// there and line up with each other:
+//
+// - road ends: a point within `ENDPOINT_RADIUS` of the end of a road's
+//   reference line moves onto it;
+// - tangents: a road drawn from a road end is turned to carry on that
+//   road's heading when it is off by less than `TANGENT_ANGLE`;
+// - the grid: points and move offsets not snapped otherwise are rounded to
+//   a grid, if one is set;
+// - standard widths: widening or narrowing a template lane steps through
+//   `STANDARD_WIDTHS` rather than by a fixed amount.
+//
+// Each kind can be switched on and off with `snap` in the console (the grid
+// is off until given a size). Every snap is marked on the map for a few
+// seconds: road ends with a ring, tangents with a line along the heading
+// carried on, grid points with a square.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::cut;
+use crate::origin::RenderOrigin;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+// How close a point must be to a road end to snap to it, in meters.
+const ENDPOINT_RADIUS: f64 = 3.0;
+
+// The largest heading change snapped away, in degrees.
+const TANGENT_ANGLE: f64 = 15.0;
+
+// Common lane widths, in meters.
+const STANDARD_WIDTHS: [f64; 8] = [2.5, 2.75, 3.0, 3.25, 3.5, 3.65, 3.75, 4.0];
+
+// How long a snap stays marked, in seconds.
+const MARK_SECONDS: f32 = 3.0;
+
+// Size of the marks, in meters.
+const MARK_SIZE: f32 = 1.0;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Snapping {
+    // The grid spacing in meters, if snapping to a grid.
+    pub grid: Option<f64>,
+    pub endpoints: bool,
+    pub tangent: bool,
+    pub widths: bool,
+}
+
+impl Default for Snapping {
+    fn default() -> Self {
+        Self {
+            grid: None,
+            endpoints: true,
+            tangent: true,
+            widths: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapKind {
+    Grid,
+    Endpoint,
+    // Along the given direction.
+    Tangent(DVec3),
+}
+
+// The snaps of the last few seconds, with when they happened.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SnapMarks(pub Vec<(DVec3, SnapKind, f32)>);
+
+// The ends of every road's reference line, with the direction leading out
+// of the road there.
+fn road_ends(network: &RoadNetwork) -> Vec<(DVec3, DVec3)> {
+    network
+        .roads
+        .keys()
+        .flat_map(|&road_id| {
+            let lanes = network
+                .segments
+                .iter()
+                .filter(|lane| lane.road_id == road_id);
+            let (start, end) = lanes.fold((f64::MAX, f64::MIN), |(start, end), lane| {
+                (start.min(lane.start_s), end.max(lane.end_s))
+            });
+            [(start, -1.0), (end, 1.0)]
+                .into_iter()
+                .filter_map(move |(s, outwards)| {
+                    let cut = cut(network, road_id, s)?;
+                    Some((cut.reference, cut.direction * outwards))
+                })
+        })
+        .collect()
+}
+
+impl Snapping {
+    fn grid_point(&self, p: DVec3) -> Option<DVec3> {
+        let grid = self.grid?;
+        Some(DVec3::new(
+            (p.x / grid).round() * grid,
+            p.y,
+            (p.z / grid).round() * grid,
+        ))
+    }
+
+    // Snaps a point to a road end or the grid.
+    pub fn point(&self, network: &RoadNetwork, p: DVec3) -> (DVec3, Option<SnapKind>) {
+        if self.endpoints {
+            let nearest = road_ends(network)
+                .into_iter()
+                .map(|(end, _)| end)
+                .filter(|end| end.distance(p) <= ENDPOINT_RADIUS)
+                .min_by(|a, b| a.distance(p).total_cmp(&b.distance(p)));
+            if let Some(end) = nearest {
+                return (end, Some(SnapKind::Endpoint));
+            }
+        }
+        match self.grid_point(p) {
+            Some(on_grid) => (on_grid, Some(SnapKind::Grid)),
+            None => (p, None),
+        }
+    }
+
+    // Snaps the ends of a straight line: each to a road end or the grid,
+    // and the far end onto the heading of a road the line starts from.
+    pub fn line(
+        &self,
+        network: &RoadNetwork,
+        from: DVec3,
+        to: DVec3,
+    ) -> ((DVec3, Option<SnapKind>), (DVec3, Option<SnapKind>)) {
+        let (from, from_snap) = self.point(network, from);
+        let (snapped_to, to_snap) = self.point(network, to);
+        if to_snap == Some(SnapKind::Endpoint) || !self.tangent {
+            return ((from, from_snap), (snapped_to, to_snap));
+        }
+        let heading = road_ends(network)
+            .into_iter()
+            .find(|(end, _)| end.distance(from) < 1e-6)
+            .map(|(_, outwards)| outwards);
+        let line = to - from;
+        let along = heading.map(|heading| (heading, line.dot(heading)));
+        match along {
+            Some((heading, along))
+                if along > 0.0 && line.angle_between(heading).to_degrees() < TANGENT_ANGLE =>
+            {
+                (
+                    (from, from_snap),
+                    (from + heading * along, Some(SnapKind::Tangent(heading))),
+                )
+            }
+            _ => ((from, from_snap), (snapped_to, to_snap)),
+        }
+    }
+
+    // Snaps an offset to the grid.
+    pub fn offset(&self, offset: DVec3) -> DVec3 {
+        self.grid_point(offset).unwrap_or(offset)
+    }
+
+    // The width after widening (or narrowing) a lane by `step`: the next
+    // standard width that way, if snapping to them and there is one.
+    pub fn step_width(&self, width: f64, step: f64) -> f64 {
+        let next = if step > 0.0 {
+            STANDARD_WIDTHS.iter().find(|w| **w > width + 1e-6).copied()
+        } else {
+            STANDARD_WIDTHS
+                .iter()
+                .rev()
+                .find(|w| **w < width - 1e-6)
+                .copied()
+        };
+        match next {
+            Some(next) if self.widths => next,
+            _ => width + step,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let on = |on: bool| if on { "on" } else { "off" };
+        let grid = self
+            .grid
+            .map_or("off".to_string(), |grid| format!("{grid} m"));
+        format!(
+            "grid {grid}, road ends {}, tangents {}, standard widths {}",
+            on(self.endpoints),
+            on(self.tangent),
+            on(self.widths)
+        )
+    }
+}
+
+// Marks the snaps of an edit on the map.
+pub fn mark(world: &mut World, snaps: &[(DVec3, Option<SnapKind>)]) {
+    let now = world.resource::<Time>().elapsed_seconds();
+    let mut marks = world.resource_mut::<SnapMarks>();
+    marks.0.extend(
+        snaps
+            .iter()
+            .filter_map(|&(at, kind)| Some((at, kind?, now))),
+    );
+}
+
+// `snap` in the console: describes the settings or changes one.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut snapping = world.resource_mut::<Snapping>();
+    let switch = |value: &str| match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, not `{value}`")),
+    };
+    match args {
+        [] => return Ok(snapping.describe()),
+        ["grid", "off"] => snapping.grid = None,
+        ["grid", size] => {
+            snapping.grid = Some(
+                size.parse()
+                    .ok()
+                    .filter(|size: &f64| *size > 0.0)
+                    .ok_or_else(|| format!("invalid grid size `{size}`"))?,
+            )
+        }
+        ["ends", value] => snapping.endpoints = switch(value)?,
+        ["tangent", value] => snapping.tangent = switch(value)?,
+        ["widths", value] => snapping.widths = switch(value)?,
+        _ => {
+            return Err(
+                "expected grid <m>|off, or ends, tangent or widths with on or off".to_string(),
+            )
+        }
+    }
+    Ok(snapping.describe())
+}
+
+pub struct SnappingPlugin;
+
+impl Plugin for SnappingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Snapping>()
+            .init_resource::<SnapMarks>()
+            .add_systems(Update, draw_marks.after(camera_orbit));
+    }
+}
+
+fn draw_marks(
+    time: Res<Time>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut marks: ResMut<SnapMarks>,
+    mut gizmos: Gizmos,
+) {
+    let now = time.elapsed_seconds();
+    marks.0.retain(|(_, _, at)| now - at < MARK_SECONDS);
+    for &(position, kind, _) in &marks.0 {
+        let at = origin.to_render(position) + Vec3::Y * 0.1;
+        match kind {
+            SnapKind::Endpoint => {
+                gizmos.circle(at, Direction3d::Y, MARK_SIZE, theme.highlight);
+            }
+            SnapKind::Grid => {
+                gizmos.rect(
+                    at,
+                    Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+                    Vec2::splat(MARK_SIZE),
+                    theme.highlight,
+                );
+            }
+            SnapKind::Tangent(direction) => {
+                let along = direction.as_vec3() * MARK_SIZE * 4.0;
+                gizmos.line(at - along, at + along, theme.highlight);
+                gizmos.circle(at, Direction3d::Y, MARK_SIZE / 2.0, theme.highlight);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{assert_points_near, load};
+
+    #[test]
+    fn grid_and_steps() {
+        let snapping = Snapping::default();
+        let grid = Snapping {
+            grid: Some(5.0),
+            ..Snapping::default()
+        };
+        assert_eq!(
+            grid.offset(DVec3::new(3.0, 0.0, -7.0)),
+            DVec3::new(5.0, 0.0, -5.0)
+        );
+        assert!((snapping.step_width(3.5, 0.25) - 3.65).abs() < 1e-9);
+        assert!((snapping.step_width(2.5, -0.25) - 2.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn snapped_points() {
+        let network = load("straight.xodr");
+        let snapping = Snapping::default();
+        // Near the end of the road, onto the end and then along the heading.
+        let (start, end) = snapping.line(
+            &network,
+            DVec3::new(101.0, 0.0, 1.5),
+            DVec3::new(150.0, 0.0, 5.0),
+        );
+        assert_eq!(start.1, Some(SnapKind::Endpoint));
+        assert_points_near(start.0, DVec3::new(100.0, 0.0, 0.0));
+        assert!(matches!(end.1, Some(SnapKind::Tangent(_))));
+        assert_points_near(end.0, DVec3::new(150.0, 0.0, 0.0));
+
+        let grid = Snapping {
+            grid: Some(5.0),
+            ..Snapping::default()
+        };
+        assert_eq!(
+            grid.point(&network, DVec3::new(42.0, 0.0, 18.0)).1,
+            Some(SnapKind::Grid)
+        );
+    }
+}
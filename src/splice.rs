@@ -0,0 +1,460 @@
+// Splitting a road in two and joining two roads into one.
+//
+// `road split <id> <s>` cuts a road at station `s`: the road keeps the part
+// before it, and the part after becomes a new road with the next free ID,
+// its stations, lane sections, signals and objects counted from the cut.
+// `road join <id> <id>` appends the second road to the first where the first
+// ends and the second starts; the ends must be linked or meet. Both move the
+// links at the far end over to the road that now ends there, link the two
+// halves of a split, and point the connections of the junctions reached
+// from that end at it, rewriting the links of the elements as read of the
+// roads beyond. Lanes keep their IDs, which link them across the
+// cut. Roads inside junctions are left alone. Both run as transactions and
+// can be undone (see `edit`).
+
+use bevy::prelude::*;
+
+use crate::cross_section::{cut, road_range};
+use crate::edit::linked_roads;
+use crate::lane_edit;
+use crate::transaction::{self, Transaction};
+use crate::xodr;
+use crate::{ContactPoint, RoadInfo, RoadLink, RoadNetwork};
+
+// The shortest road a split may leave, in meters.
+const MIN_LENGTH: f64 = 0.5;
+
+// How far apart the ends of joined roads may be without a link, in meters.
+const JOIN_DISTANCE: f64 = 0.1;
+
+// Points the junctions reached from the end of `to` that used to be the end
+// of `from` at `to`.
+fn retarget_junctions(network: &mut RoadNetwork, from: u32, to: u32) {
+    let connecting: Vec<u32> = network
+        .links
+        .iter()
+        .filter(|link| link.other_road_id == to && link.other_contact == ContactPoint::End)
+        .map(|link| link.road_id)
+        .filter(|road_id| {
+            network
+                .roads
+                .get(road_id)
+                .is_some_and(|info| info.junction.is_some())
+        })
+        .collect();
+    for xml in network.junctions.values_mut() {
+        *xml = xodr::retarget_connections(&xml.text().unwrap_or_default(), from, to, &connecting)
+            .into();
+    }
+}
+
+// Rewrites the road links of the elements as read of roads whose links
+// moved: each road's link at the given end now names the given road, or is
+// gone.
+fn relink_roads(network: &mut RoadNetwork, moved: &[(u32, ContactPoint, Option<u32>)]) {
+    for &(road_id, contact, road) in moved {
+        if let Some(info) = network.roads.get_mut(&road_id) {
+            if let Some(xml) = info.xml.text() {
+                info.xml = xodr::relink(&xml, contact, road).into();
+            }
+        }
+    }
+}
+
+fn outside_junctions(network: &RoadNetwork, road_id: u32) -> Result<(), String> {
+    match network.roads.get(&road_id) {
+        None => Err(format!("no road {road_id}")),
+        Some(info) if info.junction.is_some() => {
+            Err(format!("road {road_id} is inside a junction"))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+// Splits a road at station `s` and returns the ID of the road made of the
+// part after it.
+pub fn split_road(network: &mut RoadNetwork, road_id: u32, s: f64) -> Result<u32, String> {
+    outside_junctions(network, road_id)?;
+    let (first, last) = road_range(network, road_id).ok_or(format!("no road {road_id}"))?;
+    if s < first + MIN_LENGTH || s > last - MIN_LENGTH {
+        return Err(format!(
+            "road {road_id} can only be split between {:.2} and {:.2}",
+            first + MIN_LENGTH,
+            last - MIN_LENGTH
+        ));
+    }
+    let section = lane_edit::split(network, road_id, s)?;
+    let new_id = network
+        .roads
+        .keys()
+        .copied()
+        .chain(network.segments.iter().map(|lane| lane.road_id))
+        .max()
+        .map_or(1, |id| id + 1);
+
+    for segment in network
+        .segments
+        .iter_mut()
+        .filter(|segment| segment.road_id == road_id && segment.lane_section_id >= section)
+    {
+        segment.road_id = new_id;
+        segment.lane_section_id -= section - 1;
+        segment.start_s -= s;
+        segment.end_s -= s;
+    }
+    for signal in network
+        .signals
+        .iter_mut()
+        .filter(|signal| signal.road_id == road_id && signal.s >= s)
+    {
+        signal.road_id = new_id;
+        signal.s -= s;
+        for repeat in &mut signal.repeats {
+            repeat.s -= s;
+        }
+    }
+    let info = network.roads.entry(road_id).or_default();
+    let (before, after): (Vec<_>, Vec<_>) = info.plan_view.iter().partition(|p| p.s <= s);
+    info.plan_view = before;
+    info.xml.clear();
+    // Both parts are the same street.
+    let name = info.name.clone();
+    let plan_view = after
+        .into_iter()
+        .map(|mut sample| {
+            sample.s -= s;
+            sample
+        })
+        .collect();
+    network.roads.insert(
+        new_id,
+        RoadInfo {
+            name,
+            plan_view,
+            ..RoadInfo::default()
+        },
+    );
+
+    // The links at the end now belong to the new road.
+    let mut moved = Vec::new();
+    for link in &mut network.links {
+        if link.road_id == road_id && link.contact == ContactPoint::End {
+            link.road_id = new_id;
+        }
+        if link.other_road_id == road_id && link.other_contact == ContactPoint::End {
+            link.other_road_id = new_id;
+            moved.push((link.road_id, link.contact, Some(new_id)));
+        }
+    }
+    relink_roads(network, &moved);
+    network.links.extend([
+        RoadLink {
+            road_id,
+            contact: ContactPoint::End,
+            other_road_id: new_id,
+            other_contact: ContactPoint::Start,
+        },
+        RoadLink {
+            road_id: new_id,
+            contact: ContactPoint::Start,
+            other_road_id: road_id,
+            other_contact: ContactPoint::End,
+        },
+    ]);
+    retarget_junctions(network, road_id, new_id);
+    Ok(new_id)
+}
+
+// Appends road `second` to road `first`, which then takes its place.
+pub fn join_roads(network: &mut RoadNetwork, first: u32, second: u32) -> Result<(), String> {
+    if first == second {
+        return Err("a road cannot be joined to itself".to_string());
+    }
+    outside_junctions(network, first)?;
+    outside_junctions(network, second)?;
+    let (_, end) = road_range(network, first).ok_or(format!("no road {first}"))?;
+    let (second_start, _) = road_range(network, second).ok_or(format!("no road {second}"))?;
+    let linked = network.links.iter().any(|link| {
+        link.road_id == first
+            && link.contact == ContactPoint::End
+            && link.other_road_id == second
+            && link.other_contact == ContactPoint::Start
+    });
+    let meet = match (cut(network, first, end), cut(network, second, second_start)) {
+        (Some(a), Some(b)) => a.reference.distance(b.reference) <= JOIN_DISTANCE,
+        _ => false,
+    };
+    if !linked && !meet {
+        return Err(format!(
+            "road {first} does not end where road {second} starts"
+        ));
+    }
+
+    let sections = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == first)
+        .map(|segment| segment.lane_section_id)
+        .max()
+        .unwrap_or(0);
+    let first_section = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == second)
+        .map(|segment| segment.lane_section_id)
+        .min()
+        .unwrap_or(1);
+    let shift = end - second_start;
+    for segment in network
+        .segments
+        .iter_mut()
+        .filter(|segment| segment.road_id == second)
+    {
+        segment.road_id = first;
+        segment.lane_section_id = segment.lane_section_id - first_section + sections + 1;
+        segment.start_s += shift;
+        segment.end_s += shift;
+    }
+    for signal in network
+        .signals
+        .iter_mut()
+        .filter(|signal| signal.road_id == second)
+    {
+        signal.road_id = first;
+        signal.s += shift;
+        for repeat in &mut signal.repeats {
+            repeat.s += shift;
+        }
+    }
+    let appended = network.roads.remove(&second).unwrap_or_default();
+    let info = network.roads.entry(first).or_default();
+    info.xml.clear();
+    // A plan view is only kept if both roads had one.
+    if info.plan_view.is_empty() || appended.plan_view.is_empty() {
+        info.plan_view.clear();
+    } else {
+        info.plan_view
+            .extend(appended.plan_view.into_iter().map(|mut sample| {
+                sample.s += shift;
+                sample
+            }));
+    }
+
+    // The joined ends are gone; the far end of the second road is now the
+    // end of the first.
+    let joined = |road_id: u32, contact: ContactPoint| {
+        (road_id == first && contact == ContactPoint::End)
+            || (road_id == second && contact == ContactPoint::Start)
+    };
+    let mut moved: Vec<_> = network
+        .links
+        .iter()
+        .filter(|link| joined(link.other_road_id, link.other_contact))
+        .map(|link| (link.road_id, link.contact, None))
+        .collect();
+    network.links.retain(|link| {
+        !joined(link.road_id, link.contact) && !joined(link.other_road_id, link.other_contact)
+    });
+    for link in &mut network.links {
+        if link.road_id == second {
+            link.road_id = first;
+        }
+        if link.other_road_id == second {
+            link.other_road_id = first;
+            moved.push((link.road_id, link.contact, Some(first)));
+        }
+    }
+    relink_roads(network, &moved);
+    retarget_junctions(network, second, first);
+    Ok(())
+}
+
+// Starts an edit of the given roads and those linked to them.
+fn begin(world: &World, roads: &[u32], description: String) -> Transaction {
+    let network = world.resource::<RoadNetwork>();
+    let mut touched = roads.to_vec();
+    for &road_id in roads {
+        touched.extend(linked_roads(network, road_id));
+    }
+    touched.sort_unstable();
+    touched.dedup();
+    Transaction::begin(network, description, &touched)
+}
+
+// `road split` in the console.
+pub fn split(world: &mut World, road_id: u32, s: f64) -> Result<String, String> {
+    let transaction = begin(world, &[road_id], format!("splitting road {road_id}"));
+    transaction::run(world, transaction, |network| {
+        let new_id = split_road(network, road_id, s)?;
+        Ok((
+            format!("split road {road_id}; the part after {s} is road {new_id}"),
+            vec![new_id],
+        ))
+    })
+}
+
+// `road join` in the console.
+pub fn join(world: &mut World, first: u32, second: u32) -> Result<String, String> {
+    let description = format!("joining roads {first} and {second}");
+    let transaction = begin(world, &[first, second], description);
+    transaction::run(world, transaction, |network| {
+        join_roads(network, first, second)?;
+        Ok((
+            format!("appended road {second} to road {first}"),
+            Vec::new(),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::Snapshot;
+    use crate::sample_maps::{assert_near, lane, load, TOLERANCE};
+    use crate::transform::LoadTransform;
+
+    fn round_trip(network: &RoadNetwork) -> RoadNetwork {
+        xodr::read_str(&xodr::to_xml(network), &LoadTransform::default()).unwrap()
+    }
+
+    // The connections of junction 100, as (incoming, connecting) roads.
+    fn connections(network: &RoadNetwork) -> Vec<(String, String)> {
+        let xml = network.junctions[&100].text().unwrap();
+        let mut reader = quick_xml::Reader::from_str(&xml);
+        let mut found = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                quick_xml::events::Event::Start(e) | quick_xml::events::Event::Empty(e)
+                    if e.name().as_ref() == b"connection" =>
+                {
+                    let attribute = |name: &str| {
+                        let value = e.try_get_attribute(name).unwrap().unwrap();
+                        String::from_utf8(value.value.to_vec()).unwrap()
+                    };
+                    found.push((attribute("incomingRoad"), attribute("connectingRoad")));
+                }
+                quick_xml::events::Event::Eof => break,
+                _ => {}
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn junctions_follow_a_split_through_a_round_trip() {
+        let mut network = load("junction.xodr");
+        assert_eq!(split_road(&mut network, 1, 20.0), Ok(4));
+        assert_eq!(connections(&network), [("4".to_string(), "3".to_string())]);
+        // The connecting road's element as read links to the new road.
+        let through = network.roads[&3].xml.text().unwrap();
+        assert!(through
+            .contains("<predecessor elementType=\"road\" elementId=\"4\" contactPoint=\"end\"/>"));
+        assert!(through.contains("elementId=\"2\""));
+
+        let read = round_trip(&network);
+        assert_eq!(connections(&read), [("4".to_string(), "3".to_string())]);
+        assert_eq!(read.roads[&3].junction, Some(100));
+        let link = |road_id, contact, other_road_id, other_contact| RoadLink {
+            road_id,
+            contact,
+            other_road_id,
+            other_contact,
+        };
+        for expected in [
+            link(3, ContactPoint::Start, 4, ContactPoint::End),
+            link(1, ContactPoint::End, 4, ContactPoint::Start),
+            link(3, ContactPoint::End, 2, ContactPoint::Start),
+        ] {
+            assert!(read.links.contains(&expected), "{expected:?}");
+        }
+
+        join_roads(&mut network, 1, 4).unwrap();
+        assert_eq!(connections(&network), [("1".to_string(), "3".to_string())]);
+        assert!(network.roads[&3]
+            .xml
+            .text()
+            .unwrap()
+            .contains("elementId=\"1\" contactPoint=\"end\""));
+        let read = round_trip(&network);
+        assert_eq!(connections(&read), [("1".to_string(), "3".to_string())]);
+        assert!(read
+            .links
+            .contains(&link(3, ContactPoint::Start, 1, ContactPoint::End)));
+    }
+
+    #[test]
+    fn only_connections_through_the_moved_end_are_retargeted() {
+        let junction = r#"<junction id="7">
+  <connection id="0" incomingRoad="1" connectingRoad="3" contactPoint="start"/>
+  <connection id="1" incomingRoad="1" connectingRoad="5" contactPoint="end">
+    <laneLink from="1" to="1"/>
+  </connection>
+  <connection id="2" incomingRoad="11" connectingRoad="3" contactPoint="end"/>
+</junction>"#;
+        let retargeted = xodr::retarget_connections(junction, 1, 9, &[3]);
+        assert!(retargeted.contains(r#"incomingRoad="9" connectingRoad="3""#));
+        assert!(retargeted.contains(r#"incomingRoad="1" connectingRoad="5""#));
+        assert!(retargeted.contains(r#"incomingRoad="11" connectingRoad="3""#));
+
+        let road = r#"<road id="3" junction="7">
+  <link>
+    <predecessor elementType="road" elementId="1" contactPoint="end"/>
+    <successor elementType="road" elementId="1" contactPoint="start"/>
+  </link>
+  <lanes><laneSection s="0"><right><lane id="-1"><link><successor id="-1"/></link></lane></right></laneSection></lanes>
+</road>"#;
+        let relinked = xodr::relink(road, ContactPoint::Start, Some(9));
+        assert!(relinked
+            .contains(r#"<predecessor elementType="road" elementId="9" contactPoint="end"/>"#));
+        assert!(relinked.contains(r#"<successor elementType="road" elementId="1""#));
+        assert!(relinked.contains(r#"<successor id="-1"/>"#));
+        let unlinked = xodr::relink(road, ContactPoint::End, None);
+        assert!(!unlinked.contains("contactPoint=\"start\""));
+        assert!(unlinked.contains(r#"<successor id="-1"/>"#));
+    }
+
+    #[test]
+    fn road_splits_and_joins() {
+        let mut network = load("junction.xodr");
+        let original = network.clone();
+        let snapshot = Snapshot::take(&network, "splitting road 1".to_string(), &[1, 3]).adding(4);
+
+        // Road 1 leads into the junction, so the part after the cut does.
+        assert_eq!(split_road(&mut network, 1, 20.0), Ok(4));
+        assert_near(lane(&network, 4, 1, -1).end_s, 30.0, TOLERANCE);
+        let link = |road_id, contact, other_road_id, other_contact| RoadLink {
+            road_id,
+            contact,
+            other_road_id,
+            other_contact,
+        };
+        assert!(network
+            .links
+            .contains(&link(3, ContactPoint::Start, 4, ContactPoint::End)));
+        assert!(network
+            .links
+            .contains(&link(1, ContactPoint::End, 4, ContactPoint::Start)));
+        assert!(network.junctions[&100]
+            .text()
+            .unwrap()
+            .contains("incomingRoad=\"4\" connectingRoad=\"3\""));
+
+        assert!(join_roads(&mut network, 4, 1).is_err());
+        join_roads(&mut network, 1, 4).unwrap();
+        assert!(!network.roads.contains_key(&4));
+        assert_near(lane(&network, 1, 2, -1).end_s, 50.0, TOLERANCE);
+        assert!(network.junctions[&100]
+            .text()
+            .unwrap()
+            .contains("incomingRoad=\"1\""));
+
+        snapshot.restore(&mut network);
+        let sorted = |network: &RoadNetwork| {
+            let mut segments = network.segments.clone();
+            segments.sort_by_key(|s| (s.road_id, s.lane_section_id, s.lane_id));
+            segments
+        };
+        assert_eq!(sorted(&network), sorted(&original));
+        assert_eq!(network.junctions, original.junctions);
+    }
+}
@@ -0,0 +1,272 @@
+// Split view: the perspective view and a top-down plan side by side.
+//
+// With the split on, the main camera keeps the left half of the window and a
+// second camera looks straight down on the map in the right half, north up,
+// with an orthographic projection. Synchronized, the plan follows the main
+// camera's orbit center and zoom; otherwise it is moved on its own, with the
+// wheel and a middle or left drag over the right half. The viewer loads one
+// map, so both halves show the same one.
+//
+// Keys: F5 splits the view or joins it again, F6 switches the plan between
+// synchronized and independent.
+
+use bevy::input::mouse::MouseWheel;
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::render::camera::{ScalingMode, Viewport};
+use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
+
+use crate::origin::RenderOrigin;
+use crate::overlay_pass::MAP_OVERLAY_LAYER;
+use crate::{camera_orbit, CameraOrbit, MainCamera};
+
+// How high above the map the plan camera sits, and how deep it sees.
+const PLAN_HEIGHT: f32 = 5_000.0;
+const PLAN_DEPTH: f32 = 10_000.0;
+
+// Synchronized, the plan shows this many times the orbit distance.
+const SYNC_EXTENT: f32 = 1.5;
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SplitView {
+    pub enabled: bool,
+    pub synchronized: bool,
+}
+
+impl Default for SplitView {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            synchronized: true,
+        }
+    }
+}
+
+impl SplitView {
+    // Whether the cursor is over the plan, which then takes mouse input.
+    pub fn in_plan(&self, window: &Window) -> bool {
+        self.enabled
+            && window
+                .cursor_position()
+                .is_some_and(|cursor| cursor.x > window.width() / 2.0)
+    }
+}
+
+// The top-down camera: the viewer-frame point in the middle of the plan and
+// the north-south extent shown, in meters.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PlanCamera {
+    pub center: DVec3,
+    pub extent: f32,
+}
+
+// Draws the UI over the whole window, whatever the viewports.
+#[derive(Component)]
+struct UiCamera;
+
+pub struct SplitViewPlugin;
+
+impl Plugin for SplitViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplitView>()
+            .add_systems(Startup, spawn_cameras)
+            .add_systems(
+                Update,
+                (
+                    toggle_split,
+                    plan_input.after(toggle_split),
+                    (layout_viewports, follow_plan).after(camera_orbit),
+                ),
+            );
+    }
+}
+
+fn spawn_cameras(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                order: 3,
+                is_active: false,
+                ..default()
+            },
+            projection: Projection::Orthographic(OrthographicProjection {
+                near: 0.0,
+                far: PLAN_DEPTH,
+                scaling_mode: ScalingMode::FixedVertical(400.0),
+                ..default()
+            }),
+            ..default()
+        },
+        PlanCamera {
+            center: DVec3::ZERO,
+            extent: 400.0,
+        },
+        // The plan draws the overlays along with the map.
+        RenderLayers::from_layers(&[0, MAP_OVERLAY_LAYER]),
+    ));
+    commands.spawn((
+        Camera2dBundle {
+            camera: Camera {
+                order: 10,
+                clear_color: ClearColorConfig::None,
+                ..default()
+            },
+            ..default()
+        },
+        IsDefaultUiCamera,
+        UiCamera,
+    ));
+}
+
+fn toggle_split(
+    keys: Res<ButtonInput<KeyCode>>,
+    origin: Res<RenderOrigin>,
+    mut split: ResMut<SplitView>,
+    orbits: Query<&CameraOrbit, With<MainCamera>>,
+    mut plans: Query<&mut PlanCamera>,
+) {
+    if keys.just_pressed(KeyCode::F5) {
+        split.enabled = !split.enabled;
+        // An independent plan starts where the main view is.
+        if let (Ok(orbit), Ok(mut plan)) = (orbits.get_single(), plans.get_single_mut()) {
+            plan.center = origin.0 + orbit.center.as_dvec3();
+            plan.extent = orbit.distance * SYNC_EXTENT;
+        }
+    }
+    if keys.just_pressed(KeyCode::F6) {
+        split.synchronized = !split.synchronized;
+        info!(
+            "plan view {}",
+            if split.synchronized {
+                "follows the main view"
+            } else {
+                "moves on its own"
+            }
+        );
+    }
+}
+
+// Pans and zooms an independent plan with the mouse over it.
+fn plan_input(
+    split: Res<SplitView>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut plans: Query<&mut PlanCamera>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    let cursor = cursor_moved.read().last().map(|event| event.position);
+    let previous = *last_cursor;
+    *last_cursor = cursor.or(previous);
+    let over_plan = windows
+        .get_single()
+        .is_ok_and(|window| split.in_plan(window));
+    let Ok(mut plan) = plans.get_single_mut() else {
+        return;
+    };
+    if !over_plan || split.synchronized {
+        wheel.clear();
+        return;
+    }
+    for event in wheel.read() {
+        plan.extent = (plan.extent * (1.0 - event.y * 0.1)).clamp(10.0, 20_000.0);
+    }
+    let (Some(cursor), Some(previous)) = (cursor, previous) else {
+        return;
+    };
+    if buttons.any_pressed([MouseButton::Left, MouseButton::Middle]) {
+        let window_height = windows.get_single().map_or(1.0, |w| w.height().max(1.0));
+        let meters_per_pixel = plan.extent / window_height;
+        let delta = (cursor - previous) * meters_per_pixel;
+        // North up: right is +x, down the screen is +z.
+        plan.center -= DVec3::new(f64::from(delta.x), 0.0, f64::from(delta.y));
+    }
+}
+
+// Gives the main camera (and the cameras that draw along with it) the left
+// half of the window and the plan the right half while split.
+fn layout_viewports(
+    split: Res<SplitView>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut mains: Query<(&mut Camera, Option<&Children>), With<MainCamera>>,
+    mut plans: Query<&mut Camera, (With<PlanCamera>, Without<MainCamera>)>,
+    mut others: Query<&mut Camera, (Without<PlanCamera>, Without<MainCamera>)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let size = UVec2::new(window.physical_width(), window.physical_height());
+    let half = UVec2::new((size.x / 2).max(1), size.y.max(1));
+    let (left, right) = if split.enabled {
+        (
+            Some(Viewport {
+                physical_position: UVec2::ZERO,
+                physical_size: half,
+                ..default()
+            }),
+            Some(Viewport {
+                physical_position: UVec2::new(size.x / 2, 0),
+                physical_size: UVec2::new(size.x - size.x / 2, size.y).max(UVec2::ONE),
+                ..default()
+            }),
+        )
+    } else {
+        (None, None)
+    };
+
+    for (mut camera, children) in &mut mains {
+        if bounds(&camera.viewport) != bounds(&left) {
+            camera.viewport.clone_from(&left);
+        }
+        for child in children.into_iter().flatten() {
+            if let Ok(mut camera) = others.get_mut(*child) {
+                if bounds(&camera.viewport) != bounds(&left) {
+                    camera.viewport.clone_from(&left);
+                }
+            }
+        }
+    }
+    for mut camera in &mut plans {
+        if camera.is_active != split.enabled {
+            camera.is_active = split.enabled;
+        }
+        if bounds(&camera.viewport) != bounds(&right) {
+            camera.viewport.clone_from(&right);
+        }
+    }
+}
+
+// Places the plan camera above its center, following the main view when
+// synchronized.
+fn follow_plan(
+    split: Res<SplitView>,
+    origin: Res<RenderOrigin>,
+    orbits: Query<&CameraOrbit, With<MainCamera>>,
+    mut plans: Query<(&mut PlanCamera, &mut Transform, &mut Projection)>,
+) {
+    if !split.enabled {
+        return;
+    }
+    for (mut plan, mut transform, mut projection) in &mut plans {
+        if split.synchronized {
+            if let Ok(orbit) = orbits.get_single() {
+                plan.center = origin.0 + orbit.center.as_dvec3();
+                plan.extent = orbit.distance * SYNC_EXTENT;
+            }
+        }
+        let above = origin.to_render(plan.center) + Vec3::Y * PLAN_HEIGHT;
+        *transform = Transform::from_translation(above).looking_to(Vec3::NEG_Y, Vec3::NEG_Z);
+        if let Projection::Orthographic(ortho) = projection.as_mut() {
+            ortho.scaling_mode = ScalingMode::FixedVertical(plan.extent);
+        }
+    }
+}
+
+// Viewports do not compare; their placement does.
+fn bounds(viewport: &Option<Viewport>) -> Option<(UVec2, UVec2)> {
+    viewport
+        .as_ref()
+        .map(|viewport| (viewport.physical_position, viewport.physical_size))
+}
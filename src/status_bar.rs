@@ -0,0 +1,250 @@
+// Status bar.
+//
+// A line along the bottom of the window describes the point under the
+// cursor: its position, the lane it lies on with its station s and its
+// offset t from the road's reference line, and its latitude and longitude
+// when the map is georeferenced. Where the cursor is over no lane, the
+// position is taken on the ground plane and the nearest lane within
+// `NEAREST_DISTANCE` is named instead. Positions and stations follow the
+// display settings (see `display`).
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::display::DisplaySettings;
+use crate::i18n::Locale;
+use crate::origin::RenderOrigin;
+use crate::selection::{lateral_offset, pick};
+use crate::{MainCamera, RoadNetwork};
+
+// Lanes further than this from the point under the cursor, in meters, are
+// not named.
+const NEAREST_DISTANCE: f64 = 50.0;
+
+const SEPARATOR: &str = "   |   ";
+
+#[derive(Component)]
+struct StatusText;
+
+pub struct StatusBarPlugin;
+
+impl Plugin for StatusBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_bar)
+            .add_systems(Update, update_bar);
+    }
+}
+
+// Describes the point a viewer-frame ray points at, or None if it points at
+// neither a lane nor the ground.
+pub fn describe(
+    network: &RoadNetwork,
+    display: &DisplaySettings,
+    locale: &Locale,
+    origin: DVec3,
+    direction: DVec3,
+) -> Option<String> {
+    let mut parts = Vec::new();
+    let position = match pick(network, origin, direction) {
+        Some(hit) => {
+            parts.push(locale.text(
+                "status-lane",
+                &[
+                    ("road", &hit.road_id),
+                    ("lane", &hit.lane_id),
+                    ("s", &display.station(hit.s)),
+                    ("t", &display.station(hit.t.unwrap_or_default())),
+                ],
+            ));
+            hit.position
+        }
+        None => {
+            // The ground plane, y = 0 in the viewer frame.
+            if direction.y >= 0.0 {
+                return None;
+            }
+            let ground = origin - direction * (origin.y / direction.y);
+            let nearest = network
+                .nearest_lane(DVec2::new(ground.x, -ground.z), None, NEAREST_DISTANCE)
+                .into_iter()
+                .next();
+            parts.push(match nearest {
+                Some(lane) => {
+                    let segment = &network.segments[lane.segment];
+                    let t = lateral_offset(network, segment.road_id, lane.s, ground);
+                    locale.text(
+                        "status-nearest-lane",
+                        &[
+                            ("road", &segment.road_id),
+                            ("lane", &segment.lane_id),
+                            ("s", &display.station(lane.s)),
+                            ("t", &display.station(t.unwrap_or_default())),
+                            ("distance", &display.station(lane.lateral.abs())),
+                        ],
+                    )
+                }
+                None => locale.text("status-no-lane", &[]),
+            });
+            ground
+        }
+    };
+    parts.insert(0, display.position(network, position));
+    // Geographic positions are shown first already.
+    if !display.geographic {
+        parts.extend(display.geographic_position(network, position));
+    }
+    Some(parts.join(SEPARATOR))
+}
+
+fn spawn_bar(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(0.0),
+                right: Val::Px(0.0),
+                bottom: Val::Px(0.0),
+                padding: UiRect::axes(Val::Px(10.0), Val::Px(3.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            ..default()
+        })
+        .with_children(|bar| {
+            bar.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 13.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                StatusText,
+            ));
+        });
+}
+
+// Casting a ray at every lane is not free, so the text is only worked out
+// again when the cursor, the camera, the map or the settings change.
+#[allow(clippy::too_many_arguments)]
+fn update_bar(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, Ref<GlobalTransform>), With<MainCamera>>,
+    origin: Res<RenderOrigin>,
+    network: Res<RoadNetwork>,
+    display: Res<DisplaySettings>,
+    locale: Res<Locale>,
+    mut texts: Query<&mut Text, With<StatusText>>,
+    mut last_cursor: Local<Option<Vec2>>,
+) {
+    let cursor = windows.get_single().ok().and_then(|w| w.cursor_position());
+    let Ok((camera, transform)) = cameras.get_single() else {
+        return;
+    };
+    if cursor == *last_cursor
+        && !transform.is_changed()
+        && !network.is_changed()
+        && !display.is_changed()
+    {
+        return;
+    }
+    *last_cursor = cursor;
+    let ray = cursor.and_then(|cursor| {
+        let viewport = camera.logical_viewport_rect()?;
+        if !viewport.contains(cursor) {
+            return None;
+        }
+        camera.viewport_to_world(&transform, cursor - viewport.min)
+    });
+    let status = ray
+        .and_then(|ray| {
+            describe(
+                &network,
+                &display,
+                &locale,
+                origin.0 + ray.origin.as_dvec3(),
+                ray.direction.as_dvec3(),
+            )
+        })
+        .unwrap_or_default();
+    for mut text in &mut texts {
+        if text.sections[0].value != status {
+            text.sections[0].value.clone_from(&status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::GeoReference;
+    use crate::i18n::Locale;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn the_point_under_the_cursor_is_described() {
+        let network = load("straight.xodr");
+        let display = DisplaySettings::default();
+        let locale = Locale::default();
+        let down = DVec3::NEG_Y;
+        // Over the first right lane, which runs south of the reference line.
+        assert_eq!(
+            describe(
+                &network,
+                &display,
+                &locale,
+                DVec3::new(30.0, 10.0, 1.75),
+                down
+            )
+            .unwrap(),
+            "30.00, -1.75, 0.00   |   road 1 lane -1, s 30.00 m, t -1.75 m"
+        );
+        // Off the road, the ground is named with the nearest lane, the sidewalk.
+        assert_eq!(
+            describe(
+                &network,
+                &display,
+                &locale,
+                DVec3::new(30.0, 10.0, 20.0),
+                down
+            )
+            .unwrap(),
+            "30.00, -20.00, 0.00   |   nearest road 1 lane -2, s 30.00 m, t -20.00 m, \
+             15.00 m from its center"
+        );
+        assert_eq!(
+            describe(
+                &network,
+                &display,
+                &locale,
+                DVec3::new(30.0, 10.0, 200.0),
+                down
+            )
+            .unwrap(),
+            "30.00, -200.00, 0.00   |   no lane nearby"
+        );
+        assert_eq!(
+            describe(&network, &display, &locale, DVec3::ZERO, DVec3::Y),
+            None
+        );
+
+        // Georeferenced maps add latitude and longitude.
+        let mut network = network;
+        network.geo = Some(GeoReference::parse("+proj=tmerc +lat_0=49 +lon_0=8").unwrap());
+        let status = describe(
+            &network,
+            &display,
+            &locale,
+            DVec3::new(0.0, 10.0, 1.0),
+            down,
+        )
+        .unwrap();
+        // A meter south of the origin is 1e-5° less latitude, give or take.
+        assert!(
+            status.ends_with("   |   48.9999910°, 8.0000000°"),
+            "{status}"
+        );
+    }
+}
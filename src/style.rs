@@ -0,0 +1,368 @@
+// Rule-based map styles.
+//
+// A style sheet is a JSON file of rules, each a selector and the look of
+// the elements it selects, so that a team can review maps in its own
+// colors:
+//
+//   { "rules": [
+//     { "lane_type": "shoulder", "visible": false },
+//     { "road_type": "motorway", "speed": [100, 130], "color": "#3050a0" },
+//     { "lane_type": "sidewalk", "color": "#b4a48c", "roughness": 0.9 },
+//     { "object": "tree", "color": "#30a040" }
+//   ] }
+//
+// A lane selector may name a lane type, a road type and a speed range in
+// km/h, and a lane must match all it names; lanes without a speed limit are
+// outside every range, and a rule naming nothing selects every lane. An
+// `object` selector selects the signals and objects whose type or name it
+// gives, ignoring case. The first rule selecting an element decides its
+// look: lanes get their surface in the rule's color and roughness instead
+// of the theme's, or are left out of the meshes like filtered lanes (see
+// `tiles`); signals and objects get their icon tinted, or are not shown.
+// Types are matched as the map gives them, so road types are camel case as
+// in OpenDRIVE (e.g. "townExpressway").
+//
+// A sheet is given to `view --style`, or loaded while the viewer runs with
+// `style <file>` in the console; `style clear` goes back to the theme.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::i18n::Locale;
+use crate::json::{self, Json};
+use crate::legend::{FillLegend, Legend};
+use crate::signals::Signal;
+use crate::theme::{LaneClass, Theme};
+use crate::tiles::ReloadTiles;
+use crate::units::KMH;
+use crate::weather::Weather;
+use crate::{RoadLayer, RoadSegment};
+
+// What a rule selects.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selector {
+    pub lane_type: Option<String>,
+    pub road_type: Option<String>,
+    // Inclusive, in km/h.
+    pub speed: Option<(f64, f64)>,
+    // Selects signals and objects instead of lanes.
+    pub object: Option<String>,
+}
+
+impl Selector {
+    fn selects_lane(&self, lane: &RoadSegment) -> bool {
+        self.object.is_none()
+            && self
+                .lane_type
+                .as_ref()
+                .is_none_or(|kind| *kind == lane.lane_type)
+            && self
+                .road_type
+                .as_ref()
+                .is_none_or(|kind| *kind == lane.road_type)
+            && self.speed.is_none_or(|(min, max)| {
+                lane.speed
+                    .is_some_and(|speed| (min..=max).contains(&(speed / KMH)))
+            })
+    }
+
+    // What the selector selects, in words.
+    fn describe(&self) -> String {
+        let mut what = Vec::new();
+        if let Some(object) = &self.object {
+            what.push(format!("object {object}"));
+        }
+        if let Some(kind) = &self.lane_type {
+            what.push(format!("{kind} lanes"));
+        }
+        if let Some(kind) = &self.road_type {
+            what.push(format!("{kind} roads"));
+        }
+        if let Some((min, max)) = self.speed {
+            what.push(format!("{min}-{max} km/h"));
+        }
+        if what.is_empty() {
+            what.push("all lanes".to_string());
+        }
+        what.join(", ")
+    }
+
+    fn selects_signal(&self, signal: &Signal) -> bool {
+        self.object.as_ref().is_some_and(|name| {
+            signal.type_code.eq_ignore_ascii_case(name) || signal.name.eq_ignore_ascii_case(name)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleRule {
+    pub selector: Selector,
+    // Left as the theme has it where not given.
+    pub color: Option<Color>,
+    pub roughness: Option<f32>,
+    pub visible: bool,
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct StyleSheet {
+    pub rules: Vec<StyleRule>,
+}
+
+impl StyleSheet {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json = json::parse(text)?;
+        let rules = json
+            .get("rules")
+            .and_then(Json::as_array)
+            .ok_or("expected an object with a `rules` array")?;
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| parse_rule(rule).map_err(|e| format!("rule {}: {e}", i + 1)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    // The index of the rule styling a lane, if any.
+    pub fn lane_rule(&self, lane: &RoadSegment) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|rule| rule.selector.selects_lane(lane))
+    }
+
+    // The index of the rule styling a signal or object, if any.
+    pub fn signal_rule(&self, signal: &Signal) -> Option<usize> {
+        self.rules
+            .iter()
+            .position(|rule| rule.selector.selects_signal(signal))
+    }
+
+    pub fn shows_lane(&self, lane: &RoadSegment) -> bool {
+        self.lane_rule(lane)
+            .is_none_or(|rule| self.rules[rule].visible)
+    }
+
+    pub fn shows_signal(&self, signal: &Signal) -> bool {
+        self.signal_rule(signal)
+            .is_none_or(|rule| self.rules[rule].visible)
+    }
+
+    // Whether any lanes are styled, which keeps their tiles out of the mesh
+    // cache.
+    pub fn styles_lanes(&self) -> bool {
+        self.rules.iter().any(|rule| rule.selector.object.is_none())
+    }
+
+    fn describe(&self) -> String {
+        if self.rules.is_empty() {
+            return "no style sheet".to_string();
+        }
+        let mut out = String::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let look = if !rule.visible {
+                "hidden".to_string()
+            } else {
+                let mut look = Vec::new();
+                if let Some(color) = rule.color {
+                    let [r, g, b, _] = color.as_rgba_u8();
+                    look.push(format!("#{r:02x}{g:02x}{b:02x}"));
+                }
+                if let Some(roughness) = rule.roughness {
+                    look.push(format!("roughness {roughness}"));
+                }
+                look.join(", ")
+            };
+            let _ = writeln!(out, "{}. {}: {look}", i + 1, rule.selector.describe());
+        }
+        out.trim_end().to_string()
+    }
+}
+
+fn parse_rule(rule: &Json) -> Result<StyleRule, String> {
+    let Json::Object(fields) = rule else {
+        return Err("expected an object".to_string());
+    };
+    let text = |value: &Json, key: &str| {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("`{key}` must be a string"))
+    };
+    let mut selector = Selector::default();
+    let mut style = StyleRule {
+        selector: Selector::default(),
+        color: None,
+        roughness: None,
+        visible: true,
+    };
+    for (key, value) in fields {
+        match key.as_str() {
+            "lane_type" => selector.lane_type = Some(text(value, key)?),
+            "road_type" => selector.road_type = Some(text(value, key)?),
+            "object" => selector.object = Some(text(value, key)?),
+            "speed" => {
+                let range = value.as_array().unwrap_or_default();
+                let [min, max] = range else {
+                    return Err("`speed` must be [min, max] in km/h".to_string());
+                };
+                let (Some(min), Some(max)) = (min.as_f64(), max.as_f64()) else {
+                    return Err("`speed` must be [min, max] in km/h".to_string());
+                };
+                selector.speed = Some((min, max));
+            }
+            "color" => {
+                let color = text(value, key)?;
+                style.color =
+                    Some(Color::hex(&color).map_err(|_| format!("`{color}` is not a color"))?);
+            }
+            "roughness" => {
+                let roughness = value
+                    .as_f64()
+                    .filter(|r| (0.0..=1.0).contains(r))
+                    .ok_or("`roughness` must be a number from 0 to 1")?;
+                style.roughness = Some(roughness as f32);
+            }
+            "visible" => {
+                let Json::Bool(visible) = value else {
+                    return Err("`visible` must be true or false".to_string());
+                };
+                style.visible = *visible;
+            }
+            other => return Err(format!("unknown key `{other}`")),
+        }
+    }
+    if selector.object.is_some()
+        && (selector.lane_type.is_some()
+            || selector.road_type.is_some()
+            || selector.speed.is_some())
+    {
+        return Err("a rule selects either objects or lanes".to_string());
+    }
+    style.selector = selector;
+    Ok(style)
+}
+
+// The surface materials of styled lanes, one per rule.
+#[derive(Resource, Debug, Default)]
+pub struct StyleMaterials(HashMap<usize, Handle<StandardMaterial>>);
+
+impl StyleMaterials {
+    pub fn get(
+        &mut self,
+        materials: &mut Assets<StandardMaterial>,
+        sheet: &StyleSheet,
+        theme: &Theme,
+        weather: Weather,
+        rule: usize,
+    ) -> Handle<StandardMaterial> {
+        self.0
+            .entry(rule)
+            .or_insert_with(|| materials.add(surface_material(&sheet.rules[rule], theme, weather)))
+            .clone()
+    }
+}
+
+fn surface_material(rule: &StyleRule, theme: &Theme, weather: Weather) -> StandardMaterial {
+    let mut material = RoadLayer::Surface(LaneClass::Driving).material(theme);
+    if let Some(color) = rule.color {
+        material.base_color = color;
+    }
+    weather.adjust(RoadLayer::Surface(LaneClass::Driving), &mut material);
+    if let Some(roughness) = rule.roughness {
+        material.perceptual_roughness = roughness;
+    }
+    material
+}
+
+// `style` in the console: describes the sheet, loads one or drops it.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => return Ok(world.resource::<StyleSheet>().describe()),
+        ["clear"] => *world.resource_mut::<StyleSheet>() = StyleSheet::default(),
+        [path] => *world.resource_mut::<StyleSheet>() = StyleSheet::load(Path::new(path))?,
+        _ => return Err("expected a style sheet file or `clear`".to_string()),
+    }
+    Ok(String::new())
+}
+
+pub struct StylePlugin;
+
+impl Plugin for StylePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StyleSheet>()
+            .init_resource::<StyleMaterials>()
+            .add_systems(Update, (restyle, add_legend.in_set(FillLegend)));
+    }
+}
+
+// The colors of the rules, in order; the first rule selecting an element
+// decides, so a later rule's color may not show where an earlier one's does.
+fn add_legend(sheet: Res<StyleSheet>, locale: Res<Locale>, mut legend: ResMut<Legend>) {
+    let rows = sheet
+        .rules
+        .iter()
+        .filter(|rule| rule.visible)
+        .filter_map(|rule| Some((rule.color?, rule.selector.describe())))
+        .collect();
+    legend.add(locale.text("legend-style", &[]), rows);
+}
+
+// Drops the materials made for a previous sheet, theme or weather and
+// rebuilds the road tiles with the new ones.
+fn restyle(
+    sheet: Res<StyleSheet>,
+    theme: Res<Theme>,
+    weather: Res<Weather>,
+    mut styled: ResMut<StyleMaterials>,
+    mut reload: EventWriter<ReloadTiles>,
+) {
+    if sheet.is_added() || !(sheet.is_changed() || theme.is_changed() || weather.is_changed()) {
+        return;
+    }
+    styled.0.clear();
+    if sheet.is_changed() || sheet.styles_lanes() {
+        reload.send(ReloadTiles);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{lane, load};
+
+    #[test]
+    fn rules_need_a_selector_and_a_look() {
+        assert!(StyleSheet::parse(r#"{ "rules": [{ "speed": 30 }] }"#).is_err());
+        assert!(
+            StyleSheet::parse(r#"{ "rules": [{ "object": "tree", "lane_type": "x" }] }"#).is_err()
+        );
+    }
+
+    #[test]
+    fn style_rules() {
+        let network = load("straight.xodr");
+        let style = StyleSheet::parse(
+            r##"{ "rules": [
+                { "lane_type": "sidewalk", "visible": false },
+                { "road_type": "town", "speed": [30, 50], "color": "#3050a0" }
+            ] }"##,
+        )
+        .unwrap();
+        // The first rule selecting a lane decides.
+        let sidewalk = lane(&network, 1, 1, -2);
+        assert_eq!(style.lane_rule(sidewalk), Some(0));
+        assert!(!style.shows_lane(sidewalk));
+        let driving = lane(&network, 1, 1, -1);
+        assert_eq!(style.lane_rule(driving), Some(1));
+        assert!(style.shows_lane(driving));
+        assert!(style.styles_lanes());
+    }
+}
@@ -0,0 +1,518 @@
+// Export of the road network to SUMO's network format (.net.xml).
+//
+// Each lane section becomes up to two SUMO edges, one per direction of
+// travel: `<road>_<section>` along the reference line and
+// `-<road>_<section>` against it, holding the section's lanes from the
+// rightmost (index 0) to the leftmost. Lanes SUMO has no use for (borders,
+// shoulders, medians and the like) are left out; sidewalks, bike lanes and
+// bus lanes are restricted to their road users with `allow`.
+//
+// Connecting roads do not become edges. Each OpenDRIVE junction becomes a
+// SUMO junction, and each way through one a `<connection>` from the lane
+// entering a connecting lane to the lane it leads to, in the state the
+// right-of-way inference (see `priority`) gives it. Other nodes are where
+// lanes of different edges meet, found through the lane ends, and dead ends.
+// No internal lanes are written, so SUMO drives straight across junctions.
+//
+// The reader keeps no `<controller>` elements, so every junction with a
+// traffic light gets a generated fixed-time program that gives each
+// approach green in turn.
+//
+// SUMO's frame is the OpenDRIVE one (x east, y north), without an offset.
+
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::io;
+use std::path::Path;
+
+use bevy::math::{DVec3, IVec3};
+
+use crate::conflicts::{self, END_TOLERANCE};
+use crate::priority::{self, Rule};
+use crate::route_export::to_odr;
+use crate::routing::{DEFAULT_SPEED, DRIVING_TYPES};
+use crate::units::KMH;
+use crate::xodr::escape;
+use crate::{RoadNetwork, RoadSegment};
+
+// Turning by more than this, in radians, is a left or right turn, and by
+// more than `TURN_AROUND` a U-turn.
+const TURN: f64 = 30.0 * PI / 180.0;
+const TURN_AROUND: f64 = 150.0 * PI / 180.0;
+
+// Phase durations of generated traffic light programs, in seconds.
+const GREEN_TIME: u32 = 30;
+const YELLOW_TIME: u32 = 3;
+
+// Writes the network as a SUMO network to `path`.
+pub fn write_map(network: &RoadNetwork, path: &Path) -> io::Result<()> {
+    std::fs::write(path, to_xml(network))
+}
+
+// The SUMO vehicle class a lane type is restricted to: `Some(None)` for
+// general traffic, `None` for lanes not exported.
+fn vehicle_class(lane: &RoadSegment) -> Option<Option<&'static str>> {
+    match lane.lane_type.as_str() {
+        t if DRIVING_TYPES.contains(&t) || t == "HOV" => Some(None),
+        "sidewalk" | "walking" => Some(Some("pedestrian")),
+        "biking" => Some(Some("bicycle")),
+        "bus" => Some(Some("bus")),
+        "taxi" => Some(Some("taxi")),
+        "tram" => Some(Some("tram")),
+        _ => None,
+    }
+}
+
+fn junction_of(network: &RoadNetwork, lane: &RoadSegment) -> Option<u32> {
+    network
+        .roads
+        .get(&lane.road_id)
+        .and_then(|info| info.junction)
+}
+
+// A SUMO edge: the lanes of one lane section going one way.
+struct Edge {
+    id: String,
+    // Indices into `RoadNetwork::segments`, rightmost first.
+    lanes: Vec<usize>,
+}
+
+// A way from one lane to another, through a junction's connecting lane
+// (`via`) or directly.
+struct Connection {
+    from: usize,
+    to: usize,
+    via: Option<usize>,
+}
+
+// Sets of edge ends that are the same node.
+struct Nodes(Vec<usize>);
+
+impl Nodes {
+    fn find(&mut self, mut a: usize) -> usize {
+        while self.0[a] != a {
+            self.0[a] = self.0[self.0[a]];
+            a = self.0[a];
+        }
+        a
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0[a.max(b)] = a.min(b);
+    }
+}
+
+// The lanes each lane leads to in its direction of travel, found through a
+// grid of travel starts.
+fn next_lanes(paths: &[Vec<DVec3>]) -> Vec<Vec<usize>> {
+    let cell = |p: DVec3| (p / END_TOLERANCE).floor().as_ivec3();
+    let mut starts: HashMap<IVec3, Vec<usize>> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        if let Some(&start) = path.first() {
+            starts.entry(cell(start)).or_default().push(index);
+        }
+    }
+    paths
+        .iter()
+        .enumerate()
+        .map(|(from, path)| {
+            let Some(&end) = path.last() else {
+                return Vec::new();
+            };
+            let mut next = Vec::new();
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        let around = cell(end) + IVec3::new(dx, dy, dz);
+                        next.extend(starts.get(&around).into_iter().flatten().filter(|&&to| {
+                            to != from && paths[to][0].distance(end) < END_TOLERANCE
+                        }));
+                    }
+                }
+            }
+            next
+        })
+        .collect()
+}
+
+// The SUMO direction of a turn from one path into another.
+fn direction(before: &[DVec3], after: &[DVec3]) -> &'static str {
+    let heading = |a: DVec3, b: DVec3| {
+        let (a, b) = (to_odr(a), to_odr(b));
+        (b.y - a.y).atan2(b.x - a.x)
+    };
+    let (Some(in_end), Some(out_start)) = (before.len().checked_sub(2), after.get(1)) else {
+        return "s";
+    };
+    let mut turn = heading(after[0], *out_start) - heading(before[in_end], before[in_end + 1]);
+    while turn > PI {
+        turn -= 2.0 * PI;
+    }
+    while turn <= -PI {
+        turn += 2.0 * PI;
+    }
+    match turn {
+        t if t.abs() > TURN_AROUND => "t",
+        t if t > TURN => "l",
+        t if t < -TURN => "r",
+        _ => "s",
+    }
+}
+
+fn shape(points: &[DVec3]) -> String {
+    points
+        .iter()
+        .map(|p| {
+            let p = to_odr(*p);
+            format!("{:.2},{:.2}", p.x, p.y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Renders the network as a SUMO `<net>` document.
+pub fn to_xml(network: &RoadNetwork) -> String {
+    let paths: Vec<Vec<DVec3>> = network.segments.iter().map(conflicts::path).collect();
+    let next = next_lanes(&paths);
+
+    // Edges, by lane section and direction.
+    let mut grouped: BTreeMap<(u32, u32, bool), Vec<usize>> = BTreeMap::new();
+    for (index, lane) in network.segments.iter().enumerate() {
+        if junction_of(network, lane).is_none() && vehicle_class(lane).is_some() {
+            let key = (lane.road_id, lane.lane_section_id, lane.follows_reference());
+            grouped.entry(key).or_default().push(index);
+        }
+    }
+    let mut edge_of: HashMap<usize, usize> = HashMap::new();
+    let edges: Vec<Edge> = grouped
+        .into_iter()
+        .enumerate()
+        .map(|(edge, ((road, section, forward), mut lanes))| {
+            // Right to left in the direction of travel.
+            lanes.sort_by_key(|&i| network.segments[i].lane_id);
+            if !forward {
+                lanes.reverse();
+            }
+            for &lane in &lanes {
+                edge_of.insert(lane, edge);
+            }
+            let sign = if forward { "" } else { "-" };
+            Edge {
+                id: format!("{sign}{road}_{section}"),
+                lanes,
+            }
+        })
+        .collect();
+
+    // Connections, through junctions where the lane leads into one.
+    let mut connections: Vec<Connection> = Vec::new();
+    let mut exported: Vec<usize> = edge_of.keys().copied().collect();
+    exported.sort_unstable();
+    for from in exported {
+        for &after in &next[from] {
+            if junction_of(network, &network.segments[after]).is_none() {
+                if edge_of.get(&after).is_some_and(|&e| e != edge_of[&from]) {
+                    connections.push(Connection {
+                        from,
+                        to: after,
+                        via: None,
+                    });
+                }
+                continue;
+            }
+            // Along connecting lanes to the first lane outside the junction.
+            let mut stack = vec![after];
+            let mut seen = vec![after];
+            while let Some(lane) = stack.pop() {
+                for &to in &next[lane] {
+                    if junction_of(network, &network.segments[to]).is_some() {
+                        if !seen.contains(&to) {
+                            seen.push(to);
+                            stack.push(to);
+                        }
+                    } else if edge_of.contains_key(&to)
+                        && !connections.iter().any(|c| c.from == from && c.to == to)
+                    {
+                        connections.push(Connection {
+                            from,
+                            to,
+                            via: Some(after),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Nodes: each edge has a start (2e) and an end (2e + 1), and each
+    // junction one more, after them.
+    let junction_ids: Vec<u32> = network.junctions.keys().copied().collect();
+    let junction_node =
+        |j: u32| 2 * edges.len() + junction_ids.iter().position(|&id| id == j).unwrap_or(0);
+    let mut nodes = Nodes((0..2 * edges.len() + junction_ids.len()).collect());
+    for connection in &connections {
+        let (out_end, in_start) = (
+            2 * edge_of[&connection.from] + 1,
+            2 * edge_of[&connection.to],
+        );
+        match connection
+            .via
+            .and_then(|via| junction_of(network, &network.segments[via]))
+        {
+            Some(j) if junction_ids.contains(&j) => {
+                nodes.union(out_end, junction_node(j));
+                nodes.union(in_start, junction_node(j));
+            }
+            _ => nodes.union(out_end, in_start),
+        }
+    }
+    let mut node_names: BTreeMap<usize, String> = BTreeMap::new();
+    for &j in &junction_ids {
+        let root = nodes.find(junction_node(j));
+        node_names.entry(root).or_insert_with(|| format!("J{j}"));
+    }
+    let mut positions: BTreeMap<usize, (DVec3, f64)> = BTreeMap::new();
+    let mut plain = 0;
+    for (e, edge) in edges.iter().enumerate() {
+        for (end, last) in [(2 * e, false), (2 * e + 1, true)] {
+            let root = nodes.find(end);
+            node_names.entry(root).or_insert_with(|| {
+                plain += 1;
+                format!("n{plain}")
+            });
+            let sum = positions.entry(root).or_default();
+            for &lane in &edge.lanes {
+                let point = if last {
+                    paths[lane].last()
+                } else {
+                    paths[lane].first()
+                };
+                if let Some(point) = point {
+                    sum.0 += *point;
+                    sum.1 += 1.0;
+                }
+            }
+        }
+    }
+
+    // Rules of the ways through junctions, by connecting lane.
+    let rules: HashMap<usize, Rule> = junction_ids
+        .iter()
+        .flat_map(|&j| priority::movements(network, j))
+        .map(|movement| (movement.path, movement.rule))
+        .collect();
+    let junction_rules = |j: u32| -> Vec<Rule> {
+        conflicts::paths(network, j)
+            .iter()
+            .filter_map(|path| rules.get(path).copied())
+            .collect()
+    };
+    let controlled = |j: u32| junction_rules(j).contains(&Rule::Signalized);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<net version=\"1.16\" junctionCornerDetail=\"5\" limitTurnSpeed=\"5.50\">\n");
+    let points = paths.iter().flatten().map(|p| to_odr(*p));
+    let (min, max) = points.fold(
+        (DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)),
+        |(min, max), p| (min.min(p), max.max(p)),
+    );
+    let (min, max) = if min.x <= max.x {
+        (min, max)
+    } else {
+        (DVec3::ZERO, DVec3::ZERO)
+    };
+    let _ = writeln!(
+        xml,
+        "    <location netOffset=\"0.00,0.00\" convBoundary=\"{:.2},{:.2},{:.2},{:.2}\" \
+         origBoundary=\"{:.2},{:.2},{:.2},{:.2}\" projParameter=\"!\"/>",
+        min.x, min.y, max.x, max.y, min.x, min.y, max.x, max.y
+    );
+
+    for (e, edge) in edges.iter().enumerate() {
+        let from = &node_names[&nodes.find(2 * e)];
+        let to = &node_names[&nodes.find(2 * e + 1)];
+        let _ = writeln!(
+            xml,
+            "    <edge id=\"{}\" from=\"{}\" to=\"{}\" priority=\"-1\">",
+            edge.id,
+            escape(from),
+            escape(to)
+        );
+        for (index, &lane) in edge.lanes.iter().enumerate() {
+            let segment = &network.segments[lane];
+            let length: f64 = paths[lane].windows(2).map(|w| w[0].distance(w[1])).sum();
+            let speed = segment.speed.unwrap_or(DEFAULT_SPEED * KMH);
+            let allow = match vehicle_class(segment).flatten() {
+                Some(class) => format!(" allow=\"{class}\""),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                xml,
+                "        <lane id=\"{}_{index}\" index=\"{index}\" speed=\"{speed:.2}\" \
+                 length=\"{length:.2}\" width=\"{:.2}\"{allow} shape=\"{}\"/>",
+                edge.id,
+                segment.width,
+                shape(&paths[lane])
+            );
+        }
+        xml.push_str("    </edge>\n");
+    }
+
+    // The connections each node controls, in order, for link indices and
+    // traffic light programs.
+    let lane_name = |lane: usize| {
+        let edge = edge_of[&lane];
+        let index = edges[edge]
+            .lanes
+            .iter()
+            .position(|&l| l == lane)
+            .unwrap_or(0);
+        (edge, index)
+    };
+    let node_of_connection =
+        |nodes: &mut Nodes, c: &Connection| nodes.find(2 * edge_of[&c.from] + 1);
+    let mut by_node: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (k, connection) in connections.iter().enumerate() {
+        by_node
+            .entry(node_of_connection(&mut nodes, connection))
+            .or_default()
+            .push(k);
+    }
+    let junction_at = |root: usize| -> Option<u32> {
+        node_names[&root]
+            .strip_prefix('J')
+            .and_then(|id| id.parse().ok())
+    };
+
+    for (&root, links) in &by_node {
+        let Some(j) = junction_at(root).filter(|&j| controlled(j)) else {
+            continue;
+        };
+        // Each approach green in turn, then yellow.
+        let mut approaches: Vec<usize> = links
+            .iter()
+            .map(|&k| edge_of[&connections[k].from])
+            .collect();
+        approaches.sort_unstable();
+        approaches.dedup();
+        let _ = writeln!(
+            xml,
+            "    <tlLogic id=\"J{j}\" type=\"static\" programID=\"0\" offset=\"0\">"
+        );
+        for approach in approaches {
+            for (light, duration) in [('G', GREEN_TIME), ('y', YELLOW_TIME)] {
+                let state: String = links
+                    .iter()
+                    .map(|&k| {
+                        if edge_of[&connections[k].from] == approach {
+                            light
+                        } else {
+                            'r'
+                        }
+                    })
+                    .collect();
+                let _ = writeln!(
+                    xml,
+                    "        <phase duration=\"{duration}\" state=\"{state}\"/>"
+                );
+            }
+        }
+        xml.push_str("    </tlLogic>\n");
+    }
+
+    for (&root, name) in &node_names {
+        let incoming: Vec<String> = (0..edges.len())
+            .filter(|&e| nodes.find(2 * e + 1) == root)
+            .flat_map(|e| (0..edges[e].lanes.len()).map(move |i| (e, i)))
+            .map(|(e, i)| format!("{}_{i}", edges[e].id))
+            .collect();
+        let outgoing = (0..edges.len()).any(|e| nodes.find(2 * e) == root);
+        let kind = match junction_at(root) {
+            Some(j) if controlled(j) => "traffic_light",
+            Some(j) if junction_rules(j).iter().all(|&rule| rule == Rule::Unknown) => {
+                "right_before_left"
+            }
+            _ if incoming.is_empty() || !outgoing => "dead_end",
+            _ => "priority",
+        };
+        let (sum, count) = positions.get(&root).copied().unwrap_or_default();
+        let p = to_odr(sum / count.max(1.0));
+        let _ = writeln!(
+            xml,
+            "    <junction id=\"{}\" type=\"{kind}\" x=\"{:.2}\" y=\"{:.2}\" \
+             incLanes=\"{}\" intLanes=\"\"/>",
+            escape(name),
+            p.x,
+            p.y,
+            incoming.join(" ")
+        );
+    }
+
+    for (&root, links) in &by_node {
+        let light = junction_at(root).filter(|&j| controlled(j));
+        for (link, &k) in links.iter().enumerate() {
+            let connection = &connections[k];
+            let (from, from_lane) = lane_name(connection.from);
+            let (to, to_lane) = lane_name(connection.to);
+            let (before, after) = (&paths[connection.from], &paths[connection.to]);
+            let state = match connection.via.and_then(|via| rules.get(&via)) {
+                Some(Rule::Priority) | None => "M",
+                Some(Rule::Yield) => "m",
+                Some(Rule::Stop) => "s",
+                Some(Rule::Signalized) => "O",
+                Some(Rule::Unknown) => "=",
+            };
+            let tl = match light {
+                Some(j) => format!(" tl=\"J{j}\" linkIndex=\"{link}\""),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                xml,
+                "    <connection from=\"{}\" to=\"{}\" fromLane=\"{from_lane}\" toLane=\"{to_lane}\"{tl} \
+                 dir=\"{}\" state=\"{state}\"/>",
+                edges[from].id,
+                edges[to].id,
+                direction(before, after)
+            );
+        }
+    }
+
+    xml.push_str("</net>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn edges_per_direction_of_travel() {
+        let xml = to_xml(&load("straight.xodr"));
+        // Lane -1 and the sidewalk along the reference line, right to left.
+        assert!(xml.contains("<edge id=\"1_1\""));
+        assert!(xml.contains("<lane id=\"1_1_0\" index=\"0\""));
+        assert!(xml.contains("<lane id=\"1_1_1\" index=\"1\""));
+        assert!(xml.contains("allow=\"pedestrian\""));
+        // Lane 1 against it, at the 50 km/h limit.
+        assert!(xml.contains("<edge id=\"-1_1\""));
+        assert!(xml.contains("<lane id=\"-1_1_0\" index=\"0\" speed=\"13.89\" length=\"100.00\""));
+        assert!(!xml.contains("<connection"));
+        assert_eq!(xml.matches("type=\"dead_end\"").count(), 4);
+    }
+
+    #[test]
+    fn connecting_roads_become_connections() {
+        let xml = to_xml(&load("junction.xodr"));
+        assert!(xml.contains("<edge id=\"1_1\" from=\"n1\" to=\"J100\""));
+        assert!(xml.contains("<edge id=\"2_1\" from=\"J100\" to=\"n2\""));
+        assert!(!xml.contains("<edge id=\"3_1\""));
+        assert!(xml
+            .contains("<connection from=\"1_1\" to=\"2_1\" fromLane=\"0\" toLane=\"0\" dir=\"s\""));
+        assert!(xml.contains("<junction id=\"J100\""));
+        assert!(xml.contains("incLanes=\"1_1_0\""));
+    }
+}
@@ -0,0 +1,647 @@
+// Cross-section templates for new roads.
+//
+// A template lists the lanes of a road across, outwards from the reference
+// line on each side, with their types, widths and the marking on their
+// outer boundaries, so that roads drawn for a scenario get a consistent
+// layout without typing out every lane. Three templates are built in
+// ("2-lane rural", "4-lane divided" and "urban with sidewalks"); more are
+// read from a JSON file given to `view --templates` or loaded with
+// `template load <file>`, and replace built-in ones of the same name:
+//
+//   { "templates": [
+//     { "name": "2-lane rural", "rule": "RHT", "center_mark": "broken",
+//       "left": [ { "type": "driving", "width": 3.5, "mark": "solid" },
+//                 { "type": "shoulder", "width": 1.0 } ],
+//       "right": [ { "type": "driving", "width": 3.5, "mark": "solid" },
+//                  { "type": "shoulder", "width": 1.0 } ] }
+//   ] }
+//
+// `road new <x1> <y1> <x2> <y2>` in the console creates a straight road
+// between two points with the current template, as an edit (see `edit`);
+// the points are snapped to road ends and tangents (see `snapping`). The
+// template panel lists the templates, makes the one clicked current and
+// widens or narrows its lanes, to the next standard width when snapping to
+// them; `template save <file>` writes the templates out in the format
+// above, edits included.
+//
+// Keys: B shows or hides the template panel.
+
+use std::path::Path;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::i18n::Locale;
+use crate::json::{self, Json};
+use crate::snapping::{self, Snapping};
+use crate::tessellation::offset_line;
+use crate::transaction::{self, Transaction};
+use crate::{RoadInfo, RoadMark, RoadNetwork, RoadSegment, TrafficRule};
+
+// Distance between the samples of a new road's reference line, in meters.
+const STEP: f64 = 2.0;
+
+// How much a panel button widens or narrows a lane, in meters.
+const WIDTH_STEP: f64 = 0.25;
+
+// The narrowest a lane is made from the panel, in meters.
+const MIN_WIDTH: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaneTemplate {
+    pub lane_type: String,
+    pub width: f64,
+    // The road mark type on the outer boundary, e.g. "solid".
+    pub mark: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub rule: TrafficRule,
+    // The road mark type of the center lane.
+    pub center_mark: Option<String>,
+    // Outwards from the reference line.
+    pub left: Vec<LaneTemplate>,
+    pub right: Vec<LaneTemplate>,
+}
+
+impl Template {
+    // The lanes of a road with this cross section along a reference line,
+    // numbered outwards from it as in OpenDRIVE.
+    pub fn lanes(&self, road_id: u32, reference: &[DVec3]) -> Vec<RoadSegment> {
+        let length: f64 = reference.windows(2).map(|w| w[0].distance(w[1])).sum();
+        let marks = |kind: &Option<String>| -> Vec<RoadMark> {
+            kind.iter()
+                .map(|kind| RoadMark {
+                    kind: kind.clone(),
+                    color: "standard".to_string(),
+                    weight: "standard".to_string(),
+                    material: "standard".to_string(),
+                    ..default()
+                })
+                .collect()
+        };
+        let mut out = Vec::new();
+        for (lanes, sign) in [(&self.left, 1.0), (&self.right, -1.0)] {
+            let mut inner = 0.0;
+            for (index, lane) in lanes.iter().enumerate() {
+                let outer = inner + lane.width;
+                let (left, right) = if sign > 0.0 {
+                    (outer, inner)
+                } else {
+                    (-inner, -outer)
+                };
+                let middle = offset_line(reference, (left + right) / 2.0);
+                out.push(RoadSegment {
+                    start_pos: middle[0],
+                    end_pos: middle[middle.len() - 1],
+                    start_s: 0.0,
+                    end_s: length,
+                    width: lane.width,
+                    left_side: offset_line(reference, left),
+                    right_side: offset_line(reference, right),
+                    road_id,
+                    lane_id: (index as i32 + 1) * sign as i32,
+                    lane_section_id: 1,
+                    rule: self.rule,
+                    lane_type: lane.lane_type.clone(),
+                    road_type: String::new(),
+                    speed: None,
+                    materials: Vec::new(),
+                    access: Vec::new(),
+                    road_marks: marks(&lane.mark),
+                    center_marks: if index == 0 {
+                        marks(&self.center_mark)
+                    } else {
+                        Vec::new()
+                    },
+                    predecessor: None,
+                    successor: None,
+                });
+                inner = outer;
+            }
+        }
+        out
+    }
+
+    fn to_json(&self) -> Json {
+        let lanes = |lanes: &[LaneTemplate]| {
+            Json::Array(
+                lanes
+                    .iter()
+                    .map(|lane| {
+                        let mut fields = vec![
+                            ("type".to_string(), Json::from(lane.lane_type.as_str())),
+                            ("width".to_string(), Json::from(lane.width)),
+                        ];
+                        if let Some(mark) = &lane.mark {
+                            fields.push(("mark".to_string(), Json::from(mark.as_str())));
+                        }
+                        Json::Object(fields)
+                    })
+                    .collect(),
+            )
+        };
+        let mut fields = vec![
+            ("name".to_string(), Json::from(self.name.as_str())),
+            ("rule".to_string(), Json::from(self.rule.as_attribute())),
+        ];
+        if let Some(mark) = &self.center_mark {
+            fields.push(("center_mark".to_string(), Json::from(mark.as_str())));
+        }
+        fields.push(("left".to_string(), lanes(&self.left)));
+        fields.push(("right".to_string(), lanes(&self.right)));
+        Json::Object(fields)
+    }
+}
+
+fn parse_lanes(value: Option<&Json>) -> Result<Vec<LaneTemplate>, String> {
+    let Some(value) = value else {
+        return Ok(Vec::new());
+    };
+    let lanes = value
+        .as_array()
+        .ok_or("`left` and `right` must be arrays")?;
+    lanes
+        .iter()
+        .enumerate()
+        .map(|(i, lane)| {
+            let invalid = |what: &str| format!("lane {}: {what}", i + 1);
+            Ok(LaneTemplate {
+                lane_type: lane
+                    .get("type")
+                    .and_then(Json::as_str)
+                    .ok_or_else(|| invalid("no `type`"))?
+                    .to_string(),
+                width: lane
+                    .get("width")
+                    .and_then(Json::as_f64)
+                    .filter(|width| *width > 0.0)
+                    .ok_or_else(|| invalid("`width` must be a positive number"))?,
+                mark: lane.get("mark").and_then(Json::as_str).map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+fn parse_template(template: &Json) -> Result<Template, String> {
+    let name = template
+        .get("name")
+        .and_then(Json::as_str)
+        .ok_or("no `name`")?;
+    let invalid = |e: String| format!("{name}: {e}");
+    let template = Template {
+        name: name.to_string(),
+        rule: template
+            .get("rule")
+            .and_then(Json::as_str)
+            .map_or(TrafficRule::RightHand, TrafficRule::from_attribute),
+        center_mark: template
+            .get("center_mark")
+            .and_then(Json::as_str)
+            .map(str::to_string),
+        left: parse_lanes(template.get("left")).map_err(invalid)?,
+        right: parse_lanes(template.get("right")).map_err(invalid)?,
+    };
+    if template.left.is_empty() && template.right.is_empty() {
+        return Err(invalid("no lanes".to_string()));
+    }
+    Ok(template)
+}
+
+// Reads templates in the format described at the top.
+pub fn parse(text: &str) -> Result<Vec<Template>, String> {
+    let json = json::parse(text)?;
+    json.get("templates")
+        .and_then(Json::as_array)
+        .ok_or("expected an object with a `templates` array")?
+        .iter()
+        .enumerate()
+        .map(|(i, template)| {
+            parse_template(template).map_err(|e| format!("template {}: {e}", i + 1))
+        })
+        .collect()
+}
+
+fn lane(lane_type: &str, width: f64, mark: Option<&str>) -> LaneTemplate {
+    LaneTemplate {
+        lane_type: lane_type.to_string(),
+        width,
+        mark: mark.map(str::to_string),
+    }
+}
+
+pub fn built_in() -> Vec<Template> {
+    let rural = vec![
+        lane("driving", 3.5, Some("solid")),
+        lane("shoulder", 1.0, None),
+    ];
+    let divided = vec![
+        lane("median", 1.0, Some("solid")),
+        lane("driving", 3.5, Some("broken")),
+        lane("driving", 3.5, Some("solid")),
+        lane("shoulder", 2.5, None),
+    ];
+    let urban = vec![
+        lane("driving", 3.25, None),
+        lane("parking", 2.25, None),
+        lane("curb", 0.15, None),
+        lane("sidewalk", 2.0, None),
+    ];
+    [
+        ("2-lane rural", Some("broken"), rural),
+        ("4-lane divided", None, divided),
+        ("urban with sidewalks", Some("solid solid"), urban),
+    ]
+    .into_iter()
+    .map(|(name, center_mark, lanes)| Template {
+        name: name.to_string(),
+        rule: TrafficRule::RightHand,
+        center_mark: center_mark.map(str::to_string),
+        left: lanes.clone(),
+        right: lanes,
+    })
+    .collect()
+}
+
+// The templates, and the one new roads get.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct TemplateLibrary {
+    pub templates: Vec<Template>,
+    pub current: usize,
+}
+
+impl Default for TemplateLibrary {
+    fn default() -> Self {
+        Self {
+            templates: built_in(),
+            current: 0,
+        }
+    }
+}
+
+impl TemplateLibrary {
+    // The built-in templates, with those in a file added or replacing them.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut library = Self::default();
+        library.read(path)?;
+        Ok(library)
+    }
+
+    fn read(&mut self, path: &Path) -> Result<usize, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let templates = parse(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        let count = templates.len();
+        for template in templates {
+            match self.templates.iter_mut().find(|t| t.name == template.name) {
+                Some(existing) => *existing = template,
+                None => self.templates.push(template),
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn current(&self) -> &Template {
+        &self.templates[self.current]
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::Object(vec![(
+            "templates".to_string(),
+            Json::Array(self.templates.iter().map(Template::to_json).collect()),
+        )])
+    }
+
+    fn describe(&self) -> String {
+        self.templates
+            .iter()
+            .enumerate()
+            .map(|(i, template)| {
+                let marker = if i == self.current { '*' } else { ' ' };
+                let lanes = |lanes: &[LaneTemplate]| {
+                    lanes
+                        .iter()
+                        .map(|lane| format!("{} {}", lane.lane_type, lane.width))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                format!(
+                    "{marker} {}: left {} | right {}",
+                    template.name,
+                    lanes(&template.left),
+                    lanes(&template.right)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Creates a straight road between two viewer-frame points with the current
+// template.
+pub fn new_road(world: &mut World, from: DVec3, to: DVec3) -> Result<String, String> {
+    let snapping = *world.resource::<Snapping>();
+    let (start, end) = snapping.line(world.resource::<RoadNetwork>(), from, to);
+    let (from, to) = (start.0, end.0);
+    let length = from.distance(to);
+    if length < STEP {
+        return Err(format!("a road must be at least {STEP} m long"));
+    }
+    let samples = (length / STEP).ceil() as usize;
+    let reference: Vec<DVec3> = (0..=samples)
+        .map(|i| from.lerp(to, i as f64 / samples as f64))
+        .collect();
+    let template = world.resource::<TemplateLibrary>().current().clone();
+    let network = world.resource::<RoadNetwork>();
+    let road_id = network
+        .roads
+        .keys()
+        .copied()
+        .chain(network.segments.iter().map(|lane| lane.road_id))
+        .max()
+        .map_or(1, |id| id + 1);
+    let transaction = Transaction::begin(network, format!("creating road {road_id}"), &[]);
+    let created = transaction::run(world, transaction, |network| {
+        network.segments.extend(template.lanes(road_id, &reference));
+        network.roads.insert(road_id, RoadInfo::default());
+        let message = format!("created road {road_id} ({})", template.name);
+        Ok((message, vec![road_id]))
+    })?;
+    snapping::mark(world, &[start, end]);
+    Ok(created)
+}
+
+// `template` in the console: lists the templates, makes one current, or
+// loads or saves a file.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut library = world.resource_mut::<TemplateLibrary>();
+    match args {
+        [] => Ok(library.describe()),
+        ["load", path] => {
+            let count = library.read(Path::new(path))?;
+            Ok(format!("read {count} templates"))
+        }
+        ["save", path] => {
+            std::fs::write(path, library.to_json().write()).map_err(|e| format!("{path}: {e}"))?;
+            Ok(format!("wrote {path}"))
+        }
+        name => {
+            let name = name.join(" ");
+            library.current = library
+                .templates
+                .iter()
+                .position(|template| template.name == name)
+                .ok_or_else(|| format!("no template `{name}`"))?;
+            Ok(String::new())
+        }
+    }
+}
+
+// Marks the template panel.
+#[derive(Component)]
+struct TemplatePanel;
+
+// A panel button making a template current.
+#[derive(Component)]
+struct Choose(usize);
+
+// A panel button changing the width of a lane of the current template: the
+// side (true for left), the lane's index outwards, and the change.
+#[derive(Component)]
+struct Widen(bool, usize, f64);
+
+pub struct TemplatePlugin;
+
+impl Plugin for TemplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_panel)
+            .add_systems(Update, (toggle_panel, edit_on_click, fill_panel).chain());
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Percent(50.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                row_gap: Val::Px(2.0),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        TemplatePanel,
+    ));
+}
+
+fn toggle_panel(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panels: Query<&mut Visibility, With<TemplatePanel>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyB) {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn edit_on_click(
+    mut library: ResMut<TemplateLibrary>,
+    snapping: Res<Snapping>,
+    chooses: Query<(&Interaction, &Choose), Changed<Interaction>>,
+    widens: Query<(&Interaction, &Widen), Changed<Interaction>>,
+) {
+    for (interaction, choose) in &chooses {
+        if *interaction == Interaction::Pressed {
+            library.current = choose.0;
+        }
+    }
+    for (interaction, &Widen(left, index, change)) in &widens {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let current = library.current;
+        let template = &mut library.templates[current];
+        let lanes = if left {
+            &mut template.left
+        } else {
+            &mut template.right
+        };
+        if let Some(lane) = lanes.get_mut(index) {
+            lane.width = snapping.step_width(lane.width, change).max(MIN_WIDTH);
+        }
+    }
+}
+
+fn button(parent: &mut ChildBuilder, caption: String, color: Color, marker: impl Component) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(6.0), Val::Px(1.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(
+                caption,
+                TextStyle {
+                    font_size: 13.0,
+                    color,
+                    ..default()
+                },
+            ));
+        });
+}
+
+// Rebuilds the panel when the templates change.
+fn fill_panel(
+    mut commands: Commands,
+    library: Res<TemplateLibrary>,
+    locale: Res<Locale>,
+    panels: Query<Entity, With<TemplatePanel>>,
+) {
+    if !library.is_changed() {
+        return;
+    }
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+    let template = library.current();
+    for panel in &panels {
+        commands.entity(panel).despawn_descendants();
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                locale.text("templates-title", &[]),
+                style(Color::WHITE),
+            ));
+            for (i, template) in library.templates.iter().enumerate() {
+                let color = if i == library.current {
+                    Color::rgb(1.0, 0.9, 0.1)
+                } else {
+                    Color::GRAY
+                };
+                button(panel, template.name.clone(), color, Choose(i));
+            }
+            // The current template's lanes from the left edge to the right.
+            let left = template.left.iter().enumerate().rev().map(|l| (true, l));
+            let right = template.right.iter().enumerate().map(|l| (false, l));
+            for (is_left, (index, lane)) in left.chain(right) {
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(6.0),
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        button(
+                            row,
+                            "-".to_string(),
+                            Color::WHITE,
+                            Widen(is_left, index, -WIDTH_STEP),
+                        );
+                        button(
+                            row,
+                            "+".to_string(),
+                            Color::WHITE,
+                            Widen(is_left, index, WIDTH_STEP),
+                        );
+                        let id = if is_left {
+                            index as i32 + 1
+                        } else {
+                            -(index as i32 + 1)
+                        };
+                        row.spawn(TextBundle::from_section(
+                            locale.text(
+                                "templates-lane",
+                                &[
+                                    ("lane", &id),
+                                    ("type", &lane.lane_type),
+                                    ("width", &format!("{:.2}", lane.width)),
+                                ],
+                            ),
+                            style(Color::rgb(0.85, 0.85, 0.85)),
+                        ));
+                    });
+            }
+            panel.spawn(TextBundle::from_section(
+                locale.text("templates-hint", &[]),
+                style(Color::GRAY),
+            ));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::LoadTransform;
+    use crate::xodr;
+
+    fn lane(network: &RoadNetwork, lane_id: i32) -> &RoadSegment {
+        let index = network.find_segment(7, 1, lane_id).unwrap();
+        &network.segments[index]
+    }
+
+    fn assert_points_near(actual: DVec3, expected: DVec3) {
+        assert!(
+            actual.distance(expected) < 0.01,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn templates_make_lanes() {
+        // The built-in templates come back unchanged from a saved file.
+        let library = TemplateLibrary::default();
+        assert_eq!(
+            parse(&library.to_json().write()).unwrap(),
+            library.templates
+        );
+        assert!(parse(r#"{ "templates": [{ "name": "empty" }] }"#).is_err());
+
+        // A road 40 m east along the x axis, with the divided template: a
+        // median lane of 1 m, two driving lanes of 3.5 m and a shoulder on
+        // each side.
+        let divided = &library.templates[1];
+        let reference: Vec<DVec3> = (0..=20).map(|i| DVec3::X * (i * 2) as f64).collect();
+        let network = RoadNetwork::new(divided.lanes(7, &reference));
+        assert_eq!(network.segments.len(), 8);
+        let outer = lane(&network, -3);
+        assert!((outer.width - 3.5).abs() < 0.01);
+        // Right of the road going east is south, +z in the viewer.
+        assert_points_near(outer.right_side[0], DVec3::new(0.0, 0.0, 8.0));
+        assert_points_near(
+            lane(&network, 4).left_side[20],
+            DVec3::new(40.0, 0.0, -10.5),
+        );
+        assert!(lane(&network, -1).center_marks.is_empty());
+        assert_eq!(lane(&network, 2).road_marks[0].kind, "broken");
+
+        // The road is written out and read back with the same lanes.
+        let read = xodr::read_str(&xodr::to_xml(&network), &LoadTransform::default()).unwrap();
+        let edge = lane(&read, -4);
+        assert_eq!(edge.lane_type, "shoulder");
+        assert!((edge.width - 2.5).abs() < 0.01);
+        assert_points_near(edge.right_side[0], DVec3::new(0.0, 0.0, 10.5));
+    }
+}
@@ -0,0 +1,512 @@
+// Turns road segments into triangle meshes.
+//
+// The meshes follow the actual boundary polylines; the viewer draws them and
+// the exporters write them out. Mesh positions are f32 and relative to an
+// origin chosen by the caller, which is where the f64 model geometry is
+// converted.
+
+use std::borrow::Cow;
+
+use bevy::math::{DVec3, Vec3};
+use bevy::render::mesh::{Indices, Mesh, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+
+use crate::road_marks::{self, Stroke};
+use crate::RoadSegment;
+
+// Triangles below this area (in square meters) are dropped. Physics engines
+// tend to choke on slivers, and they add nothing visually.
+pub const MIN_TRIANGLE_AREA: f32 = 1e-6;
+
+// Painted lines are 15 cm wide and lifted 1 cm above the asphalt.
+pub const MARKING_WIDTH: f64 = 0.15;
+pub const MARKING_LIFT: f64 = 0.01;
+
+// Raised markers are domes this high, in meters, with this many segments
+// around and rings from top to bottom.
+const MARKER_HEIGHT: f64 = 0.02;
+const DOME_SEGMENTS: usize = 8;
+const DOME_RINGS: usize = 2;
+
+// A plain indexed triangle mesh, independent of any rendering backend.
+#[derive(Debug, Clone, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+    // Linear RGBA per vertex, multiplied into the material's color; empty
+    // when the mesh is all one color.
+    pub colors: Vec<[f32; 4]>,
+}
+
+// The vertex color that leaves the material's color as it is.
+pub const PLAIN: [f32; 4] = [1.0; 4];
+
+impl TriangleMesh {
+    // Appends another mesh, offsetting its indices.
+    pub fn append(&mut self, other: &TriangleMesh) {
+        let base = self.positions.len() as u32;
+        if !self.colors.is_empty() || !other.colors.is_empty() {
+            self.colors.resize(self.positions.len(), PLAIN);
+            self.colors.extend_from_slice(&other.colors);
+            self.colors
+                .resize(self.positions.len() + other.positions.len(), PLAIN);
+        }
+        self.positions.extend_from_slice(&other.positions);
+        self.normals.extend_from_slice(&other.normals);
+        self.indices.extend(other.indices.iter().map(|i| i + base));
+    }
+
+    // Drops the triangles none of whose corners pass `keep`. Vertices are
+    // left in place.
+    pub fn retain_triangles(&mut self, keep: impl Fn(Vec3) -> bool) {
+        let positions = &self.positions;
+        let indices = self
+            .indices
+            .chunks_exact(3)
+            .filter(|triangle| triangle.iter().any(|&i| keep(positions[i as usize])))
+            .flatten()
+            .copied()
+            .collect();
+        self.indices = indices;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    // A copy of the mesh scaled per axis, moved to `at` and painted `color`.
+    fn placed(&self, at: Vec3, scale: Vec3, color: [f32; 4]) -> TriangleMesh {
+        TriangleMesh {
+            positions: self.positions.iter().map(|p| *p * scale + at).collect(),
+            // Normals scale inversely, to stay perpendicular to the surface.
+            normals: self
+                .normals
+                .iter()
+                .map(|n| (*n / scale).normalize_or_zero())
+                .collect(),
+            indices: self.indices.clone(),
+            colors: vec![color; self.positions.len()],
+        }
+    }
+
+    // Converts the mesh for rendering. The data only lives on the GPU.
+    pub fn to_mesh(&self) -> Mesh {
+        let mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone())
+        .with_inserted_indices(Indices::U32(self.indices.clone()));
+        if self.colors.is_empty() {
+            mesh
+        } else {
+            mesh.with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone())
+        }
+    }
+
+    // Adds a strip as `add_strip` does, in a color of its own.
+    pub fn add_colored_strip(&mut self, left: &[Vec3], right: &[Vec3], color: [f32; 4]) {
+        let base = self.positions.len();
+        self.add_strip(left, right);
+        if color != PLAIN || !self.colors.is_empty() {
+            self.colors.resize(base, PLAIN);
+            self.colors.resize(self.positions.len(), color);
+        }
+    }
+
+    // Adds a triangle strip between two polylines. Every triangle is wound
+    // counter-clockwise when seen from above, so all normals point up (+Y).
+    pub fn add_strip(&mut self, left: &[Vec3], right: &[Vec3]) {
+        let count = left.len().min(right.len());
+        if count < 2 {
+            return;
+        }
+
+        let base = self.positions.len() as u32;
+        for i in 0..count {
+            self.positions.push(left[i]);
+            self.positions.push(right[i]);
+            self.normals.push(Vec3::Y);
+            self.normals.push(Vec3::Y);
+        }
+
+        for i in 0..count as u32 - 1 {
+            let l0 = base + 2 * i;
+            let r0 = l0 + 1;
+            let l1 = l0 + 2;
+            let r1 = l0 + 3;
+            self.add_upward_triangle(l0, r0, l1);
+            self.add_upward_triangle(r0, r1, l1);
+        }
+        if !self.colors.is_empty() {
+            self.colors.resize(self.positions.len(), PLAIN);
+        }
+    }
+
+    // Adds a triangle, flipping its winding if needed so that it faces up, and
+    // skipping it entirely if it is degenerate.
+    fn add_upward_triangle(&mut self, a: u32, b: u32, c: u32) {
+        let (pa, pb, pc) = (
+            self.positions[a as usize],
+            self.positions[b as usize],
+            self.positions[c as usize],
+        );
+        let normal = (pb - pa).cross(pc - pa);
+        if normal.length() / 2.0 < MIN_TRIANGLE_AREA {
+            return;
+        }
+        if normal.y >= 0.0 {
+            self.indices.extend([a, b, c]);
+        } else {
+            self.indices.extend([a, c, b]);
+        }
+    }
+}
+
+// Builds the drivable surface of a segment, relative to `origin`.
+pub fn road_surface(segment: &RoadSegment, origin: DVec3) -> TriangleMesh {
+    let (left, right) = boundaries(segment);
+    let mut mesh = TriangleMesh::default();
+    mesh.add_strip(&local(&left, origin), &local(&right, origin));
+    mesh
+}
+
+// Builds the painted lines of a segment, raised slightly above the surface
+// to avoid z-fighting (see `painted_lines`).
+pub fn boundary_markings(
+    segment: &RoadSegment,
+    width: f64,
+    lift: f64,
+    origin: DVec3,
+) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+    let dome = unit_dome();
+    for (stroke, dash) in painted_lines(segment, width) {
+        if stroke.raised {
+            let length: f64 = dash.windows(2).map(|w| w[0].distance(w[1])).sum();
+            if let Some((center, _)) = point_at(&dash, length / 2.0) {
+                let scale = DVec3::new(stroke.width / 2.0, MARKER_HEIGHT, stroke.width / 2.0);
+                let at = center + DVec3::Y * lift - origin;
+                mesh.append(&dome.placed(at.as_vec3(), scale.as_vec3(), stroke.color));
+            }
+            continue;
+        }
+        let (left, right) = offset_polyline(&dash, stroke.width / 2.0, lift);
+        mesh.add_colored_strip(&local(&left, origin), &local(&right, origin), stroke.color);
+    }
+    mesh
+}
+
+// The painted lines of a segment, each dash as the polyline along its
+// middle with the stroke it belongs to: its road marks along the outer
+// boundary and, for lanes 1 and -1, the center lane's along the inner one
+// (see `road_marks`). Segments without road marks get a plain stripe of the
+// given width along both boundaries.
+pub fn painted_lines(segment: &RoadSegment, width: f64) -> Vec<(Stroke, Vec<DVec3>)> {
+    let (left_side, right_side) = boundaries(segment);
+    if segment.road_marks.is_empty() && segment.center_marks.is_empty() {
+        let stripe = Stroke {
+            t_offset: 0.0,
+            width,
+            length: 0.0,
+            space: 0.0,
+            s_offset: 0.0,
+            color: PLAIN,
+            raised: false,
+        };
+        return [left_side, right_side]
+            .into_iter()
+            .map(|side| (stripe, side.into_owned()))
+            .collect();
+    }
+
+    // Boundaries run along the reference line; left lanes have theirs on
+    // the left.
+    let (outer, inner, inward) = if segment.lane_id > 0 {
+        (&left_side, &right_side, -1.0)
+    } else {
+        (&right_side, &left_side, 1.0)
+    };
+    let marks = [
+        (outer, &segment.road_marks, inward),
+        (inner, &segment.center_marks, 1.0),
+    ];
+    // Each mark runs from its `s_offset` to the next one's, stations being
+    // spread evenly over the boundary.
+    let length = segment.end_s - segment.start_s;
+    let mut lines = Vec::new();
+    for (boundary, marks, inward) in marks {
+        let total: f64 = boundary.windows(2).map(|w| w[0].distance(w[1])).sum();
+        let along = |s: f64| match length > 0.0 {
+            true => total * (s / length).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        for (k, mark) in marks.iter().enumerate() {
+            let end = marks.get(k + 1).map_or(length, |next| next.s_offset);
+            let part = if mark.s_offset <= 0.0 && end >= length {
+                boundary.to_vec()
+            } else {
+                sub_polyline(boundary, along(mark.s_offset), along(end))
+            };
+            if part.len() < 2 {
+                continue;
+            }
+            for stroke in road_marks::strokes(mark, inward, width) {
+                let line = offset_line(&part, stroke.t_offset);
+                for dash in dashes(&line, stroke.length, stroke.space, stroke.s_offset) {
+                    lines.push((stroke, dash));
+                }
+            }
+        }
+    }
+    lines
+}
+
+// A dome of radius and height 1 standing on the origin, copies of which
+// make the raised markers.
+fn unit_dome() -> TriangleMesh {
+    let mut dome = TriangleMesh::default();
+    dome.positions.push(Vec3::Y);
+    for ring in 1..=DOME_RINGS {
+        let polar = std::f32::consts::FRAC_PI_2 * ring as f32 / DOME_RINGS as f32;
+        for segment in 0..DOME_SEGMENTS {
+            let around = std::f32::consts::TAU * segment as f32 / DOME_SEGMENTS as f32;
+            dome.positions.push(Vec3::new(
+                polar.sin() * around.cos(),
+                polar.cos(),
+                polar.sin() * around.sin(),
+            ));
+        }
+    }
+    // On a unit hemisphere the normals are the positions.
+    dome.normals = dome.positions.clone();
+    let at = |ring: usize, segment: usize| {
+        (1 + (ring - 1) * DOME_SEGMENTS + segment % DOME_SEGMENTS) as u32
+    };
+    for segment in 0..DOME_SEGMENTS {
+        dome.indices.extend([0, at(1, segment + 1), at(1, segment)]);
+        for ring in 1..DOME_RINGS {
+            let (a, b) = (at(ring, segment), at(ring, segment + 1));
+            let (c, d) = (at(ring + 1, segment), at(ring + 1, segment + 1));
+            dome.indices.extend([a, b, c, b, d, c]);
+        }
+    }
+    dome
+}
+
+// Cuts a polyline into dashes `length` meters long with `space` meters
+// between them, the pattern starting `start` meters in. A zero space leaves
+// the polyline whole.
+fn dashes(points: &[DVec3], length: f64, space: f64, start: f64) -> Vec<Vec<DVec3>> {
+    if space <= 0.0 {
+        return vec![points.to_vec()];
+    }
+    if length <= 0.0 {
+        return Vec::new();
+    }
+    let total: f64 = points.windows(2).map(|w| w[0].distance(w[1])).sum();
+    let period = length + space;
+    // Begin one period early, so that a dash cut by the start still shows.
+    let mut from = start.rem_euclid(period) - period;
+    let mut pieces = Vec::new();
+    while from < total {
+        let (a, b) = (from.max(0.0), (from + length).min(total));
+        if b > a {
+            pieces.push(sub_polyline(points, a, b));
+        }
+        from += period;
+    }
+    pieces
+}
+
+// The part of a polyline between two distances along it.
+fn sub_polyline(points: &[DVec3], from: f64, to: f64) -> Vec<DVec3> {
+    let mut piece = Vec::new();
+    let mut along = 0.0;
+    for pair in points.windows(2) {
+        let length = pair[0].distance(pair[1]);
+        let end = along + length;
+        if length > 0.0 && end > from && along < to {
+            let at = |d: f64| pair[0].lerp(pair[1], ((d - along) / length).clamp(0.0, 1.0));
+            if piece.is_empty() {
+                piece.push(at(from));
+            }
+            piece.push(at(to.min(end)));
+        }
+        along = end;
+    }
+    piece
+}
+
+// Builds flat arrows every `spacing` meters along the middle of a lane,
+// pointing the way its traffic flows. The first arrow sits half a spacing in,
+// so short lanes still get one.
+pub fn direction_arrows(
+    segment: &RoadSegment,
+    spacing: f64,
+    lift: f64,
+    origin: DVec3,
+) -> TriangleMesh {
+    let (left, right) = boundaries(segment);
+    let middle: Vec<DVec3> = left
+        .iter()
+        .zip(right.iter())
+        .map(|(l, r)| (*l + *r) / 2.0)
+        .collect();
+    let length: f64 = middle.windows(2).map(|w| w[0].distance(w[1])).sum();
+    // Arrows are sized to the lane but kept within reason.
+    let size = (segment.width * 0.8).clamp(1.0, 3.0);
+
+    let mut mesh = TriangleMesh::default();
+    let mut s = spacing.min(length) / 2.0;
+    while s < length {
+        let Some((center, mut ahead)) = point_at(&middle, s) else {
+            break;
+        };
+        if !segment.follows_reference() {
+            ahead = -ahead;
+        }
+        let side = DVec3::Y.cross(ahead) * size;
+        let up = DVec3::Y * lift;
+        let at = |along: f64, across: f64| center + ahead * size * along + side * across + up;
+
+        // Shaft, then head.
+        let shaft = (
+            [at(-1.0, 0.08), at(0.2, 0.08)],
+            [at(-1.0, -0.08), at(0.2, -0.08)],
+        );
+        mesh.add_strip(&local(&shaft.0, origin), &local(&shaft.1, origin));
+        let head = ([at(0.2, 0.3), at(1.0, 0.0)], [at(0.2, -0.3), at(1.0, 0.0)]);
+        mesh.add_strip(&local(&head.0, origin), &local(&head.1, origin));
+        s += spacing;
+    }
+    mesh
+}
+
+// The point `s` meters along a polyline and the direction it runs in there.
+pub fn point_at(points: &[DVec3], s: f64) -> Option<(DVec3, DVec3)> {
+    let mut remaining = s.max(0.0);
+    for pair in points.windows(2) {
+        let piece = pair[0].distance(pair[1]);
+        if piece <= 0.0 {
+            continue;
+        }
+        if remaining <= piece {
+            let direction = (pair[1] - pair[0]) / piece;
+            return Some((pair[0] + direction * remaining, direction));
+        }
+        remaining -= piece;
+    }
+    // Past the end: clamp to the last point.
+    let pair = points.windows(2).rev().find(|w| w[0] != w[1])?;
+    Some((pair[1], (pair[1] - pair[0]).normalize()))
+}
+
+// The boundary polylines of a segment. Segments that only come with end
+// points and a width get straight boundaries at half the width either side.
+pub fn boundaries(segment: &RoadSegment) -> (Cow<'_, [DVec3]>, Cow<'_, [DVec3]>) {
+    if segment.left_side.len() >= 2 && segment.right_side.len() >= 2 {
+        return (
+            Cow::Borrowed(&segment.left_side),
+            Cow::Borrowed(&segment.right_side),
+        );
+    }
+    let line = [segment.start_pos, segment.end_pos];
+    (
+        Cow::Owned(offset_line(&line, segment.width / 2.0)),
+        Cow::Owned(offset_line(&line, -segment.width / 2.0)),
+    )
+}
+
+// Converts f64 positions into f32 positions relative to `origin`.
+fn local(points: &[DVec3], origin: DVec3) -> Vec<Vec3> {
+    points.iter().map(|p| (*p - origin).as_vec3()).collect()
+}
+
+// Offsets a polyline sideways in the ground plane by `half_width` on each side
+// and lifts it by `lift`, returning the two resulting edges.
+fn offset_polyline(points: &[DVec3], half_width: f64, lift: f64) -> (Vec<DVec3>, Vec<DVec3>) {
+    let up = DVec3::Y * lift;
+    let left = offset_line(points, half_width).into_iter().map(|p| p + up);
+    let right = offset_line(points, -half_width).into_iter().map(|p| p + up);
+    (left.collect(), right.collect())
+}
+
+// Offsets a polyline sideways in the ground plane. Positive offsets move it to
+// the left of the direction of travel.
+pub fn offset_line(points: &[DVec3], offset: f64) -> Vec<DVec3> {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            // Average the directions of the adjoining pieces for a smooth offset.
+            let prev = points[i.saturating_sub(1)];
+            let next = points[(i + 1).min(points.len() - 1)];
+            let tangent = (next - prev).normalize_or_zero();
+            *p + DVec3::Y.cross(tangent).normalize_or_zero() * offset
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_qa;
+    use crate::road_marks;
+    use crate::sample_maps::{lane, load, SAMPLES};
+    use crate::validation::Severity;
+
+    #[test]
+    fn samples_tessellate_cleanly() {
+        for name in SAMPLES {
+            let network = load(name);
+            assert!(!network.segments.is_empty(), "{name} has no lanes");
+            for segment in &network.segments {
+                assert!(
+                    !road_surface(segment, DVec3::ZERO).is_empty(),
+                    "{name}: lane {}:{}:{} has no surface",
+                    segment.road_id,
+                    segment.lane_section_id,
+                    segment.lane_id
+                );
+            }
+            let errors: Vec<_> = mesh_qa::check(&network)
+                .into_iter()
+                .filter(|issue| issue.severity == Severity::Error)
+                .map(|issue| issue.message)
+                .collect();
+            assert!(errors.is_empty(), "{name}: {errors:?}");
+        }
+    }
+
+    #[test]
+    fn road_marks_are_painted_in_their_colors() {
+        let network = load("straight.xodr");
+        // The center line is painted yellow, the custom line as blue dots up to
+        // the solid line.
+        let yellow = road_marks::color("yellow");
+        let markings = boundary_markings(lane(&network, 1, 1, -1), 0.15, 0.01, DVec3::ZERO);
+        assert!(markings.colors.contains(&yellow));
+        assert!(markings.colors.contains(&PLAIN));
+        let dots = boundary_markings(lane(&network, 1, 1, -2), 0.15, 0.01, DVec3::ZERO);
+        let blue = road_marks::color("blue");
+        assert_eq!(dots.colors.iter().filter(|c| **c == blue).count(), 17 * 4);
+        assert!(dots
+            .positions
+            .iter()
+            .zip(&dots.colors)
+            .all(|(p, c)| (*c == blue) == (p.x < 50.0)));
+
+        // Botts' dots stand up from the road as domes facing outwards.
+        let left = boundary_markings(lane(&network, 1, 1, 1), 0.15, 0.01, DVec3::ZERO);
+        assert!(left.positions.iter().any(|p| p.y > 0.025));
+        for triangle in left.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| triangle[k] as usize);
+            let (pa, pb, pc) = (left.positions[a], left.positions[b], left.positions[c]);
+            let normal = left.normals[a] + left.normals[b] + left.normals[c];
+            assert!((pb - pa).cross(pc - pa).dot(normal) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+// Color themes.
+//
+// Every color the viewer draws the map, its overlays and its highlights in
+// comes from the `Theme` resource, so a theme changes them all at once. The
+// road materials are recolored in place; gizmos and labels read the theme
+// as they are drawn. Besides the default theme there is a colorblind-safe
+// one, built from the Okabe-Ito palette so that no two meanings differ by
+// red against green only, and a high-contrast one for projectors. Lane
+// surfaces take the color of their lane type's class (see `LaneClass`).
+//
+// Keys: F9 switches to the next theme.
+
+use bevy::prelude::*;
+
+use crate::{RoadLayer, RoadMaterials};
+
+// The lane types the themes color apart. Types not named here are drawn
+// like driving lanes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LaneClass {
+    #[default]
+    Driving,
+    Sidewalk,
+    Biking,
+    Shoulder,
+    Parking,
+    Median,
+}
+
+impl LaneClass {
+    pub fn of(lane_type: &str) -> Self {
+        match lane_type {
+            "sidewalk" | "walking" => LaneClass::Sidewalk,
+            "biking" => LaneClass::Biking,
+            "shoulder" | "border" | "stop" => LaneClass::Shoulder,
+            "parking" => LaneClass::Parking,
+            "median" | "curb" => LaneClass::Median,
+            _ => LaneClass::Driving,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    // Road meshes; `surface` is the driving lanes'.
+    pub surface: Color,
+    pub sidewalk: Color,
+    pub biking: Color,
+    pub shoulder: Color,
+    pub parking: Color,
+    pub median: Color,
+    pub marking: Color,
+    pub arrow: Color,
+    // Debug views.
+    pub wireframe: Color,
+    pub normals: Color,
+    pub backface: Color,
+    // The selection.
+    pub highlight: Color,
+    // Check results and annotations: notes, warnings, errors and passes.
+    pub info: Color,
+    pub warning: Color,
+    pub error: Color,
+    pub pass: Color,
+    // Lane width findings, by rule.
+    pub narrow: Color,
+    pub wide: Color,
+    pub sudden: Color,
+    // Design rule findings: grade, radius and transition.
+    pub steep: Color,
+    pub tight: Color,
+    pub abrupt: Color,
+    // Map labels, by kind.
+    pub road_label: Color,
+    pub lane_label: Color,
+    pub station_label: Color,
+    // Cross-section plane and profile.
+    pub cut_plane: Color,
+    pub cut_profile: Color,
+    // Chart cursors on the map: elevation profile and curvature.
+    pub profile_cursor: Color,
+    pub curvature_cursor: Color,
+    // Trajectory overlays.
+    pub trajectory: Color,
+    // Road type overlay, by class.
+    pub motorway: Color,
+    pub rural: Color,
+    pub town: Color,
+    pub low_speed: Color,
+}
+
+pub const DEFAULT: Theme = Theme {
+    name: "default",
+    surface: Color::rgb(0.2, 0.2, 0.2),
+    sidewalk: Color::rgb(0.5, 0.48, 0.44),
+    biking: Color::rgb(0.45, 0.2, 0.18),
+    shoulder: Color::rgb(0.3, 0.3, 0.3),
+    parking: Color::rgb(0.2, 0.24, 0.34),
+    median: Color::rgb(0.28, 0.38, 0.24),
+    marking: Color::rgb(0.9, 0.9, 0.9),
+    arrow: Color::rgb(0.95, 0.8, 0.2),
+    wireframe: Color::rgb(0.1, 0.9, 0.3),
+    normals: Color::rgb(0.2, 0.5, 1.0),
+    backface: Color::rgb(1.0, 0.0, 1.0),
+    highlight: Color::rgb(1.0, 0.9, 0.1),
+    info: Color::rgb(0.6, 0.8, 1.0),
+    warning: Color::rgb(1.0, 0.75, 0.1),
+    error: Color::rgb(1.0, 0.15, 0.1),
+    pass: Color::rgb(0.2, 0.9, 0.4),
+    narrow: Color::rgb(0.2, 0.5, 1.0),
+    wide: Color::rgb(1.0, 0.2, 0.9),
+    sudden: Color::rgb(1.0, 0.55, 0.1),
+    steep: Color::rgb(0.6, 0.3, 1.0),
+    tight: Color::rgb(1.0, 0.3, 0.3),
+    abrupt: Color::rgb(0.2, 0.9, 0.9),
+    road_label: Color::rgb(1.0, 1.0, 1.0),
+    lane_label: Color::rgb(0.6, 0.9, 1.0),
+    station_label: Color::rgb(1.0, 0.85, 0.4),
+    cut_plane: Color::rgb(0.3, 0.8, 1.0),
+    cut_profile: Color::rgb(1.0, 0.3, 0.1),
+    profile_cursor: Color::rgb(1.0, 0.2, 0.6),
+    curvature_cursor: Color::rgb(0.8, 0.6, 1.0),
+    trajectory: Color::rgb(0.2, 1.0, 0.8),
+    motorway: Color::rgb(0.25, 0.45, 1.0),
+    rural: Color::rgb(0.3, 0.8, 0.3),
+    town: Color::rgb(1.0, 0.6, 0.2),
+    low_speed: Color::rgb(0.9, 0.4, 0.9),
+};
+
+// Okabe-Ito: orange, sky blue, bluish green, yellow, blue, vermillion and
+// reddish purple, told apart with any of the common color vision
+// deficiencies.
+pub const COLORBLIND: Theme = Theme {
+    name: "colorblind",
+    surface: Color::rgb(0.2, 0.2, 0.2),
+    sidewalk: Color::rgb(0.55, 0.55, 0.55),
+    biking: Color::rgb(0.5, 0.22, 0.0),
+    shoulder: Color::rgb(0.33, 0.33, 0.33),
+    parking: Color::rgb(0.0, 0.27, 0.42),
+    median: Color::rgb(0.0, 0.37, 0.27),
+    marking: Color::rgb(0.9, 0.9, 0.9),
+    arrow: Color::rgb(0.94, 0.89, 0.26),
+    wireframe: Color::rgb(0.34, 0.71, 0.91),
+    normals: Color::rgb(0.9, 0.62, 0.0),
+    backface: Color::rgb(0.8, 0.47, 0.65),
+    highlight: Color::rgb(0.94, 0.89, 0.26),
+    info: Color::rgb(0.34, 0.71, 0.91),
+    warning: Color::rgb(0.9, 0.62, 0.0),
+    error: Color::rgb(0.84, 0.37, 0.0),
+    pass: Color::rgb(0.0, 0.45, 0.7),
+    narrow: Color::rgb(0.0, 0.45, 0.7),
+    wide: Color::rgb(0.8, 0.47, 0.65),
+    sudden: Color::rgb(0.9, 0.62, 0.0),
+    steep: Color::rgb(0.34, 0.71, 0.91),
+    tight: Color::rgb(0.84, 0.37, 0.0),
+    abrupt: Color::rgb(0.94, 0.89, 0.26),
+    road_label: Color::rgb(1.0, 1.0, 1.0),
+    lane_label: Color::rgb(0.34, 0.71, 0.91),
+    station_label: Color::rgb(0.94, 0.89, 0.26),
+    cut_plane: Color::rgb(0.34, 0.71, 0.91),
+    cut_profile: Color::rgb(0.84, 0.37, 0.0),
+    profile_cursor: Color::rgb(0.8, 0.47, 0.65),
+    curvature_cursor: Color::rgb(0.0, 0.62, 0.45),
+    trajectory: Color::rgb(0.0, 0.45, 0.7),
+    motorway: Color::rgb(0.0, 0.45, 0.7),
+    rural: Color::rgb(0.0, 0.62, 0.45),
+    town: Color::rgb(0.9, 0.62, 0.0),
+    low_speed: Color::rgb(0.8, 0.47, 0.65),
+};
+
+// Black roads, white markings and saturated overlays.
+pub const HIGH_CONTRAST: Theme = Theme {
+    name: "high-contrast",
+    surface: Color::rgb(0.02, 0.02, 0.02),
+    sidewalk: Color::rgb(0.6, 0.6, 0.6),
+    biking: Color::rgb(0.6, 0.0, 0.0),
+    shoulder: Color::rgb(0.25, 0.25, 0.25),
+    parking: Color::rgb(0.0, 0.0, 0.6),
+    median: Color::rgb(0.0, 0.45, 0.0),
+    marking: Color::rgb(1.0, 1.0, 1.0),
+    arrow: Color::rgb(1.0, 1.0, 0.0),
+    wireframe: Color::rgb(0.0, 1.0, 0.0),
+    normals: Color::rgb(0.0, 1.0, 1.0),
+    backface: Color::rgb(1.0, 0.0, 1.0),
+    highlight: Color::rgb(1.0, 1.0, 0.0),
+    info: Color::rgb(0.0, 1.0, 1.0),
+    warning: Color::rgb(1.0, 0.6, 0.0),
+    error: Color::rgb(1.0, 0.0, 0.0),
+    pass: Color::rgb(0.0, 1.0, 0.0),
+    narrow: Color::rgb(0.0, 0.6, 1.0),
+    wide: Color::rgb(1.0, 0.0, 1.0),
+    sudden: Color::rgb(1.0, 0.6, 0.0),
+    steep: Color::rgb(0.6, 0.0, 1.0),
+    tight: Color::rgb(1.0, 0.0, 0.0),
+    abrupt: Color::rgb(0.0, 1.0, 1.0),
+    road_label: Color::rgb(1.0, 1.0, 1.0),
+    lane_label: Color::rgb(0.0, 1.0, 1.0),
+    station_label: Color::rgb(1.0, 1.0, 0.0),
+    cut_plane: Color::rgb(0.0, 1.0, 1.0),
+    cut_profile: Color::rgb(1.0, 0.0, 0.0),
+    profile_cursor: Color::rgb(1.0, 0.0, 1.0),
+    curvature_cursor: Color::rgb(0.0, 1.0, 0.0),
+    trajectory: Color::rgb(1.0, 0.5, 0.0),
+    motorway: Color::rgb(0.0, 0.6, 1.0),
+    rural: Color::rgb(0.0, 1.0, 0.0),
+    town: Color::rgb(1.0, 0.6, 0.0),
+    low_speed: Color::rgb(1.0, 0.0, 1.0),
+};
+
+pub const THEMES: [Theme; 3] = [DEFAULT, COLORBLIND, HIGH_CONTRAST];
+
+impl Default for Theme {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+impl Theme {
+    // Looks a theme up by name.
+    pub fn named(name: &str) -> Option<Theme> {
+        THEMES.into_iter().find(|theme| theme.name == name)
+    }
+
+    // The surface color of a class of lanes.
+    pub fn lane_surface(&self, class: LaneClass) -> Color {
+        match class {
+            LaneClass::Driving => self.surface,
+            LaneClass::Sidewalk => self.sidewalk,
+            LaneClass::Biking => self.biking,
+            LaneClass::Shoulder => self.shoulder,
+            LaneClass::Parking => self.parking,
+            LaneClass::Median => self.median,
+        }
+    }
+
+    // The color of a road mesh layer.
+    pub fn layer(&self, layer: RoadLayer) -> Color {
+        match layer {
+            RoadLayer::Surface(class) => self.lane_surface(class),
+            RoadLayer::Marking => self.marking,
+            RoadLayer::Arrow => self.arrow,
+            RoadLayer::Wireframe => self.wireframe,
+            RoadLayer::Normals => self.normals,
+            RoadLayer::Backface => self.backface,
+        }
+    }
+}
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Theme>()
+            .add_systems(Update, (switch_theme, recolor_roads).chain());
+    }
+}
+
+fn switch_theme(keys: Res<ButtonInput<KeyCode>>, mut theme: ResMut<Theme>) {
+    if !keys.just_pressed(KeyCode::F9) {
+        return;
+    }
+    let current = THEMES.iter().position(|t| t.name == theme.name);
+    *theme = THEMES[current.map_or(0, |i| (i + 1) % THEMES.len())];
+    info!("theme: {}", theme.name);
+}
+
+// Recolors the shared road materials when the theme changes, in the weather
+// they are shown in (see `weather`).
+pub fn recolor_roads(
+    theme: Res<Theme>,
+    mut road_materials: ResMut<RoadMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+    road_materials.theme = *theme;
+    for (layer, handle) in &road_materials.handles {
+        if let Some(material) = materials.get_mut(handle) {
+            *material = layer.material(&theme);
+            road_materials.weather.adjust(*layer, material);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lane_classes_are_told_apart_in_every_theme() {
+        assert_eq!(LaneClass::of("walking"), LaneClass::Sidewalk);
+        assert_eq!(LaneClass::of("entry"), LaneClass::Driving);
+        let classes = [
+            LaneClass::Driving,
+            LaneClass::Sidewalk,
+            LaneClass::Biking,
+            LaneClass::Shoulder,
+            LaneClass::Parking,
+            LaneClass::Median,
+        ];
+        for theme in THEMES {
+            for (i, a) in classes.iter().enumerate() {
+                for b in &classes[i + 1..] {
+                    assert_ne!(
+                        theme.lane_surface(*a),
+                        theme.lane_surface(*b),
+                        "{} {a:?} {b:?}",
+                        theme.name
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,540 @@
+// Tile streaming for large maps.
+//
+// Country-scale networks have far too many segments to keep on the GPU at
+// once. At startup the network is partitioned into square tiles on the
+// ground plane, each segment going to the tile holding its midpoint. Every
+// frame the tiles around the camera are ranked by distance: the nearest are
+// spawned until the memory budget is used up, and tiles that drift out of
+// range or no longer fit in the budget are despawned, which frees their
+// meshes. Small maps fit into a handful of tiles and simply load whole.
+//
+// When the network is reloaded or edited it is partitioned afresh, but only
+// the loaded tiles showing a road that changed are rebuilt; the rest keep
+// their meshes. A road counts as changed when its `OdrRoad` component was
+// marked changed (see `edit`) or a `NetworkChanged` event names it, which
+// also covers roads that went away.
+//
+// Tiles are read from the mesh cache where it has them, and written to it
+// once tessellated (see `mesh_cache`). Lanes the filter hides are left out of
+// the meshes, and such tiles bypass the cache (see `filter`). So do tiles
+// under a style sheet that styles lanes, whose roads are split into one mesh
+// per style rule (see `style`).
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+
+use crate::cross_section::{cut, CrossSection};
+use crate::debug_view::DebugView;
+use crate::edit::NetworkChanged;
+use crate::entity_index::{OdrEntityIndex, OdrId};
+use crate::filter::Filter;
+use crate::mesh_cache::{self, MeshCache};
+use crate::odr::{self, OdrLanes, OdrRoad};
+use crate::origin::RenderOrigin;
+use crate::overlays::Overlays;
+use crate::style::{StyleMaterials, StyleSheet};
+use crate::theme::LaneClass;
+use crate::{
+    camera_orbit, spawn_road, tessellate_road, MainCamera, RoadMaterials, RoadMeshOptions,
+    RoadNetwork, RoadSegment,
+};
+
+// Tiles farther than the load radius times this factor are unloaded. The gap
+// keeps tiles on the edge of the radius from loading and unloading as the
+// camera jitters across it.
+const UNLOAD_FACTOR: f64 = 1.25;
+
+// At most this many tiles are loaded per frame, so that crossing into a new
+// area does not stall rendering.
+const LOADS_PER_FRAME: usize = 4;
+
+// How the network is tiled and how much of it may be loaded.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct TileSettings {
+    // Edge length of a tile, in meters.
+    pub tile_size: f64,
+    // Tiles whose nearest point is within this distance of the camera (in
+    // meters, on the ground plane) are loaded.
+    pub load_radius: f64,
+    // Upper bound on the mesh data held by loaded tiles, in bytes. Loading
+    // stops as soon as it is reached.
+    pub memory_budget: usize,
+    // If set, tile meshes are simplified to within this many meters, for
+    // machines that struggle with the full triangle count.
+    pub max_error: Option<f64>,
+}
+
+impl Default for TileSettings {
+    fn default() -> Self {
+        Self {
+            tile_size: 500.0,
+            load_radius: 3_000.0,
+            memory_budget: 512 * 1024 * 1024,
+            max_error: None,
+        }
+    }
+}
+
+// The segments of the network, grouped by tile. Within a tile they are
+// sorted by road, lane section and lane so that each road's segments are
+// adjacent and can be merged into one mesh.
+#[derive(Resource, Debug, Default)]
+struct TileGrid {
+    tiles: HashMap<IVec2, Vec<usize>>,
+}
+
+// A tile that currently has entities in the scene.
+#[derive(Debug)]
+struct LoadedTile {
+    entities: Vec<Entity>,
+    bytes: usize,
+}
+
+#[derive(Resource, Debug, Default)]
+struct LoadedTiles(HashMap<IVec2, LoadedTile>);
+
+// Roads whose meshes are out of date.
+#[derive(Resource, Debug, Default)]
+struct DirtyRoads(HashSet<u32>);
+
+// Asks for every loaded tile to be rebuilt, e.g. after a display setting
+// that affects the meshes changed.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ReloadTiles;
+
+pub struct TileStreamingPlugin;
+
+impl Plugin for TileStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileSettings>()
+            .init_resource::<LoadedTiles>()
+            .init_resource::<DirtyRoads>()
+            .add_event::<ReloadTiles>()
+            .add_systems(Startup, partition_network)
+            .add_systems(
+                Update,
+                (
+                    mesh_cache::follow_changes.run_if(on_event::<NetworkChanged>()),
+                    mark_dirty,
+                    stream_tiles,
+                )
+                    .chain()
+                    .after(camera_orbit),
+            );
+    }
+}
+
+// The tile holding a viewer-frame position.
+fn tile_of(settings: &TileSettings, p: DVec3) -> IVec2 {
+    IVec2::new(
+        (p.x / settings.tile_size).floor() as i32,
+        (p.z / settings.tile_size).floor() as i32,
+    )
+}
+
+// Ground-plane distance from `p` to the nearest point of a tile.
+fn tile_distance(settings: &TileSettings, tile: IVec2, p: DVec2) -> f64 {
+    let min = tile.as_dvec2() * settings.tile_size;
+    let max = min + DVec2::splat(settings.tile_size);
+    p.distance(p.clamp(min, max))
+}
+
+// Number of bytes a mesh takes up in vertex and index buffers.
+pub fn mesh_bytes(mesh: &Mesh) -> usize {
+    let vertices: usize = mesh
+        .attributes()
+        .map(|(_, values)| values.get_bytes().len())
+        .sum();
+    let indices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.len() * 2,
+        Some(Indices::U32(indices)) => indices.len() * 4,
+        None => 0,
+    };
+    vertices + indices
+}
+
+fn partition_network(
+    mut commands: Commands,
+    settings: Res<TileSettings>,
+    network: Res<RoadNetwork>,
+) {
+    let grid = partition(&settings, &network);
+    info!(
+        "{} segments in {} tiles of {} m",
+        network.segments.len(),
+        grid.tiles.len(),
+        settings.tile_size
+    );
+    commands.insert_resource(grid);
+}
+
+fn partition(settings: &TileSettings, network: &RoadNetwork) -> TileGrid {
+    let mut grid = TileGrid::default();
+    for (index, segment) in network.segments.iter().enumerate() {
+        let middle = (segment.start_pos + segment.end_pos) / 2.0;
+        grid.tiles
+            .entry(tile_of(settings, middle))
+            .or_default()
+            .push(index);
+    }
+    for indices in grid.tiles.values_mut() {
+        indices.sort_by_key(|&i| {
+            let segment = &network.segments[i];
+            (segment.road_id, segment.lane_section_id, segment.lane_id)
+        });
+    }
+    grid
+}
+
+fn mark_dirty(
+    roads: Query<Ref<OdrRoad>, (Changed<OdrRoad>, Without<OdrLanes>)>,
+    mut changed: EventReader<NetworkChanged>,
+    mut dirty: ResMut<DirtyRoads>,
+) {
+    // Roads spawned with the map are new rather than changed; those
+    // respawned later are named by the event.
+    dirty.0.extend(
+        roads
+            .iter()
+            .filter(|road| !road.is_added())
+            .map(|road| road.id),
+    );
+    dirty.0.extend(
+        changed
+            .read()
+            .flat_map(|event| event.changed.iter().copied()),
+    );
+}
+
+// Despawns a tile's entities, which frees their meshes.
+fn unload(commands: &mut Commands, index: &mut OdrEntityIndex, tile: LoadedTile) {
+    for entity in tile.entities {
+        index.remove(entity);
+        commands.entity(entity).despawn();
+    }
+}
+
+// Loads the tiles nearest the camera and unloads the rest.
+#[allow(clippy::too_many_arguments)]
+fn stream_tiles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut road_materials: ResMut<RoadMaterials>,
+    settings: Res<TileSettings>,
+    grid: Option<ResMut<TileGrid>>,
+    mut loaded: ResMut<LoadedTiles>,
+    mut index: ResMut<OdrEntityIndex>,
+    mut reload: EventReader<ReloadTiles>,
+    mut dirty: ResMut<DirtyRoads>,
+    network: Res<RoadNetwork>,
+    cache: Res<MeshCache>,
+    (debug, overlays, section, filter): (
+        Res<DebugView>,
+        Res<Overlays>,
+        Res<CrossSection>,
+        Res<Filter>,
+    ),
+    (style, mut styled): (Res<StyleSheet>, ResMut<StyleMaterials>),
+    origin: Res<RenderOrigin>,
+    cameras: Query<&Transform, With<MainCamera>>,
+) {
+    let (Some(mut grid), Ok(camera)) = (grid, cameras.get_single()) else {
+        return;
+    };
+    // Rebuilding is done by dropping everything; the loop below then loads
+    // the tiles afresh, nearest first.
+    if reload.read().count() > 0 {
+        for (_, tile) in loaded.0.drain() {
+            unload(&mut commands, &mut index, tile);
+        }
+    }
+    // After a reload or an edit the segments may be numbered anew or have
+    // moved. Tiles that show a changed road, or will, are dropped; the others
+    // stay as they are.
+    if !dirty.0.is_empty() {
+        let changed = std::mem::take(&mut dirty.0);
+        *grid = partition(&settings, &network);
+        let tiles: Vec<IVec2> = loaded
+            .0
+            .iter()
+            .filter(|(tile, loaded)| {
+                loaded.entities.iter().any(|&e| {
+                    matches!(index.element_of(e), Some(OdrId::Road(road)) if changed.contains(road))
+                })
+                    || grid.tiles.get(*tile).is_some_and(|indices| {
+                        indices
+                            .iter()
+                            .any(|&i| changed.contains(&network.segments[i].road_id))
+                    })
+            })
+            .map(|(tile, _)| *tile)
+            .collect();
+        for tile in tiles {
+            if let Some(tile) = loaded.0.remove(&tile) {
+                unload(&mut commands, &mut index, tile);
+            }
+        }
+    }
+
+    let clip = section
+        .station
+        .and_then(|(road_id, s)| cut(&network, road_id, s))
+        .map(|cut| cut.plane());
+
+    let camera = origin.0 + camera.translation.as_dvec3();
+    let ground = DVec2::new(camera.x, camera.z);
+    let unload_radius = settings.load_radius * UNLOAD_FACTOR;
+
+    // Rank the tiles in range by distance from the camera.
+    let center = tile_of(&settings, camera);
+    let reach = (unload_radius / settings.tile_size).ceil() as i32 + 1;
+    let mut candidates = Vec::new();
+    for x in -reach..=reach {
+        for y in -reach..=reach {
+            let tile = center + IVec2::new(x, y);
+            if !grid.tiles.contains_key(&tile) {
+                continue;
+            }
+            let distance = tile_distance(&settings, tile, ground);
+            if distance <= unload_radius {
+                candidates.push((distance, tile));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Keep or load tiles in that order while the budget allows.
+    let mut keep = HashMap::new();
+    let mut bytes = 0;
+    // The road components the meshes carry, built once the first tile loads.
+    let mut odr_roads = None;
+    let mut loads = 0;
+    for (distance, tile) in candidates {
+        if bytes >= settings.memory_budget {
+            break;
+        }
+        if let Some(existing) = loaded.0.remove(&tile) {
+            bytes += existing.bytes;
+            keep.insert(tile, existing);
+            continue;
+        }
+        if distance > settings.load_radius || loads == LOADS_PER_FRAME {
+            continue;
+        }
+        let mut entities = Vec::new();
+        let mut tile_bytes = 0;
+        let options = RoadMeshOptions {
+            debug: *debug,
+            overlays: *overlays,
+            clip,
+        };
+        let segments: Vec<&RoadSegment> = grid.tiles[&tile]
+            .iter()
+            .map(|&i| &network.segments[i])
+            .filter(|lane| filter.shows_lane(lane) && style.shows_lane(lane))
+            .collect();
+        let roads: Vec<&[&RoadSegment]> = segments
+            .chunk_by(|a, b| {
+                a.road_id == b.road_id
+                    && LaneClass::of(&a.lane_type) == LaneClass::of(&b.lane_type)
+                    && (!style.styles_lanes() || style.lane_rule(a) == style.lane_rule(b))
+            })
+            .collect();
+        let road_ids: Vec<u32> = roads.iter().map(|road| road[0].road_id).collect();
+        // The cache holds the meshes of whole roads.
+        let cache = (!filter.hides_lanes() && !style.styles_lanes()).then_some(&*cache);
+        let tessellated = cache.and_then(|cache| cache.read(tile, &road_ids));
+        let tessellated = tessellated.unwrap_or_else(|| {
+            let tessellated: Vec<_> = roads
+                .iter()
+                .map(|road| tessellate_road(road, settings.max_error))
+                .collect();
+            if let Some(cache) = cache {
+                cache.write(tile, &tessellated);
+            }
+            tessellated
+        });
+        for (road, road_meshes) in roads.into_iter().zip(tessellated) {
+            let surface = style.lane_rule(road[0]).map(|rule| {
+                styled.get(
+                    &mut materials,
+                    &style,
+                    &road_materials.theme,
+                    road_materials.weather,
+                    rule,
+                )
+            });
+            let road_id = road[0].road_id;
+            let odr_road = odr_roads.get_or_insert_with(|| odr::roads(&network))[&road_id].clone();
+            let (spawned, size) = spawn_road(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &mut road_materials,
+                &odr_road,
+                road_meshes,
+                road,
+                &options,
+                surface,
+            );
+            let mut ids = vec![OdrId::Road(road_id)];
+            for segment in road {
+                let lane = OdrId::Lane {
+                    road_id,
+                    lane_id: segment.lane_id,
+                };
+                if !ids.contains(&lane) {
+                    ids.push(lane);
+                }
+            }
+            if let Some(junction) = network.roads.get(&road_id).and_then(|info| info.junction) {
+                ids.push(OdrId::Junction(junction));
+            }
+            for &entity in &spawned {
+                index.insert(entity, ids.clone());
+            }
+            entities.extend(spawned);
+            tile_bytes += size;
+        }
+        bytes += tile_bytes;
+        loads += 1;
+        keep.insert(
+            tile,
+            LoadedTile {
+                entities,
+                bytes: tile_bytes,
+            },
+        );
+    }
+
+    // Whatever was not kept is out of range or over budget. Despawning drops
+    // the last handles to the meshes, which frees them on the GPU.
+    for (_, tile) in loaded.0.drain() {
+        unload(&mut commands, &mut index, tile);
+    }
+    loaded.0 = keep;
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::sample_maps::load;
+
+    // The junction sample in tiles of 50 m: road 1 (x 0 to 50) in the tile
+    // at the origin, the connecting road 3 and road 2 (x 50 to 110) in the
+    // next one east.
+    fn settings() -> TileSettings {
+        TileSettings {
+            tile_size: 50.0,
+            ..TileSettings::default()
+        }
+    }
+
+    #[test]
+    fn segments_go_to_the_tile_of_their_middle() {
+        let network = load("junction.xodr");
+        let grid = partition(&settings(), &network);
+        let roads = |tile: IVec2| -> Vec<u32> {
+            grid.tiles[&tile]
+                .iter()
+                .map(|&i| network.segments[i].road_id)
+                .collect()
+        };
+        assert_eq!(grid.tiles.len(), 2);
+        assert_eq!(roads(IVec2::new(0, 0)), [1]);
+        // Sorted by road, whatever the order in the network.
+        assert_eq!(roads(IVec2::new(1, 0)), [2, 3]);
+
+        assert_eq!(
+            tile_of(&settings(), DVec3::new(-0.5, 0.0, 120.0)),
+            IVec2::new(-1, 2)
+        );
+        assert_eq!(
+            tile_distance(&settings(), IVec2::new(1, 0), DVec2::new(20.0, 10.0)),
+            30.0
+        );
+        assert_eq!(
+            tile_distance(&settings(), IVec2::new(0, 0), DVec2::new(20.0, 10.0)),
+            0.0
+        );
+    }
+
+    // A world streaming the junction sample around a camera at the origin.
+    fn world(settings: TileSettings) -> World {
+        let network = load("junction.xodr");
+        let mut world = World::new();
+        world.insert_resource(partition(&settings, &network));
+        world.insert_resource(network);
+        world.insert_resource(settings);
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<StandardMaterial>>();
+        world.init_resource::<RoadMaterials>();
+        world.init_resource::<LoadedTiles>();
+        world.init_resource::<DirtyRoads>();
+        world.init_resource::<OdrEntityIndex>();
+        world.init_resource::<Events<ReloadTiles>>();
+        world.init_resource::<MeshCache>();
+        world.init_resource::<DebugView>();
+        world.init_resource::<Overlays>();
+        world.init_resource::<CrossSection>();
+        world.init_resource::<Filter>();
+        world.init_resource::<StyleSheet>();
+        world.init_resource::<StyleMaterials>();
+        world.init_resource::<RenderOrigin>();
+        world.spawn((Transform::default(), MainCamera));
+        world
+    }
+
+    fn loaded(world: &World) -> Vec<(IVec2, usize)> {
+        let mut tiles: Vec<(IVec2, usize)> = world
+            .resource::<LoadedTiles>()
+            .0
+            .iter()
+            .map(|(&tile, loaded)| (tile, loaded.bytes))
+            .collect();
+        tiles.sort_by_key(|(tile, _)| (tile.x, tile.y));
+        tiles
+    }
+
+    #[test]
+    fn tiles_over_the_budget_are_unloaded_farthest_first() {
+        let mut world = world(settings());
+        world.run_system_once(stream_tiles);
+        let tiles = loaded(&world);
+        assert_eq!(tiles.len(), 2, "{tiles:?}");
+        let (nearest, bytes) = tiles[0];
+        assert_eq!(nearest, IVec2::ZERO);
+        assert!(bytes > 0);
+        let meshes = world.query::<&Handle<Mesh>>().iter(&world).count();
+
+        // With room for the nearest tile only, the other one goes, and its
+        // entities with it.
+        world.resource_mut::<TileSettings>().memory_budget = bytes;
+        world.run_system_once(stream_tiles);
+        assert_eq!(loaded(&world), [(nearest, bytes)]);
+        assert!(world.query::<&Handle<Mesh>>().iter(&world).count() < meshes);
+    }
+
+    #[test]
+    fn tiles_out_of_range_are_not_loaded() {
+        let mut world = world(TileSettings {
+            load_radius: 500.0,
+            ..settings()
+        });
+        // The camera 1 km north of the map.
+        let mut cameras = world.query_filtered::<&mut Transform, With<MainCamera>>();
+        cameras.single_mut(&mut world).translation.z = -1_000.0;
+        world.run_system_once(stream_tiles);
+        assert!(loaded(&world).is_empty());
+
+        cameras.single_mut(&mut world).translation.z = -450.0;
+        world.run_system_once(stream_tiles);
+        assert_eq!(loaded(&world).len(), 2);
+    }
+}
@@ -0,0 +1,606 @@
+// Lane connectivity graph.
+//
+// A schematic of how the lanes around the selection connect, drawn as nodes
+// and links rather than geometry. It covers the selected road, or the whole
+// junction it belongs to, and the roads linked to it (the roads `isolate`
+// keeps). Each road is a box with its lane sections from left to right and
+// its lanes from top (leftmost) to bottom, each lane of a section a node.
+// Lanes continue into the next section of their road, and across a road
+// link into the lanes of the other road that begin where they end.
+// Lanes of a section that traffic may change between (see
+// `routing::lane_changes`) are joined too.
+//
+// Link errors stand out: a lane at a linked road end that meets no lane of
+// the other road is drawn in the error color, as is the link, and a lane at
+// an unlinked end of a junction road in the warning color. The selected
+// lanes are highlighted, and clicking a node selects its lane on the map.
+//
+// Keys: G shows or hides the graph.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+
+use crate::canvas::Canvas;
+use crate::i18n::Locale;
+use crate::isolate::isolated_roads;
+use crate::routing::lane_changes;
+use crate::selection::{Detail, Pick, Selection};
+use crate::theme::Theme;
+use crate::{ContactPoint, RoadNetwork};
+
+// Size of the graph image, in pixels.
+const WIDTH: i32 = 480;
+const HEIGHT: i32 = 360;
+
+// Lane ends closer than this, in meters, meet.
+const CONNECTION_TOLERANCE: f64 = 0.5;
+
+// Radius of a lane node, in pixels, and of the ring around a selected or
+// broken one.
+const NODE_RADIUS: f32 = 3.5;
+const RING_RADIUS: f32 = 6.0;
+
+// Margin around each road box, and the height of the road ID at its top,
+// in pixels.
+const CELL_MARGIN: f32 = 6.0;
+const LABEL_HEIGHT: f32 = 24.0;
+
+const BACKGROUND: [u8; 4] = [20, 20, 24, 200];
+const ROAD_BOX: [u8; 4] = [70, 70, 80, 255];
+const LANE: [u8; 4] = [200, 200, 200, 255];
+const CONTINUATION: [u8; 4] = [120, 120, 120, 255];
+const LANE_CHANGE: [u8; 4] = [90, 150, 230, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Health {
+    Connected,
+    // At an unlinked end of a junction road.
+    Dangling,
+    // At a linked end, but meeting no lane of the other road.
+    Broken,
+}
+
+// One lane of one lane section.
+#[derive(Debug, Clone)]
+struct Node {
+    segment: usize,
+    position: Vec2,
+    health: Health,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Graph {
+    // The road the graph was built around.
+    road_id: u32,
+    roads: usize,
+    boxes: Vec<(u32, Rect)>,
+    nodes: Vec<Node>,
+    // Lane continuations and lane changes within a road, and lane
+    // connections and broken links between roads, by node index.
+    continuations: Vec<(usize, usize)>,
+    changes: Vec<(usize, usize)>,
+    connections: Vec<(usize, usize)>,
+    broken: Vec<(usize, usize)>,
+}
+
+// The graph shown, if a road was selected since the panel was opened.
+#[derive(Resource, Debug, Clone, Default)]
+struct Shown(Option<Graph>);
+
+#[derive(Component)]
+struct TopologyPanel;
+
+#[derive(Component)]
+struct TopologyImage;
+
+#[derive(Component)]
+struct TopologyCaption;
+
+pub struct TopologyPlugin;
+
+impl Plugin for TopologyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Shown>()
+            .add_systems(Startup, spawn_panel)
+            .add_systems(
+                Update,
+                (toggle_panel, select_on_click, redraw_graph).chain(),
+            );
+    }
+}
+
+// The ends of the lanes at one end of a road: segment indices and points.
+fn lane_ends(network: &RoadNetwork, road_id: u32, contact: ContactPoint) -> Vec<(usize, DVec3)> {
+    let sections = network
+        .segments
+        .iter()
+        .filter(|segment| segment.road_id == road_id)
+        .map(|segment| segment.lane_section_id);
+    let section = match contact {
+        ContactPoint::Start => sections.min(),
+        ContactPoint::End => sections.max(),
+    };
+    network
+        .segments
+        .iter()
+        .enumerate()
+        .filter(|(_, segment)| {
+            segment.road_id == road_id && Some(segment.lane_section_id) == section
+        })
+        .filter_map(|(index, segment)| {
+            let centerline = segment.centerline();
+            let end = match contact {
+                ContactPoint::Start => centerline.first(),
+                ContactPoint::End => centerline.last(),
+            };
+            end.map(|end| (index, *end))
+        })
+        .collect()
+}
+
+fn build_graph(network: &RoadNetwork, road_id: u32) -> Graph {
+    let roads = isolated_roads(network, road_id);
+    let mut graph = Graph {
+        road_id,
+        roads: roads.len(),
+        ..default()
+    };
+    let columns = (roads.len() as f32).sqrt().ceil().max(1.0) as usize;
+    let rows = roads.len().div_ceil(columns).max(1);
+    let cell = Vec2::new(WIDTH as f32 / columns as f32, HEIGHT as f32 / rows as f32);
+
+    // Lays out each road in its cell: sections as columns, lanes as rows.
+    let mut node_of = BTreeMap::new();
+    for (index, &road) in roads.iter().enumerate() {
+        let corner = Vec2::new((index % columns) as f32, (index / columns) as f32) * cell;
+        let area = Rect::from_corners(
+            corner + CELL_MARGIN,
+            corner + cell - Vec2::new(CELL_MARGIN, CELL_MARGIN),
+        );
+        graph.boxes.push((road, area));
+        let area = Rect::from_corners(area.min + Vec2::new(0.0, LABEL_HEIGHT), area.max);
+        let lanes: Vec<usize> = (0..network.segments.len())
+            .filter(|&i| network.segments[i].road_id == road)
+            .collect();
+        let sections: BTreeSet<u32> = lanes
+            .iter()
+            .map(|&i| network.segments[i].lane_section_id)
+            .collect();
+        let lane_ids: BTreeSet<i32> = lanes.iter().map(|&i| network.segments[i].lane_id).collect();
+        let spread = |count: usize, rank: usize, from: f32, to: f32| {
+            from + (to - from) * (rank as f32 + 0.5) / count as f32
+        };
+        for &i in &lanes {
+            let segment = &network.segments[i];
+            let column = sections.range(..segment.lane_section_id).count();
+            // Left lanes (positive IDs) on top.
+            let row = lane_ids.range(segment.lane_id + 1..).count();
+            let position = Vec2::new(
+                spread(sections.len(), column, area.min.x, area.max.x),
+                spread(lane_ids.len(), row, area.min.y, area.max.y),
+            );
+            node_of.insert(i, graph.nodes.len());
+            graph.nodes.push(Node {
+                segment: i,
+                position,
+                health: Health::Connected,
+            });
+        }
+    }
+
+    for (&segment, &node) in &node_of {
+        let lane = &network.segments[segment];
+        let next = network.find_segment(lane.road_id, lane.lane_section_id + 1, lane.lane_id);
+        if let Some(next) = next.and_then(|next| node_of.get(&next)) {
+            graph.continuations.push((node, *next));
+        }
+    }
+    let changes = lane_changes(network);
+    for (&segment, &node) in &node_of {
+        for &other in &changes[segment] {
+            // Each pair once, whichever ways the change goes.
+            let once = other > segment || !changes[other].contains(&segment);
+            if let Some(&other) = node_of.get(&other).filter(|_| once) {
+                graph.changes.push((node, other));
+            }
+        }
+    }
+
+    // Road links within the graph, each once.
+    let mut links = BTreeSet::new();
+    for link in &network.links {
+        if roads.contains(&link.road_id) && roads.contains(&link.other_road_id) {
+            let ends = [
+                (link.road_id, link.contact == ContactPoint::End),
+                (link.other_road_id, link.other_contact == ContactPoint::End),
+            ];
+            links.insert((ends[0].min(ends[1]), ends[0].max(ends[1])));
+        }
+    }
+    let contact = |end: bool| {
+        if end {
+            ContactPoint::End
+        } else {
+            ContactPoint::Start
+        }
+    };
+    let mut linked_ends = BTreeSet::new();
+    for &((road, end), (other_road, other_end)) in &links {
+        linked_ends.insert((road, end));
+        linked_ends.insert((other_road, other_end));
+        let ours = lane_ends(network, road, contact(end));
+        let theirs = lane_ends(network, other_road, contact(other_end));
+        let meets = |a: &[(usize, DVec3)], b: &[(usize, DVec3)], i: usize| {
+            b.iter()
+                .filter(|(_, q)| a[i].1.distance(*q) < CONNECTION_TOLERANCE)
+                .map(|(j, _)| *j)
+                .collect::<Vec<_>>()
+        };
+        for i in 0..ours.len() {
+            for j in meets(&ours, &theirs, i) {
+                graph.connections.push((node_of[&ours[i].0], node_of[&j]));
+            }
+        }
+        // A lane meeting nothing is broken; its link is drawn to the
+        // nearest lane of the other road.
+        for (a, b) in [(&ours, &theirs), (&theirs, &ours)] {
+            for i in 0..a.len() {
+                if !meets(a, b, i).is_empty() {
+                    continue;
+                }
+                let node = node_of[&a[i].0];
+                graph.nodes[node].health = Health::Broken;
+                let nearest = b
+                    .iter()
+                    .min_by(|x, y| a[i].1.distance(x.1).total_cmp(&a[i].1.distance(y.1)));
+                if let Some((j, _)) = nearest {
+                    graph.broken.push((node, node_of[j]));
+                }
+            }
+        }
+    }
+
+    // Junction roads lead from one road to another; an end without a link
+    // leads nowhere.
+    for &road in &roads {
+        let in_junction = network
+            .roads
+            .get(&road)
+            .is_some_and(|info| info.junction.is_some());
+        if !in_junction {
+            continue;
+        }
+        for end in [false, true] {
+            if linked_ends.contains(&(road, end)) {
+                continue;
+            }
+            for (segment, _) in lane_ends(network, road, contact(end)) {
+                let node = &mut graph.nodes[node_of[&segment]];
+                if node.health == Health::Connected {
+                    node.health = Health::Dangling;
+                }
+            }
+        }
+    }
+    graph
+}
+
+fn color(color: Color) -> [u8; 4] {
+    color.as_rgba_u8()
+}
+
+fn disc(canvas: &mut Canvas, center: Vec2, radius: f32, color: [u8; 4]) {
+    let r = radius.ceil() as i32;
+    for y in -r..=r {
+        for x in -r..=r {
+            if Vec2::new(x as f32, y as f32).length() <= radius {
+                canvas.set(center.x as i32 + x, center.y as i32 + y, color);
+            }
+        }
+    }
+}
+
+fn paint(graph: &Graph, network: &RoadNetwork, pick: Option<Pick>, theme: &Theme) -> Image {
+    let mut canvas = Canvas::new(WIDTH, HEIGHT);
+    canvas.fill(|_| true, BACKGROUND);
+    for (road, area) in &graph.boxes {
+        let corners = [
+            area.min,
+            Vec2::new(area.max.x, area.min.y),
+            area.max,
+            Vec2::new(area.min.x, area.max.y),
+        ];
+        for k in 0..4 {
+            canvas.line(corners[k], corners[(k + 1) % 4], ROAD_BOX);
+        }
+        canvas.number(
+            *road,
+            Vec2::new(area.center().x, area.min.y + LABEL_HEIGHT / 2.0),
+            ROAD_BOX,
+        );
+    }
+
+    let position = |node: usize| graph.nodes[node].position;
+    for &(a, b) in &graph.continuations {
+        canvas.line(position(a), position(b), CONTINUATION);
+    }
+    for &(a, b) in &graph.changes {
+        canvas.line(position(a), position(b), LANE_CHANGE);
+    }
+    for &(a, b) in &graph.connections {
+        canvas.line(position(a), position(b), color(theme.pass));
+    }
+    for &(a, b) in &graph.broken {
+        canvas.line(position(a), position(b), color(theme.error));
+    }
+
+    for node in &graph.nodes {
+        let lane = &network.segments[node.segment];
+        let selected = pick.is_some_and(|pick| {
+            pick.road_id == lane.road_id
+                && match pick.detail {
+                    Detail::Road => true,
+                    Detail::Lane => pick.lane_id == lane.lane_id,
+                    Detail::Section | Detail::Point => pick.segment == node.segment,
+                }
+        });
+        if selected {
+            disc(
+                &mut canvas,
+                node.position,
+                RING_RADIUS,
+                color(theme.highlight),
+            );
+        }
+        let fill = match node.health {
+            Health::Connected => LANE,
+            Health::Dangling => color(theme.warning),
+            Health::Broken => color(theme.error),
+        };
+        disc(&mut canvas, node.position, NODE_RADIUS, fill);
+    }
+    canvas.into_image()
+}
+
+impl Graph {
+    // The node under a point of the image, if any.
+    fn node_at(&self, point: Vec2) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .filter(|node| node.position.distance(point) <= RING_RADIUS)
+            .min_by(|a, b| {
+                a.position
+                    .distance(point)
+                    .total_cmp(&b.position.distance(point))
+            })
+    }
+
+    fn contains(&self, road_id: u32) -> bool {
+        self.boxes.iter().any(|(road, _)| *road == road_id)
+    }
+
+    fn count(&self, health: Health) -> usize {
+        self.nodes
+            .iter()
+            .filter(|node| node.health == health)
+            .count()
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(10.0),
+                    top: Val::Percent(35.0),
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            TopologyPanel,
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 13.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                TopologyCaption,
+            ));
+            panel.spawn((
+                ImageBundle {
+                    style: Style {
+                        width: Val::Px(WIDTH as f32),
+                        height: Val::Px(HEIGHT as f32),
+                        ..default()
+                    },
+                    ..default()
+                },
+                // Interaction also keeps clicks on the graph from picking the
+                // road behind it.
+                Interaction::default(),
+                RelativeCursorPosition::default(),
+                TopologyImage,
+            ));
+        });
+}
+
+fn toggle_panel(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut panels: Query<&mut Visibility, With<TopologyPanel>>,
+) {
+    if !keys.just_pressed(KeyCode::KeyG) {
+        return;
+    }
+    for mut visibility in &mut panels {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+// Selects the lane of a clicked node.
+#[allow(clippy::type_complexity)]
+fn select_on_click(
+    images: Query<
+        (&Interaction, &RelativeCursorPosition),
+        (Changed<Interaction>, With<TopologyImage>),
+    >,
+    shown: Res<Shown>,
+    network: Res<RoadNetwork>,
+    mut selection: ResMut<Selection>,
+) {
+    let Some(graph) = &shown.0 else {
+        return;
+    };
+    for (interaction, cursor) in &images {
+        let Some(normalized) = cursor
+            .normalized
+            .filter(|_| *interaction == Interaction::Pressed)
+        else {
+            continue;
+        };
+        let point = normalized * Vec2::new(WIDTH as f32, HEIGHT as f32);
+        let Some(node) = graph.node_at(point) else {
+            continue;
+        };
+        let lane = &network.segments[node.segment];
+        let centerline = lane.centerline();
+        selection.0 = Some(Pick {
+            detail: Detail::Section,
+            segment: node.segment,
+            road_id: lane.road_id,
+            lane_section_id: lane.lane_section_id,
+            lane_id: lane.lane_id,
+            s: lane.start_s,
+            position: centerline.first().copied().unwrap_or(lane.start_pos),
+            t: None,
+        });
+    }
+}
+
+// Rebuilds the graph when the selection leaves it or the network changes,
+// and repaints it when the selection within it changes.
+#[allow(clippy::too_many_arguments)]
+fn redraw_graph(
+    selection: Res<Selection>,
+    network: Res<RoadNetwork>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut shown: ResMut<Shown>,
+    panels: Query<Ref<Visibility>, With<TopologyPanel>>,
+    mut images: Query<&mut UiImage, With<TopologyImage>>,
+    mut captions: Query<&mut Text, With<TopologyCaption>>,
+    mut assets: ResMut<Assets<Image>>,
+) {
+    let Ok(visibility) = panels.get_single() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    let opened = visibility.is_changed();
+    if !opened && !selection.is_changed() && !network.is_changed() && !theme.is_changed() {
+        return;
+    }
+    if let Some(road_id) = selection.road_id() {
+        let stale = network.is_changed()
+            || shown
+                .0
+                .as_ref()
+                .is_none_or(|graph| !graph.contains(road_id));
+        if stale {
+            shown.0 = Some(build_graph(&network, road_id));
+        }
+    } else if network.is_changed() {
+        shown.0 = None;
+    }
+
+    let caption = match &shown.0 {
+        Some(graph) => locale.text(
+            "topology-title",
+            &[
+                ("road", &graph.road_id),
+                ("roads", &graph.roads),
+                ("broken", &graph.count(Health::Broken)),
+                ("dangling", &graph.count(Health::Dangling)),
+            ],
+        ),
+        None => locale.text("topology-hint", &[]),
+    };
+    for mut text in &mut captions {
+        if let Some(section) = text.sections.first_mut() {
+            section.value.clone_from(&caption);
+        }
+    }
+    let texture = match &shown.0 {
+        Some(graph) => assets.add(paint(graph, &network, selection.0, &theme)),
+        None => Handle::default(),
+    };
+    for mut image in &mut images {
+        // The previous texture is freed with its last handle.
+        image.texture = texture.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::load;
+
+    fn health(network: &RoadNetwork, graph: &Graph, road: u32) -> Vec<Health> {
+        graph
+            .nodes
+            .iter()
+            .filter(|node| network.segments[node.segment].road_id == road)
+            .map(|node| node.health)
+            .collect()
+    }
+
+    #[test]
+    fn junction_lanes_connect_across_the_road_links() {
+        let mut network = load("junction.xodr");
+        let graph = build_graph(&network, 3);
+        assert_eq!((graph.roads, graph.nodes.len()), (3, 3));
+        assert_eq!(graph.connections.len(), 2);
+        assert!(graph.broken.is_empty());
+        assert!(graph
+            .nodes
+            .iter()
+            .all(|node| node.health == Health::Connected));
+
+        // Moved aside, the east road no longer meets the connecting road.
+        let mut moved = network.clone();
+        for segment in moved.segments.iter_mut().filter(|s| s.road_id == 2) {
+            for point in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                point.z += 5.0;
+            }
+        }
+        let graph = build_graph(&moved, 3);
+        assert_eq!(graph.connections.len(), 1);
+        assert_eq!(graph.broken.len(), 2);
+        assert_eq!(health(&moved, &graph, 2), [Health::Broken]);
+        assert_eq!(health(&moved, &graph, 3), [Health::Broken]);
+
+        // Unlinked, the end of the connecting road leads nowhere.
+        network
+            .links
+            .retain(|link| link.road_id != 2 && link.other_road_id != 2);
+        let graph = build_graph(&network, 3);
+        assert_eq!(graph.roads, 2);
+        assert_eq!(health(&network, &graph, 3), [Health::Dangling]);
+    }
+}
@@ -0,0 +1,114 @@
+// Matched GPS traces.
+//
+// `match <trace.csv>` in the console snaps a recorded trace to the lanes
+// (see `map_matching`) and shows it: the raw fixes and their matched
+// positions as dots, joined by a line that shows how far each was moved,
+// and the matched lane path as a line along the lanes. `match export
+// <file>` writes the matched path as a route (`.csv` or `.xosc`, as
+// `export-route` does).
+
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::map_matching::{match_trace, read_trace, MatchedTrace};
+use crate::origin::RenderOrigin;
+use crate::ribbons::LINE_LIFT;
+use crate::route_export::write_route;
+use crate::theme::Theme;
+use crate::{camera_orbit, RoadNetwork};
+
+// Radius of the dots, in meters.
+const DOT_RADIUS: f32 = 0.4;
+
+const OFFSET_LINE: Color = Color::rgba(0.8, 0.8, 0.8, 0.6);
+
+// The traces matched in this session.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MatchedTraces(pub Vec<MatchedTrace>);
+
+pub struct TracePlugin;
+
+impl Plugin for TracePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchedTraces>().add_systems(
+            Update,
+            (forget_traces, draw_traces.after(camera_orbit)).chain(),
+        );
+    }
+}
+
+// Backs the console's `match` commands.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["clear"] => {
+            world.resource_mut::<MatchedTraces>().0.clear();
+            Ok(String::new())
+        }
+        ["export", file] => {
+            let traces = world.resource::<MatchedTraces>();
+            let trace = traces.0.last().ok_or("no trace; match one with `match`")?;
+            let path = Path::new(file);
+            write_route(world.resource::<RoadNetwork>(), &trace.route, path)
+                .map_err(|e| format!("{file}: {e}"))?;
+            Ok(format!("wrote {file}"))
+        }
+        [file] => {
+            let text = std::fs::read_to_string(file).map_err(|e| format!("{file}: {e}"))?;
+            let trace = read_trace(&text, world.resource::<RoadNetwork>())
+                .map_err(|e| format!("{file}: {e}"))?;
+            let matched = match_trace(world.resource::<RoadNetwork>(), &trace)
+                .ok_or("no fix of the trace is near a lane")?;
+            let message = format!(
+                "matched {} of {} fix(es) to {} lane(s), {:.0} m",
+                matched.matched.iter().flatten().count(),
+                trace.len(),
+                matched.route.segments.len(),
+                matched.route.length
+            );
+            world.resource_mut::<MatchedTraces>().0.push(matched);
+            Ok(message)
+        }
+        _ => {
+            Err("expected `match <trace.csv>`, `match export <file>` or `match clear`".to_string())
+        }
+    }
+}
+
+// Drops the traces when the network changes under them.
+fn forget_traces(network: Res<RoadNetwork>, mut traces: ResMut<MatchedTraces>) {
+    if network.is_changed() && !network.is_added() && !traces.0.is_empty() {
+        traces.0.clear();
+    }
+}
+
+fn draw_traces(
+    traces: Res<MatchedTraces>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    let lift = |p| origin.to_render(p) + Vec3::Y * LINE_LIFT;
+    for trace in &traces.0 {
+        let path = trace.route.polyline(&network);
+        gizmos.linestrip(
+            path.iter().map(|point| lift(point.position)),
+            theme.trajectory,
+        );
+        for (raw, matched) in trace.raw.iter().zip(&trace.matched) {
+            let raw = lift(*raw);
+            match matched {
+                Some(matched) => {
+                    let matched = lift(*matched);
+                    gizmos.line(raw, matched, OFFSET_LINE);
+                    gizmos.sphere(raw, Quat::IDENTITY, DOT_RADIUS, theme.warning);
+                    gizmos.sphere(matched, Quat::IDENTITY, DOT_RADIUS, theme.pass);
+                }
+                None => {
+                    gizmos.sphere(raw, Quat::IDENTITY, DOT_RADIUS, theme.error);
+                }
+            }
+        }
+    }
+}
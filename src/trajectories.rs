@@ -0,0 +1,43 @@
+// Trajectory overlays.
+//
+// Trajectories, e.g. recorded drives or routes written by `export-route`,
+// are drawn as lines over the map. They are read from route CSV files
+// (`s,x,y,z,...` in map coordinates) named with `--trajectory` or in a
+// project.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::camera_orbit;
+use crate::origin::RenderOrigin;
+use crate::ribbons::{Ribbons, LINE_LIFT};
+use crate::theme::Theme;
+
+// The trajectories shown, as viewer-frame polylines.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Trajectories(pub Vec<Vec<DVec3>>);
+
+pub struct TrajectoryPlugin;
+
+impl Plugin for TrajectoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Trajectories>()
+            .add_systems(Update, draw_trajectories.after(camera_orbit));
+    }
+}
+
+fn draw_trajectories(
+    trajectories: Res<Trajectories>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut ribbons: ResMut<Ribbons>,
+) {
+    for trajectory in &trajectories.0 {
+        ribbons.linestrip(
+            trajectory
+                .iter()
+                .map(|p| origin.to_render(*p) + Vec3::Y * LINE_LIFT),
+            theme.trajectory,
+        );
+    }
+}
@@ -0,0 +1,357 @@
+// Transactions around edits of the model.
+//
+// Edits change the `RoadNetwork` inside a transaction. `Transaction::begin`
+// takes a snapshot of the roads about to change (see `edit::Snapshot`) and
+// notes the integrity problems they already have; `commit` checks them
+// again and, if the edit brought new problems, puts the snapshot back and
+// returns the problems, so that neither a command nor a script can leave the
+// map broken without saying so. Committed, the snapshot goes onto the undo
+// stack.
+//
+// The checks are about the structure of the map rather than its design,
+// which `validation` reviews:
+//
+// - links point at roads that exist;
+// - linked ends meet, within `GAP`, and carry on each other's heading,
+//   within `KINK_ANGLE`;
+// - the lane sections of a road are numbered in order and follow each other
+//   without a gap or an overlap, all lanes of a section spanning it.
+//
+// An edit that means to leave a gap or a kink, like moving a road with
+// continuity off, allows those.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::cross_section::{cut, road_range};
+use crate::edit::{remember, NetworkChanged, Snapshot};
+use crate::odr::touch;
+use crate::{ContactPoint, RoadNetwork};
+
+// How far apart linked ends may be, in meters.
+const GAP: f64 = 0.1;
+
+// The largest heading change between linked ends, in degrees.
+const KINK_ANGLE: f64 = 5.0;
+
+// Stations closer than this are the same, in meters.
+const EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProblemKind {
+    // A link to a road that does not exist.
+    MissingRoad,
+    // Linked ends that do not meet.
+    Gap,
+    // Linked ends that meet at an angle.
+    Kink,
+    // Lane sections out of order or apart.
+    Sections,
+}
+
+impl ProblemKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProblemKind::MissingRoad => "missing road",
+            ProblemKind::Gap => "gap",
+            ProblemKind::Kink => "kink",
+            ProblemKind::Sections => "lane sections",
+        }
+    }
+}
+
+// An integrity problem of the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub road_id: u32,
+    // The road at the other end of the link concerned, if any.
+    pub other_road_id: Option<u32>,
+    pub message: String,
+}
+
+impl Problem {
+    // What makes two problems the same one, before and after an edit.
+    fn key(&self) -> (ProblemKind, u32, Option<u32>) {
+        match self.other_road_id {
+            Some(other) if other < self.road_id => (self.kind, other, Some(self.road_id)),
+            other => (self.kind, self.road_id, other),
+        }
+    }
+}
+
+// One end of a road: its position on the reference line, and the direction
+// leading out of the road there.
+fn road_end(network: &RoadNetwork, road_id: u32, contact: ContactPoint) -> Option<(DVec3, DVec3)> {
+    let (first, last) = road_range(network, road_id)?;
+    let (s, outwards) = match contact {
+        ContactPoint::Start => (first, -1.0),
+        ContactPoint::End => (last, 1.0),
+    };
+    let cut = cut(network, road_id, s)?;
+    Some((cut.reference, cut.direction * outwards))
+}
+
+// The problems of the given roads and of the links from or to them.
+pub fn check(network: &RoadNetwork, roads: &[u32]) -> Vec<Problem> {
+    let existing: BTreeSet<u32> = network.segments.iter().map(|s| s.road_id).collect();
+    let mut problems = Vec::new();
+    // Links are kept from both sides; each is checked once.
+    let mut seen = BTreeSet::new();
+    for link in network
+        .links
+        .iter()
+        .filter(|link| roads.contains(&link.road_id) || roads.contains(&link.other_road_id))
+    {
+        let (a, b) = (link.road_id, link.other_road_id);
+        let ends = (
+            (a, link.contact == ContactPoint::End),
+            (b, link.other_contact == ContactPoint::End),
+        );
+        if !seen.insert(if a <= b { ends } else { (ends.1, ends.0) }) {
+            continue;
+        }
+        if let Some(missing) = [a, b].into_iter().find(|id| !existing.contains(id)) {
+            problems.push(Problem {
+                kind: ProblemKind::MissingRoad,
+                road_id: a,
+                other_road_id: Some(b),
+                message: format!("road {a} is linked to road {b}, but there is no road {missing}"),
+            });
+            continue;
+        }
+        let (Some((here, out)), Some((there, back))) = (
+            road_end(network, a, link.contact),
+            road_end(network, b, link.other_contact),
+        ) else {
+            continue;
+        };
+        let gap = here.distance(there);
+        if gap > GAP {
+            problems.push(Problem {
+                kind: ProblemKind::Gap,
+                road_id: a,
+                other_road_id: Some(b),
+                message: format!("roads {a} and {b} are linked but {gap:.2} m apart"),
+            });
+        }
+        let angle = out.angle_between(-back).to_degrees();
+        if angle > KINK_ANGLE {
+            problems.push(Problem {
+                kind: ProblemKind::Kink,
+                road_id: a,
+                other_road_id: Some(b),
+                message: format!("roads {a} and {b} meet at {angle:.1}°"),
+            });
+        }
+    }
+
+    for &road_id in roads.iter().filter(|id| existing.contains(id)) {
+        let mut sections: BTreeMap<u32, Vec<(f64, f64)>> = BTreeMap::new();
+        for lane in network.segments.iter().filter(|s| s.road_id == road_id) {
+            sections
+                .entry(lane.lane_section_id)
+                .or_default()
+                .push((lane.start_s, lane.end_s));
+        }
+        let mut problem = |message: String| {
+            problems.push(Problem {
+                kind: ProblemKind::Sections,
+                road_id,
+                other_road_id: None,
+                message,
+            })
+        };
+        let mut previous: Option<(u32, f64)> = None;
+        for (&section, spans) in &sections {
+            let (start, end) = spans[0];
+            if spans
+                .iter()
+                .any(|(s, e)| (s - start).abs() > EPSILON || (e - end).abs() > EPSILON)
+            {
+                problem(format!(
+                    "the lanes of section {section} of road {road_id} span different stations"
+                ));
+                break;
+            }
+            if let Some((last, last_end)) = previous {
+                if section != last + 1 {
+                    problem(format!(
+                        "road {road_id} has lane section {section} after section {last}"
+                    ));
+                    break;
+                }
+                if (start - last_end).abs() > EPSILON {
+                    problem(format!(
+                        "section {section} of road {road_id} starts at {start:.2}, \
+                         not where section {last} ends ({last_end:.2})"
+                    ));
+                    break;
+                }
+            }
+            previous = Some((section, end));
+        }
+    }
+    problems
+}
+
+// Formats problems one per line.
+pub fn describe(problems: &[Problem]) -> String {
+    let mut out = String::new();
+    for problem in problems {
+        let _ = write!(out, "\n  {}: {}", problem.kind.name(), problem.message);
+    }
+    out
+}
+
+// An edit in progress.
+pub struct Transaction {
+    snapshot: Snapshot,
+    roads: Vec<u32>,
+    // The problems the roads had before, which the edit may keep.
+    known: BTreeSet<(ProblemKind, u32, Option<u32>)>,
+    allowed: Vec<ProblemKind>,
+}
+
+impl Transaction {
+    // Starts an edit of the given roads, described as the edit, e.g.
+    // "splitting road 4".
+    pub fn begin(network: &RoadNetwork, description: String, roads: &[u32]) -> Self {
+        Self {
+            snapshot: Snapshot::take(network, description, roads),
+            roads: roads.to_vec(),
+            known: check(network, roads).iter().map(Problem::key).collect(),
+            allowed: Vec::new(),
+        }
+    }
+
+    // Also covers a road the edit adds.
+    pub fn adding(mut self, road_id: u32) -> Self {
+        self.snapshot = self.snapshot.adding(road_id);
+        self.roads.push(road_id);
+        self
+    }
+
+    // Lets the edit leave problems of a kind.
+    pub fn allowing(mut self, kind: ProblemKind) -> Self {
+        self.allowed.push(kind);
+        self
+    }
+
+    // Puts the roads back as they were.
+    pub fn roll_back(self, network: &mut RoadNetwork) {
+        self.snapshot.restore(network);
+    }
+
+    // Checks the edited roads and returns the snapshot to undo the edit
+    // with, or rolls the edit back and returns the problems it brought.
+    pub fn commit(self, network: &mut RoadNetwork) -> Result<Snapshot, Vec<Problem>> {
+        let problems: Vec<Problem> = check(network, &self.roads)
+            .into_iter()
+            .filter(|problem| {
+                !self.known.contains(&problem.key()) && !self.allowed.contains(&problem.kind)
+            })
+            .collect();
+        if problems.is_empty() {
+            Ok(self.snapshot)
+        } else {
+            self.roll_back(network);
+            Err(problems)
+        }
+    }
+}
+
+// Runs an edit in a transaction. `change` edits the network and returns a
+// message and the roads it added. The roads that changed are then flagged
+// as dirty and their elements as read dropped, and the edit can be undone.
+pub fn run(
+    world: &mut World,
+    transaction: Transaction,
+    change: impl FnOnce(&mut RoadNetwork) -> Result<(String, Vec<u32>), String>,
+) -> Result<String, String> {
+    let mut network = world.resource_mut::<RoadNetwork>();
+    let roads = transaction.roads.clone();
+    let changed = match change(&mut network) {
+        Ok((message, added)) => {
+            let transaction = added.into_iter().fold(transaction, Transaction::adding);
+            match transaction.commit(&mut network) {
+                Ok(snapshot) => Ok((message, snapshot)),
+                Err(problems) => Err(format!(
+                    "the edit was undone, as it left {} problem(s):{}",
+                    problems.len(),
+                    describe(&problems)
+                )),
+            }
+        }
+        Err(e) => {
+            transaction.roll_back(&mut network);
+            Err(e)
+        }
+    };
+    let (message, snapshot) = match changed {
+        Ok(done) => done,
+        Err(e) => {
+            // Rolling back may have put the lanes in another order.
+            world.send_event(NetworkChanged { changed: roads });
+            return Err(e);
+        }
+    };
+    let changed = snapshot.changed(&network);
+    // The elements as read no longer describe the roads; exports write
+    // them from the model.
+    for road_id in &changed {
+        if let Some(info) = network.roads.get_mut(road_id) {
+            info.xml.clear();
+        }
+    }
+    remember(world, snapshot);
+    for &road_id in &changed {
+        touch(world, road_id);
+    }
+    world.send_event(NetworkChanged { changed });
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample_maps::{load, SAMPLES};
+
+    #[test]
+    fn transactions_check_edits() {
+        for name in SAMPLES {
+            let network = load(name);
+            let roads: Vec<u32> = network.roads.keys().copied().collect();
+            assert_eq!(check(&network, &roads), Vec::new(), "{name}");
+        }
+
+        let mut network = load("junction.xodr");
+        let mut original = network.clone();
+        original.segments.sort_by_key(|s| s.road_id);
+        let shift = |network: &mut RoadNetwork| {
+            for segment in network.segments.iter_mut().filter(|s| s.road_id == 1) {
+                for p in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                    p.z -= 2.0;
+                }
+            }
+        };
+        // Moving road 1 alone opens a gap to the connecting road 3.
+        let moving = Transaction::begin(&network, "moving road 1".to_string(), &[1, 3]);
+        shift(&mut network);
+        let problems = moving.commit(&mut network).unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].kind, ProblemKind::Gap);
+        // Rolled back, though not in the same order.
+        network.segments.sort_by_key(|s| s.road_id);
+        assert!(network.segments == original.segments);
+
+        let moving = Transaction::begin(&network, "moving road 1".to_string(), &[1, 3])
+            .allowing(ProblemKind::Gap);
+        shift(&mut network);
+        let undo = moving.commit(&mut network).unwrap();
+        assert_eq!(undo.changed(&network), vec![1]);
+    }
+}
@@ -0,0 +1,116 @@
+// Load-time coordinate transforms.
+//
+// Data from different tools rarely agrees on conventions: some write Y-up
+// coordinates, some use a rotated local grid, most carry large projected
+// (UTM) offsets. Each layer can therefore be given a transform that brings
+// its source coordinates into the shared map frame (x east, y north, z up).
+// Like all geometry, transforms are applied in f64, so shifting by a large
+// offset does not lose precision.
+
+use bevy::math::{DVec2, DVec3};
+use bevy::prelude::Component;
+
+// Which source axis points up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UpAxis {
+    // x east, y north, z up: OpenDRIVE, LAS, most GIS data.
+    #[default]
+    Z,
+    // x east, y up, z south: glTF and most game engines.
+    Y,
+}
+
+// A transform from a layer's source coordinates into the map frame. The axis
+// convention is resolved first, then the rotation, then the offset.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoadTransform {
+    pub up: UpAxis,
+    // Counter-clockwise rotation about the up axis, in radians.
+    pub rotation: f64,
+    // Shift in meters, applied after the rotation.
+    pub offset: DVec3,
+}
+
+impl LoadTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // Maps a source position into the map frame.
+    pub fn apply(&self, p: DVec3) -> DVec3 {
+        let p = match self.up {
+            UpAxis::Z => p,
+            UpAxis::Y => DVec3::new(p.x, -p.z, p.y),
+        };
+        let ground = DVec2::from_angle(self.rotation).rotate(p.truncate());
+        ground.extend(p.z) + self.offset
+    }
+
+    // Maps a map-frame position back into the source coordinates.
+    pub fn source_position(&self, p: DVec3) -> DVec3 {
+        let p = p - self.offset;
+        let ground = DVec2::from_angle(-self.rotation).rotate(p.truncate());
+        match self.up {
+            UpAxis::Z => ground.extend(p.z),
+            UpAxis::Y => DVec3::new(ground.x, p.z, -ground.y),
+        }
+    }
+
+    // Maps a source position into the viewer frame (Y up, north along -z).
+    pub fn viewer_position(&self, p: DVec3) -> DVec3 {
+        let map = self.apply(p);
+        // Adding zero turns the -0.0 of points on the x axis into 0.0, so
+        // that exports do not write them as "-0".
+        DVec3::new(map.x, map.z, -map.y + 0.0)
+    }
+
+    // Applies the transform to a position that was loaded into the viewer
+    // frame without one.
+    pub fn apply_viewer(&self, p: DVec3) -> DVec3 {
+        self.viewer_position(DVec3::new(p.x, -p.z, p.y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn source_positions_undo_the_transform() {
+        let points = [
+            DVec3::ZERO,
+            DVec3::new(12.5, -3.25, 7.0),
+            DVec3::new(-4.0e5, 5.6e6, -12.0),
+        ];
+        for up in [UpAxis::Z, UpAxis::Y] {
+            let transform = LoadTransform {
+                up,
+                rotation: PI / 7.0,
+                offset: DVec3::new(-500_000.0, -5_400_000.0, 12.0),
+            };
+            for p in points {
+                let back = transform.source_position(transform.apply(p));
+                assert!(back.distance(p) < 1e-6, "{up:?}: {p} came back as {back}");
+            }
+        }
+    }
+
+    #[test]
+    fn y_up_sources_are_turned_z_up() {
+        let transform = LoadTransform {
+            up: UpAxis::Y,
+            rotation: PI / 2.0,
+            ..LoadTransform::default()
+        };
+        // One meter up, one east and one south in the source.
+        let p = transform.apply(DVec3::new(1.0, 1.0, 1.0));
+        // South becomes -y, and the quarter turn takes east to north and
+        // south to east.
+        assert!(p.distance(DVec3::new(1.0, 1.0, 1.0)) < 1e-12, "{p}");
+        let viewer = LoadTransform::default().viewer_position(DVec3::new(2.0, 0.0, 3.0));
+        assert_eq!(viewer, DVec3::new(2.0, 3.0, 0.0));
+        assert!(viewer.z.is_sign_positive());
+    }
+}
@@ -0,0 +1,118 @@
+// Speed units.
+//
+// Speed limits are kept in m/s everywhere in the network, whatever unit the
+// map gives them in: OpenDRIVE `<speed>` and `<type>` records say km/h, mph
+// or m/s in their `unit` attribute (km/h if they say nothing), and OSM
+// `maxspeed` tags km/h or mph. They are converted once, when read. Files and
+// settings that are documented in km/h (style sheets, filters, design rule
+// speed classes, route CSVs) stay in km/h and convert as they compare.
+//
+// The viewer shows speeds in the unit of `SpeedUnit`: km/h unless `speed-unit
+// <unit>` in the console or `view --speed-unit <unit>` says mph or m/s.
+
+use bevy::prelude::*;
+
+// One km/h and one mph, in m/s.
+pub const KMH: f64 = 1.0 / 3.6;
+pub const MPH: f64 = 0.44704;
+
+// A speed in m/s from a value in an OpenDRIVE unit. Unknown units are taken
+// as km/h, OpenDRIVE's default.
+pub fn speed_from(value: f64, unit: &str) -> f64 {
+    match unit.trim() {
+        "m/s" => value,
+        "mph" => value * MPH,
+        _ => value / 3.6,
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpeedUnit {
+    #[default]
+    Kmh,
+    Mph,
+    MetersPerSecond,
+}
+
+pub const SPEED_UNITS: [SpeedUnit; 3] =
+    [SpeedUnit::Kmh, SpeedUnit::Mph, SpeedUnit::MetersPerSecond];
+
+impl SpeedUnit {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeedUnit::Kmh => "km/h",
+            SpeedUnit::Mph => "mph",
+            SpeedUnit::MetersPerSecond => "m/s",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<SpeedUnit, String> {
+        let name = match name {
+            "kmh" | "kph" => "km/h",
+            "ms" => "m/s",
+            name => name,
+        };
+        SPEED_UNITS
+            .into_iter()
+            .find(|unit| unit.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = SPEED_UNITS.iter().map(|unit| unit.name()).collect();
+                format!("unknown speed unit `{name}` (try {})", names.join(", "))
+            })
+    }
+
+    // A speed in m/s, in this unit.
+    pub fn value(self, speed: f64) -> f64 {
+        match self {
+            SpeedUnit::Kmh => speed * 3.6,
+            SpeedUnit::Mph => speed / MPH,
+            SpeedUnit::MetersPerSecond => speed,
+        }
+    }
+
+    // A speed in m/s, written in this unit to the precision limits are
+    // posted in.
+    pub fn format(self, speed: f64) -> String {
+        let value = self.value(speed);
+        match self {
+            SpeedUnit::MetersPerSecond => format!("{value:.1} {}", self.name()),
+            _ => format!("{value:.0} {}", self.name()),
+        }
+    }
+}
+
+// `speed-unit` in the console: names the unit speeds are shown in, or
+// switches to another.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<SpeedUnit>().name().to_string()),
+        [name] => {
+            *world.resource_mut::<SpeedUnit>() = SpeedUnit::parse(name)?;
+            Ok(String::new())
+        }
+        _ => Err("expected km/h, mph or m/s".to_string()),
+    }
+}
+
+pub struct UnitPlugin;
+
+impl Plugin for UnitPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpeedUnit>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_units() {
+        let speed = 50.0 / 3.6;
+        assert_eq!(SpeedUnit::Kmh.format(speed), "50 km/h");
+        assert_eq!(SpeedUnit::Mph.format(speed), "31 mph");
+        assert_eq!(SpeedUnit::MetersPerSecond.format(speed), "13.9 m/s");
+        assert_eq!(SpeedUnit::parse("kmh"), Ok(SpeedUnit::Kmh));
+        assert!(SpeedUnit::parse("knots").is_err());
+    }
+}
@@ -0,0 +1,403 @@
+// Map validation.
+//
+// Checks look for problems in a road network and report them as issues, each
+// tied to a road and station and a position on the map. The `validate`
+// command prints the report; in the viewer every issue is marked on the map
+// with a sphere colored by severity and listed in the top right corner.
+// Clicking an entry of the list moves the camera to the issue.
+//
+// Keys: I shows or hides the issue markers and list, J jumps to the next
+// issue (Shift+J to the previous one).
+
+use std::fmt::Write as _;
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+
+use crate::camera_tween::{fly_to, OrbitPose};
+use crate::edit::NetworkChanged;
+use crate::i18n::Locale;
+use crate::legend::{FillLegend, Legend};
+use crate::origin::RenderOrigin;
+use crate::theme::Theme;
+use crate::{
+    camera_orbit, clearance, design_rules, lane_width, mesh_qa, overlap, CameraOrbit, MainCamera,
+    RoadNetwork,
+};
+
+// Radius of an issue marker, in meters.
+const MARKER_RADIUS: f32 = 1.0;
+
+// The list shows this many issues at most.
+const LISTED_ISSUES: usize = 20;
+
+// Camera distance when jumping to an issue, in meters.
+const JUMP_DISTANCE: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    // Only given to annotations; checks report warnings and errors.
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Severity::Info, Severity::Warning, Severity::Error]
+            .into_iter()
+            .find(|severity| severity.name() == name)
+    }
+
+    pub fn color(self, theme: &Theme) -> Color {
+        match self {
+            Severity::Info => theme.info,
+            Severity::Warning => theme.warning,
+            Severity::Error => theme.error,
+        }
+    }
+}
+
+// A problem found by a check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    // The check that found it, e.g. "clearance" or "mesh".
+    pub check: &'static str,
+    pub severity: Severity,
+    pub road_id: u32,
+    // The lane concerned, if the issue is about one lane.
+    pub lane_id: Option<i32>,
+    pub s: f64,
+    // Viewer-frame position to mark.
+    pub position: DVec3,
+    pub message: String,
+}
+
+// Thresholds used by the checks.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ValidationSettings {
+    pub clearance: clearance::ClearanceSettings,
+    pub lane_width: lane_width::LaneWidthSettings,
+    pub design: design_rules::DesignRuleSettings,
+}
+
+// The issues found in the loaded network.
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct Report(pub Vec<Issue>);
+
+// Whether issue markers are shown.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShowIssues(pub bool);
+
+impl Default for ShowIssues {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+// The issue last jumped to, as an index into the report.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FocusedIssue(pub Option<usize>);
+
+// Marks the issue list.
+#[derive(Component)]
+struct IssueList;
+
+// An entry of the issue list, jumping to the issue with this index.
+#[derive(Component)]
+struct JumpTo(usize);
+
+pub struct ValidationPlugin;
+
+impl Plugin for ValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ValidationSettings>()
+            .init_resource::<Report>()
+            .init_resource::<ShowIssues>()
+            .init_resource::<FocusedIssue>()
+            .add_systems(Startup, (run_checks, spawn_list))
+            .add_systems(Update, run_checks.run_if(on_event::<NetworkChanged>()))
+            .add_systems(
+                Update,
+                (
+                    toggle_issues,
+                    fill_list,
+                    (jump_on_key, jump_on_click).before(camera_orbit),
+                    draw_markers.after(camera_orbit),
+                    add_legend
+                        .in_set(FillLegend)
+                        .run_if(|show: Res<ShowIssues>| show.0),
+                ),
+            );
+    }
+}
+
+// Runs every check, returning the issues by road and station.
+pub fn validate(network: &RoadNetwork, settings: &ValidationSettings) -> Vec<Issue> {
+    let mut issues = clearance::check(network, &settings.clearance);
+    issues.extend(overlap::check(network));
+    issues.extend(mesh_qa::check(network));
+    issues.extend(lane_width::check(network, &settings.lane_width));
+    issues.extend(design_rules::check(network, &settings.design));
+    issues.sort_by(|a, b| {
+        a.road_id
+            .cmp(&b.road_id)
+            .then(a.s.total_cmp(&b.s))
+            .then(b.severity.cmp(&a.severity))
+    });
+    issues
+}
+
+// Formats issues one per line, followed by a summary.
+pub fn format_report(issues: &[Issue]) -> String {
+    let mut out = String::new();
+    for issue in issues {
+        let lane = issue
+            .lane_id
+            .map(|lane| format!(" lane {lane}"))
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "{:<7} {:<10} road {}{lane} s={:.1}: {}",
+            issue.severity.name(),
+            issue.check,
+            issue.road_id,
+            issue.s,
+            issue.message
+        );
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    let _ = writeln!(
+        out,
+        "{} issue(s): {errors} error(s), {} warning(s)",
+        issues.len(),
+        issues.len() - errors
+    );
+    out
+}
+
+fn run_checks(
+    network: Res<RoadNetwork>,
+    settings: Res<ValidationSettings>,
+    mut report: ResMut<Report>,
+) {
+    report.0 = validate(&network, &settings);
+    if !report.0.is_empty() {
+        info!("validation:\n{}", format_report(&report.0));
+    }
+}
+
+fn toggle_issues(keys: Res<ButtonInput<KeyCode>>, mut show: ResMut<ShowIssues>) {
+    if keys.just_pressed(KeyCode::KeyI) {
+        show.0 = !show.0;
+    }
+}
+
+fn spawn_list(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        IssueList,
+    ));
+}
+
+// Rebuilds the list when the report changes, and shows or hides it.
+#[allow(clippy::too_many_arguments)]
+fn fill_list(
+    mut commands: Commands,
+    report: Res<Report>,
+    show: Res<ShowIssues>,
+    focused: Res<FocusedIssue>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut lists: Query<(Entity, &mut Visibility), With<IssueList>>,
+    mut entries: Query<(&JumpTo, &mut BackgroundColor)>,
+) {
+    for (list, mut visibility) in &mut lists {
+        *visibility = if show.0 && !report.0.is_empty() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if !report.is_changed() && !theme.is_changed() {
+            continue;
+        }
+        commands.entity(list).despawn_descendants();
+        commands.entity(list).with_children(|list| {
+            for (index, issue) in report.0.iter().take(LISTED_ISSUES).enumerate() {
+                let lane = issue
+                    .lane_id
+                    .map(|lane| format!(" lane {lane}"))
+                    .unwrap_or_default();
+                list.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::axes(Val::Px(4.0), Val::Px(1.0)),
+                            ..default()
+                        },
+                        background_color: Color::NONE.into(),
+                        ..default()
+                    },
+                    JumpTo(index),
+                ))
+                .with_children(|entry| {
+                    entry.spawn(TextBundle::from_section(
+                        format!(
+                            "{} road {}{lane} s={:.1}: {}",
+                            issue.check, issue.road_id, issue.s, issue.message
+                        ),
+                        TextStyle {
+                            font_size: 13.0,
+                            color: issue.severity.color(&theme),
+                            ..default()
+                        },
+                    ));
+                });
+            }
+            if report.0.len() > LISTED_ISSUES {
+                list.spawn(TextBundle::from_section(
+                    locale.text(
+                        "issues-more",
+                        &[("count", &(report.0.len() - LISTED_ISSUES))],
+                    ),
+                    TextStyle {
+                        font_size: 13.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ));
+            }
+        });
+    }
+    if focused.is_changed() {
+        for (jump, mut background) in &mut entries {
+            *background = if focused.0 == Some(jump.0) {
+                Color::rgba(1.0, 1.0, 1.0, 0.2).into()
+            } else {
+                Color::NONE.into()
+            };
+        }
+    }
+}
+
+// Turns the camera to an issue, keeping the direction it looks in.
+fn focus(
+    commands: &mut Commands,
+    camera: Entity,
+    orbit: &CameraOrbit,
+    origin: &RenderOrigin,
+    issue: &Issue,
+) {
+    let to = OrbitPose {
+        center: issue.position,
+        distance: JUMP_DISTANCE,
+        pan: Vec2::ZERO,
+        ..OrbitPose::of(orbit, origin)
+    };
+    fly_to(commands, camera, orbit, origin, to);
+}
+
+fn jump_on_key(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    report: Res<Report>,
+    origin: Res<RenderOrigin>,
+    mut focused: ResMut<FocusedIssue>,
+    cameras: Query<(Entity, &CameraOrbit), With<MainCamera>>,
+) {
+    let count = report.0.len();
+    if !keys.just_pressed(KeyCode::KeyJ) || count == 0 {
+        return;
+    }
+    let index = match (
+        focused.0,
+        keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+    ) {
+        (None, false) => 0,
+        (None, true) => count - 1,
+        (Some(index), false) => (index + 1) % count,
+        (Some(index), true) => (index + count - 1) % count,
+    };
+    focused.0 = Some(index);
+    for (camera, orbit) in &cameras {
+        focus(&mut commands, camera, orbit, &origin, &report.0[index]);
+    }
+}
+
+fn jump_on_click(
+    mut commands: Commands,
+    report: Res<Report>,
+    origin: Res<RenderOrigin>,
+    entries: Query<(&Interaction, &JumpTo), Changed<Interaction>>,
+    mut focused: ResMut<FocusedIssue>,
+    cameras: Query<(Entity, &CameraOrbit), With<MainCamera>>,
+) {
+    for (interaction, jump) in &entries {
+        let Some(issue) = report.0.get(jump.0) else {
+            continue;
+        };
+        if *interaction == Interaction::Pressed {
+            focused.0 = Some(jump.0);
+            for (camera, orbit) in &cameras {
+                focus(&mut commands, camera, orbit, &origin, issue);
+            }
+        }
+    }
+}
+
+fn add_legend(
+    report: Res<Report>,
+    theme: Res<Theme>,
+    locale: Res<Locale>,
+    mut legend: ResMut<Legend>,
+) {
+    let rows = [Severity::Info, Severity::Warning, Severity::Error]
+        .into_iter()
+        .filter(|severity| report.0.iter().any(|issue| issue.severity == *severity))
+        .map(|severity| {
+            let message = format!("legend-{}", severity.name());
+            (severity.color(&theme), locale.text(&message, &[]))
+        })
+        .collect();
+    legend.add(locale.text("legend-issues", &[]), rows);
+}
+
+fn draw_markers(
+    show: Res<ShowIssues>,
+    report: Res<Report>,
+    origin: Res<RenderOrigin>,
+    theme: Res<Theme>,
+    mut gizmos: Gizmos,
+) {
+    if !show.0 {
+        return;
+    }
+    for issue in &report.0 {
+        let p = origin.to_render(issue.position);
+        let color = issue.severity.color(&theme);
+        gizmos.sphere(p, Quat::IDENTITY, MARKER_RADIUS, color);
+        gizmos.line(p, p + Vec3::Y * 4.0, color);
+    }
+}
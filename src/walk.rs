@@ -0,0 +1,209 @@
+// Walk mode: reviewing the network at 1:1 scale, from eye height.
+//
+// In walk mode the main camera stands on the road surface at `EYE_HEIGHT`
+// and looks where the orbit azimuth and elevation point, so a left drag
+// still turns the head. The arrow keys walk (Shift runs) and keep the eye
+// on the surface below it; T teleports to the road point under the cursor.
+// Leaving walk mode puts the orbit camera back where it was.
+//
+// A headset mode would drive this same eye pose from an OpenXR session. This
+// build has no OpenXR runtime binding, so walk mode renders to the window
+// only.
+//
+// Keys: F7 enters or leaves walk mode, arrows walk, Shift runs, T teleports.
+
+use bevy::math::DVec3;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::camera_tween::OrbitPose;
+use crate::drive::DriveMode;
+use crate::origin::RenderOrigin;
+use crate::selection::pick;
+use crate::{camera_input, camera_orbit, CameraOrbit, MainCamera, RoadNetwork};
+
+// Height of the eye above the road surface, in meters.
+const EYE_HEIGHT: f64 = 1.7;
+
+// Walking and running speeds, in meters per second.
+const WALK_SPEED: f64 = 1.4;
+const RUN_SPEED: f64 = 7.0;
+
+// How far above and below the eye the surface is looked for, in meters.
+const GROUND_SEARCH: f64 = 50.0;
+
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct WalkMode {
+    pub active: bool,
+    // The orbit camera to return to.
+    saved: Option<OrbitPose>,
+}
+
+pub struct WalkPlugin;
+
+impl Plugin for WalkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WalkMode>().add_systems(
+            Update,
+            (
+                (toggle_walk, walk, teleport)
+                    .chain()
+                    .after(camera_input)
+                    .before(camera_orbit),
+                place_eye.after(camera_orbit),
+            ),
+        );
+    }
+}
+
+// The road surface below (or just above) a viewer-frame point.
+fn ground(network: &RoadNetwork, at: DVec3) -> Option<DVec3> {
+    let from = at + DVec3::Y * GROUND_SEARCH;
+    pick(network, from, DVec3::NEG_Y)
+        .map(|hit| hit.position)
+        .filter(|hit| from.y - hit.y <= 2.0 * GROUND_SEARCH)
+}
+
+// The direction the eye looks in: the one the orbit camera would look in.
+fn look_direction(orbit: &CameraOrbit) -> Vec3 {
+    let rotation = Quat::from_axis_angle(Vec3::Y, orbit.azimuth)
+        * Quat::from_axis_angle(Vec3::X, orbit.elevation);
+    rotation * Vec3::NEG_Z
+}
+
+fn toggle_walk(
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    drive: Res<DriveMode>,
+    mut mode: ResMut<WalkMode>,
+    mut cameras: Query<&mut CameraOrbit, With<MainCamera>>,
+) {
+    if !keys.just_pressed(KeyCode::F7) {
+        return;
+    }
+    if drive.active {
+        info!("leave drive mode (F12) before walking");
+        return;
+    }
+    let Ok(mut orbit) = cameras.get_single_mut() else {
+        return;
+    };
+    if mode.active {
+        if let Some(pose) = mode.saved.take() {
+            orbit.center = origin.to_render(pose.center);
+            orbit.distance = pose.distance;
+            orbit.azimuth = pose.azimuth;
+            orbit.elevation = pose.elevation;
+            orbit.pan = pose.pan;
+        }
+        mode.active = false;
+        info!("walk mode off");
+        return;
+    }
+    let pose = OrbitPose::of(&orbit, &origin);
+    let Some(surface) = ground(&network, pose.center) else {
+        info!("walk mode needs a road below the view center");
+        return;
+    };
+    mode.saved = Some(pose);
+    mode.active = true;
+    orbit.center = origin.to_render(surface + DVec3::Y * EYE_HEIGHT);
+    orbit.elevation = 0.0;
+    orbit.pan = Vec2::ZERO;
+    info!("walk mode: arrows walk, Shift runs, T teleports, F7 leaves");
+}
+
+fn walk(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    mode: Res<WalkMode>,
+    mut cameras: Query<&mut CameraOrbit, With<MainCamera>>,
+) {
+    if !mode.active {
+        return;
+    }
+    let Ok(mut orbit) = cameras.get_single_mut() else {
+        return;
+    };
+    let look = look_direction(&orbit).as_dvec3();
+    let forward = DVec3::new(look.x, 0.0, look.z).normalize_or_zero();
+    let right = forward.cross(DVec3::Y);
+    let mut step = DVec3::ZERO;
+    for (key, direction) in [
+        (KeyCode::ArrowUp, forward),
+        (KeyCode::ArrowDown, -forward),
+        (KeyCode::ArrowRight, right),
+        (KeyCode::ArrowLeft, -right),
+    ] {
+        if keys.pressed(key) {
+            step += direction;
+        }
+    }
+    if step == DVec3::ZERO {
+        return;
+    }
+    let speed = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        RUN_SPEED
+    } else {
+        WALK_SPEED
+    };
+    let eye = origin.0
+        + orbit.center.as_dvec3()
+        + step.normalize() * speed * f64::from(time.delta_seconds());
+    // Off the road, the eye keeps its height.
+    let eye = match ground(&network, eye - DVec3::Y * EYE_HEIGHT) {
+        Some(surface) => surface + DVec3::Y * EYE_HEIGHT,
+        None => eye,
+    };
+    orbit.center = origin.to_render(eye);
+}
+
+fn teleport(
+    keys: Res<ButtonInput<KeyCode>>,
+    network: Res<RoadNetwork>,
+    origin: Res<RenderOrigin>,
+    mode: Res<WalkMode>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cameras: Query<(&Camera, &GlobalTransform, &mut CameraOrbit), With<MainCamera>>,
+) {
+    if !mode.active || !keys.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+    let Some(cursor) = windows.get_single().ok().and_then(|w| w.cursor_position()) else {
+        return;
+    };
+    let Ok((camera, transform, mut orbit)) = cameras.get_single_mut() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+    let Some(ray) = camera.viewport_to_world(transform, cursor - viewport.min) else {
+        return;
+    };
+    let Some(hit) = pick(
+        &network,
+        origin.0 + ray.origin.as_dvec3(),
+        ray.direction.as_dvec3(),
+    ) else {
+        return;
+    };
+    orbit.center = origin.to_render(hit.position + DVec3::Y * EYE_HEIGHT);
+}
+
+// Puts the camera at the eye, replacing the orbit placement.
+fn place_eye(
+    mode: Res<WalkMode>,
+    mut cameras: Query<(&mut Transform, &CameraOrbit), With<MainCamera>>,
+) {
+    if !mode.active {
+        return;
+    }
+    for (mut transform, orbit) in &mut cameras {
+        *transform =
+            Transform::from_translation(orbit.center).looking_to(look_direction(orbit), Vec3::Y);
+    }
+}
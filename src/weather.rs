@@ -0,0 +1,237 @@
+// Weather presets.
+//
+// The same map can be previewed under the conditions a scenario is driven
+// in. A preset changes the shared road materials and the camera's fog:
+//
+// - clear: the materials as the theme has them, no fog;
+// - wet: a darker, glossier road surface that mirrors the sky and lights,
+//   and markings that stand out less from it;
+// - rain: a wet road, under haze;
+// - fog: a dry road with faded markings, in thick fog.
+//
+// Presets are chosen with `weather <name>` in the console or `view --weather
+// <name>`, and apply on top of the theme: switching themes keeps the
+// weather. Lanes colored by a style sheet get the same surface.
+//
+// Keys: W switches to the next preset.
+
+use bevy::pbr::{FogFalloff, FogSettings};
+use bevy::prelude::*;
+
+use crate::theme::{self, Theme};
+use crate::{MainCamera, RoadLayer, RoadMaterials};
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Wet,
+    Rain,
+    Fog,
+}
+
+pub const PRESETS: [Weather; 4] = [Weather::Clear, Weather::Wet, Weather::Rain, Weather::Fog];
+
+// How a preset changes the look of the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conditions {
+    // Factors on the brightness of the road surface and of the markings.
+    pub surface_shade: f32,
+    pub marking_shade: f32,
+    // The road surface's roughness and reflectance, if they change.
+    pub surface_finish: Option<(f32, f32)>,
+    // The color of the fog and how far one sees through it, in meters.
+    pub fog: Option<(Color, f32)>,
+}
+
+impl Weather {
+    pub fn name(self) -> &'static str {
+        match self {
+            Weather::Clear => "clear",
+            Weather::Wet => "wet",
+            Weather::Rain => "rain",
+            Weather::Fog => "fog",
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Weather> {
+        PRESETS.into_iter().find(|weather| weather.name() == name)
+    }
+
+    pub fn parse(name: &str) -> Result<Weather, String> {
+        Weather::named(name).ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|weather| weather.name()).collect();
+            format!("unknown weather `{name}` (try {})", names.join(", "))
+        })
+    }
+
+    pub fn conditions(self) -> Conditions {
+        let wet = Conditions {
+            surface_shade: 0.55,
+            marking_shade: 0.7,
+            surface_finish: Some((0.12, 0.8)),
+            fog: None,
+        };
+        match self {
+            Weather::Clear => Conditions {
+                surface_shade: 1.0,
+                marking_shade: 1.0,
+                surface_finish: None,
+                fog: None,
+            },
+            Weather::Wet => wet,
+            Weather::Rain => Conditions {
+                fog: Some((Color::rgb(0.45, 0.48, 0.52), 800.0)),
+                ..wet
+            },
+            Weather::Fog => Conditions {
+                surface_shade: 0.9,
+                marking_shade: 0.8,
+                surface_finish: None,
+                fog: Some((Color::rgb(0.72, 0.74, 0.76), 120.0)),
+            },
+        }
+    }
+
+    // Changes the material of a road layer, as the theme has it, for the
+    // weather. Debug overlays are left as they are.
+    pub fn adjust(self, layer: RoadLayer, material: &mut StandardMaterial) {
+        let conditions = self.conditions();
+        let shade = match layer {
+            RoadLayer::Surface(_) => conditions.surface_shade,
+            RoadLayer::Marking | RoadLayer::Arrow => conditions.marking_shade,
+            _ => return,
+        };
+        let color = material.base_color;
+        material.base_color = Color::rgba(
+            color.r() * shade,
+            color.g() * shade,
+            color.b() * shade,
+            color.a(),
+        );
+        if let (RoadLayer::Surface(_), Some((roughness, reflectance))) =
+            (layer, conditions.surface_finish)
+        {
+            material.perceptual_roughness = roughness;
+            material.reflectance = reflectance;
+        }
+    }
+}
+
+// `weather` in the console: names the preset, or switches to another.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<Weather>().name().to_string()),
+        [name] => {
+            *world.resource_mut::<Weather>() = Weather::parse(name)?;
+            Ok(String::new())
+        }
+        _ => Err("expected a preset".to_string()),
+    }
+}
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>().add_systems(
+            Update,
+            (
+                switch_weather,
+                (apply_weather, update_fog)
+                    .run_if(resource_changed::<Weather>)
+                    .after(theme::recolor_roads),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn switch_weather(keys: Res<ButtonInput<KeyCode>>, mut weather: ResMut<Weather>) {
+    if !keys.just_pressed(KeyCode::KeyW) {
+        return;
+    }
+    let current = PRESETS.iter().position(|w| w == &*weather);
+    *weather = PRESETS[current.map_or(0, |i| (i + 1) % PRESETS.len())];
+    info!("weather: {}", weather.name());
+}
+
+// Makes the shared road materials over for the weather.
+fn apply_weather(
+    weather: Res<Weather>,
+    theme: Res<Theme>,
+    mut road_materials: ResMut<RoadMaterials>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    road_materials.weather = *weather;
+    for (layer, handle) in &road_materials.handles {
+        if let Some(material) = materials.get_mut(handle) {
+            *material = layer.material(&theme);
+            weather.adjust(*layer, material);
+        }
+    }
+}
+
+fn update_fog(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    cameras: Query<Entity, With<MainCamera>>,
+) {
+    for camera in &cameras {
+        match weather.conditions().fog {
+            Some((color, visibility)) => {
+                commands.entity(camera).insert(FogSettings {
+                    color,
+                    falloff: FogFalloff::from_visibility_color(visibility, color),
+                    ..default()
+                });
+            }
+            None => {
+                commands.entity(camera).remove::<FogSettings>();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theme::LaneClass;
+
+    #[test]
+    fn presets() {
+        assert_eq!(Weather::parse("rain"), Ok(Weather::Rain));
+        assert!(Weather::parse("snow").is_err());
+
+        let theme = Theme::default();
+        let look = |weather: Weather, layer: RoadLayer| {
+            let mut material = layer.material(&theme);
+            weather.adjust(layer, &mut material);
+            material
+        };
+        let dry = look(Weather::Clear, RoadLayer::Surface(LaneClass::Driving));
+        assert_eq!(dry.base_color, theme.surface);
+        // A wet road is darker and glossier, and its markings stand out less.
+        let wet = look(Weather::Wet, RoadLayer::Surface(LaneClass::Driving));
+        assert!(wet.base_color.r() < dry.base_color.r());
+        assert!(wet.perceptual_roughness < dry.perceptual_roughness);
+        assert!(wet.reflectance > dry.reflectance);
+        let contrast = |weather| {
+            look(weather, RoadLayer::Marking).base_color.r()
+                - look(weather, RoadLayer::Surface(LaneClass::Driving))
+                    .base_color
+                    .r()
+        };
+        assert!(contrast(Weather::Rain) < contrast(Weather::Clear));
+        assert!(contrast(Weather::Fog) < contrast(Weather::Clear));
+        // Debug overlays keep their colors.
+        assert_eq!(
+            look(Weather::Fog, RoadLayer::Wireframe).base_color,
+            theme.wireframe
+        );
+        assert!(Weather::Wet.conditions().fog.is_none());
+        assert!(
+            Weather::Fog.conditions().fog.unwrap().1 < Weather::Rain.conditions().fog.unwrap().1
+        );
+    }
+}
@@ -0,0 +1,714 @@
+// Raw XML tree panel.
+//
+// The panel on the left shows the OpenDRIVE elements kept with the network,
+// roads with everything in them and junctions, as a tree of their XML as it
+// was read, so that what the file says about an element can be checked
+// against what the viewer made of it. Each row is a start tag, a text or a
+// comment exactly as written, on one line. Elements are only parsed into
+// rows when they are first opened, so large maps cost nothing until looked
+// at.
+//
+// The tree follows the selection: picking a lane in the scene opens the
+// tree down to its `<lane>` element and marks it. The other way round,
+// clicking a row opens or closes it and selects the road, lane section or
+// lane it belongs to. `xml find <text>` in the console opens the tree at the
+// next element whose XML contains the text (ignoring case).
+//
+// Keys: Z shows or hides the XML tree.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use bevy::prelude::*;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::console;
+use crate::i18n::Locale;
+use crate::selection::{Detail, Pick, Selection};
+use crate::RoadNetwork;
+
+// Rows shown at a time, and the longest row, in characters.
+const MAX_ROWS: usize = 30;
+const MAX_LABEL: usize = 90;
+
+// A top-level element of the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Root {
+    Road(u32),
+    Junction(u32),
+}
+
+impl Root {
+    // The root's XML; a road's is read back from the map file.
+    fn xml(self, network: &RoadNetwork) -> Cow<'_, str> {
+        match self {
+            Root::Road(id) => network
+                .roads
+                .get(&id)
+                .and_then(|info| info.xml.text())
+                .unwrap_or_default(),
+            Root::Junction(id) => network
+                .junctions
+                .get(&id)
+                .and_then(|xml| xml.text())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlNode {
+    // The start tag, text or comment as written, on one line.
+    pub label: String,
+    // The element name, empty for texts and comments.
+    pub name: String,
+    // The `id` attribute, if there is one.
+    pub id: Option<String>,
+    // Where the node is in its root's XML.
+    pub range: Range<usize>,
+    // Read when the node is first opened; empty for nodes without any.
+    pub children: Option<Vec<XmlNode>>,
+    pub open: bool,
+}
+
+impl XmlNode {
+    fn element(e: &BytesStart, xml: &str, range: Range<usize>, empty: bool) -> Self {
+        let id = e
+            .attributes()
+            .flatten()
+            .find(|attribute| attribute.key.as_ref() == b"id")
+            .map(|attribute| String::from_utf8_lossy(&attribute.value).into_owned());
+        Self {
+            label: one_line(&xml[range.clone()]),
+            name: String::from_utf8_lossy(e.name().as_ref()).into_owned(),
+            id,
+            range,
+            children: empty.then(Vec::new),
+            open: false,
+        }
+    }
+
+    fn text(xml: &str, range: Range<usize>) -> Self {
+        Self {
+            label: one_line(&xml[range.clone()]),
+            name: String::new(),
+            id: None,
+            range,
+            children: Some(Vec::new()),
+            open: false,
+        }
+    }
+
+    // The children, read from the root's XML if they have not been yet.
+    fn children(&mut self, xml: &str) -> &mut Vec<XmlNode> {
+        let range = self.range.clone();
+        self.children
+            .get_or_insert_with(|| read_children(xml, range))
+    }
+
+    pub fn has_children(&self) -> bool {
+        self.children
+            .as_ref()
+            .is_none_or(|children| !children.is_empty())
+    }
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// The top node of an element's XML.
+fn read_root(xml: &str) -> Option<XmlNode> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        let start = reader.buffer_position();
+        match reader.read_event().ok()? {
+            Event::Start(e) => {
+                return Some(XmlNode {
+                    range: 0..xml.len(),
+                    ..XmlNode::element(&e, xml, start..reader.buffer_position(), false)
+                })
+            }
+            Event::Empty(e) => {
+                return Some(XmlNode {
+                    range: 0..xml.len(),
+                    ..XmlNode::element(&e, xml, start..reader.buffer_position(), true)
+                })
+            }
+            Event::Eof => return None,
+            _ => {}
+        }
+    }
+}
+
+// The elements, texts and comments directly inside the element at `range`.
+fn read_children(xml: &str, range: Range<usize>) -> Vec<XmlNode> {
+    let mut reader = Reader::from_str(&xml[range.clone()]);
+    let mut nodes = Vec::new();
+    let mut depth = 0;
+    loop {
+        let start = range.start + reader.buffer_position();
+        let Ok(event) = reader.read_event() else {
+            break;
+        };
+        let span = start..range.start + reader.buffer_position();
+        match event {
+            Event::Start(e) => {
+                depth += 1;
+                if depth == 2 {
+                    nodes.push(XmlNode::element(&e, xml, span, false));
+                }
+            }
+            Event::End(_) => {
+                if depth == 2 {
+                    if let Some(node) = nodes.last_mut() {
+                        node.range.end = span.end;
+                    }
+                }
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            Event::Empty(e) if depth == 1 => nodes.push(XmlNode::element(&e, xml, span, true)),
+            Event::Text(_) | Event::CData(_) | Event::Comment(_)
+                if depth == 1 && !xml[span.clone()].trim().is_empty() =>
+            {
+                nodes.push(XmlNode::text(xml, span));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    nodes
+}
+
+// Where in the scene a node belongs: road, and lane section and lane if it
+// is inside one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub road: u32,
+    pub section: Option<u32>,
+    pub lane: Option<i32>,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct XmlTree {
+    pub shown: bool,
+    // The top level, read when the tree is first needed after the map
+    // changed.
+    roots: Option<Vec<(Root, XmlNode)>>,
+    // The marked node: the index of its root, then of each child on the
+    // way down.
+    pub marked: Option<Vec<usize>>,
+    // The first row shown.
+    scroll: usize,
+    // The last search, and the root and byte offset of its match.
+    search: Option<(String, usize, usize)>,
+}
+
+impl XmlTree {
+    fn roots(&mut self, network: &RoadNetwork) -> &mut Vec<(Root, XmlNode)> {
+        self.roots.get_or_insert_with(|| {
+            let roads = network.roads.keys().map(|&id| Root::Road(id));
+            let junctions = network.junctions.keys().map(|&id| Root::Junction(id));
+            roads
+                .chain(junctions)
+                .filter_map(|root| Some((root, read_root(&root.xml(network))?)))
+                .collect()
+        })
+    }
+
+    // Opens the nodes down to `path`, reading them as needed, and returns the
+    // node there.
+    fn open_to(&mut self, network: &RoadNetwork, path: &[usize]) -> Option<&mut XmlNode> {
+        let (first, rest) = path.split_first()?;
+        let (root, node) = self.roots(network).get_mut(*first)?;
+        let xml = root.xml(network);
+        let mut node = node;
+        for &index in rest {
+            node.open = true;
+            node = node.children(&xml).get_mut(index)?;
+        }
+        Some(node)
+    }
+
+    pub fn node(&self, path: &[usize]) -> Option<&XmlNode> {
+        let (first, rest) = path.split_first()?;
+        let mut node = &self.roots.as_ref()?.get(*first)?.1;
+        for &index in rest {
+            node = node.children.as_ref()?.get(index)?;
+        }
+        Some(node)
+    }
+
+    // Opens a node that is closed and closes one that is open.
+    pub fn toggle(&mut self, network: &RoadNetwork, path: &[usize]) {
+        let Some((root, _)) = path.first().and_then(|&i| self.roots(network).get(i)) else {
+            return;
+        };
+        let xml = root.xml(network);
+        if let Some(node) = self.open_to(network, path) {
+            node.open = !node.open;
+            node.children(&xml);
+        }
+    }
+
+    // Marks a node, opening the tree down to it and scrolling it into view.
+    pub fn mark(&mut self, network: &RoadNetwork, path: Vec<usize>) {
+        if self.open_to(network, &path).is_none() {
+            return;
+        }
+        self.marked = Some(path);
+        let rows = self.rows();
+        if let Some(row) = rows
+            .iter()
+            .position(|(p, _)| Some(p) == self.marked.as_ref())
+        {
+            if row < self.scroll || row >= self.scroll + MAX_ROWS {
+                self.scroll = row.saturating_sub(MAX_ROWS / 2);
+            }
+        }
+    }
+
+    // The shown rows in order: each node's path and depth.
+    pub fn rows(&self) -> Vec<(Vec<usize>, usize)> {
+        fn walk(node: &XmlNode, path: &mut Vec<usize>, rows: &mut Vec<(Vec<usize>, usize)>) {
+            rows.push((path.clone(), path.len() - 1));
+            if !node.open {
+                return;
+            }
+            for (index, child) in node.children.iter().flatten().enumerate() {
+                path.push(index);
+                walk(child, path, rows);
+                path.pop();
+            }
+        }
+        let mut rows = Vec::new();
+        for (index, (_, node)) in self.roots.iter().flatten().enumerate() {
+            walk(node, &mut vec![index], &mut rows);
+        }
+        rows
+    }
+
+    // The node of a pick: the `<lane>` element of a picked lane, or the
+    // `<road>` element when the pick is a whole road.
+    pub fn path_to(&mut self, network: &RoadNetwork, pick: &Pick) -> Option<Vec<usize>> {
+        let wanted = Root::Road(pick.road_id);
+        let root = self.roots(network).iter().position(|(r, _)| *r == wanted)?;
+        if pick.detail == Detail::Road {
+            return Some(vec![root]);
+        }
+        let xml = wanted.xml(network);
+        let mut path = vec![root];
+        let mut node = &mut self.roots(network)[root].1;
+        let lanes = node.children(&xml).iter().position(|n| n.name == "lanes")?;
+        path.push(lanes);
+        node = &mut node.children(&xml)[lanes];
+        let section = node
+            .children(&xml)
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.name == "laneSection")
+            .nth(pick.lane_section_id.checked_sub(1)? as usize)?
+            .0;
+        path.push(section);
+        node = &mut node.children(&xml)[section];
+        let lane_id = pick.lane_id.to_string();
+        for side in 0..node.children(&xml).len() {
+            let lanes = node.children(&xml)[side].children(&xml);
+            if let Some(lane) = lanes
+                .iter()
+                .position(|n| n.name == "lane" && n.id.as_deref() == Some(lane_id.as_str()))
+            {
+                path.extend([side, lane]);
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    // Where a node belongs in the scene; nothing for junctions.
+    pub fn target(&self, path: &[usize]) -> Option<Target> {
+        let (first, rest) = path.split_first()?;
+        let (root, mut node) = self.roots.as_ref()?.get(*first).map(|(r, n)| (*r, n))?;
+        let Root::Road(road) = root else {
+            return None;
+        };
+        let mut target = Target {
+            road,
+            section: None,
+            lane: None,
+        };
+        for &index in rest {
+            let siblings = node.children.as_ref()?;
+            node = siblings.get(index)?;
+            match node.name.as_str() {
+                "laneSection" => {
+                    let before = siblings[..index]
+                        .iter()
+                        .filter(|n| n.name == "laneSection")
+                        .count();
+                    target.section = Some(before as u32 + 1);
+                }
+                "lane" => target.lane = node.id.as_deref().and_then(|id| id.parse().ok()),
+                _ => {}
+            }
+        }
+        Some(target)
+    }
+
+    // Marks the next node whose XML contains `text`, ignoring case, after
+    // the last match of the same search. Returns its path.
+    pub fn find(&mut self, network: &RoadNetwork, text: &str) -> Option<Vec<usize>> {
+        let needle = text.to_ascii_lowercase();
+        if needle.is_empty() {
+            return None;
+        }
+        let (start_root, start_offset) = match &self.search {
+            Some((last, root, offset)) if *last == needle => (*root, offset + 1),
+            _ => (0, 0),
+        };
+        let count = self.roots(network).len();
+        if count == 0 {
+            return None;
+        }
+        // From the last match to the end, then round from the top.
+        let order = (start_root..count).chain(0..=start_root.min(count - 1));
+        for (pass, root) in order.enumerate() {
+            let xml = self.roots(network)[root]
+                .0
+                .xml(network)
+                .to_ascii_lowercase();
+            let from = if pass == 0 { start_offset } else { 0 };
+            let Some(offset) = xml.get(from..).and_then(|rest| rest.find(&needle)) else {
+                continue;
+            };
+            let offset = from + offset;
+            self.search = Some((needle, root, offset));
+            let path = self.path_at(network, root, offset);
+            self.mark(network, path.clone());
+            return Some(path);
+        }
+        self.search = None;
+        None
+    }
+
+    // The innermost node holding a byte of a root's XML.
+    fn path_at(&mut self, network: &RoadNetwork, root: usize, offset: usize) -> Vec<usize> {
+        let (kind, node) = &mut self.roots(network)[root];
+        let xml = kind.xml(network);
+        let mut path = vec![root];
+        let mut node = node;
+        loop {
+            let children = node.children(&xml);
+            let Some(index) = children.iter().position(|n| n.range.contains(&offset)) else {
+                return path;
+            };
+            path.push(index);
+            node = &mut children[index];
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = XmlTree {
+            shown: self.shown,
+            ..XmlTree::default()
+        };
+    }
+}
+
+// `xml` in the console: describes the marked node, or with `find <text>`
+// looks for the next node containing the text.
+pub fn command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    world.resource_scope(|world, mut tree: Mut<XmlTree>| {
+        let network = world.resource::<RoadNetwork>();
+        tree.shown = true;
+        let path = match args {
+            [] => tree.marked.clone().ok_or("no element is marked")?,
+            ["find", text @ ..] if !text.is_empty() => {
+                let text = text.join(" ");
+                tree.find(network, &text)
+                    .ok_or_else(|| format!("no XML contains `{text}`"))?
+            }
+            _ => return Err("expected `xml` or `xml find <text>`".to_string()),
+        };
+        Ok(tree
+            .node(&path)
+            .map(|node| node.label.clone())
+            .unwrap_or_default())
+    })
+}
+
+// The panel.
+#[derive(Component)]
+struct XmlPanel;
+
+// A row standing for a node.
+#[derive(Component)]
+struct Row(Vec<usize>);
+
+// A row paging the tree by this many rows.
+#[derive(Component)]
+struct Page(isize);
+
+pub struct XmlTreePlugin;
+
+impl Plugin for XmlTreePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<XmlTree>()
+            .add_systems(Startup, spawn_panel)
+            .add_systems(
+                Update,
+                (
+                    forget_tree,
+                    toggle_panel,
+                    click_rows,
+                    follow_selection,
+                    fill_panel,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn spawn_panel(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Percent(15.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(4.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        XmlPanel,
+    ));
+}
+
+// Drops the tree read from a network that has changed since.
+fn forget_tree(network: Res<RoadNetwork>, mut tree: ResMut<XmlTree>) {
+    if network.is_changed() {
+        tree.clear();
+    }
+}
+
+fn toggle_panel(keys: Res<ButtonInput<KeyCode>>, mut tree: ResMut<XmlTree>) {
+    if keys.just_pressed(KeyCode::KeyZ) {
+        tree.shown = !tree.shown;
+    }
+}
+
+// Opens or closes a clicked row's node and selects what it belongs to.
+fn click_rows(
+    network: Res<RoadNetwork>,
+    mut tree: ResMut<XmlTree>,
+    mut selection: ResMut<Selection>,
+    rows: Query<(&Interaction, &Row), Changed<Interaction>>,
+    pages: Query<(&Interaction, &Page), Changed<Interaction>>,
+) {
+    for (_, page) in pages.iter().filter(|(i, _)| **i == Interaction::Pressed) {
+        let end = tree.rows().len().saturating_sub(MAX_ROWS);
+        tree.scroll = tree.scroll.saturating_add_signed(page.0).min(end);
+    }
+    for (_, row) in rows.iter().filter(|(i, _)| **i == Interaction::Pressed) {
+        tree.toggle(&network, &row.0);
+        tree.marked = Some(row.0.clone());
+        let Some(target) = tree.target(&row.0) else {
+            continue;
+        };
+        let selected = selection.0.is_some_and(|pick| {
+            pick.road_id == target.road
+                && target.section.is_none_or(|s| s == pick.lane_section_id)
+                && target.lane.is_none_or(|l| l == pick.lane_id)
+        });
+        if !selected {
+            match console::select(&network, target.road, target.lane, target.section) {
+                Ok(pick) => selection.0 = Some(pick),
+                Err(message) => warn!("{message}"),
+            }
+        }
+    }
+}
+
+// Marks the selected element when the selection changes or the panel is
+// shown, unless the marked node is already inside it.
+fn follow_selection(
+    network: Res<RoadNetwork>,
+    selection: Res<Selection>,
+    mut tree: ResMut<XmlTree>,
+    mut was_shown: Local<bool>,
+) {
+    let shown_now = tree.shown && !*was_shown;
+    *was_shown = tree.shown;
+    if !tree.shown || !(selection.is_changed() || shown_now) {
+        return;
+    }
+    let Some(pick) = selection.0 else {
+        return;
+    };
+    let Some(path) = tree.path_to(&network, &pick) else {
+        return;
+    };
+    if !tree.marked.as_ref().is_some_and(|m| m.starts_with(&path)) {
+        tree.mark(&network, path);
+    }
+}
+
+fn fill_panel(
+    mut commands: Commands,
+    network: Res<RoadNetwork>,
+    locale: Res<Locale>,
+    mut tree: ResMut<XmlTree>,
+    mut panels: Query<(Entity, &mut Visibility), With<XmlPanel>>,
+) {
+    if !tree.is_changed() {
+        return;
+    }
+    if tree.shown {
+        // Reads the top level the first time it is shown.
+        tree.roots(&network);
+    }
+    let rows = tree.rows();
+    let first = tree.scroll.min(rows.len().saturating_sub(MAX_ROWS));
+    let last = (first + MAX_ROWS).min(rows.len());
+    let style = |color: Color| TextStyle {
+        font_size: 13.0,
+        color,
+        ..default()
+    };
+    let button = |padding: f32| ButtonBundle {
+        style: Style {
+            padding: UiRect::left(Val::Px(padding)),
+            ..default()
+        },
+        background_color: Color::NONE.into(),
+        ..default()
+    };
+
+    for (panel, mut visibility) in &mut panels {
+        *visibility = if tree.shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        commands.entity(panel).despawn_descendants();
+        if !tree.shown {
+            continue;
+        }
+        commands.entity(panel).with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                locale.text("xml-title", &[]),
+                style(Color::WHITE),
+            ));
+            if rows.is_empty() {
+                panel.spawn(TextBundle::from_section(
+                    locale.text("xml-empty", &[]),
+                    style(Color::GRAY),
+                ));
+            }
+            if first > 0 {
+                panel
+                    .spawn((button(0.0), Page(-(MAX_ROWS as isize))))
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(
+                            locale.text("xml-more-above", &[("count", &first)]),
+                            style(Color::GRAY),
+                        ));
+                    });
+            }
+            for (path, depth) in &rows[first..last] {
+                let Some(node) = tree.node(path) else {
+                    continue;
+                };
+                let sign = match (node.has_children(), node.open) {
+                    (false, _) => "  ",
+                    (true, false) => "+ ",
+                    (true, true) => "- ",
+                };
+                let mut label: String = node.label.chars().take(MAX_LABEL).collect();
+                if label.len() < node.label.len() {
+                    label.push_str("...");
+                }
+                let color = if tree.marked.as_ref() == Some(path) {
+                    Color::rgb(1.0, 0.9, 0.1)
+                } else if node.name.is_empty() {
+                    Color::GRAY
+                } else {
+                    Color::WHITE
+                };
+                panel
+                    .spawn((button(12.0 * *depth as f32), Row(path.clone())))
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(
+                            format!("{sign}{label}"),
+                            style(color),
+                        ));
+                    });
+            }
+            if last < rows.len() {
+                panel
+                    .spawn((button(0.0), Page(MAX_ROWS as isize)))
+                    .with_children(|row| {
+                        row.spawn(TextBundle::from_section(
+                            locale.text("xml-more-below", &[("count", &(rows.len() - last))]),
+                            style(Color::GRAY),
+                        ));
+                    });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console;
+    use crate::sample_maps::load;
+
+    #[test]
+    fn the_tree_follows_the_selection_and_searches() {
+        let network = load("straight.xodr");
+        let mut tree = XmlTree::default();
+        assert!(tree.rows().is_empty());
+
+        // Selecting a lane opens the tree down to its element, and no further.
+        let pick = console::select(&network, 1, Some(-2), Some(1)).unwrap();
+        let path = tree.path_to(&network, &pick).unwrap();
+        tree.mark(&network, path.clone());
+        let lane = tree.node(&path).unwrap();
+        assert_eq!(lane.label, r#"<lane id="-2" type="sidewalk">"#);
+        assert_eq!(lane.children, None);
+        assert_eq!(
+            tree.target(&path),
+            Some(Target {
+                road: 1,
+                section: Some(1),
+                lane: Some(-2),
+            })
+        );
+        // The road, its four children, the lane section, its three sides and
+        // the two right lanes.
+        let rows = tree.rows();
+        assert_eq!(rows.len(), 11);
+        assert_eq!(rows[10], (path.clone(), 4));
+
+        let found = tree.find(&network, "THERMOPLASTIC").unwrap();
+        let node = tree.node(&found).unwrap();
+        assert!(node
+            .label
+            .starts_with(r#"<roadMark sOffset="0.0" type="custom""#));
+        assert_eq!(tree.target(&found).unwrap().lane, Some(-2));
+        assert_eq!(tree.marked, Some(found));
+        // Searching again goes on to the next match, round to the first.
+        let first = tree.find(&network, "botts dots").unwrap();
+        assert_eq!(tree.find(&network, "botts dots"), Some(first));
+        assert_eq!(tree.find(&network, "no such text"), None);
+    }
+}
@@ -0,0 +1,3020 @@
+// OpenDRIVE (.xodr) input and output.
+//
+// Reading samples each road's reference line (lines, arcs, spirals, poly3 and
+// paramPoly3 pieces), elevation profile, lane offset and lane widths into
+// boundary polylines, one segment per lane and lane section.
+//
+// Lane `<height>` records raise a lane's inner and outer edges off the road
+// surface, for curbs, sidewalks and medians. A record holds until the next
+// one; the edges are not blended between them.
+//
+// Writing goes the other way with the information the lane model keeps: the
+// reference line is recovered as the inner edge of the innermost lane and
+// written as straight `<line>` pieces, lane widths become piecewise linear
+// `<width>` records (one per polyline vertex), the polyline heights become
+// the elevation profile, and lane edges above or below it become `<height>`
+// records where their offset changes. Junctions are written back as they
+// were read, their road references renumbered to the IDs the network uses,
+// and so are the header's name and georeference and the lanes' links.
+//
+// OpenDRIVE is Z-up with y pointing north, so positions are converted between
+// the two frames at the boundary of this module.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use bevy::math::{DVec2, DVec3};
+use memmap2::Mmap;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::failures::LoadFailure;
+use crate::geo::GeoReference;
+use crate::lane_records::LaneRecord;
+use crate::numbers;
+use crate::route_export::to_odr;
+use crate::signals::{ObjectRepeat, Signal, SignalKind};
+use crate::transform::{LoadTransform, UpAxis};
+use crate::units::{self, KMH};
+use crate::{
+    ContactPoint, LaneAccess, LaneMaterial, MarkLine, PlanSample, RoadInfo, RoadLink, RoadMark,
+    RoadNetwork, RoadSegment, TrafficRule,
+};
+
+// Longest distance between two samples along the reference line, in meters.
+const SAMPLE_STEP: f64 = 1.0;
+
+// Resolution of the lookup tables for curves without a closed form.
+const TABLE_STEP: f64 = 0.1;
+
+// Lane edges closer to the road surface than this are written as on it, in
+// meters.
+const HEIGHT_TOLERANCE: f64 = 1e-4;
+
+// How far the lanes may have left the reference line records a road was
+// read with for them still to be written, in meters.
+const FOLLOW_TOLERANCE: f64 = 1e-3;
+
+// Slopes and directions closer than this are written as one record.
+const SLOPE_TOLERANCE: f64 = 1e-9;
+
+// ---------------------------------------------------------------------------
+// Reading
+// ---------------------------------------------------------------------------
+
+// A cubic polynomial record starting at station `s`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cubic {
+    pub s: f64,
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+}
+
+impl Cubic {
+    fn from_attributes(e: &BytesStart, station: &str, warnings: &mut Vec<String>) -> Self {
+        Self {
+            s: number(e, station, warnings),
+            a: number(e, "a", warnings),
+            b: number(e, "b", warnings),
+            c: number(e, "c", warnings),
+            d: number(e, "d", warnings),
+        }
+    }
+
+    fn value(&self, ds: f64) -> f64 {
+        self.a + ds * (self.b + ds * (self.c + ds * self.d))
+    }
+}
+
+// Evaluates a list of records sorted by station at `s`, zero before the first.
+fn evaluate(records: &[Cubic], s: f64) -> f64 {
+    records
+        .iter()
+        .rev()
+        .find(|r| r.s <= s + 1e-9)
+        .map_or(0.0, |r| r.value(s - r.s))
+}
+
+// The shape of one reference line piece.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Line,
+    Arc {
+        curvature: f64,
+    },
+    Spiral {
+        start: f64,
+        end: f64,
+    },
+    Poly3 {
+        v: [f64; 4],
+    },
+    ParamPoly3 {
+        u: [f64; 4],
+        v: [f64; 4],
+        normalized: bool,
+    },
+}
+
+// One reference line piece, from `<planView><geometry>`.
+#[derive(Debug, Clone)]
+struct Geometry {
+    s: f64,
+    x: f64,
+    y: f64,
+    hdg: f64,
+    length: f64,
+    shape: Shape,
+    // Samples of (ds, x, y, hdg) for shapes evaluated numerically.
+    table: Vec<[f64; 4]>,
+}
+
+impl Geometry {
+    fn new(record: &PlanRecord) -> Self {
+        let mut geometry = Geometry {
+            s: record.s,
+            x: record.x,
+            y: record.y,
+            hdg: record.hdg,
+            length: record.length,
+            shape: record.shape,
+            table: Vec::new(),
+        };
+        geometry.build_table();
+        geometry
+    }
+
+    fn record(&self) -> PlanRecord {
+        PlanRecord {
+            s: self.s,
+            x: self.x,
+            y: self.y,
+            hdg: self.hdg,
+            length: self.length,
+            shape: self.shape,
+        }
+    }
+
+    // Position and heading at `ds` meters into the piece.
+    fn point(&self, ds: f64) -> (f64, f64, f64) {
+        let ds = ds.clamp(0.0, self.length);
+        match self.shape {
+            Shape::Line => (
+                self.x + ds * self.hdg.cos(),
+                self.y + ds * self.hdg.sin(),
+                self.hdg,
+            ),
+            Shape::Arc { curvature } if curvature.abs() > 1e-12 => {
+                let hdg = self.hdg + curvature * ds;
+                (
+                    self.x + (hdg.sin() - self.hdg.sin()) / curvature,
+                    self.y - (hdg.cos() - self.hdg.cos()) / curvature,
+                    hdg,
+                )
+            }
+            Shape::Arc { .. } => Geometry {
+                shape: Shape::Line,
+                ..self.clone()
+            }
+            .point(ds),
+            _ => self.lookup(ds),
+        }
+    }
+
+    // Curvature at `ds` meters into the piece.
+    fn curvature(&self, ds: f64) -> f64 {
+        match self.shape {
+            Shape::Line => 0.0,
+            Shape::Arc { curvature } => curvature,
+            Shape::Spiral { start, end } => {
+                start + (end - start) * (ds / self.length.max(1e-12)).clamp(0.0, 1.0)
+            }
+            // The rate of change of the tabulated heading.
+            _ => {
+                let i = self
+                    .table
+                    .partition_point(|row| row[0] <= ds)
+                    .clamp(1, self.table.len().saturating_sub(1).max(1));
+                match (self.table.get(i - 1), self.table.get(i)) {
+                    (Some(a), Some(b)) if b[0] > a[0] => (b[3] - a[3]) / (b[0] - a[0]),
+                    _ => 0.0,
+                }
+            }
+        }
+    }
+
+    // Interpolates the precomputed table.
+    fn lookup(&self, ds: f64) -> (f64, f64, f64) {
+        let i = self.table.partition_point(|row| row[0] <= ds);
+        match (
+            i.checked_sub(1).map(|j| self.table[j]),
+            self.table.get(i).copied(),
+        ) {
+            (Some(a), Some(b)) if b[0] > a[0] => {
+                let t = (ds - a[0]) / (b[0] - a[0]);
+                (
+                    a[1] + (b[1] - a[1]) * t,
+                    a[2] + (b[2] - a[2]) * t,
+                    a[3] + (b[3] - a[3]) * t,
+                )
+            }
+            (Some(a), _) | (None, Some(a)) => (a[1], a[2], a[3]),
+            (None, None) => (self.x, self.y, self.hdg),
+        }
+    }
+
+    // Fills the lookup table for shapes without a closed form.
+    fn build_table(&mut self) {
+        let steps = ((self.length / TABLE_STEP).ceil() as usize).max(1);
+        match self.shape {
+            Shape::Line | Shape::Arc { .. } => {}
+            Shape::Spiral { start, end } => {
+                // Integrate the linearly changing curvature with the midpoint rule.
+                let rate = (end - start) / self.length.max(1e-12);
+                let h = self.length / steps as f64;
+                let (mut x, mut y) = (self.x, self.y);
+                self.table.push([0.0, x, y, self.hdg]);
+                for i in 0..steps {
+                    let mid = (i as f64 + 0.5) * h;
+                    let hdg = self.hdg + start * mid + rate * mid * mid / 2.0;
+                    x += h * hdg.cos();
+                    y += h * hdg.sin();
+                    let s = (i + 1) as f64 * h;
+                    self.table
+                        .push([s, x, y, self.hdg + start * s + rate * s * s / 2.0]);
+                }
+            }
+            Shape::Poly3 { v } => {
+                // poly3 is parameterized by the local u coordinate, not by
+                // arc length, so walk u until the length is used up.
+                let local = |u: f64| (u, v[0] + u * (v[1] + u * (v[2] + u * v[3])));
+                self.walk(local, TABLE_STEP / 4.0, f64::INFINITY);
+            }
+            Shape::ParamPoly3 { u, v, normalized } => {
+                let end = if normalized { 1.0 } else { self.length };
+                let cubic = |c: [f64; 4], p: f64| c[0] + p * (c[1] + p * (c[2] + p * c[3]));
+                self.walk(
+                    |p| (cubic(u, p), cubic(v, p)),
+                    end / (steps * 4) as f64,
+                    end,
+                );
+            }
+        }
+    }
+
+    // Samples a curve given in local coordinates by parameter, recording rows
+    // by accumulated arc length until the piece length or `end` is reached.
+    fn walk(&mut self, local: impl Fn(f64) -> (f64, f64), dp: f64, end: f64) {
+        let (sin, cos) = self.hdg.sin_cos();
+        let to_world =
+            |(u, v): (f64, f64)| (self.x + u * cos - v * sin, self.y + u * sin + v * cos);
+
+        let mut p = 0.0;
+        let mut s = 0.0;
+        let mut prev = to_world(local(0.0));
+        let mut rows = vec![[0.0, prev.0, prev.1, self.hdg]];
+        while s < self.length && p < end {
+            p = (p + dp).min(end);
+            let next = to_world(local(p));
+            let (dx, dy) = (next.0 - prev.0, next.1 - prev.1);
+            s += dx.hypot(dy);
+            rows.push([s, next.0, next.1, dy.atan2(dx)]);
+            prev = next;
+        }
+        // The first row takes the heading of the first piece, keeping
+        // interpolation smooth at the start.
+        if rows.len() > 1 {
+            rows[0][3] = rows[1][3];
+        }
+        // Headings are interpolated, so unwrap them to avoid jumps at ±π.
+        for i in 1..rows.len() {
+            let mut h = rows[i][3];
+            while h - rows[i - 1][3] > PI {
+                h -= 2.0 * PI;
+            }
+            while h - rows[i - 1][3] < -PI {
+                h += 2.0 * PI;
+            }
+            rows[i][3] = h;
+        }
+        self.table = rows;
+    }
+}
+
+// A `<planView><geometry>` record.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanRecord {
+    pub s: f64,
+    pub x: f64,
+    pub y: f64,
+    pub hdg: f64,
+    pub length: f64,
+    pub shape: Shape,
+}
+
+// A road's reference line as its OpenDRIVE records give it, in the map
+// frame: the plan view, elevation profile and lane offset. It is kept with
+// the road so that the road is written back with the pieces it was read
+// with rather than with its sampled outline, for as long as its lanes still
+// follow it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReferenceLine {
+    pub geometry: Vec<PlanRecord>,
+    pub elevation: Vec<Cubic>,
+    pub lane_offset: Vec<Cubic>,
+}
+
+impl ReferenceLine {
+    // The reference line rotated counter-clockwise by `rotation` about the
+    // origin, then shifted by `offset`.
+    pub fn moved(&self, rotation: f64, offset: DVec3) -> Self {
+        let turn = DVec2::from_angle(rotation);
+        let geometry = self
+            .geometry
+            .iter()
+            .map(|record| {
+                let p = turn.rotate(DVec2::new(record.x, record.y));
+                PlanRecord {
+                    x: p.x + offset.x,
+                    y: p.y + offset.y,
+                    hdg: record.hdg + rotation,
+                    ..*record
+                }
+            })
+            .collect();
+        let mut elevation: Vec<Cubic> = self
+            .elevation
+            .iter()
+            .map(|record| Cubic {
+                a: record.a + offset.z,
+                ..*record
+            })
+            .collect();
+        // Elevation is zero before the first record, which the shift has to
+        // raise as well.
+        if offset.z != 0.0 && elevation.first().is_none_or(|first| first.s > 0.0) {
+            elevation.insert(
+                0,
+                Cubic {
+                    a: offset.z,
+                    ..Cubic::default()
+                },
+            );
+        }
+        Self {
+            geometry,
+            elevation,
+            lane_offset: self.lane_offset.clone(),
+        }
+    }
+
+    // Where the reference line ends.
+    fn length(&self) -> f64 {
+        self.geometry.last().map_or(0.0, |g| g.s + g.length)
+    }
+}
+
+// One lane from a `<laneSection>`.
+#[derive(Debug, Clone, Default)]
+struct Lane {
+    id: i32,
+    lane_type: String,
+    widths: Vec<Cubic>,
+    // The `<height>` records, as constant pieces: the inner and the outer
+    // edge's offset from the road surface.
+    inner_heights: Vec<Cubic>,
+    outer_heights: Vec<Cubic>,
+    // The first `<speed>` record, in m/s.
+    speed: Option<f64>,
+    // The `<material>` records.
+    materials: Vec<LaneMaterial>,
+    // The `<access>` records with their `sOffset`.
+    access: Vec<(f64, LaneAccess)>,
+    // The `<roadMark>` records.
+    road_marks: Vec<RoadMark>,
+    // The lane IDs of `<link><predecessor>` and `<successor>`.
+    predecessor: Option<i32>,
+    successor: Option<i32>,
+}
+
+// A `<laneSection>` with its lanes.
+#[derive(Debug, Clone, Default)]
+struct Section {
+    s: f64,
+    lanes: Vec<Lane>,
+    // The center lane, for its road mark.
+    center: Lane,
+}
+
+// A road link as read, before road IDs are resolved.
+#[derive(Debug, Clone)]
+struct RawLink {
+    contact: ContactPoint,
+    element: String,
+    other_contact: ContactPoint,
+}
+
+// A `<junction>` as read. Junctions are only kept as they appear in the file.
+#[derive(Debug, Clone)]
+struct RawJunction {
+    id: String,
+    xml: ElementXml,
+}
+
+// Everything read for one `<road>`.
+#[derive(Debug, Clone, Default)]
+struct Road {
+    id: String,
+    name: String,
+    length: f64,
+    rule: TrafficRule,
+    // The `junction` attribute, "-1" for roads outside junctions.
+    junction: String,
+    geometries: Vec<Geometry>,
+    elevations: Vec<Cubic>,
+    lane_offsets: Vec<Cubic>,
+    // The `<type>` records: station, road type and speed limit in m/s, if
+    // any.
+    types: Vec<(f64, String, Option<f64>)>,
+    sections: Vec<Section>,
+    links: Vec<RawLink>,
+    // Signals and objects; road ID and position are filled in on sampling.
+    signals: Vec<Signal>,
+    // The element as it appears in the file.
+    xml: ElementXml,
+}
+
+impl Road {
+    // Reference line position and heading at station `s`.
+    fn reference(&self, s: f64) -> (f64, f64, f64) {
+        let geometry = self
+            .geometries
+            .iter()
+            .rev()
+            .find(|g| g.s <= s + 1e-9)
+            .or(self.geometries.first());
+        match geometry {
+            Some(g) => g.point(s - g.s),
+            None => (0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Where the text of the elements kept with a network is read back from.
+#[derive(Debug)]
+pub enum XmlSource {
+    // A file as it was when read; once it changes, its elements are no
+    // longer read from it.
+    File {
+        path: PathBuf,
+        modified: Option<SystemTime>,
+    },
+    // A document read from memory, such as a decompressed file.
+    Text(String),
+}
+
+// The XML of an element as it was read. Elements of a file are kept as
+// their place in it and read back when asked for, so that a big map does
+// not hold the text of every road and signal.
+#[derive(Debug, Clone, Default)]
+pub enum ElementXml {
+    // Not read from OpenDRIVE, or changed since.
+    #[default]
+    None,
+    Span(Arc<XmlSource>, Range<usize>),
+    // Text made for the element, such as a renumbered copy.
+    Text(String),
+}
+
+impl ElementXml {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ElementXml::None => true,
+            ElementXml::Span(_, range) => range.is_empty(),
+            ElementXml::Text(text) => text.is_empty(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = ElementXml::None;
+    }
+
+    // The element's text, none if it has none or its file has changed or
+    // cannot be read.
+    pub fn text(&self) -> Option<Cow<'_, str>> {
+        if self.is_empty() {
+            return None;
+        }
+        match self {
+            ElementXml::None => None,
+            ElementXml::Span(source, range) => match &**source {
+                XmlSource::File { path, modified } => {
+                    let mut file = File::open(path).ok()?;
+                    let now = file.metadata().and_then(|m| m.modified()).ok();
+                    if now != *modified {
+                        return None;
+                    }
+                    read_range(&mut file, range.clone()).ok().map(Cow::Owned)
+                }
+                XmlSource::Text(text) => text.get(range.clone()).map(Cow::Borrowed),
+            },
+            ElementXml::Text(text) => Some(Cow::Borrowed(text)),
+        }
+    }
+}
+
+impl From<String> for ElementXml {
+    fn from(text: String) -> Self {
+        if text.is_empty() {
+            ElementXml::None
+        } else {
+            ElementXml::Text(text)
+        }
+    }
+}
+
+// Elements are the same if their texts are.
+impl PartialEq for ElementXml {
+    fn eq(&self, other: &Self) -> bool {
+        self.text() == other.text()
+    }
+}
+
+// Reads an OpenDRIVE file, bringing it into the map frame with `transform`.
+// The file is memory-mapped and streamed rather than read whole: each road
+// is sampled as soon as its element ends and its records are dropped, so
+// that besides the network only one road's records are held at a time, and
+// the pages of the file already parsed can be dropped by the system. The
+// map is let go once parsed; elements kept with the network are kept as
+// their place in the file (see `ElementXml`).
+pub fn read_file(path: &Path, transform: &LoadTransform) -> Result<RoadNetwork, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let source = XmlSource::File {
+        path: path.to_path_buf(),
+        modified: file.metadata().and_then(|m| m.modified()).ok(),
+    };
+    // Mapping is unsafe because the file may change while it is mapped. A
+    // map edited meanwhile reads as whatever bytes it then holds, and the
+    // reader treats those as any other malformed XML; one truncated meanwhile
+    // ends the process, as it would any program mapping it.
+    let map = unsafe { Mmap::map(&file) }.map_err(|e| e.to_string())?;
+    read(&map[..], Arc::new(source), transform)
+}
+
+// Parses OpenDRIVE XML into a road network, which keeps a copy of the
+// text. Only tests, which hold their maps as string literals, need the copy.
+#[cfg(test)]
+pub fn read_str(xml: &str, transform: &LoadTransform) -> Result<RoadNetwork, String> {
+    read_string(xml.to_string(), transform)
+}
+
+// Parses OpenDRIVE XML into a road network, which keeps the text for the
+// elements read from it.
+pub fn read_string(xml: String, transform: &LoadTransform) -> Result<RoadNetwork, String> {
+    let source = Arc::new(XmlSource::Text(xml));
+    let XmlSource::Text(text) = &*source else {
+        unreachable!("the source was made from text");
+    };
+    read(text.as_bytes(), source.clone(), transform)
+}
+
+// Reads a span of a file as text.
+fn read_range(file: &mut File, range: Range<usize>) -> Result<String, String> {
+    let mut bytes = vec![0; range.len()];
+    file.seek(SeekFrom::Start(range.start as u64))
+        .and_then(|_| file.read_exact(&mut bytes))
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+// Reads OpenDRIVE XML from `input`, whose text `source` keeps.
+fn read(
+    input: impl BufRead,
+    source: Arc<XmlSource>,
+    transform: &LoadTransform,
+) -> Result<RoadNetwork, String> {
+    let mut network = RoadNetwork {
+        transform: *transform,
+        ..RoadNetwork::default()
+    };
+    // Roads are numbered by their place in the file while reading; the
+    // numbers are replaced once all IDs are known. Kept per road: its
+    // OpenDRIVE ID and junction, and what else the network keeps of it
+    // together with its links.
+    let mut read_roads: Vec<(String, String)> = Vec::new();
+    let mut details: Vec<(RoadInfo, Vec<RawLink>)> = Vec::new();
+    let parsed = parse(input, source, |road| {
+        if let Some(problem) = road_problem(&road) {
+            network.failures.push(failure(&road, problem, transform));
+            return;
+        }
+        let index = read_roads.len() as u32;
+        network
+            .segments
+            .extend(sample_road(&road, index, transform));
+        for signal in &road.signals {
+            let (x, y, hdg) = road.reference(signal.s);
+            let z = evaluate(&road.elevations, signal.s);
+            network.signals.push(Signal {
+                road_id: index,
+                position: transform.viewer_position(DVec3::new(
+                    x - signal.t * hdg.sin(),
+                    y + signal.t * hdg.cos(),
+                    z,
+                )),
+                ..signal.clone()
+            });
+        }
+        let info = RoadInfo {
+            name: road.name.clone(),
+            plan_view: sample_plan_view(&road, transform),
+            reference: reference_line(&road, transform),
+            junction: None,
+            xml: road.xml,
+        };
+        read_roads.push((road.id, road.junction));
+        details.push((info, road.links));
+    })?;
+    let junction_elements = parsed.junctions;
+    if let Some((message, road)) = parsed.error {
+        network.failures.push(match road {
+            Some(road) => failure(&road, message, transform),
+            None => LoadFailure {
+                message,
+                ..LoadFailure::default()
+            },
+        });
+    }
+
+    // OpenDRIVE IDs are strings. Numeric ones are kept as they are; the rest
+    // get fresh numbers above the largest numeric ID.
+    let mut ids: HashMap<&str, u32> = HashMap::new();
+    let mut numbers = Vec::with_capacity(read_roads.len());
+    let mut next = read_roads
+        .iter()
+        .filter_map(|(id, _)| id.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+    for (id, _) in &read_roads {
+        let number = id.parse::<u32>().unwrap_or_else(|_| {
+            next += 1;
+            next
+        });
+        ids.insert(id, number);
+        numbers.push(number);
+    }
+
+    // Junction IDs likewise; "-1" marks roads outside any junction.
+    let junction_ids = || {
+        read_roads
+            .iter()
+            .map(|(_, junction)| junction.as_str())
+            .chain(junction_elements.iter().map(|j| j.id.as_str()))
+    };
+    let mut junctions: HashMap<&str, u32> = HashMap::new();
+    let mut next_junction = junction_ids()
+        .filter_map(|id| id.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+    for id in junction_ids() {
+        if id.is_empty() || id == "-1" || junctions.contains_key(id) {
+            continue;
+        }
+        let number = id.parse::<u32>().unwrap_or_else(|_| {
+            next_junction += 1;
+            next_junction
+        });
+        junctions.insert(id, number);
+    }
+
+    for segment in &mut network.segments {
+        segment.road_id = numbers[segment.road_id as usize];
+    }
+    for signal in &mut network.signals {
+        signal.road_id = numbers[signal.road_id as usize];
+    }
+    network.name = parsed.name;
+    network.warnings = parsed.warnings;
+    if !parsed.geo_reference.trim().is_empty() {
+        match GeoReference::parse(&parsed.geo_reference) {
+            Ok(geo) => network.geo = Some(geo),
+            Err(message) => network
+                .warnings
+                .push(format!("geoReference is not used: {message}")),
+        }
+    }
+    // Junctions are kept with the IDs the network uses, so that they can be
+    // written back as they are. Those whose references keep their numbers
+    // stay their place in the file.
+    network.junctions = junction_elements
+        .iter()
+        .filter_map(|j| {
+            let id = *junctions.get(j.id.as_str())?;
+            let text = j.xml.text().unwrap_or_default();
+            let renumbered = renumber(
+                &text,
+                |road| ids.get(road).copied(),
+                |junction| junctions.get(junction).copied(),
+            );
+            let xml = if renumbered == text {
+                j.xml.clone()
+            } else {
+                renumbered.into()
+            };
+            Some((id, xml))
+        })
+        .collect();
+    for (index, (mut info, links)) in details.into_iter().enumerate() {
+        let road_id = numbers[index];
+        info.junction = junctions.get(read_roads[index].1.as_str()).copied();
+        network.roads.insert(road_id, info);
+        for link in links {
+            // Links to junctions are resolved through the junction's
+            // connections, which the lane model does not keep yet.
+            if let Some(&other_road_id) = ids.get(link.element.as_str()) {
+                network.links.push(RoadLink {
+                    road_id,
+                    contact: link.contact,
+                    other_road_id,
+                    other_contact: link.other_contact,
+                });
+            }
+        }
+    }
+    Ok(network)
+}
+
+// What keeps a road from being sampled, if anything.
+fn road_problem(road: &Road) -> Option<String> {
+    if road.geometries.is_empty() {
+        return Some("no reference line geometry".to_string());
+    }
+    if road.sections.is_empty() {
+        return Some("no lane sections".to_string());
+    }
+    if road.length <= 0.0 {
+        return Some(format!("length {} is not positive", road.length));
+    }
+    let ends = road
+        .geometries
+        .iter()
+        .flat_map(|g| [g.point(0.0), g.point(g.length)]);
+    for (x, y, hdg) in ends {
+        if !(x.is_finite() && y.is_finite() && hdg.is_finite()) {
+            return Some("reference line leaves the finite plane".to_string());
+        }
+    }
+    None
+}
+
+// A road that could not be read, placed where its reference line starts if
+// that much is known.
+fn failure(road: &Road, message: String, transform: &LoadTransform) -> LoadFailure {
+    let position = road
+        .geometries
+        .first()
+        .filter(|g| g.x.is_finite() && g.y.is_finite())
+        .map(|g| transform.viewer_position(DVec3::new(g.x, g.y, evaluate(&road.elevations, g.s))));
+    LoadFailure {
+        road: road.id.clone(),
+        message,
+        position,
+    }
+}
+
+// Turns a road into lane segments, one per lane and lane section.
+fn sample_road(road: &Road, road_id: u32, transform: &LoadTransform) -> Vec<RoadSegment> {
+    let mut segments = Vec::new();
+    for (index, section) in road.sections.iter().enumerate() {
+        let end = road
+            .sections
+            .get(index + 1)
+            .map_or(road.length, |next| next.s)
+            .max(section.s);
+        // The road type in force where the section starts, and its speed
+        // limit for lanes that give none.
+        let road_type = road
+            .types
+            .iter()
+            .rev()
+            .find(|(s, _, _)| *s <= section.s + 1e-9);
+        let road_speed = road_type.and_then(|(_, _, speed)| *speed);
+        let road_type = road_type
+            .map(|(_, kind, _)| kind.clone())
+            .unwrap_or_default();
+
+        // Sample evenly, never further apart than `SAMPLE_STEP`.
+        let count = (((end - section.s) / SAMPLE_STEP).ceil() as usize).max(1);
+        let stations: Vec<f64> = (0..=count)
+            .map(|i| section.s + (end - section.s) * i as f64 / count as f64)
+            .collect();
+
+        // Lanes are stacked outwards from the reference line on each side.
+        let mut left: Vec<&Lane> = section.lanes.iter().filter(|l| l.id > 0).collect();
+        let mut right: Vec<&Lane> = section.lanes.iter().filter(|l| l.id < 0).collect();
+        left.sort_by_key(|l| l.id);
+        right.sort_by_key(|l| -l.id);
+
+        for (side, lanes) in [(1.0, left), (-1.0, right)] {
+            let mut inner: Vec<f64> = stations
+                .iter()
+                .map(|s| evaluate(&road.lane_offsets, *s))
+                .collect();
+            for lane in lanes {
+                let widths: Vec<f64> = stations
+                    .iter()
+                    .map(|s| evaluate(&lane.widths, s - section.s).max(0.0))
+                    .collect();
+                let outer: Vec<f64> = inner
+                    .iter()
+                    .zip(&widths)
+                    .map(|(t, w)| t + side * w)
+                    .collect();
+                let heights = |records: &[Cubic]| -> Vec<f64> {
+                    stations
+                        .iter()
+                        .map(|s| evaluate(records, s - section.s))
+                        .collect()
+                };
+                let (inner_heights, outer_heights) =
+                    (heights(&lane.inner_heights), heights(&lane.outer_heights));
+
+                let edge = |offsets: &[f64], heights: &[f64]| -> Vec<DVec3> {
+                    stations
+                        .iter()
+                        .zip(offsets)
+                        .zip(heights)
+                        .map(|((s, t), h)| {
+                            let (x, y, hdg) = road.reference(*s);
+                            let z = evaluate(&road.elevations, *s) + h;
+                            transform.viewer_position(DVec3::new(
+                                x - t * hdg.sin(),
+                                y + t * hdg.cos(),
+                                z,
+                            ))
+                        })
+                        .collect()
+                };
+                let mean = |a: &[f64], b: &[f64]| -> Vec<f64> {
+                    a.iter().zip(b).map(|(a, b)| (a + b) / 2.0).collect()
+                };
+                let middle = edge(&mean(&inner, &outer), &mean(&inner_heights, &outer_heights));
+                let (inner_side, outer_side) =
+                    (edge(&inner, &inner_heights), edge(&outer, &outer_heights));
+                let (left_side, right_side) = if side > 0.0 {
+                    (outer_side, inner_side)
+                } else {
+                    (inner_side, outer_side)
+                };
+
+                segments.push(RoadSegment {
+                    start_pos: middle[0],
+                    end_pos: middle[middle.len() - 1],
+                    start_s: section.s,
+                    end_s: end,
+                    width: widths.iter().sum::<f64>() / widths.len() as f64,
+                    left_side,
+                    right_side,
+                    road_id,
+                    lane_id: lane.id,
+                    lane_section_id: index as u32 + 1,
+                    rule: road.rule,
+                    lane_type: lane.lane_type.clone(),
+                    road_type: road_type.clone(),
+                    speed: lane.speed.or(road_speed),
+                    materials: by_offset(&lane.materials),
+                    access: first_access(&lane.access),
+                    road_marks: by_offset(&lane.road_marks),
+                    center_marks: match lane.id.abs() {
+                        1 => by_offset(&section.center.road_marks),
+                        _ => Vec::new(),
+                    },
+                    predecessor: lane.predecessor,
+                    successor: lane.successor,
+                });
+                inner = outer;
+            }
+        }
+    }
+    segments
+}
+
+// The records of a road's reference line, moved into the map frame. They
+// cannot be kept for maps read with another axis pointing up, as the
+// reference line then leaves the ground plane.
+fn reference_line(road: &Road, transform: &LoadTransform) -> Option<ReferenceLine> {
+    let line = ReferenceLine {
+        geometry: road.geometries.iter().map(Geometry::record).collect(),
+        elevation: road.elevations.clone(),
+        lane_offset: road.lane_offsets.clone(),
+    };
+    (transform.up == UpAxis::Z).then(|| line.moved(transform.rotation, transform.offset))
+}
+
+// Samples the heading and curvature of each reference line piece, both ends
+// included. Headings are rotated into the map frame.
+fn sample_plan_view(road: &Road, transform: &LoadTransform) -> Vec<PlanSample> {
+    let mut samples = Vec::new();
+    for geometry in &road.geometries {
+        let count = ((geometry.length / SAMPLE_STEP).ceil() as usize).max(1);
+        for i in 0..=count {
+            let ds = geometry.length * i as f64 / count as f64;
+            let (_, _, hdg) = geometry.point(ds);
+            samples.push(PlanSample {
+                s: geometry.s + ds,
+                heading: hdg + transform.rotation,
+                curvature: geometry.curvature(ds),
+            });
+        }
+    }
+    samples
+}
+
+// What `parse` found besides the roads.
+#[derive(Debug, Default)]
+struct Parsed {
+    junctions: Vec<RawJunction>,
+    // Numbers that had to be repaired or could not be read.
+    warnings: Vec<String>,
+    // The XML error that ended reading early, and the road that was being
+    // read then.
+    error: Option<(String, Option<Road>)>,
+    // The PROJ string of `<header><geoReference>`.
+    geo_reference: String,
+    // The `name` of the header.
+    name: String,
+}
+
+// Walks the XML and collects the records needed for sampling, handing
+// each road to `on_road` as soon as it is complete. Malformed XML ends the
+// walk, but what was read before it is kept.
+fn parse(
+    input: impl BufRead,
+    source: Arc<XmlSource>,
+    mut on_road: impl FnMut(Road),
+) -> Result<Parsed, String> {
+    let element = |range: Range<usize>| ElementXml::Span(source.clone(), range);
+    let mut reader = Reader::from_reader(input);
+    let mut buffer = Vec::new();
+    let mut road: Option<Road> = None;
+    let mut junctions: Vec<RawJunction> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut geo_reference = String::new();
+    let mut header_name = String::new();
+    // Names of the currently open elements, outermost first, and where each
+    // of them starts in the file.
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut starts: Vec<usize> = Vec::new();
+
+    loop {
+        let start = reader.buffer_position();
+        buffer.clear();
+        let event = match reader.read_event_into(&mut buffer) {
+            Ok(event) => event,
+            Err(e) => {
+                let message = format!("XML error at byte {}: {e}", reader.buffer_position());
+                return Ok(Parsed {
+                    junctions,
+                    warnings,
+                    error: Some((message, road)),
+                    geo_reference,
+                    name: header_name,
+                });
+            }
+        };
+        let (e, empty) = match event {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::End(e) => {
+                let range = starts.pop().unwrap_or(0)..reader.buffer_position();
+                match e.name().as_ref() {
+                    b"road" => {
+                        if let Some(mut road) = road.take() {
+                            road.xml = element(range);
+                            on_road(finish_road(road));
+                        }
+                    }
+                    b"signal" | b"object" => {
+                        let signal = road.as_mut().and_then(|r| r.signals.last_mut());
+                        if let Some(signal) = signal {
+                            signal.xml = element(range);
+                        }
+                    }
+                    b"junction" if path.len() == 2 => {
+                        if let Some(junction) = junctions.last_mut() {
+                            junction.xml = element(range);
+                        }
+                    }
+                    _ => {}
+                }
+                path.pop();
+                continue;
+            }
+            // The PROJ string, usually in a CDATA section.
+            Event::Text(text) if path.last().is_some_and(|name| name == b"geoReference") => {
+                geo_reference.push_str(&String::from_utf8_lossy(&text));
+                continue;
+            }
+            Event::CData(text) if path.last().is_some_and(|name| name == b"geoReference") => {
+                geo_reference.push_str(&String::from_utf8_lossy(&text));
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let name = e.name().as_ref().to_vec();
+        let parent = path.last().map(Vec::as_slice);
+        let noted = warnings.len();
+        match (parent, name.as_slice()) {
+            (_, b"road") => {
+                road = Some(Road {
+                    id: text(&e, "id"),
+                    name: text(&e, "name"),
+                    length: number(&e, "length", &mut warnings),
+                    rule: TrafficRule::from_attribute(&text(&e, "rule")),
+                    junction: text(&e, "junction"),
+                    ..Road::default()
+                });
+            }
+            (Some(b"OpenDRIVE"), b"header") => header_name = text(&e, "name"),
+            (Some(b"link"), b"predecessor" | b"successor")
+                if path.len() >= 2 && path[path.len() - 2] == b"road" =>
+            {
+                if let Some(road) = road
+                    .as_mut()
+                    .filter(|_| text(&e, "elementType") != "junction")
+                {
+                    road.links.push(RawLink {
+                        contact: if name == b"predecessor" {
+                            ContactPoint::Start
+                        } else {
+                            ContactPoint::End
+                        },
+                        element: text(&e, "elementId"),
+                        other_contact: if text(&e, "contactPoint") == "end" {
+                            ContactPoint::End
+                        } else {
+                            ContactPoint::Start
+                        },
+                    });
+                }
+            }
+            (Some(b"link"), b"predecessor" | b"successor")
+                if path.len() >= 2 && path[path.len() - 2] == b"lane" =>
+            {
+                let id = lane_id(&e, &mut warnings);
+                if let Some(lane) = current_lane(&mut road, &path) {
+                    if name == b"predecessor" {
+                        lane.predecessor = id;
+                    } else {
+                        lane.successor = id;
+                    }
+                }
+            }
+            (Some(b"planView"), b"geometry") => {
+                if let Some(road) = road.as_mut() {
+                    road.geometries.push(Geometry {
+                        s: number(&e, "s", &mut warnings),
+                        x: number(&e, "x", &mut warnings),
+                        y: number(&e, "y", &mut warnings),
+                        hdg: number(&e, "hdg", &mut warnings),
+                        length: number(&e, "length", &mut warnings),
+                        shape: Shape::Line,
+                        table: Vec::new(),
+                    });
+                }
+            }
+            (Some(b"geometry"), shape) => {
+                let geometry = road.as_mut().and_then(|r| r.geometries.last_mut());
+                if let Some(geometry) = geometry {
+                    geometry.shape = match shape {
+                        b"arc" => Shape::Arc {
+                            curvature: number(&e, "curvature", &mut warnings),
+                        },
+                        b"spiral" => Shape::Spiral {
+                            start: number(&e, "curvStart", &mut warnings),
+                            end: number(&e, "curvEnd", &mut warnings),
+                        },
+                        b"poly3" => Shape::Poly3 {
+                            v: ["a", "b", "c", "d"].map(|k| number(&e, k, &mut warnings)),
+                        },
+                        b"paramPoly3" => Shape::ParamPoly3 {
+                            u: ["aU", "bU", "cU", "dU"].map(|k| number(&e, k, &mut warnings)),
+                            v: ["aV", "bV", "cV", "dV"].map(|k| number(&e, k, &mut warnings)),
+                            normalized: text(&e, "pRange") != "arcLength",
+                        },
+                        _ => Shape::Line,
+                    };
+                }
+            }
+            (Some(b"elevationProfile"), b"elevation") => {
+                if let Some(road) = road.as_mut() {
+                    road.elevations
+                        .push(Cubic::from_attributes(&e, "s", &mut warnings));
+                }
+            }
+            (Some(b"lanes"), b"laneOffset") => {
+                if let Some(road) = road.as_mut() {
+                    road.lane_offsets
+                        .push(Cubic::from_attributes(&e, "s", &mut warnings));
+                }
+            }
+            (Some(b"lanes"), b"laneSection") => {
+                if let Some(road) = road.as_mut() {
+                    road.sections.push(Section {
+                        s: number(&e, "s", &mut warnings),
+                        ..Section::default()
+                    });
+                }
+            }
+            (Some(b"left" | b"right"), b"lane") => {
+                let section = road.as_mut().and_then(|r| r.sections.last_mut());
+                // A lane without an ID is kept as lane zero, which sampling
+                // leaves out, so that its records are not taken for those of
+                // the lane before.
+                let id = lane_id(&e, &mut warnings).unwrap_or(0);
+                if let Some(section) = section {
+                    section.lanes.push(Lane {
+                        id,
+                        lane_type: Some(text(&e, "type"))
+                            .filter(|t| !t.is_empty())
+                            .unwrap_or_else(|| "driving".to_string()),
+                        widths: Vec::new(),
+                        speed: None,
+                        ..Lane::default()
+                    });
+                }
+            }
+            (Some(b"signals"), b"signal") | (Some(b"objects"), b"object") => {
+                if let Some(road) = road.as_mut() {
+                    let is_object = name == b"object";
+                    let dynamic = text(&e, "dynamic") == "yes";
+                    let type_code = text(&e, "type");
+                    let signal_name = text(&e, "name");
+                    road.signals.push(Signal {
+                        id: text(&e, "id"),
+                        road_id: 0,
+                        s: number(&e, "s", &mut warnings),
+                        t: number(&e, "t", &mut warnings),
+                        z_offset: number(&e, "zOffset", &mut warnings),
+                        height: number(&e, "height", &mut warnings),
+                        width: number(&e, "width", &mut warnings),
+                        length: number(&e, "length", &mut warnings),
+                        radius: number(&e, "radius", &mut warnings),
+                        orientation: text(&e, "orientation"),
+                        kind: if is_object {
+                            SignalKind::Object
+                        } else {
+                            SignalKind::classify(&type_code, &signal_name, dynamic)
+                        },
+                        subtype: text(&e, "subtype"),
+                        country: text(&e, "country"),
+                        dynamic,
+                        value: optional_number(&e, "value", &mut warnings),
+                        unit: text(&e, "unit"),
+                        position: DVec3::ZERO,
+                        repeats: Vec::new(),
+                        type_code,
+                        name: signal_name,
+                        xml: if empty {
+                            element(start..reader.buffer_position())
+                        } else {
+                            ElementXml::None
+                        },
+                    });
+                }
+            }
+            (Some(b"object"), b"repeat") => {
+                let object = road.as_mut().and_then(|r| r.signals.last_mut());
+                if let Some(object) = object {
+                    object.repeats.push(ObjectRepeat {
+                        s: number(&e, "s", &mut warnings),
+                        length: number(&e, "length", &mut warnings),
+                        distance: number(&e, "distance", &mut warnings),
+                        t_start: number(&e, "tStart", &mut warnings),
+                        t_end: number(&e, "tEnd", &mut warnings),
+                        z_offset_start: number(&e, "zOffsetStart", &mut warnings),
+                        z_offset_end: number(&e, "zOffsetEnd", &mut warnings),
+                        height_start: number(&e, "heightStart", &mut warnings),
+                        height_end: number(&e, "heightEnd", &mut warnings),
+                        width_start: number(&e, "widthStart", &mut warnings),
+                        width_end: number(&e, "widthEnd", &mut warnings),
+                    });
+                }
+            }
+            (Some(b"OpenDRIVE"), b"junction") => {
+                let xml = if empty {
+                    element(start..reader.buffer_position())
+                } else {
+                    ElementXml::None
+                };
+                junctions.push(RawJunction {
+                    id: text(&e, "id"),
+                    xml,
+                });
+            }
+            (Some(b"road"), b"type") => {
+                if let Some(road) = road.as_mut() {
+                    let kind = text(&e, "type").trim().to_string();
+                    road.types
+                        .push((number(&e, "s", &mut warnings), kind, None));
+                }
+            }
+            (Some(b"type"), b"speed") => {
+                let record = road.as_mut().and_then(|r| r.types.last_mut());
+                if let Some(record) = record {
+                    record.2 = speed(&e, &mut warnings);
+                }
+            }
+            (Some(b"lane"), b"speed") => {
+                let lane = current_lane(&mut road, &path);
+                if let Some(lane) = lane.filter(|lane| lane.speed.is_none()) {
+                    lane.speed = speed(&e, &mut warnings);
+                }
+            }
+            (Some(b"lane"), b"material") => {
+                let lane = current_lane(&mut road, &path);
+                if let Some(lane) = lane {
+                    lane.materials.push(LaneMaterial {
+                        s_offset: number(&e, "sOffset", &mut warnings),
+                        surface: text(&e, "surface").trim().to_string(),
+                        friction: number(&e, "friction", &mut warnings),
+                        roughness: number(&e, "roughness", &mut warnings),
+                    });
+                }
+            }
+            (Some(b"lane"), b"roadMark") => {
+                if let Some(lane) = current_lane(&mut road, &path) {
+                    let s_offset = number(&e, "sOffset", &mut warnings);
+                    let width = optional_number(&e, "width", &mut warnings);
+                    lane.road_marks.push(RoadMark {
+                        s_offset,
+                        kind: text(&e, "type").trim().to_string(),
+                        color: text(&e, "color").trim().to_string(),
+                        weight: text(&e, "weight").trim().to_string(),
+                        material: text(&e, "material").trim().to_string(),
+                        width,
+                        ..RoadMark::default()
+                    });
+                }
+            }
+            // A road mark's explicit type and its lines.
+            (Some(b"roadMark"), b"type") => {
+                let lane = current_lane(&mut road, &path);
+                if let Some(mark) = lane.and_then(|lane| lane.road_marks.last_mut()) {
+                    mark.type_name = text(&e, "name").trim().to_string();
+                    mark.type_width = optional_number(&e, "width", &mut warnings);
+                }
+            }
+            (Some(b"type"), b"line") if path.len() >= 2 && path[path.len() - 2] == b"roadMark" => {
+                let lane = current_lane(&mut road, &path);
+                if let Some(mark) = lane.and_then(|lane| lane.road_marks.last_mut()) {
+                    mark.lines.push(MarkLine {
+                        length: number(&e, "length", &mut warnings),
+                        space: number(&e, "space", &mut warnings),
+                        t_offset: number(&e, "tOffset", &mut warnings),
+                        s_offset: number(&e, "sOffset", &mut warnings),
+                        width: optional_number(&e, "width", &mut warnings),
+                        color: Some(text(&e, "color").trim().to_string())
+                            .filter(|color| !color.is_empty()),
+                    });
+                }
+            }
+            (Some(b"lane"), b"access") => {
+                if let Some(lane) = current_lane(&mut road, &path) {
+                    // Before OpenDRIVE 1.5 there was no `rule`, and the
+                    // restriction named who is allowed.
+                    let rule = LaneAccess {
+                        allow: text(&e, "rule") != "deny",
+                        restriction: text(&e, "restriction"),
+                    };
+                    lane.access
+                        .push((number(&e, "sOffset", &mut warnings), rule));
+                }
+            }
+            (Some(b"lane"), b"width") => {
+                if let Some(lane) = current_lane(&mut road, &path) {
+                    lane.widths
+                        .push(Cubic::from_attributes(&e, "sOffset", &mut warnings));
+                }
+            }
+            (Some(b"lane"), b"height") => {
+                if let Some(lane) = current_lane(&mut road, &path) {
+                    let s = number(&e, "sOffset", &mut warnings);
+                    let mut height = |name| Cubic {
+                        s,
+                        a: number(&e, name, &mut warnings),
+                        ..Cubic::default()
+                    };
+                    lane.inner_heights.push(height("inner"));
+                    lane.outer_heights.push(height("outer"));
+                }
+            }
+            _ => {}
+        }
+        if let Some(road) = &road {
+            for warning in &mut warnings[noted..] {
+                *warning = format!("road {}: {warning}", road.id);
+            }
+        }
+
+        if !empty {
+            path.push(name);
+            starts.push(start);
+        }
+    }
+
+    Ok(Parsed {
+        junctions,
+        warnings,
+        error: None,
+        geo_reference,
+        name: header_name,
+    })
+}
+
+// Sorts a road's records and prepares its geometry for evaluation.
+fn finish_road(mut road: Road) -> Road {
+    let by_s = |a: &Cubic, b: &Cubic| a.s.total_cmp(&b.s);
+    road.elevations.sort_by(by_s);
+    road.lane_offsets.sort_by(by_s);
+    road.types.sort_by(|a, b| a.0.total_cmp(&b.0));
+    road.sections.sort_by(|a, b| a.s.total_cmp(&b.s));
+    road.geometries.sort_by(|a, b| a.s.total_cmp(&b.s));
+    for geometry in &mut road.geometries {
+        geometry.build_table();
+    }
+    for section in &mut road.sections {
+        section.lanes.retain(|lane| lane.id != 0);
+        for lane in &mut section.lanes {
+            lane.widths.sort_by(by_s);
+            lane.inner_heights.sort_by(by_s);
+            lane.outer_heights.sort_by(by_s);
+        }
+    }
+    if road.length <= 0.0 {
+        road.length = road.geometries.last().map_or(0.0, |g| g.s + g.length);
+    }
+    road
+}
+
+// The lane the element being read belongs to: the last one read, or the
+// center lane inside `<center>`.
+fn current_lane<'a>(road: &'a mut Option<Road>, path: &[Vec<u8>]) -> Option<&'a mut Lane> {
+    let section = road.as_mut()?.sections.last_mut()?;
+    if path.iter().any(|name| name == b"center") {
+        Some(&mut section.center)
+    } else {
+        section.lanes.last_mut()
+    }
+}
+
+// The attributes of a junction's elements that refer to roads.
+const JUNCTION_ROAD_ATTRIBUTES: [&str; 9] = [
+    "incomingRoad",
+    "connectingRoad",
+    "linkedRoad",
+    "high",
+    "low",
+    "crossingRoad",
+    "roadAtStart",
+    "roadAtEnd",
+    "roadId",
+];
+
+// Renders a `<road>` or `<junction>` element with its own ID and its
+// references to roads and junctions passed through `road` and `junction`,
+// which give None for references they leave alone. Elements that do not
+// change are kept as they were written.
+pub fn renumber(
+    xml: &str,
+    road: impl Fn(&str) -> Option<u32>,
+    junction: impl Fn(&str) -> Option<u32>,
+) -> String {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = quick_xml::Writer::new(Vec::with_capacity(xml.len()));
+    let renumber = |e| renumber_element(e, &road, &junction);
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) => Event::Start(renumber(e)),
+            Ok(Event::Empty(e)) => Event::Empty(renumber(e)),
+            Ok(event) => event,
+            Err(_) => return xml.to_string(),
+        };
+        if writer.write_event(event).is_err() {
+            return xml.to_string();
+        }
+    }
+    String::from_utf8(writer.into_inner()).unwrap_or_else(|_| xml.to_string())
+}
+
+// Renders a `<junction>` element with the ID `id` and its references to
+// roads passed through `road`, leaving out the elements that refer to a
+// road `road` does not give. None if no connection is left.
+pub fn remap_junction(xml: &str, id: u32, road: impl Fn(&str) -> Option<u32>) -> Option<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = quick_xml::Writer::new(Vec::with_capacity(xml.len()));
+    let junction = |_: &str| Some(id);
+    // How deep inside a left out element the reader is.
+    let mut skipping = 0;
+    let mut connections = 0;
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => event,
+            Err(_) => return None,
+        };
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        let event = match event {
+            Event::Start(e) if refers_elsewhere(&e, &road) => {
+                skipping = 1;
+                continue;
+            }
+            Event::Empty(e) if refers_elsewhere(&e, &road) => continue,
+            Event::Start(e) => {
+                connections += usize::from(e.name().as_ref() == b"connection");
+                Event::Start(renumber_element(e, &road, &junction))
+            }
+            Event::Empty(e) => {
+                connections += usize::from(e.name().as_ref() == b"connection");
+                Event::Empty(renumber_element(e, &road, &junction))
+            }
+            event => event,
+        };
+        writer.write_event(event).ok()?;
+    }
+    (connections > 0).then(|| String::from_utf8(writer.into_inner()).ok())?
+}
+
+// Points the connections of a junction that come in on road `from` through
+// one of the `connecting` roads at road `to`. Other connections, and other
+// references to `from`, are kept as they are.
+pub fn retarget_connections(xml: &str, from: u32, to: u32, connecting: &[u32]) -> String {
+    let road = |value: &str| (value.parse() == Ok(from)).then_some(to);
+    let junction = |_: &str| None;
+    let reached = |e: &BytesStart| {
+        element_name(e) == "connection"
+            && text(e, "incomingRoad").trim().parse() == Ok(from)
+            && text(e, "connectingRoad")
+                .trim()
+                .parse()
+                .is_ok_and(|road: u32| connecting.contains(&road))
+    };
+    let mut reader = Reader::from_str(xml);
+    let mut writer = quick_xml::Writer::new(Vec::with_capacity(xml.len()));
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(Event::Start(e)) if reached(&e) => {
+                Event::Start(renumber_element(e, &road, &junction))
+            }
+            Ok(Event::Empty(e)) if reached(&e) => {
+                Event::Empty(renumber_element(e, &road, &junction))
+            }
+            Ok(event) => event,
+            Err(_) => return xml.to_string(),
+        };
+        if writer.write_event(event).is_err() {
+            return xml.to_string();
+        }
+    }
+    String::from_utf8(writer.into_inner()).unwrap_or_else(|_| xml.to_string())
+}
+
+// Points the link of a `<road>` element at its start or end at road `road`,
+// keeping the contact point, or leaves it out with none. Lane links are left
+// alone.
+pub fn relink(xml: &str, contact: ContactPoint, road: Option<u32>) -> String {
+    let wanted: &[u8] = match contact {
+        ContactPoint::Start => b"predecessor",
+        ContactPoint::End => b"successor",
+    };
+    let mut reader = Reader::from_str(xml);
+    let mut writer = quick_xml::Writer::new(Vec::with_capacity(xml.len()));
+    // The elements the reader is inside, and how deep inside a left out
+    // element.
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut skipping = 0;
+    let target = |e: &BytesStart, path: &[Vec<u8>]| {
+        path.len() == 2
+            && path[0] == b"road"
+            && path[1] == b"link"
+            && e.name().as_ref() == wanted
+            && text(e, "elementType") == "road"
+    };
+    let renumber = |e| {
+        let road = |_: &str| road;
+        renumber_element(e, &road, &|_: &str| None)
+    };
+    loop {
+        let event = match reader.read_event() {
+            Ok(Event::Eof) => break,
+            Ok(event) => event,
+            Err(_) => return xml.to_string(),
+        };
+        if skipping > 0 {
+            match event {
+                Event::Start(_) => skipping += 1,
+                Event::End(_) => skipping -= 1,
+                _ => {}
+            }
+            continue;
+        }
+        let event = match event {
+            Event::Start(e) if target(&e, &path) && road.is_none() => {
+                skipping = 1;
+                continue;
+            }
+            Event::Empty(e) if target(&e, &path) && road.is_none() => continue,
+            Event::Start(e) if target(&e, &path) => {
+                path.push(e.name().as_ref().to_vec());
+                Event::Start(renumber(e))
+            }
+            Event::Empty(e) if target(&e, &path) => Event::Empty(renumber(e)),
+            Event::Start(e) => {
+                path.push(e.name().as_ref().to_vec());
+                Event::Start(e)
+            }
+            Event::End(e) => {
+                path.pop();
+                Event::End(e)
+            }
+            event => event,
+        };
+        if writer.write_event(event).is_err() {
+            return xml.to_string();
+        }
+    }
+    String::from_utf8(writer.into_inner()).unwrap_or_else(|_| xml.to_string())
+}
+
+fn refers_elsewhere(e: &BytesStart, road: &impl Fn(&str) -> Option<u32>) -> bool {
+    let links_road = text(e, "elementType") == "road";
+    e.attributes().flatten().any(|attribute| {
+        let key = attribute.key.as_ref();
+        let names_road = JUNCTION_ROAD_ATTRIBUTES
+            .iter()
+            .any(|name| name.as_bytes() == key)
+            || links_road && key == b"elementId";
+        names_road
+            && attribute
+                .unescape_value()
+                .map_or(true, |value| road(value.trim()).is_none())
+    })
+}
+
+fn renumber_element<'a>(
+    e: BytesStart<'a>,
+    road: &impl Fn(&str) -> Option<u32>,
+    junction: &impl Fn(&str) -> Option<u32>,
+) -> BytesStart<'a> {
+    let name = element_name(&e);
+    let element_type = text(&e, "elementType");
+    let mut changed = false;
+    let attributes: Vec<(String, String)> = e
+        .attributes()
+        .flatten()
+        .map(|attribute| {
+            let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+            let value = attribute
+                .unescape_value()
+                .map(|v| v.into_owned())
+                .unwrap_or_default();
+            let refers_to_road = (name == "road" && key == "id")
+                || JUNCTION_ROAD_ATTRIBUTES.contains(&key.as_str())
+                || element_type == "road" && key == "elementId";
+            let refers_to_junction = (name == "junction" && key == "id")
+                || (name == "road" && key == "junction")
+                || element_type == "junction" && key == "elementId";
+            let number = if refers_to_road {
+                road(value.trim())
+            } else if refers_to_junction {
+                junction(value.trim())
+            } else {
+                None
+            };
+            match number.map(|n| n.to_string()) {
+                Some(number) if number != value => {
+                    changed = true;
+                    (key, number)
+                }
+                _ => (key, value),
+            }
+        })
+        .collect();
+    if !changed {
+        return e;
+    }
+    let mut renumbered = BytesStart::new(name);
+    for (key, value) in &attributes {
+        renumbered.push_attribute((key.as_str(), value.as_str()));
+    }
+    renumbered
+}
+
+// Reads an attribute as text, empty if missing.
+fn text(e: &BytesStart, name: &str) -> String {
+    e.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+        .unwrap_or_default()
+}
+
+// Reads the `max` of a `<speed>` record in m/s, in the record's unit. "no
+// limit" and "undefined" give none.
+fn speed(e: &BytesStart, warnings: &mut Vec<String>) -> Option<f64> {
+    let max = optional_number(e, "max", warnings)?;
+    Some(units::speed_from(max, &text(e, "unit")))
+}
+
+// Reads the `id` of a lane or lane link, None if it is not a whole number,
+// which is noted in `warnings`.
+fn lane_id(e: &BytesStart, warnings: &mut Vec<String>) -> Option<i32> {
+    let value = text(e, "id");
+    let id = value.trim().parse().ok();
+    if id.is_none() {
+        warnings.push(format!(
+            "{} id=\"{}\" is not a lane ID, left out",
+            element_name(e),
+            value.trim()
+        ));
+    }
+    id
+}
+
+// A lane's road marks or materials in `sOffset` order, those at the same
+// offset in the order read.
+fn by_offset<T: LaneRecord>(records: &[T]) -> Vec<T> {
+    let mut records = records.to_vec();
+    records.sort_by(|a, b| a.s_offset().total_cmp(&b.s_offset()));
+    records
+}
+
+// The access rules in force where a lane section starts: those of the first
+// `sOffset`, as only the first speed record is kept.
+fn first_access(records: &[(f64, LaneAccess)]) -> Vec<LaneAccess> {
+    let first = records
+        .iter()
+        .map(|(offset, _)| *offset)
+        .fold(f64::INFINITY, f64::min);
+    records
+        .iter()
+        .filter(|(offset, _)| *offset == first)
+        .map(|(_, rule)| rule.clone())
+        .collect()
+}
+
+// Reads a numeric attribute, 0 if it is missing. Numbers that had to be
+// repaired, or could not be read at all, are noted in `warnings`.
+fn number(e: &BytesStart, name: &str, warnings: &mut Vec<String>) -> f64 {
+    let value = text(e, name);
+    if value.trim().is_empty() {
+        return 0.0;
+    }
+    optional_number(e, name, warnings).unwrap_or_else(|| {
+        warnings.push(format!(
+            "{} {name}=\"{}\" is not a number, read as 0",
+            element_name(e),
+            value.trim()
+        ));
+        0.0
+    })
+}
+
+// Reads a numeric attribute that may be missing or hold a word such as "no
+// limit" instead. Numbers that had to be repaired are noted in `warnings`.
+fn optional_number(e: &BytesStart, name: &str, warnings: &mut Vec<String>) -> Option<f64> {
+    let value = text(e, name);
+    let (number, repairs) = numbers::parse(&value)?;
+    if !repairs.is_empty() {
+        let repairs: Vec<&str> = repairs.iter().map(|repair| repair.describe()).collect();
+        warnings.push(format!(
+            "{} {name}=\"{}\" read as {number} ({})",
+            element_name(e),
+            value.trim(),
+            repairs.join(", ")
+        ));
+    }
+    Some(number)
+}
+
+fn element_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.name().as_ref()).into_owned()
+}
+
+// ---------------------------------------------------------------------------
+// Writing
+// ---------------------------------------------------------------------------
+
+// Writes the network as an OpenDRIVE 1.6 file.
+pub fn write_map(network: &RoadNetwork, path: &Path) -> io::Result<()> {
+    std::fs::write(path, to_xml(network))
+}
+
+// Renders the network as OpenDRIVE XML.
+pub fn to_xml(network: &RoadNetwork) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" standalone=\"yes\"?>\n");
+    xml.push_str("<OpenDRIVE>\n");
+    let header = format!(
+        "  <header revMajor=\"1\" revMinor=\"6\" name=\"{}\" version=\"1.00\" vendor=\"rsodr\"",
+        escape(&network.name)
+    );
+    match &network.geo {
+        Some(geo) => {
+            let _ = writeln!(
+                xml,
+                "{header}>\n    <geoReference><![CDATA[{}]]></geoReference>\n  </header>",
+                geo.proj.replace("]]>", "]]]]><![CDATA[>")
+            );
+        }
+        None => {
+            let _ = writeln!(xml, "{header}/>");
+        }
+    }
+
+    // Group lanes by road and lane section, both in ID order.
+    let mut roads: BTreeMap<u32, BTreeMap<u32, Vec<&RoadSegment>>> = BTreeMap::new();
+    for segment in &network.segments {
+        roads
+            .entry(segment.road_id)
+            .or_default()
+            .entry(segment.lane_section_id)
+            .or_default()
+            .push(segment);
+    }
+    for (road_id, sections) in &roads {
+        let sections: Vec<Vec<&RoadSegment>> = sections.values().cloned().collect();
+        let links: Vec<&RoadLink> = network
+            .links
+            .iter()
+            .filter(|link| link.road_id == *road_id)
+            .collect();
+        let signals: Vec<&Signal> = network
+            .signals
+            .iter()
+            .filter(|signal| signal.road_id == *road_id)
+            .collect();
+        let info = network.roads.get(road_id);
+        let name = info.map_or("", |info| info.name.as_str());
+        let junction = info.and_then(|info| info.junction);
+        // Ends of the road that lead into a junction, as its connecting
+        // roads' links tell.
+        let junction_links: Vec<(ContactPoint, u32)> = [ContactPoint::Start, ContactPoint::End]
+            .into_iter()
+            .filter(|&contact| !links.iter().any(|link| link.contact == contact))
+            .filter_map(|contact| {
+                let into = network.links.iter().find_map(|link| {
+                    let connecting = network.roads.get(&link.road_id)?.junction?;
+                    (link.other_road_id == *road_id && link.other_contact == contact)
+                        .then_some(connecting)
+                });
+                into.filter(|&into| Some(into) != junction)
+                    .map(|into| (contact, into))
+            })
+            .collect();
+        let road = WrittenRoad {
+            id: *road_id,
+            name,
+            junction,
+            links: &links,
+            junction_links: &junction_links,
+            signals: &signals,
+            reference: info.and_then(|info| info.reference.as_ref()),
+        };
+        write_road(&mut xml, &road, &sections);
+    }
+    for element in network.junctions.values() {
+        let _ = writeln!(xml, "  {}", element.text().unwrap_or_default().trim());
+    }
+
+    xml.push_str("</OpenDRIVE>\n");
+    xml
+}
+
+// The reference line edge of a lane: the boundary facing lane zero.
+pub fn inner_edge(lane: &RoadSegment) -> &[DVec3] {
+    if lane.lane_id < 0 {
+        &lane.left_side
+    } else {
+        &lane.right_side
+    }
+}
+
+// What is written of a road beside its lanes.
+struct WrittenRoad<'a> {
+    id: u32,
+    name: &'a str,
+    junction: Option<u32>,
+    links: &'a [&'a RoadLink],
+    // Ends of the road leading into a junction, with the junction's ID.
+    junction_links: &'a [(ContactPoint, u32)],
+    signals: &'a [&'a Signal],
+    // The reference line records the road was read with.
+    reference: Option<&'a ReferenceLine>,
+}
+
+// Writes one road with its lane sections.
+fn write_road(xml: &mut String, road: &WrittenRoad, sections: &[Vec<&RoadSegment>]) {
+    let WrittenRoad {
+        id: road_id,
+        name,
+        links,
+        signals,
+        ..
+    } = *road;
+    // The reference line of each section is the inner edge of its innermost
+    // lane. Sections are joined end to end, dropping the shared point.
+    let mut reference: Vec<DVec3> = Vec::new();
+    let mut section_starts = Vec::new();
+    for lanes in sections {
+        section_starts.push(reference.len().saturating_sub(1));
+        let innermost = lanes.iter().min_by_key(|lane| lane.lane_id.abs()).unwrap();
+        let edge = inner_edge(innermost).iter().copied().map(to_odr);
+        if reference.is_empty() {
+            reference.extend(edge);
+        } else {
+            reference.extend(edge.skip(1));
+        }
+    }
+    // The records the road was read with are written back while its lanes
+    // still follow them; otherwise the inner edge is written as straight
+    // pieces.
+    let source = road.reference.filter(|line| follows(line, sections));
+    let stations = match source {
+        Some(_) => sections
+            .iter()
+            .enumerate()
+            .flat_map(|(index, lanes)| {
+                let innermost = lanes.iter().min_by_key(|lane| lane.lane_id.abs()).unwrap();
+                section_stations(innermost)
+                    .into_iter()
+                    .skip(usize::from(index > 0))
+            })
+            .collect(),
+        None => stations(&reference),
+    };
+    let length = match sections.last().and_then(|lanes| lanes.first()) {
+        Some(lane) if source.is_some() => lane.end_s,
+        _ => stations.last().copied().unwrap_or(0.0),
+    };
+
+    // The rule is only written for left-hand traffic, right-hand being the
+    // default.
+    let rule = match sections.first().and_then(|lanes| lanes.first()) {
+        Some(lane) if lane.rule == TrafficRule::LeftHand => {
+            format!(" rule=\"{}\"", lane.rule.as_attribute())
+        }
+        _ => String::new(),
+    };
+    let _ = writeln!(
+        xml,
+        "  <road name=\"{}\" length=\"{length:.6}\" id=\"{road_id}\" junction=\"{}\"{rule}>",
+        escape(name),
+        road.junction.map_or(-1, i64::from)
+    );
+
+    if !links.is_empty() || !road.junction_links.is_empty() {
+        xml.push_str("    <link>\n");
+        for (contact, element) in [
+            (ContactPoint::Start, "predecessor"),
+            (ContactPoint::End, "successor"),
+        ] {
+            // OpenDRIVE allows one road link per end.
+            if let Some(link) = links.iter().find(|link| link.contact == contact) {
+                let other = match link.other_contact {
+                    ContactPoint::Start => "start",
+                    ContactPoint::End => "end",
+                };
+                let _ = writeln!(
+                    xml,
+                    "      <{element} elementType=\"road\" elementId=\"{}\" contactPoint=\"{other}\"/>",
+                    link.other_road_id
+                );
+            } else if let Some((_, junction)) =
+                road.junction_links.iter().find(|(end, _)| *end == contact)
+            {
+                let _ = writeln!(
+                    xml,
+                    "      <{element} elementType=\"junction\" elementId=\"{junction}\"/>"
+                );
+            }
+        }
+        xml.push_str("    </link>\n");
+    }
+
+    // Speed limits are written with the lanes, so road types have none.
+    let mut road_type = "";
+    for (index, lanes) in sections.iter().enumerate() {
+        let kind = lanes.first().map_or("", |lane| lane.road_type.as_str());
+        if !kind.is_empty() && kind != road_type {
+            let _ = writeln!(
+                xml,
+                "    <type s=\"{:.6}\" type=\"{}\"/>",
+                stations[section_starts[index]],
+                escape(kind)
+            );
+        }
+        road_type = kind;
+    }
+
+    xml.push_str("    <planView>\n");
+    match source {
+        Some(line) => {
+            for record in &line.geometry {
+                write_geometry(xml, record);
+            }
+        }
+        None => {
+            for (from, to) in straight_runs(&reference) {
+                let d = reference[to] - reference[from];
+                let _ = writeln!(
+                    xml,
+                    "      <geometry s=\"{:.6}\" x=\"{:.6}\" y=\"{:.6}\" hdg=\"{:.9}\" length=\"{:.6}\"><line/></geometry>",
+                    stations[from],
+                    reference[from].x,
+                    reference[from].y,
+                    d.y.atan2(d.x),
+                    stations[to] - stations[from]
+                );
+            }
+        }
+    }
+    xml.push_str("    </planView>\n");
+
+    xml.push_str("    <elevationProfile>\n");
+    match source {
+        Some(line) => {
+            for record in &line.elevation {
+                write_cubic(xml, "      <elevation s", record);
+            }
+        }
+        None => {
+            let heights: Vec<f64> = reference.iter().map(|p| p.z).collect();
+            for (from, to) in linear_runs(&heights, &stations) {
+                let grade = (heights[to] - heights[from]) / (stations[to] - stations[from]);
+                let _ = writeln!(
+                    xml,
+                    "      <elevation s=\"{:.6}\" a=\"{:.6}\" b=\"{grade:.9}\" c=\"0\" d=\"0\"/>",
+                    stations[from], heights[from]
+                );
+            }
+        }
+    }
+    xml.push_str("    </elevationProfile>\n");
+
+    // Lane links at the ends of the road need a road there to refer to.
+    let linked_at = |contact| links.iter().any(|link| link.contact == contact);
+    let ends = (linked_at(ContactPoint::Start), linked_at(ContactPoint::End));
+    xml.push_str("    <lanes>\n");
+    for record in source.map_or(&[][..], |line| &line.lane_offset) {
+        write_cubic(xml, "      <laneOffset s", record);
+    }
+    for (index, lanes) in sections.iter().enumerate() {
+        let first = section_starts[index];
+        let _ = writeln!(xml, "      <laneSection s=\"{:.6}\">", stations[first]);
+
+        let mut left: Vec<&&RoadSegment> = lanes.iter().filter(|l| l.lane_id > 0).collect();
+        let mut right: Vec<&&RoadSegment> = lanes.iter().filter(|l| l.lane_id < 0).collect();
+        left.sort_by_key(|l| -l.lane_id);
+        right.sort_by_key(|l| -l.lane_id);
+
+        if !left.is_empty() {
+            xml.push_str("        <left>\n");
+            for lane in left {
+                write_lane(
+                    xml,
+                    lane,
+                    &stations[first..],
+                    &reference[first..],
+                    sections,
+                    index,
+                    ends,
+                );
+            }
+            xml.push_str("        </left>\n");
+        }
+        let center = lanes
+            .iter()
+            .map(|lane| &lane.center_marks)
+            .find(|marks| !marks.is_empty());
+        match center {
+            Some(marks) => {
+                xml.push_str(
+                    "        <center>\n          <lane id=\"0\" type=\"none\" level=\"false\">\n",
+                );
+                for mark in marks {
+                    write_road_mark(xml, mark);
+                }
+                xml.push_str("          </lane>\n        </center>\n");
+            }
+            None => xml.push_str(
+                "        <center>\n          <lane id=\"0\" type=\"none\" level=\"false\"/>\n        </center>\n",
+            ),
+        }
+        if !right.is_empty() {
+            xml.push_str("        <right>\n");
+            for lane in right {
+                write_lane(
+                    xml,
+                    lane,
+                    &stations[first..],
+                    &reference[first..],
+                    sections,
+                    index,
+                    ends,
+                );
+            }
+            xml.push_str("        </right>\n");
+        }
+        xml.push_str("      </laneSection>\n");
+    }
+    xml.push_str("    </lanes>\n");
+
+    // Objects come before signals in the schema. Stations are written as
+    // read; the reference line written above follows the original one.
+    let (objects, signals): (Vec<&Signal>, Vec<&Signal>) = signals
+        .iter()
+        .partition(|signal| signal.kind == SignalKind::Object);
+    if !objects.is_empty() {
+        xml.push_str("    <objects>\n");
+        for object in objects {
+            // The footprint is written as read: a radius or a box.
+            let footprint = if object.radius > 0.0 {
+                format!(" radius=\"{:.6}\"", object.radius)
+            } else if object.width > 0.0 || object.length > 0.0 {
+                format!(
+                    " width=\"{:.6}\" length=\"{:.6}\"",
+                    object.width, object.length
+                )
+            } else {
+                String::new()
+            };
+            let subtype = if object.subtype.is_empty() {
+                String::new()
+            } else {
+                format!(" subtype=\"{}\"", escape(&object.subtype))
+            };
+            let close = if object.repeats.is_empty() { "/" } else { "" };
+            let _ = writeln!(
+                xml,
+                "      <object id=\"{}\" name=\"{}\" type=\"{}\"{subtype} s=\"{:.6}\" t=\"{:.6}\" zOffset=\"{:.6}\" height=\"{:.6}\" orientation=\"{}\"{footprint}{close}>",
+                escape(&object.id),
+                escape(&object.name),
+                escape(&object.type_code),
+                object.s,
+                object.t,
+                object.z_offset,
+                object.height,
+                escape(&object.orientation),
+            );
+            if object.repeats.is_empty() {
+                continue;
+            }
+            for repeat in &object.repeats {
+                // Sizes not given are left out, as they were read.
+                let mut sizes = String::new();
+                for (name, start, end) in [
+                    ("height", repeat.height_start, repeat.height_end),
+                    ("width", repeat.width_start, repeat.width_end),
+                ] {
+                    if start > 0.0 || end > 0.0 {
+                        let _ = write!(sizes, " {name}Start=\"{start:.6}\" {name}End=\"{end:.6}\"");
+                    }
+                }
+                let _ = writeln!(
+                    xml,
+                    "        <repeat s=\"{:.6}\" length=\"{:.6}\" distance=\"{:.6}\" tStart=\"{:.6}\" tEnd=\"{:.6}\" zOffsetStart=\"{:.6}\" zOffsetEnd=\"{:.6}\"{sizes}/>",
+                    repeat.s,
+                    repeat.length,
+                    repeat.distance,
+                    repeat.t_start,
+                    repeat.t_end,
+                    repeat.z_offset_start,
+                    repeat.z_offset_end,
+                );
+            }
+            xml.push_str("      </object>\n");
+        }
+        xml.push_str("    </objects>\n");
+    }
+    if !signals.is_empty() {
+        xml.push_str("    <signals>\n");
+        for signal in signals {
+            let mut value = signal
+                .value
+                .map(|v| format!(" value=\"{v}\" unit=\"{}\"", escape(&signal.unit)))
+                .unwrap_or_default();
+            // Sizes not given are left out, as they were read.
+            for (name, size) in [("width", signal.width), ("length", signal.length)] {
+                if size > 0.0 {
+                    let _ = write!(value, " {name}=\"{size:.6}\"");
+                }
+            }
+            let _ = writeln!(
+                xml,
+                "      <signal id=\"{}\" name=\"{}\" s=\"{:.6}\" t=\"{:.6}\" zOffset=\"{:.6}\" height=\"{:.6}\" orientation=\"{}\" dynamic=\"{}\" country=\"{}\" type=\"{}\" subtype=\"{}\"{value}/>",
+                escape(&signal.id),
+                escape(&signal.name),
+                signal.s,
+                signal.t,
+                signal.z_offset,
+                signal.height,
+                escape(&signal.orientation),
+                if signal.dynamic { "yes" } else { "no" },
+                escape(&signal.country),
+                escape(&signal.type_code),
+                escape(&signal.subtype),
+            );
+        }
+        xml.push_str("    </signals>\n");
+    }
+
+    xml.push_str("  </road>\n");
+}
+
+// Writes one lane. `stations` holds the reference line station of each
+// polyline vertex, starting at the section start, and `ends` whether the
+// road is linked to another road at its start and end.
+fn write_lane(
+    xml: &mut String,
+    lane: &RoadSegment,
+    stations: &[f64],
+    reference: &[DVec3],
+    sections: &[Vec<&RoadSegment>],
+    section: usize,
+    ends: (bool, bool),
+) {
+    let id = lane.lane_id;
+    let _ = writeln!(
+        xml,
+        "          <lane id=\"{id}\" type=\"{}\" level=\"false\">",
+        escape(&lane.lane_type)
+    );
+
+    // The links as read, as long as the lane they name is still there: in
+    // the neighbouring section, or at the ends of the road in a linked road.
+    let linked = |other: Option<i32>, index: Option<usize>, end: bool| {
+        other.filter(|&other| match index.and_then(|i| sections.get(i)) {
+            Some(lanes) => lanes.iter().any(|l| l.lane_id == other),
+            None => end,
+        })
+    };
+    let predecessor = linked(lane.predecessor, section.checked_sub(1), ends.0);
+    let successor = linked(lane.successor, Some(section + 1), ends.1);
+    if predecessor.is_some() || successor.is_some() {
+        xml.push_str("            <link>\n");
+        if let Some(other) = predecessor {
+            let _ = writeln!(xml, "              <predecessor id=\"{other}\"/>");
+        }
+        if let Some(other) = successor {
+            let _ = writeln!(xml, "              <successor id=\"{other}\"/>");
+        }
+        xml.push_str("            </link>\n");
+    }
+
+    let widths: Vec<f64> = lane
+        .left_side
+        .iter()
+        .zip(&lane.right_side)
+        .map(|(l, r)| to_odr(*l).truncate().distance(to_odr(*r).truncate()))
+        .collect();
+    let start = stations[0];
+    let runs = match linear_runs(&widths, stations) {
+        runs if runs.is_empty() => vec![(0, 0)],
+        runs => runs,
+    };
+    for (from, to) in runs {
+        let (Some(width), Some(s)) = (widths.get(from), stations.get(from)) else {
+            continue;
+        };
+        let slope = match (widths.get(to), stations.get(to)) {
+            (Some(next), Some(next_s)) if next_s - s > f64::EPSILON => {
+                (next - width) / (next_s - s)
+            }
+            _ => 0.0,
+        };
+        let _ = writeln!(
+            xml,
+            "            <width sOffset=\"{:.6}\" a=\"{width:.6}\" b=\"{slope:.9}\" c=\"0\" d=\"0\"/>",
+            s - start
+        );
+    }
+    for mark in &lane.road_marks {
+        write_road_mark(xml, mark);
+    }
+    for material in &lane.materials {
+        let surface = if material.surface.is_empty() {
+            String::new()
+        } else {
+            format!(" surface=\"{}\"", escape(&material.surface))
+        };
+        let _ = writeln!(
+            xml,
+            "            <material sOffset=\"{:.6}\"{surface} friction=\"{:.3}\" roughness=\"{:.3}\"/>",
+            material.s_offset, material.friction, material.roughness
+        );
+    }
+    if let Some(speed) = lane.speed {
+        let _ = writeln!(
+            xml,
+            "            <speed sOffset=\"0\" max=\"{:.1}\" unit=\"km/h\"/>",
+            speed / KMH
+        );
+    }
+    for access in &lane.access {
+        let _ = writeln!(
+            xml,
+            "            <access sOffset=\"0\" rule=\"{}\" restriction=\"{}\"/>",
+            if access.allow { "allow" } else { "deny" },
+            escape(&access.restriction)
+        );
+    }
+    write_heights(xml, lane, stations, reference);
+
+    xml.push_str("          </lane>\n");
+}
+
+// Writes `<height>` records for a lane whose edges leave the road surface,
+// one wherever their offsets change.
+fn write_heights(xml: &mut String, lane: &RoadSegment, stations: &[f64], reference: &[DVec3]) {
+    let outer = if lane.lane_id < 0 {
+        &lane.right_side
+    } else {
+        &lane.left_side
+    };
+    let offsets = |side: &[DVec3], k: usize| {
+        side.get(k)
+            .zip(reference.get(k))
+            .map_or(0.0, |(p, r)| to_odr(*p).z - r.z)
+    };
+    let start = stations[0];
+    let mut last = (0.0, 0.0);
+    for k in 0..lane.left_side.len() {
+        let height = (offsets(inner_edge(lane), k), offsets(outer, k));
+        if (height.0 - last.0).abs() < HEIGHT_TOLERANCE
+            && (height.1 - last.1).abs() < HEIGHT_TOLERANCE
+        {
+            continue;
+        }
+        let s = stations.get(k).copied().unwrap_or(start) - start;
+        let _ = writeln!(
+            xml,
+            "            <height sOffset=\"{s:.6}\" inner=\"{:.4}\" outer=\"{:.4}\"/>",
+            height.0, height.1
+        );
+        last = height;
+    }
+}
+
+// Writes a lane's road mark, with its explicit type if it has one.
+fn write_road_mark(xml: &mut String, mark: &RoadMark) {
+    let mut attributes = format!(
+        " sOffset=\"{:.6}\" type=\"{}\"",
+        mark.s_offset,
+        escape(&mark.kind)
+    );
+    for (name, value) in [
+        ("color", &mark.color),
+        ("weight", &mark.weight),
+        ("material", &mark.material),
+    ] {
+        if !value.is_empty() {
+            let _ = write!(attributes, " {name}=\"{}\"", escape(value));
+        }
+    }
+    if let Some(width) = mark.width {
+        let _ = write!(attributes, " width=\"{width:.3}\"");
+    }
+    if mark.lines.is_empty() {
+        let _ = writeln!(xml, "            <roadMark{attributes}/>");
+        return;
+    }
+    let _ = writeln!(xml, "            <roadMark{attributes}>");
+    // Types made here rather than read are named after the mark.
+    let name = match mark.type_name.is_empty() {
+        true => &mark.kind,
+        false => &mark.type_name,
+    };
+    let width = mark
+        .type_width
+        .map(|width| format!(" width=\"{width:.3}\""))
+        .unwrap_or_default();
+    let _ = writeln!(xml, "              <type name=\"{}\"{width}>", escape(name));
+    for line in &mark.lines {
+        let MarkLine {
+            length,
+            space,
+            t_offset,
+            s_offset,
+            ..
+        } = line;
+        let mut extra = String::new();
+        if let Some(width) = line.width {
+            let _ = write!(extra, " width=\"{width:.3}\"");
+        }
+        if let Some(color) = &line.color {
+            let _ = write!(extra, " color=\"{}\"", escape(color));
+        }
+        let _ = writeln!(
+            xml,
+            "                <line length=\"{length:.3}\" space=\"{space:.3}\" tOffset=\"{t_offset:.3}\" sOffset=\"{s_offset:.3}\" rule=\"none\"{extra}/>"
+        );
+    }
+    xml.push_str("              </type>\n            </roadMark>\n");
+}
+
+// Escapes text for use in an XML attribute.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Writes a `<planView><geometry>` record as it was read.
+fn write_geometry(xml: &mut String, record: &PlanRecord) {
+    let shape = match record.shape {
+        Shape::Line => "<line/>".to_string(),
+        Shape::Arc { curvature } => format!("<arc curvature=\"{curvature}\"/>"),
+        Shape::Spiral { start, end } => {
+            format!("<spiral curvStart=\"{start}\" curvEnd=\"{end}\"/>")
+        }
+        Shape::Poly3 { v: [a, b, c, d] } => {
+            format!("<poly3 a=\"{a}\" b=\"{b}\" c=\"{c}\" d=\"{d}\"/>")
+        }
+        Shape::ParamPoly3 { u, v, normalized } => format!(
+            "<paramPoly3 aU=\"{}\" bU=\"{}\" cU=\"{}\" dU=\"{}\" aV=\"{}\" bV=\"{}\" cV=\"{}\" dV=\"{}\" pRange=\"{}\"/>",
+            u[0],
+            u[1],
+            u[2],
+            u[3],
+            v[0],
+            v[1],
+            v[2],
+            v[3],
+            if normalized { "normalized" } else { "arcLength" }
+        ),
+    };
+    let _ = writeln!(
+        xml,
+        "      <geometry s=\"{}\" x=\"{}\" y=\"{}\" hdg=\"{}\" length=\"{}\">{shape}</geometry>",
+        record.s, record.x, record.y, record.hdg, record.length
+    );
+}
+
+// Writes a cubic record as it was read, opened by `start` up to the name of
+// its station attribute.
+fn write_cubic(xml: &mut String, start: &str, record: &Cubic) {
+    let Cubic { s, a, b, c, d } = record;
+    let _ = writeln!(
+        xml,
+        "{start}=\"{s}\" a=\"{a}\" b=\"{b}\" c=\"{c}\" d=\"{d}\"/>"
+    );
+}
+
+// Whether the lanes of a road still start from the reference line records
+// it was read with: the inner edge of each section's innermost lane lies
+// where the records put the lane offset, and the sections cover the whole
+// line.
+fn follows(line: &ReferenceLine, sections: &[Vec<&RoadSegment>]) -> bool {
+    let road = Road {
+        geometries: line.geometry.iter().map(Geometry::new).collect(),
+        ..Road::default()
+    };
+    let innermost: Vec<&RoadSegment> = sections
+        .iter()
+        .filter_map(|lanes| lanes.iter().min_by_key(|lane| lane.lane_id.abs()).copied())
+        .collect();
+    let (Some(first), Some(last)) = (innermost.first(), innermost.last()) else {
+        return false;
+    };
+    let close = |a: f64, b: f64| (a - b).abs() <= FOLLOW_TOLERANCE;
+    if road.geometries.is_empty()
+        || !close(first.start_s, 0.0)
+        || !close(last.end_s, line.length())
+        || innermost
+            .windows(2)
+            .any(|pair| !close(pair[0].end_s, pair[1].start_s))
+    {
+        return false;
+    }
+    innermost.iter().all(|lane| {
+        inner_edge(lane)
+            .iter()
+            .zip(section_stations(lane))
+            .all(|(p, s)| {
+                let (x, y, hdg) = road.reference(s);
+                let t = evaluate(&line.lane_offset, s);
+                let expected = DVec3::new(
+                    x - t * hdg.sin(),
+                    y + t * hdg.cos(),
+                    evaluate(&line.elevation, s),
+                );
+                to_odr(*p).distance(expected) <= FOLLOW_TOLERANCE
+            })
+    })
+}
+
+// The reference line station of each vertex of a lane's inner edge, spread
+// over the lane's stations in proportion to the distance along the edge.
+fn section_stations(lane: &RoadSegment) -> Vec<f64> {
+    let edge: Vec<DVec3> = inner_edge(lane).iter().copied().map(to_odr).collect();
+    let along = stations(&edge);
+    let total = along.last().copied().unwrap_or(0.0);
+    along
+        .iter()
+        .map(|d| match total > f64::EPSILON {
+            true => lane.start_s + (lane.end_s - lane.start_s) * d / total,
+            false => lane.start_s,
+        })
+        .collect()
+}
+
+// Splits a polyline into runs of pieces heading the same way, as the
+// indices of their first and last points. Pieces of no length are left out.
+fn straight_runs(points: &[DVec3]) -> Vec<(usize, usize)> {
+    let direction = |from: usize, to: usize| (points[to] - points[from]).truncate().normalize();
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for i in 1..points.len() {
+        if points[i].truncate().distance(points[i - 1].truncate()) <= f64::EPSILON {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run)
+                if run.1 == i - 1
+                    && direction(run.0, run.1).distance(direction(i - 1, i)) <= SLOPE_TOLERANCE =>
+            {
+                run.1 = i
+            }
+            _ => runs.push((i - 1, i)),
+        }
+    }
+    runs
+}
+
+// Splits values along a polyline into runs of the same slope, as the
+// indices of their first and last points. Pieces of no length are left out.
+fn linear_runs(values: &[f64], stations: &[f64]) -> Vec<(usize, usize)> {
+    let slope =
+        |from: usize, to: usize| (values[to] - values[from]) / (stations[to] - stations[from]);
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for i in 1..values.len().min(stations.len()) {
+        if stations[i] - stations[i - 1] <= f64::EPSILON {
+            continue;
+        }
+        match runs.last_mut() {
+            Some(run)
+                if run.1 == i - 1
+                    && (slope(run.0, run.1) - slope(i - 1, i)).abs() <= SLOPE_TOLERANCE =>
+            {
+                run.1 = i
+            }
+            _ => runs.push((i - 1, i)),
+        }
+    }
+    runs
+}
+
+// Cumulative distance along a polyline in the ground plane.
+fn stations(points: &[DVec3]) -> Vec<f64> {
+    let mut s = 0.0;
+    let mut out = Vec::with_capacity(points.len());
+    for (i, p) in points.iter().enumerate() {
+        if i > 0 {
+            s += p.truncate().distance(points[i - 1].truncate());
+        }
+        out.push(s);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::sample_maps::{
+        assert_near, assert_points_near, lane, length, load, path, temp_dir, text, TOLERANCE,
+    };
+    use crate::units::{KMH, MPH};
+    use crate::{ContactPoint, LaneMaterial, MarkLine, RoadLink, RoadMark};
+
+    #[test]
+    fn references_are_renumbered() {
+        let xml = r#"<junction name="X" id="j1">
+            <connection id="0" incomingRoad="west" connectingRoad="turn" contactPoint="start">
+              <laneLink from="-1" to="-1"/>
+            </connection>
+            <priority high="west" low="9"/>
+          </junction>"#;
+        let ids = HashMap::from([("west", 5), ("turn", 6)]);
+        let renumbered = renumber(xml, |road| ids.get(road).copied(), |_| Some(7));
+        assert!(renumbered.starts_with(r#"<junction name="X" id="7">"#));
+        assert!(renumbered.contains(r#"incomingRoad="5" connectingRoad="6""#));
+        assert!(renumbered.contains(r#"<connection id="0""#));
+        assert!(renumbered.contains(r#"<priority high="5" low="9"/>"#));
+        assert!(renumbered.contains(r#"<laneLink from="-1" to="-1"/>"#));
+    }
+
+    #[test]
+    fn header_name_and_lane_links_are_written_as_read() {
+        let width = r#"<width sOffset="0" a="3" b="0" c="0" d="0"/>"#;
+        let xml = format!(
+            r#"<OpenDRIVE>
+              <header name="Town &amp; Country"/>
+              <road id="1" length="20" junction="-1">
+                <planView>
+                  <geometry s="0" x="0" y="0" hdg="0" length="20"><line/></geometry>
+                </planView>
+                <lanes>
+                  <laneSection s="0">
+                    <right>
+                      <lane id="-1" type="driving">
+                        <link><successor id="-2"/></link>{width}
+                      </lane>
+                    </right>
+                  </laneSection>
+                  <laneSection s="10">
+                    <right>
+                      <lane id="-1" type="shoulder">{width}</lane>
+                      <lane id="-2" type="driving">
+                        <link><predecessor id="-1"/><successor id="-7"/></link>{width}
+                      </lane>
+                    </right>
+                  </laneSection>
+                </lanes>
+              </road>
+            </OpenDRIVE>"#
+        );
+        let network = read_str(&xml, &LoadTransform::default()).unwrap();
+        assert_eq!(network.name, "Town & Country");
+
+        let written = to_xml(&network);
+        assert!(written.contains(r#"name="Town &amp; Country""#));
+        let read = read_str(&written, &LoadTransform::default()).unwrap();
+        let links = |section: u32, lane: i32| {
+            let index = read.find_segment(1, section, lane).unwrap();
+            let lane = &read.segments[index];
+            (lane.predecessor, lane.successor)
+        };
+        assert_eq!(links(1, -1), (None, Some(-2)));
+        assert_eq!(links(2, -1), (None, None));
+        // There is no road after this one for the last link to point into.
+        assert_eq!(links(2, -2), (Some(-1), None));
+    }
+
+    #[test]
+    fn geo_reference_is_written_as_read() {
+        let proj = "+proj=tmerc +lat_0=48.1 +lon_0=11.5 +ellps=WGS84";
+        let xml = format!(
+            r#"<OpenDRIVE>
+              <header name="geo"><geoReference><![CDATA[{proj}]]></geoReference></header>
+              <road id="1" length="10" junction="-1">
+                <planView>
+                  <geometry s="0" x="0" y="0" hdg="0" length="10"><line/></geometry>
+                </planView>
+                <lanes>
+                  <laneSection s="0">
+                    <right>
+                      <lane id="-1" type="driving">
+                        <width sOffset="0" a="3" b="0" c="0" d="0"/>
+                      </lane>
+                    </right>
+                  </laneSection>
+                </lanes>
+              </road>
+            </OpenDRIVE>"#
+        );
+        let network = read_str(&xml, &LoadTransform::default()).unwrap();
+        let read = read_str(&to_xml(&network), &LoadTransform::default()).unwrap();
+        assert_eq!(read.geo.unwrap().proj, proj);
+        assert_eq!(read.name, "geo");
+
+        // Without one, none is written.
+        let plain = to_xml(&RoadNetwork::default());
+        assert!(!plain.contains("geoReference"));
+        assert_eq!(
+            read_str(&plain, &LoadTransform::default()).unwrap().geo,
+            None
+        );
+    }
+
+    #[test]
+    fn reference_line_records_are_written_while_the_lanes_follow_them() {
+        let network = load("curve.xodr");
+        let xml = to_xml(&network);
+        assert_eq!(xml.matches("<geometry ").count(), 1);
+        assert!(xml.contains("length=\"78.53981633974483\"><arc curvature=\"0.02\"/>"));
+
+        // Lanes moved off the records are written from their outline, with
+        // pieces heading the same way as one.
+        let mut network = load("elevation.xodr");
+        for segment in &mut network.segments {
+            for p in segment.left_side.iter_mut().chain(&mut segment.right_side) {
+                p.x += 1.0;
+            }
+        }
+        let xml = to_xml(&network);
+        assert_eq!(xml.matches("<geometry ").count(), 1);
+        assert!(
+            xml.contains("x=\"1.000000\" y=\"0.000000\" hdg=\"0.000000000\" length=\"100.000000\"")
+        );
+        assert_eq!(xml.matches("<elevation ").count(), 1);
+        assert!(xml.contains("b=\"0.050000000\""));
+        assert_eq!(xml.matches("<width ").count(), 3);
+    }
+
+    #[test]
+    fn lanes_without_an_id_are_left_out() {
+        let xml = r#"<OpenDRIVE>
+              <road id="1" length="10" junction="-1">
+                <planView>
+                  <geometry s="0" x="0" y="0" hdg="0" length="10"><line/></geometry>
+                </planView>
+                <lanes>
+                  <laneSection s="0">
+                    <right>
+                      <lane id="-1" type="driving">
+                        <width sOffset="0" a="3" b="0" c="0" d="0"/>
+                      </lane>
+                      <lane id="outer" type="sidewalk">
+                        <width sOffset="0" a="2" b="0" c="0" d="0"/>
+                      </lane>
+                    </right>
+                  </laneSection>
+                </lanes>
+              </road>
+            </OpenDRIVE>"#;
+        let network = read_str(xml, &LoadTransform::default()).unwrap();
+        assert_eq!(network.segments.len(), 1);
+        assert!((network.segments[0].width - 3.0).abs() < 1e-9);
+        assert!(network
+            .warnings
+            .iter()
+            .any(|w| w.contains("id=\"outer\" is not a lane ID")));
+    }
+
+    #[test]
+    fn elements_of_a_file_are_read_back_from_it() {
+        let copy = temp_dir("elements").join("signals.xodr");
+        std::fs::copy(path("signals.xodr"), &copy).unwrap();
+        let network = read_file(&copy, &LoadTransform::default()).unwrap();
+        let road = &network.roads.values().next().unwrap().xml;
+        assert!(matches!(road, ElementXml::Span(..)));
+        assert!(road.text().unwrap().starts_with("<road"));
+        let signal = &network.signals[0].xml;
+        assert!(signal.text().unwrap().starts_with("<signal"));
+
+        // Once the file changes, its elements are no longer read from it.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        std::fs::write(&copy, "<OpenDRIVE/>").unwrap();
+        assert_eq!(road.text(), None);
+        let _ = std::fs::remove_dir_all(copy.parent().unwrap());
+    }
+
+    #[test]
+    fn junctions_stay_their_place_in_the_file_unless_renumbered() {
+        let network = load("junction.xodr");
+        let junction = &network.junctions[&100];
+        assert!(matches!(junction, ElementXml::Span(..)));
+        assert!(junction
+            .text()
+            .unwrap()
+            .starts_with(r#"<junction name="Junction" id="100">"#));
+
+        let xml = text("junction.xodr")
+            .replace(r#"id="3""#, r#"id="through""#)
+            .replace(r#"connectingRoad="3""#, r#"connectingRoad="through""#);
+        let network = read_string(xml, &LoadTransform::default()).unwrap();
+        let junction = network.junctions.values().next().unwrap();
+        assert!(matches!(junction, ElementXml::Text(..)));
+        assert!(!junction.text().unwrap().contains("through"));
+    }
+
+    #[test]
+    fn straight_road() {
+        let network = load("straight.xodr");
+        assert_eq!(network.roads.len(), 1);
+        assert_eq!(network.segments.len(), 3);
+
+        // Lanes stack outwards from the reference line along x; the right side
+        // of the road is +z in the viewer frame.
+        let inner = lane(&network, 1, 1, -1);
+        assert_eq!(inner.lane_type, "driving");
+        // Speeds are kept in m/s.
+        assert_eq!(inner.speed, Some(50.0 / 3.6));
+        assert_eq!(inner.road_type, "town");
+        assert_eq!(
+            inner.road_marks.first().map(|mark| mark.kind.as_str()),
+            Some("broken")
+        );
+        assert_points_near(inner.left_side[0], DVec3::new(0.0, 0.0, 0.0));
+        assert_points_near(inner.right_side[0], DVec3::new(0.0, 0.0, 3.5));
+        assert_points_near(
+            *inner.right_side.last().unwrap(),
+            DVec3::new(100.0, 0.0, 3.5),
+        );
+        let outer = lane(&network, 1, 1, -2);
+        assert_eq!(outer.lane_type, "sidewalk");
+        // The sidewalk is raised by its height records.
+        assert_points_near(outer.right_side[0], DVec3::new(0.0, 0.15, 6.5));
+        let left = lane(&network, 1, 1, 1);
+        assert_points_near(left.left_side[0], DVec3::new(0.0, 0.0, -3.5));
+
+        for segment in &network.segments {
+            assert_near(segment.start_s, 0.0, 1e-9);
+            assert_near(segment.end_s, 100.0, 1e-9);
+            assert_near(length(&network, segment), 100.0, TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn road_marks_are_kept_with_their_types() {
+        let network = load("straight.xodr");
+        let center = RoadMark {
+            kind: "solid solid".to_string(),
+            color: "yellow".to_string(),
+            weight: "standard".to_string(),
+            material: "standard".to_string(),
+            width: Some(0.12),
+            ..RoadMark::default()
+        };
+        for lane_id in [-1, 1] {
+            assert_eq!(
+                lane(&network, 1, 1, lane_id).center_marks,
+                std::slice::from_ref(&center)
+            );
+        }
+        let outer = lane(&network, 1, 1, -2);
+        assert!(outer.center_marks.is_empty());
+        // Every road mark of a lane is kept, with its explicit type.
+        let [custom, solid] = outer.road_marks.as_slice() else {
+            panic!("{:?}", outer.road_marks);
+        };
+        assert_eq!((solid.s_offset, solid.kind.as_str()), (50.0, "solid"));
+        assert_eq!(custom.kind, "custom");
+        assert_eq!(custom.material, "thermoplastic");
+        assert_eq!(custom.type_name, "dotted");
+        assert_eq!(custom.type_width, Some(0.3));
+        assert_eq!(
+            custom.lines,
+            [MarkLine {
+                length: 1.0,
+                space: 2.0,
+                t_offset: 0.0,
+                s_offset: 0.0,
+                width: Some(0.3),
+                color: Some("blue".to_string()),
+            }]
+        );
+
+        // Written out and read back, the marks stay as they were.
+        let read = read_str(&to_xml(&network), &LoadTransform::default()).unwrap();
+        for segment in &network.segments {
+            let again = lane(
+                &read,
+                segment.road_id,
+                segment.lane_section_id,
+                segment.lane_id,
+            );
+            assert_eq!(again.road_marks, segment.road_marks);
+            assert_eq!(again.center_marks, segment.center_marks);
+        }
+    }
+
+    #[test]
+    fn lane_heights() {
+        let network = load("straight.xodr");
+        // A record holds until the next one, on the lane's inner and outer edge.
+        let sidewalk = lane(&network, 1, 1, -2);
+        for (s, inner, outer) in [
+            (10, 0.15, 0.15),
+            (49, 0.15, 0.15),
+            (50, 0.1, 0.15),
+            (90, 0.1, 0.15),
+        ] {
+            assert_near(sidewalk.left_side[s].y, inner, 1e-9);
+            assert_near(sidewalk.right_side[s].y, outer, 1e-9);
+        }
+        assert!(lane(&network, 1, 1, -1)
+            .right_side
+            .iter()
+            .all(|p| p.y.abs() < 1e-9));
+
+        // Written out and read back, the edges stay where they were.
+        let read = read_str(&to_xml(&network), &LoadTransform::default()).unwrap();
+        let again = lane(&read, 1, 1, -2);
+        for (a, b) in again.left_side.iter().zip(&sidewalk.left_side) {
+            assert_points_near(*a, *b);
+        }
+        for (a, b) in again.right_side.iter().zip(&sidewalk.right_side) {
+            assert_points_near(*a, *b);
+        }
+    }
+
+    #[test]
+    fn lane_materials() {
+        let network = load("straight.xodr");
+        // Every material record of a lane is kept, in `sOffset` order.
+        let asphalt = LaneMaterial {
+            s_offset: 0.0,
+            surface: "asphalt".to_string(),
+            friction: 0.9,
+            roughness: 0.015,
+        };
+        let ice = LaneMaterial {
+            s_offset: 60.0,
+            surface: "ice".to_string(),
+            friction: 0.1,
+            roughness: 0.0,
+        };
+        assert_eq!(lane(&network, 1, 1, -1).materials, [asphalt, ice]);
+        assert!(lane(&network, 1, 1, 1).materials.is_empty());
+
+        let read = read_str(&to_xml(&network), &LoadTransform::default()).unwrap();
+        for segment in &network.segments {
+            let again = lane(
+                &read,
+                segment.road_id,
+                segment.lane_section_id,
+                segment.lane_id,
+            );
+            assert_eq!(again.materials, segment.materials);
+        }
+    }
+
+    #[test]
+    fn curved_road() {
+        let network = load("curve.xodr");
+        assert_eq!(network.roads.len(), 1);
+
+        // A left turn: the right lane runs outside the reference line, the left
+        // lane inside it.
+        let right = lane(&network, 1, 1, -1);
+        let left = lane(&network, 1, 1, 1);
+        assert_near(length(&network, right), 51.75 * PI / 2.0, 0.05);
+        assert_near(length(&network, left), 48.25 * PI / 2.0, 0.05);
+
+        // The reference line ends 50 m east and 50 m north, heading north.
+        assert_points_near(
+            *right.left_side.last().unwrap(),
+            DVec3::new(50.0, 0.0, -50.0),
+        );
+        assert_points_near(
+            *right.right_side.last().unwrap(),
+            DVec3::new(53.5, 0.0, -50.0),
+        );
+        assert_points_near(
+            *left.left_side.last().unwrap(),
+            DVec3::new(46.5, 0.0, -50.0),
+        );
+    }
+
+    #[test]
+    fn junction_roads() {
+        let network = load("junction.xodr");
+        assert_eq!(network.roads.len(), 3);
+        assert_eq!(network.roads[&1].junction, None);
+        assert_eq!(network.roads[&3].junction, Some(100));
+        assert!(network.junctions.contains_key(&100));
+
+        // Links to the junction itself are resolved through its connections, so
+        // only the connecting road's links are kept.
+        let mut links = network.links.clone();
+        links.sort_by_key(|link| link.other_road_id);
+        assert_eq!(
+            links,
+            [
+                RoadLink {
+                    road_id: 3,
+                    contact: ContactPoint::Start,
+                    other_road_id: 1,
+                    other_contact: ContactPoint::End,
+                },
+                RoadLink {
+                    road_id: 3,
+                    contact: ContactPoint::End,
+                    other_road_id: 2,
+                    other_contact: ContactPoint::Start,
+                },
+            ]
+        );
+
+        // The connecting lane closes the gap between the two roads.
+        let west = lane(&network, 1, 1, -1);
+        let through = lane(&network, 3, 1, -1);
+        let east = lane(&network, 2, 1, -1);
+        assert_points_near(through.right_side[0], *west.right_side.last().unwrap());
+        assert_points_near(*through.right_side.last().unwrap(), east.right_side[0]);
+        assert_near(length(&network, through), 10.0, TOLERANCE);
+    }
+
+    #[test]
+    fn elevation_and_lane_sections() {
+        let network = load("elevation.xodr");
+        assert_eq!(network.roads.len(), 1);
+
+        let first = lane(&network, 1, 1, -1);
+        let second = lane(&network, 1, 2, -1);
+        assert_near(first.end_s, 50.0, 1e-9);
+        assert_near(second.start_s, 50.0, 1e-9);
+        assert_near(second.end_s, 100.0, 1e-9);
+        assert!(network
+            .segments
+            .iter()
+            .all(|s| s.lane_section_id != 1 || s.lane_id != -2));
+
+        // Heights follow the 5 % grade, and lengths the slope.
+        assert_near(first.left_side[0].y, 0.0, 1e-9);
+        assert_near(second.left_side.last().unwrap().y, 5.0, TOLERANCE);
+        let slope = 50.0 * (1.0_f64 + 0.05 * 0.05).sqrt();
+        assert_near(length(&network, first), slope, TOLERANCE);
+
+        // The new lane opens from nothing to 3.5 m.
+        let opening = lane(&network, 1, 2, -2);
+        assert_points_near(opening.left_side[0], opening.right_side[0]);
+        let end = opening
+            .left_side
+            .last()
+            .unwrap()
+            .distance(*opening.right_side.last().unwrap());
+        assert_near(end, 3.5, TOLERANCE);
+    }
+
+    #[test]
+    fn road_names_and_junctions() {
+        let network = load("junction.xodr");
+        let names: Vec<&str> = network
+            .roads
+            .values()
+            .map(|info| info.name.as_str())
+            .collect();
+        assert_eq!(names, ["West", "East", "Through"]);
+        // Names are written back, escaped.
+        let mut renamed = network.clone();
+        renamed.roads.get_mut(&1).unwrap().name = "Rue \"Haute\" & Co".to_string();
+        let written = to_xml(&renamed);
+        let read = read_str(&written, &LoadTransform::default()).unwrap();
+        assert_eq!(read.roads[&1].name, "Rue \"Haute\" & Co");
+        assert_eq!(read.roads[&2].name, "East");
+        // So are the junction, the roads' places in it and the links into it.
+        assert_eq!(read.roads[&3].junction, Some(100));
+        assert_eq!(read.roads[&1].junction, None);
+        assert_eq!(read.junctions, network.junctions);
+        assert_eq!(read.links, network.links);
+        assert!(written.contains("<successor elementType=\"junction\" elementId=\"100\"/>"));
+        assert!(written.contains("<predecessor elementType=\"junction\" elementId=\"100\"/>"));
+    }
+
+    #[test]
+    fn speed_units() {
+        // Limits are read in their unit and kept in m/s.
+        let xml = text("straight.xodr");
+        for (unit, expected) in [("mph", 50.0 * MPH), ("m/s", 50.0), ("", 50.0 * KMH)] {
+            let xml = xml.replace("unit=\"km/h\"", &format!("unit=\"{unit}\""));
+            let network = read_str(&xml, &LoadTransform::default()).unwrap();
+            let speed = lane(&network, 1, 1, -1).speed.unwrap();
+            assert_near(speed, expected, 1e-9);
+            // Written back in km/h, and read again as the same limit.
+            let back = read_str(&to_xml(&network), &LoadTransform::default()).unwrap();
+            assert_near(lane(&back, 1, 1, -1).speed.unwrap(), speed, 0.05 * KMH);
+        }
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        // A misspelled length still makes a 100 m road, with a warning saying
+        // where and how it was read.
+        let xml = text("straight.xodr").replace(
+            "hdg=\"0.0\" length=\"100.0\"",
+            "hdg=\"0.0\" length=\"1,0D+2\"",
+        );
+        let network = read_str(&xml, &LoadTransform::default()).unwrap();
+        assert_near(lane(&network, 1, 1, -1).end_pos.x, 100.0, 1e-6);
+        assert_eq!(network.warnings.len(), 1);
+        assert!(network.warnings[0].starts_with("road 1: geometry length=\"1,0D+2\" read as 100"));
+        assert!(load("straight.xodr").warnings.is_empty());
+    }
+}